@@ -0,0 +1,186 @@
+//! Python bindings for reading cores and decoding neopack
+//!
+//! Build and install into the current virtualenv with `maturin develop`
+//! from this directory. The module exposes:
+//!
+//! - `NeoDiskReader(path)`: `len(r)`, `r.read(id) -> bytes`
+//! - `IsoCore.load(path)`: `len(c)`, `c.get_message(i) -> bytes`,
+//!   `c.root_hash() -> bytes`, `c.signer -> bytes`, `c.sealed -> bool`
+//! - `decode(bytes) -> list`: every top-level neopack value, with maps as
+//!   dicts, lists and arrays as lists, raw structs and fixed-size values
+//!   as bytes, timestamps and durations as integer nanoseconds, and
+//!   extension values as `(tag, bytes)` tuples.
+//!
+//! Input buffers are borrowed from Python without copying. Returned byte
+//! strings are copied once into Python-owned `bytes`, since the backing
+//! frames are decompressed into temporary buffers anyway.
+
+use pyo3::exceptions::PyIOError;
+use pyo3::exceptions::PyIndexError;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use pyo3::types::PyDict;
+use pyo3::types::PyList;
+
+use home::covering::ItemId;
+use home::isocore::IsoCoreError;
+use home::neodisk;
+use home::neodisk::MessageId;
+use home::neopack;
+use home::neopack::Decoder;
+use home::neopack::ValueDecoder;
+
+fn neopack_err(e: neopack::Error) -> PyErr {
+    PyValueError::new_err(format!("neopack: {:?}", e))
+}
+
+fn neodisk_err(e: neodisk::Error) -> PyErr {
+    match e {
+        neodisk::Error::MessageNotFound(id) => PyIndexError::new_err(id),
+        neodisk::Error::Io(e) => PyIOError::new_err(e.to_string()),
+        e => PyValueError::new_err(format!("neodisk: {:?}", e)),
+    }
+}
+
+fn isocore_err(e: IsoCoreError) -> PyErr {
+    match e {
+        IsoCoreError::Io(e) => PyIOError::new_err(e.to_string()),
+        IsoCoreError::Core(home::core::CoreError::FutureMessage) => PyIndexError::new_err("item out of range"),
+        e => PyValueError::new_err(format!("isocore: {:?}", e)),
+    }
+}
+
+/// Read-only handle to a single neodisk file.
+#[pyclass]
+struct NeoDiskReader {
+    inner: neodisk::NeoDiskReader,
+}
+
+#[pymethods]
+impl NeoDiskReader {
+    #[new]
+    fn new(path: std::path::PathBuf) -> PyResult<Self> {
+        let inner = neodisk::NeoDiskReader::open(path).map_err(neodisk_err)?;
+        Ok(Self { inner })
+    }
+
+    fn __len__(&self) -> PyResult<usize> {
+        Ok(self.inner.len().map_err(neodisk_err)? as usize)
+    }
+
+    /// Returns the raw neopack bytes of message `id`.
+    fn read<'py>(&self, py: Python<'py>, id: u64) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self.inner.read(MessageId(id)).map_err(neodisk_err)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+}
+
+/// A loaded isocore. Reads verify each message against its leaf hash.
+#[pyclass(unsendable)]
+struct IsoCore {
+    inner: home::isocore::IsoCore,
+}
+
+#[pymethods]
+impl IsoCore {
+    #[staticmethod]
+    fn load(path: std::path::PathBuf) -> PyResult<Self> {
+        let inner = home::isocore::IsoCore::load(path).map_err(isocore_err)?;
+        Ok(Self { inner })
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len().0 as usize
+    }
+
+    #[getter]
+    fn signer<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.inner.signer().0)
+    }
+
+    /// False for a core whose info.nd is from before seals were signed
+    #[getter]
+    fn sealed(&self) -> bool {
+        self.inner.is_sealed()
+    }
+
+    fn get_message<'py>(&mut self, py: Python<'py>, index: u64) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = self.inner.get_message(ItemId(index)).map_err(isocore_err)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    fn root_hash<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let hash = self.inner.get_root_hash().map_err(isocore_err)?;
+        Ok(PyBytes::new(py, &hash.0))
+    }
+}
+
+/// Decodes every top-level neopack value in `data` into Python objects.
+#[pyfunction]
+fn decode<'py>(py: Python<'py>, data: &[u8]) -> PyResult<Bound<'py, PyList>> {
+    let out = PyList::empty(py);
+    let mut dec = Decoder::new(data);
+    while dec.remaining() > 0 {
+        let value = dec.value().map_err(neopack_err)?;
+        out.append(to_py(py, value)?)?;
+    }
+    Ok(out)
+}
+
+fn to_py<'py>(py: Python<'py>, value: ValueDecoder<'_>) -> PyResult<Bound<'py, PyAny>> {
+    use ValueDecoder::*;
+    let obj = match value {
+        Bool(v) => v.into_pyobject(py)?.to_owned().into_any(),
+        U8(v) => v.into_pyobject(py)?.into_any(),
+        S8(v) => v.into_pyobject(py)?.into_any(),
+        U16(v) => v.into_pyobject(py)?.into_any(),
+        S16(v) => v.into_pyobject(py)?.into_any(),
+        U32(v) => v.into_pyobject(py)?.into_any(),
+        S32(v) => v.into_pyobject(py)?.into_any(),
+        U64(v) => v.into_pyobject(py)?.into_any(),
+        S64(v) => v.into_pyobject(py)?.into_any(),
+        F32(v) => v.into_pyobject(py)?.into_any(),
+        F64(v) => v.into_pyobject(py)?.into_any(),
+        F16(v) => v.to_f32().into_pyobject(py)?.into_any(),
+        U128(v) => v.into_pyobject(py)?.into_any(),
+        S128(v) => v.into_pyobject(py)?.into_any(),
+        Timestamp(v) => v.0.into_pyobject(py)?.into_any(),
+        Duration(v) => v.0.into_pyobject(py)?.into_any(),
+        Bytes(v) | Struct(v) => PyBytes::new(py, v).into_any(),
+        Fixed16(v) => PyBytes::new(py, v).into_any(),
+        Fixed32(v) => PyBytes::new(py, v).into_any(),
+        Str(v) => v.into_pyobject(py)?.into_any(),
+        List(mut list) => {
+            let out = PyList::empty(py);
+            while let Some(item) = list.next().map_err(neopack_err)? {
+                out.append(to_py(py, item)?)?;
+            }
+            out.into_any()
+        }
+        Map(mut map) => {
+            let out = PyDict::new(py);
+            while let Some((key, item)) = map.next().map_err(neopack_err)? {
+                out.set_item(key, to_py(py, item)?)?;
+            }
+            out.into_any()
+        }
+        Array(mut array) => {
+            let out = PyList::empty(py);
+            while let Some(item) = array.next().map_err(neopack_err)? {
+                out.append(to_py(py, item)?)?;
+            }
+            out.into_any()
+        }
+        Ext(tag, v) => (tag, PyBytes::new(py, v)).into_pyobject(py)?.into_any(),
+    };
+    Ok(obj)
+}
+
+#[pymodule]
+fn home_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<NeoDiskReader>()?;
+    m.add_class::<IsoCore>()?;
+    m.add_function(wrap_pyfunction!(decode, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,30 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    generate_header();
+}
+
+/// Writes the C header for the `ffi` module to `$OUT_DIR/home.h`. The copy
+/// checked in at `include/home.h` is only rewritten when
+/// `HOME_UPDATE_HEADER` is set, so building never touches the source tree
+/// unasked.
+#[cfg(feature = "ffi")]
+fn generate_header() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+        .expect("cbindgen.toml should be valid");
+
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=HOME_UPDATE_HEADER");
+
+    let bindings = cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("ffi module should produce a C header");
+    bindings.write_to_file(format!("{}/home.h", out_dir));
+    if std::env::var_os("HOME_UPDATE_HEADER").is_some() {
+        bindings.write_to_file(format!("{}/include/home.h", crate_dir));
+    }
+}
@@ -0,0 +1,41 @@
+//! Compares hashing verkle nodes with committing to them: proof sizes and
+//! the time to build, open, and verify each. Run with
+//! `cargo run --release --example commitment_bench --features vector-commitment`.
+
+use home::commitment::Generators;
+use home::format::FormatVersion;
+use home::key::hash;
+use home::key::Hash;
+use std::time::Duration;
+use std::time::Instant;
+
+fn time<R>(iterations: u32, mut f: impl FnMut() -> R) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        std::hint::black_box(f());
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    println!("{:>6} {:>9} {:>8} {:>10} {:>10} {:>10} {:>10}",
+        "width", "siblings", "opening", "hash node", "commit", "open", "verify");
+
+    for width in [8usize, 256] {
+        let generators = Generators::new(width);
+        let children: Vec<Hash> = (0..width).map(|i| hash(&i.to_le_bytes())).collect();
+        let index = width / 2;
+        let commitment = generators.commit(&children).unwrap();
+        let opening = generators.open(&children, index).unwrap();
+        generators.verify(&commitment, index, &children[index], &opening).unwrap();
+
+        let iterations = if width > 64 { 10 } else { 200 };
+        let hash_node = time(iterations * 100, || FormatVersion::CURRENT.hash_node(&children));
+        let commit = time(iterations, || generators.commit(&children).unwrap());
+        let open = time(iterations, || generators.open(&children, index).unwrap());
+        let verify = time(iterations, || generators.verify(&commitment, index, &children[index], &opening));
+
+        println!("{:>6} {:>8}B {:>7}B {:>10.2?} {:>10.2?} {:>10.2?} {:>10.2?}",
+            width, (width - 1) * 32, opening.to_bytes().len(), hash_node, commit, open, verify);
+    }
+}
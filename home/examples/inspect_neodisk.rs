@@ -1,12 +1,17 @@
 //! Inspect neodisk file format
+//!
+//! Usage: inspect_neodisk [path] [--messages]
+//! With `--messages`, also dumps every message as a neopack tree.
 
 use std::fs::File;
 use std::io::Read;
 use home::jumpheader::FrameHeader;
-use home::neopack::{Cursor, Decoder};
+use home::neodisk::NeoDiskReader;
+use home::neopack::{dump, Cursor, Decoder};
 
 fn main() {
-    let path = std::env::args().nth(1).unwrap_or_else(|| {
+    let dump_messages = std::env::args().skip(1).any(|arg| arg == "--messages");
+    let path = std::env::args().skip(1).find(|arg| !arg.starts_with("--")).unwrap_or_else(|| {
         "../cores/compact/data.nd".to_string()
     });
     
@@ -51,6 +56,10 @@ fn main() {
                         println!("Compressed size: {} bytes", header.compressed_size);
                         println!("Decompressed size: {} bytes", header.decompressed_size);
                         println!("Jump offsets: {:?}", header.jump_offsets);
+                        if let Some(forward) = &header.forward_offsets {
+                            println!("Forward offsets: {:?}", forward);
+                        }
+                        print!("{}", dump(header_bytes));
                         
                         let header_size = decoder.pos();
                         println!("Header size: {} bytes", header_size);
@@ -80,4 +89,12 @@ fn main() {
     }
     
     println!("Total frames: {}", frame_num);
+
+    if dump_messages {
+        let reader = NeoDiskReader::open(&path).unwrap();
+        for (i, message) in reader.read_range(0..reader.len().unwrap()).unwrap().iter().enumerate() {
+            println!("\n=== Message {} ({} bytes) ===", i, message.len());
+            print!("{}", dump(message));
+        }
+    }
 }
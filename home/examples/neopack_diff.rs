@@ -0,0 +1,31 @@
+//! Show what changed between two neopack files
+//!
+//! Usage: neopack_diff <old> <new>
+
+use home::neopack::diff;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let (Some(old_path), Some(new_path)) = (args.next(), args.next()) else {
+        eprintln!("usage: neopack_diff <old> <new>");
+        std::process::exit(2);
+    };
+
+    let old = std::fs::read(&old_path).unwrap();
+    let new = std::fs::read(&new_path).unwrap();
+    let entries = match diff(&old, &new) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("ERROR: failed to decode: {:?}", e);
+            std::process::exit(2);
+        }
+    };
+
+    for entry in &entries {
+        println!("{}", entry);
+    }
+    // Like diff(1): 1 when the documents differ
+    if !entries.is_empty() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,53 @@
+//! Example: neopack on a target without std
+//!
+//! Only `core` and `alloc` are available here, so this crate doubles as a
+//! build check that the neopack encoder and decoder stay no_std. The final
+//! firmware image provides the global allocator and panic handler.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use home::neopack::{Decoder, Encoder, Error, Tag};
+
+/// A sensor reading as it would be logged by a microcontroller.
+pub struct Reading {
+    pub sensor: u8,
+    pub millis: u64,
+    pub samples: [i16; 4],
+}
+
+/// Encodes a reading as `[sensor, millis, array<i16>]`.
+pub fn encode(reading: &Reading) -> Result<Vec<u8>, Error> {
+    let mut enc = Encoder::new();
+    let mut list = enc.list()?;
+    list.u8(reading.sensor)?;
+    list.u64(reading.millis)?;
+    let mut samples = list.array(Tag::S16, 2)?;
+    for sample in reading.samples {
+        samples.i16(sample)?;
+    }
+    samples.finish()?;
+    list.finish()?;
+    Ok(enc.into_bytes())
+}
+
+/// Decodes a reading written by `encode`.
+pub fn decode(bytes: &[u8]) -> Result<Reading, Error> {
+    let mut dec = Decoder::new(bytes);
+    let mut list = dec.list()?;
+    let sensor = list.next()?.ok_or(Error::Malformed)?.as_u8()?;
+    let millis = list.next()?.ok_or(Error::Malformed)?.as_u64()?;
+
+    let home::neopack::ValueDecoder::Array(mut array) = list.next()?.ok_or(Error::Malformed)? else {
+        return Err(Error::TypeMismatch);
+    };
+    let mut samples = [0i16; 4];
+    for sample in samples.iter_mut() {
+        *sample = array.i16()?.ok_or(Error::Malformed)?;
+    }
+
+    Ok(Reading { sensor, millis, samples })
+}
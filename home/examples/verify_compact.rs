@@ -16,7 +16,7 @@ fn main() {
         let item_id = ItemId(i);
         match isocore.get_message(item_id) {
             Ok(contents) => {
-                let msg = String::from_utf8_lossy(contents);
+                let msg = String::from_utf8_lossy(&contents);
                 println!("Message {}: {}", i, msg);
             }
             Err(e) => {
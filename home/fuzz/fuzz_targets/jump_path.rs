@@ -0,0 +1,30 @@
+//! Finds jump paths between arbitrary frame numbers, which must come back
+//! as a short chain of valid jumps or a clean error rather than panic or
+//! spin.
+
+#![no_main]
+
+use home::jumpheader::JumpPathError;
+use home::jumpheader::MAX_JUMP_PATH;
+use home::jumpheader::compute_jump_indices;
+use home::jumpheader::find_jump_path;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (u64, u64)| {
+    let (from, to) = input;
+
+    let path = match find_jump_path(from, to) {
+        Ok(path) => path,
+        Err(err) => {
+            assert!(to >= from);
+            assert_eq!(err, JumpPathError::NotBehind { from, to });
+            return;
+        }
+    };
+    assert_eq!(path.first(), Some(&from));
+    assert_eq!(path.last(), Some(&to));
+    assert!(path.len() <= MAX_JUMP_PATH);
+    for step in path.windows(2) {
+        assert!(step[1] == 0 || compute_jump_indices(step[0]).contains(&step[1]));
+    }
+});
@@ -0,0 +1,29 @@
+//! Round trips generated neopack documents, and decodes the raw input as
+//! neopack, which must fail cleanly rather than panic.
+
+#![no_main]
+
+use home::neopack::Decoder;
+use home::neopack::Doc;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (Doc, &[u8])| {
+    let (doc, raw) = input;
+
+    let bytes = doc.encode().unwrap();
+    let decoded = Doc::decode(&bytes).unwrap();
+    assert_eq!(decoded.encode().unwrap(), bytes);
+
+    let mut decoder = Decoder::new(raw);
+    while decoder.remaining() > 0 {
+        let mut skipper = decoder.clone();
+        let skipped = skipper.skip_value();
+        match decoder.value().and_then(Doc::read) {
+            Ok(_) => {
+                assert!(skipped.is_ok());
+                assert_eq!(skipper.pos(), decoder.pos());
+            }
+            Err(_) => break,
+        }
+    }
+});
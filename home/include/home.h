@@ -0,0 +1,96 @@
+#ifndef HOME_H
+#define HOME_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result code returned by every FFI function.
+ */
+typedef enum HomeStatus {
+  HOME_STATUS_OK = 0,
+  HOME_STATUS_NULL_POINTER = 1,
+  HOME_STATUS_INVALID_PATH = 2,
+  HOME_STATUS_IO = 3,
+  HOME_STATUS_NOT_FOUND = 4,
+  HOME_STATUS_BUFFER_TOO_SMALL = 5,
+  HOME_STATUS_INTEGRITY = 6,
+  HOME_STATUS_FORMAT = 7,
+  HOME_STATUS_PANIC = 8,
+} HomeStatus;
+
+/**
+ * Opaque handle to an opened isocore.
+ */
+typedef struct HomeCore HomeCore;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Opens the isocore directory at `path` (NUL-terminated UTF-8).
+ * On success, writes a new handle to `out`.
+ *
+ * # Safety
+ *
+ * `path` must be a valid C string and `out` a valid pointer to write to.
+ */
+enum HomeStatus home_core_open(const char *path, struct HomeCore **out);
+
+/**
+ * Writes the number of messages in the core to `out_len`.
+ *
+ * # Safety
+ *
+ * `core` must be a live handle from `home_core_open`, `out_len` writable.
+ */
+enum HomeStatus home_core_len(const struct HomeCore *core, uint64_t *out_len);
+
+/**
+ * Reads message `index` into `buf`, verifying it against its leaf hash.
+ * The message length is always written to `out_len`; if it exceeds
+ * `buf_len`, nothing is copied and `BufferTooSmall` is returned, so
+ * callers can retry with a larger buffer. `buf` may be null when
+ * `buf_len` is zero.
+ *
+ * # Safety
+ *
+ * `core` must be a live handle, `buf` valid for `buf_len` bytes of
+ * writes, and `out_len` writable.
+ */
+enum HomeStatus home_core_read(struct HomeCore *core,
+                               uint64_t index,
+                               uint8_t *buf,
+                               uintptr_t buf_len,
+                               uintptr_t *out_len);
+
+/**
+ * Verifies the latest signed root against the tree and the core's signer.
+ * On success, writes the 32-byte global root to `out_root` if non-null.
+ *
+ * # Safety
+ *
+ * `core` must be a live handle; `out_root`, if non-null, must be valid
+ * for 32 bytes of writes.
+ */
+enum HomeStatus home_core_verify_head(struct HomeCore *core, uint8_t *out_root);
+
+/**
+ * Releases a handle. Passing null is a no-op.
+ *
+ * # Safety
+ *
+ * `core` must come from `home_core_open` and not be used afterwards.
+ */
+void home_core_free(struct HomeCore *core);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* HOME_H */
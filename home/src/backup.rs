@@ -0,0 +1,447 @@
+//! Backups: a core directory as one streamable archive
+//!
+//! `export` writes every file under a core's directory (the three cores,
+//! info.nd, the root index, and a light core's payloads) to any `Write`,
+//! so a snapshot can be piped straight to object storage. `import` reads
+//! it back into a directory.
+//!
+//! An archive starts with the `Artifact::Backup` header, then a sequence of
+//! frames, each a little-endian u32 length and a neopack value, as
+//! `TcpTransport` frames messages. The first frame is the manifest,
+//! `List<[name: String, len: u64, hash: Fixed32]>`, and the second the BLAKE3 hash of the first
+//! frame's bytes. The rest are chunks, `[file: u32, data: Bytes]`, with
+//! each file's chunks in order and the files in manifest order. The
+//! manifest comes first so `import` knows what to expect before any data
+//! arrives; `export` reads the directory twice to build it.
+//!
+//! The core must not be written to while it is exported; flush it first.
+//!
+//! `import` checks the manifest against its hash and each file against the
+//! manifest. A file is written to `<name>.part` and renamed into place
+//! once it is complete and verified, so an interrupted restore can be run
+//! again with the same archive: files already in place are skipped, and a
+//! partial file carries on from where it stopped. As with
+//! `neodisk::write_atomic`, the file is synced before the rename and its
+//! directory after, as far as the `Durability` given to
+//! `import_with_durability` asks.
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use crate::format::Artifact;
+use crate::format::FormatError;
+use crate::format::HEADER_LEN;
+use crate::key::Hash;
+use crate::key::HashBuilder;
+use crate::key::hash;
+use crate::neodisk::Durability;
+use crate::platform;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+use crate::neopack::Pack;
+use crate::neopack::ValueDecoder;
+
+/// Bytes of file data per chunk frame.
+const CHUNK: usize = 1 << 20;
+/// Largest frame `import` will accept: a chunk plus its framing, or a
+/// manifest of many files.
+const MAX_FRAME: usize = 16 << 20;
+
+#[derive(Debug)]
+pub enum BackupError {
+    Io(io::Error),
+    Neopack(neopack::Error),
+    /// The stream isn't a backup archive, or ends early.
+    BadArchive,
+    UnsupportedVersion { found: u8, expected: u8 },
+    /// The manifest doesn't match its hash.
+    ManifestHash,
+    /// A file in the archive doesn't match the manifest.
+    Corrupt(String),
+    /// A file name that would land outside the destination.
+    BadName(String),
+}
+
+impl From<io::Error> for BackupError {
+    fn from(err: io::Error) -> Self {
+        return BackupError::Io(err);
+    }
+}
+
+impl From<FormatError> for BackupError {
+    fn from(err: FormatError) -> Self {
+        return match err {
+            FormatError::UnsupportedVersion { found, expected } => BackupError::UnsupportedVersion { found, expected },
+            _ => BackupError::BadArchive,
+        };
+    }
+}
+
+impl From<neopack::Error> for BackupError {
+    fn from(err: neopack::Error) -> Self {
+        return BackupError::Neopack(err);
+    }
+}
+
+/// A file in an archive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// Path under the core directory, with `/` separators.
+    pub name: String,
+    pub len: u64,
+    pub hash: Hash,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut files = enc.list()?;
+        for file in &self.files {
+            let mut entry = files.list()?;
+            entry.str(&file.name)?;
+            entry.u64(file.len)?;
+            file.hash.write(&mut entry)?;
+            entry.finish()?;
+        }
+        files.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, BackupError> {
+        let mut dec = Decoder::new(bytes);
+        let mut entries = dec.list()?;
+        let mut files = Vec::new();
+        while let Some(entry) = entries.next()? {
+            let ValueDecoder::List(mut entry) = entry else {
+                return Err(BackupError::BadArchive);
+            };
+            let name = entry.next()?.ok_or(BackupError::BadArchive)?.as_str()?.to_string();
+            let len = entry.next()?.ok_or(BackupError::BadArchive)?.as_u64()?;
+            let hash = Hash::read(entry.next()?.ok_or(BackupError::BadArchive)?)?;
+            files.push(ManifestEntry { name, len, hash });
+        }
+        return Ok(Manifest { files });
+    }
+
+    /// The hash written after the manifest, to check it by.
+    pub fn hash(&self) -> Result<Hash, neopack::Error> {
+        return Ok(hash(&self.to_bytes()?));
+    }
+}
+
+/// What `import` did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Restore {
+    pub manifest: Manifest,
+    /// File bytes written this run.
+    pub written: u64,
+    /// File bytes already in place from an earlier run, and skipped.
+    pub skipped: u64,
+}
+
+/// Writes the core directory at `core_dir` to `writer` as one archive.
+/// Returns the manifest, whose hash can be kept to check the archive by.
+pub fn export<W: Write>(core_dir: &Path, mut writer: W) -> Result<Manifest, BackupError> {
+    let mut names = Vec::new();
+    list_files(core_dir, "", &mut names)?;
+    names.sort();
+
+    let mut files = Vec::with_capacity(names.len());
+    for name in names {
+        let (len, hash) = hash_file(&core_dir.join(&name))?;
+        files.push(ManifestEntry { name, len, hash });
+    }
+    let manifest = Manifest { files };
+    let bytes = manifest.to_bytes()?;
+    writer.write_all(&Artifact::Backup.header())?;
+    write_frame(&mut writer, &bytes)?;
+    write_frame(&mut writer, &hash(&bytes).0)?;
+
+    let mut buffer = vec![0; CHUNK];
+    for (index, file) in manifest.files.iter().enumerate() {
+        let mut source = File::open(core_dir.join(&file.name))?.take(file.len);
+        let mut sent = 0;
+        while sent < file.len {
+            let read = source.read(&mut buffer)?;
+            if read == 0 {
+                // The file shrank since the manifest was built
+                return Err(BackupError::Corrupt(file.name.clone()));
+            }
+            let mut enc = Encoder::new();
+            let mut chunk = enc.list()?;
+            chunk.u32(index as u32)?;
+            chunk.bytes(&buffer[..read])?;
+            chunk.finish()?;
+            write_frame(&mut writer, enc.as_bytes())?;
+            sent += read as u64;
+        }
+    }
+    writer.flush()?;
+    return Ok(manifest);
+}
+
+/// Restores an archive from `reader` into `dest`, creating it if needed.
+/// Safe to run again after an interruption; see the module docs.
+pub fn import<R: Read>(reader: R, dest: &Path) -> Result<Restore, BackupError> {
+    return import_with_durability(reader, dest, Durability::default());
+}
+
+/// Like `import`, syncing each restored file and the directories naming
+/// it as `durability` says.
+pub fn import_with_durability<R: Read>(mut reader: R, dest: &Path, durability: Durability) -> Result<Restore, BackupError> {
+    let mut header = [0u8; HEADER_LEN];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Err(BackupError::BadArchive),
+        Err(e) => return Err(e.into()),
+    }
+    Artifact::Backup.read_header(&header)?;
+    let bytes = read_frame(&mut reader)?.ok_or(BackupError::BadArchive)?;
+    let expected = read_frame(&mut reader)?.ok_or(BackupError::BadArchive)?;
+    if expected != hash(&bytes).0 {
+        return Err(BackupError::ManifestHash);
+    }
+    let manifest = Manifest::from_bytes(&bytes)?;
+    for file in &manifest.files {
+        check_name(&file.name)?;
+    }
+
+    std::fs::create_dir_all(dest)?;
+    let mut restore = Restore { manifest: manifest.clone(), written: 0, skipped: 0 };
+    for (index, file) in manifest.files.iter().enumerate() {
+        let path = dest.join(&file.name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Already restored, or partway there
+        let done = path.is_file() && hash_file(&path)? == (file.len, file.hash.clone());
+        let part = part_path(&path);
+        let have = match std::fs::metadata(&part) {
+            Ok(metadata) if metadata.len() <= file.len => metadata.len(),
+            _ => 0,
+        };
+        let mut out = None;
+        if !done {
+            let part_file = OpenOptions::new().create(true).append(true).open(&part)?;
+            if have == 0 {
+                part_file.set_len(0)?;
+            }
+            out = Some(part_file);
+        }
+
+        let mut received = 0;
+        while received < file.len {
+            let frame = read_frame(&mut reader)?.ok_or(BackupError::BadArchive)?;
+            let mut dec = Decoder::new(&frame);
+            let mut chunk = dec.list()?;
+            let chunk_index = chunk.next()?.ok_or(BackupError::BadArchive)?.as_u32()?;
+            let data = chunk.next()?.ok_or(BackupError::BadArchive)?.as_bytes()?;
+            if chunk_index as usize != index || received + data.len() as u64 > file.len {
+                return Err(BackupError::Corrupt(file.name.clone()));
+            }
+
+            // Skip what an earlier run already wrote
+            let start = match &mut out {
+                Some(out) => {
+                    let start = have.saturating_sub(received).min(data.len() as u64) as usize;
+                    out.write_all(&data[start..])?;
+                    restore.written += (data.len() - start) as u64;
+                    start
+                }
+                None => data.len(),
+            };
+            restore.skipped += start as u64;
+            received += data.len() as u64;
+        }
+
+        if let Some(out) = out {
+            durability.sync_file(&out)?;
+            drop(out);
+            if hash_file(&part)? != (file.len, file.hash.clone()) {
+                std::fs::remove_file(&part)?;
+                return Err(BackupError::Corrupt(file.name.clone()));
+            }
+            platform::rename(&part, &path)?;
+            // The file's directory, and any created above it
+            for entry in path.ancestors().take_while(|entry| *entry != dest) {
+                durability.sync_parent(entry)?;
+            }
+        }
+    }
+    return Ok(restore);
+}
+
+/// Names of the files under `dir`, relative to the core directory.
+fn list_files(dir: &Path, prefix: &str, names: &mut Vec<String>) -> Result<(), BackupError> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().into_string()
+            .map_err(|name| BackupError::BadName(name.to_string_lossy().into_owned()))?;
+        let name = format!("{}{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), &format!("{}/", name), names)?;
+        } else {
+            names.push(name);
+        }
+    }
+    return Ok(());
+}
+
+/// Rejects names that are empty, absolute, or climb out of the destination.
+fn check_name(name: &str) -> Result<(), BackupError> {
+    let ok = !name.is_empty()
+        && name.split('/').all(|part| !part.is_empty() && part != "." && part != ".." && !part.contains('\\'))
+        && !Path::new(name).has_root();
+    if !ok {
+        return Err(BackupError::BadName(name.to_string()));
+    }
+    return Ok(());
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    return path.with_file_name(name);
+}
+
+fn hash_file(path: &Path) -> Result<(u64, Hash), BackupError> {
+    let mut file = File::open(path)?;
+    let mut hasher = HashBuilder::new();
+    let mut buffer = vec![0; CHUNK];
+    let mut len = 0;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok((len, hasher.finish()));
+        }
+        hasher.update(&buffer[..read]);
+        len += read as u64;
+    }
+}
+
+fn write_frame<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<(), BackupError> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)?;
+    return Ok(());
+}
+
+/// The next frame, or `None` at a clean end of stream.
+fn read_frame<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, BackupError> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(BackupError::BadArchive);
+    }
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => BackupError::BadArchive,
+        _ => BackupError::Io(e),
+    })?;
+    return Ok(Some(bytes));
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+    use crate::isocore::IsoCore;
+    use crate::key::KeyPair;
+
+    #[test]
+    fn export_and_resumable_import() {
+        let source = PathBuf::from("/tmp/test_backup_source");
+        let dest = PathBuf::from("/tmp/test_backup_dest");
+        let _ = std::fs::remove_dir_all(&source);
+        let _ = std::fs::remove_dir_all(&dest);
+        let signer = KeyPair::ephemeral();
+
+        let mut core = IsoCore::create(source.clone(), &signer).unwrap();
+        for i in 0..50 {
+            core.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+        core.flush().unwrap();
+        let root = core.verify_head().unwrap();
+        drop(core);
+
+        let mut archive = Vec::new();
+        let manifest = export(&source, &mut archive).unwrap();
+        assert!(manifest.files.iter().any(|file| file.name == "data.nd"));
+
+        // Cut off partway through, then run again with the whole archive
+        let cut = archive.len() * 2 / 3;
+        assert!(matches!(import(&archive[..cut], &dest), Err(BackupError::BadArchive)));
+        let restore = import(&archive[..], &dest).unwrap();
+        assert_eq!(restore.manifest, manifest);
+        let total: u64 = manifest.files.iter().map(|file| file.len).sum();
+        assert!(restore.skipped > 0);
+        assert_eq!(restore.written + restore.skipped, total);
+
+        let mut restored = IsoCore::load(&dest).unwrap();
+        assert_eq!(restored.len().0, 50);
+        assert_eq!(restored.verify_head().unwrap(), root);
+
+        // Nothing left to write on a third run
+        let again = import(&archive[..], &dest).unwrap();
+        assert_eq!((again.written, again.skipped), (0, total));
+
+        std::fs::remove_dir_all(&source).unwrap();
+        std::fs::remove_dir_all(&dest).unwrap();
+    }
+
+    #[test]
+    fn import_rejects_damage() {
+        let source = PathBuf::from("/tmp/test_backup_damage");
+        let dest = PathBuf::from("/tmp/test_backup_damage_dest");
+        let _ = std::fs::remove_dir_all(&source);
+        let _ = std::fs::remove_dir_all(&dest);
+        std::fs::create_dir_all(source.join("payloads")).unwrap();
+        std::fs::write(source.join("info.nd"), b"info").unwrap();
+        std::fs::write(source.join("payloads").join("one"), b"payload").unwrap();
+
+        let mut archive = Vec::new();
+        let manifest = export(&source, &mut archive).unwrap();
+        let names: Vec<&str> = manifest.files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["info.nd", "payloads/one"]);
+
+        let mut flipped = archive.clone();
+        *flipped.last_mut().unwrap() ^= 1;
+        assert!(matches!(import(&flipped[..], &dest), Err(BackupError::Corrupt(name)) if name == "payloads/one"));
+        assert!(!dest.join("payloads").join("one").exists());
+        import_with_durability(&archive[..], &dest, Durability::Data).unwrap();
+        assert_eq!(std::fs::read(dest.join("payloads").join("one")).unwrap(), b"payload");
+
+        let mut flipped = archive.clone();
+        flipped[HEADER_LEN + 8] ^= 1;
+        assert!(matches!(import(&flipped[..], &dest), Err(BackupError::ManifestHash)));
+
+        let mut flipped = archive.clone();
+        flipped[0] ^= 1;
+        assert!(matches!(import(&flipped[..], &dest), Err(BackupError::BadArchive)));
+        flipped[0] ^= 1;
+        flipped[HEADER_LEN - 1] = 2;
+        assert!(matches!(import(&flipped[..], &dest), Err(BackupError::UnsupportedVersion { found: 2, expected: 1 })));
+
+        for name in ["", "/etc/passwd", "../up", "a//b", "a/./b"] {
+            assert!(matches!(check_name(name), Err(BackupError::BadName(_))), "{}", name);
+        }
+        check_name("payloads/one").unwrap();
+
+        std::fs::remove_dir_all(&source).unwrap();
+        std::fs::remove_dir_all(&dest).unwrap();
+    }
+}
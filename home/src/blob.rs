@@ -0,0 +1,126 @@
+//! Content-addressed blobs
+//!
+//! Images and attachments are too big to be items in a core, so they live
+//! beside it in a `BlobStore` and documents refer to them by hash (see
+//! `markup`). A store is a directory with one file per blob, named by the
+//! hex BLAKE3 hash of its contents. The same bytes always land in the same
+//! file, so putting a blob twice, or fetching one a peer already has, is
+//! free.
+//!
+//! `put` writes to a temporary file, syncs it, renames it into place and
+//! syncs the directory, as `neodisk::write_atomic` does, so a crash never
+//! leaves part of a blob under its hash. How much of that syncing happens
+//! is set with `with_durability`. `get` hashes what it reads and reports a
+//! file that doesn't match as corrupt rather than serving it.
+
+use std::io;
+use std::path::PathBuf;
+use crate::hex;
+use crate::key::hash;
+use crate::key::Hash;
+use crate::neodisk::write_atomic;
+use crate::neodisk::Durability;
+
+#[derive(Debug)]
+pub enum BlobError {
+    Io(io::Error),
+    /// The store has no blob with this hash.
+    Missing(Hash),
+    /// The file stored under this hash holds something else.
+    Corrupt(Hash),
+}
+
+impl From<io::Error> for BlobError {
+    fn from(err: io::Error) -> Self {
+        return BlobError::Io(err);
+    }
+}
+
+/// A directory of blobs, each named by its hash.
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    dir: PathBuf,
+    durability: Durability,
+}
+
+impl BlobStore {
+    /// Opens the store in `dir`, creating the directory if needed.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, BlobError> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        return Ok(BlobStore { dir, durability: Durability::default() });
+    }
+
+    /// Sets how blobs are synced as they are put.
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        return self;
+    }
+
+    /// Where the blob with this hash is, or would be, stored.
+    pub fn path(&self, hash: &Hash) -> PathBuf {
+        return self.dir.join(hex::encode(&hash.0));
+    }
+
+    pub fn contains(&self, hash: &Hash) -> bool {
+        return self.path(hash).is_file();
+    }
+
+    /// Stores `bytes`, returning the hash to refer to them by.
+    pub fn put(&self, bytes: &[u8]) -> Result<Hash, BlobError> {
+        let hash = hash(bytes);
+        let path = self.path(&hash);
+        if !path.is_file() {
+            write_atomic(&path, bytes, self.durability)?;
+        }
+        return Ok(hash);
+    }
+
+    pub fn get(&self, hash: &Hash) -> Result<Vec<u8>, BlobError> {
+        let bytes = match std::fs::read(self.path(hash)) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(BlobError::Missing(hash.clone())),
+            Err(err) => return Err(err.into()),
+        };
+        if crate::key::hash(&bytes) != *hash {
+            return Err(BlobError::Corrupt(hash.clone()));
+        }
+        return Ok(bytes);
+    }
+
+    /// The hashes in `hashes` the store doesn't have yet, for a replicator
+    /// to fetch.
+    pub fn missing<'a>(&self, hashes: impl IntoIterator<Item = &'a Hash>) -> Vec<Hash> {
+        return hashes.into_iter().filter(|hash| !self.contains(hash)).cloned().collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blobs_are_stored_by_hash() {
+        let dir = PathBuf::from("/tmp/test_blob_store");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = BlobStore::open(&dir).unwrap();
+
+        let a = store.put(b"a picture").unwrap();
+        assert_eq!(a, hash(b"a picture"));
+        assert_eq!(store.put(b"a picture").unwrap(), a);
+        assert_eq!(store.get(&a).unwrap(), b"a picture");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let unsynced = BlobStore::open(&dir).unwrap().with_durability(Durability::None);
+        assert_eq!(unsynced.get(&unsynced.put(b"a sketch").unwrap()).unwrap(), b"a sketch");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        let b = hash(b"not stored");
+        assert!(matches!(store.get(&b), Err(BlobError::Missing(h)) if h == b));
+        assert_eq!(store.missing([&a, &b]), [b]);
+
+        std::fs::write(store.path(&a), b"something else").unwrap();
+        assert!(matches!(store.get(&a), Err(BlobError::Corrupt(h)) if h == a));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
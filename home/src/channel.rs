@@ -0,0 +1,341 @@
+//! Channels: a chat kept as a log of messages in an IsoCore
+//!
+//! Each item is a `Message`: who wrote it, when, a markup body, and, for a
+//! reply, a `Link` to the message it answers. The link names the parent by
+//! its core's key, so a reply can answer a message in someone else's
+//! channel as well as one in its own.
+//!
+//! A `Channel` indexes the replies to its own messages as it reads and
+//! sends them, so `thread` can lay out a conversation without rescanning
+//! the log. Replies that live in other cores are only found by reading
+//! those cores' channels.
+//!
+//! A message is a neopack Map: `author: Fixed32`, `time: Timestamp`,
+//! `body: String`, and, for a reply, `reply_to: Bytes` holding an encoded
+//! `Link`. Links are stored without proofs; the channel's core can prove
+//! any of its items again with `Link::new`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use crate::covering::ItemId;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::key::Hash;
+use crate::key::KeyPair;
+use crate::key::KeyPub;
+use crate::link::Link;
+use crate::link::LinkError;
+use crate::markup;
+use crate::markup::Item;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+use crate::neopack::Pack;
+use crate::neopack::Timestamp;
+
+#[derive(Debug)]
+pub enum ChannelError {
+    IsoCore(IsoCoreError),
+    Neopack(neopack::Error),
+    Link(LinkError),
+    /// The item isn't a well-formed message.
+    BadRecord(ItemId),
+    /// The item to reply to or show a thread for isn't in the channel.
+    NoSuchMessage(ItemId),
+}
+
+impl From<IsoCoreError> for ChannelError {
+    fn from(err: IsoCoreError) -> Self {
+        return ChannelError::IsoCore(err);
+    }
+}
+
+impl From<neopack::Error> for ChannelError {
+    fn from(err: neopack::Error) -> Self {
+        return ChannelError::Neopack(err);
+    }
+}
+
+impl From<LinkError> for ChannelError {
+    fn from(err: LinkError) -> Self {
+        return ChannelError::Link(err);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub author: KeyPub,
+    pub time: Timestamp,
+    /// Markup; see `markup`.
+    pub body: String,
+    pub reply_to: Option<Link>,
+}
+
+impl Message {
+    pub fn new(author: KeyPub, time: Timestamp, body: impl Into<String>) -> Self {
+        return Message { author, time, body: body.into(), reply_to: None };
+    }
+
+    /// The same message as a reply to `parent`.
+    pub fn reply_to(self, parent: Link) -> Self {
+        return Message { reply_to: Some(parent.without_proof()), ..self };
+    }
+
+    pub fn items(&self) -> Vec<Item> {
+        return markup::parse_string(self.body.clone());
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        self.author.write(&mut map.key("author")?)?;
+        map.key("time")?.timestamp(self.time)?;
+        map.key("body")?.str(&self.body)?;
+        if let Some(parent) = &self.reply_to {
+            map.key("reply_to")?.bytes(&parent.to_bytes()?)?;
+        }
+        map.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ChannelError> {
+        let mut map = Decoder::new(bytes).map()?;
+        let Some(("author", author)) = map.next()? else {
+            return Err(neopack::Error::Malformed.into());
+        };
+        let author = KeyPub::read(author)?;
+        let Some(("time", time)) = map.next()? else {
+            return Err(neopack::Error::Malformed.into());
+        };
+        let time = time.as_timestamp()?;
+        let Some(("body", body)) = map.next()? else {
+            return Err(neopack::Error::Malformed.into());
+        };
+        let body = body.as_str()?.to_string();
+        let reply_to = match map.next()? {
+            Some(("reply_to", parent)) => Some(Link::from_bytes(parent.as_bytes()?)?),
+            None => None,
+            Some(_) => return Err(neopack::Error::Malformed.into()),
+        };
+        return Ok(Message { author, time, body, reply_to });
+    }
+}
+
+/// A message in a thread, and how many replies deep it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreadEntry {
+    pub item_id: ItemId,
+    pub depth: usize,
+    pub message: Message,
+}
+
+#[derive(Debug)]
+pub struct Channel {
+    core: IsoCore,
+    /// Replies to each of the channel's own messages, oldest first.
+    replies: BTreeMap<ItemId, Vec<ItemId>>,
+}
+
+impl Channel {
+    /// Wraps `core`, indexing every message already in it.
+    pub fn new(core: IsoCore) -> Result<Self, ChannelError> {
+        let mut channel = Self {
+            core,
+            replies: BTreeMap::new(),
+        };
+        for item in 0..channel.core.len().0 as u64 {
+            let item_id = ItemId(item);
+            let message = channel.read(item_id)?;
+            channel.index(item_id, &message);
+        }
+        return Ok(channel);
+    }
+
+    pub fn create_mem(signer: &KeyPair) -> Self {
+        return Self {
+            core: IsoCore::create_mem(signer),
+            replies: BTreeMap::new(),
+        };
+    }
+
+    pub fn create(path: PathBuf, signer: &KeyPair) -> Result<Self, ChannelError> {
+        return Ok(Self {
+            core: IsoCore::create(path, signer)?,
+            replies: BTreeMap::new(),
+        });
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ChannelError> {
+        return Self::new(IsoCore::load(path)?);
+    }
+
+    /// The channel's core, for reads such as its root or signer. Messages
+    /// are added through `send`, which keeps the reply index in step.
+    pub fn core(&self) -> &IsoCore {
+        return &self.core;
+    }
+
+    pub fn flush(&mut self) -> Result<(), ChannelError> {
+        self.core.flush()?;
+        return Ok(());
+    }
+
+    pub fn len(&self) -> u64 {
+        return self.core.len().0 as u64;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /// Appends `message`, which may have any author; the channel's signer
+    /// vouches only that it was posted here.
+    pub fn send(&mut self, message: Message, signer: &KeyPair) -> Result<ItemId, ChannelError> {
+        let item_id = ItemId(self.len());
+        self.core.add_message(&message.to_bytes()?, signer)?;
+        self.index(item_id, &message);
+        return Ok(item_id);
+    }
+
+    /// Posts `body` now, written by `signer`.
+    pub fn post(&mut self, body: &str, signer: &KeyPair) -> Result<ItemId, ChannelError> {
+        let message = Message::new(signer.key_pub.clone(), Timestamp::now()?, body);
+        return self.send(message, signer);
+    }
+
+    /// Posts `body` now as a reply to `parent`, one of the channel's own
+    /// messages.
+    pub fn reply(&mut self, parent: ItemId, body: &str, signer: &KeyPair) -> Result<ItemId, ChannelError> {
+        let message = Message::new(signer.key_pub.clone(), Timestamp::now()?, body)
+            .reply_to(self.link(parent)?);
+        return self.send(message, signer);
+    }
+
+    /// A link to one of the channel's messages, with a proof, for replies
+    /// from other channels.
+    pub fn link(&mut self, item_id: ItemId) -> Result<Link, ChannelError> {
+        if item_id.0 >= self.len() {
+            return Err(ChannelError::NoSuchMessage(item_id));
+        }
+        return Ok(Link::new(&mut self.core, item_id)?);
+    }
+
+    /// Reads one message, checked against the log.
+    pub fn get(&mut self, item_id: ItemId) -> Result<Message, ChannelError> {
+        if item_id.0 >= self.len() {
+            return Err(ChannelError::NoSuchMessage(item_id));
+        }
+        return self.read(item_id);
+    }
+
+    /// The messages in `range`, oldest first, with their item ids.
+    pub fn list(&mut self, range: std::ops::Range<u64>) -> Result<Vec<(ItemId, Message)>, ChannelError> {
+        let end = range.end.min(self.len());
+        return (range.start..end)
+            .map(|item| Ok((ItemId(item), self.read(ItemId(item))?)))
+            .collect();
+    }
+
+    /// Replies to `item_id` in this channel, oldest first.
+    pub fn replies(&self, item_id: ItemId) -> &[ItemId] {
+        return self.replies.get(&item_id).map(Vec::as_slice).unwrap_or_default();
+    }
+
+    /// The conversation under `root`: `root` itself, then each reply
+    /// followed by its own replies, depth first, oldest first at each
+    /// level.
+    pub fn thread(&mut self, root: ItemId) -> Result<Vec<ThreadEntry>, ChannelError> {
+        let mut entries = Vec::new();
+        let mut stack = vec![(root, 0)];
+        while let Some((item_id, depth)) = stack.pop() {
+            let message = self.get(item_id)?;
+            entries.push(ThreadEntry { item_id, depth, message });
+            stack.extend(self.replies(item_id).iter().rev().map(|&reply| (reply, depth + 1)));
+        }
+        return Ok(entries);
+    }
+
+    /// Checks the signed root over the whole log. See `IsoCore::verify_head`.
+    pub fn verify_head(&mut self) -> Result<Hash, ChannelError> {
+        return Ok(self.core.verify_head()?);
+    }
+
+    fn read(&mut self, item_id: ItemId) -> Result<Message, ChannelError> {
+        let bytes = self.core.get_message(item_id)?;
+        return Message::from_bytes(&bytes).map_err(|_| ChannelError::BadRecord(item_id));
+    }
+
+    fn index(&mut self, item_id: ItemId, message: &Message) {
+        if let Some(parent) = &message.reply_to
+            && parent.pubkey == *self.core.signer()
+            && parent.item_id < item_id
+        {
+            self.replies.entry(parent.item_id).or_default().push(item_id);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_list_and_thread() {
+        let path = PathBuf::from("/tmp/test_channel");
+        let _ = std::fs::remove_dir_all(&path);
+        let alice = KeyPair::ephemeral();
+        let bob = KeyPair::ephemeral();
+
+        let mut channel = Channel::create(path.clone(), &alice).unwrap();
+        let hello = channel.post("# Hello\nanyone *here*?", &alice).unwrap();
+        let other = channel.post("another topic", &alice).unwrap();
+        let yes = channel.reply(hello, "yes", &alice).unwrap();
+        let relayed = Message::new(bob.key_pub.clone(), Timestamp(5), "me too")
+            .reply_to(channel.link(hello).unwrap());
+        let me_too = channel.send(relayed.clone(), &alice).unwrap();
+        let nested = channel.reply(yes, "good", &alice).unwrap();
+        assert!(matches!(channel.reply(ItemId(99), "lost", &alice), Err(ChannelError::NoSuchMessage(ItemId(99)))));
+        channel.flush().unwrap();
+        drop(channel);
+
+        let mut channel = Channel::load(&path).unwrap();
+        assert_eq!(channel.len(), 5);
+        assert_eq!(channel.core().signer(), &alice.key_pub);
+        assert_eq!(channel.get(me_too).unwrap(), relayed);
+        assert_eq!(channel.get(hello).unwrap().items().len(), 2);
+        let listed = channel.list(1..10).unwrap();
+        assert_eq!(listed.iter().map(|(item, _)| *item).collect::<Vec<_>>(), [other, yes, me_too, nested]);
+        assert_eq!(listed[1].1.reply_to.as_ref().unwrap().item_id, hello);
+        assert_eq!(channel.replies(hello), [yes, me_too]);
+        assert_eq!(channel.replies(other), []);
+
+        let thread = channel.thread(hello).unwrap();
+        let shape: Vec<_> = thread.iter().map(|entry| (entry.item_id, entry.depth)).collect();
+        assert_eq!(shape, [(hello, 0), (yes, 1), (nested, 2), (me_too, 1)]);
+        assert_eq!(thread[3].message.author, bob.key_pub);
+        channel.verify_head().unwrap();
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn replies_across_channels() {
+        let alice = KeyPair::ephemeral();
+        let bob = KeyPair::ephemeral();
+        let mut ours = Channel::create_mem(&alice);
+        let mut theirs = Channel::create_mem(&bob);
+
+        let question = ours.post("question", &alice).unwrap();
+        let link = ours.link(question).unwrap();
+        let answer = Message::new(bob.key_pub.clone(), Timestamp(1), "answer").reply_to(link.clone());
+        let answer = theirs.send(answer, &bob).unwrap();
+
+        // The reply points into the other core, so neither channel indexes it
+        assert_eq!(theirs.replies(question), []);
+        let parent = theirs.get(answer).unwrap().reply_to.unwrap();
+        assert_eq!(parent, link.without_proof());
+        parent.verify_with(link.proof.as_ref().unwrap()).unwrap();
+        assert!(matches!(Message::from_bytes(b"junk"), Err(ChannelError::Neopack(_))));
+    }
+}
@@ -0,0 +1,364 @@
+//! Vector commitments for verkle nodes
+//!
+//! A verkle node is hashed by concatenating its child hashes, so showing
+//! that a hash is one of a node's children means shipping all of them.
+//! This module commits to the children instead, as a Pedersen vector
+//! commitment over ristretto: each child hash is mapped to a scalar `a_i`
+//! and the node commits to `C = sum(a_i * G_i)` for fixed generators
+//! `G_i`. An inner product argument then opens `C` at one position, proving
+//! `a_i` is the child at index `i` with `2 * log2(width)` points and one
+//! scalar, without the other children.
+//!
+//! The argument is the Bulletproofs one for `<a, b> = v` with `b` the unit
+//! vector at `i`, made non-interactive with a blake3 transcript over the
+//! commitment, index, value, and each round's points. Commitments are not
+//! blinded; child hashes are public anyway.
+//!
+//! The tradeoff, from `examples/commitment_bench.rs` in a release build:
+//!
+//! | width | siblings | opening | hash node | commit | open    | verify  |
+//! |-------|----------|---------|-----------|--------|---------|---------|
+//! | 8     | 224 B    | 224 B   | 0.3 us    | 0.1 ms | 1.2 ms  | 0.8 ms  |
+//! | 256   | 8160 B   | 544 B   | 10 us     | 2.2 ms | 23 ms   | 13 ms   |
+//!
+//! At the width IsoCore uses, 8, an opening is exactly as large as the
+//! seven sibling hashes it replaces and thousands of times slower to make
+//! and check, so cores keep hashing their nodes. Openings only pay for
+//! themselves in much wider nodes.
+
+use std::sync::OnceLock;
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::ristretto::RistrettoPoint;
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::VartimeMultiscalarMul;
+use crate::isocore::VerkleNode;
+use crate::format::WIDTH;
+use crate::key::Hash;
+
+const GENERATOR_CONTEXT: &str = "home verkle commitment generator";
+const SCALAR_CONTEXT: &str = "home verkle commitment scalar";
+const TRANSCRIPT_CONTEXT: &str = "home verkle commitment transcript";
+const HASH_CONTEXT: &str = "home verkle commitment hash";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitmentError {
+    /// More children than the generators cover, or an index past the end.
+    Width,
+    /// A point or scalar doesn't decode, or an opening has the wrong length.
+    Encoding,
+    /// The opening doesn't show the child at the index.
+    Mismatch,
+}
+
+/// A commitment to the children of one node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Commitment(pub [u8; 32]);
+
+impl Commitment {
+    /// The hash a parent node records for this child.
+    pub fn to_hash(&self) -> Hash {
+        let mut hasher = blake3::Hasher::new_derive_key(HASH_CONTEXT);
+        hasher.update(&self.0);
+        return Hash(*hasher.finalize().as_bytes());
+    }
+}
+
+/// Proof that a commitment holds a given child at a given index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opening {
+    /// One `(L, R)` pair per halving of the width.
+    rounds: Vec<(CompressedRistretto, CompressedRistretto)>,
+    /// The single value left after the last round.
+    a: Scalar,
+}
+
+impl Opening {
+    /// `L` and `R` of each round, then the final scalar, 32 bytes each.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.rounds.len() * 64 + 32);
+        for (l, r) in &self.rounds {
+            out.extend_from_slice(l.as_bytes());
+            out.extend_from_slice(r.as_bytes());
+        }
+        out.extend_from_slice(self.a.as_bytes());
+        return out;
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CommitmentError> {
+        if bytes.len() < 32 || !(bytes.len() - 32).is_multiple_of(64) {
+            return Err(CommitmentError::Encoding);
+        }
+        let (points, a) = bytes.split_at(bytes.len() - 32);
+        let rounds = points.chunks_exact(64)
+            .map(|pair| (compressed(&pair[..32]), compressed(&pair[32..])))
+            .collect();
+        let a = Option::from(Scalar::from_canonical_bytes(a.try_into().unwrap()))
+            .ok_or(CommitmentError::Encoding)?;
+        return Ok(Opening { rounds, a });
+    }
+}
+
+/// The fixed points commitments are made against, derived by hashing, so
+/// nobody knows a relation between them.
+#[derive(Debug, Clone)]
+pub struct Generators {
+    g: Vec<RistrettoPoint>,
+    /// Binds the opened value into the argument.
+    u: RistrettoPoint,
+}
+
+impl Generators {
+    /// Generators for nodes of up to `width` children. Panics unless
+    /// `width` is a power of two.
+    pub fn new(width: usize) -> Self {
+        assert!(width.is_power_of_two(), "width must be a power of two");
+        let g = (0..width as u64).map(|i| generator(&i.to_le_bytes())).collect();
+        return Generators { g, u: generator(b"u") };
+    }
+
+    /// Generators for IsoCore's node width.
+    pub fn standard() -> &'static Generators {
+        static STANDARD: OnceLock<Generators> = OnceLock::new();
+        return STANDARD.get_or_init(|| Generators::new(WIDTH as usize));
+    }
+
+    pub fn width(&self) -> usize {
+        return self.g.len();
+    }
+
+    /// Commits to `children`; missing positions count as zero.
+    pub fn commit(&self, children: &[Hash]) -> Result<Commitment, CommitmentError> {
+        let a = self.scalars(children)?;
+        let point = RistrettoPoint::vartime_multiscalar_mul(&a, &self.g);
+        return Ok(Commitment(point.compress().to_bytes()));
+    }
+
+    /// Proves that the commitment to `children` holds `children[index]`.
+    pub fn open(&self, children: &[Hash], index: usize) -> Result<Opening, CommitmentError> {
+        if index >= children.len() {
+            return Err(CommitmentError::Width);
+        }
+        let commitment = self.commit(children)?;
+        let mut a = self.scalars(children)?;
+        let mut b = unit(self.width(), index);
+        let mut g = self.g.clone();
+        let mut transcript = Transcript::new(&commitment, index, &children[index]);
+        let q = self.u * transcript.challenge();
+
+        let mut rounds = Vec::new();
+        while a.len() > 1 {
+            let half = a.len() / 2;
+            let (a_lo, a_hi) = a.split_at(half);
+            let (b_lo, b_hi) = b.split_at(half);
+            let (g_lo, g_hi) = g.split_at(half);
+
+            let l = RistrettoPoint::vartime_multiscalar_mul(a_lo, g_hi) + q * inner(a_lo, b_hi);
+            let r = RistrettoPoint::vartime_multiscalar_mul(a_hi, g_lo) + q * inner(a_hi, b_lo);
+            let (l, r) = (l.compress(), r.compress());
+            let x = transcript.round(&l, &r);
+            let x_inv = x.invert();
+
+            a = fold(a_lo, a_hi, x, x_inv);
+            b = fold(b_lo, b_hi, x_inv, x);
+            g = fold_points(g_lo, g_hi, x_inv, x);
+            rounds.push((l, r));
+        }
+        return Ok(Opening { rounds, a: a[0] });
+    }
+
+    /// Checks that `opening` shows `child` at `index` in `commitment`.
+    pub fn verify(&self, commitment: &Commitment, index: usize, child: &Hash, opening: &Opening) -> Result<(), CommitmentError> {
+        if index >= self.width() {
+            return Err(CommitmentError::Width);
+        }
+        if opening.rounds.len() != self.width().trailing_zeros() as usize {
+            return Err(CommitmentError::Encoding);
+        }
+        let c = decompress(&CompressedRistretto(commitment.0))?;
+        let mut transcript = Transcript::new(commitment, index, child);
+        let q = self.u * transcript.challenge();
+
+        let mut p = c + q * to_scalar(child);
+        let mut b = unit(self.width(), index);
+        let mut g = self.g.clone();
+        for (l, r) in &opening.rounds {
+            let x = transcript.round(l, r);
+            let x_inv = x.invert();
+            p += decompress(l)? * (x * x) + decompress(r)? * (x_inv * x_inv);
+
+            let half = b.len() / 2;
+            b = fold(&b[..half], &b[half..], x_inv, x);
+            g = fold_points(&g[..half], &g[half..], x_inv, x);
+        }
+
+        let expected = g[0] * opening.a + q * (opening.a * b[0]);
+        if p != expected {
+            return Err(CommitmentError::Mismatch);
+        }
+        return Ok(());
+    }
+
+    fn scalars(&self, children: &[Hash]) -> Result<Vec<Scalar>, CommitmentError> {
+        if children.len() > self.width() {
+            return Err(CommitmentError::Width);
+        }
+        let mut a: Vec<Scalar> = children.iter().map(to_scalar).collect();
+        a.resize(self.width(), Scalar::ZERO);
+        return Ok(a);
+    }
+}
+
+impl VerkleNode {
+    /// Commits to this node's child hashes with the standard generators.
+    pub fn commit(&self) -> Result<Commitment, CommitmentError> {
+        return Generators::standard().commit(&self.child_hashes());
+    }
+
+    /// Proves which hash is this node's child at `index`.
+    pub fn open(&self, index: usize) -> Result<Opening, CommitmentError> {
+        return Generators::standard().open(&self.child_hashes(), index);
+    }
+
+    fn child_hashes(&self) -> Vec<Hash> {
+        return self.children.iter().map(|child| child.hash.clone()).collect();
+    }
+}
+
+/// Fiat-Shamir challenges, each bound to everything sent before it.
+struct Transcript(blake3::Hasher);
+
+impl Transcript {
+    fn new(commitment: &Commitment, index: usize, child: &Hash) -> Self {
+        let mut hasher = blake3::Hasher::new_derive_key(TRANSCRIPT_CONTEXT);
+        hasher.update(&commitment.0);
+        hasher.update(&(index as u64).to_le_bytes());
+        hasher.update(&child.0);
+        return Transcript(hasher);
+    }
+
+    fn round(&mut self, l: &CompressedRistretto, r: &CompressedRistretto) -> Scalar {
+        self.0.update(l.as_bytes());
+        self.0.update(r.as_bytes());
+        return self.challenge();
+    }
+
+    fn challenge(&mut self) -> Scalar {
+        let scalar = wide_scalar(&self.0);
+        // Chain the challenge in so the next one depends on it
+        self.0.update(scalar.as_bytes());
+        return scalar;
+    }
+}
+
+fn generator(label: &[u8]) -> RistrettoPoint {
+    let mut hasher = blake3::Hasher::new_derive_key(GENERATOR_CONTEXT);
+    hasher.update(label);
+    let mut bytes = [0; 64];
+    hasher.finalize_xof().fill(&mut bytes);
+    return RistrettoPoint::from_uniform_bytes(&bytes);
+}
+
+fn to_scalar(hash: &Hash) -> Scalar {
+    let mut hasher = blake3::Hasher::new_derive_key(SCALAR_CONTEXT);
+    hasher.update(&hash.0);
+    return wide_scalar(&hasher);
+}
+
+fn wide_scalar(hasher: &blake3::Hasher) -> Scalar {
+    let mut bytes = [0; 64];
+    hasher.finalize_xof().fill(&mut bytes);
+    return Scalar::from_bytes_mod_order_wide(&bytes);
+}
+
+fn unit(width: usize, index: usize) -> Vec<Scalar> {
+    let mut b = vec![Scalar::ZERO; width];
+    b[index] = Scalar::ONE;
+    return b;
+}
+
+fn inner(a: &[Scalar], b: &[Scalar]) -> Scalar {
+    return a.iter().zip(b).map(|(a, b)| a * b).sum();
+}
+
+/// `lo * x + hi * y`, elementwise.
+fn fold(lo: &[Scalar], hi: &[Scalar], x: Scalar, y: Scalar) -> Vec<Scalar> {
+    return lo.iter().zip(hi).map(|(lo, hi)| lo * x + hi * y).collect();
+}
+
+fn fold_points(lo: &[RistrettoPoint], hi: &[RistrettoPoint], x: Scalar, y: Scalar) -> Vec<RistrettoPoint> {
+    return lo.iter().zip(hi)
+        .map(|(lo, hi)| RistrettoPoint::vartime_multiscalar_mul([x, y], [lo, hi]))
+        .collect();
+}
+
+fn compressed(bytes: &[u8]) -> CompressedRistretto {
+    return CompressedRistretto(bytes.try_into().unwrap());
+}
+
+fn decompress(point: &CompressedRistretto) -> Result<RistrettoPoint, CommitmentError> {
+    return point.decompress().ok_or(CommitmentError::Encoding);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isocore::NodeChild;
+    use crate::isocore::NodeType;
+    use crate::core::MessageId;
+    use crate::key::hash;
+
+    fn children(count: usize) -> Vec<Hash> {
+        return (0..count).map(|i| hash(format!("child {}", i).as_bytes())).collect();
+    }
+
+    #[test]
+    fn openings_verify_only_for_their_child() {
+        let generators = Generators::standard();
+        let children = children(8);
+        let commitment = generators.commit(&children).unwrap();
+
+        for index in 0..8 {
+            let opening = generators.open(&children, index).unwrap();
+            assert_eq!(opening.to_bytes().len(), 7 * 32);
+            let opening = Opening::from_bytes(&opening.to_bytes()).unwrap();
+            generators.verify(&commitment, index, &children[index], &opening).unwrap();
+
+            let other = (index + 1) % 8;
+            assert_eq!(generators.verify(&commitment, other, &children[index], &opening), Err(CommitmentError::Mismatch));
+            assert_eq!(generators.verify(&commitment, index, &children[other], &opening), Err(CommitmentError::Mismatch));
+        }
+
+        // A different node's commitment doesn't accept the opening
+        let mut changed = children.clone();
+        changed[3] = hash(b"changed");
+        let changed = generators.commit(&changed).unwrap();
+        let opening = generators.open(&children, 0).unwrap();
+        assert_eq!(generators.verify(&changed, 0, &children[0], &opening), Err(CommitmentError::Mismatch));
+
+        assert_eq!(generators.open(&children, 8), Err(CommitmentError::Width));
+        assert_eq!(generators.commit(&self::children(9)), Err(CommitmentError::Width));
+        assert_eq!(Opening::from_bytes(&[0; 40]), Err(CommitmentError::Encoding));
+    }
+
+    #[test]
+    fn verkle_nodes_commit_to_partial_children() {
+        let node = VerkleNode {
+            children: children(3).into_iter().enumerate().map(|(i, hash)| NodeChild {
+                node_type: NodeType::Leaf,
+                hash,
+                index: MessageId(i as u16),
+                data: None,
+            }).collect(),
+        };
+        let commitment = node.commit().unwrap();
+        let opening = node.open(2).unwrap();
+        Generators::standard().verify(&commitment, 2, &node.children[2].hash, &opening).unwrap();
+        assert_ne!(commitment.to_hash(), node.compute_hash(crate::isocore::FormatVersion::CURRENT));
+
+        let wide = Generators::new(32);
+        let children = children(20);
+        let opening = wide.open(&children, 17).unwrap();
+        assert_eq!(opening.to_bytes().len(), 11 * 32);
+        wide.verify(&wide.commit(&children).unwrap(), 17, &children[17], &opening).unwrap();
+    }
+}
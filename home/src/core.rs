@@ -1,11 +1,28 @@
 //! A core is an append-only log of byte messages.
+//!
+//! Appended messages are held in memory until a flush hands them to the
+//! log. `flush` writes them all and syncs; `flush_some` writes a bounded
+//! batch, so a big flush can be spread over the ticks of an event loop,
+//! and `flush_with_progress` reports after each batch and can be stopped
+//! between them. Messages written without the final sync are in the log
+//! but not yet durable; the next flush picks up where the last stopped.
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::ops::Range;
 use std::path::Path;
 use std::path::PathBuf;
 
+use crate::metrics;
+use crate::metrics::MetricsHandle;
 use crate::neopack;
-use crate::neodisk::{NeoDiskWriter, NeoDiskReader, MessageId as DiskMessageId};
+use crate::platform;
+use crate::neodisk::{Durability, NeoDiskWriter, NeoDiskReader, MessageId as DiskMessageId};
+
+/// Messages read per batch by `content_bytes`.
+const CONTENT_BATCH: u64 = 4096;
+/// The message count of a core kept as a directory of message files.
+const DIR_INFO: &str = "core.info";
 
 #[derive(Debug)]
 pub enum CoreError {
@@ -16,6 +33,10 @@ pub enum CoreError {
     Io(std::io::Error),
     Neopack(neopack::Error),
     NeoDisk(crate::neodisk::Error),
+    /// A core directory's core.info isn't a message count
+    DirInfo,
+    /// A log for a core directory already exists, holding other messages
+    LogExists,
 }
 
 impl From<std::io::Error> for CoreError {
@@ -39,66 +60,276 @@ impl From<crate::neodisk::Error> for CoreError {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct MessageId(pub u16);
 
+/// What a flush wrote, from `Core::flush_some` and `flush_with_progress`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlushProgress {
+    /// Messages handed to the log
+    pub messages: u64,
+    /// Their encoded size, before compression
+    pub bytes: u64,
+    /// Messages still waiting to be written
+    pub remaining: u64,
+}
+
+impl FlushProgress {
+    /// Whether every message is written and the log synced
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
 impl MessageId {
     pub fn to_file_name(&self) -> String {
         format!("{:04x}.bin", self.0)
     }
 }
 
+/// What `Core::compact_into_neodisk` moves, or would move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compaction {
+    /// Messages in the directory, one file each.
+    pub messages: u64,
+    /// Bytes in the directory's files, core.info included.
+    pub dir_bytes: u64,
+    /// Bytes in the log holding the same messages.
+    pub log_bytes: u64,
+}
+
+impl Compaction {
+    /// Bytes freed by replacing the directory with the log. Filesystems
+    /// round every file up to a whole block, so a directory of small
+    /// messages frees more than this.
+    pub fn saved(&self) -> i64 {
+        self.dir_bytes as i64 - self.log_bytes as i64
+    }
+}
+
 #[derive(Debug)]
 pub struct Core {
+    path: Option<PathBuf>,
     disk_writer: Option<NeoDiskWriter>,
     disk_reader: Option<NeoDiskReader>,
     cache: HashMap<MessageId, Vec<u8>>,
     next_id: MessageId,
+    /// First message not yet handed to `disk_writer`
+    written: MessageId,
+    metrics: MetricsHandle,
 }
 
 impl Core {
     pub fn create_mem() -> Self {
         Self {
+            path: None,
             disk_writer: None,
             disk_reader: None,
             cache: HashMap::new(),
             next_id: MessageId(0),
+            written: MessageId(0),
+            metrics: MetricsHandle::default(),
         }
     }
 
     pub fn create(path: PathBuf) -> Result<Self, CoreError> {
-        let writer = NeoDiskWriter::create(path)?;
+        let writer = NeoDiskWriter::create(&path)?;
         Ok(Self {
+            path: Some(path),
             disk_writer: Some(writer),
             disk_reader: None,
             cache: HashMap::new(),
             next_id: MessageId(0),
+            written: MessageId(0),
+            metrics: MetricsHandle::default(),
         })
     }
 
+    /// Opens a core's log. A log without a valid footer, as left by a
+    /// crash part way through a write, is refused; `recover` opens one.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CoreError> {
         let path = path.as_ref();
-        
-        let reader = NeoDiskReader::open(&path)?;
-        let size = reader.len();
-        let writer = NeoDiskWriter::open(&path)?;
-        
+        Self::open_with(path, NeoDiskWriter::open(path)?)
+    }
+
+    /// Like `load`, first cutting a log torn by a crash part way through a
+    /// write back to its last whole frame. Returns the core and the bytes
+    /// cut; anything cut was never synced. A file that doesn't look like a
+    /// log, or is from a newer version, is refused and left as it is. See
+    /// `NeoDiskWriter::recover_in`.
+    pub fn recover<P: AsRef<Path>>(path: P) -> Result<(Self, u64), CoreError> {
+        let path = path.as_ref();
+        let (writer, cut) = NeoDiskWriter::recover(path)?;
+        Ok((Self::open_with(path, writer)?, cut))
+    }
+
+    fn open_with(path: &Path, writer: NeoDiskWriter) -> Result<Self, CoreError> {
+        let reader = NeoDiskReader::open(path)?;
+        let size = reader.len()?;
+
         Ok(Self {
+            path: Some(path.to_path_buf()),
             disk_writer: Some(writer),
             disk_reader: Some(reader),
             cache: HashMap::new(),
             next_id: MessageId(size as u16),
+            written: MessageId(size as u16),
+            metrics: MetricsHandle::default(),
         })
     }
 
-    pub fn flush(&mut self) -> Result<(), CoreError> {
-        if let Some(ref mut writer) = self.disk_writer {
+    /// Moves a core kept the old way, as a directory holding a file per
+    /// message named by `MessageId::to_file_name` and a core.info with
+    /// their count, into a log beside it: `data/` becomes `data.nd`,
+    /// which `load` opens.
+    ///
+    /// The log is written and synced under a temporary name and renamed
+    /// into place before the directory is removed, so a crash at any
+    /// point leaves every message in one or the other. Run again after
+    /// one, it finds the log in place and finishes the removal. With
+    /// `dry_run` the log is written, measured and deleted, and the
+    /// directory is left as it was.
+    pub fn compact_into_neodisk(dir: &Path, dry_run: bool) -> Result<Compaction, CoreError> {
+        let info = std::fs::read_to_string(dir.join(DIR_INFO))?;
+        let count: u16 = info.trim().parse().map_err(|_| CoreError::DirInfo)?;
+        let mut dir_bytes = 0;
+        for entry in std::fs::read_dir(dir)? {
+            dir_bytes += entry?.metadata()?.len();
+        }
+
+        let log = dir.with_extension("nd");
+        if log.exists() {
+            // An earlier run renamed it into place and stopped there
+            if NeoDiskReader::open(&log)?.len()? != count as u64 {
+                return Err(CoreError::LogExists);
+            }
+        } else {
+            let tmp = platform::tmp_path(&log);
+            let mut writer = NeoDiskWriter::create(&tmp)?;
+            for id in (0..count).map(MessageId) {
+                let contents = std::fs::read(dir.join(id.to_file_name()))?;
+                let mut enc = neopack::Encoder::new();
+                enc.bytes(&contents)?;
+                writer.append(enc.as_bytes())?;
+            }
             writer.flush()?;
+            drop(writer);
+            if dry_run {
+                let log_bytes = std::fs::metadata(&tmp)?.len();
+                std::fs::remove_file(&tmp)?;
+                return Ok(Compaction { messages: count as u64, dir_bytes, log_bytes });
+            }
+            platform::rename(&tmp, &log)?;
+            platform::sync_dir(platform::parent_dir(&log))?;
+        }
+
+        let log_bytes = std::fs::metadata(&log)?.len();
+        if !dry_run {
+            std::fs::remove_dir_all(dir)?;
+            platform::sync_dir(platform::parent_dir(&log))?;
         }
+        Ok(Compaction { messages: count as u64, dir_bytes, log_bytes })
+    }
+
+    /// Writes every pending message to the log and syncs it.
+    pub fn flush(&mut self) -> Result<(), CoreError> {
+        self.flush_some(usize::MAX)?;
         Ok(())
     }
 
+    /// Writes up to `max_messages` pending messages to the log, and syncs
+    /// it once none are left.
+    pub fn flush_some(&mut self, max_messages: usize) -> Result<FlushProgress, CoreError> {
+        let end = (self.written.0 as usize).saturating_add(max_messages).min(self.next_id.0 as usize) as u16;
+        let mut progress = FlushProgress::default();
+        if let Some(ref mut writer) = self.disk_writer {
+            for id in self.written.0..end {
+                let contents = self.cache.get(&MessageId(id)).ok_or(CoreError::NotCached)?;
+                let mut enc = neopack::Encoder::new();
+                enc.bytes(contents)?;
+                writer.append(enc.as_bytes())?;
+                progress.messages += 1;
+                progress.bytes += enc.as_bytes().len() as u64;
+            }
+            if end == self.next_id.0 {
+                writer.flush()?;
+            }
+        }
+        self.written = MessageId(end);
+        progress.remaining = (self.next_id.0 - end) as u64;
+        Ok(progress)
+    }
+
+    /// Flushes in batches of `batch` messages, calling `on_progress` with
+    /// the running total after each. Stops early, without syncing, if it
+    /// returns false. Returns the total written.
+    pub fn flush_with_progress<F>(&mut self, batch: usize, mut on_progress: F) -> Result<FlushProgress, CoreError>
+    where
+        F: FnMut(&FlushProgress) -> bool,
+    {
+        let mut total = FlushProgress::default();
+        loop {
+            let progress = self.flush_some(batch.max(1))?;
+            total.messages += progress.messages;
+            total.bytes += progress.bytes;
+            total.remaining = progress.remaining;
+            if total.is_done() || !on_progress(&total) {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    /// Number of appended messages not yet handed to the log.
+    pub fn pending(&self) -> u64 {
+        (self.next_id.0 - self.written.0) as u64
+    }
+
+    /// Sets how `flush` syncs the log. In-memory cores ignore it.
+    pub fn set_durability(&mut self, durability: Durability) {
+        if let Some(ref mut writer) = self.disk_writer {
+            writer.set_durability(durability);
+        }
+    }
+
+    /// Reports appends, cache hits and misses, and the log's writes to
+    /// `metrics` from now on.
+    pub fn set_metrics(&mut self, metrics: MetricsHandle) {
+        if let Some(ref mut writer) = self.disk_writer {
+            writer.set_metrics(metrics.clone());
+        }
+        self.metrics = metrics;
+    }
+
     pub fn len(&self) -> MessageId {
         self.next_id
     }
 
+    /// Whether `count` more messages fit before the core is full.
+    pub fn has_room(&self, count: usize) -> bool {
+        self.next_id.0 as usize + count <= 0xFFFF
+    }
+
+    /// Drops every message at or after `len`, on disk and in the cache,
+    /// and flushes the shortened log.
+    pub fn truncate(&mut self, len: MessageId) -> Result<(), CoreError> {
+        if len.0 >= self.next_id.0 {
+            return Ok(());
+        }
+
+        // Unmap before the file shrinks underneath the reader
+        let had_reader = self.disk_reader.take().is_some();
+        if let Some(ref mut writer) = self.disk_writer {
+            writer.truncate(len.0 as u64)?;
+            writer.flush()?;
+        }
+        if let (true, Some(path)) = (had_reader, &self.path) {
+            self.disk_reader = Some(NeoDiskReader::open(path)?);
+        }
+
+        self.cache.retain(|id, _| id.0 < len.0);
+        self.next_id = len;
+        self.written = self.written.min(len);
+        Ok(())
+    }
+
     fn check_future_message(&self, id: MessageId) -> Result<(), CoreError> {
         if id.0 >= self.next_id.0 {
             Err(CoreError::FutureMessage)
@@ -112,8 +343,10 @@ impl Core {
         
         // Already in cache
         if self.cache.contains_key(&id) {
+            self.metrics.counter(metrics::CORE_CACHE_HITS, 1);
             return Ok(());
         }
+        self.metrics.counter(metrics::CORE_CACHE_MISSES, 1);
 
         // Load from disk if available - unwrap from neopack Bytes
         if let Some(ref reader) = self.disk_reader {
@@ -126,33 +359,187 @@ impl Core {
         Ok(())
     }
 
+    /// Loads every message in `range` into the cache, reading each disk
+    /// frame once.
+    pub fn load_range(&mut self, range: Range<MessageId>) -> Result<(), CoreError> {
+        if range.start.0 >= range.end.0 {
+            return Ok(());
+        }
+        self.check_future_message(MessageId(range.end.0 - 1))?;
+
+        // Only the uncached span needs to come from disk
+        let Some(start) = (range.start.0..range.end.0).find(|&id| !self.cache.contains_key(&MessageId(id))) else {
+            self.metrics.counter(metrics::CORE_CACHE_HITS, (range.end.0 - range.start.0) as u64);
+            return Ok(());
+        };
+        let end = (start..range.end.0).rev()
+            .find(|&id| !self.cache.contains_key(&MessageId(id)))
+            .map_or(start, |id| id + 1);
+        self.metrics.counter(metrics::CORE_CACHE_HITS, (range.end.0 - range.start.0 - (end - start)) as u64);
+        self.metrics.counter(metrics::CORE_CACHE_MISSES, (end - start) as u64);
+
+        if let Some(ref reader) = self.disk_reader {
+            let messages = reader.read_range(start as u64..end as u64)?;
+            for (id, encoded) in (start..end).zip(messages) {
+                let mut dec = neopack::Decoder::new(&encoded);
+                let contents = dec.bytes()?.to_vec();
+                self.cache.entry(MessageId(id)).or_insert(contents);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn add_message(&mut self, contents: &[u8]) -> Result<MessageId, CoreError> {
         if self.next_id.0 == 0xFFFF {
             return Err(CoreError::CoreFull);
         }
         
         let id = self.next_id;
-        
-        // Write to disk if available - wrap in neopack Bytes for framing
-        if let Some(ref mut writer) = self.disk_writer {
-            let mut enc = neopack::Encoder::new();
-            enc.bytes(contents)?;
-            writer.append(enc.as_bytes())?;
-        }
-        
-        // Add to cache (raw contents)
+
+        // Held in the cache (raw contents) until a flush writes it to
+        // disk, wrapped in neopack Bytes for framing
         self.cache.insert(id, contents.to_vec());
+        self.metrics.counter(metrics::CORE_MESSAGES_APPENDED, 1);
         
         self.next_id = MessageId(id.0 + 1);
         Ok(id)
     }
 
-    pub fn get_contents(&mut self, id: MessageId) -> Result<&[u8], CoreError> {
+    /// Every message held in memory, in id order. Messages only on disk
+    /// aren't included; `load_range` brings them in.
+    pub fn iter_cached(&self) -> impl Iterator<Item = (MessageId, &[u8])> + '_ {
+        let mut ids: Vec<_> = self.cache.keys().copied().collect();
+        ids.sort_unstable();
+        ids.into_iter().map(|id| (id, self.cache[&id].as_slice()))
+    }
+
+    /// Total length of every message's contents. Messages on disk are
+    /// read in batches and not cached.
+    pub fn content_bytes(&self) -> Result<u64, CoreError> {
+        let on_disk = match self.disk_reader {
+            Some(ref reader) => reader.len()?.min(self.next_id.0 as u64),
+            None => 0,
+        };
+        let mut total = 0;
+        if let Some(ref reader) = self.disk_reader {
+            let mut start = 0;
+            while start < on_disk {
+                let end = (start + CONTENT_BATCH).min(on_disk);
+                for encoded in reader.read_range(start..end)? {
+                    total += neopack::Decoder::new(&encoded).bytes()?.len() as u64;
+                }
+                start = end;
+            }
+        }
+        total += self.cache.iter()
+            .filter(|(id, _)| id.0 as u64 >= on_disk)
+            .map(|(_, contents)| contents.len() as u64)
+            .sum::<u64>();
+        Ok(total)
+    }
+
+    /// The contents of message `id`, borrowed from the cache it is loaded
+    /// into.
+    pub fn get_contents(&mut self, id: MessageId) -> Result<Cow<'_, [u8]>, CoreError> {
         self.check_future_message(id)?;
         self.load_message(id)?;
         
         self.cache.get(&id)
-            .map(|v| v.as_slice())
+            .map(|v| Cow::Borrowed(v.as_slice()))
             .ok_or(CoreError::NotCached)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Copies a core directory from the repository's cores/ into `to`.
+    fn copy_dir(from: &str, to: &Path) {
+        let from = Path::new(env!("CARGO_MANIFEST_DIR")).join("..").join("cores").join(from);
+        let _ = std::fs::remove_dir_all(to);
+        std::fs::create_dir_all(to).unwrap();
+        for entry in std::fs::read_dir(from).unwrap() {
+            let entry = entry.unwrap();
+            std::fs::copy(entry.path(), to.join(entry.file_name())).unwrap();
+        }
+    }
+
+    #[test]
+    fn core_compacts_message_files() {
+        let dir = PathBuf::from("/tmp/test_core_compact/data");
+        let log = PathBuf::from("/tmp/test_core_compact/data.nd");
+        let _ = std::fs::remove_file(&log);
+        copy_dir("test/data", &dir);
+
+        // A dry run measures the log without keeping it
+        let dry = Core::compact_into_neodisk(&dir, true).unwrap();
+        assert_eq!(dry.messages, 64);
+        assert!(dry.saved() > 0);
+        assert!(!log.exists());
+        assert!(dir.join("0000.bin").exists());
+
+        assert_eq!(Core::compact_into_neodisk(&dir, false).unwrap(), dry);
+        assert!(!dir.exists());
+        let mut core = Core::load(&log).unwrap();
+        assert_eq!(core.len(), MessageId(64));
+        for i in 0..64 {
+            assert_eq!(&*core.get_contents(MessageId(i)).unwrap(), format!("message {}", i).as_bytes());
+        }
+        drop(core);
+
+        // A run stopped after the rename finishes by removing the directory
+        copy_dir("test/data", &dir);
+        assert_eq!(Core::compact_into_neodisk(&dir, false).unwrap().messages, 64);
+        assert!(!dir.exists());
+
+        // But a log of some other core is left alone
+        copy_dir("demo", &dir);
+        assert!(matches!(Core::compact_into_neodisk(&dir, false), Err(CoreError::LogExists)));
+        std::fs::remove_file(&log).unwrap();
+        assert_eq!(Core::compact_into_neodisk(&dir, false).unwrap().messages, 3);
+        assert_eq!(&*Core::load(&log).unwrap().get_contents(MessageId(0)).unwrap(), b"Hello, World!\n");
+
+        copy_dir("demo", &dir);
+        std::fs::write(dir.join(DIR_INFO), "three").unwrap();
+        assert!(matches!(Core::compact_into_neodisk(&dir, false), Err(CoreError::DirInfo)));
+        std::fs::remove_dir_all("/tmp/test_core_compact").unwrap();
+    }
+
+    #[test]
+    fn core_flushes_in_batches() {
+        let path = PathBuf::from("/tmp/test_core_flush_some.nd");
+        let mut core = Core::create(path.clone()).unwrap();
+        for i in 0..10u8 {
+            core.add_message(&[i; 5]).unwrap();
+        }
+        assert_eq!(core.pending(), 10);
+
+        let progress = core.flush_some(4).unwrap();
+        assert_eq!(progress, FlushProgress { messages: 4, bytes: 4 * 10, remaining: 6 });
+        assert!(!progress.is_done());
+
+        // Stopped after the first batch of three
+        let mut calls = 0;
+        let progress = core.flush_with_progress(3, |_| { calls += 1; false }).unwrap();
+        assert_eq!((calls, progress.messages, progress.remaining), (1, 3, 3));
+
+        let mut seen = Vec::new();
+        let progress = core.flush_with_progress(2, |progress| { seen.push(progress.messages); true }).unwrap();
+        assert_eq!(seen, vec![2]);
+        assert_eq!((progress.messages, progress.bytes), (3, 30));
+        assert!(progress.is_done());
+        assert_eq!(core.pending(), 0);
+
+        // Pending messages cut by a truncate are never written
+        core.add_message(b"gone").unwrap();
+        core.truncate(MessageId(10)).unwrap();
+        assert_eq!(core.flush_some(usize::MAX).unwrap(), FlushProgress::default());
+
+        let mut loaded = Core::load(&path).unwrap();
+        assert_eq!(loaded.len(), MessageId(10));
+        assert_eq!(&*loaded.get_contents(MessageId(9)).unwrap(), &[9; 5]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -20,8 +20,8 @@
 //! 1. ItemId (n): The 0-based sequence number of the data leaves.
 //!
 //! 2. CoveringId (y): The 0-based index of a node in the post-order
-//! traversal. This space includes both the leaves (items) and the internal
-//! nodes (parents).
+//!    traversal. This space includes both the leaves (items) and the internal
+//!    nodes (parents).
 //!
 //! For a binary tree (width 2), the sequence of CoveringId types looks like this:
 //! Leaf, Leaf, Parent, Leaf, Leaf, Parent, Grandparent...
@@ -29,11 +29,11 @@
 //! Key Invariants
 //!
 //! 1. Post-Order Placement: A parent node always appears immediately after its
-//! last (right-most) child in the CoveringIndex sequence.
+//!    last (right-most) child in the CoveringIndex sequence.
 //!
 //! 2. Geometric Distribution: Internal nodes are inserted at regular intervals
-//! based on the tree width (w). A parent of height 1 is generated every w
-//! leaves; a parent of height 2 every w^2 leaves, etc.
+//!    based on the tree width (w). A parent of height 1 is generated every w
+//!    leaves; a parent of height 2 every w^2 leaves, etc.
 //!
 //! Algorithms
 //!
@@ -79,14 +79,26 @@
 //! fewer).
 //!
 //! Space Complexity: O(1). No memory is allocated for the tree structure.
+//!
+//! Debugging
+//!
+//! `dump_tree` draws the forest over the first n items as indented ASCII,
+//! one node per line with its CoveringId, height, and item range, peaks
+//! first and marked. `dump_tree_with` appends a note to each line, which
+//! `IsoCore::debug_tree` uses to show stored node hashes.
+
 
+use core::fmt::Write;
+use core::ops::Range;
 
-use std::ops::Range;
+use alloc::string::String;
+use alloc::vec::Vec;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct CoveringId(pub u64);
 
 impl CoveringId {
+    #[cfg(feature = "disk")]
     pub fn to_verkle_id(&self) -> crate::core::MessageId {
         return crate::core::MessageId(self.0 as u16);
     }
@@ -96,6 +108,7 @@ impl CoveringId {
 pub struct ItemId(pub u64);
 
 impl ItemId {
+    #[cfg(feature = "disk")]
     pub fn to_data_id(&self) -> crate::core::MessageId {
         return crate::core::MessageId(self.0 as u16);
     }
@@ -190,6 +203,56 @@ pub fn get_peaks(n: u64, width: u64) -> Vec<CoveringId> {
     return peaks;
 }
 
+/// The forest over the first `len` items as indented ASCII; see the
+/// module docs.
+pub fn dump_tree(len: u64, width: u64) -> String {
+    return dump_tree_with(len, width, |_| String::new());
+}
+
+/// Like `dump_tree`, with `annotate`'s note for each node at the end of
+/// its line.
+pub fn dump_tree_with(len: u64, width: u64, mut annotate: impl FnMut(CoveringId) -> String) -> String {
+    assert!(width > 1 && width.is_power_of_two());
+
+    let peaks = get_peaks(len, width);
+    let mut out = String::new();
+    let _ = write!(out, "{} items, width {}, peaks", len, width);
+    for peak in &peaks {
+        let _ = write!(out, " {}", peak.0);
+    }
+    out.push('\n');
+    for peak in peaks {
+        dump_node(&mut out, peak, 0, width, &mut annotate);
+    }
+    return out;
+}
+
+fn dump_node(out: &mut String, y: CoveringId, depth: usize, width: u64, annotate: &mut impl FnMut(CoveringId) -> String) {
+    let (_, height) = decode_covering(y.0, width);
+    let range = covering_range(y, width);
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+    let _ = write!(out, "{} h{} ", y.0, height);
+    if height == 0 {
+        let _ = write!(out, "item {}", range.start.0);
+    } else {
+        let _ = write!(out, "items {}..{}", range.start.0, range.end.0);
+    }
+    if depth == 0 {
+        out.push_str(" peak");
+    }
+    let note = annotate(y);
+    if !note.is_empty() {
+        out.push(' ');
+        out.push_str(&note);
+    }
+    out.push('\n');
+    for child in children_for_covering(y, width) {
+        dump_node(out, child, depth + 1, width, annotate);
+    }
+}
+
 fn map_item_to_covering(n: u64, w: u64) -> u64 {
     let mut offset = 0;
     let mut div = w;
@@ -263,6 +326,26 @@ mod tests {
         assert_eq!(c1, vec![CoveringId(0), CoveringId(1), CoveringId(2), CoveringId(3)]);
     }
 
+    #[test]
+    fn dumps_forest() {
+        let expected = "\
+5 items, width 2, peaks 6 7
+6 h2 items 0..4 peak
+  2 h1 items 0..2
+    0 h0 item 0
+    1 h0 item 1
+  5 h1 items 2..4
+    3 h0 item 2
+    4 h0 item 3
+7 h0 item 4 peak
+";
+        assert_eq!(dump_tree(5, 2), expected);
+        assert_eq!(dump_tree(0, 4), "0 items, width 4, peaks\n");
+
+        let annotated = dump_tree_with(2, 2, |y| alloc::format!("#{}", y.0 * 10));
+        assert_eq!(annotated, "2 items, width 2, peaks 2\n2 h1 items 0..2 peak #20\n  0 h0 item 0 #0\n  1 h0 item 1 #10\n");
+    }
+
     #[test]
     fn peaks() {
         // 0 items = no peaks
@@ -0,0 +1,166 @@
+//! Discovery: finding peers that serve cores on the local network
+//!
+//! Each served core is announced over mDNS as an instance of
+//! `SERVICE_TYPE`, named after a prefix of its public key, with the full
+//! key in the `core` TXT property. Browsing turns resolved instances into
+//! `Peer`s: an address to connect to and the core served there.
+//!
+//! Discovery only says where a core claims to be served. Anyone on the
+//! network can announce any key, so replicate with the handshake's
+//! `expected` peer set and check items against the core's signatures.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+use mdns_sd::Receiver;
+use mdns_sd::ServiceDaemon;
+use mdns_sd::ServiceEvent;
+use mdns_sd::ServiceInfo;
+use crate::hex;
+use crate::key::KeyPub;
+
+/// mDNS service type under which cores are announced.
+pub const SERVICE_TYPE: &str = "_home-core._tcp.local.";
+/// Hex digits of the public key used in instance names.
+const PREFIX_LEN: usize = 16;
+
+#[derive(Debug)]
+pub enum DiscoveryError {
+    Mdns(mdns_sd::Error),
+}
+
+impl From<mdns_sd::Error> for DiscoveryError {
+    fn from(err: mdns_sd::Error) -> Self {
+        return DiscoveryError::Mdns(err);
+    }
+}
+
+/// A core served at an address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    pub core: KeyPub,
+}
+
+/// Announces and browses for cores. Announcements are withdrawn when it
+/// is dropped.
+pub struct Discovery {
+    daemon: ServiceDaemon,
+    /// Full service names of announced cores, by key.
+    announced: HashMap<[u8; 32], String>,
+}
+
+impl Discovery {
+    pub fn new() -> Result<Self, DiscoveryError> {
+        return Ok(Discovery {
+            daemon: ServiceDaemon::new()?,
+            announced: HashMap::new(),
+        });
+    }
+
+    /// Announces that `core` is served on `port` at every local address.
+    pub fn announce(&mut self, core: &KeyPub, port: u16) -> Result<(), DiscoveryError> {
+        let info = service_info(core, port)?;
+        self.announced.insert(core.0, info.get_fullname().to_string());
+        self.daemon.register(info)?;
+        return Ok(());
+    }
+
+    /// Stops announcing `core`.
+    pub fn withdraw(&mut self, core: &KeyPub) -> Result<(), DiscoveryError> {
+        if let Some(fullname) = self.announced.remove(&core.0) {
+            self.daemon.unregister(&fullname)?;
+        }
+        return Ok(());
+    }
+
+    /// Starts browsing for announced cores.
+    pub fn browse(&self) -> Result<Browser, DiscoveryError> {
+        return Ok(Browser { events: self.daemon.browse(SERVICE_TYPE)? });
+    }
+}
+
+impl Drop for Discovery {
+    fn drop(&mut self) {
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// Peers found while browsing.
+pub struct Browser {
+    events: Receiver<ServiceEvent>,
+}
+
+impl Browser {
+    /// Waits up to `timeout` for the next announcement to resolve, and
+    /// returns a peer for each of its addresses. Empty on timeout.
+    pub fn next(&self, timeout: Duration) -> Vec<Peer> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let Ok(event) = self.events.recv_timeout(remaining) else {
+                return Vec::new();
+            };
+            if let ServiceEvent::ServiceResolved(info) = event {
+                let peers = peers_from(&info);
+                if !peers.is_empty() {
+                    return peers;
+                }
+            }
+        }
+    }
+}
+
+fn service_info(core: &KeyPub, port: u16) -> Result<ServiceInfo, DiscoveryError> {
+    let key = hex::encode(&core.0);
+    let instance = format!("home-{}", &key[..PREFIX_LEN]);
+    let host = format!("{}.local.", instance);
+    let properties = [("core", key.as_str())];
+    let info = ServiceInfo::new(SERVICE_TYPE, &instance, &host, "", port, &properties[..])?;
+    return Ok(info.enable_addr_auto());
+}
+
+/// The peers an announcement describes; none if its `core` property is
+/// missing or malformed.
+fn peers_from(info: &ServiceInfo) -> Vec<Peer> {
+    let Some(core) = info.get_property_val_str("core").and_then(|key| hex::decode_array(key).ok()) else {
+        return Vec::new();
+    };
+    return info.get_addresses().iter()
+        .map(|ip| Peer { addr: SocketAddr::new(*ip, info.get_port()), core: KeyPub(core) })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::KeyPair;
+
+    #[test]
+    fn announcements_describe_peers() {
+        let core = KeyPair::ephemeral().key_pub;
+        let info = service_info(&core, 7000).unwrap();
+        assert_eq!(info.get_fullname(), format!("home-{}.{}", &hex::encode(&core.0)[..PREFIX_LEN], SERVICE_TYPE));
+
+        let resolved = ServiceInfo::new(
+            SERVICE_TYPE,
+            "home-test",
+            "home-test.local.",
+            "192.168.1.20",
+            7000,
+            &[("core", hex::encode(&core.0).as_str())][..],
+        ).unwrap();
+        assert_eq!(peers_from(&resolved), vec![Peer { addr: "192.168.1.20:7000".parse().unwrap(), core }]);
+
+        let garbled = ServiceInfo::new(
+            SERVICE_TYPE,
+            "home-test",
+            "home-test.local.",
+            "192.168.1.20",
+            7000,
+            &[("core", "zz")][..],
+        ).unwrap();
+        assert!(peers_from(&garbled).is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+//! EncryptedIsoCore: an IsoCore whose messages are encrypted at rest
+//!
+//! Each message is encrypted with the owner's at-rest key before it is
+//! hashed and appended, so the verkle tree and signatures commit to the
+//! ciphertext. Replicas can load the same directory as a plain IsoCore and
+//! verify or audit it without ever seeing plaintext; only holders of the
+//! owner's KeyPair can read messages back.
+//!
+//! Stored messages are a `Payload`: the 24-byte nonce, then the ciphertext.
+
+use std::path::Path;
+use std::path::PathBuf;
+use rand_core::CryptoRngCore;
+#[cfg(feature = "rng")]
+use rand_core::OsRng;
+use crate::covering::ItemId;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::proof::InclusionProof;
+use crate::key::Hash;
+use crate::key::KeyPair;
+use crate::key::KeyPub;
+use crate::key::KeyShared;
+use crate::key::Payload;
+
+#[derive(Debug)]
+pub struct EncryptedIsoCore {
+    inner: IsoCore,
+    key: KeyShared,
+}
+
+impl EncryptedIsoCore {
+    /// Wraps `inner`, reading and writing with `owner`'s at-rest key.
+    pub fn new(inner: IsoCore, owner: &KeyPair) -> Self {
+        return Self {
+            inner,
+            key: owner.at_rest(),
+        };
+    }
+
+    pub fn create_mem(owner: &KeyPair) -> Self {
+        return Self::new(IsoCore::create_mem(owner), owner);
+    }
+
+    pub fn create(path: PathBuf, owner: &KeyPair) -> Result<Self, IsoCoreError> {
+        return Ok(Self::new(IsoCore::create(path, owner)?, owner));
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, owner: &KeyPair) -> Result<Self, IsoCoreError> {
+        return Ok(Self::new(IsoCore::load(path)?, owner));
+    }
+
+    /// Encrypts `message` and appends the ciphertext.
+    #[cfg(feature = "rng")]
+    pub fn add_message(&mut self, message: &[u8], signer: &KeyPair) -> Result<Hash, IsoCoreError> {
+        return self.add_message_with(message, signer, &mut OsRng);
+    }
+
+    /// Like `add_message`, drawing the nonce from `rng`.
+    pub fn add_message_with(
+        &mut self,
+        message: &[u8],
+        signer: &KeyPair,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Hash, IsoCoreError> {
+        let payload = self.key.encrypt_with(message, rng);
+        return self.inner.add_message(&payload.to_bytes(), signer);
+    }
+
+    /// Reads, verifies, and decrypts a message.
+    pub fn get_message(&mut self, item_id: ItemId) -> Result<Vec<u8>, IsoCoreError> {
+        let payload = Payload::from_bytes(&self.inner.get_message(item_id)?)?;
+        return Ok(self.key.decrypt(payload)?);
+    }
+
+    pub fn flush(&mut self) -> Result<(), IsoCoreError> {
+        return self.inner.flush();
+    }
+
+    pub fn len(&self) -> crate::core::MessageId {
+        return self.inner.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.inner.len().0 == 0;
+    }
+
+    /// The underlying core, for reads that don't touch message contents.
+    /// Appending and reading messages go through this type instead.
+    pub fn inner(&self) -> &IsoCore {
+        return &self.inner;
+    }
+
+    pub fn signer(&self) -> &KeyPub {
+        return self.inner.signer();
+    }
+
+    /// Root over the ciphertext, as replicas see it.
+    pub fn get_root_hash(&mut self) -> Result<Hash, IsoCoreError> {
+        return self.inner.get_root_hash();
+    }
+
+    pub fn verify_head(&mut self) -> Result<Hash, IsoCoreError> {
+        return self.inner.verify_head();
+    }
+
+    /// Proves item `item_id`'s ciphertext under the root signed after
+    /// `len` items.
+    pub fn prove(&mut self, item_id: ItemId, len: u64) -> Result<InclusionProof, IsoCoreError> {
+        return self.inner.prove(item_id, len);
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypted_round_trip_and_replica_audit() {
+        let path = PathBuf::from("/tmp/test_encrypted_isocore");
+        let _ = std::fs::remove_dir_all(&path);
+        let owner = KeyPair::ephemeral();
+
+        let mut core = EncryptedIsoCore::create(path.clone(), &owner).unwrap();
+        for i in 0..5 {
+            core.add_message(format!("secret {}", i).as_bytes(), &owner).unwrap();
+        }
+        core.flush().unwrap();
+        drop(core);
+
+        // A replica without the key verifies the ciphertext tree
+        let mut replica = IsoCore::load(&path).unwrap();
+        replica.verify_head().unwrap();
+        assert!(replica.audit(|_| {}).is_ok());
+        let stored = replica.get_message(ItemId(2)).unwrap();
+        assert!(!stored.windows(6).any(|w| w == b"secret"));
+        drop(replica);
+
+        let mut core = EncryptedIsoCore::load(&path, &owner).unwrap();
+        assert_eq!(core.get_message(ItemId(2)).unwrap(), b"secret 2");
+        assert_eq!(core.signer(), &owner.key_pub);
+        let root = core.verify_head().unwrap();
+        assert_eq!(core.prove(ItemId(2), 5).unwrap().verify(&owner.key_pub).unwrap(), root);
+
+        // Someone else's key can't read it
+        let mut stranger = EncryptedIsoCore::load(&path, &KeyPair::ephemeral()).unwrap();
+        assert!(matches!(stranger.get_message(ItemId(2)), Err(IsoCoreError::Decrypt(_))));
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
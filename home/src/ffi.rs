@@ -0,0 +1,238 @@
+//! C FFI for embedding core reading in other languages
+//!
+//! Every function returns a `HomeStatus` and writes results through out
+//! pointers. Handles are opaque and must be released with `home_core_free`.
+//! Panics never cross the boundary: each call runs inside `catch_unwind`
+//! and reports `HomeStatus::Panic` instead.
+//!
+//! Building with `--features ffi` writes the C header to `home.h` in the
+//! build script's `OUT_DIR`. The copy checked in at `include/home.h` is
+//! refreshed by building with `HOME_UPDATE_HEADER=1` set.
+//! To produce a linkable library:
+//!
+//! ```text
+//! cargo rustc --lib --release --features ffi --crate-type staticlib
+//! ```
+
+use std::ffi::CStr;
+use std::ffi::c_char;
+use std::panic::AssertUnwindSafe;
+use std::panic::catch_unwind;
+
+use crate::core::CoreError;
+use crate::covering::ItemId;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+
+/// Result code returned by every FFI function.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HomeStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidPath = 2,
+    Io = 3,
+    NotFound = 4,
+    BufferTooSmall = 5,
+    Integrity = 6,
+    Format = 7,
+    Panic = 8,
+}
+
+impl From<IsoCoreError> for HomeStatus {
+    fn from(e: IsoCoreError) -> Self {
+        match e {
+            IsoCoreError::Io(_) => HomeStatus::Io,
+            IsoCoreError::Core(CoreError::Io(_)) => HomeStatus::Io,
+            IsoCoreError::Core(CoreError::FutureMessage) => HomeStatus::NotFound,
+            IsoCoreError::NeedsData { .. } => HomeStatus::NotFound,
+            IsoCoreError::IntegrityError => HomeStatus::Integrity,
+            IsoCoreError::SignerMismatch => HomeStatus::Integrity,
+            IsoCoreError::BadInfo => HomeStatus::Integrity,
+            IsoCoreError::Layout(_) => HomeStatus::InvalidPath,
+            _ => HomeStatus::Format,
+        }
+    }
+}
+
+/// Opaque handle to an opened isocore.
+pub struct HomeCore {
+    inner: IsoCore,
+}
+
+fn guard(f: impl FnOnce() -> HomeStatus) -> HomeStatus {
+    return catch_unwind(AssertUnwindSafe(f)).unwrap_or(HomeStatus::Panic);
+}
+
+/// Opens the isocore directory at `path` (NUL-terminated UTF-8).
+/// On success, writes a new handle to `out`.
+///
+/// # Safety
+///
+/// `path` must be a valid C string and `out` a valid pointer to write to.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn home_core_open(path: *const c_char, out: *mut *mut HomeCore) -> HomeStatus {
+    return guard(|| {
+        if path.is_null() || out.is_null() {
+            return HomeStatus::NullPointer;
+        }
+        let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+            return HomeStatus::InvalidPath;
+        };
+        match IsoCore::load(path) {
+            Ok(inner) => {
+                let handle = Box::new(HomeCore { inner });
+                unsafe { *out = Box::into_raw(handle) };
+                HomeStatus::Ok
+            }
+            Err(e) => e.into(),
+        }
+    });
+}
+
+/// Writes the number of messages in the core to `out_len`.
+///
+/// # Safety
+///
+/// `core` must be a live handle from `home_core_open`, `out_len` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn home_core_len(core: *const HomeCore, out_len: *mut u64) -> HomeStatus {
+    return guard(|| {
+        let (Some(core), false) = (unsafe { core.as_ref() }, out_len.is_null()) else {
+            return HomeStatus::NullPointer;
+        };
+        unsafe { *out_len = core.inner.len().0 as u64 };
+        HomeStatus::Ok
+    });
+}
+
+/// Reads message `index` into `buf`, verifying it against its leaf hash.
+/// The message length is always written to `out_len`; if it exceeds
+/// `buf_len`, nothing is copied and `BufferTooSmall` is returned, so
+/// callers can retry with a larger buffer. `buf` may be null when
+/// `buf_len` is zero.
+///
+/// # Safety
+///
+/// `core` must be a live handle, `buf` valid for `buf_len` bytes of
+/// writes, and `out_len` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn home_core_read(
+    core: *mut HomeCore,
+    index: u64,
+    buf: *mut u8,
+    buf_len: usize,
+    out_len: *mut usize,
+) -> HomeStatus {
+    return guard(|| {
+        let (Some(core), false) = (unsafe { core.as_mut() }, out_len.is_null()) else {
+            return HomeStatus::NullPointer;
+        };
+        if index >= core.inner.len().0 as u64 {
+            return HomeStatus::NotFound;
+        }
+        let message = match core.inner.get_message(ItemId(index)) {
+            Ok(message) => message,
+            Err(e) => return e.into(),
+        };
+
+        unsafe { *out_len = message.len() };
+        if message.len() > buf_len {
+            return HomeStatus::BufferTooSmall;
+        }
+        if buf.is_null() && !message.is_empty() {
+            return HomeStatus::NullPointer;
+        }
+        if !message.is_empty() {
+            unsafe { std::ptr::copy_nonoverlapping(message.as_ptr(), buf, message.len()) };
+        }
+        HomeStatus::Ok
+    });
+}
+
+/// Verifies the latest signed root against the tree and the core's signer.
+/// On success, writes the 32-byte global root to `out_root` if non-null.
+///
+/// # Safety
+///
+/// `core` must be a live handle; `out_root`, if non-null, must be valid
+/// for 32 bytes of writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn home_core_verify_head(core: *mut HomeCore, out_root: *mut u8) -> HomeStatus {
+    return guard(|| {
+        let Some(core) = (unsafe { core.as_mut() }) else {
+            return HomeStatus::NullPointer;
+        };
+        match core.inner.verify_head() {
+            Ok(root) => {
+                if !out_root.is_null() {
+                    unsafe { std::ptr::copy_nonoverlapping(root.0.as_ptr(), out_root, 32) };
+                }
+                HomeStatus::Ok
+            }
+            Err(e) => e.into(),
+        }
+    });
+}
+
+/// Releases a handle. Passing null is a no-op.
+///
+/// # Safety
+///
+/// `core` must come from `home_core_open` and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn home_core_free(core: *mut HomeCore) {
+    let _ = guard(|| {
+        if !core.is_null() {
+            drop(unsafe { Box::from_raw(core) });
+        }
+        HomeStatus::Ok
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::KeyPair;
+    use std::ffi::CString;
+
+    #[test]
+    fn ffi_open_read_verify() {
+        let dir = std::env::temp_dir().join("home_ffi_open_read_verify");
+        let _ = std::fs::remove_dir_all(&dir);
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create(dir.clone(), &signer).unwrap();
+        isocore.add_message(b"hello", &signer).unwrap();
+        isocore.add_message(b"from c", &signer).unwrap();
+        isocore.flush().unwrap();
+        drop(isocore);
+
+        let path = CString::new(dir.to_str().unwrap()).unwrap();
+        let mut core = std::ptr::null_mut();
+        unsafe {
+            assert_eq!(home_core_open(path.as_ptr(), &mut core), HomeStatus::Ok);
+
+            let mut len = 0;
+            assert_eq!(home_core_len(core, &mut len), HomeStatus::Ok);
+            assert_eq!(len, 2);
+
+            let mut needed = 0;
+            assert_eq!(home_core_read(core, 1, std::ptr::null_mut(), 0, &mut needed), HomeStatus::BufferTooSmall);
+            assert_eq!(needed, 6);
+
+            let mut buf = vec![0u8; needed];
+            assert_eq!(home_core_read(core, 1, buf.as_mut_ptr(), buf.len(), &mut needed), HomeStatus::Ok);
+            assert_eq!(buf, b"from c");
+            assert_eq!(home_core_read(core, 2, buf.as_mut_ptr(), buf.len(), &mut needed), HomeStatus::NotFound);
+
+            let mut root = [0u8; 32];
+            assert_eq!(home_core_verify_head(core, root.as_mut_ptr()), HomeStatus::Ok);
+            assert_ne!(root, [0u8; 32]);
+
+            home_core_free(core);
+            assert_eq!(home_core_len(std::ptr::null(), &mut len), HomeStatus::NullPointer);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,532 @@
+//! Formats shared by cores and the programs that verify them
+//!
+//! Everything a verifier needs to agree with a core on, without the core
+//! itself: the format version, which fixes how leaves, nodes, and roots
+//! are hashed; the tree width; and the signature block signed after each
+//! append. None of it touches the filesystem, so it builds without the
+//! `disk` feature.
+//!
+//! A signature block is a neopack List: `[version: U8, global_root:
+//! Fixed32, signature: Bytes]`, with `version` 2. Version 1 blocks, still
+//! read, are 64 lowercase hex characters of the global root, a newline,
+//! then the raw 64-byte ed25519 signature over the root. The two can't be
+//! confused: a version 2 block starts with the List tag, which isn't a hex
+//! digit.
+//!
+//! A threshold core is signed by any `threshold` of several keys, its
+//! `Signers`. Its blocks are version 3, `[3, global_root: Fixed32,
+//! List<[index: U8, signature: Bytes]>]`, each signature with the index of
+//! its key among the signers. A threshold core is known by `Signers::id`,
+//! a hash of its keys and threshold, where a single-signer core is known
+//! by its key.
+//!
+//! A `Checkpoint` is the signed head of a core at some length, `[len: U64,
+//! root: Fixed32, signature: Bytes]`, kept so verification can start there
+//! instead of at the first item.
+//!
+//! A `Cosignature` is a witness's signature over a core's head, `[witness:
+//! Fixed32, len: U64, root: Fixed32, signature: Bytes]`. The witness signs
+//! the length and root after a context string, so a cosignature can't be
+//! passed off as the core's own signature over the root.
+//!
+//! # Files
+//!
+//! Every file a core writes beside its logs (info.nd, the root index, the
+//! flush intent, checkpoints, cosignatures) and every replication session
+//! or backup archive starts with a header: a 4-byte magic naming the
+//! `Artifact`, then a version byte. Readers accept versions from the
+//! artifact's oldest to its current one, and report anything else as
+//! `UnsupportedVersion` with what was found and what this build writes.
+//! Files written before headers start with a neopack tag, never a magic
+//! byte, and are read as version 0 where the artifact still accepts it.
+//!
+//! NeoDisk logs are the exception: their magic, `NEODISK_MAGIC`, ends the
+//! footer rather than starting the file, since opening a log reads the
+//! footer first and frames are addressed from offset 0.
+
+use crate::key::Hash;
+use crate::key::HashBuilder;
+use crate::key::HashDomain;
+use crate::key::KeyPair;
+use crate::key::KeyPub;
+use crate::key::Signature;
+use crate::neopack;
+use crate::neopack::Pack;
+use crate::neopack::ValueDecoder;
+use crate::neopack::ValueWriter;
+use crate::neopack::spec;
+
+/// Children per tree node.
+pub(crate) const WIDTH: u64 = 8;
+
+/// Ends every NeoDisk log, after the offset of its last frame. Its last
+/// byte is the log's version, 0 here.
+#[cfg(feature = "disk")]
+pub(crate) const NEODISK_MAGIC: &[u8; 8] = b"NEODISK\0";
+
+/// Newest NeoDisk log version. A log is version 1 once any frame in it
+/// has a jump density other than 1 or forward slots, which version 0
+/// readers would misread; see the `jumpheader` docs.
+#[cfg(feature = "disk")]
+pub(crate) const NEODISK_VERSION: u8 = 1;
+
+/// Bytes of magic and version at the start of every `Artifact`.
+pub const HEADER_LEN: usize = 5;
+
+/// Length of a version 1, hex and newline, signature block.
+const SIGNATURE_BLOCK_V1_LEN: usize = 64 + 1 + 64;
+
+/// Version of a single-signer block written by `SignatureBlock::to_bytes`.
+const SIGNATURE_BLOCK_VERSION: u8 = 2;
+
+/// Version of a threshold block, with several signatures.
+const THRESHOLD_BLOCK_VERSION: u8 = 3;
+
+/// Prefixes the length and root a witness signs.
+const COSIGNATURE_CONTEXT: &[u8] = b"home witness cosignature\n";
+
+/// Prefixes the keys and threshold hashed into `Signers::id`.
+const SIGNERS_CONTEXT: &[u8] = b"home isocore signers\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatError {
+    UnsupportedVersion { found: u8, expected: u8 },
+    /// A file doesn't start with the artifact's magic.
+    BadMagic(Artifact),
+    /// A signature block is neither a version 2 or 3 List nor hex, a
+    /// newline, and a signature.
+    SignatureBlock,
+    /// A signer set with no keys, more than 255, a key twice, or a
+    /// threshold of 0 or more than its keys.
+    Signers,
+}
+
+/// On-disk format version, recorded in info.nd.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatVersion {
+    /// Plain blake3 over concatenated bytes everywhere.
+    V1 = 1,
+    /// Leaf data, tree nodes, and the bagged root are hashed in separate
+    /// domains, so leaf data can't be passed off as a node encoding.
+    V2 = 2,
+    /// As V2, but the bagged root starts with the item count, so a signed
+    /// root fixes the tree's shape and proofs can't claim another length.
+    V3 = 3,
+}
+
+impl FormatVersion {
+    pub const CURRENT: FormatVersion = FormatVersion::V3;
+
+    pub fn from_u8(version: u8) -> Result<Self, FormatError> {
+        return match version {
+            1 => Ok(FormatVersion::V1),
+            2 => Ok(FormatVersion::V2),
+            3 => Ok(FormatVersion::V3),
+            found => Err(FormatError::UnsupportedVersion { found, expected: FormatVersion::CURRENT as u8 }),
+        };
+    }
+
+    pub fn hasher(self, domain: HashDomain) -> HashBuilder {
+        return match self {
+            FormatVersion::V1 => HashBuilder::new(),
+            FormatVersion::V2 | FormatVersion::V3 => HashBuilder::for_domain(domain),
+        };
+    }
+
+    pub fn hash_leaf(self, data: &[u8]) -> Hash {
+        return self.hasher(HashDomain::Leaf).update(data).finish();
+    }
+
+    /// Hashes a node from its child hashes.
+    pub fn hash_node<'a>(self, children: impl IntoIterator<Item = &'a Hash>) -> Hash {
+        let mut builder = self.hasher(HashDomain::Node);
+        for child in children {
+            builder.update(&child.0);
+        }
+        return builder.finish();
+    }
+
+    /// Starts the global root after `len` items, for the peak hashes to be
+    /// fed to in order.
+    pub fn root_hasher(self, len: u64) -> HashBuilder {
+        let mut builder = self.hasher(HashDomain::Root);
+        if self == FormatVersion::V3 {
+            builder.update(&len.to_le_bytes());
+        }
+        return builder;
+    }
+
+    /// Bags the peak hashes after `len` items, in order, into the global
+    /// root that gets signed.
+    pub fn hash_root<'a>(self, len: u64, peaks: impl IntoIterator<Item = &'a Hash>) -> Hash {
+        let mut builder = self.root_hasher(len);
+        for peak in peaks {
+            builder.update(&peak.0);
+        }
+        return builder.finish();
+    }
+}
+
+/// A kind of file with a header. See the module docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Artifact {
+    /// info.nd: format version, signer, and whether the core is light.
+    Info,
+    /// roots.nd: the index from signed global root to item.
+    Roots,
+    /// intent.nd: the marker left while a flush is in progress.
+    Intent,
+    /// checkpoints.nd: signed heads kept to verify from.
+    Checkpoints,
+    /// witnesses.nd: cosignatures of the core's heads by witnesses.
+    Cosignatures,
+    /// A saved replication session.
+    Session,
+    /// A backup archive.
+    Backup,
+}
+
+impl Artifact {
+    pub fn magic(self) -> [u8; 4] {
+        return match self {
+            Artifact::Info => *b"HMIF",
+            Artifact::Roots => *b"HMRT",
+            Artifact::Intent => *b"HMIT",
+            Artifact::Checkpoints => *b"HMCP",
+            Artifact::Cosignatures => *b"HMWS",
+            Artifact::Session => *b"HMSS",
+            Artifact::Backup => *b"HMBK",
+        };
+    }
+
+    /// The version written by this build.
+    pub fn version(self) -> u8 {
+        return match self {
+            // 2 added the width, creation time, and seal; 3 the inline limit
+            Artifact::Info => 3,
+            _ => 1,
+        };
+    }
+
+    /// The oldest version still read; 0 is a file from before headers.
+    pub fn oldest(self) -> u8 {
+        return match self {
+            Artifact::Info | Artifact::Roots | Artifact::Intent | Artifact::Session => 0,
+            Artifact::Checkpoints | Artifact::Cosignatures | Artifact::Backup => 1,
+        };
+    }
+
+    pub fn header(self) -> [u8; HEADER_LEN] {
+        let magic = self.magic();
+        return [magic[0], magic[1], magic[2], magic[3], self.version()];
+    }
+
+    /// Checks the header of a whole file, returning its version and the
+    /// bytes after the header.
+    pub fn read_header(self, bytes: &[u8]) -> Result<(u8, &[u8]), FormatError> {
+        let version = match bytes.get(..4) {
+            Some(magic) if magic == self.magic() => {
+                *bytes.get(4).ok_or(FormatError::BadMagic(self))?
+            }
+            _ if self.oldest() == 0 && bytes.first().is_none_or(|tag| *tag < b'A') => return Ok((0, bytes)),
+            _ => return Err(FormatError::BadMagic(self)),
+        };
+        if version < self.oldest() || version > self.version() {
+            return Err(FormatError::UnsupportedVersion { found: version, expected: self.version() });
+        }
+        return Ok((version, &bytes[HEADER_LEN..]));
+    }
+
+    /// Prefixes `body` with the header.
+    pub fn with_header(self, body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + body.len());
+        out.extend_from_slice(&self.header());
+        out.extend_from_slice(body);
+        return out;
+    }
+}
+
+/// The keys that may sign a core's roots, and how many of them must.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signers {
+    keys: Vec<KeyPub>,
+    threshold: u8,
+}
+
+impl Signers {
+    /// `threshold` of `keys`, in the order their indices refer to.
+    pub fn new(keys: Vec<KeyPub>, threshold: u8) -> Result<Self, FormatError> {
+        let distinct = keys.iter().enumerate().all(|(i, key)| !keys[..i].contains(key));
+        if keys.is_empty() || keys.len() > u8::MAX as usize || !distinct
+            || threshold == 0 || threshold as usize > keys.len() {
+            return Err(FormatError::Signers);
+        }
+        return Ok(Signers { keys, threshold });
+    }
+
+    pub fn single(key: KeyPub) -> Self {
+        return Signers { keys: vec![key], threshold: 1 };
+    }
+
+    pub fn keys(&self) -> &[KeyPub] {
+        return &self.keys;
+    }
+
+    pub fn threshold(&self) -> u8 {
+        return self.threshold;
+    }
+
+    pub fn is_single(&self) -> bool {
+        return self.keys.len() == 1;
+    }
+
+    pub fn index_of(&self, key: &KeyPub) -> Option<u8> {
+        return self.keys.iter().position(|k| k == key).map(|i| i as u8);
+    }
+
+    /// The key a core is known by: its signer's, or for a threshold core
+    /// a hash of the keys and threshold, which no one can sign with.
+    pub fn id(&self) -> KeyPub {
+        if self.is_single() {
+            return self.keys[0].clone();
+        }
+        let mut builder = HashBuilder::new();
+        builder.update(SIGNERS_CONTEXT);
+        builder.update(&[self.threshold]);
+        for key in &self.keys {
+            builder.update(&key.0);
+        }
+        return KeyPub(builder.finish().0);
+    }
+
+    /// Whether at least `threshold` distinct keys signed the block's root.
+    pub fn verify(&self, block: &SignatureBlock) -> bool {
+        let mut signed = Vec::new();
+        for (index, signature) in &block.signatures {
+            let Some(key) = self.keys.get(*index as usize) else {
+                return false;
+            };
+            if signed.contains(index) || !key.verify(&block.global_root.0, signature) {
+                return false;
+            }
+            signed.push(*index);
+        }
+        return signed.len() >= self.threshold as usize;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SignatureBlock {
+    pub global_root: Hash,
+    /// Signatures over the root, each with the index of its key among the
+    /// core's `Signers`; a single-signer core's block has just index 0.
+    pub signatures: Vec<(u8, Signature)>,
+}
+
+impl SignatureBlock {
+    /// A block signed by a single-signer core's key.
+    pub fn single(global_root: Hash, signature: Signature) -> Self {
+        return SignatureBlock { global_root, signatures: vec![(0, signature)] };
+    }
+
+    /// The signature of a single-signer block, or None for a threshold
+    /// block.
+    pub fn signature(&self) -> Option<&Signature> {
+        return match self.signatures.as_slice() {
+            [(0, signature)] => Some(signature),
+            _ => None,
+        };
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        return self.encode().expect("signature blocks are far below neopack's limits");
+    }
+
+    /// Reads a block of any version.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FormatError> {
+        if bytes.first() != Some(&spec::TAG_LIST) {
+            return SignatureBlock::from_v1_bytes(bytes);
+        }
+        let mut list = neopack::Decoder::new(bytes).list().map_err(|_| FormatError::SignatureBlock)?;
+        let version = list.next().ok().flatten().and_then(|value| value.as_u8().ok());
+        match version {
+            Some(SIGNATURE_BLOCK_VERSION | THRESHOLD_BLOCK_VERSION) => {}
+            Some(found) => return Err(FormatError::UnsupportedVersion { found, expected: THRESHOLD_BLOCK_VERSION }),
+            None => return Err(FormatError::SignatureBlock),
+        }
+        return SignatureBlock::decode(bytes).map_err(|_| FormatError::SignatureBlock);
+    }
+
+    /// The hex and newline encoding written before blocks were neopack.
+    /// Only a single-signer block has one.
+    pub fn to_v1_bytes(&self) -> Option<Vec<u8>> {
+        let signature = self.signature()?;
+        let mut out = Vec::with_capacity(SIGNATURE_BLOCK_V1_LEN);
+        out.extend_from_slice(self.global_root.to_hex().as_bytes());
+        out.push(b'\n');
+        out.extend_from_slice(&signature.0);
+        return Some(out);
+    }
+
+    fn from_v1_bytes(bytes: &[u8]) -> Result<Self, FormatError> {
+        if bytes.len() != SIGNATURE_BLOCK_V1_LEN || bytes[64] != b'\n' {
+            return Err(FormatError::SignatureBlock);
+        }
+        let root_hex = std::str::from_utf8(&bytes[..64])
+            .map_err(|_| FormatError::SignatureBlock)?;
+        let global_root = Hash::from_hex(root_hex).map_err(|_| FormatError::SignatureBlock)?;
+        let mut signature = [0u8; 64];
+        signature.copy_from_slice(&bytes[65..]);
+
+        return Ok(SignatureBlock::single(global_root, Signature(signature)));
+    }
+}
+
+/// The version 2 List for a single signer, else version 3. See the module
+/// docs.
+impl Pack for SignatureBlock {
+    fn write<W: ValueWriter>(&self, w: &mut W) -> neopack::Result<()> {
+        let mut list = w.list()?;
+        if let Some(signature) = self.signature() {
+            list.u8(SIGNATURE_BLOCK_VERSION)?;
+            self.global_root.write(&mut list)?;
+            signature.write(&mut list)?;
+        } else {
+            list.u8(THRESHOLD_BLOCK_VERSION)?;
+            self.global_root.write(&mut list)?;
+            let mut signatures = list.list()?;
+            for (index, signature) in &self.signatures {
+                let mut entry = signatures.list()?;
+                entry.u8(*index)?;
+                signature.write(&mut entry)?;
+                entry.finish()?;
+            }
+            signatures.finish()?;
+        }
+        list.finish()?;
+        return Ok(());
+    }
+
+    fn read(value: ValueDecoder<'_>) -> neopack::Result<Self> {
+        let ValueDecoder::List(mut list) = value else {
+            return Err(neopack::Error::TypeMismatch);
+        };
+        let version = list.next()?.ok_or(neopack::Error::Malformed)?.as_u8()?;
+        let global_root = Hash::read(list.next()?.ok_or(neopack::Error::Malformed)?)?;
+        let signatures = match (version, list.next()?.ok_or(neopack::Error::Malformed)?) {
+            (SIGNATURE_BLOCK_VERSION, signature) => vec![(0, Signature::read(signature)?)],
+            (THRESHOLD_BLOCK_VERSION, ValueDecoder::List(mut entries)) => {
+                let mut signatures = Vec::new();
+                while let Some(entry) = entries.next()? {
+                    let ValueDecoder::List(mut entry) = entry else {
+                        return Err(neopack::Error::Malformed);
+                    };
+                    let index = entry.next()?.ok_or(neopack::Error::Malformed)?.as_u8()?;
+                    let signature = Signature::read(entry.next()?.ok_or(neopack::Error::Malformed)?)?;
+                    signatures.push((index, signature));
+                }
+                signatures
+            }
+            _ => return Err(neopack::Error::Malformed),
+        };
+        if list.next()?.is_some() {
+            return Err(neopack::Error::Malformed);
+        }
+        return Ok(SignatureBlock { global_root, signatures });
+    }
+}
+
+/// The signed global root after the first `len` items.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub len: u64,
+    pub root: Hash,
+    pub signature: Signature,
+}
+
+impl Checkpoint {
+    /// Whether `signer` signed the root. Says nothing about the items
+    /// behind it; see `IsoCore::verify_from_checkpoint`.
+    pub fn verify(&self, signer: &KeyPub) -> bool {
+        return self.len > 0 && signer.verify(&self.root.0, &self.signature);
+    }
+}
+
+impl Pack for Checkpoint {
+    fn write<W: ValueWriter>(&self, w: &mut W) -> neopack::Result<()> {
+        let mut list = w.list()?;
+        list.u64(self.len)?;
+        self.root.write(&mut list)?;
+        self.signature.write(&mut list)?;
+        list.finish()?;
+        return Ok(());
+    }
+
+    fn read(value: ValueDecoder<'_>) -> neopack::Result<Self> {
+        let ValueDecoder::List(mut list) = value else {
+            return Err(neopack::Error::TypeMismatch);
+        };
+        let len = list.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+        let root = Hash::read(list.next()?.ok_or(neopack::Error::Malformed)?)?;
+        let signature = Signature::read(list.next()?.ok_or(neopack::Error::Malformed)?)?;
+        if list.next()?.is_some() {
+            return Err(neopack::Error::Malformed);
+        }
+        return Ok(Checkpoint { len, root, signature });
+    }
+}
+
+/// A witness's signature over a core's head: the root signed at `len`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cosignature {
+    pub witness: KeyPub,
+    pub len: u64,
+    pub root: Hash,
+    pub signature: Signature,
+}
+
+impl Cosignature {
+    pub fn sign(witness: &KeyPair, len: u64, root: Hash) -> Self {
+        let signature = witness.sign(&Cosignature::message(len, &root));
+        return Cosignature { witness: witness.key_pub.clone(), len, root, signature };
+    }
+
+    /// Whether the witness signed this head. Says nothing of whether the
+    /// core did; see `IsoCore::add_cosignature`.
+    pub fn verify(&self) -> bool {
+        return self.len > 0 && self.witness.verify(&Cosignature::message(self.len, &self.root), &self.signature);
+    }
+
+    fn message(len: u64, root: &Hash) -> Vec<u8> {
+        let mut message = COSIGNATURE_CONTEXT.to_vec();
+        message.extend_from_slice(&len.to_le_bytes());
+        message.extend_from_slice(&root.0);
+        return message;
+    }
+}
+
+impl Pack for Cosignature {
+    fn write<W: ValueWriter>(&self, w: &mut W) -> neopack::Result<()> {
+        let mut list = w.list()?;
+        self.witness.write(&mut list)?;
+        list.u64(self.len)?;
+        self.root.write(&mut list)?;
+        self.signature.write(&mut list)?;
+        list.finish()?;
+        return Ok(());
+    }
+
+    fn read(value: ValueDecoder<'_>) -> neopack::Result<Self> {
+        let ValueDecoder::List(mut list) = value else {
+            return Err(neopack::Error::TypeMismatch);
+        };
+        let witness = KeyPub::read(list.next()?.ok_or(neopack::Error::Malformed)?)?;
+        let len = list.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+        let root = Hash::read(list.next()?.ok_or(neopack::Error::Malformed)?)?;
+        let signature = Signature::read(list.next()?.ok_or(neopack::Error::Malformed)?)?;
+        if list.next()?.is_some() {
+            return Err(neopack::Error::Malformed);
+        }
+        return Ok(Cosignature { witness, len, root, signature });
+    }
+}
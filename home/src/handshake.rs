@@ -0,0 +1,423 @@
+//! Handshake: a mutually authenticated, encrypted channel between peers
+//!
+//! Both peers prove their ed25519 identities and agree on a pair of
+//! `KeyShared` session keys, one per direction, in three messages:
+//!
+//! 1. Initiator → responder: `["hello", ephemeral]`, a fresh X25519 key.
+//! 2. Responder → initiator: `["hello", ephemeral, auth]`.
+//! 3. Initiator → responder: `["auth", auth]`.
+//!
+//! Each `auth` is the sender's public key and its signature over the
+//! transcript (both ephemeral keys), encrypted under a key derived from the
+//! ephemeral Diffie-Hellman, so identities aren't sent in the clear. The
+//! session keys mix that ephemeral secret with `conspire` over the two
+//! static keys, so only the holders of both identities can derive them.
+//!
+//! After the handshake, `Channel` seals each message with
+//! XChaCha20-Poly1305 under a nonce built from its sequence number.
+//! Messages must be opened in the order they were sealed; a replayed,
+//! dropped, or reordered message fails to open.
+//!
+//! `Initiator` and `Responder` do no I/O; `SecureTransport::connect` and
+//! `accept` run them over any `Transport`.
+
+use blake3::Hasher;
+use rand_core::CryptoRngCore;
+use crate::key::BoxKeyPair;
+use crate::key::BoxPub;
+use crate::key::DecryptError;
+use crate::key::KeyPair;
+use crate::key::KeyPub;
+use crate::key::KeyShared;
+use crate::key::Payload;
+use crate::key::Signature;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+use crate::neopack::Pack;
+use crate::transport::Transport;
+use crate::transport::TransportError;
+
+#[derive(Debug)]
+pub enum HandshakeError {
+    Transport(TransportError),
+    Neopack(neopack::Error),
+    Decrypt(DecryptError),
+    /// A handshake message has the wrong tag or fields.
+    BadMessage,
+    /// A public key isn't a valid curve point.
+    BadKey,
+    /// The peer's signature over the transcript doesn't verify.
+    BadSignature,
+    /// The responder isn't the peer the initiator meant to reach.
+    UnexpectedPeer(KeyPub),
+}
+
+impl From<TransportError> for HandshakeError {
+    fn from(err: TransportError) -> Self {
+        return HandshakeError::Transport(err);
+    }
+}
+
+impl From<neopack::Error> for HandshakeError {
+    fn from(err: neopack::Error) -> Self {
+        return HandshakeError::Neopack(err);
+    }
+}
+
+impl From<DecryptError> for HandshakeError {
+    fn from(err: DecryptError) -> Self {
+        return HandshakeError::Decrypt(err);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Initiator,
+    Responder,
+}
+
+impl Role {
+    fn context(self) -> &'static str {
+        return match self {
+            Role::Initiator => "home handshake v1 initiator",
+            Role::Responder => "home handshake v1 responder",
+        };
+    }
+}
+
+/// Keys and transcript shared once both ephemeral keys are known.
+struct Secrets {
+    transcript: [u8; 32],
+    ephemeral: KeyShared,
+}
+
+impl Secrets {
+    fn new(local: &BoxKeyPair, remote: &BoxPub, initiator: &BoxPub, responder: &BoxPub) -> Self {
+        let mut hasher = Hasher::new_derive_key("home handshake v1 transcript");
+        hasher.update(&initiator.0);
+        hasher.update(&responder.0);
+        return Secrets {
+            transcript: *hasher.finalize().as_bytes(),
+            ephemeral: local.conspire(remote),
+        };
+    }
+
+    /// Key for the `auth` sent by `role`. Each is used for one message, so
+    /// a zero nonce is safe.
+    fn auth_key(&self, role: Role) -> KeyShared {
+        let mut hasher = Hasher::new_derive_key(role.context());
+        hasher.update(b"auth");
+        hasher.update(&self.ephemeral.0);
+        hasher.update(&self.transcript);
+        return KeyShared(*hasher.finalize().as_bytes());
+    }
+
+    /// Key for messages sent by `role` once the handshake is done.
+    fn session_key(&self, role: Role, statics: &KeyShared) -> KeyShared {
+        let mut hasher = Hasher::new_derive_key(role.context());
+        hasher.update(b"session");
+        hasher.update(&self.ephemeral.0);
+        hasher.update(&statics.0);
+        hasher.update(&self.transcript);
+        return KeyShared(*hasher.finalize().as_bytes());
+    }
+
+    fn seal_auth(&self, role: Role, me: &KeyPair) -> Result<Vec<u8>, neopack::Error> {
+        let signature = me.sign(&self.signed(role));
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        me.key_pub.write(&mut list)?;
+        signature.write(&mut list)?;
+        list.finish()?;
+        return Ok(self.auth_key(role).encrypt_with_nonce(enc.as_bytes(), [0; 24]).ciphertext);
+    }
+
+    /// Opens the peer's `auth` and checks its signature. Returns its key.
+    fn open_auth(&self, role: Role, ciphertext: &[u8]) -> Result<KeyPub, HandshakeError> {
+        let plain = self.auth_key(role).decrypt(Payload { nonce: [0; 24], ciphertext: ciphertext.to_vec() })?;
+        let mut dec = Decoder::new(&plain);
+        let mut list = dec.list()?;
+        let mut next = || list.next()?.ok_or(neopack::Error::Malformed);
+        let key = KeyPub::read(next()?).map_err(|_| HandshakeError::BadKey)?;
+        let signature = Signature(next()?.as_bytes()?.try_into().map_err(|_| HandshakeError::BadMessage)?);
+        if !key.verify(&self.signed(role), &signature) {
+            return Err(HandshakeError::BadSignature);
+        }
+        return Ok(key);
+    }
+
+    fn signed(&self, role: Role) -> Vec<u8> {
+        return [role.context().as_bytes(), &self.transcript].concat();
+    }
+
+    fn channel(&self, role: Role, me: &KeyPair, peer: KeyPub) -> Result<Channel, HandshakeError> {
+        let peer_box = BoxPub::from_signing(&peer).ok_or(HandshakeError::BadKey)?;
+        let statics = BoxKeyPair::from_signing(me).conspire(&peer_box);
+        let other = match role {
+            Role::Initiator => Role::Responder,
+            Role::Responder => Role::Initiator,
+        };
+        return Ok(Channel {
+            peer,
+            send_key: self.session_key(role, &statics),
+            recv_key: self.session_key(other, &statics),
+            send_seq: 0,
+            recv_seq: 0,
+        });
+    }
+}
+
+/// The side that opens a connection, knowing who it means to reach.
+pub struct Initiator {
+    me: KeyPair,
+    ephemeral: BoxKeyPair,
+    expected: Option<KeyPub>,
+}
+
+impl Initiator {
+    /// Starts a handshake, returning the first message. If `expected` is
+    /// set, the handshake fails unless the responder proves that identity.
+    #[cfg(feature = "rng")]
+    pub fn new(me: &KeyPair, expected: Option<KeyPub>) -> Result<(Self, Vec<u8>), HandshakeError> {
+        return Self::new_with(me, expected, &mut rand_core::OsRng);
+    }
+
+    /// Like `new`, drawing the ephemeral key from `rng`
+    pub fn new_with(me: &KeyPair, expected: Option<KeyPub>, rng: &mut impl CryptoRngCore) -> Result<(Self, Vec<u8>), HandshakeError> {
+        let ephemeral = BoxKeyPair::generate(rng);
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        list.str("hello")?;
+        list.bytes(&ephemeral.box_pub.0)?;
+        list.finish()?;
+
+        let initiator = Initiator { me: me.clone(), ephemeral, expected };
+        return Ok((initiator, enc.into_bytes()));
+    }
+
+    /// Takes the responder's reply, returning the channel and the final
+    /// message to send.
+    pub fn finish(self, reply: &[u8]) -> Result<(Channel, Vec<u8>), HandshakeError> {
+        let mut dec = Decoder::new(reply);
+        let mut list = dec.list()?;
+        let mut next = || list.next()?.ok_or(HandshakeError::BadMessage);
+        if next()?.as_str()? != "hello" {
+            return Err(HandshakeError::BadMessage);
+        }
+        let remote = BoxPub(next()?.as_bytes()?.try_into().map_err(|_| HandshakeError::BadKey)?);
+        let auth = next()?.as_bytes()?;
+
+        let secrets = Secrets::new(&self.ephemeral, &remote, &self.ephemeral.box_pub, &remote);
+        let peer = secrets.open_auth(Role::Responder, auth)?;
+        if self.expected.as_ref().is_some_and(|expected| *expected != peer) {
+            return Err(HandshakeError::UnexpectedPeer(peer));
+        }
+
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        list.str("auth")?;
+        list.bytes(&secrets.seal_auth(Role::Initiator, &self.me)?)?;
+        list.finish()?;
+
+        let channel = secrets.channel(Role::Initiator, &self.me, peer)?;
+        return Ok((channel, enc.into_bytes()));
+    }
+}
+
+/// The side that accepts a connection, learning who is on the other end.
+pub struct Responder {
+    me: KeyPair,
+    secrets: Secrets,
+}
+
+impl Responder {
+    /// Answers the initiator's first message, returning the reply.
+    #[cfg(feature = "rng")]
+    pub fn new(me: &KeyPair, hello: &[u8]) -> Result<(Self, Vec<u8>), HandshakeError> {
+        return Self::new_with(me, hello, &mut rand_core::OsRng);
+    }
+
+    /// Like `new`, drawing the ephemeral key from `rng`
+    pub fn new_with(me: &KeyPair, hello: &[u8], rng: &mut impl CryptoRngCore) -> Result<(Self, Vec<u8>), HandshakeError> {
+        let mut dec = Decoder::new(hello);
+        let mut list = dec.list()?;
+        let mut next = || list.next()?.ok_or(HandshakeError::BadMessage);
+        if next()?.as_str()? != "hello" {
+            return Err(HandshakeError::BadMessage);
+        }
+        let remote = BoxPub(next()?.as_bytes()?.try_into().map_err(|_| HandshakeError::BadKey)?);
+
+        let ephemeral = BoxKeyPair::generate(rng);
+        let secrets = Secrets::new(&ephemeral, &remote, &remote, &ephemeral.box_pub);
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        list.str("hello")?;
+        list.bytes(&ephemeral.box_pub.0)?;
+        list.bytes(&secrets.seal_auth(Role::Responder, me)?)?;
+        list.finish()?;
+
+        let responder = Responder { me: me.clone(), secrets };
+        return Ok((responder, enc.into_bytes()));
+    }
+
+    /// Takes the initiator's final message. The channel's `peer` is the
+    /// initiator's proven identity; deciding whether to trust it is up to
+    /// the caller.
+    pub fn finish(self, auth: &[u8]) -> Result<Channel, HandshakeError> {
+        let mut dec = Decoder::new(auth);
+        let mut list = dec.list()?;
+        let mut next = || list.next()?.ok_or(HandshakeError::BadMessage);
+        if next()?.as_str()? != "auth" {
+            return Err(HandshakeError::BadMessage);
+        }
+        let peer = self.secrets.open_auth(Role::Initiator, next()?.as_bytes()?)?;
+        return self.secrets.channel(Role::Responder, &self.me, peer);
+    }
+}
+
+/// An established channel: seals outgoing messages and opens incoming
+/// ones, each direction under its own key and sequence numbers.
+#[derive(Debug)]
+pub struct Channel {
+    /// The peer's proven identity.
+    pub peer: KeyPub,
+    send_key: KeyShared,
+    recv_key: KeyShared,
+    send_seq: u64,
+    recv_seq: u64,
+}
+
+fn sequence_nonce(seq: u64) -> [u8; 24] {
+    let mut nonce = [0u8; 24];
+    nonce[16..].copy_from_slice(&seq.to_le_bytes());
+    return nonce;
+}
+
+impl Channel {
+    pub fn seal(&mut self, message: &[u8]) -> Vec<u8> {
+        let payload = self.send_key.encrypt_with_nonce(message, sequence_nonce(self.send_seq));
+        self.send_seq += 1;
+        return payload.ciphertext;
+    }
+
+    /// Opens the next incoming message. Fails if it is forged, replayed,
+    /// or out of order.
+    pub fn open(&mut self, sealed: &[u8]) -> Result<Vec<u8>, DecryptError> {
+        let message = self.recv_key.decrypt(Payload {
+            nonce: sequence_nonce(self.recv_seq),
+            ciphertext: sealed.to_vec(),
+        })?;
+        self.recv_seq += 1;
+        return Ok(message);
+    }
+}
+
+/// A transport whose messages all go through a `Channel`.
+#[derive(Debug)]
+pub struct SecureTransport<T> {
+    inner: T,
+    pub channel: Channel,
+}
+
+impl<T: Transport> SecureTransport<T> {
+    /// Runs the handshake as initiator over `inner`.
+    #[cfg(feature = "rng")]
+    pub fn connect(mut inner: T, me: &KeyPair, expected: Option<KeyPub>) -> Result<Self, HandshakeError> {
+        let (initiator, hello) = Initiator::new(me, expected)?;
+        inner.send(&hello)?;
+        let (channel, auth) = initiator.finish(&inner.recv()?)?;
+        inner.send(&auth)?;
+        return Ok(SecureTransport { inner, channel });
+    }
+
+    /// Runs the handshake as responder over `inner`.
+    #[cfg(feature = "rng")]
+    pub fn accept(mut inner: T, me: &KeyPair) -> Result<Self, HandshakeError> {
+        let (responder, reply) = Responder::new(me, &inner.recv()?)?;
+        inner.send(&reply)?;
+        let channel = responder.finish(&inner.recv()?)?;
+        return Ok(SecureTransport { inner, channel });
+    }
+
+    /// The peer's proven identity.
+    pub fn peer(&self) -> &KeyPub {
+        return &self.channel.peer;
+    }
+}
+
+impl<T: Transport> Transport for SecureTransport<T> {
+    fn send(&mut self, message: &[u8]) -> Result<(), TransportError> {
+        let sealed = self.channel.seal(message);
+        return self.inner.send(&sealed);
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, TransportError> {
+        let sealed = self.inner.recv()?;
+        return self.channel.open(&sealed).map_err(|_| TransportError::Authentication);
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+    use crate::transport::MemTransport;
+
+    #[test]
+    fn handshake_authenticates_both_sides() {
+        let alice = KeyPair::ephemeral();
+        let bob = KeyPair::ephemeral();
+
+        let (initiator, hello) = Initiator::new(&alice, Some(bob.key_pub.clone())).unwrap();
+        let (responder, reply) = Responder::new(&bob, &hello).unwrap();
+        let (mut a, auth) = initiator.finish(&reply).unwrap();
+        let mut b = responder.finish(&auth).unwrap();
+        assert_eq!(a.peer, bob.key_pub);
+        assert_eq!(b.peer, alice.key_pub);
+
+        let first = a.seal(b"first");
+        let second = a.seal(b"second");
+        assert_ne!(first[..5], b"first"[..]);
+        assert!(b.open(&second).is_err(), "out of order");
+        assert_eq!(b.open(&first).unwrap(), b"first");
+        assert!(b.open(&first).is_err(), "replayed");
+        assert_eq!(b.open(&second).unwrap(), b"second");
+        assert_eq!(a.open(&b.seal(b"reply")).unwrap(), b"reply");
+
+        // Someone other than the expected responder is refused
+        let mallory = KeyPair::ephemeral();
+        let (initiator, hello) = Initiator::new(&alice, Some(bob.key_pub.clone())).unwrap();
+        let (_, reply) = Responder::new(&mallory, &hello).unwrap();
+        assert!(matches!(initiator.finish(&reply), Err(HandshakeError::UnexpectedPeer(_))));
+    }
+
+    #[test]
+    fn handshake_rejects_tampering() {
+        let alice = KeyPair::ephemeral();
+        let bob = KeyPair::ephemeral();
+        let (initiator, hello) = Initiator::new(&alice, None).unwrap();
+        let (_, mut reply) = Responder::new(&bob, &hello).unwrap();
+        let last = reply.len() - 1;
+        reply[last] ^= 1;
+        assert!(matches!(initiator.finish(&reply), Err(HandshakeError::Decrypt(_))));
+    }
+
+    #[test]
+    fn secure_transport_carries_messages() {
+        let alice = KeyPair::ephemeral();
+        let bob = KeyPair::ephemeral();
+        let (near, far) = MemTransport::pair();
+        let bob_pub = bob.key_pub.clone();
+        let server = std::thread::spawn(move || {
+            let mut far = SecureTransport::accept(far, &bob).unwrap();
+            let message = far.recv().unwrap();
+            far.send(&message).unwrap();
+            return far.peer().clone();
+        });
+
+        let mut near = SecureTransport::connect(near, &alice, Some(bob_pub)).unwrap();
+        near.send(b"over the wire").unwrap();
+        assert_eq!(near.recv().unwrap(), b"over the wire");
+        assert_eq!(server.join().unwrap(), alice.key_pub);
+    }
+}
@@ -0,0 +1,100 @@
+//! Hex encoding and decoding
+//!
+//! Hashes, keys, and signatures are shown and stored as hex in node files,
+//! the root index, signature blocks, discovery records, and exported
+//! sites. Everything goes through here so there is one encoding: lowercase,
+//! two digits per byte. Decoding accepts either case and fails on anything
+//! that isn't hex rather than guessing, since the input may come from a
+//! file or a peer.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexError {
+    /// An odd number of digits.
+    OddLength,
+    /// The wrong number of digits for a fixed-size value.
+    Length { expected: usize, found: usize },
+    /// A character that isn't a hex digit, at this byte offset.
+    Digit(usize),
+}
+
+const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Lowercase hex, two digits per byte.
+pub fn encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    push(&mut out, bytes);
+    return out;
+}
+
+/// Appends the hex of `bytes` to `out`.
+pub fn push(out: &mut String, bytes: &[u8]) {
+    for b in bytes {
+        out.push(DIGITS[(b >> 4) as usize] as char);
+        out.push(DIGITS[(b & 0xf) as usize] as char);
+    }
+}
+
+pub fn decode(hex: &str) -> Result<Vec<u8>, HexError> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(HexError::OddLength);
+    }
+    let mut out = Vec::with_capacity(hex.len() / 2);
+    for (i, pair) in hex.as_bytes().chunks(2).enumerate() {
+        out.push(decode_pair(pair, i * 2)?);
+    }
+    return Ok(out);
+}
+
+/// Decodes exactly `N` bytes, as for a hash or key.
+pub fn decode_array<const N: usize>(hex: &str) -> Result<[u8; N], HexError> {
+    if hex.len() != N * 2 {
+        return Err(HexError::Length { expected: N * 2, found: hex.len() });
+    }
+    let mut out = [0u8; N];
+    for (i, pair) in hex.as_bytes().chunks(2).enumerate() {
+        out[i] = decode_pair(pair, i * 2)?;
+    }
+    return Ok(out);
+}
+
+fn decode_pair(pair: &[u8], offset: usize) -> Result<u8, HexError> {
+    let high = digit(pair[0]).ok_or(HexError::Digit(offset))?;
+    let low = digit(pair[1]).ok_or(HexError::Digit(offset + 1))?;
+    return Ok(high << 4 | low);
+}
+
+fn digit(c: u8) -> Option<u8> {
+    return match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_rejects_bad_input() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let hex = encode(&bytes);
+        assert_eq!(&hex[..8], "00010203");
+        assert_eq!(&hex[hex.len() - 4..], "feff");
+        assert_eq!(decode(&hex).unwrap(), bytes);
+        assert_eq!(decode(&hex.to_uppercase()).unwrap(), bytes);
+        assert_eq!(decode("").unwrap(), []);
+
+        assert_eq!(decode_array::<2>("beef").unwrap(), [0xbe, 0xef]);
+        assert_eq!(decode_array::<2>("bee"), Err(HexError::Length { expected: 4, found: 3 }));
+        assert_eq!(decode("abc"), Err(HexError::OddLength));
+        // Would once have decoded to zero bytes without complaint
+        assert_eq!(decode("00zz"), Err(HexError::Digit(2)));
+        assert_eq!(decode_array::<2>("0g00"), Err(HexError::Digit(1)));
+        assert_eq!(decode("é"), Err(HexError::Digit(0)));
+    }
+}
@@ -1,9 +1,10 @@
 //! IsoCore: Append-only Merkle DAG using covering tree indexing
 //!
-//! An IsoCore maintains two cores:
+//! An IsoCore maintains three cores:
 //!
 //! - data_core: Stores actual message data
 //! - verkle_core: Stores tree structure (nodes)
+//! - sig_core: Stores a signature block per item
 //!
 //! IsoCore uses the covering tree module to precisely calculate which
 //! nodes need to be created when each item is added. This gives us:
@@ -11,15 +12,62 @@
 //! - O(1) space: No need to track a forest of roots
 //! - Deterministic: The tree structure is fully determined by the count
 //! - Stateless navigation: Can compute any node's children without state
+//!
+//! A light core, made by `create_light` or `light_mem`, has no data core.
+//! It takes items by leaf hash alone (`add_signed_hash`), so a mirror can
+//! hold and prove a core without seeing its payloads. `get_message` on an
+//! item whose payload it lacks returns `NeedsData` with the expected hash,
+//! and `hydrate` checks a payload against that hash and keeps it, in
+//! memory or in a `payloads` directory named by leaf hash.
+//!
+//! info.nd says who signs the core and how it is laid out: `[version,
+//! signer, width, created_at, light?, inline_limit?]` as a neopack map, then a seal over
+//! those fields, signed by the core's key pair; a threshold core's are
+//! signed by its signers, as a block would be. A replica or light core
+//! keeps its source's info.nd as is, so it needs no secret key, and is
+//! told apart by its payloads directory. `load` checks the seal, so a
+//! flipped bit in the signer is reported as `BadInfo` rather than
+//! trusted. Files from before seals were signed, with no seal or only a
+//! hash of the fields, still load for reading, with `is_sealed` false;
+//! appends are `UnsealedInfo` until `reseal` signs them.
+//!
+//! A core made by `create_inline` keeps payloads up to its inline limit
+//! in their leaf nodes, hex-encoded after the data index, and serves them
+//! from there. The data core still gets an empty message for each, so it
+//! holds one per item as recovery expects. The leaf hash is the same
+//! either way, so inlining never changes a root.
+//!
+//! For bulk imports, `ingest` stores payloads and records only their leaf
+//! hashes, and `build_tree` then makes every node and signature block in
+//! one pass, keeping node hashes in memory instead of reading each peak
+//! back and hashing it again on every append. Ingested items that
+//! haven't been built are like a torn append: `load` drops their
+//! payloads.
+//!
+//! A threshold core, made by `create_threshold`, is signed by any
+//! `threshold` of its `Signers` through `add_message_by`, and info.nd
+//! lists them. Its blocks carry a signature from each, and every check
+//! that verifies a block counts them. Proofs, checkpoints, and
+//! replication carry a single signature, so don't cover threshold cores
+//! yet.
 
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::ops::Range;
 use std::path::Path;
 use std::path::PathBuf;
 use std::io::Write;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::channel;
+use std::time::Instant;
 use crate::core::MessageId;
 use crate::core::CoreError;
 use crate::core::Core;
-use crate::key::hash;
+use crate::key::DecryptError;
 use crate::key::Hash;
+use crate::key::hash;
 use crate::key::KeyPair;
 use crate::key::KeyPub;
 use crate::key::Signature;
@@ -28,14 +76,61 @@ use crate::covering::coverings_for_item;
 use crate::covering::ItemId;
 use crate::covering::CoveringId;
 use crate::covering::get_peaks;
+use crate::covering::covering_range;
+use crate::covering::dump_tree_with;
+use crate::proof::InclusionProof;
+use crate::shared::SharedIsoCore;
+use crate::proof::ConsistencyProof;
+use crate::proof::ForkProof;
+use crate::proof::HeadProof;
+use crate::proof::RangeProof;
+use crate::proof::overlaps;
+use crate::proof::ascent;
+use crate::proof::nodes_below;
+use crate::metrics;
+use crate::metrics::MetricsHandle;
+use crate::neodisk::Durability;
+use crate::neodisk::write_atomic;
 use crate::neopack::Encoder;
+use crate::neopack::MapEncoder;
+use crate::neopack::Timestamp;
+use crate::neopack::ValueDecoder;
 use crate::neopack::Decoder;
+use crate::neopack::Pack;
+use crate::format::Artifact;
+use crate::format::FormatError;
+use crate::format::Signers;
+use crate::format::WIDTH;
+use crate::hex;
+
+pub use crate::format::FormatVersion;
+pub use crate::format::Checkpoint;
+pub use crate::format::Cosignature;
+pub use crate::format::SignatureBlock;
 
-const WIDTH: u64 = 8;
-const INFO_ISOCORE: &'static str = "info.nd";
-const FILE_DATA: &'static str = "data.nd";
-const FILE_VERKLE: &'static str = "verkle.nd";
-const FILE_SIG: &'static str = "sig.nd";
+pub(crate) const INFO_ISOCORE: &str = "info.nd";
+pub(crate) const FILE_DATA: &str = "data.nd";
+pub(crate) const FILE_VERKLE: &str = "verkle.nd";
+pub(crate) const FILE_SIG: &str = "sig.nd";
+const FILE_INTENT: &str = "intent.nd";
+const FILE_ROOTS: &str = "roots.nd";
+const FILE_CHECKPOINTS: &str = "checkpoints.nd";
+const FILE_COSIGNATURES: &str = "witnesses.nd";
+const DIR_PAYLOADS: &str = "payloads";
+/// Prefixes the fields info.nd's seal covers, so the signature can't be
+/// taken for one over anything else.
+const INFO_CONTEXT: &[u8] = b"home isocore info\n";
+/// Items read per batch by the parallel audit and batch proofs, to bound
+/// memory use.
+#[cfg(feature = "parallel")]
+const AUDIT_BATCH: u64 = 4096;
+
+#[cfg(test)]
+thread_local! {
+    /// An item `build_item` fails on after writing its nodes, so tests can
+    /// see what a failed `build_tree` leaves behind.
+    static FAIL_BUILD_AT: std::cell::Cell<Option<u64>> = const { std::cell::Cell::new(None) };
+}
 
 #[derive(Debug)]
 pub enum IsoCoreError {
@@ -47,7 +142,32 @@ pub enum IsoCoreError {
     HexEncoding,
     MessageIdParse(std::num::ParseIntError),
     IntegrityError,
+    /// The core doesn't hold the payload of this item; a light core can
+    /// be given it with `hydrate`.
+    NeedsData { item_id: ItemId, hash: Hash },
     SignerMismatch,
+    /// Fewer distinct keys signed than a threshold core needs.
+    BelowThreshold,
+    /// Fewer of the required witnesses cosigned the head than needed.
+    Unwitnessed { needed: usize, found: usize },
+    /// Proofs, checkpoints, and replicated items carry a single signature,
+    /// so a threshold core can't make them.
+    ThresholdCore,
+    /// Items were ingested and wait for `build_tree`; nothing else can be
+    /// appended, or collected by `gc`, until then.
+    Ingesting,
+    Layout(LayoutError),
+    UnsupportedVersion { found: u8, expected: u8 },
+    /// A file in the core directory isn't the kind expected there.
+    BadMagic(Artifact),
+    /// info.nd doesn't match its signature or hash, or records a tree
+    /// width other than this build's.
+    BadInfo,
+    /// info.nd is from before seals were signed: it has no seal, or only
+    /// a hash of its fields. Such a core can be read but not appended to
+    /// until `IsoCore::reseal` signs it.
+    UnsealedInfo,
+    Decrypt(DecryptError),
     Io(std::io::Error),
 }
 
@@ -57,12 +177,35 @@ impl From<CoreError> for IsoCoreError {
     }
 }
 
+impl From<DecryptError> for IsoCoreError {
+    fn from(e: DecryptError) -> Self {
+        return IsoCoreError::Decrypt(e);
+    }
+}
+
 impl From<std::io::Error> for IsoCoreError {
     fn from(e: std::io::Error) -> Self {
         return IsoCoreError::Io(e);
     }
 }
 
+impl From<LayoutError> for IsoCoreError {
+    fn from(e: LayoutError) -> Self {
+        return IsoCoreError::Layout(e);
+    }
+}
+
+impl From<FormatError> for IsoCoreError {
+    fn from(e: FormatError) -> Self {
+        return match e {
+            FormatError::UnsupportedVersion { found, expected } => IsoCoreError::UnsupportedVersion { found, expected },
+            FormatError::BadMagic(artifact) => IsoCoreError::BadMagic(artifact),
+            FormatError::SignatureBlock => IsoCoreError::NodeFormat,
+            FormatError::Signers => IsoCoreError::BadInfo,
+        };
+    }
+}
+
 impl From<crate::neopack::Error> for IsoCoreError {
     fn from(e: crate::neopack::Error) -> Self {
         return IsoCoreError::Neopack(e);
@@ -80,6 +223,9 @@ pub struct NodeChild {
     pub node_type: NodeType,
     pub hash: Hash,
     pub index: MessageId,
+    /// The payload itself, for a leaf small enough to inline. See
+    /// `IsoCore::inline_limit`.
+    pub data: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone)]
@@ -87,28 +233,12 @@ pub struct VerkleNode {
     pub children: Vec<NodeChild>,
 }
 
-#[derive(Debug, Clone)]
-pub struct SignatureBlock {
-    pub global_root: Hash,
-    pub signature: Signature,
-}
-
-impl SignatureBlock {
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut out = Vec::new();
-        out.extend_from_slice(&self.global_root.to_hex());
-        out.push(b'\n');
-        out.extend_from_slice(&self.signature.0);
-        return out;
-    }
-}
-
 impl VerkleNode {
-    pub fn to_bytes(&self) -> Vec<u8> {
+    pub fn to_bytes(&self, version: FormatVersion) -> Vec<u8> {
         let mut out = Vec::new();
 
-        let root_hash = self.compute_hash();
-        out.extend_from_slice(&root_hash.to_hex());
+        let root_hash = self.compute_hash(version);
+        out.extend_from_slice(root_hash.to_hex().as_bytes());
         out.push(b'\n');
 
         for child in &self.children {
@@ -118,9 +248,13 @@ impl VerkleNode {
             };
             out.extend_from_slice(type_str);
             out.push(b' ');
-            out.extend_from_slice(&child.hash.to_hex());
+            out.extend_from_slice(child.hash.to_hex().as_bytes());
             out.push(b' ');
             out.extend_from_slice(child.index.to_file_name().as_bytes());
+            if let Some(data) = &child.data {
+                out.push(b' ');
+                out.extend_from_slice(hex::encode(data).as_bytes());
+            }
             out.push(b'\n');
         }
 
@@ -142,324 +276,3584 @@ impl VerkleNode {
         return Ok(VerkleNode { children: children? });
     }
 
-    pub fn compute_hash(&self) -> Hash {
-        let mut data = Vec::new();
-        for child in &self.children {
-            data.extend_from_slice(&child.hash.0);
-        }
-        return hash(&data);
+    pub fn compute_hash(&self, version: FormatVersion) -> Hash {
+        return version.hash_node(self.children.iter().map(|child| &child.hash));
     }
 }
 
-#[derive(Debug)]
-pub struct IsoCore {
-    pub path: Option<PathBuf>,
-    pub signer: KeyPub,
-    pub data_core: Core,
-    pub verkle_core: Core,
-    pub sig_core: Core,
+/// The kinds of damage `IsoCore::audit` checks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditCategory {
+    /// A message does not hash to its leaf hash, or can't be read.
+    Data,
+    /// A verkle node is malformed or a child hash doesn't match the child.
+    Node,
+    /// The bagged peaks don't match the global root recorded for an item.
+    Root,
+    /// A signature block doesn't verify against the core's signer.
+    Signature,
 }
 
-impl IsoCore {
-    pub fn create_mem(signer: &KeyPair) -> Self {
-        return Self {
-            path: None,
-            signer: signer.key_pub.clone(),
-            data_core: Core::create_mem(),
-            verkle_core: Core::create_mem(),
-            sig_core: Core::create_mem(),
-        };
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEvent {
+    /// Every check for this item has run.
+    Checked { item: ItemId, total: u64 },
+    /// A check failed. `index` is a covering id for `Node`, else an item id.
+    Failure { category: AuditCategory, index: u64 },
+}
 
-    pub fn create(path: PathBuf, signer: &KeyPair) -> Result<Self, IsoCoreError> {
-        // Create directory
-        std::fs::create_dir_all(&path)?;
-        
-        let data_path = path.join(FILE_DATA);
-        let verkle_path = path.join(FILE_VERKLE);
-        let sig_path = path.join(FILE_SIG);
-        
-        // Write info.nd with public key as neopack
-        let info_path = path.join(INFO_ISOCORE);
-        let mut enc = Encoder::new();
-        let mut map = enc.map()?;
-        map.key("version")?.u8(0x01)?;
-        map.key("signer")?.bytes(&signer.key_pub.0)?;
-        map.finish()?;
-        
-        let mut file = std::fs::File::create(info_path)?;
-        file.write_all(enc.as_bytes())?;
+/// Failure count for one audit category, and where it first failed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AuditCount {
+    pub errors: u64,
+    pub first: Option<u64>,
+}
 
-        return Ok(Self {
-            path: Some(path),
-            signer: signer.key_pub.clone(),
-            data_core: Core::create(data_path)?,
-            verkle_core: Core::create(verkle_path)?,
-            sig_core: Core::create(sig_path)?,
-        });
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    pub items: u64,
+    pub data: AuditCount,
+    pub nodes: AuditCount,
+    pub roots: AuditCount,
+    pub signatures: AuditCount,
+}
+
+impl AuditReport {
+    pub fn is_ok(&self) -> bool {
+        return self.data.errors == 0
+            && self.nodes.errors == 0
+            && self.roots.errors == 0
+            && self.signatures.errors == 0;
     }
 
-    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, IsoCoreError> {
-        let path = path.as_ref();
-        let data_path = path.join(FILE_DATA);
-        let verkle_path = path.join(FILE_VERKLE);
-        let sig_path = path.join(FILE_SIG);
-        
-        // Read info.nd to get public key
-        let info_path = path.join(INFO_ISOCORE);
-        let info_bytes = std::fs::read(info_path)?;
-        
-        let mut dec = Decoder::new(&info_bytes);
-        let mut map = dec.map()?;
-        
-        let Some(("version", version)) = map.next()? else {
-            return Err(IsoCoreError::NodeFormat);
-        };
-        let _version = version.as_u8()?;
-        
-        let Some(("signer", signer_val)) = map.next()? else {
-            return Err(IsoCoreError::NodeFormat);
+    fn record(&mut self, category: AuditCategory, index: u64, progress: &mut impl FnMut(AuditEvent)) {
+        let count = match category {
+            AuditCategory::Data => &mut self.data,
+            AuditCategory::Node => &mut self.nodes,
+            AuditCategory::Root => &mut self.roots,
+            AuditCategory::Signature => &mut self.signatures,
         };
-        let signer_bytes = signer_val.as_bytes()?;
-        
-        if signer_bytes.len() != 32 {
-            return Err(IsoCoreError::NodeFormat);
-        }
-        
-        let mut pubkey_array = [0u8; 32];
-        pubkey_array.copy_from_slice(signer_bytes);
+        count.errors += 1;
+        count.first.get_or_insert(index);
+        progress(AuditEvent::Failure { category, index });
+    }
+}
 
-        return Ok(Self {
-            path: Some(path.to_path_buf()),
-            signer: KeyPub(pubkey_array),
-            data_core: Core::load(data_path)?,
-            verkle_core: Core::load(verkle_path)?,
-            sig_core: Core::load(sig_path)?,
-        });
+/// What `IsoCore::gc` found and removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Verkle nodes reachable from the peaks.
+    pub reachable: u64,
+    /// Verkle nodes stored but not reachable.
+    pub unreachable: u64,
+    pub removed_nodes: u64,
+    pub removed_signatures: u64,
+    pub removed_messages: u64,
+    /// Bytes of message contents removed, before framing.
+    pub reclaimed_bytes: u64,
+}
+
+/// Sizes of a core, from `IsoCore::stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoreStats {
+    pub items: u64,
+    /// Bytes of item payloads held, before framing. A light core keeps
+    /// its payloads outside the cores, so counts none; inlined payloads
+    /// count toward `verkle_bytes` instead.
+    pub payload_bytes: u64,
+    /// Bytes of encoded tree nodes.
+    pub verkle_bytes: u64,
+    /// Bytes of encoded signature blocks.
+    pub sig_bytes: u64,
+    /// Size on disk of each file in the core's directory as of the last
+    /// flush, by name; a directory counts the files directly in it. Empty
+    /// for an in-memory core.
+    pub disk: BTreeMap<String, u64>,
+}
+
+impl CoreStats {
+    /// Bytes spent on the tree and signatures.
+    pub fn overhead_bytes(&self) -> u64 {
+        return self.verkle_bytes + self.sig_bytes;
     }
 
-    pub fn add_message(&mut self, message: &[u8], signer: &KeyPair) -> Result<Hash, IsoCoreError> {
-        // Verify signer matches IsoCore's public key
-        if signer.key_pub != self.signer {
-            return Err(IsoCoreError::SignerMismatch);
-        }
-        
-        let data_index = self.data_core.add_message(message)?;
-        let msg_hash = hash(message);
+    /// Bytes the core's directory takes on disk.
+    pub fn disk_bytes(&self) -> u64 {
+        return self.disk.values().sum();
+    }
+}
 
-        let item_id = ItemId((self.len().0 - 1) as u64);
-        let coverings = coverings_for_item(item_id, WIDTH);
+/// What appends have written to each core since the core was opened,
+/// from `IsoCore::amplification_stats`. Counts the bytes handed to the
+/// cores, before NeoDisk frames and compresses them; `CoreStats::disk`
+/// gives what the files take.
+///
+/// Each append writes its payload, one tree node per level it touches
+/// and a signature block. A large `verkle_bytes` share means nodes are
+/// rewritten often for what they carry, which the tree width trades
+/// against node size; a high ratio over small messages means fixed
+/// per-item costs dominate, which inlining or batching can spread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AmplificationStats {
+    pub appends: u64,
+    /// Bytes of the messages appended.
+    pub message_bytes: u64,
+    /// Bytes written to data_core, or to a light core's payload store.
+    /// Inlined payloads are in `verkle_bytes` instead.
+    pub data_bytes: u64,
+    pub verkle_nodes: u64,
+    pub verkle_bytes: u64,
+    pub sig_bytes: u64,
+}
 
-        for covering_id_val in coverings.range().start.0..coverings.range().end.0 {
-            let covering_id = CoveringId(covering_id_val);
-            let node = self.build_node(covering_id, msg_hash.clone(), data_index)?;
-            let node_bytes = node.to_bytes();
-            self.verkle_core.add_message(&node_bytes)?;
-        }
+impl AmplificationStats {
+    pub fn written_bytes(&self) -> u64 {
+        return self.data_bytes + self.verkle_bytes + self.sig_bytes;
+    }
 
-        // Bag the peaks: get all peak roots and hash them together
-        let current_len = self.len().0 as u64;
-        let peaks = get_peaks(current_len, WIDTH);
-        
-        let mut peak_hashes = Vec::new();
-        for peak_id in peaks {
-            let peak_node = self.get_node(peak_id)?;
-            peak_hashes.push(peak_node.compute_hash());
-        }
-        
-        // Concatenate all peak hashes and hash them to create global root
-        let mut global_data = Vec::new();
-        for peak_hash in &peak_hashes {
-            global_data.extend_from_slice(&peak_hash.0);
-        }
-        let global_root = hash(&global_data);
-        
-        // Sign the global root
-        let signature = signer.sign(&global_root.0);
-        
-        // Store signature block
-        let sig_block = SignatureBlock {
-            global_root: global_root.clone(),
-            signature,
-        };
-        self.sig_core.add_message(&sig_block.to_bytes())?;
+    /// Bytes written per message byte; 1.0 would be no overhead. `None`
+    /// until a message byte is written.
+    pub fn ratio(&self) -> Option<f64> {
+        return (self.message_bytes > 0).then(|| self.written_bytes() as f64 / self.message_bytes as f64);
+    }
 
-        return Ok(global_root);
+    /// Bytes written per append, on average.
+    pub fn bytes_per_append(&self) -> Option<f64> {
+        return (self.appends > 0).then(|| self.written_bytes() as f64 / self.appends as f64);
     }
 
-    fn build_node(&mut self, covering_id: CoveringId, leaf_hash: Hash, leaf_index: MessageId) -> Result<VerkleNode, IsoCoreError> {
-        let children_ids = children_for_covering(covering_id, WIDTH);
+    /// Tree nodes written per append, on average.
+    pub fn nodes_per_append(&self) -> Option<f64> {
+        return (self.appends > 0).then(|| self.verkle_nodes as f64 / self.appends as f64);
+    }
+}
 
-        if children_ids.is_empty() {
-            return Ok(VerkleNode {
-                children: vec![NodeChild {
-                    node_type: NodeType::Leaf,
-                    hash: leaf_hash,
-                    index: leaf_index,
-                }],
-            });
-        }
+/// Content bytes of each core, for `CoreStats`.
+#[derive(Debug, Clone, Copy, Default)]
+struct ContentSizes {
+    payload: u64,
+    verkle: u64,
+    sig: u64,
+}
 
-        let mut children = Vec::new();
-        for child_id in children_ids {
-            let child_node = self.get_node(child_id)?;
-            children.push(NodeChild {
-                node_type: NodeType::Branch,
-                hash: child_node.compute_hash(),
-                index: child_id.to_verkle_id(),
-            });
-        }
+/// Sent to every `IsoCore::events` receiver after an append.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppendEvent {
+    pub item_id: ItemId,
+    /// Leaf hash of the appended message.
+    pub hash: Hash,
+    /// Signed global root after the append.
+    pub root: Hash,
+    /// Whether this is the last item of its batch. Always set for
+    /// `add_message`; for `add_messages`, only on the final item appended.
+    pub last_in_batch: bool,
+}
 
-        return Ok(VerkleNode { children });
-    }
+/// Order of items returned by `IsoCore::page`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageDirection {
+    OldestFirst,
+    NewestFirst,
+}
 
-    pub fn get_root_hash(&mut self) -> Result<Hash, IsoCoreError> {
-        let len = self.len();
-        if len.0 == 0 {
-            return Ok(hash(&[]));
-        }
+/// One item returned by `IsoCore::page`. IsoCore records no timestamps;
+/// payloads that need one carry it themselves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageEntry {
+    pub item_id: ItemId,
+    /// Leaf hash of the message.
+    pub hash: Hash,
+    /// Signed global root after this item was appended.
+    pub root: Hash,
+    pub data: Vec<u8>,
+}
 
-        let last_item = ItemId((len.0 - 1) as u64);
-        let coverings = coverings_for_item(last_item, WIDTH);
-        let root_node = self.get_node(coverings.root())?;
+/// An item's id, leaf hash, and payload.
+pub type VerifiedItem<'a> = (ItemId, Hash, Cow<'a, [u8]>);
 
-        return Ok(root_node.compute_hash());
-    }
+/// Items of a core in order, each checked against its stored leaf as it
+/// is read, from `IsoCore::iter_verified`. The payloads borrow from the
+/// core, so this is a cursor rather than an `Iterator`: call `next_item`
+/// until it returns `None`. It stops at the first error, leaving
+/// `checkpoint` at the item that failed, so a walk can be resumed from
+/// there or from any checkpoint saved along the way.
+#[derive(Debug)]
+pub struct VerifiedItems<'a> {
+    core: &'a mut IsoCore,
+    next: u64,
+    failed: bool,
+}
 
-    pub fn len(&self) -> MessageId {
-        return self.data_core.len();
+impl VerifiedItems<'_> {
+    pub fn next_item(&mut self) -> Option<Result<VerifiedItem<'_>, IsoCoreError>> {
+        if self.failed || self.next >= self.core.len().0 as u64 {
+            return None;
+        }
+        let item_id = ItemId(self.next);
+        return Some(match self.core.verified_message(item_id) {
+            Ok((hash, data)) => {
+                self.next += 1;
+                Ok((item_id, hash, data))
+            }
+            Err(e) => {
+                self.failed = true;
+                Err(e)
+            }
+        });
     }
 
-    fn load_node(&mut self, covering_id: CoveringId) -> Result<(), IsoCoreError> {
-        let verkle_id = covering_id.to_verkle_id();
-        self.verkle_core.load_message(verkle_id)?;
-        return Ok(());
+    /// The next item to be read.
+    pub fn checkpoint(&self) -> ItemId {
+        return ItemId(self.next);
     }
+}
 
-    fn get_node(&mut self, covering_id: CoveringId) -> Result<VerkleNode, IsoCoreError> {
-        self.load_node(covering_id)?;
-        let verkle_id = covering_id.to_verkle_id();
-        let bytes = self.verkle_core.get_contents(verkle_id)?;
-        return VerkleNode::from_bytes(bytes);
+/// A core as it stood after its first `len` items, from `IsoCore::at`.
+/// Items from `len` on read as not written yet, and the root and proofs
+/// are the ones signed at `len`, so a replica that stopped there would
+/// answer the same.
+#[derive(Debug)]
+pub struct HistoricalView<'a> {
+    core: &'a mut IsoCore,
+    len: u64,
+}
+
+impl HistoricalView<'_> {
+    pub fn len(&self) -> u64 {
+        return self.len;
     }
 
-    pub fn get_message(&mut self, item_id: ItemId) -> Result<&[u8], IsoCoreError> {
-        let coverings = coverings_for_item(item_id, WIDTH);
-        let leaf_node = self.get_node(coverings.leaf())?;
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
 
-        if leaf_node.children.len() != 1 || leaf_node.children[0].node_type != NodeType::Leaf {
-            return Err(IsoCoreError::NodeFormat);
+    /// The global root signed at `len`, unchecked; `None` before the
+    /// first item.
+    pub fn root(&mut self) -> Result<Option<Hash>, IsoCoreError> {
+        if self.len == 0 {
+            return Ok(None);
         }
+        return Ok(Some(self.core.get_signature(ItemId(self.len - 1))?.global_root));
+    }
 
-        let data_id = leaf_node.children[0].index;
-        let expected_hash = leaf_node.children[0].hash.clone();
-        
-        self.data_core.load_message(data_id)?;
-        let data = self.data_core.get_contents(data_id)?;
-        
-        // Verify data integrity
-        let actual_hash = hash(data);
-        if actual_hash != expected_hash {
+    /// Checks the root signed at `len` as `IsoCore::verify_head` checks
+    /// the latest. Returns the verified root.
+    pub fn verify_head(&mut self) -> Result<Hash, IsoCoreError> {
+        if self.len == 0 {
             return Err(IsoCoreError::IntegrityError);
         }
+        return self.core.verify_signature(ItemId(self.len - 1));
+    }
 
-        return Ok(data);
+    /// The payload of `item_id`, as `IsoCore::get_message` reads it.
+    pub fn get_message(&mut self, item_id: ItemId) -> Result<Cow<'_, [u8]>, IsoCoreError> {
+        if item_id.0 >= self.len {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        return self.core.get_message(item_id);
     }
-}
 
-fn parse_child_line(line: &str) -> Result<NodeChild, IsoCoreError> {
-    let parts: Vec<&str> = line.split_whitespace().collect();
-    if parts.len() != 3 {
-        return Err(IsoCoreError::NodeFormat);
+    /// Proves `item_id` against the root signed at `len`.
+    pub fn prove(&mut self, item_id: ItemId) -> Result<InclusionProof, IsoCoreError> {
+        return self.core.prove(item_id, self.len);
     }
 
-    let node_type = match parts[0] {
-        "leaf" => NodeType::Leaf,
-        "branch" => NodeType::Branch,
-        _ => return Err(IsoCoreError::NodeType),
-    };
+    pub fn prove_range(&mut self, items: Range<u64>) -> Result<RangeProof, IsoCoreError> {
+        return self.core.prove_range(items, self.len);
+    }
+}
 
-    let hash = Hash::from_hex(parts[1]);
-    let index_str = parts[2].trim_end_matches(".bin");
-    let index_num = u16::from_str_radix(index_str, 16)
-        .map_err(|e| IsoCoreError::MessageIdParse(e))?;
-    let index = MessageId(index_num);
+#[derive(Debug)]
+pub struct IsoCore {
+    path: Option<PathBuf>,
+    /// The key the core is known by, `signers.id()`.
+    signer: KeyPub,
+    signers: Signers,
+    version: FormatVersion,
+    data_core: Core,
+    verkle_core: Core,
+    sig_core: Core,
+    /// Length as of the last flush (or load); never rolled back by recovery.
+    committed: u64,
+    /// Global root recorded after each item, for `find_by_root`.
+    roots: HashMap<[u8; 32], ItemId>,
+    /// Receivers of append events; dropped ones are forgotten on send.
+    subscribers: Vec<Sender<AppendEvent>>,
+    durability: Durability,
+    metrics: MetricsHandle,
+    /// Set for a light core, whose data_core stays empty.
+    payloads: Option<Payloads>,
+    /// Items between recorded checkpoints; 0 records none.
+    checkpoint_interval: u64,
+    checkpoints: Vec<Checkpoint>,
+    /// How many of `checkpoints` are in the checkpoints file.
+    checkpoints_saved: usize,
+    cosignatures: Vec<Cosignature>,
+    /// How many of `cosignatures` are in the cosignatures file.
+    cosignatures_saved: usize,
+    /// When info.nd was written; None in memory or for older cores.
+    created_at: Option<Timestamp>,
+    /// Kept up to date by appends once known; None until `stats` counts
+    /// them after a load or `gc`.
+    sizes: Option<ContentSizes>,
+    /// Writes since the core was opened.
+    amplification: AmplificationStats,
+    /// Leaf hashes of items ingested but not yet in the tree, with the
+    /// payloads to inline.
+    ingested: Vec<(Hash, Option<Vec<u8>>)>,
+    /// Payloads of up to this many bytes are kept in their leaf nodes.
+    inline_limit: u64,
+    /// info.nd as written or read, for a replica to copy; None in memory.
+    info: Option<Vec<u8>>,
+    /// False for a core whose info.nd is from before seals were signed,
+    /// which can be read but not appended to until `reseal`.
+    sealed: bool,
+}
 
-    return Ok(NodeChild {
-        node_type,
-        hash,
-        index,
-    });
+/// What info.nd records. See the module docs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CoreInfo {
+    pub(crate) version: FormatVersion,
+    pub(crate) signers: Signers,
+    width: u64,
+    /// None in an info.nd from before it was sealed.
+    created_at: Option<Timestamp>,
+    pub(crate) light: bool,
+    /// 0 in an info.nd from before payloads were inlined.
+    inline_limit: u64,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl CoreInfo {
+    /// The info of a core created now.
+    fn new(signers: Signers, inline_limit: u64) -> Result<Self, IsoCoreError> {
+        return Ok(CoreInfo {
+            version: FormatVersion::CURRENT,
+            signers,
+            width: WIDTH,
+            created_at: Some(Timestamp::now()?),
+            light: false,
+            inline_limit,
+        });
+    }
 
-    #[test]
-    fn isocore_create_and_add_message() {
-        let signer = KeyPair::ephemeral();
-        let mut isocore = IsoCore::create_mem(&signer);
+    /// Encodes the fields, then a seal over them by `keys`: a single
+    /// signer's signature, or for a threshold core a signature block over
+    /// their hash from at least the threshold of its signers.
+    fn to_bytes(&self, keys: &[&KeyPair]) -> Result<Vec<u8>, IsoCoreError> {
+        let signing = signing_keys(&self.signers, keys)?;
+        let sealed = self.sealed_bytes()?;
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        self.write_fields(&mut map)?;
+        if self.signers.is_single() {
+            signing[0].1.sign(&sealed).write(&mut map.key("signature")?)?;
+        } else {
+            let global_root = hash(&sealed);
+            let signatures = signing.iter().map(|(index, key)| (*index, key.sign(&global_root.0))).collect();
+            SignatureBlock { global_root, signatures }.write(&mut map.key("signatures")?)?;
+        }
+        map.finish()?;
+        return Ok(Artifact::Info.with_header(enc.as_bytes()));
+    }
 
-        let msg1 = b"hello world";
-        let hash1 = isocore.add_message(msg1, &signer).unwrap();
+    /// Reads info.nd, which must be sealed by its signers. One from before
+    /// seals were signed is `UnsealedInfo`.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self, IsoCoreError> {
+        return match CoreInfo::read(bytes)? {
+            (info, true) => Ok(info),
+            (_, false) => Err(IsoCoreError::UnsealedInfo),
+        };
+    }
 
-        assert_eq!(isocore.len().0, 1);
+    /// Reads info.nd, and whether it is sealed. One from before seals were
+    /// signed, with no seal or only a hash of its fields, is read as is;
+    /// a seal that doesn't match is still `BadInfo`.
+    ///
+    /// Nothing marks a file as once having been sealed, so one rewritten
+    /// with an older header or a hash seal reads the same as a genuinely
+    /// old one: unsealed, and so read-only. Whoever can rewrite info.nd
+    /// can downgrade a core that way, but not make it accept appends, and
+    /// its items are still checked against the signer it names.
+    pub(crate) fn read(bytes: &[u8]) -> Result<(Self, bool), IsoCoreError> {
+        let (header, body) = Artifact::Info.read_header(bytes)?;
+        let mut dec = Decoder::new(body);
+        let mut map = dec.map()?;
 
-        let retrieved = isocore.get_message(ItemId(0)).unwrap();
-        assert_eq!(retrieved, msg1);
+        let Some(("version", version)) = map.next()? else {
+            return Err(IsoCoreError::NodeFormat);
+        };
+        let version = FormatVersion::from_u8(version.as_u8()?)?;
+        let Some(("signer", signer)) = map.next()? else {
+            return Err(IsoCoreError::NodeFormat);
+        };
+        let signer = KeyPub::read(signer).map_err(|_| IsoCoreError::NodeFormat)?;
 
-        // Verify global root is stored in sig_core
-        assert_eq!(isocore.sig_core.len().0, 1);
-        
-        // The global root should be deterministic for the same message
-        assert!(!hash1.0.iter().all(|&b| b == 0));
-    }
+        // Before version 2 only the light flag followed, and nothing was sealed
+        if header < 2 {
+            let light = match map.next()? {
+                Some(("light", light)) => light.as_bool()?,
+                Some(_) => return Err(IsoCoreError::NodeFormat),
+                None => false,
+            };
+            let signers = Signers::single(signer);
+            return Ok((CoreInfo { version, signers, width: WIDTH, created_at: None, light, inline_limit: 0 }, false));
+        }
 
-    #[test]
-    fn isocore_multiple_messages() {
-        let signer = KeyPair::ephemeral();
-        let mut isocore = IsoCore::create_mem(&signer);
+        let Some(("width", width)) = map.next()? else {
+            return Err(IsoCoreError::NodeFormat);
+        };
+        let width = width.as_u64()?;
+        let Some(("created_at", created_at)) = map.next()? else {
+            return Err(IsoCoreError::NodeFormat);
+        };
+        let created_at = Some(created_at.as_timestamp()?);
+        let mut next = map.next()?;
+        let light = match next {
+            Some(("light", light)) => {
+                next = map.next()?;
+                light.as_bool()?
+            }
+            _ => false,
+        };
+        let inline_limit = match next {
+            Some(("inline_limit", limit)) => {
+                next = map.next()?;
+                limit.as_u64()?
+            }
+            _ => 0,
+        };
 
-        let messages = vec![
-            b"message 1",
-            b"message 2",
-            b"message 3",
-            b"message 4",
-        ];
+        // Only threshold cores list their signers, which hash to `signer`
+        let signers = match next {
+            Some(("signers", ValueDecoder::List(mut list))) => {
+                let mut keys = Vec::new();
+                while let Some(key) = list.next()? {
+                    keys.push(KeyPub::read(key)?);
+                }
+                let Some(("threshold", threshold)) = map.next()? else {
+                    return Err(IsoCoreError::NodeFormat);
+                };
+                next = map.next()?;
+                Signers::new(keys, threshold.as_u8()?)?
+            }
+            _ => Signers::single(signer.clone()),
+        };
+        let info = CoreInfo { version, signers, width, created_at, light, inline_limit };
 
-        for msg in &messages {
-            isocore.add_message(*msg, &signer).unwrap();
+        // A hash seal proves nothing about who wrote the fields, so a file
+        // with one counts as unsealed
+        let fields = info.sealed_bytes()?;
+        let (ok, sealed) = match next {
+            Some(("signature", signature)) if info.signers.is_single() => {
+                (signer.verify(&fields, &Signature::read(signature)?), true)
+            }
+            Some(("signatures", block)) if !info.signers.is_single() => {
+                let block = SignatureBlock::read(block)?;
+                (block.global_root == hash(&fields) && info.signers.verify(&block), true)
+            }
+            Some(("hash", expected)) => (Hash::read(expected)? == hash(&fields), false),
+            _ => return Err(IsoCoreError::NodeFormat),
+        };
+        if !ok || info.signers.id() != signer || info.width != WIDTH {
+            return Err(IsoCoreError::BadInfo);
         }
+        return Ok((info, sealed));
+    }
+
+    fn write_fields(&self, map: &mut MapEncoder<'_>) -> Result<(), IsoCoreError> {
+        map.key("version")?.u8(self.version as u8)?;
+        self.signers.id().write(&mut map.key("signer")?)?;
+        map.key("width")?.u64(self.width)?;
+        map.key("created_at")?.timestamp(self.created_at.unwrap_or_default())?;
+        if self.light {
+            map.key("light")?.bool(true)?;
+        }
+        if self.inline_limit > 0 {
+            map.key("inline_limit")?.u64(self.inline_limit)?;
+        }
+        if !self.signers.is_single() {
+            let mut keys = map.key("signers")?.list()?;
+            for key in self.signers.keys() {
+                key.write(&mut keys)?;
+            }
+            keys.finish()?;
+            map.key("threshold")?.u8(self.signers.threshold())?;
+        }
+        return Ok(());
+    }
+
+    /// What the seal covers: a context string, then the fields as a map.
+    fn sealed_bytes(&self) -> Result<Vec<u8>, IsoCoreError> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        self.write_fields(&mut map)?;
+        map.finish()?;
+        let mut sealed = INFO_CONTEXT.to_vec();
+        sealed.extend_from_slice(enc.as_bytes());
+        return Ok(sealed);
+    }
+}
+
+/// Each distinct key of `keys` with its index among `signers`, in index
+/// order. There must be at least the threshold of them, all signers.
+fn signing_keys<'a>(signers: &Signers, keys: &[&'a KeyPair]) -> Result<Vec<(u8, &'a KeyPair)>, IsoCoreError> {
+    let mut signing = Vec::new();
+    for key in keys {
+        let index = signers.index_of(&key.key_pub).ok_or(IsoCoreError::SignerMismatch)?;
+        if !signing.iter().any(|(i, _)| *i == index) {
+            signing.push((index, *key));
+        }
+    }
+    if signing.len() < signers.threshold() as usize {
+        return Err(IsoCoreError::BelowThreshold);
+    }
+    signing.sort_by_key(|(index, _)| *index);
+    return Ok(signing);
+}
+
+/// Payloads a light core has been given, by leaf hash.
+#[derive(Debug, Default)]
+struct Payloads {
+    /// Where they are kept on disk, one file per payload.
+    dir: Option<PathBuf>,
+    cache: HashMap<[u8; 32], Vec<u8>>,
+}
+
+impl Payloads {
+    fn get(&mut self, version: FormatVersion, item_id: ItemId, hash: &Hash) -> Result<&[u8], IsoCoreError> {
+        if !self.cache.contains_key(&hash.0) {
+            let needs_data = || IsoCoreError::NeedsData { item_id, hash: hash.clone() };
+            let Some(dir) = &self.dir else {
+                return Err(needs_data());
+            };
+            let bytes = match std::fs::read(dir.join(hash.to_hex())) {
+                Ok(bytes) => bytes,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(needs_data()),
+                Err(e) => return Err(e.into()),
+            };
+            if version.hash_leaf(&bytes) != *hash {
+                return Err(IsoCoreError::IntegrityError);
+            }
+            self.cache.insert(hash.0, bytes);
+        }
+        return Ok(&self.cache[&hash.0]);
+    }
+
+    fn put(&mut self, hash: &Hash, bytes: &[u8], durability: Durability) -> Result<(), IsoCoreError> {
+        if let Some(dir) = &self.dir {
+            let path = dir.join(hash.to_hex());
+            if !path.is_file() {
+                write_atomic(&path, bytes, durability)?;
+            }
+        }
+        self.cache.insert(hash.0, bytes.to_vec());
+        return Ok(());
+    }
+}
+
+/// The files an IsoCore keeps in its directory. Paths are built by
+/// joining components, never by formatting strings, so they use the
+/// platform's separator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreFiles {
+    /// Signer and format version.
+    pub info: PathBuf,
+    pub data: PathBuf,
+    pub verkle: PathBuf,
+    pub sig: PathBuf,
+    /// Present only while a flush is in progress.
+    pub intent: PathBuf,
+    /// Index from global root to item.
+    pub roots: PathBuf,
+    /// Payloads of a light core, which has no data file.
+    pub payloads: PathBuf,
+    /// Checkpoints recorded every `set_checkpoint_interval` items.
+    pub checkpoints: PathBuf,
+    /// Witnesses' cosignatures of the core's heads.
+    pub cosignatures: PathBuf,
+}
+
+impl CoreFiles {
+    pub fn new(dir: &Path) -> Self {
+        return CoreFiles {
+            info: dir.join(INFO_ISOCORE),
+            data: dir.join(FILE_DATA),
+            verkle: dir.join(FILE_VERKLE),
+            sig: dir.join(FILE_SIG),
+            intent: dir.join(FILE_INTENT),
+            roots: dir.join(FILE_ROOTS),
+            payloads: dir.join(DIR_PAYLOADS),
+            checkpoints: dir.join(FILE_CHECKPOINTS),
+            cosignatures: dir.join(FILE_COSIGNATURES),
+        };
+    }
+}
+
+/// Read-only views of the cores behind an IsoCore.
+#[derive(Debug, Clone, Copy)]
+pub struct Cores<'a> {
+    /// Item payloads.
+    pub data: &'a Core,
+    /// Tree nodes.
+    pub verkle: &'a Core,
+    /// Signature blocks.
+    pub sig: &'a Core,
+}
+
+/// A directory that `IsoCore::open` can't treat as a core.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The path exists but isn't a directory.
+    NotADirectory(PathBuf),
+    /// This file should be there and isn't. A directory with core files but
+    /// no info.nd is reported too, rather than overwritten by a new core.
+    Missing(PathBuf),
+}
+
+/// How many messages each core holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoreLengths {
+    /// For a light core, which has no data core, the signature count.
+    pub data: u64,
+    pub verkle: u64,
+    pub sig: u64,
+}
+
+impl CoreLengths {
+    /// Items that all three cores hold completely.
+    pub fn items(&self) -> u64 {
+        return self.data.min(self.sig).min(items_for_verkle_len(self.verkle));
+    }
+
+    /// Verkle nodes the covering tree needs for `items()` items.
+    pub fn expected_verkle(&self) -> u64 {
+        return verkle_len_for_items(self.items());
+    }
+
+    /// Whether the cores agree: one data message and one signature block
+    /// per item, and exactly the tree nodes those items created.
+    pub fn consistent(&self) -> bool {
+        let items = self.items();
+        return self.data == items && self.sig == items && self.verkle == self.expected_verkle();
+    }
+}
+
+/// What `IsoCore::open` found and did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenReport {
+    /// Whether the core was created rather than loaded.
+    pub created: bool,
+    /// The cores as found on disk, before recovery.
+    pub found: CoreLengths,
+    /// The cores after recovery cut back any torn append.
+    pub recovered: CoreLengths,
+}
+
+impl IsoCore {
+    pub fn create_mem(signer: &KeyPair) -> Self {
+        return Self::replica_mem(&signer.key_pub, FormatVersion::CURRENT);
+    }
+
+    /// An empty in-memory core for items signed by `signer` elsewhere,
+    /// filled with `add_signed`. `version` must match the source core's.
+    pub fn replica_mem(signer: &KeyPub, version: FormatVersion) -> Self {
+        return Self::mem(Signers::single(signer.clone()), version, None);
+    }
+
+    /// An empty in-memory light core for items signed by `signer`.
+    pub fn light_mem(signer: &KeyPub, version: FormatVersion) -> Self {
+        return Self::mem(Signers::single(signer.clone()), version, Some(Payloads::default()));
+    }
+
+    /// An empty in-memory threshold core, appended to with
+    /// `add_message_by`.
+    pub fn threshold_mem(signers: Signers) -> Self {
+        return Self::mem(signers, FormatVersion::CURRENT, None);
+    }
+
+    fn mem(signers: Signers, version: FormatVersion, payloads: Option<Payloads>) -> Self {
+        return Self {
+            path: None,
+            signer: signers.id(),
+            signers,
+            version,
+            data_core: Core::create_mem(),
+            verkle_core: Core::create_mem(),
+            sig_core: Core::create_mem(),
+            committed: 0,
+            roots: HashMap::new(),
+            subscribers: Vec::new(),
+            durability: Durability::default(),
+            metrics: MetricsHandle::default(),
+            payloads,
+            checkpoint_interval: 0,
+            checkpoints: Vec::new(),
+            checkpoints_saved: 0,
+            cosignatures: Vec::new(),
+            cosignatures_saved: 0,
+            created_at: None,
+            sizes: Some(ContentSizes::default()),
+            amplification: AmplificationStats::default(),
+            ingested: Vec::new(),
+            inline_limit: 0,
+            info: None,
+            sealed: true,
+        };
+    }
+
+    /// Creates a core at `path`, with info.nd signed by `signer`.
+    pub fn create(path: PathBuf, signer: &KeyPair) -> Result<Self, IsoCoreError> {
+        return Self::create_inline(path, signer, 0);
+    }
+
+    /// Like `create`, keeping payloads of up to `inline_limit` bytes in
+    /// their leaf nodes rather than the data core.
+    pub fn create_inline(path: PathBuf, signer: &KeyPair, inline_limit: u64) -> Result<Self, IsoCoreError> {
+        let info = CoreInfo::new(Signers::single(signer.key_pub.clone()), inline_limit)?;
+        let info_bytes = info.to_bytes(&[signer])?;
+        return Self::create_with(path, info, info_bytes, false);
+    }
+
+    /// Like `replica_mem`, stored at `path`, for the core whose info.nd is
+    /// `info`. It must be signed by `signer`, and is kept as is, so no
+    /// secret key is needed to set up a replica. See `info_bytes`.
+    pub fn create_replica(path: PathBuf, signer: &KeyPub, info: &[u8]) -> Result<Self, IsoCoreError> {
+        return Self::create_copy(path, signer, info, false);
+    }
+
+    /// Like `light_mem`, stored at `path`; see `create_replica`.
+    pub fn create_light(path: PathBuf, signer: &KeyPub, info: &[u8]) -> Result<Self, IsoCoreError> {
+        return Self::create_copy(path, signer, info, true);
+    }
+
+    /// Like `threshold_mem`, stored at `path`. info.nd is sealed by `keys`,
+    /// at least the threshold of `signers`, as an item would be.
+    pub fn create_threshold(path: PathBuf, signers: Signers, keys: &[&KeyPair]) -> Result<Self, IsoCoreError> {
+        let info = CoreInfo::new(signers, 0)?;
+        let info_bytes = info.to_bytes(keys)?;
+        return Self::create_with(path, info, info_bytes, false);
+    }
+
+    /// Checks another core's info.nd and creates a copy of it at `path`.
+    fn create_copy(path: PathBuf, signer: &KeyPub, info: &[u8], light: bool) -> Result<Self, IsoCoreError> {
+        let parsed = CoreInfo::from_bytes(info)?;
+        if parsed.signers != Signers::single(signer.clone()) {
+            return Err(IsoCoreError::SignerMismatch);
+        }
+        return Self::create_with(path, parsed, info.to_vec(), light);
+    }
+
+    /// Creates the directory and its cores, with `info_bytes` as info.nd.
+    /// A light core is known by its payloads directory, since a replica's
+    /// info.nd is its source's.
+    fn create_with(path: PathBuf, info: CoreInfo, info_bytes: Vec<u8>, light: bool) -> Result<Self, IsoCoreError> {
+        // Create directory
+        std::fs::create_dir_all(&path)?;
+        
+        let files = CoreFiles::new(&path);
+        let (data_core, payloads) = if light {
+            std::fs::create_dir_all(&files.payloads)?;
+            (Core::create_mem(), Some(Payloads { dir: Some(files.payloads), ..Payloads::default() }))
+        } else {
+            (Core::create(files.data)?, None)
+        };
+        write_atomic(&files.info, &info_bytes, Durability::Full)?;
+        Durability::Full.sync_parent(&path)?;
+
+        return Ok(Self {
+            path: Some(path),
+            signer: info.signers.id(),
+            signers: info.signers,
+            version: info.version,
+            data_core,
+            verkle_core: Core::create(files.verkle)?,
+            sig_core: Core::create(files.sig)?,
+            committed: 0,
+            roots: HashMap::new(),
+            subscribers: Vec::new(),
+            durability: Durability::default(),
+            metrics: MetricsHandle::default(),
+            payloads,
+            checkpoint_interval: 0,
+            checkpoints: Vec::new(),
+            checkpoints_saved: 0,
+            cosignatures: Vec::new(),
+            cosignatures_saved: 0,
+            created_at: info.created_at,
+            sizes: Some(ContentSizes::default()),
+            amplification: AmplificationStats::default(),
+            ingested: Vec::new(),
+            inline_limit: info.inline_limit,
+            info: Some(info_bytes),
+            sealed: true,
+        });
+    }
+
+    /// Loads the core at `path`, or creates one signed by `signer` if the
+    /// directory is missing or empty. An existing core must belong to
+    /// `signer`. The report says which happened and how the three cores'
+    /// lengths compared with what the covering tree expects.
+    pub fn open(path: PathBuf, signer: &KeyPair) -> Result<(Self, OpenReport), IsoCoreError> {
+        let files = CoreFiles::new(&path);
+        if path.exists() && !path.is_dir() {
+            return Err(LayoutError::NotADirectory(path).into());
+        }
+
+        if !files.info.exists() {
+            for file in [&files.data, &files.verkle, &files.sig, &files.payloads] {
+                if file.exists() {
+                    return Err(LayoutError::Missing(files.info).into());
+                }
+            }
+            let isocore = Self::create(path, signer)?;
+            let lengths = isocore.lengths();
+            let report = OpenReport { created: true, found: lengths, recovered: lengths };
+            return Ok((isocore, report));
+        }
+
+        // A light core keeps payloads in place of a data file
+        let data = if files.payloads.is_dir() { &files.payloads } else { &files.data };
+        for file in [data, &files.verkle, &files.sig] {
+            if !file.exists() {
+                return Err(LayoutError::Missing(file.clone()).into());
+            }
+        }
+        let mut isocore = Self::load_unrecovered(&path)?;
+        if isocore.signer != signer.key_pub {
+            return Err(IsoCoreError::SignerMismatch);
+        }
+        let found = isocore.lengths();
+        isocore.finish_load()?;
+        let report = OpenReport { created: false, found, recovered: isocore.lengths() };
+        return Ok((isocore, report));
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, IsoCoreError> {
+        let mut isocore = Self::load_unrecovered(path.as_ref())?;
+        isocore.finish_load()?;
+        return Ok(isocore);
+    }
+
+    /// Reads info.nd and the cores as they are on disk.
+    fn load_unrecovered(path: &Path) -> Result<Self, IsoCoreError> {
+        let files = CoreFiles::new(path);
+        
+        // Read info.nd to get public key
+        let info_bytes = std::fs::read(&files.info)?;
+        let (info, sealed) = CoreInfo::read(&info_bytes)?;
+        let (data_core, payloads) = if info.light || files.payloads.is_dir() {
+            (Core::create_mem(), Some(Payloads { dir: Some(files.payloads), ..Payloads::default() }))
+        } else {
+            (Core::load(&files.data)?, None)
+        };
+
+        return Ok(Self {
+            path: Some(path.to_path_buf()),
+            signer: info.signers.id(),
+            signers: info.signers,
+            version: info.version,
+            data_core,
+            verkle_core: Core::load(&files.verkle)?,
+            sig_core: Core::load(&files.sig)?,
+            committed: 0,
+            roots: HashMap::new(),
+            subscribers: Vec::new(),
+            durability: Durability::default(),
+            metrics: MetricsHandle::default(),
+            payloads,
+            checkpoint_interval: 0,
+            checkpoints: Vec::new(),
+            checkpoints_saved: 0,
+            cosignatures: Vec::new(),
+            cosignatures_saved: 0,
+            created_at: info.created_at,
+            sizes: None,
+            amplification: AmplificationStats::default(),
+            ingested: Vec::new(),
+            inline_limit: info.inline_limit,
+            info: Some(info_bytes),
+            sealed,
+        });
+    }
+
+    /// Migrates the info.nd at `path` from before seals were signed, which
+    /// `load` opens only for reading: checks whatever seal it has, then
+    /// rewrites it sealed by `keys`, the core's signers. Opt-in, since the
+    /// fields of such a file can't be told from forged ones. One that
+    /// never recorded its creation time gets the zero timestamp.
+    pub fn reseal<P: AsRef<Path>>(path: P, keys: &[&KeyPair]) -> Result<(), IsoCoreError> {
+        let files = CoreFiles::new(path.as_ref());
+        let (info, _) = CoreInfo::read(&std::fs::read(&files.info)?)?;
+        write_atomic(&files.info, &info.to_bytes(keys)?, Durability::Full)?;
+        return Ok(());
+    }
+
+    /// Cuts back each of the logs at `path` whose tail a crash tore part
+    /// way through a write, as `Core::recover` does, and returns the bytes
+    /// cut. `load` refuses a torn log rather than cutting it, so this is
+    /// run first when it does; what it cuts was never synced.
+    pub fn repair<P: AsRef<Path>>(path: P) -> Result<u64, IsoCoreError> {
+        let files = CoreFiles::new(path.as_ref());
+        let mut cut = 0;
+        for file in [&files.data, &files.verkle, &files.sig] {
+            // A light core has no data log
+            if file == &files.data && files.payloads.is_dir() {
+                continue;
+            }
+            cut += Core::recover(file)?.1;
+        }
+        return Ok(cut);
+    }
+
+    fn finish_load(&mut self) -> Result<(), IsoCoreError> {
+        self.recover()?;
+        self.committed = self.len().0 as u64;
+        self.load_roots()?;
+        self.load_checkpoints()?;
+        self.load_cosignatures()?;
+        return Ok(());
+    }
+
+    /// Reads the checkpoints file, dropping any past the recovered length.
+    fn load_checkpoints(&mut self) -> Result<(), IsoCoreError> {
+        let Some(files) = self.files() else {
+            return Ok(());
+        };
+        let len = self.len().0 as u64;
+        let checkpoints: Vec<Checkpoint> = read_list_file(&files.checkpoints, Artifact::Checkpoints)?;
+        self.checkpoints = checkpoints.into_iter().filter(|checkpoint| checkpoint.len <= len).collect();
+        self.checkpoints_saved = self.checkpoints.len();
+        return Ok(());
+    }
+
+    fn write_checkpoints(&mut self, path: &Path) -> Result<(), IsoCoreError> {
+        if self.checkpoints_saved == self.checkpoints.len() {
+            return Ok(());
+        }
+        write_list_file(path, Artifact::Checkpoints, &self.checkpoints, self.durability)?;
+        self.checkpoints_saved = self.checkpoints.len();
+        return Ok(());
+    }
+
+    /// Reads the cosignatures file, dropping any past the recovered length.
+    fn load_cosignatures(&mut self) -> Result<(), IsoCoreError> {
+        let Some(files) = self.files() else {
+            return Ok(());
+        };
+        let len = self.len().0 as u64;
+        let cosignatures: Vec<Cosignature> = read_list_file(&files.cosignatures, Artifact::Cosignatures)?;
+        self.cosignatures = cosignatures.into_iter().filter(|cosignature| cosignature.len <= len).collect();
+        self.cosignatures_saved = self.cosignatures.len();
+        return Ok(());
+    }
+
+    fn write_cosignatures(&mut self, path: &Path) -> Result<(), IsoCoreError> {
+        if self.cosignatures_saved == self.cosignatures.len() {
+            return Ok(());
+        }
+        write_list_file(path, Artifact::Cosignatures, &self.cosignatures, self.durability)?;
+        self.cosignatures_saved = self.cosignatures.len();
+        return Ok(());
+    }
+
+    /// Keeps a witness's cosignature of one of this core's heads, once its
+    /// signature checks out and its root is the one signed at its length.
+    /// Written out by `flush`.
+    pub fn add_cosignature(&mut self, cosignature: Cosignature) -> Result<(), IsoCoreError> {
+        if !cosignature.verify() || cosignature.len > self.len().0 as u64
+            || self.get_signature(ItemId(cosignature.len - 1))?.global_root != cosignature.root {
+            return Err(IsoCoreError::IntegrityError);
+        }
+        if !self.cosignatures.contains(&cosignature) {
+            self.cosignatures.push(cosignature);
+        }
+        return Ok(());
+    }
+
+    /// Cosignatures kept so far, in the order they were added.
+    pub fn cosignatures(&self) -> &[Cosignature] {
+        return &self.cosignatures;
+    }
+
+    /// Checks the head as `verify_head` does, then that at least `needed`
+    /// of `witnesses` cosigned it. Returns the verified root.
+    pub fn verify_witnessed(&mut self, witnesses: &[KeyPub], needed: usize) -> Result<Hash, IsoCoreError> {
+        let root = self.verify_head()?;
+        let len = self.len().0 as u64;
+        let found = witnesses.iter().enumerate()
+            .filter(|(i, witness)| !witnesses[..*i].contains(witness))
+            .filter(|(_, witness)| self.cosignatures.iter().any(|cosignature| {
+                return &cosignature.witness == *witness && cosignature.len == len
+                    && cosignature.root == root && cosignature.verify();
+            }))
+            .count();
+        if found < needed {
+            return Err(IsoCoreError::Unwitnessed { needed, found });
+        }
+        return Ok(root);
+    }
+
+    /// Records a checkpoint after every `interval` items appended from now
+    /// on, written out by `flush`; 0, the default, records none.
+    pub fn set_checkpoint_interval(&mut self, interval: u64) {
+        self.checkpoint_interval = interval;
+    }
+
+    /// Checkpoints recorded so far, oldest first.
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        return &self.checkpoints;
+    }
+
+    /// The signed head as a checkpoint, to keep somewhere else.
+    pub fn checkpoint(&mut self) -> Result<Checkpoint, IsoCoreError> {
+        let len = self.len().0 as u64;
+        if len == 0 {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        let block = self.get_signature(ItemId(len - 1))?;
+        let signature = block.signature().ok_or(IsoCoreError::ThresholdCore)?.clone();
+        return Ok(Checkpoint { len, root: block.global_root, signature });
+    }
+
+    /// Checks the items after `checkpoint` as `audit` does, trusting the
+    /// checkpoint for everything before it. The checkpoint's signature is
+    /// checked, and so is its root against the core: both the block stored
+    /// at its length and the peaks there, bagged again. Every item after
+    /// it then has its payload checked against its leaf, its nodes against
+    /// their children, and its signed root against the peaks. A light
+    /// core's missing payloads are skipped. Returns the verified head.
+    pub fn verify_from_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<Hash, IsoCoreError> {
+        let len = self.len().0 as u64;
+        if checkpoint.len > len {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        if !checkpoint.verify(&self.signer)
+            || self.get_signature(ItemId(checkpoint.len - 1))?.global_root != checkpoint.root {
+            return Err(IsoCoreError::IntegrityError);
+        }
+
+        // Every node an append after the checkpoint refers to was either
+        // made after it or is one of its peaks
+        let mut known = BTreeMap::new();
+        for peak in get_peaks(checkpoint.len, WIDTH) {
+            known.insert(peak, self.get_node(peak)?.compute_hash(self.version));
+        }
+        if self.bag_known(checkpoint.len, &known) != checkpoint.root {
+            return Err(IsoCoreError::IntegrityError);
+        }
+
+        let mut root = checkpoint.root.clone();
+        for n in checkpoint.len..len {
+            match self.verified_message(ItemId(n)) {
+                Ok(_) | Err(IsoCoreError::NeedsData { .. }) => {}
+                Err(e) => return Err(e),
+            }
+            let coverings = coverings_for_item(ItemId(n), WIDTH);
+            for y in coverings.range().start.0..coverings.range().end.0 {
+                let node = self.get_node(CoveringId(y))?;
+                if !node_matches(&node, CoveringId(y), &known) {
+                    return Err(IsoCoreError::IntegrityError);
+                }
+                known.insert(CoveringId(y), node.compute_hash(self.version));
+            }
+
+            let block = self.get_signature(ItemId(n))?;
+            if self.bag_known(n + 1, &known) != block.global_root || !self.signers.verify(&block) {
+                return Err(IsoCoreError::IntegrityError);
+            }
+            root = block.global_root;
+        }
+        return Ok(root);
+    }
+
+    /// The global root after `len` items, from peak hashes in `known`.
+    fn bag_known(&self, len: u64, known: &BTreeMap<CoveringId, Hash>) -> Hash {
+        let mut global_root = self.version.root_hasher(len);
+        for peak in get_peaks(len, WIDTH) {
+            match known.get(&peak) {
+                Some(hash) => global_root.update(&hash.0),
+                // Can't match any signed root
+                None => return Hash([0; 32]),
+            };
+        }
+        return global_root.finish();
+    }
+
+    fn lengths(&self) -> CoreLengths {
+        let data = match self.payloads {
+            Some(_) => self.sig_core.len(),
+            None => self.data_core.len(),
+        };
+        return CoreLengths {
+            data: data.0 as u64,
+            verkle: self.verkle_core.len().0 as u64,
+            sig: self.sig_core.len().0 as u64,
+        };
+    }
+
+    /// Sets how hard `flush` works to make appends survive a crash; see
+    /// `Durability`. Anything weaker than `Full` can lose flushed items.
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+        self.data_core.set_durability(durability);
+        self.verkle_core.set_durability(durability);
+        self.sig_core.set_durability(durability);
+    }
+
+    pub fn durability(&self) -> Durability {
+        return self.durability;
+    }
+
+    /// Reports appends, signing and flush times, and the work of the three
+    /// cores to `metrics` from now on.
+    pub fn set_metrics(&mut self, metrics: MetricsHandle) {
+        self.data_core.set_metrics(metrics.clone());
+        self.verkle_core.set_metrics(metrics.clone());
+        self.sig_core.set_metrics(metrics.clone());
+        self.metrics = metrics;
+    }
+
+    /// Flushes all three cores as one append transaction. An intent marker
+    /// recording the last committed length is written (and synced, with
+    /// its directory entry) first, and removed only once every core is on
+    /// disk, so `load` can tell a torn flush apart from a clean one.
+    pub fn flush(&mut self) -> Result<(), IsoCoreError> {
+        let Some(files) = self.files() else {
+            return Ok(());
+        };
+        let intent_path = files.intent;
+        let started = Instant::now();
+
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        map.key("committed")?.u64(self.committed)?;
+        map.key("target")?.u64(self.len().0 as u64)?;
+        map.finish()?;
+
+        let mut file = std::fs::File::create(&intent_path)?;
+        file.write_all(&Artifact::Intent.with_header(enc.as_bytes()))?;
+        self.durability.sync_file(&file)?;
+        self.durability.sync_parent(&intent_path)?;
+
+        self.data_core.flush()?;
+        self.verkle_core.flush()?;
+        self.sig_core.flush()?;
+
+        std::fs::remove_file(&intent_path)?;
+        self.committed = self.len().0 as u64;
+        self.write_roots(&files.roots)?;
+        self.write_checkpoints(&files.checkpoints)?;
+        self.write_cosignatures(&files.cosignatures)?;
+        self.metrics.duration(metrics::ISOCORE_FLUSH_SECONDS, started.elapsed());
+        return Ok(());
+    }
+
+    /// Finds the item whose append produced the signed global `root`.
+    pub fn find_by_root(&self, root: &Hash) -> Option<ItemId> {
+        return self.roots.get(&root.0).copied();
+    }
+
+    /// Reads the root index written by the last flush, then indexes any
+    /// items appended since from sig_core. The index is derived data: if
+    /// it is missing or unreadable it is rebuilt from scratch.
+    fn load_roots(&mut self) -> Result<(), IsoCoreError> {
+        let Some(files) = self.files() else {
+            return Ok(());
+        };
+        let len = self.len().0 as u64;
+
+        self.roots = read_roots(&files.roots).unwrap_or_default();
+        self.roots.retain(|_, item| item.0 < len);
+
+        for n in self.roots.len() as u64..len {
+            let bytes = self.sig_core.get_contents(MessageId(n as u16))?;
+            let block = SignatureBlock::from_bytes(&bytes)?;
+            self.roots.insert(block.global_root.0, ItemId(n));
+        }
+        return Ok(());
+    }
+
+    /// Writes the root index as a neopack map from hex root to item index,
+    /// via a temporary file so a crash never leaves a torn index.
+    fn write_roots(&self, path: &Path) -> Result<(), IsoCoreError> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        for (root, item) in &self.roots {
+            map.key(&Hash(*root).to_hex())?.u64(item.0)?;
+        }
+        map.finish()?;
+
+        write_atomic(path, &Artifact::Roots.with_header(enc.as_bytes()), self.durability)?;
+        return Ok(());
+    }
+
+    /// Brings the three cores back into agreement after a crash. Each core
+    /// is cut back to the longest prefix of items that all of them hold
+    /// completely: the data message, every verkle node the append created,
+    /// and its signature block. Entries from a previously committed flush
+    /// are never dropped; if they are missing the core is corrupt.
+    fn recover(&mut self) -> Result<(), IsoCoreError> {
+        let Some(files) = self.files() else {
+            return Ok(());
+        };
+        let intent_path = files.intent;
+        let intent = read_intent(&intent_path)?;
+
+        let len = self.lengths().items();
+
+        if let Some((committed, _)) = intent
+            && len < committed {
+            return Err(IsoCoreError::IntegrityError);
+        }
+
+        self.data_core.truncate(MessageId(len as u16))?;
+        self.verkle_core.truncate(MessageId(verkle_len_for_items(len) as u16))?;
+        self.sig_core.truncate(MessageId(len as u16))?;
+
+        if intent.is_some() {
+            std::fs::remove_file(&intent_path)?;
+        }
+        return Ok(());
+    }
+
+    /// Returns a receiver that gets an `AppendEvent` for every item appended
+    /// from now on. Events are sent once the append has succeeded; nothing
+    /// runs on the appending thread, so receivers can take their time.
+    pub fn events(&mut self) -> Receiver<AppendEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        return receiver;
+    }
+
+    /// Moves the core behind a handle that can be cloned and shared
+    /// between threads.
+    pub fn into_shared(self) -> Result<SharedIsoCore, IsoCoreError> {
+        return SharedIsoCore::new(self);
+    }
+
+    fn publish(&mut self, events: Vec<AppendEvent>) {
+        if events.is_empty() || self.subscribers.is_empty() {
+            return;
+        }
+        self.subscribers.retain(|subscriber| {
+            return events.iter().all(|event| subscriber.send(event.clone()).is_ok());
+        });
+    }
+
+    pub fn add_message(&mut self, message: &[u8], signer: &KeyPair) -> Result<Hash, IsoCoreError> {
+        let event = self.append(message, signer)?;
+        let root = event.root.clone();
+        self.publish(vec![event]);
+        return Ok(root);
+    }
+
+    /// Appends each message in turn, returning their global roots. Each
+    /// append is atomic but the batch is not: if one fails, the ones before
+    /// it stay, and their events are still sent with the last marked as
+    /// the end of the batch.
+    pub fn add_messages<I>(&mut self, messages: I, signer: &KeyPair) -> Result<Vec<Hash>, IsoCoreError>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut events = Vec::new();
+        let mut result = Ok(());
+        for message in messages {
+            match self.append(message.as_ref(), signer) {
+                Ok(mut event) => {
+                    event.last_in_batch = false;
+                    events.push(event);
+                }
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        if let Some(last) = events.last_mut() {
+            last.last_in_batch = true;
+        }
+
+        let roots = events.iter().map(|event| event.root.clone()).collect();
+        self.publish(events);
+        result?;
+        return Ok(roots);
+    }
+
+    /// Appends an item signed by the core's signer elsewhere, as received
+    /// from another replica. The signature must verify against the root
+    /// this append produces, so the item must be the source's next one.
+    pub fn add_signed(&mut self, message: &[u8], signature: &Signature) -> Result<Hash, IsoCoreError> {
+        let hash = self.version.hash_leaf(message);
+        return self.add_verified(hash, Some(message), signature);
+    }
+
+    /// Like `add_signed`, but with only the item's leaf hash, for a light
+    /// core. A full core needs the payload and returns `NeedsData`.
+    pub fn add_signed_hash(&mut self, hash: &Hash, signature: &Signature) -> Result<Hash, IsoCoreError> {
+        if self.payloads.is_none() {
+            let item_id = ItemId(self.len().0 as u64);
+            return Err(IsoCoreError::NeedsData { item_id, hash: hash.clone() });
+        }
+        return self.add_verified(hash.clone(), None, signature);
+    }
+
+    fn add_verified(&mut self, hash: Hash, message: Option<&[u8]>, signature: &Signature) -> Result<Hash, IsoCoreError> {
+        if !self.signers.is_single() {
+            return Err(IsoCoreError::ThresholdCore);
+        }
+        let signer = self.signer.clone();
+        let event = self.append_with(hash, message, |root| {
+            if !signer.verify(&root.0, signature) {
+                return Err(IsoCoreError::IntegrityError);
+            }
+            return Ok(vec![(0, signature.clone())]);
+        })?;
+        let root = event.root.clone();
+        self.publish(vec![event]);
+        return Ok(root);
+    }
+
+    /// The signature block written by the append of `item_id`.
+    pub fn get_signature(&mut self, item_id: ItemId) -> Result<SignatureBlock, IsoCoreError> {
+        if item_id.0 >= self.len().0 as u64 {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        return Ok(SignatureBlock::from_bytes(&self.sig_core.get_contents(MessageId(item_id.0 as u16))?)?);
+    }
+
+    /// Appends a message to a threshold core, signed by each of `keys`;
+    /// there must be at least the threshold of them, all among the core's
+    /// signers. Works for a single-signer core too, given its key.
+    pub fn add_message_by(&mut self, message: &[u8], keys: &[&KeyPair]) -> Result<Hash, IsoCoreError> {
+        let event = self.append_by(message, keys)?;
+        let root = event.root.clone();
+        self.publish(vec![event]);
+        return Ok(root);
+    }
+
+    fn append(&mut self, message: &[u8], signer: &KeyPair) -> Result<AppendEvent, IsoCoreError> {
+        // Verify signer matches IsoCore's public key
+        if signer.key_pub != self.signer {
+            return Err(IsoCoreError::SignerMismatch);
+        }
+        let hash = self.version.hash_leaf(message);
+        return self.append_with(hash, Some(message), |root| Ok(vec![(0, signer.sign(&root.0))]));
+    }
+
+    fn append_by(&mut self, message: &[u8], keys: &[&KeyPair]) -> Result<AppendEvent, IsoCoreError> {
+        let signing = signing_keys(&self.signers, keys)?;
+        let hash = self.version.hash_leaf(message);
+        return self.append_with(hash, Some(message), |root| {
+            return Ok(signing.iter().map(|(index, key)| (*index, key.sign(&root.0))).collect());
+        });
+    }
+
+    /// Stores `message` for a later `build_tree`, recording only its leaf
+    /// hash. Returns the item id it will have. Until the tree is built the
+    /// item doesn't count towards `len`, and nothing else can be appended.
+    pub fn ingest(&mut self, message: &[u8]) -> Result<ItemId, IsoCoreError> {
+        if !self.sealed {
+            return Err(IsoCoreError::UnsealedInfo);
+        }
+        let item_id = ItemId(self.len().0 as u64 + self.ingested.len() as u64);
+        let hash = self.version.hash_leaf(message);
+        match self.payloads {
+            Some(_) => {
+                if !self.sig_core.has_room(self.ingested.len() + 1) {
+                    return Err(IsoCoreError::Core(CoreError::CoreFull));
+                }
+                self.keep_payload(&hash, message)?;
+                self.amplification.message_bytes += message.len() as u64;
+                self.amplification.data_bytes += message.len() as u64;
+            }
+            None => {
+                let inline = self.inlines(message);
+                self.data_core.add_message(if inline { &[] } else { message })?;
+                if let (Some(sizes), false) = (&mut self.sizes, inline) {
+                    sizes.payload += message.len() as u64;
+                }
+                self.amplification.message_bytes += message.len() as u64;
+                if !inline {
+                    self.amplification.data_bytes += message.len() as u64;
+                }
+                if inline {
+                    self.ingested.push((hash, Some(message.to_vec())));
+                    return Ok(item_id);
+                }
+            }
+        }
+        self.ingested.push((hash, None));
+        return Ok(item_id);
+    }
+
+    /// Builds the tree over every ingested item in one pass and signs the
+    /// root after each, as their appends would have. Node hashes are kept
+    /// in memory as they are made, so no node is read back or hashed
+    /// twice. Returns the final root, or None if nothing was ingested.
+    ///
+    /// Each item still gets its own signature block: the sig core holds
+    /// one per item, and `get_signature`, `prove` at any earlier length,
+    /// checkpoints, replication, and the cut `recover` makes after a crash
+    /// all rely on it. One signature over the final root would leave every
+    /// other length unsigned.
+    ///
+    /// An item leaves the ingest queue only once its nodes and signature
+    /// block are written. If one fails, the items before it stay built,
+    /// what it wrote is cut off, and it and the rest can be built again.
+    pub fn build_tree(&mut self, signer: &KeyPair) -> Result<Option<Hash>, IsoCoreError> {
+        if signer.key_pub != self.signer {
+            return Err(IsoCoreError::SignerMismatch);
+        }
+        let len = self.len().0 as u64;
+        let count = self.ingested.len() as u64;
+        if count == 0 {
+            return Ok(None);
+        }
+        let nodes = verkle_len_for_items(len + count) - verkle_len_for_items(len);
+        if !self.verkle_core.has_room(nodes as usize) || !self.sig_core.has_room(count as usize) {
+            return Err(IsoCoreError::Core(CoreError::CoreFull));
+        }
+
+        // Complete nodes no parent has taken yet: always the peaks
+        let mut unclaimed = HashMap::new();
+        for peak_id in get_peaks(len, WIDTH) {
+            unclaimed.insert(peak_id.0, self.get_node(peak_id)?.compute_hash(self.version));
+        }
+
+        let mut events = Vec::new();
+        let mut root = None;
+        let mut built = 0;
+        let mut failed = None;
+        while built < self.ingested.len() {
+            let item_id = ItemId(len + built as u64);
+            let (leaf_hash, inline) = self.ingested[built].clone();
+            match self.build_item(item_id, &leaf_hash, inline, &mut unclaimed, signer) {
+                Ok(global_root) => {
+                    if !self.subscribers.is_empty() {
+                        events.push(AppendEvent {
+                            item_id,
+                            hash: leaf_hash,
+                            root: global_root.clone(),
+                            last_in_batch: false,
+                        });
+                    }
+                    root = Some(global_root);
+                    built += 1;
+                }
+                Err(err) => {
+                    failed = Some(err);
+                    break;
+                }
+            }
+        }
+        self.ingested.drain(..built);
+        if let Some(last) = events.last_mut() {
+            last.last_in_batch = true;
+        }
+        self.publish(events);
+
+        if let Some(err) = failed {
+            // Cut off whatever the failed item wrote, so it can be built again
+            let len = len + built as u64;
+            self.verkle_core.truncate(MessageId(verkle_len_for_items(len) as u16))?;
+            self.sig_core.truncate(MessageId(len as u16))?;
+            return Err(err);
+        }
+        return Ok(root);
+    }
+
+    /// Writes the nodes and signature block of one item for `build_tree`,
+    /// taking their children's hashes from `unclaimed` and leaving the new
+    /// nodes' there. Returns the signed root.
+    fn build_item(
+        &mut self,
+        item_id: ItemId,
+        leaf_hash: &Hash,
+        mut inline: Option<Vec<u8>>,
+        unclaimed: &mut HashMap<u64, Hash>,
+        signer: &KeyPair,
+    ) -> Result<Hash, IsoCoreError> {
+        let coverings = coverings_for_item(item_id, WIDTH);
+        let mut verkle_bytes = 0;
+        let mut verkle_nodes = 0;
+        for covering_id in (coverings.range().start.0..coverings.range().end.0).map(CoveringId) {
+            let children_ids = children_for_covering(covering_id, WIDTH);
+            let mut children = Vec::new();
+            if children_ids.is_empty() {
+                children.push(NodeChild {
+                    node_type: NodeType::Leaf,
+                    hash: leaf_hash.clone(),
+                    index: MessageId(item_id.0 as u16),
+                    data: inline.take(),
+                });
+            }
+            for child_id in children_ids {
+                children.push(NodeChild {
+                    node_type: NodeType::Branch,
+                    hash: unclaimed.remove(&child_id.0).ok_or(IsoCoreError::IntegrityError)?,
+                    index: child_id.to_verkle_id(),
+                    data: None,
+                });
+            }
+            let node = VerkleNode { children };
+            unclaimed.insert(covering_id.0, node.compute_hash(self.version));
+            let bytes = node.to_bytes(self.version);
+            verkle_bytes += bytes.len() as u64;
+            verkle_nodes += 1;
+            self.verkle_core.add_message(&bytes)?;
+        }
+        #[cfg(test)]
+        if FAIL_BUILD_AT.get() == Some(item_id.0) {
+            return Err(IsoCoreError::IntegrityError);
+        }
+
+        let mut peaks = Vec::new();
+        for peak_id in get_peaks(item_id.0 + 1, WIDTH) {
+            peaks.push(unclaimed.get(&peak_id.0).ok_or(IsoCoreError::IntegrityError)?);
+        }
+        let global_root = self.version.hash_root(item_id.0 + 1, peaks);
+        let sig_block = SignatureBlock::single(global_root.clone(), signer.sign(&global_root.0));
+        let sig_bytes = sig_block.to_bytes();
+        self.sig_core.add_message(&sig_bytes)?;
+        if let Some(sizes) = &mut self.sizes {
+            sizes.verkle += verkle_bytes;
+            sizes.sig += sig_bytes.len() as u64;
+        }
+        self.amplification.appends += 1;
+        self.amplification.verkle_nodes += verkle_nodes;
+        self.amplification.verkle_bytes += verkle_bytes;
+        self.amplification.sig_bytes += sig_bytes.len() as u64;
+        self.record_append(item_id, &sig_block);
+        return Ok(global_root);
+    }
+
+    /// Stages an append of the item with leaf hash `msg_hash`, asks `sign`
+    /// for the signatures over the new global root, then commits it. Only
+    /// a light core may be given no `message`.
+    fn append_with(
+        &mut self,
+        msg_hash: Hash,
+        message: Option<&[u8]>,
+        sign: impl FnOnce(&Hash) -> Result<Vec<(u8, Signature)>, IsoCoreError>,
+    ) -> Result<AppendEvent, IsoCoreError> {
+        if !self.sealed {
+            return Err(IsoCoreError::UnsealedInfo);
+        }
+        if !self.ingested.is_empty() {
+            return Err(IsoCoreError::Ingesting);
+        }
+        // Stage every write before touching any core, so a failure partway
+        // through (say, a full verkle core) leaves all three untouched.
+        let item_id = ItemId(self.len().0 as u64);
+        // A light core's leaves point at the item's own index
+        let data_index = match self.payloads {
+            Some(_) => MessageId(item_id.0 as u16),
+            None => self.data_core.len(),
+        };
+
+        let coverings = coverings_for_item(item_id, WIDTH);
+        let inline = message.filter(|message| self.payloads.is_none() && self.inlines(message));
+
+        let mut staged = Vec::new();
+        for covering_id_val in coverings.range().start.0..coverings.range().end.0 {
+            let covering_id = CoveringId(covering_id_val);
+            let node = self.build_node(covering_id, msg_hash.clone(), data_index, inline, &staged)?;
+            staged.push((covering_id, node));
+        }
+
+        let global_root = self.bag_peaks(item_id.0 + 1, &staged)?;
+
+        // Sign the global root
+        let started = Instant::now();
+        let signatures = sign(&global_root)?;
+        self.metrics.duration(metrics::ISOCORE_SIGN_SECONDS, started.elapsed());
+        
+        let sig_block = SignatureBlock {
+            global_root: global_root.clone(),
+            signatures,
+        };
+
+        let fits = (self.payloads.is_some() || self.data_core.has_room(1))
+            && self.verkle_core.has_room(staged.len())
+            && self.sig_core.has_room(1);
+        if !fits {
+            return Err(IsoCoreError::Core(CoreError::CoreFull));
+        }
+
+        // Commit the staged writes
+        match (self.payloads.is_some(), message) {
+            (true, Some(message)) => self.keep_payload(&msg_hash, message)?,
+            (true, None) => {}
+            // An inlined payload leaves an empty placeholder, keeping one
+            // data message per item
+            (false, Some(message)) => {
+                self.data_core.add_message(if inline.is_some() { &[] } else { message })?;
+            }
+            (false, None) => return Err(IsoCoreError::NeedsData { item_id, hash: msg_hash }),
+        }
+        let mut verkle_bytes = 0;
+        for (_, node) in &staged {
+            let bytes = node.to_bytes(self.version);
+            verkle_bytes += bytes.len() as u64;
+            self.verkle_core.add_message(&bytes)?;
+        }
+        let sig_bytes = sig_block.to_bytes();
+        self.sig_core.add_message(&sig_bytes)?;
+        if let Some(sizes) = &mut self.sizes {
+            if self.payloads.is_none() && inline.is_none() {
+                sizes.payload += message.map_or(0, |message| message.len() as u64);
+            }
+            sizes.verkle += verkle_bytes;
+            sizes.sig += sig_bytes.len() as u64;
+        }
+        let amplification = &mut self.amplification;
+        amplification.appends += 1;
+        amplification.message_bytes += message.map_or(0, |message| message.len() as u64);
+        if inline.is_none() {
+            amplification.data_bytes += message.map_or(0, |message| message.len() as u64);
+        }
+        amplification.verkle_nodes += staged.len() as u64;
+        amplification.verkle_bytes += verkle_bytes;
+        amplification.sig_bytes += sig_bytes.len() as u64;
+        self.record_append(item_id, &sig_block);
+
+        return Ok(AppendEvent {
+            item_id,
+            hash: msg_hash,
+            root: global_root,
+            last_in_batch: true,
+        });
+    }
+
+    /// Indexes the root signed after `item_id` and records a checkpoint
+    /// if one is due.
+    fn record_append(&mut self, item_id: ItemId, sig_block: &SignatureBlock) {
+        self.roots.insert(sig_block.global_root.0, item_id);
+        self.metrics.counter(metrics::ISOCORE_ITEMS_APPENDED, 1);
+        if let Some(signature) = sig_block.signature()
+            && self.checkpoint_interval > 0
+            && (item_id.0 + 1).is_multiple_of(self.checkpoint_interval) {
+            self.checkpoints.push(Checkpoint {
+                len: item_id.0 + 1,
+                root: sig_block.global_root.clone(),
+                signature: signature.clone(),
+            });
+        }
+    }
+
+    /// Bag the peaks: get all peak roots for `len` items and hash them
+    /// together into the global root that gets signed.
+    fn bag_peaks(&mut self, len: u64, staged: &[(CoveringId, VerkleNode)]) -> Result<Hash, IsoCoreError> {
+        let peaks = get_peaks(len, WIDTH);
+
+        // Hash the peak hashes, in order, into the global root
+        let mut global_root = self.version.root_hasher(len);
+        for peak_id in peaks {
+            let peak_node = self.get_staged_node(peak_id, staged)?;
+            global_root.update(&peak_node.compute_hash(self.version).0);
+        }
+        return Ok(global_root.finish());
+    }
+
+    /// Checks the latest signature block against the tree: the stored root
+    /// must match the bagged peaks, and the signature must verify against
+    /// the core's signer. Returns the verified global root.
+    pub fn verify_head(&mut self) -> Result<Hash, IsoCoreError> {
+        let len = self.len().0;
+        if len == 0 || self.sig_core.len().0 != len {
+            return Err(IsoCoreError::IntegrityError);
+        }
+
+        return self.verify_signature(ItemId(len as u64 - 1));
+    }
+
+    /// Checks the signature block written by the append of `item_id`: its
+    /// root must match the peaks of the first `item_id + 1` items, bagged
+    /// again from the tree, and its signatures must verify against the
+    /// core's signers, as many as the threshold. Returns the verified root.
+    pub fn verify_signature(&mut self, item_id: ItemId) -> Result<Hash, IsoCoreError> {
+        let block = self.get_signature(item_id)?;
+        let global_root = self.bag_peaks(item_id.0 + 1, &[])?;
+
+        if block.global_root != global_root {
+            return Err(IsoCoreError::IntegrityError);
+        }
+        if !self.signers.verify(&block) {
+            return Err(IsoCoreError::IntegrityError);
+        }
+        return Ok(global_root);
+    }
+
+    /// Checks the whole core: every message against its leaf hash, every
+    /// branch against its children, every recorded global root against the
+    /// bagged peaks at that length, and every signature against the signer.
+    /// Damage is counted rather than returned, so one bad entry doesn't
+    /// hide the rest.
+    pub fn audit(&mut self, mut progress: impl FnMut(AuditEvent)) -> AuditReport {
+        let total = self.len().0 as u64;
+        let mut report = AuditReport { items: total, ..AuditReport::default() };
+
+        for n in 0..total {
+            let item = ItemId(n);
+            let coverings = coverings_for_item(item, WIDTH);
+
+            // get_message checks the data against its leaf hash; a light
+            // core missing a payload isn't damaged
+            match self.get_message(item) {
+                Ok(_) | Err(IsoCoreError::NeedsData { .. }) => {}
+                Err(_) => report.record(AuditCategory::Data, n, &mut progress),
+            }
+
+            for covering_id_val in coverings.range().start.0..coverings.range().end.0 {
+                if !matches!(self.audit_node(CoveringId(covering_id_val)), Ok(true)) {
+                    report.record(AuditCategory::Node, covering_id_val, &mut progress);
+                }
+            }
+
+            match self.audit_root(n) {
+                Ok((root_ok, signature_ok)) => {
+                    if !root_ok {
+                        report.record(AuditCategory::Root, n, &mut progress);
+                    }
+                    if !signature_ok {
+                        report.record(AuditCategory::Signature, n, &mut progress);
+                    }
+                }
+                Err(_) => report.record(AuditCategory::Root, n, &mut progress),
+            }
+
+            progress(AuditEvent::Checked { item, total });
+        }
+
+        return report;
+    }
+
+    /// Same checks and report as `audit`, but hashing and signature checks
+    /// run on the rayon thread pool. Items are read in batches, so only one
+    /// batch of messages is held at a time, plus the hashes of the peaks
+    /// the next batch builds on.
+    #[cfg(feature = "parallel")]
+    pub fn audit_parallel(&mut self, progress: impl FnMut(AuditEvent)) -> AuditReport {
+        // Payloads of a light core are read one by one anyway
+        if self.payloads.is_some() {
+            return self.audit(progress);
+        }
+        return self.audit_batched(AUDIT_BATCH, progress);
+    }
+
+    #[cfg(feature = "parallel")]
+    fn audit_batched(&mut self, batch: u64, mut progress: impl FnMut(AuditEvent)) -> AuditReport {
+        use rayon::prelude::*;
+
+        let total = self.len().0 as u64;
+        let mut report = AuditReport { items: total, ..AuditReport::default() };
+
+        // Hashes of nodes that later items may still reference
+        let mut known: BTreeMap<CoveringId, Hash> = BTreeMap::new();
+
+        let mut start = 0;
+        while start < total {
+            let end = (start + batch).min(total);
+            let first = coverings_for_item(ItemId(start), WIDTH).range().start.0;
+            let last = coverings_for_item(ItemId(end - 1), WIDTH).range().end.0;
+
+            // Read the batch; this is the only part that touches the cores
+            let nodes: Vec<Option<VerkleNode>> = (first..last)
+                .map(|y| self.get_node(CoveringId(y)).ok())
+                .collect();
+            let mut messages = Vec::new();
+            let mut blocks = Vec::new();
+            for n in start..end {
+                let leaf = coverings_for_item(ItemId(n), WIDTH).leaf();
+                let message = match &nodes[(leaf.0 - first) as usize] {
+                    Some(node) if node.children.len() == 1 && node.children[0].node_type == NodeType::Leaf => {
+                        let child = &node.children[0];
+                        match &child.data {
+                            Some(data) => Some((data.clone(), child.hash.clone())),
+                            None => self.data_core.get_contents(child.index).ok()
+                                .map(|data| (data.to_vec(), child.hash.clone())),
+                        }
+                    }
+                    _ => None,
+                };
+                messages.push(message);
+
+                let block = self.sig_core.get_contents(MessageId(n as u16)).ok()
+                    .and_then(|bytes| SignatureBlock::from_bytes(&bytes).ok());
+                blocks.push(block);
+            }
+
+            // Node hashes, then node structure against them
+            let version = self.version;
+            let hashes: Vec<Option<Hash>> = nodes.par_iter()
+                .map(|node| node.as_ref().map(|node| node.compute_hash(version)))
+                .collect();
+            for (i, node_hash) in hashes.into_iter().enumerate() {
+                if let Some(node_hash) = node_hash {
+                    known.insert(CoveringId(first + i as u64), node_hash);
+                }
+            }
+            let nodes_ok: Vec<bool> = nodes.par_iter().enumerate()
+                .map(|(i, node)| match node {
+                    Some(node) => node_matches(node, CoveringId(first + i as u64), &known),
+                    None => false,
+                })
+                .collect();
+
+            let data_ok: Vec<bool> = messages.par_iter()
+                .map(|message| match message {
+                    Some((data, expected)) => version.hash_leaf(data) == *expected,
+                    None => false,
+                })
+                .collect();
+
+            // (root recorded and matching, signature verifies) per item
+            let signers = &self.signers;
+            let peak_hashes = &known;
+            let roots_ok: Vec<Option<(bool, bool)>> = blocks.par_iter().enumerate()
+                .map(|(i, block)| {
+                    let block = block.as_ref()?;
+                    let mut global_root = version.root_hasher(start + i as u64 + 1);
+                    for peak_id in get_peaks(start + i as u64 + 1, WIDTH) {
+                        global_root.update(&peak_hashes.get(&peak_id)?.0);
+                    }
+                    let root_ok = block.global_root == global_root.finish();
+                    let signature_ok = signers.verify(block);
+                    Some((root_ok, signature_ok))
+                })
+                .collect();
+
+            // Report in the same order as the sequential audit
+            for n in start..end {
+                let i = (n - start) as usize;
+                if !data_ok[i] {
+                    report.record(AuditCategory::Data, n, &mut progress);
+                }
+                let coverings = coverings_for_item(ItemId(n), WIDTH);
+                for y in coverings.range().start.0..coverings.range().end.0 {
+                    if !nodes_ok[(y - first) as usize] {
+                        report.record(AuditCategory::Node, y, &mut progress);
+                    }
+                }
+                match roots_ok[i] {
+                    Some((root_ok, signature_ok)) => {
+                        if !root_ok {
+                            report.record(AuditCategory::Root, n, &mut progress);
+                        }
+                        if !signature_ok {
+                            report.record(AuditCategory::Signature, n, &mut progress);
+                        }
+                    }
+                    None => report.record(AuditCategory::Root, n, &mut progress),
+                }
+                progress(AuditEvent::Checked { item: ItemId(n), total });
+            }
+
+            // Only the current peaks can be children of later nodes
+            let peaks = get_peaks(end, WIDTH);
+            let mut known_next = BTreeMap::new();
+            for peak_id in peaks {
+                if let Some(peak_hash) = known.get(&peak_id) {
+                    known_next.insert(peak_id, peak_hash.clone());
+                }
+            }
+            known = known_next;
+            start = end;
+        }
+
+        return report;
+    }
+
+    fn audit_node(&mut self, covering_id: CoveringId) -> Result<bool, IsoCoreError> {
+        let node = self.get_node(covering_id)?;
+
+        let mut child_hashes = BTreeMap::new();
+        for child_id in children_for_covering(covering_id, WIDTH) {
+            child_hashes.insert(child_id, self.get_node(child_id)?.compute_hash(self.version));
+        }
+        return Ok(node_matches(&node, covering_id, &child_hashes));
+    }
+
+    /// Removes entries a partial append left behind. Nodes are walked from
+    /// the peaks of the items all three cores hold; every reachable node
+    /// must be stored. Cores are append-only and addressed by position, so
+    /// unreachable entries can only trail the reachable ones, and they are
+    /// cut off along with any signature blocks and messages past the last
+    /// complete item. `load` does the same during recovery; this is for a
+    /// core that has been running since.
+    pub fn gc(&mut self) -> Result<GcReport, IsoCoreError> {
+        if !self.ingested.is_empty() {
+            return Err(IsoCoreError::Ingesting);
+        }
+        let lengths = self.lengths();
+        let items = lengths.items();
+        let mut report = GcReport::default();
+
+        let mut stack = get_peaks(items, WIDTH);
+        while let Some(y) = stack.pop() {
+            if y.0 >= lengths.verkle {
+                return Err(IsoCoreError::IntegrityError);
+            }
+            report.reachable += 1;
+            stack.extend(children_for_covering(y, WIDTH));
+        }
+        report.unreachable = lengths.verkle - report.reachable;
+
+        let verkle_len = verkle_len_for_items(items);
+        let cores = [
+            (&mut self.verkle_core, verkle_len, &mut report.removed_nodes),
+            (&mut self.sig_core, items, &mut report.removed_signatures),
+            (&mut self.data_core, items, &mut report.removed_messages),
+        ];
+        for (core, keep, removed) in cores {
+            let len = core.len().0 as u64;
+            for id in keep..len {
+                report.reclaimed_bytes += core.get_contents(MessageId(id as u16))?.len() as u64;
+            }
+            *removed = len.saturating_sub(keep);
+            core.truncate(MessageId(keep as u16))?;
+        }
+        self.committed = self.committed.min(items);
+        self.roots.retain(|_, item| item.0 < items);
+        self.sizes = None;
+        return Ok(report);
+    }
+
+    /// Returns whether the root recorded after item `n` matches the tree,
+    /// and whether its signature verifies.
+    fn audit_root(&mut self, n: u64) -> Result<(bool, bool), IsoCoreError> {
+        let block = SignatureBlock::from_bytes(&self.sig_core.get_contents(MessageId(n as u16))?)?;
+        let global_root = self.bag_peaks(n + 1, &[])?;
+
+        let root_ok = block.global_root == global_root;
+        let signature_ok = self.signers.verify(&block);
+        return Ok((root_ok, signature_ok));
+    }
+
+    /// Whether a full core keeps `message` in its leaf node.
+    fn inlines(&self, message: &[u8]) -> bool {
+        return !message.is_empty() && message.len() as u64 <= self.inline_limit;
+    }
+
+    /// Payloads of up to this many bytes are kept in their leaf nodes
+    /// rather than the data core; 0 inlines none. Set when the core is
+    /// created and kept in info.nd.
+    pub fn inline_limit(&self) -> u64 {
+        return self.inline_limit;
+    }
+
+    fn build_node(
+        &mut self,
+        covering_id: CoveringId,
+        leaf_hash: Hash,
+        leaf_index: MessageId,
+        inline: Option<&[u8]>,
+        staged: &[(CoveringId, VerkleNode)],
+    ) -> Result<VerkleNode, IsoCoreError> {
+        let children_ids = children_for_covering(covering_id, WIDTH);
+
+        if children_ids.is_empty() {
+            return Ok(VerkleNode {
+                children: vec![NodeChild {
+                    node_type: NodeType::Leaf,
+                    hash: leaf_hash,
+                    index: leaf_index,
+                    data: inline.map(<[u8]>::to_vec),
+                }],
+            });
+        }
+
+        let mut children = Vec::new();
+        for child_id in children_ids {
+            let child_node = self.get_staged_node(child_id, staged)?;
+            children.push(NodeChild {
+                node_type: NodeType::Branch,
+                hash: child_node.compute_hash(self.version),
+                index: child_id.to_verkle_id(),
+                data: None,
+            });
+        }
+
+        return Ok(VerkleNode { children });
+    }
+
+    /// Proves item `item_id` is in the root signed after the first `len`
+    /// items. See `InclusionProof`.
+    pub fn prove(&mut self, item_id: ItemId, len: u64) -> Result<InclusionProof, IsoCoreError> {
+        if item_id.0 >= len || len > self.len().0 as u64 {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        let block = SignatureBlock::from_bytes(&self.sig_core.get_contents(MessageId((len - 1) as u16))?)?;
+
+        let peak_ids = get_peaks(len, WIDTH);
+        let peak = *peak_ids.iter()
+            .find(|peak| covering_range(**peak, WIDTH).contains(&item_id))
+            .ok_or(IsoCoreError::IntegrityError)?;
+
+        // Child hashes of each node between the leaf node and the peak
+        let mut path = Vec::new();
+        for (node_id, _) in nodes_below(peak, item_id).into_iter().rev() {
+            let node = self.get_node(node_id)?;
+            path.push(node.children.into_iter().map(|child| child.hash).collect());
+        }
+
+        let leaf = self.get_node(coverings_for_item(item_id, WIDTH).leaf())?;
+        let leaf_hash = leaf.children.first().ok_or(IsoCoreError::NodeFormat)?.hash.clone();
+
+        let peaks = self.peaks(len)?;
+
+        return Ok(InclusionProof {
+            version: self.version,
+            item_id,
+            len,
+            leaf_hash,
+            path,
+            peaks,
+            signature: block.signature().ok_or(IsoCoreError::ThresholdCore)?.clone(),
+        });
+    }
+
+    /// Proves each of `items` under the root signed after `len` items, as
+    /// `prove` would, in the same order. The signature and peaks all the
+    /// proofs share are read once. Nodes are read a batch of items at a
+    /// time, so only one batch is held at once, then decoded and put
+    /// together into proofs on the rayon thread pool.
+    #[cfg(feature = "parallel")]
+    pub fn prove_batch(&mut self, items: &[ItemId], len: u64) -> Result<Vec<InclusionProof>, IsoCoreError> {
+        use rayon::prelude::*;
+
+        if items.iter().any(|item_id| item_id.0 >= len) || len > self.len().0 as u64 {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        if items.is_empty() {
+            return Ok(Vec::new());
+        }
+        let block = SignatureBlock::from_bytes(&self.sig_core.get_contents(MessageId((len - 1) as u16))?)?;
+        let signature = block.signature().ok_or(IsoCoreError::ThresholdCore)?.clone();
+        let peak_ids = get_peaks(len, WIDTH);
+        let peaks = self.peaks(len)?;
+        let version = self.version;
+
+        let mut proofs = Vec::with_capacity(items.len());
+        for batch in items.chunks(AUDIT_BATCH as usize) {
+            // Read every node the batch needs; this is the only part that
+            // touches the cores
+            let mut paths = Vec::with_capacity(batch.len());
+            let mut bytes = BTreeMap::new();
+            for &item_id in batch {
+                let peak = *peak_ids.iter()
+                    .find(|peak| covering_range(**peak, WIDTH).contains(&item_id))
+                    .ok_or(IsoCoreError::IntegrityError)?;
+                let mut path: Vec<CoveringId> = nodes_below(peak, item_id).into_iter()
+                    .rev()
+                    .map(|(node_id, _)| node_id)
+                    .collect();
+                path.push(coverings_for_item(item_id, WIDTH).leaf());
+                for &node_id in &path {
+                    if let std::collections::btree_map::Entry::Vacant(entry) = bytes.entry(node_id) {
+                        self.load_node(node_id)?;
+                        entry.insert(self.verkle_core.get_contents(node_id.to_verkle_id())?.into_owned());
+                    }
+                }
+                paths.push((item_id, path));
+            }
+
+            let nodes: BTreeMap<CoveringId, VerkleNode> = bytes.into_par_iter()
+                .map(|(node_id, bytes)| Ok((node_id, VerkleNode::from_bytes(&bytes)?)))
+                .collect::<Result<_, IsoCoreError>>()?;
+            let batch_proofs: Vec<InclusionProof> = paths.into_par_iter()
+                .map(|(item_id, mut path)| {
+                    let leaf = path.pop().ok_or(IsoCoreError::IntegrityError)?;
+                    let leaf_hash = nodes[&leaf].children.first().ok_or(IsoCoreError::NodeFormat)?.hash.clone();
+                    let path = path.iter()
+                        .map(|node_id| nodes[node_id].children.iter().map(|child| child.hash.clone()).collect())
+                        .collect();
+                    Ok(InclusionProof {
+                        version,
+                        item_id,
+                        len,
+                        leaf_hash,
+                        path,
+                        peaks: peaks.clone(),
+                        signature: signature.clone(),
+                    })
+                })
+                .collect::<Result<_, IsoCoreError>>()?;
+            proofs.extend(batch_proofs);
+        }
+        return Ok(proofs);
+    }
+
+    /// Proves the contiguous `items` under the root signed after `len`
+    /// items, sending the nodes they share once.
+    pub fn prove_range(&mut self, items: Range<u64>, len: u64) -> Result<RangeProof, IsoCoreError> {
+        if items.is_empty() || items.end > len || len > self.len().0 as u64 {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        let block = SignatureBlock::from_bytes(&self.sig_core.get_contents(MessageId((len - 1) as u16))?)?;
+
+        let mut leaf_hashes = Vec::new();
+        let mut siblings = Vec::new();
+        for peak_id in get_peaks(len, WIDTH) {
+            if overlaps(peak_id, &items) {
+                self.range_siblings(peak_id, &items, &mut leaf_hashes, &mut siblings)?;
+            }
+        }
+
+        let peaks = self.peaks(len)?;
+
+        return Ok(RangeProof {
+            version: self.version,
+            start: ItemId(items.start),
+            len,
+            leaf_hashes,
+            siblings,
+            peaks,
+            signature: block.signature().ok_or(IsoCoreError::ThresholdCore)?.clone(),
+        });
+    }
+
+    /// Walks down from `node_id` as `RangeProof::root` does, collecting
+    /// the leaf hashes of `items` and the child hashes off them.
+    fn range_siblings(
+        &mut self,
+        node_id: CoveringId,
+        items: &Range<u64>,
+        leaf_hashes: &mut Vec<Hash>,
+        siblings: &mut Vec<Hash>,
+    ) -> Result<(), IsoCoreError> {
+        let node = self.get_node(node_id)?;
+        let children = children_for_covering(node_id, WIDTH);
+        if children.is_empty() {
+            leaf_hashes.push(node.children.first().ok_or(IsoCoreError::NodeFormat)?.hash.clone());
+            return Ok(());
+        }
+        if node.children.len() != children.len() {
+            return Err(IsoCoreError::NodeFormat);
+        }
+        for (child_id, child) in children.into_iter().zip(node.children) {
+            match overlaps(child_id, items) {
+                true => self.range_siblings(child_id, items, leaf_hashes, siblings)?,
+                false => siblings.push(child.hash),
+            }
+        }
+        return Ok(());
+    }
+
+    /// Hashes of the peaks after `len` items, in order. They bag to the
+    /// root signed at that length.
+    pub fn peaks(&mut self, len: u64) -> Result<Vec<Hash>, IsoCoreError> {
+        if len > self.len().0 as u64 {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        let mut peaks = Vec::new();
+        for peak_id in get_peaks(len, WIDTH) {
+            peaks.push(self.get_node(peak_id)?.compute_hash(self.version));
+        }
+        return Ok(peaks);
+    }
+
+    /// Proves that the core signed at `old_len` items is a prefix of the
+    /// core signed at `new_len`.
+    pub fn prove_consistency(&mut self, old_len: u64, new_len: u64) -> Result<ConsistencyProof, IsoCoreError> {
+        if old_len == 0 || old_len > new_len || new_len > self.len().0 as u64 {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        let old_block = self.get_signature(ItemId(old_len - 1))?;
+        let new_block = self.get_signature(ItemId(new_len - 1))?;
+
+        let mut old_peaks = Vec::new();
+        let mut paths = Vec::new();
+        for peak_id in get_peaks(old_len, WIDTH) {
+            old_peaks.push(self.get_node(peak_id)?.compute_hash(self.version));
+            let (_, nodes) = ascent(peak_id, new_len).ok_or(IsoCoreError::IntegrityError)?;
+            let mut path = Vec::new();
+            for (node_id, _) in nodes.into_iter().rev() {
+                let node = self.get_node(node_id)?;
+                path.push(node.children.into_iter().map(|child| child.hash).collect());
+            }
+            paths.push(path);
+        }
+
+        let new_peaks = self.peaks(new_len)?;
+
+        return Ok(ConsistencyProof {
+            version: self.version,
+            old_len,
+            new_len,
+            old_peaks,
+            paths,
+            new_peaks,
+            old_signature: old_block.signature().ok_or(IsoCoreError::ThresholdCore)?.clone(),
+            new_signature: new_block.signature().ok_or(IsoCoreError::ThresholdCore)?.clone(),
+        });
+    }
+
+    /// The root signed at `len` items, with each peak opened down to its
+    /// first leaf. See `HeadProof`.
+    pub fn prove_head(&mut self, len: u64) -> Result<HeadProof, IsoCoreError> {
+        if len == 0 || len > self.len().0 as u64 {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        let block = self.get_signature(ItemId(len - 1))?;
+
+        let mut leaves = Vec::new();
+        let mut paths = Vec::new();
+        for peak_id in get_peaks(len, WIDTH) {
+            let first = covering_range(peak_id, WIDTH).start;
+            let mut path = Vec::new();
+            for (node_id, _) in nodes_below(peak_id, first).into_iter().rev() {
+                let node = self.get_node(node_id)?;
+                path.push(node.children.into_iter().map(|child| child.hash).collect());
+            }
+            paths.push(path);
+            let leaf = self.get_node(coverings_for_item(first, WIDTH).leaf())?;
+            leaves.push(leaf.children.first().ok_or(IsoCoreError::NodeFormat)?.hash.clone());
+        }
+
+        return Ok(HeadProof {
+            version: self.version,
+            len,
+            peaks: self.peaks(len)?,
+            leaves,
+            paths,
+            signature: block.signature().ok_or(IsoCoreError::ThresholdCore)?.clone(),
+        });
+    }
+
+    /// Compares a head from elsewhere with this core's root at the same
+    /// length. Returns a `ForkProof` if the signer signed both and they
+    /// differ, or `None` if they agree or this core is shorter. A head the
+    /// signer didn't sign is an `IntegrityError`.
+    pub fn check_head(&mut self, head: &HeadProof) -> Result<Option<ForkProof>, IsoCoreError> {
+        if head.verify(&self.signer).is_err() {
+            return Err(IsoCoreError::IntegrityError);
+        }
+        if head.len > self.len().0 as u64 {
+            return Ok(None);
+        }
+        let own = self.prove_head(head.len)?;
+        return Ok(ForkProof::new(own, head.clone()));
+    }
+
+    pub fn get_root_hash(&mut self) -> Result<Hash, IsoCoreError> {
+        let len = self.len();
+        if len.0 == 0 {
+            return Ok(self.version.root_hasher(0).finish());
+        }
+
+        let last_item = ItemId((len.0 - 1) as u64);
+        let coverings = coverings_for_item(last_item, WIDTH);
+        let root_node = self.get_node(coverings.root())?;
+
+        return Ok(root_node.compute_hash(self.version));
+    }
+
+    pub fn len(&self) -> MessageId {
+        return match self.payloads {
+            Some(_) => self.sig_core.len(),
+            // Ingested payloads are stored ahead of their items
+            None => MessageId(self.data_core.len().0 - self.ingested.len() as u16),
+        };
+    }
+
+    /// How big the core is. Content sizes are counted once after a load,
+    /// by reading every core, and kept up to date by appends from then on;
+    /// file sizes are read from the directory each call.
+    pub fn stats(&mut self) -> Result<CoreStats, IsoCoreError> {
+        let sizes = match self.sizes {
+            Some(sizes) => sizes,
+            None => ContentSizes {
+                payload: self.data_core.content_bytes()?,
+                verkle: self.verkle_core.content_bytes()?,
+                sig: self.sig_core.content_bytes()?,
+            },
+        };
+        self.sizes = Some(sizes);
+
+        let mut disk = BTreeMap::new();
+        if let Some(path) = &self.path {
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                let size = match metadata.is_dir() {
+                    true => std::fs::read_dir(entry.path())?
+                        .map(|file| Ok(file?.metadata()?.len()))
+                        .sum::<Result<u64, std::io::Error>>()?,
+                    false => metadata.len(),
+                };
+                disk.insert(entry.file_name().to_string_lossy().into_owned(), size);
+            }
+        }
+
+        return Ok(CoreStats {
+            items: self.len().0 as u64,
+            payload_bytes: sizes.payload,
+            verkle_bytes: sizes.verkle,
+            sig_bytes: sizes.sig,
+            disk,
+        });
+    }
+
+    /// What appends have written to each core since the core was opened
+    /// or the stats were last reset. See `AmplificationStats`.
+    pub fn amplification_stats(&self) -> AmplificationStats {
+        return self.amplification;
+    }
+
+    /// Starts counting `amplification_stats` afresh, to measure one
+    /// workload at a time.
+    pub fn reset_amplification_stats(&mut self) {
+        self.amplification = AmplificationStats::default();
+    }
+
+    /// `covering::dump_tree` for this core, with each node's hash as
+    /// stored in verkle_core, for tests and `home inspect`.
+    pub fn debug_tree(&mut self) -> String {
+        let len = self.len().0 as u64;
+        let version = self.version;
+        return dump_tree_with(len, WIDTH, |y| match self.get_node(y) {
+            Ok(node) => node.compute_hash(version).to_hex(),
+            Err(e) => format!("unreadable: {:?}", e),
+        });
+    }
+
+    /// Whether this is a light core, holding only the payloads it has been
+    /// given.
+    pub fn is_light(&self) -> bool {
+        return self.payloads.is_some();
+    }
+
+    /// Whether info.nd is signed by the core's signers. One from before
+    /// seals were signed still loads, for reading; see `reseal`.
+    pub fn is_sealed(&self) -> bool {
+        return self.sealed;
+    }
+
+    /// The signed info.nd, which `create_replica` and `create_light` take
+    /// to copy the core; None for an in-memory core.
+    pub fn info_bytes(&self) -> Option<&[u8]> {
+        return self.info.as_deref();
+    }
+
+    /// Where the core is stored; `None` for an in-memory core.
+    pub fn path(&self) -> Option<&Path> {
+        return self.path.as_deref();
+    }
+
+    /// Where the core's files live; `None` in memory.
+    pub fn files(&self) -> Option<CoreFiles> {
+        return self.path.as_deref().map(CoreFiles::new);
+    }
+
+    /// The key every item is signed with.
+    pub fn signer(&self) -> &KeyPub {
+        return &self.signer;
+    }
+
+    pub fn version(&self) -> FormatVersion {
+        return self.version;
+    }
+
+    /// When the core was created, as recorded in info.nd.
+    pub fn created_at(&self) -> Option<Timestamp> {
+        return self.created_at;
+    }
+
+    /// The underlying cores, for inspection. They can only be changed
+    /// through the IsoCore, which keeps them consistent.
+    pub fn cores(&self) -> Cores<'_> {
+        return Cores { data: &self.data_core, verkle: &self.verkle_core, sig: &self.sig_core };
+    }
+
+    fn load_node(&mut self, covering_id: CoveringId) -> Result<(), IsoCoreError> {
+        let verkle_id = covering_id.to_verkle_id();
+        self.verkle_core.load_message(verkle_id)?;
+        return Ok(());
+    }
+
+    fn get_node(&mut self, covering_id: CoveringId) -> Result<VerkleNode, IsoCoreError> {
+        self.load_node(covering_id)?;
+        let verkle_id = covering_id.to_verkle_id();
+        let bytes = self.verkle_core.get_contents(verkle_id)?;
+        return VerkleNode::from_bytes(&bytes);
+    }
+
+    /// Like get_node, but sees nodes staged by an in-progress append.
+    fn get_staged_node(&mut self, covering_id: CoveringId, staged: &[(CoveringId, VerkleNode)]) -> Result<VerkleNode, IsoCoreError> {
+        if let Some((_, node)) = staged.iter().find(|(id, _)| *id == covering_id) {
+            return Ok(node.clone());
+        }
+        return self.get_node(covering_id);
+    }
+
+    /// The leaf entry for `item_id`: its payload's hash and data index.
+    fn leaf(&mut self, item_id: ItemId) -> Result<NodeChild, IsoCoreError> {
+        let coverings = coverings_for_item(item_id, WIDTH);
+        let mut leaf_node = self.get_node(coverings.leaf())?;
+
+        if leaf_node.children.len() != 1 || leaf_node.children[0].node_type != NodeType::Leaf {
+            return Err(IsoCoreError::NodeFormat);
+        }
+        return Ok(leaf_node.children.remove(0));
+    }
+
+    /// The payload of `item_id`, checked against its leaf. It borrows
+    /// from the core's cache where it can; an inlined payload comes back
+    /// owned, decoded from its leaf.
+    pub fn get_message(&mut self, item_id: ItemId) -> Result<Cow<'_, [u8]>, IsoCoreError> {
+        return Ok(self.verified_message(item_id)?.1);
+    }
+
+    /// The leaf hash of `item_id` and its payload, checked against it.
+    fn verified_message(&mut self, item_id: ItemId) -> Result<(Hash, Cow<'_, [u8]>), IsoCoreError> {
+        let leaf = self.leaf(item_id)?;
+        if let Some(payloads) = &mut self.payloads {
+            let data = payloads.get(self.version, item_id, &leaf.hash)?;
+            return Ok((leaf.hash, Cow::Borrowed(data)));
+        }
+
+        if let Some(data) = leaf.data {
+            if self.version.hash_leaf(&data) != leaf.hash {
+                return Err(IsoCoreError::IntegrityError);
+            }
+            return Ok((leaf.hash, Cow::Owned(data)));
+        }
+
+        let data_id = leaf.index;
+        let expected_hash = leaf.hash;
+        
+        self.data_core.load_message(data_id)?;
+        let data = self.data_core.get_contents(data_id)?;
+        
+        // Verify data integrity
+        let actual_hash = self.version.hash_leaf(&data);
+        if actual_hash != expected_hash {
+            return Err(IsoCoreError::IntegrityError);
+        }
+
+        return Ok((expected_hash, data));
+    }
+
+    /// The core as it stood after its first `len` items. See
+    /// `HistoricalView`.
+    pub fn at(&mut self, len: u64) -> Result<HistoricalView<'_>, IsoCoreError> {
+        if len > self.len().0 as u64 {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        return Ok(HistoricalView { core: self, len });
+    }
+
+    /// Walks the items from `from` on, checking each payload against its
+    /// leaf as it goes. See `VerifiedItems`.
+    pub fn iter_verified(&mut self, from: ItemId) -> VerifiedItems<'_> {
+        return VerifiedItems { core: self, next: from.0, failed: false };
+    }
+
+    /// Gives a light core the payload of `item_id`, once it matches the
+    /// leaf hash. A full core already holds every payload, so there it is
+    /// only checked.
+    pub fn hydrate(&mut self, item_id: ItemId, bytes: &[u8]) -> Result<(), IsoCoreError> {
+        if item_id.0 >= self.len().0 as u64 {
+            return Err(IsoCoreError::Core(CoreError::FutureMessage));
+        }
+        let leaf = self.leaf(item_id)?;
+        if self.version.hash_leaf(bytes) != leaf.hash {
+            return Err(IsoCoreError::IntegrityError);
+        }
+        return self.keep_payload(&leaf.hash, bytes);
+    }
+
+    /// Keeps a light core's payload; a full core's go in data_core instead.
+    fn keep_payload(&mut self, hash: &Hash, bytes: &[u8]) -> Result<(), IsoCoreError> {
+        let durability = self.durability;
+        if let Some(payloads) = &mut self.payloads {
+            payloads.put(hash, bytes, durability)?;
+        }
+        return Ok(());
+    }
+
+    /// Up to `limit` items, skipping the first `offset` in `direction`:
+    /// `NewestFirst` with offset 0 starts at the latest item. Each disk
+    /// frame is read once for the whole page, and every message is checked
+    /// against its leaf hash as in `get_message`.
+    pub fn page(&mut self, offset: u64, limit: u64, direction: PageDirection) -> Result<Vec<PageEntry>, IsoCoreError> {
+        let len = self.len().0 as u64;
+        let count = limit.min(len.saturating_sub(offset));
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let items = match direction {
+            PageDirection::OldestFirst => offset..offset + count,
+            PageDirection::NewestFirst => len - offset - count..len - offset,
+        };
+
+        let first_leaf = coverings_for_item(ItemId(items.start), WIDTH).leaf();
+        let last_leaf = coverings_for_item(ItemId(items.end - 1), WIDTH).leaf();
+        self.verkle_core.load_range(first_leaf.to_verkle_id()..MessageId(last_leaf.0 as u16 + 1))?;
+        self.sig_core.load_range(MessageId(items.start as u16)..MessageId(items.end as u16))?;
+
+        let mut leaves = Vec::with_capacity(count as usize);
+        for item in items.clone() {
+            leaves.push(self.leaf(ItemId(item))?);
+        }
+        if self.payloads.is_some() {
+            return self.light_page(items, leaves, direction);
+        }
+        let first_data = leaves.iter().map(|leaf| leaf.index).min().unwrap();
+        let last_data = leaves.iter().map(|leaf| leaf.index).max().unwrap();
+        self.data_core.load_range(first_data..MessageId(last_data.0 + 1))?;
+
+        let mut entries = Vec::with_capacity(count as usize);
+        for (item, leaf) in items.zip(leaves) {
+            let data = match &leaf.data {
+                Some(data) => Cow::Borrowed(data.as_slice()),
+                None => self.data_core.get_contents(leaf.index)?,
+            };
+            if self.version.hash_leaf(&data) != leaf.hash {
+                return Err(IsoCoreError::IntegrityError);
+            }
+            let data = data.into_owned();
+            let block = SignatureBlock::from_bytes(&self.sig_core.get_contents(MessageId(item as u16))?)?;
+            entries.push(PageEntry {
+                item_id: ItemId(item),
+                hash: leaf.hash,
+                root: block.global_root,
+                data,
+            });
+        }
+        if direction == PageDirection::NewestFirst {
+            entries.reverse();
+        }
+        return Ok(entries);
+    }
+
+    /// `page` for a light core, reading each payload as `get_message` does.
+    fn light_page(&mut self, items: Range<u64>, leaves: Vec<NodeChild>, direction: PageDirection) -> Result<Vec<PageEntry>, IsoCoreError> {
+        let mut entries = Vec::with_capacity(leaves.len());
+        for (item, leaf) in items.zip(leaves) {
+            let data = self.get_message(ItemId(item))?.into_owned();
+            let block = SignatureBlock::from_bytes(&self.sig_core.get_contents(MessageId(item as u16))?)?;
+            entries.push(PageEntry {
+                item_id: ItemId(item),
+                hash: leaf.hash,
+                root: block.global_root,
+                data,
+            });
+        }
+        if direction == PageDirection::NewestFirst {
+            entries.reverse();
+        }
+        return Ok(entries);
+    }
+}
+
+/// Whether `node` has the shape expected at `covering_id`, and its branch
+/// children point at the right nodes with the right hashes.
+fn node_matches(node: &VerkleNode, covering_id: CoveringId, hashes: &BTreeMap<CoveringId, Hash>) -> bool {
+    let children_ids = children_for_covering(covering_id, WIDTH);
+
+    if children_ids.is_empty() {
+        return node.children.len() == 1 && node.children[0].node_type == NodeType::Leaf;
+    }
+    if node.children.len() != children_ids.len() {
+        return false;
+    }
+
+    return node.children.iter().zip(children_ids).all(|(child, child_id)| {
+        child.node_type == NodeType::Branch
+            && child.index == child_id.to_verkle_id()
+            && hashes.get(&child_id) == Some(&child.hash)
+    });
+}
+
+/// Number of verkle nodes written for the first `items` items.
+fn verkle_len_for_items(items: u64) -> u64 {
+    if items == 0 {
+        return 0;
+    }
+    return coverings_for_item(ItemId(items - 1), WIDTH).range().end.0;
+}
+
+/// Number of items whose verkle nodes all fit in `verkle_len` nodes.
+fn items_for_verkle_len(verkle_len: u64) -> u64 {
+    // Every item adds at least one node, so there are at most verkle_len items
+    let mut items = verkle_len;
+    while verkle_len_for_items(items) > verkle_len {
+        items -= 1;
+    }
+    return items;
+}
+
+fn read_roots(path: &Path) -> Result<HashMap<[u8; 32], ItemId>, IsoCoreError> {
+    let bytes = std::fs::read(path)?;
+    let (_, body) = Artifact::Roots.read_header(&bytes)?;
+    let mut dec = Decoder::new(body);
+    let mut map = dec.map()?;
+
+    let mut roots = HashMap::new();
+    while let Some((root, item)) = map.next()? {
+        let root = Hash::from_hex(root).map_err(|_| IsoCoreError::HexEncoding)?;
+        roots.insert(root.0, ItemId(item.as_u64()?));
+    }
+    return Ok(roots);
+}
+
+/// Reads a file of one neopack List of `T`s, or none if it is missing.
+fn read_list_file<T: Pack>(path: &Path, artifact: Artifact) -> Result<Vec<T>, IsoCoreError> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let (_, body) = artifact.read_header(&bytes)?;
+    let mut dec = Decoder::new(body);
+    let mut list = dec.list()?;
+    let mut items = Vec::new();
+    while let Some(value) = list.next()? {
+        items.push(T::read(value)?);
+    }
+    return Ok(items);
+}
+
+/// Writes `items` as one neopack List, via a temporary file.
+fn write_list_file<T: Pack>(path: &Path, artifact: Artifact, items: &[T], durability: Durability) -> Result<(), IsoCoreError> {
+    let mut enc = Encoder::new();
+    let mut list = enc.list()?;
+    for item in items {
+        item.write(&mut list)?;
+    }
+    list.finish()?;
+    write_atomic(path, &artifact.with_header(enc.as_bytes()), durability)?;
+    return Ok(());
+}
+
+/// Reads a pending intent marker as (committed, target) lengths.
+fn read_intent(path: &Path) -> Result<Option<(u64, u64)>, IsoCoreError> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let (_, body) = Artifact::Intent.read_header(&bytes)?;
+    let mut dec = Decoder::new(body);
+    let mut map = dec.map()?;
+    let Some(("committed", committed)) = map.next()? else {
+        return Err(IsoCoreError::NodeFormat);
+    };
+    let Some(("target", target)) = map.next()? else {
+        return Err(IsoCoreError::NodeFormat);
+    };
+    return Ok(Some((committed.as_u64()?, target.as_u64()?)));
+}
+
+fn parse_child_line(line: &str) -> Result<NodeChild, IsoCoreError> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 3 && !(parts.len() == 4 && parts[0] == "leaf") {
+        return Err(IsoCoreError::NodeFormat);
+    }
+
+    let node_type = match parts[0] {
+        "leaf" => NodeType::Leaf,
+        "branch" => NodeType::Branch,
+        _ => return Err(IsoCoreError::NodeType),
+    };
+
+    let hash = Hash::from_hex(parts[1]).map_err(|_| IsoCoreError::HexEncoding)?;
+    let index_str = parts[2].trim_end_matches(".bin");
+    let index_num = u16::from_str_radix(index_str, 16)
+        .map_err(IsoCoreError::MessageIdParse)?;
+    let index = MessageId(index_num);
+    let data = match parts.get(3) {
+        Some(data) => Some(hex::decode(data).map_err(|_| IsoCoreError::HexEncoding)?),
+        None => None,
+    };
+
+    return Ok(NodeChild {
+        node_type,
+        hash,
+        index,
+        data,
+    });
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+    use crate::platform;
+    use crate::key::hash;
+    use crate::format::HEADER_LEN;
+
+    #[test]
+    fn isocore_create_and_add_message() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+
+        let msg1 = b"hello world";
+        let hash1 = isocore.add_message(msg1, &signer).unwrap();
+
+        assert_eq!(isocore.len().0, 1);
+
+        let retrieved = isocore.get_message(ItemId(0)).unwrap();
+        assert_eq!(&*retrieved, msg1);
+
+        // Verify global root is stored in sig_core
+        let cores = isocore.cores();
+        assert_eq!(cores.sig.len().0, 1);
+        let cached: Vec<_> = cores.data.iter_cached().collect();
+        assert_eq!(cached, vec![(crate::core::MessageId(0), &msg1[..])]);
+        assert_eq!(isocore.signer(), &signer.key_pub);
+        assert!(isocore.path().is_none());
+        
+        // The global root should be deterministic for the same message
+        assert!(!hash1.0.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn isocore_multiple_messages() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+
+        let messages = vec![
+            b"message 1",
+            b"message 2",
+            b"message 3",
+            b"message 4",
+        ];
+
+        for msg in &messages {
+            isocore.add_message(*msg, &signer).unwrap();
+        }
+
+        assert_eq!(isocore.len().0, 4);
 
-        assert_eq!(isocore.len().0, 4);
-
         for (i, msg) in messages.iter().enumerate() {
             let retrieved = isocore.get_message(ItemId(i as u64)).unwrap();
-            assert_eq!(retrieved, *msg);
+            assert_eq!(&*retrieved, *msg);
+        }
+    }
+
+    #[test]
+    fn isocore_lazy_loading() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+
+        isocore.add_message(b"test message", &signer).unwrap();
+        isocore.add_message(b"another message", &signer).unwrap();
+
+        let msg = isocore.get_message(ItemId(0)).unwrap();
+        assert_eq!(&*msg, b"test message");
+
+        let msg = isocore.get_message(ItemId(1)).unwrap();
+        assert_eq!(&*msg, b"another message");
+    }
+
+    #[test]
+    fn isocore_empty_len() {
+        let signer = KeyPair::ephemeral();
+        let isocore = IsoCore::create_mem(&signer);
+        assert_eq!(isocore.len().0, 0);
+    }
+
+    #[test]
+    fn isocore_verify_head() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+
+        assert!(isocore.verify_head().is_err());
+
+        let mut last = None;
+        for i in 0..10 {
+            last = Some(isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap());
+        }
+        assert_eq!(isocore.verify_head().unwrap(), last.unwrap());
+
+        // A head signed by someone else must not verify
+        isocore.signers = Signers::single(KeyPair::ephemeral().key_pub);
+        assert!(matches!(isocore.verify_head(), Err(IsoCoreError::IntegrityError)));
+    }
+
+    #[test]
+    fn open_creates_loads_and_reports() {
+        let path = PathBuf::from("/tmp/test_isocore_open/nested/core");
+        let _ = std::fs::remove_dir_all("/tmp/test_isocore_open");
+        let signer = KeyPair::ephemeral();
+
+        let (mut isocore, report) = IsoCore::open(path.clone(), &signer).unwrap();
+        assert!(report.created);
+        assert_eq!(report.found, CoreLengths { data: 0, verkle: 0, sig: 0 });
+        for i in 0..5 {
+            isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+        isocore.flush().unwrap();
+        let lengths = isocore.lengths();
+        assert!(lengths.consistent());
+
+        // A torn append shows up in the report and is cut back
+        isocore.add_message(b"message 5", &signer).unwrap();
+        isocore.data_core.flush().unwrap();
+        drop(isocore);
+
+        let (mut isocore, report) = IsoCore::open(path.clone(), &signer).unwrap();
+        assert!(!report.created);
+        assert_eq!(report.found.data, 6);
+        assert_eq!(report.found.items(), 5);
+        assert!(!report.found.consistent());
+        assert_eq!(report.recovered, lengths);
+        isocore.verify_head().unwrap();
+        drop(isocore);
+
+        let other = KeyPair::ephemeral();
+        assert!(matches!(IsoCore::open(path.clone(), &other), Err(IsoCoreError::SignerMismatch)));
+
+        let files = CoreFiles::new(&path);
+        std::fs::remove_file(&files.sig).unwrap();
+        assert!(matches!(
+            IsoCore::open(path.clone(), &signer),
+            Err(IsoCoreError::Layout(LayoutError::Missing(file))) if file == files.sig
+        ));
+
+        // Core files without info.nd are never overwritten by a new core
+        std::fs::remove_file(&files.info).unwrap();
+        assert!(matches!(
+            IsoCore::open(path.clone(), &signer),
+            Err(IsoCoreError::Layout(LayoutError::Missing(file))) if file == files.info
+        ));
+
+        assert!(matches!(
+            IsoCore::open(files.data.clone(), &signer),
+            Err(IsoCoreError::Layout(LayoutError::NotADirectory(_)))
+        ));
+
+        std::fs::remove_dir_all("/tmp/test_isocore_open").unwrap();
+    }
+
+    #[test]
+    fn light_core_takes_hashes_and_hydrates() {
+        let signer = KeyPair::ephemeral();
+        let source_path = PathBuf::from("/tmp/test_isocore_light_source");
+        let _ = std::fs::remove_dir_all(&source_path);
+        let mut source = IsoCore::create(source_path.clone(), &signer).unwrap();
+        source.add_messages(["first", "second", "third"], &signer).unwrap();
+        let root = source.verify_head().unwrap();
+
+        let path = PathBuf::from("/tmp/test_isocore_light");
+        let _ = std::fs::remove_dir_all(&path);
+        let mut light = IsoCore::create_light(path.clone(), &signer.key_pub, source.info_bytes().unwrap()).unwrap();
+        for entry in source.page(0, 3, PageDirection::OldestFirst).unwrap() {
+            let block = source.get_signature(entry.item_id).unwrap();
+            light.add_signed_hash(&entry.hash, block.signature().unwrap()).unwrap();
         }
+        assert!(light.is_light());
+        assert_eq!(light.verify_head().unwrap(), root);
+        assert!(light.audit(|_| {}).is_ok());
+        assert!(!path.join(FILE_DATA).exists());
+
+        let hash = source.version().hash_leaf(b"second");
+        assert!(matches!(
+            light.get_message(ItemId(1)),
+            Err(IsoCoreError::NeedsData { item_id: ItemId(1), hash: h }) if h == hash
+        ));
+        assert!(matches!(light.hydrate(ItemId(1), b"forged"), Err(IsoCoreError::IntegrityError)));
+        light.hydrate(ItemId(1), b"second").unwrap();
+        assert_eq!(&*light.get_message(ItemId(1)).unwrap(), b"second");
+        assert!(light.page(0, 3, PageDirection::OldestFirst).is_err());
+        light.flush().unwrap();
+        drop(light);
+
+        // Hydrated payloads outlive the process; the rest are still missing
+        let (mut light, report) = IsoCore::open(path.clone(), &signer).unwrap();
+        assert!(light.is_light() && !report.created);
+        assert_eq!(report.recovered.items(), 3);
+        assert_eq!(&*light.get_message(ItemId(1)).unwrap(), b"second");
+        assert!(matches!(light.get_message(ItemId(2)), Err(IsoCoreError::NeedsData { .. })));
+
+        // Appending with the payload hydrates as it goes
+        light.add_message(b"fourth", &signer).unwrap();
+        assert_eq!(&*light.get_message(ItemId(3)).unwrap(), b"fourth");
+
+        // A full core can't take an item without its payload
+        let block = source.get_signature(ItemId(0)).unwrap();
+        let mut full = IsoCore::replica_mem(&signer.key_pub, source.version());
+        assert!(matches!(
+            full.add_signed_hash(&source.version().hash_leaf(b"first"), block.signature().unwrap()),
+            Err(IsoCoreError::NeedsData { item_id: ItemId(0), .. })
+        ));
+
+        std::fs::remove_dir_all(&path).unwrap();
+        std::fs::remove_dir_all(&source_path).unwrap();
     }
 
     #[test]
-    fn isocore_lazy_loading() {
+    fn debug_tree_shows_stored_hashes() {
         let signer = KeyPair::ephemeral();
         let mut isocore = IsoCore::create_mem(&signer);
+        isocore.add_messages(["a", "b", "c"], &signer).unwrap();
 
-        isocore.add_message(b"test message", &signer).unwrap();
-        isocore.add_message(b"another message", &signer).unwrap();
+        let dump = isocore.debug_tree();
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 1 + get_peaks(3, WIDTH).len());
+        let leaf = isocore.get_node(CoveringId(1)).unwrap().compute_hash(isocore.version());
+        assert_eq!(lines[2], format!("1 h0 item 1 peak {}", leaf.to_hex()));
+    }
 
-        let msg = isocore.get_message(ItemId(0)).unwrap();
-        assert_eq!(msg, b"test message");
+    #[test]
+    fn iter_verified_walks_and_resumes() {
+        let signer = KeyPair::ephemeral();
+        let mut source = IsoCore::create_mem(&signer);
+        source.add_messages(["a", "b", "c", "d"], &signer).unwrap();
 
-        let msg = isocore.get_message(ItemId(1)).unwrap();
-        assert_eq!(msg, b"another message");
+        let mut items = source.iter_verified(ItemId(0));
+        let mut seen = Vec::new();
+        while let Some(item) = items.next_item() {
+            let (item_id, hash, data) = item.unwrap();
+            assert_eq!(hash, FormatVersion::CURRENT.hash_leaf(&data));
+            seen.push((item_id, data.to_vec()));
+        }
+        assert_eq!(seen.len(), 4);
+        assert_eq!(seen[3], (ItemId(3), b"d".to_vec()));
+        assert_eq!(items.checkpoint(), ItemId(4));
+
+        // A light core stops at the first payload it lacks
+        let mut light = IsoCore::light_mem(&signer.key_pub, source.version());
+        for n in 0..4 {
+            let entry = source.page(n, 1, PageDirection::OldestFirst).unwrap().remove(0);
+            light.add_signed_hash(&entry.hash, source.get_signature(ItemId(n)).unwrap().signature().unwrap()).unwrap();
+            if n != 2 {
+                light.hydrate(ItemId(n), &entry.data).unwrap();
+            }
+        }
+        let mut items = light.iter_verified(ItemId(1));
+        assert_eq!(&*items.next_item().unwrap().unwrap().2, b"b");
+        assert!(matches!(items.next_item(), Some(Err(IsoCoreError::NeedsData { item_id: ItemId(2), .. }))));
+        assert!(items.next_item().is_none());
+        let checkpoint = items.checkpoint();
+        assert_eq!(checkpoint, ItemId(2));
+
+        light.hydrate(ItemId(2), b"c").unwrap();
+        let mut items = light.iter_verified(checkpoint);
+        assert_eq!(&*items.next_item().unwrap().unwrap().2, b"c");
+        assert_eq!(&*items.next_item().unwrap().unwrap().2, b"d");
+        assert!(items.next_item().is_none());
     }
 
     #[test]
-    fn isocore_empty_len() {
+    fn verifies_from_recorded_checkpoints() {
+        let path = PathBuf::from("/tmp/test_isocore_checkpoints");
+        let _ = std::fs::remove_dir_all(&path);
         let signer = KeyPair::ephemeral();
-        let isocore = IsoCore::create_mem(&signer);
-        assert_eq!(isocore.len().0, 0);
+
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        isocore.set_checkpoint_interval(10);
+        for i in 0..35 {
+            isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+        isocore.flush().unwrap();
+        drop(isocore);
+
+        let mut isocore = IsoCore::load(&path).unwrap();
+        let lens: Vec<u64> = isocore.checkpoints().iter().map(|cp| cp.len).collect();
+        assert_eq!(lens, [10, 20, 30]);
+        let head = isocore.verify_head().unwrap();
+        for checkpoint in isocore.checkpoints().to_vec() {
+            assert!(checkpoint.verify(&signer.key_pub));
+            assert_eq!(isocore.verify_from_checkpoint(&checkpoint).unwrap(), head);
+        }
+        let latest = isocore.checkpoint().unwrap();
+        assert_eq!(latest.len, 35);
+        assert_eq!(Checkpoint::decode(&latest.encode().unwrap()).unwrap(), latest);
+        assert_eq!(isocore.verify_from_checkpoint(&latest).unwrap(), head);
+
+        // A checkpoint the core doesn't agree with, or that isn't signed
+        let mut other = isocore.checkpoints()[1].clone();
+        other.len = 21;
+        assert!(matches!(isocore.verify_from_checkpoint(&other), Err(IsoCoreError::IntegrityError)));
+        let mut forged = isocore.checkpoints()[1].clone();
+        forged.signature.0[0] ^= 1;
+        assert!(matches!(isocore.verify_from_checkpoint(&forged), Err(IsoCoreError::IntegrityError)));
+
+        // Damage after the checkpoint is found; damage before it is trusted
+        let first = isocore.checkpoints()[0].clone();
+        let second = isocore.checkpoints()[1].clone();
+        let mut data_core = Core::create_mem();
+        for i in 0..35 {
+            let message = if i == 15 { "tampered".to_string() } else { format!("message {}", i) };
+            data_core.add_message(message.as_bytes()).unwrap();
+        }
+        isocore.data_core = data_core;
+        assert!(matches!(isocore.verify_from_checkpoint(&first), Err(IsoCoreError::IntegrityError)));
+        assert_eq!(isocore.verify_from_checkpoint(&second).unwrap(), head);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn gc_removes_what_a_partial_append_left() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        isocore.add_messages(["a", "b", "c", "d", "e"], &signer).unwrap();
+        let head = isocore.verify_head().unwrap();
+
+        let clean = isocore.gc().unwrap();
+        assert_eq!(clean.reachable, isocore.cores().verkle.len().0 as u64);
+        assert_eq!((clean.unreachable, clean.removed_nodes, clean.reclaimed_bytes), (0, 0, 0));
+
+        // The next append got as far as its message and leaf node
+        let leaf = isocore.get_node(CoveringId(0)).unwrap().to_bytes(isocore.version());
+        isocore.data_core.add_message(b"torn").unwrap();
+        isocore.verkle_core.add_message(&leaf).unwrap();
+
+        let report = isocore.gc().unwrap();
+        assert_eq!(report.unreachable, 1);
+        assert_eq!((report.removed_nodes, report.removed_signatures, report.removed_messages), (1, 0, 1));
+        assert_eq!(report.reclaimed_bytes, (leaf.len() + 4) as u64);
+        assert_eq!(isocore.len().0, 5);
+        assert_eq!(isocore.verify_head().unwrap(), head);
+
+        isocore.add_message(b"f", &signer).unwrap();
+        assert!(isocore.audit(|_| {}).is_ok());
+    }
+
+    #[test]
+    fn isocore_recovers_torn_flush() {
+        let path = PathBuf::from("/tmp/test_isocore_torn_flush");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        for i in 0..3 {
+            isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+        isocore.flush().unwrap();
+
+        // Crash after only the data core reached disk
+        for i in 3..10 {
+            isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+        isocore.data_core.flush().unwrap();
+        drop(isocore);
+
+        let mut isocore = IsoCore::load(&path).unwrap();
+        assert_eq!(isocore.len().0, 3);
+        assert_eq!(isocore.sig_core.len().0, 3);
+        isocore.verify_head().unwrap();
+
+        // Appends continue cleanly from the recovered length
+        isocore.add_message(b"message 3", &signer).unwrap();
+        isocore.flush().unwrap();
+        drop(isocore);
+
+        let mut isocore = IsoCore::load(&path).unwrap();
+        assert_eq!(isocore.len().0, 4);
+        isocore.verify_head().unwrap();
+        drop(isocore);
+
+        // Losing committed entries is corruption, not a torn flush
+        let mut enc = Encoder::new();
+        let mut map = enc.map().unwrap();
+        map.key("committed").unwrap().u64(8).unwrap();
+        map.key("target").unwrap().u64(9).unwrap();
+        map.finish().unwrap();
+        std::fs::write(path.join(FILE_INTENT), enc.as_bytes()).unwrap();
+        assert!(matches!(IsoCore::load(&path), Err(IsoCoreError::IntegrityError)));
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn isocore_repairs_torn_logs_when_asked() {
+        let path = PathBuf::from("/tmp/test_isocore_repair");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        for i in 0..5 {
+            isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+        isocore.flush().unwrap();
+        let files = isocore.files().unwrap();
+        drop(isocore);
+        assert_eq!(IsoCore::repair(&path).unwrap(), 0);
+
+        // A crash a few bytes into writing a frame over the sig log's footer
+        let mut torn = std::fs::read(&files.sig).unwrap();
+        let len = torn.len() - 16;
+        torn.truncate(len);
+        torn.extend_from_slice(&[0x42; 5]);
+        std::fs::write(&files.sig, &torn).unwrap();
+
+        assert!(matches!(IsoCore::load(&path), Err(IsoCoreError::Core(CoreError::NeoDisk(_)))));
+        assert_eq!(IsoCore::repair(&path).unwrap(), 5);
+        let mut isocore = IsoCore::load(&path).unwrap();
+        assert_eq!(isocore.len().0, 5);
+        isocore.verify_head().unwrap();
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn isocore_audit() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        for i in 0..20 {
+            isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+
+        let mut checked = 0;
+        let report = isocore.audit(|event| {
+            if let AuditEvent::Checked { .. } = event {
+                checked += 1;
+            }
+        });
+        assert!(report.is_ok());
+        assert_eq!(report.items, 20);
+        assert_eq!(checked, 20);
+
+        // Swap in cores with one tampered message and one forged signature
+        let mut data_core = Core::create_mem();
+        let mut sig_core = Core::create_mem();
+        for i in 0..20 {
+            let message = if i == 5 { "tampered".to_string() } else { format!("message {}", i) };
+            data_core.add_message(message.as_bytes()).unwrap();
+
+            let bytes = isocore.sig_core.get_contents(MessageId(i)).unwrap();
+            let mut block = SignatureBlock::from_bytes(&bytes).unwrap();
+            if i == 12 {
+                block.signatures[0].1.0[0] ^= 1;
+            }
+            sig_core.add_message(&block.to_bytes()).unwrap();
+        }
+        isocore.data_core = data_core;
+        isocore.sig_core = sig_core;
+
+        let mut failures = Vec::new();
+        let report = isocore.audit(|event| {
+            if let AuditEvent::Failure { category, index } = event {
+                failures.push((category, index));
+            }
+        });
+        assert!(!report.is_ok());
+        assert_eq!(report.data, AuditCount { errors: 1, first: Some(5) });
+        assert_eq!(report.signatures, AuditCount { errors: 1, first: Some(12) });
+        assert_eq!(report.nodes.errors, 0);
+        assert_eq!(report.roots.errors, 0);
+        assert_eq!(failures, vec![(AuditCategory::Data, 5), (AuditCategory::Signature, 12)]);
+    }
+
+    #[test]
+    fn verify_signature_checks_each_block() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        let mut roots = Vec::new();
+        for i in 0..12 {
+            roots.push(isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap());
+        }
+        for (i, root) in roots.iter().enumerate() {
+            assert_eq!(isocore.get_signature(ItemId(i as u64)).unwrap().global_root, *root);
+            assert_eq!(isocore.verify_signature(ItemId(i as u64)).unwrap(), *root);
+        }
+        assert!(matches!(isocore.get_signature(ItemId(12)), Err(IsoCoreError::Core(CoreError::FutureMessage))));
+
+        // Forge one signature and swap another block's root for a later one
+        let mut sig_core = Core::create_mem();
+        for i in 0..12 {
+            let mut block = isocore.get_signature(ItemId(i)).unwrap();
+            match i {
+                3 => block.signatures[0].1.0[0] ^= 1,
+                7 => block = isocore.get_signature(ItemId(8)).unwrap(),
+                _ => {}
+            }
+            sig_core.add_message(&block.to_bytes()).unwrap();
+        }
+        isocore.sig_core = sig_core;
+        for i in 0..12 {
+            let result = isocore.verify_signature(ItemId(i));
+            match i {
+                3 | 7 => assert!(matches!(result, Err(IsoCoreError::IntegrityError)), "item {i}"),
+                _ => assert_eq!(result.unwrap(), roots[i as usize]),
+            }
+        }
+        isocore.verify_head().unwrap();
+    }
+
+    #[test]
+    fn reads_version_1_signature_blocks() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        for i in 0..10 {
+            isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+
+        // A core written before blocks were neopack, then appended to since
+        let mut sig_core = Core::create_mem();
+        for i in 0..10 {
+            let bytes = isocore.sig_core.get_contents(MessageId(i)).unwrap();
+            assert_eq!(bytes[0], crate::neopack::spec::TAG_LIST);
+            let block = SignatureBlock::from_bytes(&bytes).unwrap();
+            let bytes = if i < 6 { block.to_v1_bytes().unwrap() } else { block.to_bytes() };
+            assert_eq!(SignatureBlock::from_bytes(&bytes).unwrap().signature().unwrap(), block.signature().unwrap());
+            sig_core.add_message(&bytes).unwrap();
+        }
+        isocore.sig_core = sig_core;
+        assert!(isocore.audit(|_| {}).is_ok());
+        isocore.verify_head().unwrap();
+        isocore.add_message(b"message 10", &signer).unwrap();
+        isocore.verify_head().unwrap();
+
+        let mut block = isocore.get_signature(ItemId(10)).unwrap().to_bytes();
+        // After the List header and the version's U8 tag
+        block[crate::neopack::spec::BLOB_HEADER + 1] = 4;
+        assert_eq!(SignatureBlock::from_bytes(&block).unwrap_err(), FormatError::UnsupportedVersion { found: 4, expected: 3 });
+        assert_eq!(SignatureBlock::from_bytes(&block[..40]).unwrap_err(), FormatError::SignatureBlock);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn isocore_audit_parallel_matches() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        for i in 0..100 {
+            isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+
+        // Forge one signature so both audits have something to find
+        let mut sig_core = Core::create_mem();
+        for i in 0..100 {
+            let mut block = SignatureBlock::from_bytes(&isocore.sig_core.get_contents(MessageId(i)).unwrap()).unwrap();
+            if i == 70 {
+                block.signatures[0].1.0[0] ^= 1;
+            }
+            sig_core.add_message(&block.to_bytes()).unwrap();
+        }
+        isocore.sig_core = sig_core;
+
+        let mut events = Vec::new();
+        let sequential = isocore.audit(|event| events.push(event));
+
+        // Small batches so peaks carry across batch boundaries
+        let mut parallel_events = Vec::new();
+        let parallel = isocore.audit_batched(7, |event| parallel_events.push(event));
+
+        assert_eq!(sequential, parallel);
+        assert_eq!(events, parallel_events);
+        assert_eq!(parallel.signatures, AuditCount { errors: 1, first: Some(70) });
+        assert_eq!(parallel.data.errors + parallel.nodes.errors + parallel.roots.errors, 0);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn isocore_prove_batch_matches_prove() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        let messages: Vec<String> = (0..300).map(|i| format!("message {}", i)).collect();
+        isocore.add_messages(&messages, &signer).unwrap();
+
+        let items: Vec<ItemId> = [0, 299, 17, 64, 17, 250].into_iter().map(ItemId).collect();
+        let proofs = isocore.prove_batch(&items, 300).unwrap();
+        assert_eq!(proofs.len(), items.len());
+        for (item_id, proof) in items.iter().zip(&proofs) {
+            assert_eq!(proof, &isocore.prove(*item_id, 300).unwrap());
+            assert_eq!(proof.verify(&signer.key_pub).unwrap(), isocore.get_signature(ItemId(299)).unwrap().global_root);
+        }
+        assert!(matches!(isocore.prove_batch(&items, 100), Err(IsoCoreError::Core(CoreError::FutureMessage))));
+        assert!(isocore.prove_batch(&[], 300).unwrap().is_empty());
+    }
+
+    #[test]
+    fn v1_node_hash_is_hash_of_concatenated_children() {
+        let children: Vec<NodeChild> = (0..8u16).map(|i| NodeChild {
+            node_type: NodeType::Branch,
+            hash: hash(&i.to_le_bytes()),
+            index: MessageId(i),
+            data: None,
+        }).collect();
+
+        let mut concatenated = Vec::new();
+        for child in &children {
+            concatenated.extend_from_slice(&child.hash.0);
+        }
+        assert_eq!(VerkleNode { children }.compute_hash(FormatVersion::V1), hash(&concatenated));
+    }
+
+    #[test]
+    fn isocore_reads_both_versions() {
+        let path = PathBuf::from("/tmp/test_isocore_versions");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+
+        // Same messages, same key, different hash domains
+        let mut v2 = IsoCore::create_mem(&signer);
+        let mut v1 = IsoCore::create(path.clone(), &signer).unwrap();
+        v1.version = FormatVersion::V1;
+        for i in 0..10 {
+            let message = format!("message {}", i);
+            v1.add_message(message.as_bytes(), &signer).unwrap();
+            v2.add_message(message.as_bytes(), &signer).unwrap();
+        }
+        assert_ne!(v1.verify_head().unwrap(), v2.verify_head().unwrap());
+        v1.flush().unwrap();
+        drop(v1);
+
+        // An older core records version 1 in its info file
+        let mut enc = Encoder::new();
+        let mut map = enc.map().unwrap();
+        map.key("version").unwrap().u8(1).unwrap();
+        map.key("signer").unwrap().bytes(&signer.key_pub.0).unwrap();
+        map.finish().unwrap();
+        std::fs::write(path.join(INFO_ISOCORE), enc.as_bytes()).unwrap();
+
+        // Which is unsealed, so it loads for reading only until resealed
+        let mut v1 = IsoCore::load(&path).unwrap();
+        assert!(!v1.is_sealed());
+        assert_eq!(v1.version, FormatVersion::V1);
+        v1.verify_head().unwrap();
+        assert!(v1.audit(|_| {}).is_ok());
+        assert_eq!(&*v1.get_message(ItemId(3)).unwrap(), b"message 3");
+        assert!(matches!(v1.add_message(b"message 4", &signer), Err(IsoCoreError::UnsealedInfo)));
+        drop(v1);
+
+        assert!(matches!(IsoCore::reseal(&path, &[&KeyPair::ephemeral()]), Err(IsoCoreError::SignerMismatch)));
+        IsoCore::reseal(&path, &[&signer]).unwrap();
+        let mut v1 = IsoCore::load(&path).unwrap();
+        assert!(v1.is_sealed());
+        assert_eq!(v1.version, FormatVersion::V1);
+        v1.add_message(b"message 4", &signer).unwrap();
+
+        std::fs::remove_dir_all(&path).unwrap();
+        assert!(matches!(FormatVersion::from_u8(4), Err(FormatError::UnsupportedVersion { found: 4, expected: 3 })));
+    }
+
+    #[test]
+    fn isocore_checks_file_headers() {
+        let path = PathBuf::from("/tmp/test_isocore_headers");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        isocore.add_message(b"one", &signer).unwrap();
+        isocore.flush().unwrap();
+        drop(isocore);
+
+        let info = std::fs::read(path.join(INFO_ISOCORE)).unwrap();
+        assert_eq!(&info[..HEADER_LEN], Artifact::Info.header());
+        let roots = std::fs::read(path.join(FILE_ROOTS)).unwrap();
+        assert_eq!(&roots[..HEADER_LEN], Artifact::Roots.header());
+
+        // A newer build's info.nd is refused, saying what was found
+        let mut future = info.clone();
+        future[4] = 9;
+        std::fs::write(path.join(INFO_ISOCORE), &future).unwrap();
+        assert!(matches!(IsoCore::load(&path), Err(IsoCoreError::UnsupportedVersion { found: 9, expected: 3 })));
+
+        // As is another artifact in its place
+        std::fs::write(path.join(INFO_ISOCORE), Artifact::Roots.with_header(&info[HEADER_LEN..])).unwrap();
+        assert!(matches!(IsoCore::load(&path), Err(IsoCoreError::BadMagic(Artifact::Info))));
+
+        // And a header of a kind that never had headerless files
+        assert_eq!(Artifact::Checkpoints.read_header(&info[HEADER_LEN..]), Err(FormatError::BadMagic(Artifact::Checkpoints)));
+
+        std::fs::write(path.join(INFO_ISOCORE), &info).unwrap();
+        assert_eq!(&*IsoCore::load(&path).unwrap().get_message(ItemId(0)).unwrap(), b"one");
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn isocore_seals_info() {
+        let path = PathBuf::from("/tmp/test_isocore_seals_info");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+        let isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        let created_at = isocore.created_at().unwrap();
+        drop(isocore);
+
+        let info = std::fs::read(path.join(INFO_ISOCORE)).unwrap();
+        assert_eq!(CoreInfo::from_bytes(&info).unwrap().created_at, Some(created_at));
+        assert_eq!(IsoCore::load(&path).unwrap().created_at(), Some(created_at));
+
+        // Any flipped bit in the fields, the signer's included, is caught
+        let signer_at = info.windows(32).position(|w| w == signer.key_pub.0).unwrap();
+        for at in [signer_at, signer_at + 31, info.len() - 1] {
+            let mut flipped = info.clone();
+            flipped[at] ^= 1;
+            std::fs::write(path.join(INFO_ISOCORE), &flipped).unwrap();
+            assert!(matches!(IsoCore::load(&path), Err(IsoCoreError::BadInfo)), "{}", at);
+        }
+
+        // Only the signer's key can seal a core's info
+        let replica = CoreInfo {
+            version: FormatVersion::CURRENT,
+            signers: Signers::single(signer.key_pub.clone()),
+            width: WIDTH,
+            created_at: Some(created_at),
+            light: true,
+            inline_limit: 0,
+        };
+        let bytes = replica.to_bytes(&[&signer]).unwrap();
+        assert_eq!(CoreInfo::from_bytes(&bytes).unwrap(), replica);
+        assert!(matches!(replica.to_bytes(&[&KeyPair::ephemeral()]), Err(IsoCoreError::SignerMismatch)));
+
+        // A hash seal can be recomputed by anyone, for any signer, so such a
+        // core only loads for reading, and a replica won't copy it
+        let other = KeyPair::ephemeral();
+        let swapped = CoreInfo { signers: Signers::single(other.key_pub.clone()), ..replica.clone() };
+        let mut enc = Encoder::new();
+        let mut map = enc.map().unwrap();
+        swapped.write_fields(&mut map).unwrap();
+        hash(&swapped.sealed_bytes().unwrap()).write(&mut map.key("hash").unwrap()).unwrap();
+        map.finish().unwrap();
+        let hashed = Artifact::Info.with_header(enc.as_bytes());
+        assert!(matches!(CoreInfo::from_bytes(&hashed), Err(IsoCoreError::UnsealedInfo)));
+        let copy = PathBuf::from("/tmp/test_isocore_seals_info_copy");
+        assert!(matches!(IsoCore::create_replica(copy, &other.key_pub, &hashed), Err(IsoCoreError::UnsealedInfo)));
+        std::fs::write(path.join(INFO_ISOCORE), &hashed).unwrap();
+        let mut loaded = IsoCore::load(&path).unwrap();
+        assert!(!loaded.is_sealed());
+        assert!(matches!(loaded.add_message(b"no", &other), Err(IsoCoreError::UnsealedInfo)));
+        drop(loaded);
+        assert!(matches!(IsoCore::reseal(&path, &[&signer]), Err(IsoCoreError::SignerMismatch)));
+        IsoCore::reseal(&path, &[&other]).unwrap();
+        let loaded = IsoCore::load(&path).unwrap();
+        assert!(loaded.is_sealed());
+        assert_eq!(loaded.signer(), &other.key_pub);
+
+        // Rewriting a sealed file with a hash seal or an older header
+        // downgrades it to read-only, but never lets it take appends
+        let sealed = std::fs::read(path.join(INFO_ISOCORE)).unwrap();
+        let (info, _) = CoreInfo::read(&sealed).unwrap();
+        let mut enc = Encoder::new();
+        let mut map = enc.map().unwrap();
+        info.write_fields(&mut map).unwrap();
+        hash(&info.sealed_bytes().unwrap()).write(&mut map.key("hash").unwrap()).unwrap();
+        map.finish().unwrap();
+        let mut enc_old = Encoder::new();
+        let mut map_old = enc_old.map().unwrap();
+        map_old.key("version").unwrap().u8(info.version as u8).unwrap();
+        map_old.key("signer").unwrap().bytes(&other.key_pub.0).unwrap();
+        map_old.finish().unwrap();
+        for downgraded in [Artifact::Info.with_header(enc.as_bytes()), enc_old.as_bytes().to_vec()] {
+            std::fs::write(path.join(INFO_ISOCORE), &downgraded).unwrap();
+            let mut loaded = IsoCore::load(&path).unwrap();
+            assert!(!loaded.is_sealed());
+            assert_eq!(loaded.signer(), &other.key_pub);
+            assert!(matches!(loaded.add_message(b"no", &other), Err(IsoCoreError::UnsealedInfo)));
+        }
+        std::fs::write(path.join(INFO_ISOCORE), &sealed).unwrap();
+        assert!(IsoCore::load(&path).unwrap().is_sealed());
+
+        // A core from a build with another tree width is refused
+        let wide = CoreInfo { width: WIDTH * 2, ..replica };
+        assert!(matches!(CoreInfo::from_bytes(&wide.to_bytes(&[&signer]).unwrap()), Err(IsoCoreError::BadInfo)));
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn threshold_core_needs_enough_signers() {
+        let path = PathBuf::from("/tmp/test_isocore_threshold");
+        let _ = std::fs::remove_dir_all(&path);
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::ephemeral()).collect();
+        let signers = Signers::new(keys.iter().map(|key| key.key_pub.clone()).collect(), 2).unwrap();
+        assert!(Signers::new(vec![keys[0].key_pub.clone(); 2], 1).is_err());
+        assert!(Signers::new(signers.keys().to_vec(), 4).is_err());
+
+        assert!(matches!(IsoCore::create_threshold(path.clone(), signers.clone(), &[&keys[0]]), Err(IsoCoreError::BelowThreshold)));
+        let mut isocore = IsoCore::create_threshold(path.clone(), signers.clone(), &[&keys[0], &keys[1]]).unwrap();
+        assert_eq!(isocore.signer(), &signers.id());
+        isocore.add_message_by(b"one", &[&keys[0], &keys[2]]).unwrap();
+        let root = isocore.add_message_by(b"two", &[&keys[1], &keys[0]]).unwrap();
+
+        // One key, even given twice, is not two; nor is an outsider
+        assert!(matches!(isocore.add_message_by(b"no", &[&keys[1], &keys[1]]), Err(IsoCoreError::BelowThreshold)));
+        assert!(matches!(isocore.add_message_by(b"no", &[&keys[0], &KeyPair::ephemeral()]), Err(IsoCoreError::SignerMismatch)));
+        assert!(matches!(isocore.add_message(b"no", &keys[0]), Err(IsoCoreError::SignerMismatch)));
+        assert!(matches!(isocore.prove(ItemId(0), 2), Err(IsoCoreError::ThresholdCore)));
+        isocore.flush().unwrap();
+        drop(isocore);
+
+        let mut isocore = IsoCore::load(&path).unwrap();
+        assert_eq!(isocore.verify_head().unwrap(), root);
+        assert!(isocore.audit(|_| {}).is_ok());
+        assert_eq!(&*isocore.get_message(ItemId(1)).unwrap(), b"two");
+
+        // info.nd needs the threshold's signatures too, not just a hash
+        let info = std::fs::read(path.join(INFO_ISOCORE)).unwrap();
+        let mut forged = CoreInfo::from_bytes(&info).unwrap().to_bytes(&[&keys[0], &keys[2]]).unwrap();
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+        assert!(matches!(CoreInfo::from_bytes(&forged), Err(IsoCoreError::BadInfo)));
+
+        // A block's signatures count once per key, and must all be good
+        let mut block = isocore.get_signature(ItemId(1)).unwrap();
+        assert!(block.signature().is_none());
+        assert_eq!(SignatureBlock::from_bytes(&block.to_bytes()).unwrap().signatures, block.signatures);
+        let first = block.signatures[0].clone();
+        block.signatures[1] = first;
+        assert!(!signers.verify(&block));
+        block.signatures.truncate(1);
+        assert!(!signers.verify(&block));
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn isocore_golden_root() {
+        // Fixed key, fixed messages: the signed head must never drift
+        let signer = KeyPair::from_seed([7; 32]);
+        let mut isocore = IsoCore::create_mem(&signer);
+        for i in 0..10 {
+            isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+
+        let root = isocore.verify_head().unwrap();
+        assert_eq!(root.to_hex(), "76c5e25c10f277113c4ba7174cb5bec6107626ed96a046acc23bdeb3377e59d2");
+
+        // Version 2 roots leave out the length
+        let mut v2 = IsoCore::create_mem(&signer);
+        v2.version = FormatVersion::V2;
+        for i in 0..10 {
+            v2.add_message(format!("message {}", i).as_bytes(), &signer).unwrap();
+        }
+        let root = v2.verify_head().unwrap();
+        assert_eq!(root.to_hex(), "ee930a0f33cf87fb3f387d62dbd4d86765224e59ff9564cb17f811cef4fdeb1b");
+    }
+
+    #[test]
+    fn isocore_find_by_root() {
+        let path = PathBuf::from("/tmp/test_isocore_find_by_root");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        let mut roots = Vec::new();
+        for i in 0..6 {
+            roots.push(isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap());
+        }
+        assert_eq!(isocore.find_by_root(&roots[3]), Some(ItemId(3)));
+        isocore.flush().unwrap();
+
+        // Unflushed items are picked up from sig_core on load
+        for i in 6..9 {
+            roots.push(isocore.add_message(format!("message {}", i).as_bytes(), &signer).unwrap());
+        }
+        isocore.sig_core.flush().unwrap();
+        isocore.verkle_core.flush().unwrap();
+        isocore.data_core.flush().unwrap();
+        drop(isocore);
+
+        let isocore = IsoCore::load(&path).unwrap();
+        for (i, root) in roots.iter().enumerate() {
+            assert_eq!(isocore.find_by_root(root), Some(ItemId(i as u64)));
+        }
+        assert_eq!(isocore.find_by_root(&hash(b"nope")), None);
+        drop(isocore);
+
+        // A damaged index is rebuilt
+        std::fs::write(path.join(FILE_ROOTS), b"garbage").unwrap();
+        let isocore = IsoCore::load(&path).unwrap();
+        assert_eq!(isocore.find_by_root(&roots[8]), Some(ItemId(8)));
+
+        std::fs::remove_dir_all(&path).unwrap();
     }
 
     #[test]
@@ -470,15 +3864,293 @@ mod tests {
                     node_type: NodeType::Leaf,
                     hash: hash(b"test"),
                     index: MessageId(0),
+                    data: None,
                 },
             ],
         };
 
-        let bytes = node.to_bytes();
+        let bytes = node.to_bytes(FormatVersion::CURRENT);
         let parsed = VerkleNode::from_bytes(&bytes).unwrap();
 
         assert_eq!(parsed.children.len(), 1);
         assert_eq!(parsed.children[0].node_type, NodeType::Leaf);
         assert_eq!(parsed.children[0].index, MessageId(0));
     }
+
+    #[test]
+    fn isocore_append_events() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        let events = isocore.events();
+        let dropped = isocore.events();
+        drop(dropped);
+
+        let root = isocore.add_message(b"one", &signer).unwrap();
+        let roots = isocore.add_messages([b"two", b"six", b"ten"], &signer).unwrap();
+        assert_eq!(isocore.subscribers.len(), 1);
+
+        let received: Vec<AppendEvent> = events.try_iter().collect();
+        assert_eq!(received.len(), 4);
+        assert_eq!(received[0].root, root);
+        assert_eq!(received[0].hash, isocore.version.hash_leaf(b"one"));
+        let batch_ends: Vec<bool> = received.iter().map(|e| e.last_in_batch).collect();
+        assert_eq!(batch_ends, vec![true, false, false, true]);
+        for (i, event) in received.iter().enumerate().skip(1) {
+            assert_eq!(event.item_id, ItemId(i as u64));
+            assert_eq!(event.root, roots[i - 1]);
+        }
+
+        // A batch that fails before appending anything sends nothing
+        let other = KeyPair::ephemeral();
+        assert!(isocore.add_messages([b"ok".as_slice()], &other).is_err());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn isocore_page() {
+        let path = PathBuf::from("/tmp/test_isocore_page");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        let mut roots = Vec::new();
+        for i in 0..200 {
+            roots.push(isocore.add_message(format!("post {}", i).as_bytes(), &signer).unwrap());
+        }
+        isocore.flush().unwrap();
+        drop(isocore);
+
+        let mut isocore = IsoCore::load(&path).unwrap();
+        let page = isocore.page(100, 50, PageDirection::OldestFirst).unwrap();
+        assert_eq!(page.len(), 50);
+        assert_eq!(page[0].item_id, ItemId(100));
+        assert_eq!(page[0].data, b"post 100");
+        assert_eq!(page[0].root, roots[100]);
+        assert_eq!(page[0].hash, isocore.version.hash_leaf(b"post 100"));
+
+        let page = isocore.page(0, 3, PageDirection::NewestFirst).unwrap();
+        let ids: Vec<ItemId> = page.iter().map(|entry| entry.item_id).collect();
+        assert_eq!(ids, [ItemId(199), ItemId(198), ItemId(197)]);
+        assert_eq!(page[2].data, b"post 197");
+
+        // Pages are cut short at either end
+        assert_eq!(isocore.page(190, 50, PageDirection::NewestFirst).unwrap().len(), 10);
+        assert_eq!(isocore.page(190, 50, PageDirection::NewestFirst).unwrap()[9].item_id, ItemId(0));
+        assert!(isocore.page(200, 10, PageDirection::OldestFirst).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn isocore_flush_with_each_durability() {
+        let path = PathBuf::from("/tmp/test_isocore_durability");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        assert_eq!(isocore.durability(), Durability::Full);
+        let mut last = None;
+        for (i, durability) in [Durability::Full, Durability::Data, Durability::None].into_iter().enumerate() {
+            isocore.set_durability(durability);
+            last = Some(isocore.add_message(format!("item {}", i).as_bytes(), &signer).unwrap());
+            isocore.flush().unwrap();
+            assert!(!path.join(FILE_INTENT).exists());
+            assert!(!platform::tmp_path(&path.join(FILE_ROOTS)).exists());
+        }
+        drop(isocore);
+
+        let isocore = IsoCore::load(&path).unwrap();
+        assert_eq!(isocore.len(), MessageId(3));
+        assert_eq!(isocore.signer(), &signer.key_pub);
+        assert_eq!(isocore.find_by_root(&last.unwrap()), Some(ItemId(2)));
+        assert!(!platform::tmp_path(&path.join(INFO_ISOCORE)).exists());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn isocore_counts_its_sizes() {
+        let path = PathBuf::from("/tmp/test_isocore_stats");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        isocore.add_messages(["one", "three", "seventeen"], &signer).unwrap();
+        isocore.flush().unwrap();
+
+        let stats = isocore.stats().unwrap();
+        assert_eq!(stats.items, 3);
+        assert_eq!(stats.payload_bytes, 17);
+        assert!(stats.verkle_bytes > 0 && stats.sig_bytes > 0);
+        for file in [INFO_ISOCORE, FILE_DATA, FILE_VERKLE, FILE_SIG] {
+            assert!(stats.disk[file] > 0);
+        }
+        assert!(stats.disk_bytes() >= stats.disk[FILE_DATA] + stats.disk[FILE_VERKLE]);
+        drop(isocore);
+
+        // Counted again from the cores after a load, then kept up to date
+        let mut isocore = IsoCore::load(&path).unwrap();
+        assert_eq!(isocore.stats().unwrap(), stats);
+        isocore.add_message(b"four", &signer).unwrap();
+        let grown = isocore.stats().unwrap();
+        assert_eq!(grown.payload_bytes, 21);
+        isocore.flush().unwrap();
+        drop(isocore);
+        let mut isocore = IsoCore::load(&path).unwrap();
+        let counted = isocore.stats().unwrap();
+        assert_eq!(counted.overhead_bytes(), grown.overhead_bytes());
+        assert_eq!(counted.payload_bytes, 21);
+
+        assert!(IsoCore::create_mem(&signer).stats().unwrap().disk.is_empty());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn isocore_reports_amplification() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        assert_eq!(isocore.amplification_stats().ratio(), None);
+        isocore.add_messages(["one", "three", "seventeen"], &signer).unwrap();
+
+        let stats = isocore.stats().unwrap();
+        let amplification = isocore.amplification_stats();
+        assert_eq!(amplification.appends, 3);
+        assert_eq!((amplification.message_bytes, amplification.data_bytes), (17, 17));
+        assert_eq!((amplification.verkle_bytes, amplification.sig_bytes), (stats.verkle_bytes, stats.sig_bytes));
+        assert_eq!(amplification.verkle_nodes, verkle_len_for_items(3));
+        assert!(amplification.ratio().unwrap() > 1.0);
+        assert_eq!(amplification.bytes_per_append(), Some(amplification.written_bytes() as f64 / 3.0));
+
+        // One workload at a time
+        isocore.reset_amplification_stats();
+        isocore.add_message(b"four", &signer).unwrap();
+        let amplification = isocore.amplification_stats();
+        assert_eq!((amplification.appends, amplification.message_bytes), (1, 4));
+        assert_eq!(amplification.verkle_nodes, verkle_len_for_items(4) - verkle_len_for_items(3));
+    }
+
+    #[test]
+    fn isocore_builds_ingested_tree() {
+        let path = PathBuf::from("/tmp/test_isocore_ingest");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+        let messages: Vec<String> = (0..150).map(|i| format!("message {}", i)).collect();
+        let mut appended = IsoCore::create_mem(&signer);
+        let roots = appended.add_messages(&messages, &signer).unwrap();
+
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        isocore.add_messages(&messages[..10], &signer).unwrap();
+        for (i, message) in messages[10..].iter().enumerate() {
+            assert_eq!(isocore.ingest(message.as_bytes()).unwrap(), ItemId(10 + i as u64));
+        }
+        assert_eq!(isocore.len().0, 10);
+        assert!(matches!(isocore.add_message(b"late", &signer), Err(IsoCoreError::Ingesting)));
+        assert!(matches!(isocore.build_tree(&KeyPair::ephemeral()), Err(IsoCoreError::SignerMismatch)));
+
+        let events = isocore.events();
+        assert_eq!(isocore.build_tree(&signer).unwrap().as_ref(), roots.last());
+        assert_eq!(events.try_iter().filter(|event| event.last_in_batch).count(), 1);
+        assert_eq!(isocore.build_tree(&signer).unwrap(), None);
+        for (i, root) in roots.iter().enumerate() {
+            assert_eq!(isocore.get_signature(ItemId(i as u64)).unwrap().to_bytes(), appended.get_signature(ItemId(i as u64)).unwrap().to_bytes());
+            assert_eq!(isocore.find_by_root(root), Some(ItemId(i as u64)));
+        }
+        assert_eq!(isocore.stats().unwrap().verkle_bytes, appended.stats().unwrap().verkle_bytes);
+        assert_eq!(isocore.amplification_stats(), appended.amplification_stats());
+
+        // Items ingested but never built are dropped on load
+        isocore.ingest(b"unbuilt").unwrap();
+        isocore.flush().unwrap();
+        drop(isocore);
+        let mut isocore = IsoCore::load(&path).unwrap();
+        assert_eq!(isocore.len().0, 150);
+        assert!(isocore.audit(|_| {}).is_ok());
+        assert_eq!(isocore.verify_head().unwrap(), roots[149]);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn isocore_build_tree_keeps_unbuilt_items() {
+        let path = PathBuf::from("/tmp/test_isocore_ingest_failure");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+        let messages: Vec<String> = (0..40).map(|i| format!("message {}", i)).collect();
+        let mut appended = IsoCore::create_mem(&signer);
+        let roots = appended.add_messages(&messages, &signer).unwrap();
+
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        for message in &messages {
+            isocore.ingest(message.as_bytes()).unwrap();
+        }
+        let events = isocore.events();
+
+        // Item 20 fails after its nodes are written
+        FAIL_BUILD_AT.set(Some(20));
+        assert!(matches!(isocore.build_tree(&signer), Err(IsoCoreError::IntegrityError)));
+        FAIL_BUILD_AT.set(None);
+        // What it wrote is cut off, leaving the queued payloads ahead
+        let lengths = isocore.lengths();
+        assert_eq!(isocore.len().0, 20);
+        assert_eq!((lengths.data, lengths.sig), (40, 20));
+        assert_eq!(lengths.verkle, verkle_len_for_items(20));
+        assert_eq!(isocore.verify_head().unwrap(), roots[19]);
+        assert_eq!(events.try_iter().filter(|event| event.last_in_batch).count(), 1);
+        assert!(matches!(isocore.add_message(b"late", &signer), Err(IsoCoreError::Ingesting)));
+
+        // It and the rest are still queued, and build as appends would have
+        assert_eq!(isocore.build_tree(&signer).unwrap().as_ref(), roots.last());
+        assert_eq!(isocore.len().0, 40);
+        assert!(isocore.audit(|_| {}).is_ok());
+        for (i, root) in roots.iter().enumerate() {
+            assert_eq!(isocore.find_by_root(root), Some(ItemId(i as u64)));
+        }
+
+        isocore.flush().unwrap();
+        drop(isocore);
+        let mut isocore = IsoCore::load(&path).unwrap();
+        assert_eq!(isocore.len().0, 40);
+        assert_eq!(isocore.verify_head().unwrap(), roots[39]);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn isocore_inlines_small_payloads() {
+        let path = PathBuf::from("/tmp/test_isocore_inline");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+        let messages = ["tiny", "a message too long to inline", "", "small"];
+        let mut isocore = IsoCore::create_inline(path.clone(), &signer, 8).unwrap();
+        let roots = isocore.add_messages(&messages[..3], &signer).unwrap();
+        isocore.ingest(messages[3].as_bytes()).unwrap();
+        isocore.build_tree(&signer).unwrap();
+
+        // Roots don't depend on where payloads are kept
+        let mut plain = IsoCore::create_mem(&signer);
+        assert_eq!(plain.add_messages(&messages[..3], &signer).unwrap(), roots);
+
+        assert_eq!(isocore.leaf(ItemId(0)).unwrap().data.as_deref(), Some(&b"tiny"[..]));
+        assert_eq!(isocore.leaf(ItemId(1)).unwrap().data, None);
+        assert_eq!(isocore.leaf(ItemId(3)).unwrap().data.as_deref(), Some(&b"small"[..]));
+        assert_eq!(isocore.stats().unwrap().payload_bytes, messages[1].len() as u64);
+        isocore.flush().unwrap();
+        drop(isocore);
+
+        let mut isocore = IsoCore::load(&path).unwrap();
+        assert_eq!(isocore.inline_limit(), 8);
+        assert_eq!(&*isocore.data_core.get_contents(MessageId(0)).unwrap(), b"");
+        for (i, message) in messages.iter().enumerate() {
+            assert_eq!(isocore.get_message(ItemId(i as u64)).unwrap(), message.as_bytes());
+        }
+        assert!(matches!(isocore.get_message(ItemId(0)).unwrap(), Cow::Owned(_)));
+        assert!(matches!(isocore.get_message(ItemId(1)).unwrap(), Cow::Borrowed(_)));
+        let page: Vec<Vec<u8>> = isocore.page(0, 4, PageDirection::OldestFirst).unwrap()
+            .into_iter()
+            .map(|entry| entry.data)
+            .collect();
+        assert_eq!(page, messages.map(|message| message.as_bytes().to_vec()));
+        assert!(isocore.audit(|_| {}).is_ok());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
 }
@@ -20,6 +20,10 @@
 //! - Space: O(log N) pointers per frame
 //! - Time: O(log N) jumps to reach any previous frame
 //!
+//! A path from frame N takes at most one jump per bit of N, so
+//! `find_jump_path` gives up past `MAX_JUMP_PATH` frames rather than
+//! trusting its input to end the walk.
+//!
 //! ## Format
 //!
 //! ```text
@@ -28,9 +32,52 @@
 //!   - compressed_size: u64
 //!   - decompressed_size: u64
 //!   - jump_list: List<u64>  (absolute file offsets to previous frame starts)
+//!   - extensions: Map (optional, omitted when empty)
+//!       - "count": u64  (number of messages in the frame)
+//!       - "checksum": u64  (truncated blake3 of the compressed data)
+//!       - "sizes": Array<u32>  (byte length of each message in the frame, with its envelope)
+//!       - "level": i32  (zstd level the data was compressed at)
+//!       - "first": u64  (id of the frame's first message in the file)
+//!       - "envelope": u8  (flags of the envelope around each message)
+//!       - "density": u64  (frames per full jump list; absent means 1)
+//!       - "forward": Array<u64>  (offsets of later frame headers, 0 until
+//!         written; always the last key)
 //! ```
+//!
+//! ## Density
+//!
+//! A writer can give only every k-th frame a jump list, so headers stay
+//! small when frames are. Frame N with N % k == 0 points back along the
+//! skip list of N / k, scaled by k, and at frame N - 1; the frames
+//! between point only at the frame before. A walk back steps frame by
+//! frame to a multiple of k, jumps, and steps again: O(k + log(N / k))
+//! header reads. Each header records its density, so readers follow it
+//! whatever the writer chose.
+//!
+//! ## Forward pointers
+//!
+//! Jump lists only point back, since a frame's successors aren't written
+//! yet. Frames with a jump list can also hold forward slots: slot i of
+//! frame N points at frame N + k * 2^i, for i up to the number of
+//! trailing zero bits in N / k, and is patched in place once that frame
+//! is written. The slots are a fixed-width array at the very end of the
+//! header, so patching never moves anything. A walk forward takes the
+//! furthest slot that doesn't overshoot, in O(k + log(N / k)) reads as
+//! well. A slot is 0 until patched, and readers check the frame it lands
+//! on, so a torn or stale slot only costs the shortcut.
+//!
+//! Decoders skip extension keys they don't know, so new ones can be added
+//! without breaking older readers. Density and forward slots are the
+//! exception: a reader that skips "density" would follow a sparse frame's
+//! jump list as if it were full. A NeoDisk log with any such frame
+//! records version 1 in its footer magic, which older readers refuse
+//! before reading a header; `min_log_version` says which a frame needs.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
 
-use crate::neopack::{Encoder, Decoder, Error as NeopackError};
+use crate::neopack::{Encoder, Decoder, Error as NeopackError, Tag};
 
 #[derive(Debug, Clone)]
 pub struct FrameHeader {
@@ -46,6 +93,78 @@ pub struct FrameHeader {
     /// Logarithmic skip list: absolute file offsets to previous frame headers
     /// For frame N, contains pointers based on binary decomposition of (N-1)
     pub jump_offsets: Vec<u64>,
+
+    /// Byte length of each message in the decompressed frame, in order,
+    /// counting its envelope if the frame has one. Their prefix sums are
+    /// the message offsets, so a reader can slice straight to a message.
+    /// Costs 4 bytes per message when present.
+    pub message_sizes: Option<Vec<u32>>,
+
+    /// Number of messages in the frame, so a file can be indexed from its
+    /// headers alone. Absent in frames written before it was recorded.
+    pub message_count: Option<u64>,
+
+    /// Checksum of the compressed frame data, checked before it is
+    /// decompressed. Absent in frames written before it was recorded.
+    pub checksum: Option<u64>,
+
+    /// zstd level the frame data was compressed at. Readers don't need it;
+    /// it pins what a writer must use to reproduce the frame byte for byte.
+    pub zstd_level: Option<i32>,
+
+    /// Id of the frame's first message, so frames can still be placed in
+    /// the log when a header before them is lost. Absent in frames
+    /// written before it was recorded.
+    pub first_message: Option<u64>,
+
+    /// Envelope around each message in the frame. Absent when the frame
+    /// holds bare neopack values, split by decoding them.
+    pub envelope: Option<Envelope>,
+
+    /// Frames per full jump list, as described in the module docs.
+    /// Absent in frames where every frame has one.
+    pub jump_density: Option<u64>,
+
+    /// Header offsets of the frames `forward_targets` names, each 0 until
+    /// that frame is written. Absent when the writer keeps no forward
+    /// pointers.
+    pub forward_offsets: Option<Vec<u64>>,
+}
+
+/// How the messages in a frame are wrapped. Each one is written as its
+/// length (u32 LE), then a type byte if `typed`, then a checksum of the
+/// payload (u32 LE) if `checksummed`, then the payload, so the frame can
+/// be split without looking inside the payloads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Envelope {
+    pub typed: bool,
+    pub checksummed: bool,
+}
+
+impl Envelope {
+    const TYPED: u8 = 1;
+    const CHECKSUMMED: u8 = 2;
+
+    /// Bytes written before each payload
+    pub fn prefix_len(&self) -> usize {
+        4 + self.typed as usize + 4 * self.checksummed as usize
+    }
+
+    fn to_flags(self) -> u8 {
+        (self.typed as u8 * Self::TYPED) | (self.checksummed as u8 * Self::CHECKSUMMED)
+    }
+
+    /// Refuses flags this version doesn't know, since it couldn't split
+    /// the frame
+    fn from_flags(flags: u8) -> Result<Self, NeopackError> {
+        if flags & !(Self::TYPED | Self::CHECKSUMMED) != 0 {
+            return Err(NeopackError::Malformed);
+        }
+        Ok(Self {
+            typed: flags & Self::TYPED != 0,
+            checksummed: flags & Self::CHECKSUMMED != 0,
+        })
+    }
 }
 
 impl FrameHeader {
@@ -61,9 +180,112 @@ impl FrameHeader {
             compressed_size,
             decompressed_size,
             jump_offsets,
+            message_sizes: None,
+            message_count: None,
+            checksum: None,
+            zstd_level: None,
+            first_message: None,
+            envelope: None,
+            jump_density: None,
+            forward_offsets: None,
         }
     }
 
+    /// Record the jump-list density the frame was written with
+    pub fn with_jump_density(mut self, density: u64) -> Self {
+        self.jump_density = Some(density);
+        self
+    }
+
+    /// Record forward pointers, with 0 for frames not yet written
+    pub fn with_forward_offsets(mut self, offsets: Vec<u64>) -> Self {
+        self.forward_offsets = Some(offsets);
+        self
+    }
+
+    /// Frames per full jump list
+    pub fn density(&self) -> u64 {
+        self.jump_density.unwrap_or(1)
+    }
+
+    /// Frames `jump_offsets` points at, in the same order
+    pub fn jump_targets(&self) -> Vec<u64> {
+        jump_targets(self.frame_number, self.density())
+    }
+
+    /// Log version a file holding this frame must record: 1 if it has a
+    /// density other than 1 or forward slots, else 0
+    pub fn min_log_version(&self) -> u8 {
+        (self.density() != 1 || self.forward_offsets.is_some()) as u8
+    }
+
+    /// Frames `forward_offsets` points at, in the same order
+    pub fn forward_targets(&self) -> Vec<u64> {
+        forward_targets(self.frame_number, self.density())
+    }
+
+    /// Each later frame a forward slot has been patched with, and the
+    /// offset of its header
+    pub fn forward(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        let offsets = self.forward_offsets.as_deref().unwrap_or_default();
+        self.forward_targets()
+            .into_iter()
+            .zip(offsets.iter().copied())
+            .filter(|&(_, offset)| offset != 0)
+    }
+
+    /// Record the envelope around each message
+    pub fn with_envelope(mut self, envelope: Envelope) -> Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    /// Record the id of the frame's first message
+    pub fn with_first_message(mut self, id: u64) -> Self {
+        self.first_message = Some(id);
+        self
+    }
+
+    /// Record the zstd level the frame data was compressed at
+    pub fn with_zstd_level(mut self, level: i32) -> Self {
+        self.zstd_level = Some(level);
+        self
+    }
+
+    /// Record a checksum of the compressed frame data in the header
+    pub fn with_checksum(mut self, checksum: u64) -> Self {
+        self.checksum = Some(checksum);
+        self
+    }
+
+    /// Record the number of messages in the header
+    pub fn with_message_count(mut self, count: u64) -> Self {
+        self.message_count = Some(count);
+        self
+    }
+
+    /// Number of messages in the frame, if the header records either
+    /// the count or the sizes
+    pub fn count(&self) -> Option<u64> {
+        self.message_count
+            .or_else(|| self.message_sizes.as_ref().map(|s| s.len() as u64))
+    }
+
+    /// Record per-message sizes in the header
+    pub fn with_message_sizes(mut self, sizes: Vec<u32>) -> Self {
+        self.message_sizes = Some(sizes);
+        self
+    }
+
+    /// Byte range of message `index` within the decompressed frame, if
+    /// the header records message sizes
+    pub fn message_range(&self, index: usize) -> Option<Range<usize>> {
+        let sizes = self.message_sizes.as_ref()?;
+        let start: usize = sizes.get(..index)?.iter().map(|&s| s as usize).sum();
+        let len = *sizes.get(index)? as usize;
+        Some(start..start + len)
+    }
+
     /// Encode frame header to neopack format
     pub fn encode(&self) -> Result<Vec<u8>, NeopackError> {
         let mut enc = Encoder::new();
@@ -79,6 +301,44 @@ impl FrameHeader {
         }
         jumps.finish()?;
 
+        if self.message_count.is_some() || self.message_sizes.is_some() || self.checksum.is_some() || self.zstd_level.is_some() || self.first_message.is_some() || self.envelope.is_some() || self.jump_density.is_some() || self.forward_offsets.is_some() {
+            let mut extensions = list.map()?;
+            if let Some(count) = self.message_count {
+                extensions.key("count")?.u64(count)?;
+            }
+            if let Some(checksum) = self.checksum {
+                extensions.key("checksum")?.u64(checksum)?;
+            }
+            if let Some(sizes) = &self.message_sizes {
+                let mut array = extensions.key("sizes")?.array(Tag::U32, 4)?;
+                for size in sizes {
+                    array.u32(*size)?;
+                }
+                array.finish()?;
+            }
+            if let Some(level) = self.zstd_level {
+                extensions.key("level")?.i32(level)?;
+            }
+            if let Some(first) = self.first_message {
+                extensions.key("first")?.u64(first)?;
+            }
+            if let Some(envelope) = self.envelope {
+                extensions.key("envelope")?.u8(envelope.to_flags())?;
+            }
+            if let Some(density) = self.jump_density {
+                extensions.key("density")?.u64(density)?;
+            }
+            // Last, so the slots end the header and can be patched in place
+            if let Some(offsets) = &self.forward_offsets {
+                let mut array = extensions.key("forward")?.array(Tag::U64, 8)?;
+                for offset in offsets {
+                    array.u64(*offset)?;
+                }
+                array.finish()?;
+            }
+            extensions.finish()?;
+        }
+
         list.finish()?;
         Ok(enc.into_bytes())
     }
@@ -105,11 +365,62 @@ impl FrameHeader {
             jump_offsets.push(val.as_u64()?);
         }
 
+        // Headers written before extensions existed end here
+        let mut message_sizes = None;
+        let mut message_count = None;
+        let mut checksum = None;
+        let mut zstd_level = None;
+        let mut first_message = None;
+        let mut envelope = None;
+        let mut jump_density = None;
+        let mut forward_offsets = None;
+        if let Some(extensions) = list.next()? {
+            let ValueDecoder::Map(mut extensions) = extensions else {
+                return Err(NeopackError::TypeMismatch);
+            };
+            while let Some((key, value)) = extensions.next()? {
+                match (key, value) {
+                    ("count", value) => message_count = Some(value.as_u64()?),
+                    ("checksum", value) => checksum = Some(value.as_u64()?),
+                    ("level", value) => zstd_level = Some(value.as_i32()?),
+                    ("first", value) => first_message = Some(value.as_u64()?),
+                    ("envelope", value) => envelope = Some(Envelope::from_flags(value.as_u8()?)?),
+                    ("density", value) => match value.as_u64()? {
+                        0 => return Err(NeopackError::Malformed),
+                        density => jump_density = Some(density),
+                    },
+                    ("forward", ValueDecoder::Array(mut array)) => {
+                        let mut offsets = Vec::with_capacity(array.remaining());
+                        while let Some(offset) = array.next()? {
+                            offsets.push(offset.as_u64()?);
+                        }
+                        forward_offsets = Some(offsets);
+                    }
+                    ("sizes", ValueDecoder::Array(mut array)) => {
+                        let mut sizes = Vec::with_capacity(array.remaining());
+                        while let Some(size) = array.next()? {
+                            sizes.push(size.as_u32()?);
+                        }
+                        message_sizes = Some(sizes);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
         Ok(Self {
             frame_number,
             compressed_size,
             decompressed_size,
             jump_offsets,
+            message_sizes,
+            message_count,
+            checksum,
+            zstd_level,
+            first_message,
+            envelope,
+            jump_density,
+            forward_offsets,
         })
     }
 }
@@ -133,29 +444,95 @@ impl FrameHeader {
 /// assert_eq!(jumps, vec![16, 20, 22, 23]);
 /// ```
 pub fn compute_jump_indices(frame_index: u64) -> Vec<u64> {
-    if frame_index == 0 {
+    let Some(n_minus_1) = frame_index.checked_sub(1) else {
         return vec![];
+    };
+
+    // Each jump is n_minus_1 cut off below one of its set bits, highest
+    // bit first, so none can overflow or reach frame_index
+    (0..u64::BITS)
+        .rev()
+        .filter(|&bit_pos| n_minus_1 & (1u64 << bit_pos) != 0)
+        .map(|bit_pos| n_minus_1 & (u64::MAX << bit_pos))
+        .collect()
+}
+
+/// Frames the jump list of frame `frame` points at, oldest first, when
+/// every `density`-th frame has a full one. See the module docs.
+pub fn jump_targets(frame: u64, density: u64) -> Vec<u64> {
+    let density = density.max(1);
+    if density == 1 {
+        return compute_jump_indices(frame);
+    }
+    let mut targets = Vec::new();
+    if frame.is_multiple_of(density) {
+        // Scaled back up, these stay below frame - 1
+        targets.extend(compute_jump_indices(frame / density).into_iter().map(|jump| jump * density));
+    }
+    // Frame 0 starts the file, and no jump list points at it
+    if frame > 1 {
+        targets.push(frame - 1);
     }
+    targets
+}
 
-    let n_minus_1 = frame_index - 1;
-    let mut jumps = Vec::new();
-    let mut accumulator = 0u64;
+/// Most forward slots a header holds. Frame 0 has this many; others
+/// have one more than the trailing zero bits of their place among the
+/// frames with jump lists, and most have one or two.
+pub const MAX_FORWARD_SLOTS: usize = 32;
+
+/// Frames the forward slots of frame `frame` point at, nearest first,
+/// when every `density`-th frame has a jump list. Frames past `u64::MAX`
+/// get no slot.
+pub fn forward_targets(frame: u64, density: u64) -> Vec<u64> {
+    let density = density.max(1);
+    if !frame.is_multiple_of(density) {
+        return vec![];
+    }
+    let levels = ((frame / density).trailing_zeros() as usize + 1).min(MAX_FORWARD_SLOTS);
+    (0..levels)
+        .map_while(|level| density.checked_mul(1u64 << level)?.checked_add(frame))
+        .collect()
+}
 
-    // Iterate through set bits in n_minus_1 from highest to lowest
-    for bit_pos in (0..64).rev() {
-        if (n_minus_1 & (1 << bit_pos)) != 0 {
-            let power_of_two = 1u64 << bit_pos;
-            accumulator += power_of_two;
-            jumps.push(accumulator);
-        }
+/// Each earlier frame with a forward slot for frame `frame`, and which of
+/// its slots that is: the slots a writer patches once `frame` is written.
+pub fn forward_sources(frame: u64, density: u64) -> Vec<(u64, usize)> {
+    let density = density.max(1);
+    if !frame.is_multiple_of(density) {
+        return vec![];
     }
+    let place = frame / density;
+    (0..MAX_FORWARD_SLOTS)
+        .take_while(|&level| level < u64::BITS as usize && 1u64 << level <= place)
+        .map(|level| (place - (1u64 << level), level))
+        .filter(|&(source, level)| source.trailing_zeros() as usize >= level)
+        .map(|(source, level)| (source * density, level))
+        .collect()
+}
 
-    jumps
+/// Most frames a path from `find_jump_path` can visit, counting both
+/// ends: it takes at most one jump per bit of the frame number, and one
+/// more to reach frame 0.
+pub const MAX_JUMP_PATH: usize = u64::BITS as usize + 2;
+
+/// Why `find_jump_path` found no path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JumpPathError {
+    /// `to` is not before `from`; jumps only go back
+    NotBehind { from: u64, to: u64 },
+    /// `to` is before `from`, for a walk forward
+    NotAhead { from: u64, to: u64 },
+    /// No jump from `frame` lands between it and the target
+    NoJump { frame: u64 },
+    /// The path grew past `MAX_JUMP_PATH` frames
+    TooLong,
 }
 
 /// Find a jump path from frame `from` to frame `to` using logarithmic skip lists
 ///
-/// Returns the sequence of frame indices to visit, or None if `to` >= `from`.
+/// Returns the sequence of frame indices to visit, starting at `from` and
+/// ending at `to`, with at most `MAX_JUMP_PATH` frames in it.
 ///
 /// # Example
 ///
@@ -166,40 +543,47 @@ pub fn compute_jump_indices(frame_index: u64) -> Vec<u64> {
 /// let path = find_jump_path(24, 17).unwrap();
 /// // Possible path: [24, 20, 18, 17]
 /// ```
-pub fn find_jump_path(from: u64, to: u64) -> Option<Vec<u64>> {
+pub fn find_jump_path(from: u64, to: u64) -> Result<Vec<u64>, JumpPathError> {
     if to >= from {
-        return None;
+        return Err(JumpPathError::NotBehind { from, to });
     }
 
     let mut path = vec![from];
     let mut current = from;
 
     while current > to {
+        if path.len() >= MAX_JUMP_PATH {
+            return Err(JumpPathError::TooLong);
+        }
         let jumps = compute_jump_indices(current);
 
         if jumps.is_empty() {
             // No jumps available, can only go to frame 0
             if to == 0 {
                 path.push(0);
-                return Some(path);
+                return Ok(path);
             }
-            return None;
+            return Err(JumpPathError::NoJump { frame: current });
         }
 
-        // Find the smallest jump that is still >= to (closest to target without overshooting)
+        // Find the smallest jump that is still >= to (closest to target
+        // without overshooting), refusing any that wouldn't move back
         let next = jumps.iter()
-            .find(|&&jump_target| jump_target >= to)
-            .copied()?;
+            .copied()
+            .find(|&jump_target| jump_target >= to)
+            .filter(|&jump_target| jump_target < current)
+            .ok_or(JumpPathError::NoJump { frame: current })?;
 
         path.push(next);
         current = next;
     }
 
-    Some(path)
+    Ok(path)
 }
 
 #[cfg(test)]
 mod tests {
+    use proptest::prelude::*;
     use super::*;
 
     #[test]
@@ -256,10 +640,101 @@ mod tests {
     #[test]
     fn test_jump_path_bounds() {
         // Cannot jump forward
-        assert_eq!(find_jump_path(10, 20), None);
+        assert_eq!(find_jump_path(10, 20), Err(JumpPathError::NotBehind { from: 10, to: 20 }));
 
         // Cannot jump to same frame
-        assert_eq!(find_jump_path(10, 10), None);
+        assert_eq!(find_jump_path(10, 10), Err(JumpPathError::NotBehind { from: 10, to: 10 }));
+
+        // Frame numbers at the top of the range
+        let path = find_jump_path(u64::MAX, 0).unwrap();
+        assert_eq!(path.last(), Some(&0));
+        assert!(path.len() <= MAX_JUMP_PATH);
+        assert_eq!(find_jump_path(u64::MAX, u64::MAX - 1).unwrap(), vec![u64::MAX, u64::MAX - 1]);
+        assert_eq!(compute_jump_indices(u64::MAX).len(), 63);
+    }
+
+    /// Frame numbers spread over the whole u64 range, with the ends of it
+    /// and powers of two around their usual share
+    fn frame_number() -> impl Strategy<Value = u64> {
+        prop_oneof![
+            any::<u64>(),
+            0..1024u64,
+            (0..1024u64).prop_map(|n| u64::MAX - n),
+            (0..u64::BITS, -2..=2i64).prop_map(|(bit, off)| (1u64 << bit).wrapping_add_signed(off)),
+        ]
+    }
+
+    /// Checks that `path` is a valid `find_jump_path(from, to)` result
+    fn check_jump_path(from: u64, to: u64, path: &[u64]) -> Result<(), TestCaseError> {
+        prop_assert_eq!(path.first(), Some(&from));
+        prop_assert_eq!(path.last(), Some(&to));
+        // One jump per bit of `from`, the jump to frame 0, and both ends
+        let bits = (u64::BITS - from.leading_zeros()) as usize;
+        prop_assert!(path.len() <= bits + 2, "{} frames from {} to {}", path.len(), from, to);
+        for step in path.windows(2) {
+            prop_assert!(step[1] < step[0]);
+            prop_assert!(step[1] == 0 || compute_jump_indices(step[0]).contains(&step[1]),
+                "frame {} cannot jump to frame {}", step[0], step[1]);
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn jump_paths_are_valid_and_short(a in frame_number(), b in frame_number()) {
+            let (from, to) = (a.max(b), a.min(b));
+            match find_jump_path(from, to) {
+                Ok(path) => check_jump_path(from, to, &path)?,
+                Err(err) => prop_assert_eq!(err, JumpPathError::NotBehind { from, to }),
+            }
+        }
+
+        #[test]
+        fn forward_slots_match_their_sources(frame in frame_number(), density in 1..64u64) {
+            for (source, slot) in forward_sources(frame, density) {
+                prop_assert_eq!(forward_targets(source, density).get(slot).copied(), Some(frame));
+            }
+            for (slot, target) in forward_targets(frame, density).into_iter().enumerate() {
+                prop_assert!(forward_sources(target, density).contains(&(frame, slot)));
+            }
+        }
+
+        #[test]
+        fn sparse_jump_targets_lead_back(frame in frame_number(), density in 1..64u64) {
+            let targets = jump_targets(frame, density);
+            prop_assert!(targets.windows(2).all(|pair| pair[0] < pair[1]));
+            prop_assert!(targets.iter().all(|&target| 0 < target && target < frame));
+            prop_assert_eq!(targets.last().copied(), (frame > 1).then(|| frame - 1));
+        }
+
+        #[test]
+        fn jump_indices_lead_back(frame in frame_number()) {
+            let jumps = compute_jump_indices(frame);
+            prop_assert_eq!(jumps.len(), frame.saturating_sub(1).count_ones() as usize);
+            prop_assert!(jumps.windows(2).all(|pair| pair[0] < pair[1]));
+            prop_assert!(jumps.iter().all(|&jump| jump < frame));
+        }
+    }
+
+    #[test]
+    fn test_forward_slots_end_the_header() {
+        assert_eq!(jump_targets(24, 8), vec![16, 23]);
+        assert_eq!(forward_targets(24, 8), vec![32]);
+        assert_eq!(forward_targets(16, 8), vec![24, 32]);
+        assert_eq!(forward_targets(0, 1).len(), MAX_FORWARD_SLOTS);
+        assert_eq!(forward_sources(32, 8), vec![(24, 0), (16, 1), (0, 2)]);
+
+        let header = FrameHeader::new(16, 100, 200, vec![0, 500])
+            .with_message_count(3)
+            .with_jump_density(8)
+            .with_forward_offsets(vec![0, 7000]);
+        let bytes = header.encode().unwrap();
+        assert_eq!(bytes[bytes.len() - 8..], 7000u64.to_le_bytes());
+
+        let decoded = FrameHeader::decode(&bytes).unwrap();
+        assert_eq!(decoded.density(), 8);
+        assert_eq!(decoded.forward().collect::<Vec<_>>(), vec![(32, 7000)]);
+        assert!(FrameHeader::decode(&FrameHeader::new(1, 1, 1, vec![]).with_jump_density(0).encode().unwrap()).is_err());
     }
 
     #[test]
@@ -279,4 +754,51 @@ mod tests {
         assert_eq!(decoded.decompressed_size, header.decompressed_size);
         assert_eq!(decoded.jump_offsets, header.jump_offsets);
     }
+
+    #[test]
+    fn test_header_message_sizes() {
+        let header = FrameHeader::new(3, 100, 60, vec![0, 500])
+            .with_message_sizes(vec![10, 20, 30]);
+
+        let decoded = FrameHeader::decode(&header.encode().unwrap()).unwrap();
+        assert_eq!(decoded.message_sizes, Some(vec![10, 20, 30]));
+        assert_eq!(decoded.message_range(0), Some(0..10));
+        assert_eq!(decoded.message_range(2), Some(30..60));
+        assert_eq!(decoded.message_range(3), None);
+
+        // Headers without extensions still decode
+        let plain = FrameHeader::new(3, 100, 60, vec![0, 500]);
+        let decoded = FrameHeader::decode(&plain.encode().unwrap()).unwrap();
+        assert_eq!(decoded.message_sizes, None);
+        assert_eq!(decoded.message_range(0), None);
+        assert_eq!(decoded.count(), None);
+    }
+
+    #[test]
+    fn test_header_message_count() {
+        let header = FrameHeader::new(1, 100, 60, vec![0])
+            .with_message_count(7)
+            .with_checksum(0xdead_beef);
+        let decoded = FrameHeader::decode(&header.encode().unwrap()).unwrap();
+        assert_eq!(decoded.message_count, Some(7));
+        assert_eq!(decoded.checksum, Some(0xdead_beef));
+        assert_eq!(decoded.count(), Some(7));
+        assert_eq!(decoded.message_sizes, None);
+        assert_eq!(decoded.zstd_level, None);
+
+        let leveled = FrameHeader::new(1, 100, 60, vec![0]).with_zstd_level(-5).with_first_message(40);
+        let decoded = FrameHeader::decode(&leveled.encode().unwrap()).unwrap();
+        assert_eq!((decoded.zstd_level, decoded.first_message), (Some(-5), Some(40)));
+
+        let wrapped = FrameHeader::new(1, 100, 60, vec![0]).with_envelope(Envelope { typed: true, checksummed: false });
+        let decoded = FrameHeader::decode(&wrapped.encode().unwrap()).unwrap();
+        assert_eq!(decoded.envelope, Some(Envelope { typed: true, checksummed: false }));
+        assert_eq!(decoded.envelope.unwrap().prefix_len(), 5);
+        assert_eq!(FrameHeader::decode(&leveled.encode().unwrap()).unwrap().envelope, None);
+
+        // Sizes alone also give the count
+        let sized = FrameHeader::new(1, 100, 60, vec![0]).with_message_sizes(vec![20, 40]);
+        let decoded = FrameHeader::decode(&sized.encode().unwrap()).unwrap();
+        assert_eq!(decoded.count(), Some(2));
+    }
 }
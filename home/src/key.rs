@@ -15,8 +15,15 @@ use chacha20poly1305::XChaCha20Poly1305;
 use argon2::Argon2;
 use argon2::PasswordHasher;
 use argon2::password_hash::SaltString;
+use rand_core::CryptoRngCore;
+use crate::hex;
+use crate::hex::HexError;
+use crate::neopack;
+use crate::neopack::Pack;
+use crate::neopack::ValueDecoder;
+use crate::neopack::ValueWriter;
+#[cfg(feature = "rng")]
 use rand_core::OsRng;
-use rand_core::RngCore;
 
 #[derive(Clone, PartialEq, Eq)]
 pub struct KeyPub(pub [u8; 32]);
@@ -30,46 +37,51 @@ pub struct KeyPair {
     pub key_sec: KeySec,
 }
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Hash(pub [u8; 32]);
 
 impl Hash {
-    pub fn to_hex(&self) -> Vec<u8> {
-        return self.0.iter()
-            .flat_map(|b| format!("{:02x}", b).into_bytes())
-            .collect();
+    pub fn to_hex(&self) -> String {
+        hex::encode(&self.0)
     }
 
-    pub fn from_hex(s: &str) -> Self {
-        let mut result = [0u8; 32];
-        for i in 0..32 {
-            let byte_str = &s[i * 2..i * 2 + 2];
-            result[i] = u8::from_str_radix(byte_str, 16).unwrap_or(0);
-        }
-        return Hash(result);
+    /// Reads 64 hex digits, failing on anything else.
+    pub fn from_hex(s: &str) -> Result<Self, HexError> {
+        hex::decode_array(s).map(Hash)
+    }
+
+    /// Reads a hash written with `fixed32`, or as 32 `bytes`.
+    pub fn from_value(value: &ValueDecoder<'_>) -> Result<Self, neopack::Error> {
+        fixed32_from(value).map(Hash)
     }
 }
 
 impl std::fmt::Debug for KeyPub {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "KeyPub({})", hex(&self.0))
+        write!(f, "KeyPub({})", hex::encode(&self.0))
     }
 }
 
 impl std::fmt::Debug for KeySec {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "KeySec({})", hex(&self.0))
+        write!(f, "KeySec({})", hex::encode(&self.0))
     }
 }
 
 impl std::fmt::Debug for Hash {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Hash({})", hex(&self.0))
+        write!(f, "Hash({})", hex::encode(&self.0))
     }
 }
 
-fn hex(bytes: &[u8]) -> String {
-    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+/// Keys and hashes used to be written as length-prefixed `bytes`; both
+/// encodings read back the same.
+fn fixed32_from(value: &ValueDecoder<'_>) -> Result<[u8; 32], neopack::Error> {
+    match value {
+        ValueDecoder::Fixed32(v) => Ok(**v),
+        ValueDecoder::Bytes(v) => (*v).try_into().map_err(|_| neopack::Error::Malformed),
+        _ => Err(neopack::Error::TypeMismatch),
+    }
 }
 
 pub fn hash(message: &[u8]) -> Hash {
@@ -79,16 +91,80 @@ pub fn hash(message: &[u8]) -> Hash {
     Hash(*result.as_bytes())
 }
 
+/// What a tree hash commits to. Each domain gets its own blake3
+/// derive_key context, so bytes hashed as one can't pass for another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashDomain {
+    Leaf,
+    Node,
+    Root,
+}
+
+impl HashDomain {
+    fn context(self) -> &'static str {
+        match self {
+            HashDomain::Leaf => "home isocore v2 leaf",
+            HashDomain::Node => "home isocore v2 node",
+            HashDomain::Root => "home isocore v2 root",
+        }
+    }
+}
+
+/// Builds a hash from several pieces without concatenating them first.
+/// Feeding `a` then `b` gives the same hash as `hash(&[a, b].concat())`.
+#[derive(Clone, Default)]
+pub struct HashBuilder(Hasher);
+
+impl HashBuilder {
+    pub fn new() -> Self {
+        HashBuilder(Hasher::new())
+    }
+
+    pub fn for_domain(domain: HashDomain) -> Self {
+        HashBuilder(Hasher::new_derive_key(domain.context()))
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) -> &mut Self {
+        self.0.update(bytes);
+        self
+    }
+
+    pub fn finish(&self) -> Hash {
+        Hash(*self.0.finalize().as_bytes())
+    }
+}
+
+#[cfg(feature = "rng")]
 pub fn generate_nonce() -> [u8; 24] {
+    generate_nonce_with(&mut OsRng)
+}
+
+/// Like `generate_nonce`, but draws from `rng`. A seeded rng makes
+/// encrypted fixtures reproducible; never reuse a seed outside tests.
+pub fn generate_nonce_with(rng: &mut impl CryptoRngCore) -> [u8; 24] {
     let mut nonce = [0u8; 24];
-    OsRng.fill_bytes(&mut nonce);
+    rng.fill_bytes(&mut nonce);
     nonce
 }
 
 impl KeyPair {
     /// Generate a random keypair
+    #[cfg(feature = "rng")]
     pub fn ephemeral() -> Self {
-        let signing_key = SigningKey::generate(&mut OsRng);
+        Self::generate(&mut OsRng)
+    }
+
+    /// Generate a keypair from the given rng
+    pub fn generate(rng: &mut impl CryptoRngCore) -> Self {
+        let mut seed = [0u8; 32];
+        rng.fill_bytes(&mut seed);
+        Self::from_seed(seed)
+    }
+
+    /// Derive the keypair for a 32-byte ed25519 seed. The same seed always
+    /// gives the same keys, which makes signed fixtures reproducible.
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        let signing_key = SigningKey::from_bytes(&seed);
         let verifying_key = signing_key.verifying_key();
 
         KeyPair {
@@ -128,28 +204,24 @@ impl KeyPair {
         }
     }
 
-    /// Derives a shared secret using X25519
+    /// Derives a shared secret by converting both ed25519 keys to X25519.
+    /// Kept for compatibility; new code should exchange `BoxKeyPair`s.
     pub fn conspire(&self, other: &KeyPub) -> KeyShared {
-        // Convert Ed25519 secret key to X25519 format
-        let signing_key = SigningKey::from_bytes(&self.key_sec.0);
-        let secret_scalar = signing_key.to_scalar_bytes();
-        let x25519_secret = StaticSecret::from(secret_scalar);
-
-        // Convert Ed25519 public key to X25519 (Montgomery) format
-        let verifying_key = VerifyingKey::from_bytes(&other.0).unwrap();
-        let montgomery_point = verifying_key.to_montgomery();
-        let x25519_public = X25519PublicKey::from(*montgomery_point.as_bytes());
-
-        // Perform X25519 Diffie-Hellman
-        let shared = x25519_secret.diffie_hellman(&x25519_public);
-        KeyShared(*shared.as_bytes())
+        let other = BoxPub::from_signing(other).expect("invalid ed25519 public key");
+        BoxKeyPair::from_signing(self).conspire(&other)
     }
 
     /// Encrypt a message to another party using X25519 key exchange
+    #[cfg(feature = "rng")]
     pub fn encrypt(&self, other: &KeyPub, message: &[u8]) -> Payload {
         self.conspire(other).encrypt(message)
     }
 
+    /// Like `encrypt`, drawing the nonce from `rng`
+    pub fn encrypt_with(&self, other: &KeyPub, message: &[u8], rng: &mut impl CryptoRngCore) -> Payload {
+        self.conspire(other).encrypt_with(message, rng)
+    }
+
     /// Decrypt a message from another party using X25519 key exchange
     pub fn decrypt(&self, other: &KeyPub, payload: Payload) -> Result<Vec<u8>, DecryptError> {
         self.conspire(other).decrypt(payload)
@@ -161,6 +233,7 @@ impl KeyPair {
     }
 
     /// Encrypt a message for yourself (encryption at rest)
+    #[cfg(feature = "rng")]
     pub fn encrypt_rest(&self, message: &[u8]) -> Payload {
         self.at_rest().encrypt(message)
     }
@@ -170,6 +243,11 @@ impl KeyPair {
         self.at_rest().decrypt(payload)
     }
 
+    /// Opens a payload sealed to this keypair's public key
+    pub fn unseal(&self, payload: Payload) -> Result<Vec<u8>, DecryptError> {
+        BoxKeyPair::from_signing(self).unseal(payload)
+    }
+
     /// Sign a message using Ed25519
     pub fn sign(&self, message: &[u8]) -> Signature {
         let signing_key = SigningKey::from_bytes(&self.key_sec.0);
@@ -178,7 +256,33 @@ impl KeyPair {
 
     /// Verify a signature using Ed25519
     pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
-        let verifying_key = ed25519_dalek::VerifyingKey::from_bytes(&self.key_pub.0);
+        self.key_pub.verify(message, signature)
+    }
+}
+
+impl KeyPub {
+    /// Reads a key written with `fixed32`, or as 32 `bytes`.
+    pub fn from_value(value: &ValueDecoder<'_>) -> Result<Self, neopack::Error> {
+        fixed32_from(value).map(KeyPub)
+    }
+
+    /// Encrypts `message` so only the holder of this key can read it, from
+    /// a fresh ephemeral key, so nothing links the payload to its sender.
+    /// Fails if the key isn't a valid ed25519 point.
+    #[cfg(feature = "rng")]
+    pub fn seal(&self, message: &[u8]) -> Result<Payload, InvalidKey> {
+        self.seal_with(message, &mut OsRng)
+    }
+
+    /// Like `seal`, drawing the ephemeral key from `rng`
+    pub fn seal_with(&self, message: &[u8], rng: &mut impl CryptoRngCore) -> Result<Payload, InvalidKey> {
+        Ok(BoxPub::from_signing(self).ok_or(InvalidKey)?.seal_with(message, rng))
+    }
+
+    /// Verify a signature made by the matching secret key.
+    /// Needs no secret material, so light clients can check heads.
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> bool {
+        let verifying_key = ed25519_dalek::VerifyingKey::from_bytes(&self.0);
         match verifying_key {
             Ok(vk) => {
                 let sig = Ed25519Signature::from_bytes(&signature.0);
@@ -189,12 +293,222 @@ impl KeyPair {
     }
 }
 
+/// X25519 public key for key agreement, separate from the signing key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BoxPub(pub [u8; 32]);
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct BoxSec(pub [u8; 32]);
+
+#[derive(Clone, Debug)]
+pub struct BoxKeyPair {
+    pub box_pub: BoxPub,
+    pub box_sec: BoxSec,
+}
+
+impl std::fmt::Debug for BoxPub {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BoxPub({})", hex::encode(&self.0))
+    }
+}
+
+impl std::fmt::Debug for BoxSec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BoxSec({})", hex::encode(&self.0))
+    }
+}
+
+impl BoxPub {
+    /// Converts an ed25519 public key to its X25519 (Montgomery) form.
+    /// Returns None if the bytes aren't a valid ed25519 point.
+    pub fn from_signing(key: &KeyPub) -> Option<Self> {
+        let verifying_key = VerifyingKey::from_bytes(&key.0).ok()?;
+        Some(BoxPub(*verifying_key.to_montgomery().as_bytes()))
+    }
+
+    /// Encrypts to this key from a fresh ephemeral key. The ephemeral
+    /// public key leads the ciphertext; the nonce is derived from both keys.
+    #[cfg(feature = "rng")]
+    pub fn seal(&self, message: &[u8]) -> Payload {
+        self.seal_with(message, &mut OsRng)
+    }
+
+    /// Like `seal`, drawing the ephemeral key from `rng`
+    pub fn seal_with(&self, message: &[u8], rng: &mut impl CryptoRngCore) -> Payload {
+        let ephemeral = BoxKeyPair::generate(rng);
+        let key = seal_key(&ephemeral.conspire(self), &ephemeral.box_pub, self);
+        let nonce = seal_nonce(&ephemeral.box_pub, self);
+
+        let cipher = XChaCha20Poly1305::new_from_slice(&key.0).unwrap();
+        let mut ciphertext = ephemeral.box_pub.0.to_vec();
+        ciphertext.extend(cipher.encrypt(XNonce::from_slice(&nonce), message).unwrap());
+
+        Payload {
+            nonce,
+            ciphertext,
+        }
+    }
+}
+
+impl BoxKeyPair {
+    /// Generate a random box keypair
+    #[cfg(feature = "rng")]
+    pub fn ephemeral() -> Self {
+        Self::generate(&mut OsRng)
+    }
+
+    /// Generate a box keypair from the given rng
+    pub fn generate(rng: &mut impl CryptoRngCore) -> Self {
+        let mut secret = [0u8; 32];
+        rng.fill_bytes(&mut secret);
+        Self::from_secret(secret)
+    }
+
+    /// Rebuild a box keypair from its 32 secret bytes
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        let secret = StaticSecret::from(secret);
+        let public = X25519PublicKey::from(&secret);
+
+        BoxKeyPair {
+            box_pub: BoxPub(public.to_bytes()),
+            box_sec: BoxSec(secret.to_bytes()),
+        }
+    }
+
+    /// Converts an ed25519 signing keypair to X25519, for talking to peers
+    /// that predate separate box keys.
+    pub fn from_signing(key: &KeyPair) -> Self {
+        let signing_key = SigningKey::from_bytes(&key.key_sec.0);
+        Self::from_secret(signing_key.to_scalar_bytes())
+    }
+
+    /// Derives a shared secret using X25519
+    pub fn conspire(&self, other: &BoxPub) -> KeyShared {
+        let secret = StaticSecret::from(self.box_sec.0);
+        let shared = secret.diffie_hellman(&X25519PublicKey::from(other.0));
+        KeyShared(*shared.as_bytes())
+    }
+
+    /// Encrypt a message to another party's box key
+    #[cfg(feature = "rng")]
+    pub fn encrypt(&self, other: &BoxPub, message: &[u8]) -> Payload {
+        self.conspire(other).encrypt(message)
+    }
+
+    /// Like `encrypt`, drawing the nonce from `rng`
+    pub fn encrypt_with(&self, other: &BoxPub, message: &[u8], rng: &mut impl CryptoRngCore) -> Payload {
+        self.conspire(other).encrypt_with(message, rng)
+    }
+
+    /// Decrypt a message from another party's box key
+    pub fn decrypt(&self, other: &BoxPub, payload: Payload) -> Result<Vec<u8>, DecryptError> {
+        self.conspire(other).decrypt(payload)
+    }
+
+    /// Opens a payload sealed to this box key
+    pub fn unseal(&self, payload: Payload) -> Result<Vec<u8>, DecryptError> {
+        if payload.ciphertext.len() < 32 {
+            return Err(DecryptError::InvalidPayload);
+        }
+        let (ephemeral, ciphertext) = payload.ciphertext.split_at(32);
+        let ephemeral = BoxPub(ephemeral.try_into().unwrap());
+        if payload.nonce != seal_nonce(&ephemeral, &self.box_pub) {
+            return Err(DecryptError::InvalidPayload);
+        }
+
+        seal_key(&self.conspire(&ephemeral), &ephemeral, &self.box_pub).decrypt(Payload {
+            nonce: payload.nonce,
+            ciphertext: ciphertext.to_vec(),
+        })
+    }
+}
+
+/// Binds the sealing key to both public keys, not just the DH output
+fn seal_key(shared: &KeyShared, ephemeral: &BoxPub, recipient: &BoxPub) -> KeyShared {
+    let mut hasher = Hasher::new_derive_key("home sealed box v1 key");
+    hasher.update(&shared.0);
+    hasher.update(&ephemeral.0);
+    hasher.update(&recipient.0);
+    KeyShared(*hasher.finalize().as_bytes())
+}
+
+fn seal_nonce(ephemeral: &BoxPub, recipient: &BoxPub) -> [u8; 24] {
+    let mut hasher = Hasher::new_derive_key("home sealed box v1 nonce");
+    hasher.update(&ephemeral.0);
+    hasher.update(&recipient.0);
+    let mut nonce = [0u8; 24];
+    nonce.copy_from_slice(&hasher.finalize().as_bytes()[..24]);
+    nonce
+}
+
+/// The keys a party publishes: its signing key and its box key, with the
+/// box key signed by the signing key so it can't be swapped in transit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyBundle {
+    pub key_pub: KeyPub,
+    pub box_pub: BoxPub,
+    pub signature: Signature,
+}
+
+#[derive(Debug)]
+pub enum BundleError {
+    InvalidLength,
+    BadSignature,
+}
+
+impl KeyBundle {
+    const CONTEXT: &'static [u8] = b"home key bundle v1";
+    const SIZE: usize = 32 + 32 + 64;
+
+    pub fn new(signer: &KeyPair, box_pub: &BoxPub) -> Self {
+        KeyBundle {
+            key_pub: signer.key_pub.clone(),
+            box_pub: box_pub.clone(),
+            signature: signer.sign(&Self::signed_bytes(box_pub)),
+        }
+    }
+
+    /// Checks the box key was signed by the bundle's signing key
+    pub fn verify(&self) -> bool {
+        self.key_pub.verify(&Self::signed_bytes(&self.box_pub), &self.signature)
+    }
+
+    /// Signing key, box key, then signature: 128 bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::SIZE);
+        out.extend_from_slice(&self.key_pub.0);
+        out.extend_from_slice(&self.box_pub.0);
+        out.extend_from_slice(&self.signature.0);
+        out
+    }
+
+    /// Parses and verifies a bundle
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BundleError> {
+        if bytes.len() != Self::SIZE {
+            return Err(BundleError::InvalidLength);
+        }
+        let bundle = KeyBundle {
+            key_pub: KeyPub(bytes[..32].try_into().unwrap()),
+            box_pub: BoxPub(bytes[32..64].try_into().unwrap()),
+            signature: Signature(bytes[64..].try_into().unwrap()),
+        };
+        if !bundle.verify() {
+            return Err(BundleError::BadSignature);
+        }
+        Ok(bundle)
+    }
+
+    fn signed_bytes(box_pub: &BoxPub) -> Vec<u8> {
+        [Self::CONTEXT, &box_pub.0].concat()
+    }
+}
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct Signature(pub [u8; 64]);
 
 impl std::fmt::Debug for Signature {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Signature({})", hex(&self.0))
+        write!(f, "Signature({})", hex::encode(&self.0))
     }
 }
 
@@ -203,7 +517,7 @@ pub struct KeyShared(pub [u8; 32]);
 
 impl std::fmt::Debug for KeyShared {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "KeyShared({})", hex(&self.0))
+        write!(f, "KeyShared({})", hex::encode(&self.0))
     }
 }
 
@@ -213,6 +527,29 @@ pub struct Payload {
     pub ciphertext: Vec<u8>,
 }
 
+impl Payload {
+    /// The nonce followed by the ciphertext
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&self.nonce[..], &self.ciphertext].concat()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecryptError> {
+        if bytes.len() < 24 {
+            return Err(DecryptError::InvalidPayload);
+        }
+        let (nonce, ciphertext) = bytes.split_at(24);
+        Ok(Payload {
+            nonce: nonce.try_into().unwrap(),
+            ciphertext: ciphertext.to_vec(),
+        })
+    }
+}
+
+/// A public key whose bytes aren't a valid ed25519 point, so nothing can
+/// be sealed to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidKey;
+
 #[derive(Debug)]
 pub enum DecryptError {
     AuthenticationFailed,
@@ -221,9 +558,15 @@ pub enum DecryptError {
 
 impl KeyShared {
     /// Encrypts a message using XChaCha20-Poly1305 AEAD.
+    #[cfg(feature = "rng")]
     pub fn encrypt(&self, message: &[u8]) -> Payload {
+        self.encrypt_with(message, &mut OsRng)
+    }
+
+    /// Like `encrypt`, drawing the nonce from `rng`
+    pub fn encrypt_with(&self, message: &[u8], rng: &mut impl CryptoRngCore) -> Payload {
         let cipher = XChaCha20Poly1305::new_from_slice(&self.0).unwrap();
-        let nonce = generate_nonce();
+        let nonce = generate_nonce_with(rng);
         let nonce_obj = XNonce::from_slice(&nonce);
         let ciphertext = cipher.encrypt(nonce_obj, message).unwrap();
 
@@ -233,6 +576,18 @@ impl KeyShared {
         }
     }
 
+    /// Encrypts with a caller-chosen nonce, for protocols that derive
+    /// nonces from a counter. A nonce must never repeat under one key.
+    pub fn encrypt_with_nonce(&self, message: &[u8], nonce: [u8; 24]) -> Payload {
+        let cipher = XChaCha20Poly1305::new_from_slice(&self.0).unwrap();
+        let ciphertext = cipher.encrypt(XNonce::from_slice(&nonce), message).unwrap();
+
+        Payload {
+            nonce,
+            ciphertext,
+        }
+    }
+
     /// Decrypts a message. Will return error if message is corrupt or forged.
     pub fn decrypt(&self, payload: Payload) -> Result<Vec<u8>, DecryptError> {
         let cipher = XChaCha20Poly1305::new_from_slice(&self.0).unwrap();
@@ -243,3 +598,218 @@ impl KeyShared {
             .map_err(|_| DecryptError::AuthenticationFailed)
     }
 }
+
+/// `fixed32`; reads `bytes` too. See `from_value`.
+impl Pack for KeyPub {
+    fn write<W: ValueWriter>(&self, w: &mut W) -> neopack::Result<()> {
+        w.fixed32(&self.0)?;
+        Ok(())
+    }
+
+    fn read(value: ValueDecoder<'_>) -> neopack::Result<Self> {
+        KeyPub::from_value(&value)
+    }
+}
+
+/// `fixed32`; reads `bytes` too. See `from_value`.
+impl Pack for Hash {
+    fn write<W: ValueWriter>(&self, w: &mut W) -> neopack::Result<()> {
+        w.fixed32(&self.0)?;
+        Ok(())
+    }
+
+    fn read(value: ValueDecoder<'_>) -> neopack::Result<Self> {
+        Hash::from_value(&value)
+    }
+}
+
+/// 64 `bytes`.
+impl Pack for Signature {
+    fn write<W: ValueWriter>(&self, w: &mut W) -> neopack::Result<()> {
+        w.bytes(&self.0)?;
+        Ok(())
+    }
+
+    fn read(value: ValueDecoder<'_>) -> neopack::Result<Self> {
+        let bytes = value.as_bytes()?;
+        bytes.try_into().map(Signature).map_err(|_| neopack::Error::Malformed)
+    }
+}
+
+/// A List: `[nonce: Bytes, ciphertext: Bytes]`.
+impl Pack for Payload {
+    fn write<W: ValueWriter>(&self, w: &mut W) -> neopack::Result<()> {
+        let mut list = w.list()?;
+        list.bytes(&self.nonce)?;
+        list.bytes(&self.ciphertext)?;
+        list.finish()?;
+        Ok(())
+    }
+
+    fn read(value: ValueDecoder<'_>) -> neopack::Result<Self> {
+        let ValueDecoder::List(mut list) = value else {
+            return Err(neopack::Error::TypeMismatch);
+        };
+        let nonce = list.next()?.ok_or(neopack::Error::Malformed)?.as_bytes()?;
+        let ciphertext = list.next()?.ok_or(neopack::Error::Malformed)?.as_bytes()?;
+        if list.next()?.is_some() {
+            return Err(neopack::Error::Malformed);
+        }
+        Ok(Payload {
+            nonce: nonce.try_into().map_err(|_| neopack::Error::Malformed)?,
+            ciphertext: ciphertext.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::CryptoRng;
+    use rand_core::RngCore;
+
+    /// Deterministic rng for tests: the blake3 output stream of a seed.
+    struct SeededRng(blake3::OutputReader);
+
+    impl SeededRng {
+        fn new(seed: &[u8]) -> Self {
+            SeededRng(Hasher::new().update(seed).finalize_xof())
+        }
+    }
+
+    impl RngCore for SeededRng {
+        fn next_u32(&mut self) -> u32 {
+            rand_core::impls::next_u32_via_fill(self)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            rand_core::impls::next_u64_via_fill(self)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            self.0.fill(dest);
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for SeededRng {}
+
+    #[test]
+    fn seeded_keys_and_nonces_are_reproducible() {
+        let alice = KeyPair::from_seed([1; 32]);
+        assert_eq!(alice.key_pub, KeyPair::from_seed([1; 32]).key_pub);
+        assert_ne!(alice.key_pub, KeyPair::from_seed([2; 32]).key_pub);
+
+        let bob = KeyPair::generate(&mut SeededRng::new(b"bob"));
+        assert_eq!(bob.key_pub, KeyPair::generate(&mut SeededRng::new(b"bob")).key_pub);
+
+        let first = alice.encrypt_with(&bob.key_pub, b"hello", &mut SeededRng::new(b"nonce"));
+        let second = alice.encrypt_with(&bob.key_pub, b"hello", &mut SeededRng::new(b"nonce"));
+        assert_eq!(first.nonce, second.nonce);
+        assert_eq!(first.ciphertext, second.ciphertext);
+        assert_eq!(bob.decrypt(&alice.key_pub, first).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn box_keys_and_bundles() {
+        let alice = KeyPair::from_seed([1; 32]);
+        let alice_box = BoxKeyPair::generate(&mut SeededRng::new(b"alice box"));
+        let bob_box = BoxKeyPair::generate(&mut SeededRng::new(b"bob box"));
+
+        // Box keys agree without touching the signing keys
+        assert_eq!(alice_box.conspire(&bob_box.box_pub), bob_box.conspire(&alice_box.box_pub));
+        let rebuilt = BoxKeyPair::from_secret(alice_box.box_sec.0);
+        assert_eq!(rebuilt.box_pub, alice_box.box_pub);
+
+        let payload = alice_box.encrypt_with(&bob_box.box_pub, b"hi", &mut SeededRng::new(b"n"));
+        assert_eq!(bob_box.decrypt(&alice_box.box_pub, payload).unwrap(), b"hi");
+
+        // Published bundles round-trip and reject a swapped box key
+        let bundle = KeyBundle::new(&alice, &alice_box.box_pub);
+        let parsed = KeyBundle::from_bytes(&bundle.to_bytes()).unwrap();
+        assert_eq!(parsed, bundle);
+        let mut forged = bundle.to_bytes();
+        forged[32..64].copy_from_slice(&bob_box.box_pub.0);
+        assert!(matches!(KeyBundle::from_bytes(&forged), Err(BundleError::BadSignature)));
+        assert!(matches!(KeyBundle::from_bytes(&forged[..100]), Err(BundleError::InvalidLength)));
+
+        // The compatibility path matches the old ed25519 conversion
+        let bob = KeyPair::from_seed([2; 32]);
+        let converted = BoxKeyPair::from_signing(&alice);
+        let bob_converted = BoxPub::from_signing(&bob.key_pub).unwrap();
+        assert_eq!(converted.conspire(&bob_converted), alice.conspire(&bob.key_pub));
+        assert_eq!(alice.conspire(&bob.key_pub), bob.conspire(&alice.key_pub));
+    }
+
+    #[test]
+    fn sealed_box() {
+        let owner = KeyPair::from_seed([3; 32]);
+        let payload = owner.key_pub.seal_with(b"sealed", &mut SeededRng::new(b"seal")).unwrap();
+
+        // Known vector: fixed recipient and ephemeral rng
+        assert_eq!(hex::encode(&payload.nonce), "20e881e0bdee50343c1d860f345b3ec718c6f71c85a7b5f9");
+        assert_eq!(hex::encode(&payload.ciphertext), "358f3bc64f42eed7d365ebcabc801e42966329d7b14052e7d349b23adb867d2a3eb965fdd5c98a146cec9f28c0726dfb642a77da198c");
+
+        assert_eq!(owner.unseal(payload.clone()).unwrap(), b"sealed");
+        assert!(KeyPair::from_seed([4; 32]).unseal(payload.clone()).is_err());
+
+        // Two seals of the same message share nothing
+        let other = owner.key_pub.seal_with(b"sealed", &mut SeededRng::new(b"other")).unwrap();
+        assert_ne!(other.ciphertext[..32], payload.ciphertext[..32]);
+
+        let mut truncated = payload;
+        truncated.ciphertext.truncate(31);
+        assert!(matches!(owner.unseal(truncated), Err(DecryptError::InvalidPayload)));
+
+        // Nothing can be sealed to bytes that aren't a key
+        let invalid = KeyPub([2; 32]);
+        assert!(matches!(invalid.seal_with(b"sealed", &mut SeededRng::new(b"seal")), Err(InvalidKey)));
+    }
+
+    #[test]
+    fn keys_read_from_either_encoding() {
+        let key = KeyPair::from_seed([5; 32]).key_pub;
+        let mut enc = neopack::Encoder::new();
+        enc.fixed32(&key.0).unwrap();
+        enc.bytes(&key.0).unwrap();
+        enc.bytes(&key.0[..31]).unwrap();
+
+        let mut dec = neopack::Decoder::new(enc.as_bytes());
+        assert_eq!(KeyPub::from_value(&dec.value().unwrap()).unwrap(), key);
+        assert_eq!(Hash::from_value(&dec.value().unwrap()).unwrap(), Hash(key.0));
+        assert!(KeyPub::from_value(&dec.value().unwrap()).is_err());
+    }
+
+    #[test]
+    fn key_types_pack_round_trip() {
+        let pair = KeyPair::from_seed([6; 32]);
+        let signature = pair.sign(b"message");
+        let payload = pair.key_pub.seal_with(b"secret", &mut SeededRng::new(b"pack")).unwrap();
+
+        let mut enc = neopack::Encoder::new();
+        let mut list = enc.list().unwrap();
+        pair.key_pub.write(&mut list).unwrap();
+        hash(b"message").write(&mut list).unwrap();
+        signature.write(&mut list).unwrap();
+        payload.write(&mut list).unwrap();
+        list.finish().unwrap();
+
+        let mut dec = neopack::Decoder::new(enc.as_bytes());
+        let mut list = dec.list().unwrap();
+        assert_eq!(KeyPub::read(list.next().unwrap().unwrap()).unwrap(), pair.key_pub);
+        assert_eq!(Hash::read(list.next().unwrap().unwrap()).unwrap(), hash(b"message"));
+        assert_eq!(Signature::read(list.next().unwrap().unwrap()).unwrap(), signature);
+        let read = Payload::read(list.next().unwrap().unwrap()).unwrap();
+        assert_eq!((read.nonce, &read.ciphertext), (payload.nonce, &payload.ciphertext));
+
+        assert_eq!(Signature::decode(&signature.encode().unwrap()).unwrap(), signature);
+        let mut short = neopack::Encoder::new();
+        short.bytes(&signature.0[..63]).unwrap();
+        assert!(Signature::decode(short.as_bytes()).is_err());
+        assert!(Hash::decode(&[pair.key_pub.encode().unwrap(), vec![0]].concat()).is_err());
+    }
+}
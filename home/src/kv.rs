@@ -0,0 +1,301 @@
+//! KvStore: a key-value database kept as a log of operations in an IsoCore
+//!
+//! `put` and `delete` append a `KvOp` record; nothing is ever overwritten,
+//! so every earlier version of a key stays in the log for `history`. An
+//! in-memory index from each key to the items that touched it is rebuilt
+//! from the log on load and kept current on each write.
+//!
+//! Values are read back through `IsoCore::get_message`, which checks each
+//! record against its leaf hash, and `verify_head` checks the signed root
+//! over the whole log, so every answer is backed by the core's proofs.
+//! Since nothing is overwritten, `get_at` can also answer as of any
+//! earlier length of the log.
+//!
+//! Records are neopack Lists: `["put", key: Bytes, value: Bytes]` or
+//! `["del", key: Bytes]`.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+use crate::covering::ItemId;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::key::Hash;
+use crate::key::KeyPair;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+
+#[derive(Debug)]
+pub enum KvError {
+    IsoCore(IsoCoreError),
+    Neopack(neopack::Error),
+    /// The item isn't a well-formed operation record.
+    BadRecord(ItemId),
+}
+
+impl From<IsoCoreError> for KvError {
+    fn from(err: IsoCoreError) -> Self {
+        return KvError::IsoCore(err);
+    }
+}
+
+impl From<neopack::Error> for KvError {
+    fn from(err: neopack::Error) -> Self {
+        return KvError::Neopack(err);
+    }
+}
+
+/// One operation record in the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvOp {
+    Put { key: Vec<u8>, value: Vec<u8> },
+    Delete { key: Vec<u8> },
+}
+
+impl KvOp {
+    pub fn key(&self) -> &[u8] {
+        return match self {
+            KvOp::Put { key, .. } => key,
+            KvOp::Delete { key } => key,
+        };
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        match self {
+            KvOp::Put { key, value } => {
+                list.str("put")?;
+                list.bytes(key)?;
+                list.bytes(value)?;
+            }
+            KvOp::Delete { key } => {
+                list.str("del")?;
+                list.bytes(key)?;
+            }
+        }
+        list.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, neopack::Error> {
+        let mut dec = Decoder::new(bytes);
+        let mut list = dec.list()?;
+        let mut next = || list.next()?.ok_or(neopack::Error::Malformed);
+        let op = next()?.as_str()?;
+        let key = next()?.as_bytes()?.to_vec();
+        return match op {
+            "put" => Ok(KvOp::Put { key, value: next()?.as_bytes()?.to_vec() }),
+            "del" => Ok(KvOp::Delete { key }),
+            _ => Err(neopack::Error::Malformed),
+        };
+    }
+}
+
+/// One version of a key, from `KvStore::history`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KvVersion {
+    pub item_id: ItemId,
+    /// `None` for a delete.
+    pub value: Option<Vec<u8>>,
+}
+
+/// Items that touched a key, oldest first.
+#[derive(Debug, Default)]
+struct KeyEntry {
+    versions: Vec<ItemId>,
+    deleted: bool,
+}
+
+#[derive(Debug)]
+pub struct KvStore {
+    core: IsoCore,
+    index: HashMap<Vec<u8>, KeyEntry>,
+}
+
+impl KvStore {
+    /// Wraps `core`, indexing every record already in it.
+    pub fn new(core: IsoCore) -> Result<Self, KvError> {
+        let mut kv = Self {
+            core,
+            index: HashMap::new(),
+        };
+        for item in 0..kv.core.len().0 as u64 {
+            let item_id = ItemId(item);
+            let op = KvOp::from_bytes(&kv.core.get_message(item_id)?)
+                .map_err(|_| KvError::BadRecord(item_id))?;
+            kv.index_op(item_id, &op);
+        }
+        return Ok(kv);
+    }
+
+    pub fn create_mem(signer: &KeyPair) -> Self {
+        return Self {
+            core: IsoCore::create_mem(signer),
+            index: HashMap::new(),
+        };
+    }
+
+    pub fn create(path: PathBuf, signer: &KeyPair) -> Result<Self, KvError> {
+        return Ok(Self {
+            core: IsoCore::create(path, signer)?,
+            index: HashMap::new(),
+        });
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, KvError> {
+        return Self::new(IsoCore::load(path)?);
+    }
+
+    /// The log the store is kept in, for reads such as its length or
+    /// signer. Writes go through `put` and `delete`, which keep the index
+    /// in step with it.
+    pub fn core(&self) -> &IsoCore {
+        return &self.core;
+    }
+
+    pub fn flush(&mut self) -> Result<(), KvError> {
+        self.core.flush()?;
+        return Ok(());
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8], signer: &KeyPair) -> Result<ItemId, KvError> {
+        let op = KvOp::Put { key: key.to_vec(), value: value.to_vec() };
+        return self.append(op, signer);
+    }
+
+    /// Appends a tombstone for `key`, whether or not it is set.
+    pub fn delete(&mut self, key: &[u8], signer: &KeyPair) -> Result<ItemId, KvError> {
+        return self.append(KvOp::Delete { key: key.to_vec() }, signer);
+    }
+
+    fn append(&mut self, op: KvOp, signer: &KeyPair) -> Result<ItemId, KvError> {
+        let item_id = ItemId(self.core.len().0 as u64);
+        self.core.add_message(&op.to_bytes()?, signer)?;
+        self.index_op(item_id, &op);
+        return Ok(item_id);
+    }
+
+    fn index_op(&mut self, item_id: ItemId, op: &KvOp) {
+        let entry = self.index.entry(op.key().to_vec()).or_default();
+        entry.versions.push(item_id);
+        entry.deleted = matches!(op, KvOp::Delete { .. });
+    }
+
+    /// The current value of `key`, read and checked against the log.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, KvError> {
+        let Some(entry) = self.index.get(key) else {
+            return Ok(None);
+        };
+        let Some(&item_id) = entry.versions.last() else {
+            return Ok(None);
+        };
+        if entry.deleted {
+            return Ok(None);
+        }
+        return Ok(self.read(item_id)?.value);
+    }
+
+    /// The value `key` had after the first `len` items, read through
+    /// `IsoCore::at`.
+    pub fn get_at(&mut self, key: &[u8], len: u64) -> Result<Option<Vec<u8>>, KvError> {
+        let item_id = self.index.get(key)
+            .and_then(|entry| entry.versions.iter().rev().find(|item_id| item_id.0 < len).copied());
+        let mut history = self.core.at(len)?;
+        let Some(item_id) = item_id else {
+            return Ok(None);
+        };
+        let op = KvOp::from_bytes(&history.get_message(item_id)?)
+            .map_err(|_| KvError::BadRecord(item_id))?;
+        return Ok(match op {
+            KvOp::Put { value, .. } => Some(value),
+            KvOp::Delete { .. } => None,
+        });
+    }
+
+    /// Every version of `key`, newest first, deletes included.
+    pub fn history(&mut self, key: &[u8]) -> Result<Vec<KvVersion>, KvError> {
+        let items = self.index.get(key)
+            .map(|entry| entry.versions.clone())
+            .unwrap_or_default();
+        return items.into_iter().rev().map(|item_id| self.read(item_id)).collect();
+    }
+
+    fn read(&mut self, item_id: ItemId) -> Result<KvVersion, KvError> {
+        let op = KvOp::from_bytes(&self.core.get_message(item_id)?)
+            .map_err(|_| KvError::BadRecord(item_id))?;
+        let value = match op {
+            KvOp::Put { value, .. } => Some(value),
+            KvOp::Delete { .. } => None,
+        };
+        return Ok(KvVersion { item_id, value });
+    }
+
+    /// Keys that currently have a value, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        return self.index.iter()
+            .filter(|(_, entry)| !entry.deleted)
+            .map(|(key, _)| key.as_slice());
+    }
+
+    /// Checks the signed root over the whole log. See `IsoCore::verify_head`.
+    pub fn verify_head(&mut self) -> Result<Hash, KvError> {
+        return Ok(self.core.verify_head()?);
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kv_put_get_delete_history() {
+        let path = PathBuf::from("/tmp/test_kv_store");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+
+        let mut kv = KvStore::create(path.clone(), &signer).unwrap();
+        kv.put(b"colour", b"red", &signer).unwrap();
+        kv.put(b"shape", b"round", &signer).unwrap();
+        kv.put(b"colour", b"blue", &signer).unwrap();
+        assert_eq!(kv.get(b"colour").unwrap(), Some(b"blue".to_vec()));
+        assert_eq!(kv.get(b"size").unwrap(), None);
+
+        kv.delete(b"shape", &signer).unwrap();
+        assert_eq!(kv.get(b"shape").unwrap(), None);
+        assert_eq!(kv.keys().collect::<Vec<_>>(), vec![b"colour".as_slice()]);
+        kv.verify_head().unwrap();
+        assert_eq!(kv.core().len().0, 4);
+        kv.flush().unwrap();
+        drop(kv);
+
+        // The index is rebuilt from the log
+        let mut kv = KvStore::load(&path).unwrap();
+        assert_eq!(kv.get(b"colour").unwrap(), Some(b"blue".to_vec()));
+        assert_eq!(kv.get_at(b"colour", 0).unwrap(), None);
+        assert_eq!(kv.get_at(b"colour", 2).unwrap(), Some(b"red".to_vec()));
+        assert_eq!(kv.get_at(b"shape", 3).unwrap(), Some(b"round".to_vec()));
+        assert_eq!(kv.get_at(b"shape", 4).unwrap(), None);
+        assert!(kv.get_at(b"shape", 5).is_err());
+        assert_eq!(kv.get(b"shape").unwrap(), None);
+        assert_eq!(kv.history(b"colour").unwrap(), vec![
+            KvVersion { item_id: ItemId(2), value: Some(b"blue".to_vec()) },
+            KvVersion { item_id: ItemId(0), value: Some(b"red".to_vec()) },
+        ]);
+        assert_eq!(kv.history(b"shape").unwrap(), vec![
+            KvVersion { item_id: ItemId(3), value: None },
+            KvVersion { item_id: ItemId(1), value: Some(b"round".to_vec()) },
+        ]);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn kv_rejects_foreign_records() {
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::create_mem(&signer);
+        core.add_message(b"not an op", &signer).unwrap();
+        assert!(matches!(KvStore::new(core), Err(KvError::BadRecord(ItemId(0)))));
+    }
+}
@@ -1,10 +1,81 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 // pub mod isopack;
 pub mod neopack;
 pub mod jumpheader;
+pub mod hex;
+#[cfg(feature = "disk")]
+pub mod platform;
+#[cfg(feature = "disk")]
 pub mod neodisk;
+#[cfg(feature = "disk")]
+pub mod neodir;
+#[cfg(feature = "disk")]
 pub mod core;
+#[cfg(all(feature = "disk", any(test, feature = "testutil")))]
+pub mod testutil;
+#[cfg(feature = "std")]
 pub mod key;
+#[cfg(feature = "std")]
+pub mod format;
+#[cfg(feature = "std")]
+pub mod metrics;
 pub mod covering;
+#[cfg(feature = "disk")]
 pub mod isocore;
+#[cfg(feature = "disk")]
+pub mod shared;
+#[cfg(feature = "disk")]
+pub mod readonly;
+#[cfg(feature = "vector-commitment")]
+pub mod commitment;
+#[cfg(feature = "std")]
+pub mod proof;
+#[cfg(feature = "std")]
+pub mod verify;
+#[cfg(feature = "std")]
+pub mod witness;
+#[cfg(feature = "disk")]
+pub mod link;
+#[cfg(feature = "disk")]
+pub mod encrypted;
+#[cfg(feature = "disk")]
+pub mod view;
+#[cfg(feature = "disk")]
+pub mod kv;
+#[cfg(feature = "disk")]
+pub mod channel;
+#[cfg(feature = "disk")]
+pub mod merge;
+#[cfg(feature = "disk")]
+pub mod txn;
+#[cfg(feature = "disk")]
+pub mod schema;
+#[cfg(feature = "disk")]
+pub mod replicate;
+#[cfg(feature = "disk")]
+pub mod remote;
+#[cfg(feature = "std")]
+pub mod transport;
+#[cfg(feature = "std")]
+pub mod handshake;
+#[cfg(feature = "discovery")]
+pub mod discovery;
+#[cfg(feature = "std")]
 pub mod store;
+#[cfg(feature = "disk")]
+pub mod blob;
+#[cfg(feature = "disk")]
+pub mod backup;
+#[cfg(feature = "std")]
 pub mod markup;
+#[cfg(feature = "disk")]
+pub mod site;
+#[cfg(feature = "search")]
+pub mod search;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
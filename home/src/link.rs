@@ -0,0 +1,192 @@
+//! Links: verifiable references from one core to an item in another
+//!
+//! A `Link` names an item by its core's public key and item id, and pins it
+//! to a signed root of that core. It may carry an `InclusionProof`, or leave
+//! the proof to be fetched from whoever holds the core and checked with
+//! `verify_with`.
+//!
+//! # Trust model
+//!
+//! A verified link shows that the holder of `pubkey` signed `root`, and
+//! that `root` commits to an item with a particular leaf hash at `item_id`.
+//! With the item's bytes, `verify_message` shows they are that item. Only
+//! the public key has to be trusted, and nothing about where the proof
+//! came from.
+//!
+//! A link does not show that `root` is the core's latest root, or that the
+//! signer never signed a different history under the same key. Two links to
+//! one core can only be trusted to agree if they pin the same root.
+//!
+//! Encoded as a neopack Map: `pubkey: Bytes`, `item: U64`, `root: Bytes`,
+//! and, if present, `proof: Bytes` holding an encoded `InclusionProof`.
+
+use crate::covering::ItemId;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::key::Hash;
+use crate::key::KeyPub;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+use crate::neopack::Pack;
+use crate::proof::InclusionProof;
+use crate::proof::ProofError;
+
+#[derive(Debug)]
+pub enum LinkError {
+    Neopack(neopack::Error),
+    Proof(ProofError),
+    /// `verify` was called on a link without a proof.
+    MissingProof,
+    /// The proof is for a different item or root than the link names.
+    Mismatch,
+    /// The data given isn't the linked item.
+    WrongData,
+    /// The encoding is missing a field or has one of the wrong length.
+    Format,
+}
+
+impl From<neopack::Error> for LinkError {
+    fn from(err: neopack::Error) -> Self {
+        return LinkError::Neopack(err);
+    }
+}
+
+impl From<ProofError> for LinkError {
+    fn from(err: ProofError) -> Self {
+        return LinkError::Proof(err);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub pubkey: KeyPub,
+    pub item_id: ItemId,
+    /// Signed root the item is pinned to.
+    pub root: Hash,
+    pub proof: Option<InclusionProof>,
+}
+
+impl Link {
+    /// Links to `item_id` in `core`, pinned to its current root, with a
+    /// proof attached.
+    pub fn new(core: &mut IsoCore, item_id: ItemId) -> Result<Self, IsoCoreError> {
+        let proof = core.prove(item_id, core.len().0 as u64)?;
+        let root = proof.root().map_err(|_| IsoCoreError::IntegrityError)?;
+        return Ok(Link {
+            pubkey: core.signer().clone(),
+            item_id,
+            root,
+            proof: Some(proof),
+        });
+    }
+
+    /// The same link with its proof dropped, for a compact reference.
+    pub fn without_proof(&self) -> Self {
+        return Link { proof: None, ..self.clone() };
+    }
+
+    /// Checks the link's own proof.
+    pub fn verify(&self) -> Result<(), LinkError> {
+        let proof = self.proof.as_ref().ok_or(LinkError::MissingProof)?;
+        return self.verify_with(proof);
+    }
+
+    /// Checks a fetched `proof` against the link: it must be for the linked
+    /// item, rebuild the pinned root, and carry the signer's signature.
+    pub fn verify_with(&self, proof: &InclusionProof) -> Result<(), LinkError> {
+        if proof.item_id != self.item_id {
+            return Err(LinkError::Mismatch);
+        }
+        if proof.verify(&self.pubkey)? != self.root {
+            return Err(LinkError::Mismatch);
+        }
+        return Ok(());
+    }
+
+    /// Checks the link's proof, and that `data` is the linked item.
+    pub fn verify_message(&self, data: &[u8]) -> Result<(), LinkError> {
+        self.verify()?;
+        let proof = self.proof.as_ref().ok_or(LinkError::MissingProof)?;
+        if !proof.matches(data) {
+            return Err(LinkError::WrongData);
+        }
+        return Ok(());
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        self.pubkey.write(&mut map.key("pubkey")?)?;
+        map.key("item")?.u64(self.item_id.0)?;
+        self.root.write(&mut map.key("root")?)?;
+        if let Some(proof) = &self.proof {
+            map.key("proof")?.bytes(&proof.to_bytes()?)?;
+        }
+        map.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LinkError> {
+        let mut dec = Decoder::new(bytes);
+        let mut map = dec.map()?;
+
+        let Some(("pubkey", pubkey)) = map.next()? else {
+            return Err(LinkError::Format);
+        };
+        let pubkey = KeyPub::read(pubkey).map_err(|_| LinkError::Format)?;
+        let Some(("item", item)) = map.next()? else {
+            return Err(LinkError::Format);
+        };
+        let item_id = ItemId(item.as_u64()?);
+        let Some(("root", root)) = map.next()? else {
+            return Err(LinkError::Format);
+        };
+        let root = Hash::read(root).map_err(|_| LinkError::Format)?;
+        let proof = match map.next()? {
+            Some(("proof", proof)) => Some(InclusionProof::from_bytes(proof.as_bytes()?)?),
+            None => None,
+            Some(_) => return Err(LinkError::Format),
+        };
+
+        return Ok(Link {
+            pubkey,
+            item_id,
+            root,
+            proof,
+        });
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+    use crate::key::KeyPair;
+
+    #[test]
+    fn link_round_trip_and_verify() {
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::create_mem(&signer);
+        for i in 0..50u32 {
+            core.add_message(format!("post {}", i).as_bytes(), &signer).unwrap();
+        }
+
+        let link = Link::new(&mut core, ItemId(42)).unwrap();
+        let decoded = Link::from_bytes(&link.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded, link);
+        decoded.verify_message(b"post 42").unwrap();
+        assert!(matches!(decoded.verify_message(b"post 41"), Err(LinkError::WrongData)));
+
+        // A bare reference is checked against a proof fetched later
+        let bare = Link::from_bytes(&link.without_proof().to_bytes().unwrap()).unwrap();
+        assert!(matches!(bare.verify(), Err(LinkError::MissingProof)));
+        let fetched = core.prove(ItemId(42), 50).unwrap();
+        bare.verify_with(&fetched).unwrap();
+
+        // Proofs for other items or roots don't satisfy the link
+        let other_item = core.prove(ItemId(41), 50).unwrap();
+        assert!(matches!(bare.verify_with(&other_item), Err(LinkError::Mismatch)));
+        let older_root = core.prove(ItemId(42), 43).unwrap();
+        assert!(matches!(bare.verify_with(&older_root), Err(LinkError::Mismatch)));
+    }
+}
@@ -1,9 +1,42 @@
+use home::core::Core;
+use home::isocore::AmplificationStats;
+use home::isocore::CoreStats;
 use home::isocore::IsoCore;
 use home::key::KeyPair;
+use std::path::Path;
 use std::path::PathBuf;
 
 pub fn main() {
-    let path = PathBuf::from("../cores/compact");
+    let mut args = std::env::args_os().skip(1);
+    match args.next() {
+        // `home inspect <path>` prints the covering tree and sizes of an
+        // existing core
+        Some(arg) if arg == "inspect" => {
+            let path = PathBuf::from(args.next().expect("usage: home inspect <path>"));
+            let mut isocore = IsoCore::load(&path).unwrap();
+            print!("{}", isocore.debug_tree());
+            print_stats(&isocore.stats().unwrap());
+            return;
+        }
+        // `home compact <dir> [--dry-run]` moves a core kept as a
+        // directory of message files into a log beside it
+        Some(arg) if arg == "compact" => {
+            let dir = PathBuf::from(args.next().expect("usage: home compact <dir> [--dry-run]"));
+            let dry_run = args.next().is_some_and(|arg| arg == "--dry-run");
+            let compaction = Core::compact_into_neodisk(&dir, dry_run).unwrap();
+            println!("Messages: {}", compaction.messages);
+            println!("Directory: {} bytes", compaction.dir_bytes);
+            println!("Log: {} bytes", compaction.log_bytes);
+            println!("Saved: {} bytes", compaction.saved());
+            return;
+        }
+        _ => {}
+    }
+
+    // Joined rather than written as one string so it suits any platform
+    let path = std::env::args_os().nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new("..").join("cores").join("compact"));
     let signer = KeyPair::ephemeral();
     
     println!("Creating new isocore at: {:?}", path);
@@ -13,31 +46,38 @@ pub fn main() {
     for i in 0..64 {
         let message = format!("message {}", i);
         let hash = isocore.add_message(message.as_bytes(), &signer).unwrap();
-        let hex_bytes = hash.to_hex();
-        let hex = String::from_utf8_lossy(&hex_bytes);
-        println!("Added message {}: {}", i, hex);
+        println!("Added message {}: {}", i, hash.to_hex());
     }
 
     println!("\nFlushing cores to disk...");
-    isocore.data_core.flush().unwrap();
-    isocore.verkle_core.flush().unwrap();
-    isocore.sig_core.flush().unwrap();
+    isocore.flush().unwrap();
 
     println!("Total messages: {}", isocore.len().0);
     
-    // Show file sizes
-    println!("\n=== File Analysis ===");
-    let data_path = path.join("data").join("core.nd");
-    let verkle_path = path.join("verkle").join("core.nd");
-    let sig_path = path.join("sig").join("core.nd");
-    
-    if let Ok(metadata) = std::fs::metadata(&data_path) {
-        println!("data/core.nd: {} bytes", metadata.len());
-    }
-    if let Ok(metadata) = std::fs::metadata(&verkle_path) {
-        println!("verkle/core.nd: {} bytes", metadata.len());
+    print_stats(&isocore.stats().unwrap());
+    print_amplification(&isocore.amplification_stats());
+}
+
+fn print_amplification(stats: &AmplificationStats) {
+    println!("\n=== Write Amplification ===");
+    println!("Appends: {} ({} message bytes)", stats.appends, stats.message_bytes);
+    println!("Data: {} bytes", stats.data_bytes);
+    println!("Tree nodes: {} ({} bytes)", stats.verkle_nodes, stats.verkle_bytes);
+    println!("Signatures: {} bytes", stats.sig_bytes);
+    if let (Some(ratio), Some(per_append), Some(nodes)) = (stats.ratio(), stats.bytes_per_append(), stats.nodes_per_append()) {
+        println!("Written per message byte: {:.2}", ratio);
+        println!("Per append: {:.1} bytes, {:.2} tree nodes", per_append, nodes);
     }
-    if let Ok(metadata) = std::fs::metadata(&sig_path) {
-        println!("sig/core.nd: {} bytes", metadata.len());
+}
+
+fn print_stats(stats: &CoreStats) {
+    println!("\n=== Size Analysis ===");
+    println!("Items: {}", stats.items);
+    println!("Payloads: {} bytes", stats.payload_bytes);
+    println!("Tree nodes: {} bytes", stats.verkle_bytes);
+    println!("Signatures: {} bytes", stats.sig_bytes);
+    for (name, bytes) in &stats.disk {
+        println!("{}: {} bytes on disk", name, bytes);
     }
+    println!("Total on disk: {} bytes", stats.disk_bytes());
 }
@@ -8,11 +8,29 @@
 //! Furthermore, each item may contain bold text.
 //! Bold text starts with `*` and ends with `*` or at the end of the line.
 //! Any character may be escaped with `\`.
+//!
+//! Blobs, such as images and attachments, are referenced inline as
+//! `![alt](hash:<64 hex digits>)`, naming the blob by the BLAKE3 hash it
+//! is stored under in a `BlobStore`. A reference that doesn't parse is
+//! kept as text. `blobs` collects the hashes a document refers to, so
+//! exporters and replicators can fetch them with the text.
+//!
+//! Parsed items render to HTML with `to_html`: headings become `h1`, each
+//! run of bullets one `ul`, lines `p`, and bold `strong`. Text is escaped,
+//! so markup can't inject tags. A blob becomes an `img` whose `src` is
+//! `blobs/<hex>`, relative to wherever the blobs are copied.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use crate::hex;
+use crate::key::Hash;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Style {
     Normal,
     Bold,
+    /// A blob reference; the fragment's text is its alt text.
+    Blob(Hash),
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -37,17 +55,22 @@ fn process_char(
                 buffer.push(rem[*index]);
             }
         }
-        b'*' => {
-            // Push current buffer if non-empty
-            if !buffer.is_empty() {
-                let contents = String::from_utf8(buffer.clone()).unwrap();
-                frags.push((*style, contents));
-                buffer.clear();
+        b'!' if rem[*index..].starts_with(b"![") => {
+            match parse_blob(&rem[*index..]) {
+                Some((hash, alt, len)) => {
+                    push_buffer(buffer, style, frags);
+                    frags.push((Style::Blob(hash), alt));
+                    *index += len - 1;
+                }
+                None => buffer.push(c),
             }
+        }
+        b'*' => {
+            push_buffer(buffer, style, frags);
             // Toggle style
             *style = match *style {
-                Style::Normal => Style::Bold,
                 Style::Bold => Style::Normal,
+                _ => Style::Bold,
             };
         }
         _ => {
@@ -57,6 +80,29 @@ fn process_char(
     return false;
 }
 
+/// Push current buffer if non-empty
+fn push_buffer(buffer: &mut Vec<u8>, style: &Style, frags: &mut Vec<(Style, String)>) {
+    if !buffer.is_empty() {
+        let contents = String::from_utf8(buffer.clone()).unwrap();
+        frags.push((style.clone(), contents));
+        buffer.clear();
+    }
+}
+
+/// Parse a blob reference, `![alt](hash:<hex>)`, at the start of `rem`.
+/// Returns the hash, the alt text, and the reference's length.
+fn parse_blob(rem: &[u8]) -> Option<(Hash, String, usize)> {
+    let rest = rem.strip_prefix(b"![")?;
+    let alt_len = rest.iter().position(|&c| c == b']' || c == b'\n')?;
+    let alt = String::from_utf8(rest[..alt_len].to_vec()).ok()?;
+    let digits = rest[alt_len..].strip_prefix(b"](hash:")?;
+    if digits.len() < 65 || digits[64] != b')' {
+        return None;
+    }
+    let hash = Hash::from_hex(std::str::from_utf8(&digits[..64]).ok()?).ok()?;
+    return Some((hash, alt, 2 + alt_len + 7 + 65));
+}
+
 /// Parse the next fragment.
 /// Filters out empty fragments, like `**` in `race**car`.
 /// Does not merge together like fragments after filtering, though!
@@ -78,7 +124,7 @@ pub fn parse_frag(rem: &[u8]) -> (Frag, &[u8]) {
     // Push remaining buffer
     if !buffer.is_empty() {
         let contents = String::from_utf8(buffer).unwrap();
-        frags.push((style, contents));
+        frags.push((style.clone(), contents));
     }
 
     return (Frag { frags }, &rem[index..]);
@@ -118,6 +164,99 @@ pub fn parse_string(string: String) -> Vec<Item> {
     return items;
 }
 
+/// Escapes text for use in HTML content and quoted attribute values.
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    return out;
+}
+
+impl Frag {
+    /// The text without styling.
+    pub fn plain(&self) -> String {
+        return self.frags.iter().map(|(_, text)| text.as_str()).collect();
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        for (style, text) in &self.frags {
+            match style {
+                Style::Blob(hash) => {
+                    let _ = write!(out, "<img src=\"blobs/{}\" alt=\"{}\">", hex::encode(&hash.0), escape_html(text));
+                }
+                Style::Normal => out.push_str(&escape_html(text)),
+                Style::Bold => {
+                    out.push_str("<strong>");
+                    out.push_str(&escape_html(text));
+                    out.push_str("</strong>");
+                }
+            }
+        }
+        return out;
+    }
+}
+
+/// Renders items as HTML, one element per line.
+pub fn to_html(items: &[Item]) -> String {
+    let mut out = String::new();
+    let mut in_list = false;
+    for item in items {
+        let bullet = matches!(item, Item::Bullet(_));
+        if in_list && !bullet {
+            out.push_str("</ul>\n");
+        }
+        if bullet && !in_list {
+            out.push_str("<ul>\n");
+        }
+        in_list = bullet;
+
+        let (open, close, frag) = match item {
+            Item::Heading(frag) => ("<h1>", "</h1>", frag),
+            Item::Bullet(frag) => ("<li>", "</li>", frag),
+            Item::Line(frag) => ("<p>", "</p>", frag),
+        };
+        out.push_str(open);
+        out.push_str(&frag.to_html());
+        out.push_str(close);
+        out.push('\n');
+    }
+    if in_list {
+        out.push_str("</ul>\n");
+    }
+    return out;
+}
+
+/// The text of the first heading, if there is one.
+pub fn title(items: &[Item]) -> Option<String> {
+    return items.iter().find_map(|item| match item {
+        Item::Heading(frag) => Some(frag.plain()),
+        _ => None,
+    });
+}
+
+/// The blobs the items refer to.
+pub fn blobs(items: &[Item]) -> BTreeSet<Hash> {
+    let mut hashes = BTreeSet::new();
+    for item in items {
+        let (Item::Heading(frag) | Item::Bullet(frag) | Item::Line(frag)) = item;
+        for (style, _) in &frag.frags {
+            if let Style::Blob(hash) = style {
+                hashes.insert(hash.clone());
+            }
+        }
+    }
+    return hashes;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -180,4 +319,31 @@ mod tests {
             Item::Line(Frag { frags: vec![(Style::Normal, "this is some ".to_string()), (Style::Bold, "text".to_string()), (Style::Normal, " info.".to_string())] }),
         ]);
     }
+
+    #[test]
+    fn html_example() {
+        let items = parse_string("# A <b> & *c*\n- one\n- two\nafter".to_string());
+        assert_eq!(to_html(&items), "<h1>A &lt;b&gt; &amp; <strong>c</strong></h1>\n<ul>\n<li>one</li>\n<li>two</li>\n</ul>\n<p>after</p>\n");
+        assert_eq!(title(&items).as_deref(), Some("A <b> & c"));
+        assert_eq!(title(&parse_string("no heading".to_string())), None);
+    }
+
+    #[test]
+    fn blob_examples() {
+        let a = "ab".repeat(32);
+        let b = "0f".repeat(32);
+        let s = format!("# *see ![a <cat>](hash:{a}) here*\n- ![](hash:{b}) and ![again](hash:{a})\n![bad](hash:{}) ![open", &a[..10]);
+        let items = parse_string(s);
+        assert_eq!(items[0], Item::Heading(Frag { frags: vec![
+            (Style::Bold, "see ".to_string()),
+            (Style::Blob(Hash::from_hex(&a).unwrap()), "a <cat>".to_string()),
+            (Style::Bold, " here".to_string()),
+        ] }));
+        assert_eq!(items[2], Item::Line(Frag { frags: vec![(Style::Normal, format!("![bad](hash:{}) ![open", &a[..10]))] }));
+        assert_eq!(blobs(&items), BTreeSet::from([Hash::from_hex(&a).unwrap(), Hash::from_hex(&b).unwrap()]));
+        assert_eq!(
+            to_html(&items[..1]),
+            format!("<h1><strong>see </strong><img src=\"blobs/{a}\" alt=\"a &lt;cat&gt;\"><strong> here</strong></h1>\n"),
+        );
+    }
 }
@@ -0,0 +1,194 @@
+//! Merging several IsoCores that hold one logical dataset
+//!
+//! Each device appends to its own core. Entries are `Stamped` with a Lamport
+//! timestamp: one more than the largest timestamp the writer has seen in any
+//! core, its own included. `merge` reads every core, checks its signed head
+//! and each entry's leaf hash, and interleaves the entries into one order
+//! that every device computes the same way:
+//!
+//! 1. Lamport timestamp, so an entry written after seeing another sorts
+//!    after it.
+//! 2. Leaf hash, to break ties between concurrent entries.
+//! 3. Source key and item id, for identical entries written on two devices.
+//!
+//! Every merged entry keeps its provenance (source key, item id, and leaf
+//! hash), so it can still be proven against the core it came from.
+//!
+//! Stamped entries are neopack Lists: `[lamport: u64, payload: Bytes]`.
+
+use crate::covering::ItemId;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::key::Hash;
+use crate::key::KeyPair;
+use crate::key::KeyPub;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+
+#[derive(Debug)]
+pub enum MergeError {
+    IsoCore(IsoCoreError),
+    Neopack(neopack::Error),
+    /// The item isn't a `Stamped` entry.
+    BadEntry { source: KeyPub, item_id: ItemId },
+    /// The item's timestamp isn't above the one before it in its core.
+    ClockWentBackwards { source: KeyPub, item_id: ItemId },
+}
+
+impl From<IsoCoreError> for MergeError {
+    fn from(err: IsoCoreError) -> Self {
+        return MergeError::IsoCore(err);
+    }
+}
+
+impl From<neopack::Error> for MergeError {
+    fn from(err: neopack::Error) -> Self {
+        return MergeError::Neopack(err);
+    }
+}
+
+/// A payload with the Lamport timestamp it was written at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stamped {
+    pub lamport: u64,
+    pub payload: Vec<u8>,
+}
+
+impl Stamped {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        list.u64(self.lamport)?;
+        list.bytes(&self.payload)?;
+        list.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, neopack::Error> {
+        let mut dec = Decoder::new(bytes);
+        let mut list = dec.list()?;
+        let lamport = list.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+        let payload = list.next()?.ok_or(neopack::Error::Malformed)?.as_bytes()?.to_vec();
+        return Ok(Stamped { lamport, payload });
+    }
+}
+
+/// Where a merged entry came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub source: KeyPub,
+    pub item_id: ItemId,
+    /// Leaf hash of the stamped entry in its source core.
+    pub hash: Hash,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedEntry {
+    pub lamport: u64,
+    pub payload: Vec<u8>,
+    pub provenance: Provenance,
+}
+
+impl MergedEntry {
+    fn sort_key(&self) -> (u64, &[u8; 32], &[u8; 32], ItemId) {
+        let provenance = &self.provenance;
+        return (self.lamport, &provenance.hash.0, &provenance.source.0, provenance.item_id);
+    }
+}
+
+/// Verifies each core's head and every entry in it, then returns all of
+/// their entries in merged order.
+pub fn merge<'a>(cores: impl IntoIterator<Item = &'a mut IsoCore>) -> Result<Vec<MergedEntry>, MergeError> {
+    let mut entries = Vec::new();
+    for core in cores {
+        if core.len().0 > 0 {
+            core.verify_head()?;
+        }
+
+        let source = core.signer().clone();
+        let version = core.version();
+        let mut last = None;
+        for item in 0..core.len().0 as u64 {
+            let item_id = ItemId(item);
+            let message = core.get_message(item_id)?;
+            let hash = version.hash_leaf(&message);
+            let Ok(stamped) = Stamped::from_bytes(&message) else {
+                return Err(MergeError::BadEntry { source, item_id });
+            };
+            if last.is_some_and(|last| stamped.lamport <= last) {
+                return Err(MergeError::ClockWentBackwards { source, item_id });
+            }
+            last = Some(stamped.lamport);
+
+            entries.push(MergedEntry {
+                lamport: stamped.lamport,
+                payload: stamped.payload,
+                provenance: Provenance { source: source.clone(), item_id, hash },
+            });
+        }
+    }
+
+    entries.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+    return Ok(entries);
+}
+
+/// The timestamp for a new entry written after seeing `entries`.
+pub fn next_lamport(entries: &[MergedEntry]) -> u64 {
+    return entries.iter().map(|e| e.lamport + 1).max().unwrap_or(0);
+}
+
+/// Appends `payload` to `core` stamped with `lamport`.
+pub fn append(core: &mut IsoCore, lamport: u64, payload: &[u8], signer: &KeyPair) -> Result<Hash, MergeError> {
+    let stamped = Stamped { lamport, payload: payload.to_vec() };
+    return Ok(core.add_message(&stamped.to_bytes()?, signer)?);
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_interleaves_devices() {
+        let laptop_key = KeyPair::ephemeral();
+        let phone_key = KeyPair::ephemeral();
+        let mut laptop = IsoCore::create_mem(&laptop_key);
+        let mut phone = IsoCore::create_mem(&phone_key);
+
+        // Both write before syncing, then the phone replies having seen all
+        append(&mut laptop, 0, b"laptop first", &laptop_key).unwrap();
+        append(&mut phone, 0, b"phone first", &phone_key).unwrap();
+        append(&mut laptop, 1, b"laptop second", &laptop_key).unwrap();
+        let seen = merge([&mut laptop, &mut phone]).unwrap();
+        append(&mut phone, next_lamport(&seen), b"phone reply", &phone_key).unwrap();
+
+        let merged = merge([&mut laptop, &mut phone]).unwrap();
+        let payloads: Vec<&[u8]> = merged.iter().map(|e| e.payload.as_slice()).collect();
+        assert_eq!(payloads[2..], [b"laptop second".as_slice(), b"phone reply"]);
+        let mut firsts = payloads[..2].to_vec();
+        firsts.sort();
+        assert_eq!(firsts, [b"laptop first".as_slice(), b"phone first"]);
+
+        // Same order no matter which core is read first
+        assert_eq!(merge([&mut phone, &mut laptop]).unwrap(), merged);
+
+        let reply = &merged[3].provenance;
+        assert_eq!(reply.source, phone_key.key_pub);
+        assert_eq!(reply.item_id, ItemId(1));
+        let version = phone.version();
+        let message = phone.get_message(reply.item_id).unwrap();
+        assert_eq!(version.hash_leaf(&message), reply.hash);
+    }
+
+    #[test]
+    fn merge_rejects_clock_going_backwards() {
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::create_mem(&signer);
+        append(&mut core, 5, b"later", &signer).unwrap();
+        append(&mut core, 5, b"same time", &signer).unwrap();
+        assert!(matches!(
+            merge([&mut core]),
+            Err(MergeError::ClockWentBackwards { item_id: ItemId(1), .. })
+        ));
+    }
+}
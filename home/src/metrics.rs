@@ -0,0 +1,172 @@
+//! Metrics hooks for embedders
+//!
+//! Storage reports what it does through a `Metrics` implementation, so an
+//! embedder can export append throughput, compression ratio, cache hit
+//! rate, and signing and sync times to Prometheus or anything similar.
+//! Every method does nothing by default, and nothing is reported until
+//! one is installed with `set_metrics` on a `NeoDiskWriter`, `Core`, or
+//! `IsoCore`; an IsoCore passes it on to its three cores.
+//!
+//! Metrics are named by the constants below. Counters are running totals
+//! reported as increments, gauges are the latest value, and histograms
+//! are individual observations, in seconds for anything timed.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Compressed bytes written to a log, frame headers included. Counter.
+pub const NEODISK_BYTES_WRITTEN: &str = "neodisk_bytes_written";
+/// Uncompressed message bytes in flushed frames. Counter.
+pub const NEODISK_BYTES_UNCOMPRESSED: &str = "neodisk_bytes_uncompressed";
+pub const NEODISK_FRAMES_FLUSHED: &str = "neodisk_frames_flushed";
+/// Uncompressed over compressed size of the last frame. Gauge.
+pub const NEODISK_COMPRESSION_RATIO: &str = "neodisk_compression_ratio";
+/// Time spent syncing a log to disk. Histogram.
+pub const NEODISK_SYNC_SECONDS: &str = "neodisk_sync_seconds";
+pub const CORE_MESSAGES_APPENDED: &str = "core_messages_appended";
+/// Reads answered from the cache. Counter.
+pub const CORE_CACHE_HITS: &str = "core_cache_hits";
+/// Reads that went to disk. Counter.
+pub const CORE_CACHE_MISSES: &str = "core_cache_misses";
+pub const ISOCORE_ITEMS_APPENDED: &str = "isocore_items_appended";
+/// Time spent producing each item's signature. Histogram.
+pub const ISOCORE_SIGN_SECONDS: &str = "isocore_sign_seconds";
+/// Time spent in each `IsoCore::flush`. Histogram.
+pub const ISOCORE_FLUSH_SECONDS: &str = "isocore_flush_seconds";
+
+/// Receives metrics. Implementations must be cheap; they're called on
+/// every append and read.
+pub trait Metrics: Send + Sync {
+    fn counter(&self, _name: &'static str, _increment: u64) {}
+    fn gauge(&self, _name: &'static str, _value: f64) {}
+    fn histogram(&self, _name: &'static str, _value: f64) {}
+}
+
+/// Discards everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoMetrics;
+
+impl Metrics for NoMetrics {}
+
+/// A shared `Metrics`, as held by storage types.
+#[derive(Clone)]
+pub struct MetricsHandle(Arc<dyn Metrics>);
+
+impl MetricsHandle {
+    pub fn new(metrics: Arc<dyn Metrics>) -> Self {
+        return MetricsHandle(metrics);
+    }
+
+    pub fn counter(&self, name: &'static str, increment: u64) {
+        self.0.counter(name, increment);
+    }
+
+    pub fn gauge(&self, name: &'static str, value: f64) {
+        self.0.gauge(name, value);
+    }
+
+    pub fn histogram(&self, name: &'static str, value: f64) {
+        self.0.histogram(name, value);
+    }
+
+    pub fn duration(&self, name: &'static str, elapsed: Duration) {
+        self.0.histogram(name, elapsed.as_secs_f64());
+    }
+}
+
+impl Default for MetricsHandle {
+    fn default() -> Self {
+        return MetricsHandle(Arc::new(NoMetrics));
+    }
+}
+
+impl fmt::Debug for MetricsHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str("MetricsHandle");
+    }
+}
+
+impl<M: Metrics + 'static> From<Arc<M>> for MetricsHandle {
+    fn from(metrics: Arc<M>) -> Self {
+        return MetricsHandle(metrics);
+    }
+}
+
+#[cfg(all(test, feature = "disk", feature = "rng"))]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use crate::covering::ItemId;
+    use crate::isocore::IsoCore;
+    use crate::key::KeyPair;
+
+    #[derive(Default)]
+    struct Recorder {
+        counters: Mutex<HashMap<&'static str, u64>>,
+        observed: Mutex<HashMap<&'static str, usize>>,
+    }
+
+    impl Metrics for Recorder {
+        fn counter(&self, name: &'static str, increment: u64) {
+            *self.counters.lock().unwrap().entry(name).or_default() += increment;
+        }
+
+        fn gauge(&self, name: &'static str, _value: f64) {
+            *self.observed.lock().unwrap().entry(name).or_default() += 1;
+        }
+
+        fn histogram(&self, name: &'static str, _value: f64) {
+            *self.observed.lock().unwrap().entry(name).or_default() += 1;
+        }
+    }
+
+    impl Recorder {
+        fn counter_value(&self, name: &'static str) -> u64 {
+            return self.counters.lock().unwrap().get(name).copied().unwrap_or(0);
+        }
+
+        fn observations(&self, name: &'static str) -> usize {
+            return self.observed.lock().unwrap().get(name).copied().unwrap_or(0);
+        }
+    }
+
+    #[test]
+    fn storage_reports_to_installed_metrics() {
+        let path = PathBuf::from("/tmp/test_metrics_isocore");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+
+        let recorder = Arc::new(Recorder::default());
+        let mut isocore = IsoCore::create(path.clone(), &signer).unwrap();
+        isocore.set_metrics(recorder.clone().into());
+        for i in 0..10u32 {
+            isocore.add_message(&i.to_le_bytes(), &signer).unwrap();
+        }
+        isocore.flush().unwrap();
+
+        assert_eq!(recorder.counter_value(ISOCORE_ITEMS_APPENDED), 10);
+        assert_eq!(recorder.observations(ISOCORE_SIGN_SECONDS), 10);
+        assert_eq!(recorder.observations(ISOCORE_FLUSH_SECONDS), 1);
+        // Data, verkle, and signature cores each flush one frame
+        assert_eq!(recorder.counter_value(NEODISK_FRAMES_FLUSHED), 3);
+        assert_eq!(recorder.observations(NEODISK_SYNC_SECONDS), 3);
+        assert!(recorder.counter_value(NEODISK_BYTES_WRITTEN) > 0);
+        assert!(recorder.counter_value(CORE_MESSAGES_APPENDED) > 10);
+        drop(isocore);
+
+        let recorder = Arc::new(Recorder::default());
+        let mut isocore = IsoCore::load(&path).unwrap();
+        isocore.set_metrics(recorder.clone().into());
+        isocore.get_message(ItemId(4)).unwrap();
+        isocore.get_message(ItemId(4)).unwrap();
+        // The message and the leaf node it's checked against come from
+        // disk once, then from the cache
+        assert_eq!(recorder.counter_value(CORE_CACHE_MISSES), 2);
+        assert!(recorder.counter_value(CORE_CACHE_HITS) >= 2);
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
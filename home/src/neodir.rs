@@ -0,0 +1,805 @@
+//! NeoDiskDir: a neodisk log split across segment files in one directory
+//!
+//! Layout:
+//!
+//! ```text
+//! dir/
+//!   manifest.nd    neopack Map { "segments": List<[segment, first, count, closed?, stored?]> }
+//!   00000000.nd    neodisk segment files, named by segment number in hex
+//!   00000001.nd
+//!   ...
+//!   00000002.frames  a segment moved into a frame store, see below
+//! ```
+//!
+//! Message ids run across segments. The writer appends to the last segment
+//! and starts a new one once `RotatePolicy` says the current one is full;
+//! segments only end between frames, so no message spans two files.
+//!
+//! The manifest is rewritten through a temporary file on every rotation,
+//! deletion, and flush. The last segment's count is always taken from the
+//! segment file itself, so appends made after the last manifest write
+//! survive a reopen.
+//!
+//! # Retention
+//!
+//! `prune` and `apply_retention` delete whole closed segments from the front
+//! of the log; the segment being written is never touched. Ids are never
+//! shifted or reused, so every message past the cut reads back unchanged,
+//! and ids before it fail with `Error::Pruned` rather than looking like
+//! they never existed.
+//!
+//! # Shared frames
+//!
+//! Mirrors of similar logs end up holding many identical frames. Given a
+//! `FrameStore`, the writer moves each segment's compressed frame data
+//! into it as the segment closes: every distinct frame is kept once,
+//! under its BLAKE3 hash, with a count of the segments that refer to it.
+//! The segment's own file is replaced by a `.frames` listing,
+//! `List<[header: Bytes, data: Fixed32]>`, that keeps its frame headers
+//! verbatim and the hash of each frame's data, and its manifest entry is
+//! marked stored. Reading it puts the original file back together in
+//! memory, so the same frames in two logs are only shared if their bytes
+//! match; writers with the same framing (see the neodisk docs on
+//! reproducible files) get that for identical message streams.
+//!
+//! Each step leaves the directory readable if interrupted: frames are
+//! stored before the listing is written, the listing before the manifest
+//! names it, and the segment file is only removed after that. Pruning a
+//! stored segment releases its frames after the manifest drops it, and a
+//! frame loses its count before its data. A crash can leak a frame, never
+//! lose one still referred to.
+//!
+//! Pruning drops message bytes and nothing else. Whatever authenticates
+//! the log (leaf hashes, tree nodes, signed roots) has to be stored outside
+//! the segments, the way an isocore keeps its verkle and signature cores
+//! apart from its data. Proofs over the remaining messages then still
+//! verify, and a pruned message can still be shown to have had a given
+//! hash, but its contents are gone for good.
+
+use std::borrow::Cow;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::hex;
+use crate::jumpheader::FrameHeader;
+use crate::neodisk::{footer, read_footer, scan_headers, write_atomic, Durability, Error, FlushPolicy, MessageId, NeoDiskReader, NeoDiskWriter, Result, FOOTER_SIZE};
+use crate::neopack::{Decoder, Encoder, ValueDecoder};
+
+const FILE_MANIFEST: &str = "manifest.nd";
+
+/// When a `NeoDiskDirWriter` moves on to a new segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotatePolicy {
+    /// Once the segment file reaches this many bytes
+    Bytes(u64),
+    /// Once the segment holds this many frames
+    Frames(u64),
+}
+
+/// Which closed segments `NeoDiskDirWriter::apply_retention` deletes.
+/// Segments are only ever deleted whole, so slightly more may be kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Keep at least the newest this many messages
+    KeepMessages(u64),
+    /// Keep this many closed segments besides the one being written
+    KeepSegments(usize),
+    /// Delete segments closed longer ago than this
+    MaxAge(Duration),
+}
+
+/// One segment's entry in the manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentInfo {
+    /// Segment number; also names the file
+    pub segment: u64,
+    /// Id of the segment's first message
+    pub first_message: u64,
+    pub message_count: u64,
+    /// Unix time in seconds when the writer moved past this segment;
+    /// `None` while it is still being written
+    pub closed_at: Option<u64>,
+    /// Whether the segment's frame data was moved into a `FrameStore`
+    pub stored: bool,
+}
+
+impl SegmentInfo {
+    /// Ids of the messages held in this segment
+    pub fn messages(&self) -> Range<MessageId> {
+        MessageId(self.first_message)..MessageId(self.first_message + self.message_count)
+    }
+
+    fn path(&self, dir: &Path) -> PathBuf {
+        segment_path(dir, self.segment)
+    }
+
+    /// The segment's own file: its log, or its listing once stored
+    fn file(&self, dir: &Path) -> PathBuf {
+        match self.stored {
+            true => stored_path(dir, self.segment),
+            false => self.path(dir),
+        }
+    }
+}
+
+/// Compressed frame data shared by the segments of any number of
+/// directories, each distinct frame stored once. See the module docs.
+#[derive(Debug, Clone)]
+pub struct FrameStore {
+    dir: PathBuf,
+    durability: Durability,
+}
+
+impl FrameStore {
+    /// Opens the store in `dir`, creating the directory if needed
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, durability: Durability::default() })
+    }
+
+    /// Sets how frames and their counts are synced
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    fn data_path(&self, hash: &[u8; 32]) -> PathBuf {
+        self.dir.join(hex::encode(hash))
+    }
+
+    fn refs_path(&self, hash: &[u8; 32]) -> PathBuf {
+        self.dir.join(format!("{}.refs", hex::encode(hash)))
+    }
+
+    /// Number of segments referring to the frame with this hash; 0 if it
+    /// isn't stored
+    pub fn refs(&self, hash: &[u8; 32]) -> Result<u64> {
+        match std::fs::read(self.refs_path(hash)) {
+            Ok(bytes) => Ok(u64::from_le_bytes(bytes.try_into().map_err(|_| Error::InvalidFormat)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Takes a reference on `data`, storing it if it's new, and returns
+    /// its hash
+    pub fn put(&self, data: &[u8]) -> Result<[u8; 32]> {
+        let hash = *blake3::hash(data).as_bytes();
+        let refs = self.refs(&hash)?;
+        if refs == 0 {
+            write_atomic(&self.data_path(&hash), data, self.durability)?;
+        }
+        write_atomic(&self.refs_path(&hash), &(refs + 1).to_le_bytes(), self.durability)?;
+        Ok(hash)
+    }
+
+    /// The frame data stored under `hash`, checked against it
+    pub fn get(&self, hash: &[u8; 32]) -> Result<Vec<u8>> {
+        let data = match std::fs::read(self.data_path(hash)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(Error::MissingFrame(*hash)),
+            Err(e) => return Err(e.into()),
+        };
+        if blake3::hash(&data).as_bytes() != hash {
+            return Err(Error::MissingFrame(*hash));
+        }
+        Ok(data)
+    }
+
+    /// Drops a reference on the frame with this hash, deleting it with
+    /// its last one. Returns whether it was deleted.
+    pub fn release(&self, hash: &[u8; 32]) -> Result<bool> {
+        match self.refs(hash)? {
+            0 => Err(Error::MissingFrame(*hash)),
+            1 => {
+                std::fs::remove_file(self.refs_path(hash))?;
+                std::fs::remove_file(self.data_path(hash))?;
+                Ok(true)
+            }
+            refs => {
+                write_atomic(&self.refs_path(hash), &(refs - 1).to_le_bytes(), self.durability)?;
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// Writer for segmented neodisk directories
+#[derive(Debug)]
+pub struct NeoDiskDirWriter {
+    path: PathBuf,
+    rotate: RotatePolicy,
+    flush_policy: FlushPolicy,
+    durability: Durability,
+    /// Live segments in order; the last one is being written
+    segments: Vec<SegmentInfo>,
+    writer: NeoDiskWriter,
+    /// Where closed segments' frames go, if anywhere
+    frames: Option<FrameStore>,
+}
+
+impl NeoDiskDirWriter {
+    /// Creates the directory with one empty segment
+    pub fn create<P: AsRef<Path>>(path: P, rotate: RotatePolicy) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        std::fs::create_dir_all(&path)?;
+        Durability::default().sync_parent(&path)?;
+
+        let segment = SegmentInfo { segment: 0, first_message: 0, message_count: 0, closed_at: None, stored: false };
+        let writer = NeoDiskWriter::create(segment.path(&path))?;
+        let dir = Self {
+            path,
+            rotate,
+            flush_policy: FlushPolicy::default(),
+            durability: Durability::default(),
+            segments: vec![segment],
+            writer,
+            frames: None,
+        };
+        dir.write_manifest()?;
+        Ok(dir)
+    }
+
+    /// Reopens a directory, continuing in its last segment
+    pub fn open<P: AsRef<Path>>(path: P, rotate: RotatePolicy) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut segments = read_manifest(&path)?;
+
+        let last = segments.last_mut().ok_or(Error::InvalidFormat)?;
+        let writer = NeoDiskWriter::open(last.path(&path))?;
+        last.message_count = writer.len();
+
+        Ok(Self {
+            path,
+            rotate,
+            flush_policy: FlushPolicy::default(),
+            durability: Durability::default(),
+            segments,
+            writer,
+            frames: None,
+        })
+    }
+
+    /// Sets when frames are closed within each segment
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = policy;
+        self.writer = self.writer.with_flush_policy(policy);
+        self
+    }
+
+    /// Moves each segment's frames into `store` as it is closed, and lets
+    /// segments already there be pruned
+    pub fn with_frame_store(mut self, store: FrameStore) -> Self {
+        self.frames = Some(store);
+        self
+    }
+
+    /// Sets how segments and the manifest are synced
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self.writer = self.writer.with_durability(durability);
+        self
+    }
+
+    pub fn append(&mut self, message: &[u8]) -> Result<MessageId> {
+        let local = self.writer.append(message)?;
+        let last = self.segments.last_mut().expect("always one segment");
+        let id = MessageId(last.first_message + local.0);
+        last.message_count = self.writer.len();
+
+        if self.segment_full()? {
+            self.rotate()?;
+        }
+        Ok(id)
+    }
+
+    /// Appends every message from `messages`, rotating segments as needed,
+    /// and returns the range of ids they were given
+    pub fn append_batch<I>(&mut self, messages: I) -> Result<Range<MessageId>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let start = MessageId(self.len());
+        for message in messages {
+            self.append(message.as_ref())?;
+        }
+        Ok(start..MessageId(self.len()))
+    }
+
+    fn segment_full(&self) -> Result<bool> {
+        Ok(match self.rotate {
+            RotatePolicy::Bytes(limit) => self.writer.file_size()? >= limit,
+            RotatePolicy::Frames(limit) => self.writer.frame_count() >= limit,
+        })
+    }
+
+    /// Closes the current segment, writing out its buffered frame, and
+    /// starts the next one
+    fn rotate(&mut self) -> Result<()> {
+        self.writer.flush()?;
+
+        let last = self.segments.last_mut().expect("always one segment");
+        last.closed_at = Some(unix_now());
+        if let Some(store) = &self.frames {
+            store_segment(&self.path, store, last.segment, self.durability)?;
+            last.stored = true;
+        }
+        let closed = last.clone();
+        let segment = SegmentInfo {
+            segment: last.segment + 1,
+            first_message: last.first_message + last.message_count,
+            message_count: 0,
+            closed_at: None,
+            stored: false,
+        };
+        self.writer = NeoDiskWriter::create(segment.path(&self.path))?
+            .with_flush_policy(self.flush_policy)
+            .with_durability(self.durability);
+        self.segments.push(segment);
+        self.write_manifest()?;
+
+        // Only once the manifest names the listing instead
+        if closed.stored {
+            std::fs::remove_file(closed.path(&self.path))?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.write_manifest()
+    }
+
+    /// Deletes every closed segment whose messages all come before `before`.
+    /// Returns how many were deleted.
+    pub fn prune(&mut self, before: MessageId) -> Result<usize> {
+        let closed = self.segments.len() - 1;
+        let count = self.segments[..closed].iter()
+            .take_while(|s| s.messages().end <= before)
+            .count();
+        if count == 0 {
+            return Ok(0);
+        }
+
+        // Drop them from the manifest first, so a crash leaves stray files
+        // rather than a manifest pointing at missing ones
+        if let Some(segment) = self.segments[..count].iter().find(|s| s.stored && self.frames.is_none()) {
+            return Err(Error::NeedsFrameStore(segment.segment));
+        }
+        let removed: Vec<SegmentInfo> = self.segments.drain(..count).collect();
+        self.write_manifest()?;
+        for segment in &removed {
+            if let (true, Some(store)) = (segment.stored, &self.frames) {
+                for (_, hash) in read_listing(&self.path, segment.segment)? {
+                    store.release(&hash)?;
+                }
+            }
+            std::fs::remove_file(segment.file(&self.path))?;
+        }
+        Ok(count)
+    }
+
+    /// Deletes the closed segments `policy` doesn't keep. Returns how
+    /// many were deleted.
+    pub fn apply_retention(&mut self, policy: RetentionPolicy) -> Result<usize> {
+        let closed = &self.segments[..self.segments.len() - 1];
+        let before = match policy {
+            RetentionPolicy::KeepMessages(count) => self.len().saturating_sub(count),
+            // Keeping none cuts up to the live segment
+            RetentionPolicy::KeepSegments(count) => closed.len()
+                .checked_sub(count)
+                .and_then(|drop| self.segments.get(drop))
+                .map(|s| s.first_message)
+                .unwrap_or(0),
+            RetentionPolicy::MaxAge(age) => {
+                let cutoff = unix_now().saturating_sub(age.as_secs());
+                closed.iter()
+                    .take_while(|s| s.closed_at.is_some_and(|t| t <= cutoff))
+                    .last()
+                    .map(|s| s.messages().end.0)
+                    .unwrap_or(0)
+            }
+        };
+        self.prune(MessageId(before))
+    }
+
+    /// Live segments in order, the one being written last
+    pub fn segments(&self) -> &[SegmentInfo] {
+        &self.segments
+    }
+
+    /// One past the last message id, counting pruned segments
+    pub fn len(&self) -> u64 {
+        self.segments.last()
+            .map(|s| s.first_message + s.message_count)
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn write_manifest(&self) -> Result<()> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        let mut list = map.key("segments")?.list()?;
+        for segment in &self.segments {
+            let mut entry = list.list()?;
+            entry.u64(segment.segment)?;
+            entry.u64(segment.first_message)?;
+            entry.u64(segment.message_count)?;
+            if let Some(closed_at) = segment.closed_at {
+                entry.u64(closed_at)?;
+                if segment.stored {
+                    entry.u64(1)?;
+                }
+            }
+            entry.finish()?;
+        }
+        list.finish()?;
+        map.finish()?;
+
+        let manifest = self.path.join(FILE_MANIFEST);
+        write_atomic(&manifest, enc.as_bytes(), self.durability)?;
+        Ok(())
+    }
+}
+
+/// Reader for segmented neodisk directories. Like `NeoDiskReader`, it sees
+/// the directory as it was when opened.
+#[derive(Debug)]
+pub struct NeoDiskDirReader {
+    segments: Vec<(SegmentInfo, NeoDiskReader)>,
+}
+
+impl NeoDiskDirReader {
+    /// Opens every live segment. Segment indexes are built on first read.
+    /// Fails with `NeedsFrameStore` if any segment is stored; see
+    /// `open_with_store`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with(path.as_ref(), None)
+    }
+
+    /// Opens every live segment, reassembling stored ones from `store`
+    pub fn open_with_store<P: AsRef<Path>>(path: P, store: &FrameStore) -> Result<Self> {
+        Self::open_with(path.as_ref(), Some(store))
+    }
+
+    fn open_with(path: &Path, store: Option<&FrameStore>) -> Result<Self> {
+        let mut segments = Vec::new();
+        for segment in read_manifest(path)? {
+            let reader = match (segment.stored, store) {
+                (false, _) => NeoDiskReader::open_lazy(segment.path(path))?,
+                (true, Some(store)) => NeoDiskReader::from_bytes(load_segment(path, store, segment.segment)?)?,
+                (true, None) => return Err(Error::NeedsFrameStore(segment.segment)),
+            };
+            segments.push((segment, reader));
+        }
+
+        // The manifest may lag behind appends to the last segment
+        if let Some((last, reader)) = segments.last_mut() {
+            last.message_count = reader.len()?;
+        }
+        Ok(Self { segments })
+    }
+
+    pub fn read(&self, id: MessageId) -> Result<Cow<'_, [u8]>> {
+        let index = self.segments
+            .partition_point(|(s, _)| s.messages().end <= id);
+        if id < self.first() {
+            return Err(Error::Pruned(id.0));
+        }
+        let (segment, reader) = self.segments.get(index)
+            .filter(|(s, _)| s.messages().contains(&id))
+            .ok_or(Error::MessageNotFound(id.0))?;
+        reader.read(MessageId(id.0 - segment.first_message))
+    }
+
+    /// Live segments in order
+    pub fn segments(&self) -> impl Iterator<Item = &SegmentInfo> {
+        self.segments.iter().map(|(s, _)| s)
+    }
+
+    /// Id of the first message still stored
+    pub fn first(&self) -> MessageId {
+        MessageId(self.segments.first().map(|(s, _)| s.first_message).unwrap_or(0))
+    }
+
+    /// One past the last message id, counting pruned segments
+    pub fn len(&self) -> u64 {
+        self.segments.last()
+            .map(|(s, _)| s.first_message + s.message_count)
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn segment_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("{:08x}.nd", segment))
+}
+
+fn stored_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("{:08x}.frames", segment))
+}
+
+/// Puts the frame data of a closed segment into `store` and writes its
+/// listing. The segment file is left for the caller to remove.
+fn store_segment(dir: &Path, store: &FrameStore, segment: u64, durability: Durability) -> Result<()> {
+    let bytes = std::fs::read(segment_path(dir, segment))?;
+    read_footer(&bytes)?;
+    let footer_start = (bytes.len() - FOOTER_SIZE) as u64;
+    let frames = scan_headers(&mut std::io::Cursor::new(&bytes), footer_start)?;
+
+    let mut enc = Encoder::new();
+    let mut list = enc.list()?;
+    for (i, frame) in frames.iter().enumerate() {
+        let end = frames.get(i + 1).map_or(footer_start, |next| next.header_offset) as usize;
+        let data_start = end - frame.compressed_size as usize;
+        let mut entry = list.list()?;
+        entry.bytes(&bytes[frame.header_offset as usize..data_start])?;
+        entry.fixed32(&store.put(&bytes[data_start..end])?)?;
+        entry.finish()?;
+    }
+    list.finish()?;
+    write_atomic(&stored_path(dir, segment), enc.as_bytes(), durability)?;
+    Ok(())
+}
+
+/// The header and data hash of each frame in a stored segment's listing
+fn read_listing(dir: &Path, segment: u64) -> Result<Vec<(Vec<u8>, [u8; 32])>> {
+    let bytes = std::fs::read(stored_path(dir, segment))?;
+    let mut dec = Decoder::new(&bytes);
+    let mut list = dec.list()?;
+    let mut frames = Vec::new();
+    while let Some(entry) = list.next()? {
+        let ValueDecoder::List(mut entry) = entry else {
+            return Err(Error::InvalidFormat);
+        };
+        let header = entry.next()?.ok_or(Error::InvalidFormat)?.as_bytes()?.to_vec();
+        let hash = *entry.next()?.ok_or(Error::InvalidFormat)?.as_fixed32()?;
+        frames.push((header, hash));
+    }
+    Ok(frames)
+}
+
+/// Puts a stored segment's file back together from its listing
+fn load_segment(dir: &Path, store: &FrameStore, segment: u64) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut last_header = 0u64;
+    let mut version = 0;
+    for (header, hash) in read_listing(dir, segment)? {
+        last_header = bytes.len() as u64;
+        version = version.max(FrameHeader::decode(&header)?.min_log_version());
+        bytes.extend_from_slice(&header);
+        bytes.extend_from_slice(&store.get(&hash)?);
+    }
+    bytes.extend_from_slice(&footer(last_header, version));
+    Ok(bytes)
+}
+
+fn read_manifest(dir: &Path) -> Result<Vec<SegmentInfo>> {
+    use crate::neopack::Error as NeopackError;
+
+    let bytes = std::fs::read(dir.join(FILE_MANIFEST))?;
+    let mut dec = Decoder::new(&bytes);
+    let mut map = dec.map()?;
+    let Some(("segments", ValueDecoder::List(mut list))) = map.next()? else {
+        return Err(Error::InvalidFormat);
+    };
+
+    let mut segments = Vec::new();
+    while let Some(entry) = list.next()? {
+        let ValueDecoder::List(mut entry) = entry else {
+            return Err(Error::InvalidFormat);
+        };
+        let mut field = || -> Result<Option<u64>> {
+            Ok(entry.next()?.map(|v| v.as_u64()).transpose()?)
+        };
+        let mut required = || -> Result<u64> {
+            Ok(field()?.ok_or(NeopackError::Malformed)?)
+        };
+        segments.push(SegmentInfo {
+            segment: required()?,
+            first_message: required()?,
+            message_count: required()?,
+            // Absent for the live segment
+            closed_at: field()?,
+            stored: field()? == Some(1),
+        });
+    }
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(i: u64) -> Vec<u8> {
+        let mut enc = Encoder::new();
+        enc.u64(i).unwrap();
+        enc.into_bytes()
+    }
+
+    #[test]
+    fn test_segment_rotation() -> Result<()> {
+        let path = "/tmp/test_neodir_rotation";
+        let _ = std::fs::remove_dir_all(path);
+
+        {
+            let mut writer = NeoDiskDirWriter::create(path, RotatePolicy::Frames(2))?
+                .with_flush_policy(FlushPolicy::Messages(5));
+            let ids = writer.append_batch((0..33).map(message))?;
+            assert_eq!(ids, MessageId(0)..MessageId(33));
+            writer.flush()?;
+
+            // Two frames of five per segment
+            let firsts: Vec<u64> = writer.segments().iter().map(|s| s.first_message).collect();
+            assert_eq!(firsts, vec![0, 10, 20, 30]);
+        }
+
+        // Appends made after the last manifest write are still found
+        {
+            let mut writer = NeoDiskDirWriter::open(path, RotatePolicy::Frames(2))?;
+            assert_eq!(writer.len(), 33);
+            writer.append(&message(33))?;
+            writer.flush()?;
+        }
+
+        let reader = NeoDiskDirReader::open(path)?;
+        assert_eq!(reader.len(), 34);
+        for i in 0..34 {
+            assert_eq!(reader.read(MessageId(i))?, message(i));
+        }
+        assert!(matches!(reader.read(MessageId(34)), Err(Error::MessageNotFound(34))));
+
+        std::fs::remove_dir_all(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_old_segments() -> Result<()> {
+        let path = "/tmp/test_neodir_delete";
+        let _ = std::fs::remove_dir_all(path);
+
+        // Any frame at all takes a segment past the bare 16 byte footer
+        let mut writer = NeoDiskDirWriter::create(path, RotatePolicy::Bytes(17))?
+            .with_flush_policy(FlushPolicy::Messages(4));
+        writer.append_batch((0..20).map(message))?;
+        writer.flush()?;
+        assert_eq!(writer.segments().len(), 6);
+
+        // Only segments wholly before the cut go, never the live one
+        assert_eq!(writer.prune(MessageId(10))?, 2);
+        assert_eq!(writer.segments()[0].first_message, 8);
+        assert!(!segment_path(Path::new(path), 0).exists());
+        assert_eq!(writer.prune(MessageId(1000))?, 3);
+        assert_eq!(writer.segments().len(), 1);
+
+        writer.append(&message(20))?;
+        writer.flush()?;
+        let reader = NeoDiskDirReader::open(path)?;
+        assert_eq!(reader.first(), MessageId(20));
+        assert_eq!(reader.len(), 21);
+        assert!(matches!(reader.read(MessageId(5)), Err(Error::Pruned(5))));
+        assert_eq!(reader.read(MessageId(20))?, message(20));
+
+        std::fs::remove_dir_all(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_retention_policies() -> Result<()> {
+        let path = "/tmp/test_neodir_retention";
+        let _ = std::fs::remove_dir_all(path);
+
+        // Segments of four messages: [0, 4) .. [20, 24), then the live one
+        let mut writer = NeoDiskDirWriter::create(path, RotatePolicy::Frames(1))?
+            .with_flush_policy(FlushPolicy::Messages(4));
+        writer.append_batch((0..24).map(message))?;
+        writer.append(&message(24))?;
+        assert_eq!(writer.segments().len(), 7);
+        assert!(writer.segments()[..6].iter().all(|s| s.closed_at.is_some()));
+
+        assert_eq!(writer.apply_retention(RetentionPolicy::MaxAge(Duration::from_secs(3600)))?, 0);
+        assert_eq!(writer.apply_retention(RetentionPolicy::KeepMessages(14))?, 2);
+        assert_eq!(writer.segments()[0].first_message, 8);
+        assert_eq!(writer.apply_retention(RetentionPolicy::KeepSegments(2))?, 2);
+        assert_eq!(writer.segments()[0].first_message, 16);
+        assert_eq!(writer.apply_retention(RetentionPolicy::MaxAge(Duration::ZERO))?, 2);
+        assert_eq!(writer.segments().len(), 1);
+        writer.flush()?;
+        drop(writer);
+
+        // Close times survive the manifest round trip
+        let writer = NeoDiskDirWriter::open(path, RotatePolicy::Frames(1))?;
+        assert_eq!(writer.segments()[0].closed_at, None);
+        let reader = NeoDiskDirReader::open(path)?;
+        assert!(matches!(reader.read(MessageId(23)), Err(Error::Pruned(23))));
+        assert_eq!(reader.read(MessageId(24))?, message(24));
+        std::fs::remove_dir_all(path)?;
+
+        // Keeping no closed segments leaves only the live one
+        let mut writer = NeoDiskDirWriter::create(path, RotatePolicy::Frames(1))?
+            .with_flush_policy(FlushPolicy::Messages(4));
+        writer.append_batch((0..9).map(message))?;
+        assert_eq!(writer.segments().len(), 3);
+        assert_eq!(writer.apply_retention(RetentionPolicy::KeepSegments(0))?, 2);
+        assert_eq!(writer.segments().len(), 1);
+        assert_eq!(writer.segments()[0].first_message, 8);
+        assert_eq!(writer.apply_retention(RetentionPolicy::KeepSegments(0))?, 0);
+
+        std::fs::remove_dir_all(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_frames() -> Result<()> {
+        let paths = ["/tmp/test_neodir_shared_a", "/tmp/test_neodir_shared_b"];
+        let store_path = "/tmp/test_neodir_shared_frames";
+        for path in paths.iter().chain([&store_path]) {
+            let _ = std::fs::remove_dir_all(path);
+        }
+        let store = FrameStore::open(store_path)?;
+
+        // Two mirrors of one log: segments of two frames of five messages
+        let mut writers = Vec::new();
+        for path in paths {
+            let mut writer = NeoDiskDirWriter::create(path, RotatePolicy::Frames(2))?
+                .with_flush_policy(FlushPolicy::Messages(5))
+                .with_frame_store(store.clone());
+            writer.append_batch((0..33).map(message))?;
+            writer.flush()?;
+            writers.push(writer);
+        }
+        assert!(writers[0].segments()[..3].iter().all(|s| s.stored));
+        assert!(!writers[0].segments()[3].stored);
+        assert!(!segment_path(Path::new(paths[0]), 0).exists());
+
+        // Six distinct frames, each referred to by both mirrors
+        let listing = read_listing(Path::new(paths[0]), 1)?;
+        assert_eq!(store.refs(&listing[0].1)?, 2);
+        assert_eq!(std::fs::read_dir(store_path)?.count(), 12);
+
+        assert!(matches!(NeoDiskDirReader::open(paths[0]), Err(Error::NeedsFrameStore(0))));
+        for path in paths {
+            let reader = NeoDiskDirReader::open_with_store(path, &store)?;
+            for i in 0..33 {
+                assert_eq!(reader.read(MessageId(i))?, message(i));
+            }
+        }
+
+        // Frames go with the last segment that refers to them
+        assert_eq!(writers[0].prune(MessageId(20))?, 2);
+        assert_eq!(store.refs(&listing[0].1)?, 1);
+        assert_eq!(writers[1].prune(MessageId(20))?, 2);
+        assert_eq!(store.refs(&listing[0].1)?, 0);
+        assert!(matches!(store.get(&listing[0].1), Err(Error::MissingFrame(_))));
+        assert_eq!(std::fs::read_dir(store_path)?.count(), 4);
+
+        // A stored segment can't be pruned without its store
+        let mut reopened = NeoDiskDirWriter::open(paths[0], RotatePolicy::Frames(2))?;
+        assert!(matches!(reopened.prune(MessageId(30)), Err(Error::NeedsFrameStore(2))));
+        let reader = NeoDiskDirReader::open_with_store(paths[0], &store)?;
+        assert_eq!(reader.read(MessageId(25))?, message(25));
+
+        for path in paths.iter().chain([&store_path]) {
+            std::fs::remove_dir_all(path)?;
+        }
+        Ok(())
+    }
+}
@@ -9,29 +9,113 @@
 //! - compressed_size: u64
 //! - decompressed_size: u64
 //! - jump_offsets: List<u64> (absolute file offsets to previous frame headers)
+//! - extensions: Map (message count, payload checksum, optional message sizes)
 //!
 //! Footer (last 16 bytes of file):
 //! - last_frame_offset: u64 (absolute offset to last frame header)
-//! - magic: [u8; 8] = b"NEODISK\0"
+//! - magic: [u8; 8] = b"NEODISK" then the log version, 0 unless a frame
+//!   has a jump density or forward slots (see `jumpheader`), else 1
 //!
-//! Each frame contains ~1MB of uncompressed neopack messages.
-
-use std::fs::OpenOptions;
+//! Each frame contains ~1MB of uncompressed neopack messages by default;
+//! `FlushPolicy` can close frames by message count or age instead.
+//!
+//! # Envelopes
+//!
+//! By default a frame's messages are bare neopack values, and readers
+//! find where each ends by decoding it, so anything else appended breaks
+//! the frame. A writer given an `Envelope` wraps each message in its
+//! length instead, optionally with a type byte and a checksum of the
+//! payload, and records the envelope in the frame header. Such frames
+//! hold arbitrary bytes and are split without reading the payloads; a
+//! payload that fails its checksum is reported on its own rather than
+//! as a damaged frame. Frames with and without an envelope can share a
+//! file, since each header says which it is.
+//!
+//! Appending a frame overwrites the old footer, so a crash part way
+//! through leaves a file with no valid footer. `NeoDiskWriter::recover`
+//! cuts such a file back to its last whole frame, checked against its
+//! checksum, and writes a fresh footer; nothing that was synced is lost.
+//!
+//! Messages never span frames. A frame is only closed between messages, so
+//! every frame decompresses to a whole number of messages and frame
+//! boundaries fall on message boundaries. A size limit may be overshot by
+//! the message that crosses it, and a message bigger than the limit is
+//! written whole.
+//!
+//! # Navigating by header
+//!
+//! A reader from `open_lazy` has checked only the footer. The last
+//! frame's header is where the footer points, and each header's jump
+//! list leads back to any earlier frame in O(log n) header reads, so
+//! `frame_header` and `walk_back` find frames without reading their
+//! compressed data or indexing the file.
+//!
+//! A writer can trade walk length for header size with
+//! `with_jump_density`, and keep forward pointers with
+//! `with_forward_pointers`, patching each new frame's offset into the
+//! earlier headers that have a slot for it before the footer moves past
+//! it. `walk_forward` follows them, and steps frame by frame where there
+//! are none. The `jumpheader` docs describe both; readers follow whatever
+//! each header records.
+//!
+//! # Salvage
+//!
+//! Indexing walks the headers from the start of the file, so one damaged
+//! header makes every frame after it unreachable, and `open` fails.
+//! `NeoDiskReader::salvage` reads what is left instead: it walks forward
+//! until a header fails, then back from the footer along the jump lists,
+//! which point past a damaged header to earlier frames as well as to the
+//! one just before. Each header found records the id of its first
+//! message, so the frames after the damage keep their ids and the
+//! messages lost can be given as exact ranges. Frames written before
+//! headers recorded that can still be read but not placed.
+//!
+//! # Reproducible files
+//!
+//! Under `FlushPolicy::Bytes` a frame closes as soon as the uncompressed
+//! bytes in it reach the limit, checked after each message is added, and
+//! under `Messages` once it holds that many. Neither looks at the clock
+//! or at how well the data compressed, so two writers given the same
+//! messages, the same policy and message index setting, and the same
+//! `flush` calls write byte-identical files: every frame is compressed at
+//! `ZSTD_LEVEL`, which each header records, and the headers and footer
+//! hold only offsets and sizes. `Elapsed` gives this up; see
+//! `FlushPolicy::is_reproducible`. The zstd library itself is not pinned,
+//! so files are only guaranteed to match when written by the same build.
+
+use std::borrow::Cow;
 use std::fs::File;
+use std::io::Read;
 use std::io::SeekFrom;
 use std::io::Seek;
 use std::io::Write;
 use std::io;
+use std::ops::Range;
 use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
 
 use memmap2::Mmap;
 
+use crate::format::NEODISK_MAGIC;
+use crate::format::NEODISK_VERSION;
+use crate::jumpheader::Envelope;
 use crate::jumpheader::FrameHeader;
+use crate::jumpheader::JumpPathError;
+use crate::jumpheader::forward_sources;
+use crate::jumpheader::forward_targets;
+use crate::jumpheader::jump_targets;
+use crate::metrics;
+use crate::metrics::MetricsHandle;
 use crate::neopack;
+use crate::platform;
+use crate::platform::OsFileOps;
 
 const DEFAULT_FRAME_SIZE: usize = 1024 * 1024; // 1MB uncompressed
-const MAGIC: &[u8; 8] = b"NEODISK\0";
-const FOOTER_SIZE: usize = 16; // 8 bytes offset + 8 bytes magic
+/// zstd level every frame is compressed at, recorded in its header
+pub const ZSTD_LEVEL: i32 = 3;
+pub(crate) const FOOTER_SIZE: usize = 16; // 8 bytes offset + 8 bytes magic
 
 #[derive(Debug)]
 pub enum Error {
@@ -41,6 +125,24 @@ pub enum Error {
     FrameNotFound(u64),
     Neopack(neopack::Error),
     InvalidFormat,
+    /// Frame's compressed data doesn't match the checksum in its header
+    ChecksumMismatch { frame: u64 },
+    /// Message was deleted by segment retention
+    Pruned(u64),
+    /// A frame store has no intact frame with this hash
+    MissingFrame([u8; 32]),
+    /// This segment's frames are in a frame store, and none was given
+    NeedsFrameStore(u64),
+    /// Message `index` of `frame` doesn't match the checksum in its envelope
+    MessageChecksumMismatch { frame: u64, index: u64 },
+    /// A message type was given to a writer whose envelope has no type byte
+    Untyped,
+    /// Frame's data doesn't decompress to the size its header records
+    DecompressedSize { frame: u64 },
+    /// No jump path leads between the frames asked for
+    JumpPath(JumpPathError),
+    /// The footer is from a newer build, whose logs this one can't read
+    UnsupportedVersion { found: u8, expected: u8 },
 }
 
 impl From<io::Error> for Error {
@@ -55,40 +157,302 @@ impl From<neopack::Error> for Error {
     }
 }
 
+impl From<JumpPathError> for Error {
+    fn from(e: JumpPathError) -> Self {
+        Error::JumpPath(e)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct MessageId(pub u64);
 
+/// Per-frame numbers from `NeoDiskReader::stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameStats {
+    pub frame_number: u64,
+    pub header_offset: u64,
+    pub header_size: u64,
+    pub compressed_size: u64,
+    pub decompressed_size: u64,
+    pub message_count: u64,
+    /// Number of back-pointers in this frame's jump list
+    pub jump_count: usize,
+}
+
+impl FrameStats {
+    /// Decompressed over compressed size; higher is better
+    pub fn compression_ratio(&self) -> f64 {
+        self.decompressed_size as f64 / self.compressed_size.max(1) as f64
+    }
+}
+
+/// Whole-file numbers from `NeoDiskReader::stats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskStats {
+    pub file_size: u64,
+    pub message_count: u64,
+    pub frames: Vec<FrameStats>,
+    /// Longest jump list in any frame
+    pub max_jump_count: usize,
+    /// Bytes between the end of the frame the footer points at and the
+    /// footer itself: frames written but never referenced by a footer
+    pub wasted_tail_bytes: u64,
+}
+
+impl DiskStats {
+    pub fn compressed_size(&self) -> u64 {
+        self.frames.iter().map(|f| f.compressed_size).sum()
+    }
+
+    pub fn decompressed_size(&self) -> u64 {
+        self.frames.iter().map(|f| f.decompressed_size).sum()
+    }
+
+    pub fn compression_ratio(&self) -> f64 {
+        self.decompressed_size() as f64 / self.compressed_size().max(1) as f64
+    }
+}
+
+/// A structural problem found by `NeoDiskReader::check`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiskIssue {
+    /// Frame at position `frame` records a different frame number
+    FrameNumber { frame: u64, found: u64 },
+    /// A frame header couldn't be decoded
+    Header { frame: u64 },
+    /// Frame's jump list doesn't match the frames it should point back to
+    JumpList { frame: u64, expected: Vec<u64>, found: Vec<u64> },
+    /// A jump offset doesn't land on a frame header
+    JumpTarget { frame: u64, offset: u64 },
+    /// A forward pointer doesn't land on the frame its slot is for
+    ForwardTarget { frame: u64, offset: u64 },
+    /// Footer doesn't point at the last frame header
+    Footer { expected: u64, found: u64 },
+}
+
+/// Frames `NeoDiskReader::salvage` couldn't read, and the messages lost
+/// with them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Damage {
+    /// Frame numbers. Runs to `u64::MAX` if the damage took the header
+    /// the footer points at, leaving the end of the file unknown.
+    pub frames: Range<u64>,
+    /// Ids of the messages lost, if the frames either side pin them down
+    pub messages: Option<Range<MessageId>>,
+}
+
+/// What `NeoDiskReader::salvage` read back from a damaged file
+#[derive(Debug, Clone, Default)]
+pub struct Salvage {
+    /// Every message read back whose id is known, in order
+    pub messages: Vec<(MessageId, Vec<u8>)>,
+    /// Frames read back whose first message id isn't known, by frame
+    /// number: they follow lost frames and predate headers recording it
+    pub unplaced: Vec<(u64, Vec<Vec<u8>>)>,
+    pub damage: Vec<Damage>,
+}
+
+impl Salvage {
+    /// Whether every frame was read back
+    pub fn is_intact(&self) -> bool {
+        self.damage.is_empty()
+    }
+}
+
+/// When a `NeoDiskWriter` closes its current frame. Frames only close
+/// between messages, so each limit is checked after a message is added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Once the frame holds at least this many uncompressed bytes
+    Bytes(usize),
+    /// Once the frame holds this many messages
+    Messages(u64),
+    /// On the first append at least this long after the frame's first
+    /// message. There is no timer: an idle writer holds its frame until
+    /// the next append or `flush`.
+    Elapsed(Duration),
+}
+
+impl FlushPolicy {
+    /// Whether frames close at the same messages every time the same
+    /// stream is written, so the file comes out byte for byte the same.
+    /// Only `Elapsed` depends on when messages arrive.
+    pub fn is_reproducible(&self) -> bool {
+        !matches!(self, FlushPolicy::Elapsed(_))
+    }
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        FlushPolicy::Bytes(DEFAULT_FRAME_SIZE)
+    }
+}
+
+/// How far a flush goes to get writes onto stable storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Sync file contents and the directories that name them, so files
+    /// created or renamed before a flush survive a crash after it
+    #[default]
+    Full,
+    /// Sync file contents only. A crash can lose a file that was created
+    /// or renamed but whose directory wasn't synced yet.
+    Data,
+    /// Leave writeback to the OS. A crash can lose anything written since
+    /// the OS last synced on its own.
+    None,
+}
+
+impl Durability {
+    /// Syncs a file's contents, unless durability is `None`.
+    pub fn sync_file(self, file: &File) -> io::Result<()> {
+        match self {
+            Durability::Full | Durability::Data => file.sync_all(),
+            Durability::None => Ok(()),
+        }
+    }
+
+    /// Syncs the directory holding `path`, so that its entry for `path`
+    /// is durable.
+    pub fn sync_parent(self, path: &Path) -> io::Result<()> {
+        match self {
+            Durability::Full => platform::sync_dir(platform::parent_dir(path)),
+            Durability::Data | Durability::None => Ok(()),
+        }
+    }
+}
+
+/// Replaces the file at `path` with `bytes` so that a crash leaves either
+/// the old contents or the new, never a mix: the bytes go to a temporary
+/// file that is synced, renamed over `path`, and then the directory is
+/// synced so the rename itself is durable.
+pub fn write_atomic(path: &Path, bytes: &[u8], durability: Durability) -> io::Result<()> {
+    platform::replace_file(&OsFileOps, path, bytes, durability != Durability::None)?;
+    durability.sync_parent(path)
+}
+
+/// What a `NeoDiskWriter` needs from the file it appends to. Implemented
+/// for `File`; tests substitute files that fail on demand.
+pub trait LogFile: Read + Write + Seek {
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+    fn sync(&mut self) -> io::Result<()>;
+    fn file_len(&self) -> io::Result<u64>;
+}
+
+impl LogFile for File {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_all()
+    }
+
+    fn file_len(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+/// The furthest jump back from `header` that doesn't pass frame `to`, as
+/// a frame number and header offset
+fn jump_back(header: &FrameHeader, to: u64) -> Result<(u64, u64)> {
+    // Jump lists are ordered oldest first
+    let jump = header.jump_targets().into_iter()
+        .zip(header.jump_offsets.iter().copied())
+        .find(|&(frame, _)| frame >= to);
+    match jump {
+        Some(jump) => Ok(jump),
+        // Frame 0 starts the file, and no jump list points at it
+        None if to == 0 => Ok((0, 0)),
+        None => Err(Error::InvalidFormat),
+    }
+}
+
 /// Frame metadata
 #[derive(Debug, Clone)]
-struct FrameInfo {
+pub(crate) struct FrameInfo {
     /// Frame number (0-indexed)
-    #[allow(dead_code)]
     frame_number: u64,
     /// Absolute file offset where frame header starts
-    header_offset: u64,
+    pub(crate) header_offset: u64,
     /// Compressed size of frame data
-    compressed_size: u64,
+    pub(crate) compressed_size: u64,
     /// Decompressed size of frame data
     #[allow(dead_code)]
     decompressed_size: u64,
     /// Number of messages in this frame
-    message_count: u64,
+    pub(crate) message_count: u64,
     /// ID of first message in frame
-    first_message_id: u64,
+    pub(crate) first_message_id: u64,
+    /// Frames per full jump list, as recorded in the header
+    pub(crate) jump_density: u64,
+    /// Where the header, and so its forward slots, ends, if it has any
+    pub(crate) forward_end: Option<u64>,
+    /// Log version the header needs the footer to record
+    log_version: u8,
+}
+
+impl FrameInfo {
+    fn new(header: &FrameHeader, header_offset: u64, header_size: u64, message_count: u64, first_message_id: u64) -> Self {
+        FrameInfo {
+            frame_number: header.frame_number,
+            header_offset,
+            compressed_size: header.compressed_size,
+            decompressed_size: header.decompressed_size,
+            message_count,
+            first_message_id,
+            jump_density: header.density(),
+            forward_end: header.forward_offsets.is_some().then_some(header_offset + header_size),
+            log_version: header.min_log_version(),
+        }
+    }
+}
+
+/// Version a log holding `frames` records in its footer
+fn log_version(frames: &[FrameInfo]) -> u8 {
+    frames.iter().map(|f| f.log_version).max().unwrap_or(0)
+}
+
+/// The footer ending a log whose last frame header is at
+/// `last_frame_offset`
+pub(crate) fn footer(last_frame_offset: u64, version: u8) -> [u8; FOOTER_SIZE] {
+    let mut footer = [0u8; FOOTER_SIZE];
+    footer[..8].copy_from_slice(&last_frame_offset.to_le_bytes());
+    footer[8..].copy_from_slice(NEODISK_MAGIC);
+    footer[FOOTER_SIZE - 1] = version;
+    footer
 }
 
 /// Writer for append-only neodisk files
 #[derive(Debug)]
-pub struct NeoDiskWriter {
-    file: File,
-    frame_size: usize,
+pub struct NeoDiskWriter<F: LogFile = File> {
+    file: F,
+    policy: FlushPolicy,
     buffer: Vec<u8>,
     message_count: u64,
     frames: Vec<FrameInfo>,
+    /// Version the footer records, the highest any frame needs
+    log_version: u8,
     current_frame_messages: u64,
     current_frame_start_message: u64,
+    /// Size of each message in `buffer`
+    message_sizes: Vec<u32>,
+    /// Whether frame headers record message sizes
+    index_messages: bool,
+    /// When the first message of the current frame was added
+    frame_started: Option<Instant>,
+    /// Envelope for frames started from now on
+    envelope: Option<Envelope>,
+    /// Envelope around the messages in `buffer`
+    frame_envelope: Option<Envelope>,
+    /// Frames per full jump list
+    jump_density: u64,
+    /// Whether frames with jump lists get forward slots
+    forward_pointers: bool,
+    durability: Durability,
+    metrics: MetricsHandle,
 }
 
 impl NeoDiskWriter {
@@ -97,71 +461,315 @@ impl NeoDiskWriter {
     }
 
     pub fn create_with_frame_size<P: AsRef<Path>>(path: P, frame_size: usize) -> Result<Self> {
-        let file = OpenOptions::new()
+        let file = platform::open_options()
             .read(true)
             .write(true)
             .create(true)
             .truncate(true)
             .open(path.as_ref())?;
 
-        Ok(Self {
-            file,
-            frame_size,
-            buffer: Vec::with_capacity(frame_size),
-            message_count: 0,
-            frames: Vec::new(),
-            current_frame_messages: 0,
-            current_frame_start_message: 0,
-        })
+        let writer = Self::create_in(file, frame_size)?;
+        Durability::default().sync_parent(path.as_ref())?;
+        Ok(writer)
     }
 
+    /// Reopens a file for appending. Only the footer and frame headers are
+    /// read, so memory use is bounded by the number of frames rather than
+    /// the file size; frames written before headers recorded message counts
+    /// are decompressed to count them.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        // Read entire file to scan frames
-        let data = std::fs::read(path.as_ref())?;
-
-        // Scan frames using same logic as reader
-        let frames = NeoDiskReader::scan_frames(&data)?;
-
-        // Calculate total message count and frame size
-        let message_count = frames.last()
-            .map(|f| f.first_message_id + f.message_count)
-            .unwrap_or(0);
-
-        let frame_size = DEFAULT_FRAME_SIZE;
+        let file = platform::open_options()
+            .read(true)
+            .write(true)
+            .open(path.as_ref())?;
+        Self::open_in(file)
+    }
 
-        // Open file for appending
-        let mut file = OpenOptions::new()
+    /// Reopens a file whose tail may have been torn by a crash. Returns
+    /// the writer and the number of bytes cut from the end of the file.
+    /// See `recover_in` for what it refuses to cut.
+    pub fn recover<P: AsRef<Path>>(path: P) -> Result<(Self, u64)> {
+        let file = platform::open_options()
             .read(true)
             .write(true)
             .open(path.as_ref())?;
+        Self::recover_in(file)
+    }
+}
 
-        // Seek to end for appending
-        file.seek(SeekFrom::End(0))?;
+impl<F: LogFile> NeoDiskWriter<F> {
+    fn with_frames(file: F, frames: Vec<FrameInfo>) -> Self {
+        let message_count = frames.last()
+            .map(|f| f.first_message_id + f.message_count)
+            .unwrap_or(0);
 
-        Ok(Self {
+        Self {
+            log_version: log_version(&frames),
             file,
-            frame_size,
-            buffer: Vec::with_capacity(frame_size),
+            policy: FlushPolicy::default(),
+            buffer: Vec::with_capacity(DEFAULT_FRAME_SIZE),
             message_count,
             frames,
             current_frame_messages: 0,
             current_frame_start_message: message_count,
-        })
+            message_sizes: Vec::new(),
+            index_messages: false,
+            frame_started: None,
+            envelope: None,
+            frame_envelope: None,
+            jump_density: 1,
+            forward_pointers: false,
+            durability: Durability::default(),
+            metrics: MetricsHandle::default(),
+        }
+    }
+
+    /// Starts an empty log in `file`, discarding anything already in it.
+    pub fn create_in(mut file: F, frame_size: usize) -> Result<Self> {
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut writer = Self::with_frames(file, Vec::new());
+        writer.policy = FlushPolicy::Bytes(frame_size);
+        writer.buffer = Vec::with_capacity(frame_size);
+
+        // An empty log is still a valid file
+        writer.write_footer()?;
+        Ok(writer)
+    }
+
+    /// Like `open`, on a file that's already open.
+    pub fn open_in(mut file: F) -> Result<Self> {
+        // Check the footer before trusting anything else in the file
+        let file_len = file.file_len()?;
+        if file_len < FOOTER_SIZE as u64 {
+            return Err(Error::InvalidFormat);
+        }
+        let footer_start = file_len - FOOTER_SIZE as u64;
+        let mut footer = [0u8; FOOTER_SIZE];
+        file.seek(SeekFrom::Start(footer_start))?;
+        file.read_exact(&mut footer)?;
+        let last_frame_offset = read_footer(&footer)?;
+
+        file.seek(SeekFrom::Start(0))?;
+        let frames = scan_headers(&mut file, footer_start)?;
+
+        // A frame torn part way through its first few bytes can leave the
+        // magic intact but not the offset before it
+        if frames.last().map(|f| f.header_offset).unwrap_or(0) != last_frame_offset {
+            return Err(Error::InvalidFormat);
+        }
+
+        // New frames overwrite the footer; flush writes a fresh one
+        file.seek(SeekFrom::Start(footer_start))?;
+        Ok(Self::with_frames(file, frames))
+    }
+
+    /// Like `recover`, on a file that's already open. Frames are read
+    /// from the start of the file until one is incomplete, misnumbered,
+    /// or fails its checksum; the file is cut there, given a new footer,
+    /// and synced.
+    ///
+    /// Only a log is cut: the file must end in the footer magic, as when
+    /// a crash tore only the first few bytes written over the footer, or
+    /// start with at least one whole frame. Anything else is refused with
+    /// `InvalidFormat`, and a footer from a newer build with
+    /// `UnsupportedVersion`, leaving the file as it was.
+    pub fn recover_in(mut file: F) -> Result<(Self, u64)> {
+        let file_len = file.file_len()?;
+        let mut has_magic = false;
+        if file_len >= FOOTER_SIZE as u64 {
+            let mut footer = [0u8; FOOTER_SIZE];
+            file.seek(SeekFrom::Start(file_len - FOOTER_SIZE as u64))?;
+            file.read_exact(&mut footer)?;
+            match read_footer(&footer) {
+                Ok(_) => has_magic = true,
+                Err(Error::InvalidFormat) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        file.seek(SeekFrom::Start(0))?;
+
+        let mut frames: Vec<FrameInfo> = Vec::new();
+        let mut end = 0u64;
+        let intact = loop {
+            let last_frame_offset = frames.last().map(|f| f.header_offset).unwrap_or(0);
+            if file_len - end == FOOTER_SIZE as u64 {
+                let mut footer = [0u8; FOOTER_SIZE];
+                file.read_exact(&mut footer)?;
+                if read_footer(&footer).ok() == Some(last_frame_offset) {
+                    break true;
+                }
+                file.seek(SeekFrom::Start(end))?;
+            }
+
+            let first_message_id = frames.last()
+                .map(|f| f.first_message_id + f.message_count)
+                .unwrap_or(0);
+            match read_whole_frame(&mut file, file_len - end) {
+                Ok((header, count)) if header.frame_number == frames.len() as u64 => {
+                    let next = file.stream_position()?;
+                    let header_size = next - end - header.compressed_size;
+                    frames.push(FrameInfo::new(&header, end, header_size, count, first_message_id));
+                    end = next;
+                }
+                _ => break false,
+            }
+        };
+
+        // An empty file has nothing to lose; a file with neither a footer
+        // nor a whole frame may not be a log at all
+        if !intact && !has_magic && frames.is_empty() && file_len > 0 {
+            return Err(Error::InvalidFormat);
+        }
+
+        file.seek(SeekFrom::Start(end))?;
+        let mut writer = Self::with_frames(file, frames);
+        if intact {
+            return Ok((writer, 0));
+        }
+        writer.file.set_len(end)?;
+        // Any frame cut off may have been patched into earlier headers
+        writer.clear_forward_from(writer.frames.len() as u64)?;
+        writer.write_footer()?;
+        writer.file.sync()?;
+        Ok((writer, file_len - end))
+    }
+
+    /// Record each message's size in the frame headers written from now
+    /// on, so readers can slice straight to a message instead of skipping
+    /// over the ones before it. Costs 4 header bytes per message.
+    pub fn with_message_index(mut self, enabled: bool) -> Self {
+        self.index_messages = enabled;
+        self
+    }
+
+    /// Wrap each message in `envelope` instead of writing it as a bare
+    /// neopack value, so it can hold any bytes. Takes effect from the
+    /// next frame started.
+    pub fn with_envelope(mut self, envelope: Envelope) -> Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    /// Give only every `density`-th frame a full jump list, and the rest
+    /// a pointer to the frame before: smaller headers, longer walks. See
+    /// the `jumpheader` docs. Applies to frames written from now on.
+    pub fn with_jump_density(mut self, density: u64) -> Self {
+        self.jump_density = density.max(1);
+        self
+    }
+
+    /// Give frames with a jump list forward slots, patched in as the
+    /// frames they point at are written, so readers can skip forward as
+    /// well as back. Applies to frames written from now on.
+    pub fn with_forward_pointers(mut self, enabled: bool) -> Self {
+        self.forward_pointers = enabled;
+        self
+    }
+
+    /// Sets when frames are closed. Applies to the frame being filled.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Sets how `flush` syncs the file from now on.
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability;
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    pub fn with_metrics(mut self, metrics: MetricsHandle) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Reports frames written and sync times to `metrics` from now on.
+    pub fn set_metrics(&mut self, metrics: MetricsHandle) {
+        self.metrics = metrics;
     }
 
     pub fn append(&mut self, message: &[u8]) -> Result<MessageId> {
-        // Add message to buffer
+        let id = self.push(0, message);
+        if self.frame_full() {
+            self.flush_frame()?;
+        }
+        Ok(id)
+    }
+
+    /// Appends a message with type byte `kind`, which `read_typed` gives
+    /// back. Needs an envelope with `typed` set.
+    pub fn append_typed(&mut self, kind: u8, message: &[u8]) -> Result<MessageId> {
+        if !self.envelope.is_some_and(|envelope| envelope.typed) {
+            return Err(Error::Untyped);
+        }
+        let id = self.push(kind, message);
+        if self.frame_full() {
+            self.flush_frame()?;
+        }
+        Ok(id)
+    }
+
+    /// Appends every message from `messages`, closing frames as the flush
+    /// policy requires, and returns the range of ids they were given.
+    /// Messages are never split across frames.
+    pub fn append_batch<I>(&mut self, messages: I) -> Result<Range<MessageId>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let start = MessageId(self.message_count);
+        for message in messages {
+            self.push(0, message.as_ref());
+            if self.frame_full() {
+                self.flush_frame()?;
+            }
+        }
+        Ok(start..MessageId(self.message_count))
+    }
+
+    /// Adds a message to the current frame without flushing. `kind` is
+    /// dropped unless the frame's envelope is typed.
+    fn push(&mut self, kind: u8, message: &[u8]) -> MessageId {
+        if self.buffer.is_empty() {
+            self.frame_started = Some(Instant::now());
+            self.frame_envelope = self.envelope;
+        }
+        let start = self.buffer.len();
+        if let Some(envelope) = self.frame_envelope {
+            self.buffer.extend_from_slice(&(message.len() as u32).to_le_bytes());
+            if envelope.typed {
+                self.buffer.push(kind);
+            }
+            if envelope.checksummed {
+                self.buffer.extend_from_slice(&message_checksum(message).to_le_bytes());
+            }
+        }
         self.buffer.extend_from_slice(message);
+        self.message_sizes.push((self.buffer.len() - start) as u32);
         let id = MessageId(self.message_count);
         self.message_count += 1;
         self.current_frame_messages += 1;
+        id
+    }
 
-        // Flush frame if buffer is full
-        if self.buffer.len() >= self.frame_size {
-            self.flush_frame()?;
+    /// Whether the flush policy says the current frame should be closed
+    fn frame_full(&self) -> bool {
+        match self.policy {
+            FlushPolicy::Bytes(limit) => self.buffer.len() >= limit,
+            FlushPolicy::Messages(limit) => self.current_frame_messages >= limit,
+            FlushPolicy::Elapsed(limit) => self.frame_started
+                .is_some_and(|started| started.elapsed() >= limit),
         }
-
-        Ok(id)
     }
 
     fn flush_frame(&mut self) -> Result<()> {
@@ -174,13 +782,13 @@ impl NeoDiskWriter {
         let frame_number = self.frames.len() as u64;
 
         // Compress frame
-        let compressed = zstd::encode_all(&self.buffer[..], 3)
+        let compressed = zstd::encode_all(&self.buffer[..], ZSTD_LEVEL)
             .map_err(|e| Error::Compression(e.to_string()))?;
 
         let compressed_size = compressed.len() as u64;
 
         // Compute logarithmic jump offsets to previous frame headers
-        let jump_indices = crate::jumpheader::compute_jump_indices(frame_number);
+        let jump_indices = jump_targets(frame_number, self.jump_density);
         let jump_offsets: Vec<u64> = jump_indices.iter()
             .filter_map(|&idx| {
                 self.frames.get(idx as usize).map(|f| f.header_offset)
@@ -188,7 +796,24 @@ impl NeoDiskWriter {
             .collect();
 
         // Create and encode frame header
-        let header = FrameHeader::new(frame_number, compressed_size, decompressed_size, jump_offsets);
+        let mut header = FrameHeader::new(frame_number, compressed_size, decompressed_size, jump_offsets)
+            .with_message_count(self.current_frame_messages)
+            .with_checksum(frame_checksum(&compressed))
+            .with_zstd_level(ZSTD_LEVEL)
+            .with_first_message(self.current_frame_start_message);
+        if let Some(envelope) = self.frame_envelope {
+            header = header.with_envelope(envelope);
+        }
+        if self.index_messages {
+            header = header.with_message_sizes(core::mem::take(&mut self.message_sizes));
+        }
+        if self.jump_density != 1 {
+            header = header.with_jump_density(self.jump_density);
+        }
+        let forward_slots = forward_targets(frame_number, self.jump_density).len();
+        if self.forward_pointers && forward_slots > 0 {
+            header = header.with_forward_offsets(vec![0; forward_slots]);
+        }
         let header_bytes = header.encode()?;
 
         // Write frame header first
@@ -197,21 +822,85 @@ impl NeoDiskWriter {
         // Write compressed frame data
         self.file.write_all(&compressed)?;
 
+        let written = header_bytes.len() as u64 + compressed_size;
+        self.metrics.counter(metrics::NEODISK_BYTES_WRITTEN, written);
+        self.metrics.counter(metrics::NEODISK_BYTES_UNCOMPRESSED, decompressed_size);
+        self.metrics.counter(metrics::NEODISK_FRAMES_FLUSHED, 1);
+        self.metrics.gauge(metrics::NEODISK_COMPRESSION_RATIO, decompressed_size as f64 / compressed_size.max(1) as f64);
+
         // Record frame info
-        self.frames.push(FrameInfo {
-            frame_number,
+        let frame = FrameInfo::new(
+            &header,
             header_offset,
-            compressed_size,
-            decompressed_size,
-            message_count: self.current_frame_messages,
-            first_message_id: self.current_frame_start_message,
-        });
+            header_bytes.len() as u64,
+            self.current_frame_messages,
+            self.current_frame_start_message,
+        );
+        self.log_version = self.log_version.max(frame.log_version);
+        self.frames.push(frame);
+
+        // Point earlier frames at this one, now that it's all written
+        self.patch_forward(frame_number, self.jump_density, header_offset)?;
 
         // Clear buffer for next frame
         self.buffer.clear();
+        self.message_sizes.clear();
+        self.frame_started = None;
         self.current_frame_start_message = self.message_count;
         self.current_frame_messages = 0;
 
+        // Keep the file readable even if we crash before the next flush
+        self.write_footer()?;
+
+        Ok(())
+    }
+
+    /// Writes `offset` into every forward slot for frame `frame_number`
+    /// in the headers of earlier frames, or clears them with 0. Only
+    /// frames of the given density are looked at. Leaves the file
+    /// position where it was.
+    fn patch_forward(&mut self, frame_number: u64, density: u64, offset: u64) -> Result<()> {
+        let resume = self.file.stream_position()?;
+        for (source, slot) in forward_sources(frame_number, density) {
+            let Some(info) = self.frames.get(source as usize) else { continue };
+            let Some(end) = info.forward_end else { continue };
+            let targets = forward_targets(source, info.jump_density);
+            if targets.get(slot) != Some(&frame_number) {
+                continue;
+            }
+            self.file.seek(SeekFrom::Start(end - 8 * (targets.len() - slot) as u64))?;
+            self.file.write_all(&offset.to_le_bytes())?;
+        }
+        self.file.seek(SeekFrom::Start(resume))?;
+        Ok(())
+    }
+
+    /// Zeroes every forward slot that points at frame `first` or later,
+    /// for when frames were cut without knowing their headers.
+    fn clear_forward_from(&mut self, first: u64) -> Result<()> {
+        let resume = self.file.stream_position()?;
+        for info in &self.frames {
+            let Some(end) = info.forward_end else { continue };
+            let targets = forward_targets(info.frame_number, info.jump_density);
+            for (slot, _) in targets.iter().enumerate().filter(|(_, target)| **target >= first) {
+                self.file.seek(SeekFrom::Start(end - 8 * (targets.len() - slot) as u64))?;
+                self.file.write_all(&0u64.to_le_bytes())?;
+            }
+        }
+        self.file.seek(SeekFrom::Start(resume))?;
+        Ok(())
+    }
+
+    /// Writes the footer after the last frame, then seeks back over it so
+    /// the next frame replaces it. The file always ends in exactly one footer.
+    fn write_footer(&mut self) -> Result<()> {
+        let last_frame_offset = self.frames.last()
+            .map(|f| f.header_offset)
+            .unwrap_or(0);
+        let footer_start = self.file.stream_position()?;
+        self.file.write_all(&footer(last_frame_offset, self.log_version))?;
+        self.file.set_len(footer_start + FOOTER_SIZE as u64)?;
+        self.file.seek(SeekFrom::Start(footer_start))?;
         Ok(())
     }
 
@@ -220,143 +909,535 @@ impl NeoDiskWriter {
         self.flush_frame()?;
         
         // Write footer with offset to last frame header
-        if let Some(last_frame) = self.frames.last() {
-            self.file.write_all(&last_frame.header_offset.to_le_bytes())?;
-            self.file.write_all(MAGIC)?;
-        }
+        self.write_footer()?;
         
-        self.file.sync_all()?;
+        let started = Instant::now();
+        if self.durability != Durability::None {
+            self.file.sync()?;
+        }
+        self.metrics.duration(metrics::NEODISK_SYNC_SECONDS, started.elapsed());
+        Ok(())
+    }
+
+    /// Drops every message at or after `len`, including buffered ones.
+    /// The frame straddling the cut is decompressed and its surviving
+    /// messages are moved back into the buffer, to be rewritten on the
+    /// next flush. Used to roll back appends that were never committed.
+    pub fn truncate(&mut self, len: u64) -> Result<()> {
+        if len >= self.message_count {
+            return Ok(());
+        }
+
+        // The cut falls inside the unflushed buffer
+        if len >= self.current_frame_start_message {
+            let keep = len - self.current_frame_start_message;
+            let mut kept_bytes = 0;
+            for _ in 0..keep {
+                kept_bytes += record_len(self.frame_envelope, &self.buffer[kept_bytes..])?;
+            }
+            self.buffer.truncate(kept_bytes);
+            self.message_sizes.truncate(keep as usize);
+            self.message_count = len;
+            self.current_frame_messages = keep;
+            if keep == 0 {
+                self.frame_started = None;
+            }
+            return Ok(());
+        }
+
+        let frame_idx = self.frames.iter()
+            .position(|f| len < f.first_message_id + f.message_count)
+            .ok_or(Error::MessageNotFound(len))?;
+        let frame = self.frames[frame_idx].clone();
+
+        let keep = len - frame.first_message_id;
+        let mut kept = Vec::new();
+        let mut kept_sizes = Vec::new();
+        if keep > 0 {
+            let (header, decompressed) = self.read_frame(&frame)?;
+            let mut kept_bytes = 0;
+            for _ in 0..keep {
+                let len = record_len(header.envelope, &decompressed[kept_bytes..])?;
+                kept_sizes.push(len as u32);
+                kept_bytes += len;
+            }
+            kept.extend_from_slice(&decompressed[..kept_bytes]);
+            self.frame_envelope = header.envelope;
+        }
+
+        // Earlier headers mustn't point forward at frames being cut
+        let cut: Vec<(u64, u64)> = self.frames[frame_idx..].iter()
+            .map(|f| (f.frame_number, f.jump_density))
+            .collect();
+        for (frame_number, density) in cut {
+            self.patch_forward(frame_number, density, 0)?;
+        }
+        self.file.set_len(frame.header_offset)?;
+        self.file.seek(SeekFrom::Start(frame.header_offset))?;
+        self.frames.truncate(frame_idx);
+        self.log_version = log_version(&self.frames);
+        self.buffer = kept;
+        self.message_sizes = kept_sizes;
+        self.message_count = len;
+        self.current_frame_start_message = frame.first_message_id;
+        self.current_frame_messages = keep;
+        self.frame_started = (keep > 0).then(Instant::now);
+        self.write_footer()?;
         Ok(())
     }
 
+    /// Reads and decompresses a frame that was already written to the file.
+    fn read_frame(&mut self, frame: &FrameInfo) -> Result<(FrameHeader, Vec<u8>)> {
+        let resume = self.file.stream_position()?;
+        self.file.seek(SeekFrom::Start(frame.header_offset))?;
+
+        let (header, _) = read_header_from(&mut self.file, u64::MAX)?;
+
+        let mut compressed = vec![0u8; header.compressed_size as usize];
+        self.file.read_exact(&mut compressed)?;
+        self.file.seek(SeekFrom::Start(resume))?;
+
+        let decompressed = decompress(&header, &compressed)?;
+        Ok((header, decompressed))
+    }
+
     pub fn len(&self) -> u64 {
         self.message_count
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.message_count == 0
+    }
+
+    /// Number of frames written to the file; the buffered frame isn't one
+    pub fn frame_count(&self) -> u64 {
+        self.frames.len() as u64
+    }
+
+    /// Size of the file on disk, footer included. Buffered messages
+    /// aren't counted until their frame is written.
+    pub fn file_size(&self) -> Result<u64> {
+        Ok(self.file.file_len()?)
+    }
+}
+
+/// The bytes a reader reads from
+#[derive(Debug)]
+enum Data {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl std::ops::Deref for Data {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Data::Mapped(mmap) => mmap,
+            Data::Owned(bytes) => bytes,
+        }
+    }
 }
 
 /// Reader for neodisk files
 #[derive(Debug)]
 pub struct NeoDiskReader {
-    mmap: Mmap,
-    frames: Vec<FrameInfo>,
+    data: Data,
+    /// Message index, built on first use by anything that maps message ids
+    /// to frames. Seeking by frame number doesn't need it.
+    frames: OnceLock<Vec<FrameInfo>>,
 }
 
 impl NeoDiskReader {
+    /// Opens a file and indexes every frame up front
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = File::open(path.as_ref())?;
-        let mmap = unsafe { Mmap::map(&file)? };
+        let reader = Self::open_lazy(path)?;
+        reader.frames()?;
+        Ok(reader)
+    }
 
-        // Scan file to build frame index
-        // We need to read backwards from the end to find the last frame header
-        let frames = Self::scan_frames(&mmap)?;
+    /// Opens a file after checking only its footer. The message index is
+    /// built on the first call that needs it; `seek_to_frame` never does.
+    pub fn open_lazy<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = platform::open_options().read(true).open(path.as_ref())?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        read_footer(&mmap)?;
 
         Ok(Self {
-            mmap,
-            frames,
+            data: Data::Mapped(mmap),
+            frames: OnceLock::new(),
         })
     }
 
-    fn scan_frames(data: &[u8]) -> Result<Vec<FrameInfo>> {
-        // Check minimum file size (need footer)
-        if data.len() < FOOTER_SIZE {
-            return Err(Error::InvalidFormat);
-        }
-
-        // Read footer to verify magic
-        let footer_start = data.len() - FOOTER_SIZE;
-        let _last_frame_offset = u64::from_le_bytes(
-            data[footer_start..footer_start + 8].try_into().map_err(|_| Error::InvalidFormat)?
-        );
+    /// Reads a log held in memory, such as one received from a peer, and
+    /// indexes every frame up front
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        read_footer(&bytes)?;
+        let reader = Self {
+            data: Data::Owned(bytes),
+            frames: OnceLock::new(),
+        };
+        reader.frames()?;
+        Ok(reader)
+    }
 
-        // Verify magic
-        if &data[footer_start + 8..footer_start + 16] != MAGIC {
-            return Err(Error::InvalidFormat);
+    fn frames(&self) -> Result<&[FrameInfo]> {
+        if let Some(frames) = self.frames.get() {
+            return Ok(frames);
         }
+        let frames = Self::scan_frames(&self.data)?;
+        Ok(self.frames.get_or_init(|| frames))
+    }
 
-        // Scan frames from beginning until we hit the footer
-        let mut frames = Vec::new();
-        let mut pos = 0;
-        let mut message_id = 0u64;
+    fn scan_frames(data: &[u8]) -> Result<Vec<FrameInfo>> {
+        read_footer(data)?;
+        let footer_start = (data.len() - FOOTER_SIZE) as u64;
+        scan_headers(&mut io::Cursor::new(data), footer_start)
+    }
 
-        while pos < footer_start {
-            let header_offset = pos as u64;
+    /// Number of messages. On a lazily opened reader this builds the
+    /// index, and fails if a frame header can't be read.
+    pub fn len(&self) -> Result<u64> {
+        Ok(self.frames()?.iter().map(|f| f.message_count).sum())
+    }
 
-            // Read frame header (neopack encoded)
-            use crate::neopack::{Cursor, Decoder};
-            let cursor = Cursor::new(&data[pos..]);
-            let mut decoder = Decoder::with_cursor(cursor);
+    /// Number of frames, from the header the footer points at
+    pub fn frame_count(&self) -> Result<u64> {
+        if self.data.len() == FOOTER_SIZE {
+            return Ok(0);
+        }
+        let (last, _) = self.read_header(read_footer(&self.data)?)?;
+        Ok(last.frame_number + 1)
+    }
 
-            let header = FrameHeader::decode(decoder.raw_value()?)?;
-            let header_size = decoder.pos();
-            pos += header_size;
+    /// Finds the header offset of frame `n` by following jump lists back
+    /// from the last frame, in O(log n) header reads.
+    pub fn seek_to_frame(&self, n: u64) -> Result<u64> {
+        let count = self.frame_count()?;
+        if n >= count {
+            return Err(Error::FrameNotFound(n));
+        }
+        // The first frame starts the file, and no jump list points at it
+        if n == 0 {
+            return Ok(0);
+        }
 
-            // Validate compressed data doesn't extend beyond footer
-            if pos + header.compressed_size as usize > footer_start {
-                return Err(Error::InvalidFormat);
-            }
+        let mut current = count - 1;
+        let mut offset = read_footer(&self.data)?;
+        while current > n {
+            let header = self.header_of(offset, current)?;
+            (current, offset) = jump_back(&header, n)?;
+        }
+        Ok(offset)
+    }
 
-            // Count messages by decompressing the frame
-            let compressed_data = &data[pos..pos + header.compressed_size as usize];
-            let decompressed = zstd::decode_all(compressed_data)
-                .map_err(|e| Error::Compression(e.to_string()))?;
+    /// Frame `n`'s header, found along the jump lists from the last frame
+    pub fn frame_header(&self, n: u64) -> Result<FrameHeader> {
+        self.header_of(self.seek_to_frame(n)?, n)
+    }
 
-            // Count messages in frame
-            let cursor = Cursor::new(&decompressed);
-            let mut decoder = Decoder::with_cursor(cursor);
-            let mut count = 0u64;
-            while decoder.remaining() > 0 {
-                decoder.skip_value()?;
-                count += 1;
+    /// Headers of the frames a jump path visits from frame `from` back to
+    /// frame `to`, both included, newest first. `walk_back(count - 1, k)`
+    /// is the chain from the tail to frame `k`. Reads only headers: one
+    /// per jump, and O(log n) more to find `from`.
+    pub fn walk_back(&self, from: u64, to: u64) -> Result<Vec<FrameHeader>> {
+        if to > from {
+            return Err(JumpPathError::NotBehind { from, to }.into());
+        }
+        let mut offset = self.seek_to_frame(from)?;
+        let mut headers = vec![self.header_of(offset, from)?];
+        let mut current = from;
+        while current > to {
+            (current, offset) = jump_back(&headers[headers.len() - 1], to)?;
+            headers.push(self.header_of(offset, current)?);
+        }
+        Ok(headers)
+    }
+
+    /// Headers of the frames a walk visits from frame `from` forward to
+    /// frame `to`, both included, oldest first. Takes the furthest forward
+    /// pointer that doesn't overshoot where a header has them, and steps
+    /// to the next frame where it doesn't. Reads only headers.
+    pub fn walk_forward(&self, from: u64, to: u64) -> Result<Vec<FrameHeader>> {
+        if to < from {
+            return Err(JumpPathError::NotAhead { from, to }.into());
+        }
+        if to >= self.frame_count()? {
+            return Err(Error::FrameNotFound(to));
+        }
+        let mut offset = self.seek_to_frame(from)?;
+        let mut current = from;
+        let mut headers = Vec::new();
+        while current < to {
+            let (header, size) = self.read_header(offset)?;
+            if header.frame_number != current {
+                return Err(Error::InvalidFormat);
             }
+            // Slots are nearest first. One left stale by a crash or a
+            // truncate lands on the wrong frame, and is passed over.
+            let jumps: Vec<(u64, u64)> = header.forward().filter(|&(frame, _)| frame <= to).collect();
+            let jump = jumps.into_iter().rev().find(|&(frame, offset)| self.header_of(offset, frame).is_ok());
+            (current, offset) = match jump {
+                Some(jump) => jump,
+                None => (current + 1, offset
+                    .checked_add(size as u64)
+                    .and_then(|end| end.checked_add(header.compressed_size))
+                    .ok_or(Error::InvalidFormat)?),
+            };
+            headers.push(header);
+        }
+        headers.push(self.header_of(offset, to)?);
+        Ok(headers)
+    }
+
+    /// Decodes the header at `offset`, which must be frame `n`'s
+    fn header_of(&self, offset: u64, n: u64) -> Result<FrameHeader> {
+        let (header, _) = self.read_header(offset)?;
+        if header.frame_number != n {
+            return Err(Error::InvalidFormat);
+        }
+        Ok(header)
+    }
 
-            pos += header.compressed_size as usize;
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
 
-            frames.push(FrameInfo {
+    /// Frame counts, sizes, compression, and jump-list depth
+    pub fn stats(&self) -> Result<DiskStats> {
+        let mut frames = Vec::new();
+        for frame in self.frames()? {
+            let (header, header_size) = self.read_header(frame.header_offset)?;
+            frames.push(FrameStats {
                 frame_number: header.frame_number,
-                header_offset,
-                compressed_size: header.compressed_size,
+                header_offset: frame.header_offset,
+                header_size: header_size as u64,
+                compressed_size: frame.compressed_size,
                 decompressed_size: header.decompressed_size,
-                message_count: count,
-                first_message_id: message_id,
+                message_count: frame.message_count,
+                jump_count: header.jump_offsets.len(),
             });
-
-            message_id += count;
         }
 
-        Ok(frames)
+        let footer_start = (self.data.len() - FOOTER_SIZE) as u64;
+        let referenced_end = if frames.is_empty() {
+            0
+        } else {
+            let offset = read_footer(&self.data)?;
+            let (header, header_size) = self.read_header(offset)?;
+            offset + header_size as u64 + header.compressed_size
+        };
+
+        Ok(DiskStats {
+            file_size: self.data.len() as u64,
+            message_count: self.frames()?.iter().map(|f| f.message_count).sum(),
+            max_jump_count: frames.iter().map(|f| f.jump_count).max().unwrap_or(0),
+            frames,
+            wasted_tail_bytes: footer_start.saturating_sub(referenced_end),
+        })
     }
 
-    pub fn len(&self) -> u64 {
-        self.frames.iter().map(|f| f.message_count).sum()
+    /// Checks that frames are numbered in order, that every jump list
+    /// points at exactly the frames the skip list says it should, and
+    /// that the footer points at the last frame. Returns every problem.
+    pub fn check(&self) -> Result<Vec<DiskIssue>> {
+        let frames = self.frames()?;
+        let mut issues = Vec::new();
+
+        for (i, frame) in frames.iter().enumerate() {
+            let i = i as u64;
+            let Ok((header, _)) = self.read_header(frame.header_offset) else {
+                issues.push(DiskIssue::Header { frame: i });
+                continue;
+            };
+            if header.frame_number != i {
+                issues.push(DiskIssue::FrameNumber { frame: i, found: header.frame_number });
+            }
+
+            let expected: Vec<u64> = header.jump_targets().iter()
+                .filter_map(|&idx| frames.get(idx as usize).map(|f| f.header_offset))
+                .collect();
+            if header.jump_offsets != expected {
+                issues.push(DiskIssue::JumpList { frame: i, expected, found: header.jump_offsets.clone() });
+            }
+
+            for &offset in &header.jump_offsets {
+                let lands = frames.iter().any(|f| f.header_offset == offset)
+                    && self.read_header(offset).is_ok();
+                if !lands {
+                    issues.push(DiskIssue::JumpTarget { frame: i, offset });
+                }
+            }
+
+            for (target, offset) in header.forward() {
+                if frames.get(target as usize).is_none_or(|f| f.header_offset != offset) {
+                    issues.push(DiskIssue::ForwardTarget { frame: i, offset });
+                }
+            }
+        }
+
+        let expected = frames.last().map(|f| f.header_offset).unwrap_or(0);
+        let found = read_footer(&self.data)?;
+        if found != expected {
+            issues.push(DiskIssue::Footer { expected, found });
+        }
+
+        Ok(issues)
     }
 
-    pub fn read(&self, id: MessageId) -> Result<Vec<u8>> {
-        // Find frame containing this message
-        let frame_idx = self.find_frame(id.0)?;
-        let frame_info = &self.frames[frame_idx];
+    /// Reads every frame that can still be read, even if the index can't
+    /// be built, and reports the rest. See the module docs.
+    pub fn salvage(&self) -> Result<Salvage> {
+        let footer_start = (self.data.len() - FOOTER_SIZE) as u64;
+        // A header only counts if its frame fits before the footer
+        let header_at = |offset: u64| -> Option<(FrameHeader, usize)> {
+            let (header, size) = self.read_header(offset).ok()?;
+            let end = offset.checked_add(size as u64)?.checked_add(header.compressed_size)?;
+            (end <= footer_start).then_some((header, size))
+        };
+
+        // Forward from the start until a header fails
+        let mut headers = std::collections::BTreeMap::new();
+        let mut offset = 0;
+        while offset < footer_start {
+            let Some((header, size)) = header_at(offset) else { break };
+            if header.frame_number != headers.len() as u64 {
+                break;
+            }
+            let next = offset + size as u64 + header.compressed_size;
+            headers.insert(header.frame_number, (offset, header, size));
+            offset = next;
+        }
+
+        // Back from the footer along every jump, checking each header is
+        // the frame its jump list says it should be
+        let last = read_footer(&self.data)?;
+        let mut tail_lost = false;
+        if offset < footer_start {
+            let mut seen: std::collections::HashSet<u64> = headers.values().map(|(offset, _, _)| *offset).collect();
+            let mut pending = vec![(last, None)];
+            while let Some((offset, expected)) = pending.pop() {
+                if !seen.insert(offset) {
+                    continue;
+                }
+                let Some((header, size)) = header_at(offset) else {
+                    tail_lost |= offset == last;
+                    continue;
+                };
+                if expected.is_some_and(|expected| expected != header.frame_number) {
+                    continue;
+                }
+                let jumps = header.jump_targets();
+                pending.extend(header.jump_offsets.iter().copied().zip(jumps.into_iter().map(Some)));
+                headers.insert(header.frame_number, (offset, header, size));
+            }
+        }
 
-        // Decompress frame
-        let decompressed = self.decompress_frame(frame_idx)?;
+        let mut salvage = Salvage::default();
+        let frame_end = headers.keys().next_back().map_or(0, |n| n + 1);
+        let mut next = Some(0u64);
+        let mut lost: Option<(u64, Option<u64>)> = None;
+        for n in 0..frame_end {
+            let Some((offset, header, size)) = headers.get(&n) else {
+                lost.get_or_insert((n, next));
+                next = None;
+                continue;
+            };
+            let first = header.first_message.or(next);
+            if let Some((start, start_id)) = lost.take() {
+                let messages = start_id.zip(first).map(|(start, end)| MessageId(start)..MessageId(end));
+                salvage.damage.push(Damage { frames: start..n, messages });
+            }
 
-        // Parse messages in frame to find the right one
-        let message_offset_in_frame = (id.0 - frame_info.first_message_id) as usize;
+            let data_start = (offset + *size as u64) as usize;
+            let compressed = &self.data[data_start..data_start + header.compressed_size as usize];
+            let payloads = decompress(header, compressed).and_then(|decompressed| {
+                let count = match header.count() {
+                    Some(count) => count,
+                    None => count_messages(header, &decompressed)?,
+                };
+                frame_messages(header, &decompressed, 0..count as usize)
+            });
+            match payloads {
+                Ok(payloads) => {
+                    let count = payloads.len() as u64;
+                    match first {
+                        Some(first) => salvage.messages.extend((first..).map(MessageId).zip(payloads)),
+                        None => salvage.unplaced.push((n, payloads)),
+                    }
+                    next = first.map(|first| first + count);
+                }
+                Err(_) => {
+                    let end = first.zip(header.count()).map(|(first, count)| first + count);
+                    let messages = first.zip(end).map(|(start, end)| MessageId(start)..MessageId(end));
+                    salvage.damage.push(Damage { frames: n..n + 1, messages });
+                    next = end;
+                }
+            }
+        }
+        if tail_lost {
+            let start = lost.map_or(frame_end, |(start, _)| start);
+            salvage.damage.push(Damage { frames: start..u64::MAX, messages: None });
+        }
+        Ok(salvage)
+    }
 
+    /// Decodes the frame header at `offset`, returning it and its size
+    fn read_header(&self, offset: u64) -> Result<(FrameHeader, usize)> {
         use crate::neopack::{Cursor, Decoder};
-        let cursor = Cursor::new(&decompressed);
+        let start = offset as usize;
+        if start >= self.data.len() {
+            return Err(Error::InvalidFormat);
+        }
+        let cursor = Cursor::new(&self.data[start..]);
         let mut decoder = Decoder::with_cursor(cursor);
+        let header = FrameHeader::decode(decoder.raw_value()?)?;
+        Ok((header, decoder.pos()))
+    }
 
-        // Skip to target message
-        for _ in 0..message_offset_in_frame {
-            decoder.skip_value()?;
-        }
+    /// Reads message `id`. Every frame is compressed, so this is always
+    /// owned for now; it is a `Cow` so a frame stored raw could be sliced
+    /// straight out of the map.
+    pub fn read(&self, id: MessageId) -> Result<Cow<'_, [u8]>> {
+        let mut messages = self.read_range(id.0..id.0 + 1)?;
+        messages.pop().map(Cow::Owned).ok_or(Error::MessageNotFound(id.0))
+    }
+
+    /// Reads message `id` with its type byte, which is `None` unless its
+    /// frame's envelope is typed
+    pub fn read_typed(&self, id: MessageId) -> Result<(Option<u8>, Cow<'_, [u8]>)> {
+        let mut records = self.read_records(id.0..id.0 + 1)?;
+        let (kind, message) = records.pop().ok_or(Error::MessageNotFound(id.0))?;
+        Ok((kind, Cow::Owned(message)))
+    }
 
-        // Extract raw bytes of target message
-        let msg = decoder.raw_value()?;
-        Ok(msg.to_vec())
+    /// Reads every message in `range`, decompressing each frame it spans
+    /// once rather than once per message.
+    pub fn read_range(&self, range: Range<u64>) -> Result<Vec<Vec<u8>>> {
+        let records = self.read_records(range)?;
+        Ok(records.into_iter().map(|(_, message)| message).collect())
+    }
+
+    fn read_records(&self, range: Range<u64>) -> Result<Vec<(Option<u8>, Vec<u8>)>> {
+        let mut messages = Vec::with_capacity(range.end.saturating_sub(range.start) as usize);
+        let mut next = range.start;
+        while next < range.end {
+            let frame_idx = self.find_frame(next)?;
+            let frame_info = &self.frames()?[frame_idx];
+            let (header, decompressed) = self.decompress_frame(frame_idx)?;
+
+            let first = (next - frame_info.first_message_id) as usize;
+            let frame_end = frame_info.first_message_id + frame_info.message_count;
+            let last = (range.end.min(frame_end) - frame_info.first_message_id) as usize;
+
+            messages.extend(frame_records(&header, &decompressed, first..last)?);
+            next = frame_end.min(range.end);
+        }
+        Ok(messages)
     }
 
     fn find_frame(&self, message_id: u64) -> Result<usize> {
-        for (idx, frame) in self.frames.iter().enumerate() {
+        for (idx, frame) in self.frames()?.iter().enumerate() {
             if message_id >= frame.first_message_id
                 && message_id < frame.first_message_id + frame.message_count {
                 return Ok(idx);
@@ -365,30 +1446,237 @@ impl NeoDiskReader {
         Err(Error::MessageNotFound(message_id))
     }
 
-    fn decompress_frame(&self, frame_idx: usize) -> Result<Vec<u8>> {
-        let frame = self.frames.get(frame_idx)
+    fn decompress_frame(&self, frame_idx: usize) -> Result<(FrameHeader, Vec<u8>)> {
+        let frame = self.frames()?.get(frame_idx)
             .ok_or(Error::FrameNotFound(frame_idx as u64))?;
 
         // Parse header to get its size, then read compressed data after it
-        use crate::neopack::{Cursor, Decoder};
-        let cursor = Cursor::new(&self.mmap[frame.header_offset as usize..]);
-        let mut decoder = Decoder::with_cursor(cursor);
-        let _header = FrameHeader::decode(decoder.raw_value()?)?;
-        let header_size = decoder.pos();
+        let (header, header_size) = self.read_header(frame.header_offset)?;
 
         // Compressed data starts right after header
         let data_start = frame.header_offset as usize + header_size;
         let data_end = data_start + frame.compressed_size as usize;
-        let compressed = &self.mmap[data_start..data_end];
+        let compressed = &self.data[data_start..data_end];
+
+        let decompressed = decompress(&header, compressed)?;
+        Ok((header, decompressed))
+    }
+}
+
+/// Truncated blake3 of a frame's compressed data
+fn frame_checksum(compressed: &[u8]) -> u64 {
+    let hash = blake3::hash(compressed);
+    u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap())
+}
+
+/// Truncated blake3 of a message's payload, kept in its envelope
+fn message_checksum(payload: &[u8]) -> u32 {
+    let hash = blake3::hash(payload);
+    u32::from_le_bytes(hash.as_bytes()[..4].try_into().unwrap())
+}
+
+/// Checks a frame's compressed data against the checksum in its header,
+/// if it has one, then decompresses it
+pub(crate) fn decompress(header: &FrameHeader, compressed: &[u8]) -> Result<Vec<u8>> {
+    if header.checksum.is_some_and(|checksum| checksum != frame_checksum(compressed)) {
+        return Err(Error::ChecksumMismatch { frame: header.frame_number });
+    }
+    // Read at most one byte past the recorded size, so a frame that
+    // decompresses to more is caught without holding all of it
+    let decoder = zstd::stream::read::Decoder::new(compressed)
+        .map_err(|e| Error::Compression(e.to_string()))?;
+    let mut decompressed = Vec::new();
+    decoder.take(header.decompressed_size.saturating_add(1))
+        .read_to_end(&mut decompressed)
+        .map_err(|e| Error::Compression(e.to_string()))?;
+    if decompressed.len() as u64 != header.decompressed_size {
+        return Err(Error::DecompressedSize { frame: header.frame_number });
+    }
+    Ok(decompressed)
+}
+
+/// Reads one frame header at the current position of `source`, refusing
+/// headers that would extend more than `limit` bytes. Returns the header
+/// and its encoded size.
+pub(crate) fn read_header_from<R: Read>(source: &mut R, limit: u64) -> Result<(FrameHeader, usize)> {
+    // Header is a neopack List: tag, u32 body length, body
+    let mut header = vec![0u8; 5];
+    source.read_exact(&mut header)?;
+    let body_len = u32::from_le_bytes(header[1..5].try_into().unwrap()) as usize;
+    if (5 + body_len) as u64 > limit {
+        return Err(Error::InvalidFormat);
+    }
+    header.resize(5 + body_len, 0);
+    source.read_exact(&mut header[5..])?;
+    Ok((FrameHeader::decode(&header)?, header.len()))
+}
+
+/// Walks the frames from the start of `source` up to `footer_start`.
+/// Frames whose headers record a message count are skipped over without
+/// reading their data; older frames are decompressed to count messages.
+pub(crate) fn scan_headers<R: Read + Seek>(source: &mut R, footer_start: u64) -> Result<Vec<FrameInfo>> {
+    let mut frames = Vec::new();
+    let mut pos = 0u64;
+    let mut message_id = 0u64;
+
+    while pos < footer_start {
+        let header_offset = pos;
+        let (header, header_size) = read_header_from(source, footer_start - pos)?;
+        pos += header_size as u64;
+
+        // Validate compressed data doesn't extend beyond footer
+        if header.compressed_size > footer_start - pos {
+            return Err(Error::InvalidFormat);
+        }
 
-        zstd::decode_all(compressed)
-            .map_err(|e| Error::Compression(e.to_string()))
+        let count = match header.count() {
+            Some(count) => {
+                source.seek(SeekFrom::Current(header.compressed_size as i64))?;
+                count
+            }
+            None => {
+                let mut compressed = vec![0u8; header.compressed_size as usize];
+                source.read_exact(&mut compressed)?;
+                count_messages(&header, &decompress(&header, &compressed)?)?
+            }
+        };
+        // Every message takes at least a byte, so a larger count is forged
+        if count > header.decompressed_size {
+            return Err(Error::InvalidFormat);
+        }
+
+        pos += header.compressed_size;
+
+        frames.push(FrameInfo::new(&header, header_offset, header_size as u64, count, message_id));
+
+        message_id = message_id.checked_add(count).ok_or(Error::InvalidFormat)?;
+    }
+
+    Ok(frames)
+}
+
+/// Reads the whole frame at the current position of `source`, which must
+/// fit in `limit` bytes, checking its data against its checksum. Returns
+/// the header and the number of messages the data holds.
+fn read_whole_frame<R: Read>(source: &mut R, limit: u64) -> Result<(FrameHeader, u64)> {
+    let (header, header_size) = read_header_from(source, limit)?;
+    if header.compressed_size > limit - header_size as u64 {
+        return Err(Error::InvalidFormat);
+    }
+    let mut compressed = vec![0u8; header.compressed_size as usize];
+    source.read_exact(&mut compressed)?;
+
+    let count = count_messages(&header, &decompress(&header, &compressed)?)?;
+    if header.count().is_some_and(|recorded| recorded != count) {
+        return Err(Error::InvalidFormat);
+    }
+    Ok((header, count))
+}
+
+/// Copies out messages `range` of a decompressed frame, by index within
+/// the frame.
+pub(crate) fn frame_messages(header: &FrameHeader, decompressed: &[u8], range: Range<usize>) -> Result<Vec<Vec<u8>>> {
+    let records = frame_records(header, decompressed, range)?;
+    Ok(records.into_iter().map(|(_, message)| message).collect())
+}
+
+/// Like `frame_messages`, with each message's type byte if its envelope
+/// has one. Payloads are checked against their envelope checksums.
+fn frame_records(header: &FrameHeader, decompressed: &[u8], range: Range<usize>) -> Result<Vec<(Option<u8>, Vec<u8>)>> {
+    // The range comes from the header, so allocate no more than the data
+    // could hold
+    let mut records = Vec::with_capacity(range.len().min(decompressed.len()));
+    // Slice directly if the header records message sizes, else walk
+    // the messages before the range
+    let mut pos = 0;
+    if header.message_sizes.is_none() {
+        for _ in 0..range.start {
+            pos += record_len(header.envelope, &decompressed[pos..])?;
+        }
+    }
+    for index in range {
+        let record = if header.message_sizes.is_some() {
+            let range = header.message_range(index).ok_or(Error::InvalidFormat)?;
+            decompressed.get(range).ok_or(Error::InvalidFormat)?
+        } else {
+            let len = record_len(header.envelope, &decompressed[pos..])?;
+            pos += len;
+            &decompressed[pos - len..pos]
+        };
+        let Some(envelope) = header.envelope else {
+            records.push((None, record.to_vec()));
+            continue;
+        };
+        // Sizes in the header must agree with the envelope's own length
+        let prefix = envelope.prefix_len();
+        let len = record.get(..4).map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize);
+        if record.len() < prefix || len != Some(record.len() - prefix) {
+            return Err(Error::InvalidFormat);
+        }
+        let payload = &record[prefix..];
+        if envelope.checksummed {
+            let at = record.len() - payload.len() - 4;
+            let checksum = u32::from_le_bytes(record[at..at + 4].try_into().unwrap());
+            if checksum != message_checksum(payload) {
+                return Err(Error::MessageChecksumMismatch { frame: header.frame_number, index: index as u64 });
+            }
+        }
+        records.push((envelope.typed.then(|| record[4]), payload.to_vec()));
     }
+    Ok(records)
+}
+
+/// Length of the message at the start of `data`: its envelope and
+/// payload, or the neopack value if there's no envelope. Fails if the
+/// message runs past the end of `data`.
+fn record_len(envelope: Option<Envelope>, data: &[u8]) -> Result<usize> {
+    let Some(envelope) = envelope else {
+        use crate::neopack::{Cursor, Decoder};
+        let mut decoder = Decoder::with_cursor(Cursor::new(data));
+        decoder.skip_value()?;
+        return Ok(decoder.pos());
+    };
+    let prefix = envelope.prefix_len();
+    let len = data.get(..4).ok_or(Error::InvalidFormat)?;
+    let len = prefix + u32::from_le_bytes(len.try_into().unwrap()) as usize;
+    if data.len() < len {
+        return Err(Error::InvalidFormat);
+    }
+    Ok(len)
+}
+
+/// Counts the messages in a decompressed frame
+fn count_messages(header: &FrameHeader, decompressed: &[u8]) -> Result<u64> {
+    let mut pos = 0;
+    let mut count = 0u64;
+    while pos < decompressed.len() {
+        pos += record_len(header.envelope, &decompressed[pos..])?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Checks the footer magic and version, and returns the last frame's
+/// header offset
+pub(crate) fn read_footer(data: &[u8]) -> Result<u64> {
+    if data.len() < FOOTER_SIZE {
+        return Err(Error::InvalidFormat);
+    }
+    let footer_start = data.len() - FOOTER_SIZE;
+    let (magic, version) = data[footer_start + 8..].split_at(7);
+    if magic != &NEODISK_MAGIC[..7] {
+        return Err(Error::InvalidFormat);
+    }
+    if version[0] > NEODISK_VERSION {
+        return Err(Error::UnsupportedVersion { found: version[0], expected: NEODISK_VERSION });
+    }
+    Ok(u64::from_le_bytes(data[footer_start..footer_start + 8].try_into().unwrap()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::jumpheader::find_jump_path;
     use crate::neopack::Encoder;
 
     #[test]
@@ -411,7 +1699,7 @@ mod tests {
         // Read
         {
             let reader = NeoDiskReader::open(path)?;
-            assert_eq!(reader.len(), 10);
+            assert_eq!(reader.len()?, 10);
 
             for i in 0..10 {
                 let msg = reader.read(MessageId(i))?;
@@ -444,7 +1732,7 @@ mod tests {
 
         {
             let reader = NeoDiskReader::open(path)?;
-            assert_eq!(reader.len(), 50);
+            assert_eq!(reader.len()?, 50);
 
             // Read from different frames
             let msg0 = reader.read(MessageId(0))?;
@@ -493,7 +1781,7 @@ mod tests {
 
         {
             let reader = NeoDiskReader::open(path)?;
-            assert_eq!(reader.len(), 100);
+            assert_eq!(reader.len()?, 100);
 
             // Verify we can read all messages
             for i in 0..100 {
@@ -507,4 +1795,753 @@ mod tests {
         std::fs::remove_file(path)?;
         Ok(())
     }
+
+    #[test]
+    fn test_reopen_and_truncate() -> Result<()> {
+        let path = "/tmp/test_neodisk_truncate.nd";
+
+        let append = |writer: &mut NeoDiskWriter, range: std::ops::Range<u64>| -> Result<()> {
+            for i in range {
+                let mut enc = Encoder::new();
+                enc.u64(i).unwrap();
+                writer.append(enc.as_bytes())?;
+            }
+            Ok(())
+        };
+
+        {
+            let mut writer = NeoDiskWriter::create_with_frame_size(path, 50)?;
+            append(&mut writer, 0..40)?;
+            writer.flush()?;
+            writer.flush()?;
+        }
+
+        {
+            // Reopening overwrites the old footer instead of appending after it
+            let mut writer = NeoDiskWriter::open(path)?;
+            append(&mut writer, 40..60)?;
+
+            // Cut inside a flushed frame, then inside the unflushed buffer
+            writer.truncate(25)?;
+            append(&mut writer, 25..30)?;
+            writer.truncate(27)?;
+            writer.flush()?;
+        }
+
+        let reader = NeoDiskReader::open(path)?;
+        assert_eq!(reader.len()?, 27);
+        for i in 0..27 {
+            use crate::neopack::Decoder;
+            let msg = reader.read(MessageId(i))?;
+            let mut dec = Decoder::new(&msg);
+            assert_eq!(dec.u64().unwrap(), i);
+        }
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats_and_check() -> Result<()> {
+        let path = "/tmp/test_neodisk_stats.nd";
+
+        {
+            let mut writer = NeoDiskWriter::create_with_frame_size(path, 50)?;
+            for i in 0..100 {
+                let mut enc = Encoder::new();
+                enc.str(&format!("message_{}", i % 3)).unwrap();
+                writer.append(enc.as_bytes())?;
+            }
+            writer.flush()?;
+        }
+
+        let reader = NeoDiskReader::open(path)?;
+        let stats = reader.stats()?;
+        assert_eq!(stats.message_count, 100);
+        assert_eq!(stats.frames.iter().map(|f| f.message_count).sum::<u64>(), 100);
+        assert_eq!(stats.file_size, std::fs::metadata(path)?.len());
+        assert_eq!(stats.wasted_tail_bytes, 0);
+        assert!(stats.max_jump_count >= 3);
+        assert!(stats.frames.iter().enumerate().all(|(i, f)| f.frame_number == i as u64));
+        assert!(reader.check()?.is_empty());
+        drop(reader);
+
+        // Point the footer at the first frame instead of the last
+        let mut data = std::fs::read(path)?;
+        let footer_start = data.len() - FOOTER_SIZE;
+        data[footer_start..footer_start + 8].copy_from_slice(&0u64.to_le_bytes());
+        std::fs::write(path, &data)?;
+
+        let reader = NeoDiskReader::open(path)?;
+        let issues = reader.check()?;
+        assert!(matches!(issues.as_slice(), [DiskIssue::Footer { found: 0, .. }]));
+        assert!(reader.stats()?.wasted_tail_bytes > 0);
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_seek_to_frame() -> Result<()> {
+        let path = "/tmp/test_neodisk_seek.nd";
+
+        {
+            let mut writer = NeoDiskWriter::create_with_frame_size(path, 20)?;
+            for i in 0..200 {
+                let mut enc = Encoder::new();
+                enc.u64(i).unwrap();
+                writer.append(enc.as_bytes())?;
+            }
+            writer.flush()?;
+        }
+
+        let reader = NeoDiskReader::open_lazy(path)?;
+        let count = reader.frame_count()?;
+        assert!(count > 40);
+
+        let mut offsets = Vec::new();
+        for n in 0..count {
+            offsets.push(reader.seek_to_frame(n)?);
+        }
+        assert!(reader.frames.get().is_none(), "seeking must not build the index");
+        assert!(matches!(reader.seek_to_frame(count), Err(Error::FrameNotFound(_))));
+
+        let indexed: Vec<u64> = reader.frames()?.iter().map(|f| f.header_offset).collect();
+        assert_eq!(offsets, indexed);
+        assert_eq!(reader.len()?, 200);
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_lazy_len_reports_bad_headers() -> Result<()> {
+        let path = "/tmp/test_neodisk_lazy_len.nd";
+
+        {
+            let mut writer = NeoDiskWriter::create_with_frame_size(path, 20)?;
+            for i in 0..50 {
+                let mut enc = Encoder::new();
+                enc.u64(i).unwrap();
+                writer.append(enc.as_bytes())?;
+            }
+            writer.flush()?;
+        }
+
+        // The footer is intact, so only building the index finds this
+        let mut data = std::fs::read(path)?;
+        data[..8].fill(0xff);
+        std::fs::write(path, &data)?;
+
+        let reader = NeoDiskReader::open_lazy(path)?;
+        assert!(reader.len().is_err());
+        assert!(reader.is_empty().is_err());
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_walk_back_reads_headers_only() -> Result<()> {
+        let path = "/tmp/test_neodisk_walk_back.nd";
+
+        {
+            let mut writer = NeoDiskWriter::create_with_frame_size(path, 20)?;
+            for i in 0..200 {
+                let mut enc = Encoder::new();
+                enc.u64(i).unwrap();
+                writer.append(enc.as_bytes())?;
+            }
+            writer.flush()?;
+        }
+
+        // Scribble over every frame's compressed data; walking must not
+        // need any of it
+        let reader = NeoDiskReader::open(path)?;
+        let count = reader.frame_count()?;
+        let mut data = std::fs::read(path)?;
+        for frame in reader.frames()? {
+            let (_, header_size) = reader.read_header(frame.header_offset)?;
+            let start = frame.header_offset as usize + header_size;
+            data[start..start + frame.compressed_size as usize].fill(0xff);
+        }
+        std::fs::write(path, &data)?;
+
+        let reader = NeoDiskReader::open_lazy(path)?;
+        for n in [0, 1, 17, count - 1] {
+            assert_eq!(reader.frame_header(n)?.frame_number, n);
+        }
+        for (from, to) in [(count - 1, 0), (count - 1, 17), (24, 17), (1, 0), (9, 9)] {
+            let chain: Vec<u64> = reader.walk_back(from, to)?.iter().map(|h| h.frame_number).collect();
+            let expected = match from == to {
+                true => vec![from],
+                false => find_jump_path(from, to).unwrap(),
+            };
+            assert_eq!(chain, expected);
+        }
+        assert!(reader.frames.get().is_none(), "walking must not build the index");
+        assert!(matches!(reader.frame_header(count), Err(Error::FrameNotFound(_))));
+        assert!(matches!(reader.walk_back(3, 5), Err(Error::JumpPath(JumpPathError::NotBehind { .. }))));
+        assert!(reader.read(MessageId(0)).is_err());
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_jump_density_and_forward_pointers() -> Result<()> {
+        let path = "/tmp/test_neodisk_density.nd";
+        let append = |writer: &mut NeoDiskWriter, range: Range<u64>| -> Result<()> {
+            for i in range {
+                let mut enc = Encoder::new();
+                enc.u64(i).unwrap();
+                writer.append(enc.as_bytes())?;
+            }
+            writer.flush()
+        };
+
+        {
+            let mut writer = NeoDiskWriter::create_with_frame_size(path, 20)?
+                .with_jump_density(4)
+                .with_forward_pointers(true);
+            append(&mut writer, 0..300)?;
+        }
+
+        let reader = NeoDiskReader::open_lazy(path)?;
+        let count = reader.frame_count()?;
+        assert!(count > 60);
+        let header = reader.frame_header(5)?;
+        assert_eq!((header.density(), header.jump_targets()), (4, vec![4]));
+        assert!(header.forward_offsets.is_none());
+        let header = reader.frame_header(8)?;
+        assert_eq!(header.jump_targets(), vec![4, 7]);
+        assert_eq!(header.forward().collect::<Vec<_>>().len(), 2);
+
+        for (from, to) in [(count - 1, 0), (count - 1, 33), (47, 45), (9, 9)] {
+            let chain: Vec<u64> = reader.walk_back(from, to)?.iter().map(|h| h.frame_number).collect();
+            assert_eq!((chain[0], chain[chain.len() - 1]), (from, to));
+        }
+        for (from, to) in [(0, count - 1), (3, count - 2), (33, 47), (9, 9)] {
+            let chain: Vec<u64> = reader.walk_forward(from, to)?.iter().map(|h| h.frame_number).collect();
+            assert_eq!((chain[0], chain[chain.len() - 1]), (from, to));
+            assert!(chain.windows(2).all(|step| step[0] < step[1]));
+        }
+        assert!(reader.walk_forward(0, count - 1)?.len() < 20);
+        assert!(reader.frames.get().is_none(), "walking must not build the index");
+        assert!(matches!(reader.walk_forward(5, 4), Err(Error::JumpPath(JumpPathError::NotAhead { .. }))));
+        assert!(reader.check()?.is_empty());
+        drop(reader);
+
+        // Cutting frames clears the slots that pointed at them, and writing
+        // them again patches the slots anew
+        let mut writer = NeoDiskWriter::open(path)?
+            .with_flush_policy(FlushPolicy::Bytes(20))
+            .with_jump_density(4)
+            .with_forward_pointers(true);
+        writer.truncate(100)?;
+        writer.flush()?;
+        let reader = NeoDiskReader::open(path)?;
+        assert!(reader.check()?.is_empty());
+        assert_eq!(reader.read(MessageId(99))?, Encoder::new().u64(99).unwrap().as_bytes());
+        drop(reader);
+        append(&mut writer, 100..300)?;
+        let reader = NeoDiskReader::open(path)?;
+        assert!(reader.check()?.is_empty());
+        assert_eq!(reader.walk_forward(0, count - 1)?.last().map(|h| h.frame_number), Some(count - 1));
+        assert_eq!(reader.read_range(0..300)?.len(), 300);
+        let damaged = reader.frames()?[12].clone();
+        let next = reader.frames()?[13].header_offset;
+        drop(reader);
+        drop(writer);
+
+        // Damage in frame 12 cuts it and every frame after it on recovery,
+        // and no earlier slot is left pointing at any of them
+        let mut bytes = std::fs::read(path)?;
+        bytes[next as usize - 1] ^= 0xff;
+        std::fs::write(path, &bytes)?;
+        let (writer, cut) = NeoDiskWriter::recover(path)?;
+        assert_eq!(cut, bytes.len() as u64 - damaged.header_offset);
+        assert_eq!(writer.len(), damaged.first_message_id);
+        let reader = NeoDiskReader::open(path)?;
+        assert!(reader.check()?.is_empty());
+        drop(reader);
+        let mut writer = writer
+            .with_flush_policy(FlushPolicy::Bytes(20))
+            .with_jump_density(4)
+            .with_forward_pointers(true);
+        append(&mut writer, damaged.first_message_id..300)?;
+        let reader = NeoDiskReader::open(path)?;
+        assert!(reader.check()?.is_empty());
+        assert_eq!(reader.read_range(0..300)?.len(), 300);
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_density_bumps_log_version() -> Result<()> {
+        let path = "/tmp/test_neodisk_log_version.nd";
+        let version = || std::fs::read(path).map(|bytes| bytes[bytes.len() - 1]);
+        let append = |writer: &mut NeoDiskWriter, range: Range<u64>| -> Result<()> {
+            for i in range {
+                writer.append(Encoder::new().u64(i).unwrap().as_bytes())?;
+            }
+            writer.flush()
+        };
+
+        let mut writer = NeoDiskWriter::create_with_frame_size(path, 20)?;
+        append(&mut writer, 0..50)?;
+        assert_eq!(version()?, 0);
+        drop(writer);
+
+        // Sparse frames make it version 1, which a reader that only knows
+        // version 0's magic refuses
+        let mut writer = NeoDiskWriter::open(path)?
+            .with_flush_policy(FlushPolicy::Bytes(20))
+            .with_jump_density(4);
+        append(&mut writer, 50..100)?;
+        assert_eq!(version()?, 1);
+        let bytes = std::fs::read(path)?;
+        assert_ne!(&bytes[bytes.len() - 8..], NEODISK_MAGIC);
+        assert_eq!(NeoDiskReader::open(path)?.read_range(0..100)?.len(), 100);
+
+        // Cutting them off makes it version 0 again
+        writer.truncate(50)?;
+        writer.flush()?;
+        assert_eq!(version()?, 0);
+        drop(writer);
+
+        // Forward slots alone need version 1 too
+        let mut writer = NeoDiskWriter::open(path)?
+            .with_flush_policy(FlushPolicy::Bytes(20))
+            .with_forward_pointers(true);
+        append(&mut writer, 50..60)?;
+        assert_eq!(version()?, 1);
+        drop(writer);
+
+        // And a version from a newer build is refused
+        let mut bytes = std::fs::read(path)?;
+        let last = bytes.len() - 1;
+        bytes[last] = NEODISK_VERSION + 1;
+        std::fs::write(path, &bytes)?;
+        assert!(matches!(NeoDiskReader::open(path), Err(Error::UnsupportedVersion { found: 2, expected: 1 })));
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_range_across_frames() -> Result<()> {
+        for indexed in [false, true] {
+            let path = format!("/tmp/test_neodisk_read_range_{}.nd", indexed);
+            let mut expected = Vec::new();
+            {
+                let mut writer = NeoDiskWriter::create_with_frame_size(&path, 64)?
+                    .with_message_index(indexed);
+                for i in 0..100u64 {
+                    let mut enc = Encoder::new();
+                    enc.u64(i).unwrap();
+                    writer.append(enc.as_bytes())?;
+                    expected.push(enc.into_bytes());
+                }
+                writer.flush()?;
+            }
+
+            let reader = NeoDiskReader::open(&path)?;
+            assert!(reader.frame_count()? > 3);
+            assert_eq!(reader.read_range(0..100)?, expected);
+            assert_eq!(reader.read_range(37..61)?, expected[37..61]);
+            assert!(reader.read_range(5..5)?.is_empty());
+            assert!(matches!(reader.read_range(90..101), Err(Error::MessageNotFound(100))));
+
+            std::fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_index() -> Result<()> {
+        let path = "/tmp/test_neodisk_message_index.nd";
+
+        {
+            let mut writer = NeoDiskWriter::create_with_frame_size(path, 64)?
+                .with_message_index(true);
+            for i in 0..50 {
+                let mut enc = Encoder::new();
+                enc.str(&"x".repeat(i)).unwrap();
+                writer.append(enc.as_bytes())?;
+            }
+            // Cut mid-frame so the rewritten frame's sizes must be rebuilt
+            writer.truncate(45)?;
+            writer.flush()?;
+        }
+
+        let reader = NeoDiskReader::open(path)?;
+        assert_eq!(reader.len()?, 45);
+        let (header, _) = reader.decompress_frame(0)?;
+        assert!(header.message_sizes.is_some());
+        for i in 0..45 {
+            use crate::neopack::Decoder;
+            let msg = reader.read(MessageId(i))?;
+            let mut dec = Decoder::new(&msg);
+            assert_eq!(dec.str().unwrap(), "x".repeat(i as usize));
+        }
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_reads_headers_only() -> Result<()> {
+        let path = "/tmp/test_neodisk_headers_only.nd";
+
+        {
+            let mut writer = NeoDiskWriter::create_with_frame_size(path, 64)?;
+            for i in 0..40u64 {
+                let mut enc = Encoder::new();
+                enc.u64(i).unwrap();
+                writer.append(enc.as_bytes())?;
+            }
+            writer.flush()?;
+        }
+
+        // Scribble over the first frame's compressed data; reopening must
+        // not need it
+        let (header, header_size) = NeoDiskReader::open(path)?.read_header(0)?;
+        assert_eq!(header.message_count, Some(header.count().unwrap()));
+        let mut data = std::fs::read(path)?;
+        for byte in &mut data[header_size..header_size + header.compressed_size as usize] {
+            *byte = 0xff;
+        }
+        std::fs::write(path, &data)?;
+
+        let mut writer = NeoDiskWriter::open(path)?;
+        assert_eq!(writer.len(), 40);
+        let mut enc = Encoder::new();
+        enc.u64(40).unwrap();
+        writer.append(enc.as_bytes())?;
+        writer.flush()?;
+        drop(writer);
+
+        let reader = NeoDiskReader::open(path)?;
+        assert_eq!(reader.len()?, 41);
+        assert!(matches!(reader.read(MessageId(0)), Err(Error::ChecksumMismatch { frame: 0 })));
+        assert_eq!(reader.read(MessageId(40))?, enc.as_bytes());
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_batch_and_flush_policy() -> Result<()> {
+        let path = "/tmp/test_neodisk_flush_policy.nd";
+        let messages: Vec<Vec<u8>> = (0..25u64)
+            .map(|i| {
+                let mut enc = Encoder::new();
+                enc.u64(i).unwrap();
+                enc.into_bytes()
+            })
+            .collect();
+
+        {
+            let mut writer = NeoDiskWriter::create(path)?
+                .with_flush_policy(FlushPolicy::Messages(10));
+            let ids = writer.append_batch(&messages)?;
+            assert_eq!(ids, MessageId(0)..MessageId(25));
+            writer.flush()?;
+        }
+
+        let reader = NeoDiskReader::open(path)?;
+        let counts: Vec<u64> = reader.stats()?.frames.iter().map(|f| f.message_count).collect();
+        assert_eq!(counts, vec![10, 10, 5]);
+        for (i, message) in messages.iter().enumerate() {
+            assert_eq!(&reader.read(MessageId(i as u64))?, message);
+        }
+
+        // A zero age closes a frame after every message
+        {
+            let mut writer = NeoDiskWriter::create(path)?
+                .with_flush_policy(FlushPolicy::Elapsed(Duration::ZERO));
+            writer.append_batch(&messages[..3])?;
+            writer.flush()?;
+        }
+        assert_eq!(NeoDiskReader::open(path)?.frame_count()?, 3);
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_is_bounded_by_header() -> Result<()> {
+        // A megabyte of zeros compresses to a few dozen bytes
+        let data = vec![0u8; 1 << 20];
+        let compressed = zstd::encode_all(&data[..], ZSTD_LEVEL)?;
+        let header = |size: u64| FrameHeader::new(0, compressed.len() as u64, size, vec![]);
+
+        assert_eq!(decompress(&header(data.len() as u64), &compressed)?, data);
+        for size in [0, 16, data.len() as u64 - 1, data.len() as u64 + 1] {
+            assert!(matches!(decompress(&header(size), &compressed), Err(Error::DecompressedSize { frame: 0 })));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_checksum_detects_bitrot() -> Result<()> {
+        let path = "/tmp/test_neodisk_checksum.nd";
+
+        {
+            let mut writer = NeoDiskWriter::create(path)?
+                .with_flush_policy(FlushPolicy::Messages(4));
+            writer.append_batch((0..8u64).map(|i| {
+                let mut enc = Encoder::new();
+                enc.u64(i).unwrap();
+                enc.into_bytes()
+            }))?;
+            writer.flush()?;
+        }
+
+        // Flip one bit in the middle of frame 1's compressed data
+        let reader = NeoDiskReader::open(path)?;
+        let offset = reader.seek_to_frame(1)?;
+        let (header, header_size) = reader.read_header(offset)?;
+        assert!(header.checksum.is_some());
+        drop(reader);
+        let mut data = std::fs::read(path)?;
+        data[offset as usize + header_size + header.compressed_size as usize / 2] ^= 0x10;
+        std::fs::write(path, &data)?;
+
+        let reader = NeoDiskReader::open(path)?;
+        assert!(reader.read(MessageId(3)).is_ok());
+        assert!(matches!(reader.read(MessageId(5)), Err(Error::ChecksumMismatch { frame: 1 })));
+
+        // Rolling back into the damaged frame has to decompress it too
+        let mut writer = NeoDiskWriter::open(path)?;
+        assert!(matches!(writer.truncate(6), Err(Error::ChecksumMismatch { frame: 1 })));
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_holds_raw_bytes() -> Result<()> {
+        let path = "/tmp/test_neodisk_envelope.nd";
+        let envelope = Envelope { typed: true, checksummed: true };
+        // Not neopack values: 0xff isn't a tag, and the empty message
+        // has nothing to decode
+        let messages: Vec<Vec<u8>> = (0..20u8).map(|i| vec![0xff; i as usize % 7]).collect();
+
+        {
+            let mut writer = NeoDiskWriter::create(path)?
+                .with_flush_policy(FlushPolicy::Messages(6));
+            assert!(matches!(writer.append_typed(1, b"x"), Err(Error::Untyped)));
+            let mut enc = Encoder::new();
+            enc.u64(7).unwrap();
+            writer.append(&enc.into_bytes())?;
+            writer.flush()?;
+
+            let mut writer = writer.with_envelope(envelope).with_message_index(true);
+            for (i, message) in messages.iter().enumerate() {
+                writer.append_typed(i as u8, message)?;
+            }
+            writer.truncate(17)?;
+            writer.flush()?;
+        }
+
+        // Frames before the envelope was set keep reading as neopack
+        let reader = NeoDiskReader::open(path)?;
+        assert_eq!(reader.len()?, 17);
+        assert_eq!(reader.read_typed(MessageId(0))?.0, None);
+        for i in 1..17u64 {
+            let (kind, message) = reader.read_typed(MessageId(i))?;
+            assert_eq!(kind, Some(i as u8 - 1));
+            assert_eq!(&*message, &messages[i as usize - 1][..]);
+        }
+        assert_eq!(reader.read_range(1..17)?, messages[..16]);
+        drop(reader);
+
+        // Without sizes in the header the envelope alone splits the frame
+        {
+            let mut writer = NeoDiskWriter::create(path)?.with_envelope(envelope);
+            writer.append_batch(&messages)?;
+            writer.flush()?;
+        }
+        let reader = NeoDiskReader::open(path)?;
+        assert_eq!(reader.read_range(0..20)?, messages);
+
+        // A payload that fails its checksum is named, not its frame
+        let (header, header_size) = reader.read_header(0)?;
+        drop(reader);
+        let mut decompressed = zstd::decode_all(&std::fs::read(path)?[header_size..][..header.compressed_size as usize])?;
+        let at = (0..5).map(|i| envelope.prefix_len() + i % 7).sum::<usize>() + envelope.prefix_len();
+        decompressed[at] ^= 1;
+        let compressed = zstd::encode_all(&decompressed[..], ZSTD_LEVEL)?;
+        let header = FrameHeader::new(0, compressed.len() as u64, decompressed.len() as u64, vec![])
+            .with_message_count(20)
+            .with_envelope(envelope);
+        let mut data = header.encode()?;
+        data.extend_from_slice(&compressed);
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(NEODISK_MAGIC);
+        let reader = NeoDiskReader::from_bytes(data)?;
+        assert_eq!(&*reader.read(MessageId(4))?, &messages[4][..]);
+        assert!(matches!(reader.read(MessageId(5)), Err(Error::MessageChecksumMismatch { frame: 0, index: 5 })));
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_envelope_sizes_must_fit_records() -> Result<()> {
+        let envelope = Envelope { typed: false, checksummed: false };
+        let frame = |decompressed: &[u8], sizes: Vec<u32>| -> Result<NeoDiskReader> {
+            let compressed = zstd::encode_all(decompressed, ZSTD_LEVEL)?;
+            let header = FrameHeader::new(0, compressed.len() as u64, decompressed.len() as u64, vec![])
+                .with_message_count(sizes.len() as u64)
+                .with_message_sizes(sizes)
+                .with_envelope(envelope);
+            let mut data = header.encode()?;
+            data.extend_from_slice(&compressed);
+            data.extend_from_slice(&0u64.to_le_bytes());
+            data.extend_from_slice(NEODISK_MAGIC);
+            NeoDiskReader::from_bytes(data)
+        };
+
+        let reader = frame(&[2, 0, 0, 0, 0xaa, 0xbb], vec![6])?;
+        assert_eq!(&*reader.read(MessageId(0))?, &[0xaa, 0xbb]);
+
+        // A record shorter than the envelope's prefix
+        let short = frame(&[0xaa, 0xbb], vec![2]).and_then(|reader| reader.read(MessageId(0)).map(|_| ()));
+        assert!(matches!(short, Err(Error::InvalidFormat)));
+
+        // An envelope whose length disagrees with the recorded size
+        let long = frame(&[1, 0, 0, 0, 0xaa, 0xbb], vec![6]).and_then(|reader| reader.read(MessageId(0)).map(|_| ()));
+        assert!(matches!(long, Err(Error::InvalidFormat)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_salvage_skips_damaged_frames() -> Result<()> {
+        let path = "/tmp/test_neodisk_salvage.nd";
+        let message = |i: u64| {
+            let mut enc = Encoder::new();
+            enc.u64(i).unwrap();
+            enc.into_bytes()
+        };
+
+        {
+            let mut writer = NeoDiskWriter::create(path)?
+                .with_flush_policy(FlushPolicy::Messages(4));
+            writer.append_batch((0..40).map(message))?;
+            writer.flush()?;
+        }
+        assert!(NeoDiskReader::open(path)?.salvage()?.is_intact());
+
+        // Wreck frame 3's header, and flip a data byte in frame 6
+        let reader = NeoDiskReader::open(path)?;
+        let header_3 = reader.seek_to_frame(3)?;
+        let data_6 = {
+            let offset = reader.seek_to_frame(6)?;
+            let (header, size) = reader.read_header(offset)?;
+            offset as usize + size + header.compressed_size as usize / 2
+        };
+        drop(reader);
+        let mut data = std::fs::read(path)?;
+        data[header_3 as usize..header_3 as usize + 8].fill(0xff);
+        data[data_6] ^= 0x10;
+        std::fs::write(path, &data)?;
+        assert!(NeoDiskReader::open(path).is_err());
+
+        let salvage = NeoDiskReader::open_lazy(path)?.salvage()?;
+        assert_eq!(salvage.damage, vec![
+            Damage { frames: 3..4, messages: Some(MessageId(12)..MessageId(16)) },
+            Damage { frames: 6..7, messages: Some(MessageId(24)..MessageId(28)) },
+        ]);
+        assert!(salvage.unplaced.is_empty());
+        let expected: Vec<_> = (0..40)
+            .filter(|i| !(12..16).contains(i) && !(24..28).contains(i))
+            .map(|i| (MessageId(i), message(i)))
+            .collect();
+        assert_eq!(salvage.messages, expected);
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_replayed_stream_is_reproducible() -> Result<()> {
+        let paths = ["/tmp/test_neodisk_replay_a.nd", "/tmp/test_neodisk_replay_b.nd"];
+        let messages: Vec<Vec<u8>> = (0..300u64)
+            .map(|i| {
+                let mut enc = Encoder::new();
+                enc.str(&"x".repeat(i as usize % 37)).unwrap();
+                enc.into_bytes()
+            })
+            .collect();
+
+        // Batched one way, appended one by one the other: only the
+        // messages and the flush between them decide the frames
+        {
+            let mut writer = NeoDiskWriter::create(paths[0])?.with_flush_policy(FlushPolicy::Bytes(512));
+            writer.append_batch(&messages[..120])?;
+            writer.flush()?;
+            writer.append_batch(&messages[120..])?;
+            writer.flush()?;
+        }
+        {
+            let mut writer = NeoDiskWriter::create(paths[1])?.with_flush_policy(FlushPolicy::Bytes(512));
+            for message in &messages[..120] {
+                writer.append(message)?;
+            }
+            writer.flush()?;
+            for message in &messages[120..] {
+                writer.append(message)?;
+            }
+            writer.flush()?;
+        }
+
+        let [a, b] = paths.map(|path| std::fs::read(path).unwrap());
+        assert_eq!(blake3::hash(&a), blake3::hash(&b));
+        let reader = NeoDiskReader::open(paths[0])?;
+        assert!(reader.frame_count()? > 2);
+        assert_eq!(reader.read_header(0)?.0.zstd_level, Some(ZSTD_LEVEL));
+
+        assert!(FlushPolicy::Bytes(512).is_reproducible());
+        assert!(FlushPolicy::Messages(10).is_reproducible());
+        assert!(!FlushPolicy::Elapsed(Duration::ZERO).is_reproducible());
+
+        for path in paths {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_file() -> Result<()> {
+        let path = Path::new("/tmp/test_neodisk_atomic.bin");
+        write_atomic(path, b"old", Durability::Full)?;
+        write_atomic(path, b"new contents", Durability::Data)?;
+        assert_eq!(std::fs::read(path)?, b"new contents");
+        assert!(!platform::tmp_path(path).exists());
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_durability_round_trips() -> Result<()> {
+        let path = "/tmp/test_neodisk_durability.nd";
+        for durability in [Durability::Full, Durability::Data, Durability::None] {
+            let mut writer = NeoDiskWriter::create(path)?.with_durability(durability);
+            assert_eq!(writer.durability(), durability);
+            writer.append(b"\x01\x02")?;
+            writer.flush()?;
+            drop(writer);
+
+            let reader = NeoDiskReader::open(path)?;
+            assert_eq!(reader.len()?, 1);
+        }
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
 }
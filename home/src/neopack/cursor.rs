@@ -1,3 +1,5 @@
+use alloc::vec::Vec;
+
 use crate::neopack::types::{Result, Error};
 
 /// A position marker in a stream that can be used to seek back
@@ -134,9 +136,15 @@ impl<'a> Cursor<'a> {
 
 /// Helper for streaming use case - manages a growable buffer with compaction
 pub struct StreamBuffer {
-    pub data: Vec<u8>,
-    pub base_offset: u64,
-    pub valid_start: usize,
+    data: Vec<u8>,
+    base_offset: u64,
+    valid_start: usize,
+}
+
+impl Default for StreamBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl StreamBuffer {
@@ -160,6 +168,17 @@ impl StreamBuffer {
         self.data.extend_from_slice(bytes);
     }
 
+    /// Reads up to `max` bytes from `reader` onto the end of the buffer.
+    /// Returns the number of bytes read; zero means the stream is exhausted.
+    #[cfg(feature = "std")]
+    pub fn fill_from<R: std::io::Read>(&mut self, reader: &mut R, max: usize) -> std::io::Result<usize> {
+        let start = self.data.len();
+        self.data.resize(start + max, 0);
+        let read = reader.read(&mut self.data[start..]);
+        self.data.truncate(start + *read.as_ref().unwrap_or(&0));
+        read
+    }
+
     pub fn cursor(&self) -> Cursor<'_> {
         Cursor::with_context(
             &self.data[self.valid_start..],
@@ -183,6 +202,11 @@ impl StreamBuffer {
         freed
     }
 
+    /// Absolute position of the first unconsumed byte.
+    pub fn base_offset(&self) -> u64 {
+        self.base_offset + self.valid_start as u64
+    }
+
     pub fn len(&self) -> usize {
         self.data.len() - self.valid_start
     }
@@ -282,6 +306,18 @@ mod tests {
         assert_eq!(cursor.remaining(), 5);
     }
 
+    #[test]
+    #[cfg(feature = "std")]
+    fn stream_buffer_fill_from_reader() {
+        let mut source: &[u8] = b"0123456789";
+        let mut buffer = StreamBuffer::new();
+
+        assert_eq!(buffer.fill_from(&mut source, 4).unwrap(), 4);
+        assert_eq!(buffer.fill_from(&mut source, 100).unwrap(), 6);
+        assert_eq!(buffer.fill_from(&mut source, 4).unwrap(), 0);
+        assert_eq!(buffer.data, b"0123456789");
+    }
+
     #[test]
     fn stream_buffer_workflow() {
         let mut buffer = StreamBuffer::new();
@@ -1,7 +1,14 @@
 use crate::neopack::types::Result;
 use crate::neopack::types::Error;
 use crate::neopack::types::Tag;
+use crate::neopack::types::F16;
+use crate::neopack::types::Timestamp;
+use crate::neopack::types::Duration;
 use crate::neopack::cursor::Cursor;
+use crate::neopack::layout::LayoutCheck;
+use crate::neopack::layout::RecordLayout;
+use crate::neopack::dump;
+use crate::neopack::spec;
 use crate::neopack::macros::impl_from_bytes;
 use crate::neopack::macros::decode_array_method;
 use crate::neopack::macros::decode_val_as;
@@ -31,6 +38,9 @@ impl_from_bytes!(u16, 2); impl_from_bytes!(i16, 2);
 impl_from_bytes!(u32, 4); impl_from_bytes!(i32, 4);
 impl_from_bytes!(u64, 8); impl_from_bytes!(i64, 8);
 impl_from_bytes!(f32, 4); impl_from_bytes!(f64, 8);
+impl_from_bytes!(u128, 16); impl_from_bytes!(i128, 16);
+impl_from_bytes!(F16, 2);
+impl_from_bytes!(Timestamp, 8); impl_from_bytes!(Duration, 8);
 
 #[derive(Debug, Clone)]
 pub struct Decoder<'a> {
@@ -83,16 +93,38 @@ impl<'a> Decoder<'a> {
 
     pub fn str(&mut self) -> Result<&'a str> {
         self.expect_blob(Tag::String, |bytes| {
-            std::str::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)
+            core::str::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)
         })
     }
 
     pub fn bytes(&mut self) -> Result<&'a [u8]> {
-        self.expect_blob(Tag::Bytes, |b| Ok(b))
+        self.expect_blob(Tag::Bytes, Ok)
     }
 
     pub fn record_raw(&mut self) -> Result<&'a [u8]> {
-        self.expect_blob(Tag::Struct, |b| Ok(b))
+        self.expect_blob(Tag::Struct, Ok)
+    }
+
+    pub fn fixed16(&mut self) -> Result<&'a [u8; 16]> {
+        self.expect_fixed(Tag::Fixed16)
+    }
+
+    pub fn fixed32(&mut self) -> Result<&'a [u8; 32]> {
+        self.expect_fixed(Tag::Fixed32)
+    }
+
+    fn expect_fixed<const N: usize>(&mut self, expected: Tag) -> Result<&'a [u8; N]> {
+        let tag = self.read_tag()?;
+        if tag != expected {
+            return Err(Error::TypeMismatch);
+        }
+        let bytes = self.cursor.read_bytes(N)?;
+        Ok(bytes.try_into().expect("read exactly N bytes"))
+    }
+
+    /// Reads an extension value as its id and payload.
+    pub fn ext(&mut self) -> Result<(u16, &'a [u8])> {
+        self.expect_blob(Tag::Ext, split_ext)
     }
 
     fn expect_blob<F, T>(&mut self, expected: Tag, f: F) -> Result<T>
@@ -116,12 +148,15 @@ impl<'a> Decoder<'a> {
         let tag = self.read_tag()?;
         match tag {
             Tag::Bool | Tag::U8 | Tag::S8 => self.cursor.skip(1),
-            Tag::U16 | Tag::S16 => self.cursor.skip(2),
+            Tag::U16 | Tag::S16 | Tag::F16 => self.cursor.skip(2),
             Tag::U32 | Tag::S32 | Tag::F32 => self.cursor.skip(4),
-            Tag::U64 | Tag::S64 | Tag::F64 => self.cursor.skip(8),
+            Tag::U64 | Tag::S64 | Tag::F64 |
+            Tag::Timestamp | Tag::Duration => self.cursor.skip(8),
+            Tag::U128 | Tag::S128 | Tag::Fixed16 => self.cursor.skip(16),
+            Tag::Fixed32 => self.cursor.skip(32),
 
             Tag::String | Tag::Bytes | Tag::Struct |
-            Tag::List | Tag::Map | Tag::Array => {
+            Tag::List | Tag::Map | Tag::Array | Tag::Ext => {
                 let len: u32 = self.read_primitive()?;
                 self.cursor.skip(len as usize)
             }
@@ -179,10 +214,10 @@ impl<'a> Decoder<'a> {
         let stride_bytes = inner.read_bytes(4)?;
         let stride = u32::from_le_bytes([stride_bytes[0], stride_bytes[1], stride_bytes[2], stride_bytes[3]]) as usize;
 
-        let header_size = 5;
+        let header_size = spec::ARRAY_ITEM_HEADER;
         let payload_len = bytes.len().saturating_sub(header_size);
 
-        if stride == 0 || payload_len % stride != 0 { return Err(Error::Malformed); }
+        if stride == 0 || !payload_len.is_multiple_of(stride) { return Err(Error::Malformed); }
         let count = payload_len / stride;
 
         Ok(ArrayDecoder {
@@ -197,6 +232,12 @@ impl<'a> Decoder<'a> {
         let bytes = self.record_raw()?;
         Ok(RecordDecoder::new(bytes))
     }
+
+    /// Reads a Record whose fields are checked against `layout`.
+    pub fn record_with(&mut self, layout: &RecordLayout<'a>) -> Result<RecordDecoder<'a>> {
+        let bytes = self.record_raw()?;
+        RecordDecoder::with_layout(bytes, layout)
+    }
 }
 
 #[derive(Debug)]
@@ -206,6 +247,7 @@ pub struct ListDecoder<'a> {
 }
 
 impl<'a> ListDecoder<'a> {
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Result<Option<ValueDecoder<'a>>> {
         if self.cursor.pos() >= self.end_pos {
             return Ok(None);
@@ -217,6 +259,19 @@ impl<'a> ListDecoder<'a> {
     }
 }
 
+/// Yields each item, then stops after the end or the first error.
+impl<'a> Iterator for ListDecoder<'a> {
+    type Item = Result<ValueDecoder<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = ListDecoder::next(self).transpose();
+        if let Some(Err(_)) = item {
+            self.end_pos = 0;
+        }
+        item
+    }
+}
+
 #[derive(Debug)]
 pub struct MapDecoder<'a> {
     cursor: Cursor<'a>,
@@ -224,6 +279,7 @@ pub struct MapDecoder<'a> {
 }
 
 impl<'a> MapDecoder<'a> {
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Result<Option<(&'a str, ValueDecoder<'a>)>> {
         if self.cursor.pos() >= self.end_pos {
             return Ok(None);
@@ -235,7 +291,7 @@ impl<'a> MapDecoder<'a> {
         if tag != Tag::String { return Err(Error::TypeMismatch); }
         let k_len: u32 = decoder.read_primitive()?;
         let k_bytes = decoder.cursor.read_bytes(k_len as usize)?;
-        let key = std::str::from_utf8(k_bytes).map_err(|_| Error::InvalidUtf8)?;
+        let key = core::str::from_utf8(k_bytes).map_err(|_| Error::InvalidUtf8)?;
 
         let val = ValueDecoder::read(&mut decoder)?;
         self.cursor = decoder.cursor;
@@ -243,7 +299,20 @@ impl<'a> MapDecoder<'a> {
     }
 }
 
-#[derive(Debug)]
+/// Yields each entry, then stops after the end or the first error.
+impl<'a> Iterator for MapDecoder<'a> {
+    type Item = Result<(&'a str, ValueDecoder<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = MapDecoder::next(self).transpose();
+        if let Some(Err(_)) = entry {
+            self.end_pos = 0;
+        }
+        entry
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ArrayDecoder<'a> {
     cursor: Cursor<'a>,
     item_tag: Tag,
@@ -256,6 +325,7 @@ impl<'a> ArrayDecoder<'a> {
     pub fn stride(&self) -> usize { self.stride }
     pub fn remaining(&self) -> usize { self.remaining }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Result<Option<ValueDecoder<'a>>> {
         if self.remaining == 0 { return Ok(None); }
         self.remaining -= 1;
@@ -278,6 +348,23 @@ impl<'a> ArrayDecoder<'a> {
     for_each_scalar!(decode_array_method, ());
 }
 
+/// Yields each item, then stops after the end or the first error.
+impl<'a> Iterator for ArrayDecoder<'a> {
+    type Item = Result<ValueDecoder<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = ArrayDecoder::next(self).transpose();
+        if let Some(Err(_)) = item {
+            self.remaining = 0;
+        }
+        item
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
 #[derive(Debug)]
 pub enum ValueDecoder<'a> {
     Bool(bool),
@@ -291,17 +378,30 @@ pub enum ValueDecoder<'a> {
     S64(i64),
     F32(f32),
     F64(f64),
+    F16(F16),
+    U128(u128),
+    S128(i128),
+    Timestamp(Timestamp),
+    Duration(Duration),
     Bytes(&'a [u8]),
     Struct(&'a [u8]),
+    Fixed16(&'a [u8; 16]),
+    Fixed32(&'a [u8; 32]),
     Str(&'a str),
     List(ListDecoder<'a>),
     Map(MapDecoder<'a>),
     Array(ArrayDecoder<'a>),
+    Ext(u16, &'a [u8]),
 }
 
 impl<'a> ValueDecoder<'a> {
     pub fn from_untagged_bytes(tag: Tag, bytes: &'a [u8]) -> Result<Self> {
         use ValueDecoder::*;
+        // Array items are cut to the array's stride, which may not be the
+        // item width
+        if tag.scalar_size().is_some_and(|size| size != bytes.len()) {
+            return Err(Error::Malformed);
+        }
         match tag {
             Tag::Bool => Ok(Bool(FromBytes::read_from(bytes))),
             Tag::U8   => Ok(U8(FromBytes::read_from(bytes))),
@@ -314,12 +414,25 @@ impl<'a> ValueDecoder<'a> {
             Tag::S64  => Ok(S64(FromBytes::read_from(bytes))),
             Tag::F32  => Ok(F32(FromBytes::read_from(bytes))),
             Tag::F64  => Ok(F64(FromBytes::read_from(bytes))),
+            Tag::F16  => Ok(F16(FromBytes::read_from(bytes))),
+            Tag::U128 => Ok(U128(FromBytes::read_from(bytes))),
+            Tag::S128 => Ok(S128(FromBytes::read_from(bytes))),
+            Tag::Timestamp => Ok(Timestamp(FromBytes::read_from(bytes))),
+            Tag::Duration  => Ok(Duration(FromBytes::read_from(bytes))),
 
             Tag::Bytes => Ok(Bytes(bytes)),
             Tag::Struct => Ok(Struct(bytes)),
 
+            Tag::Fixed16 => Ok(Fixed16(bytes.try_into().map_err(|_| Error::Malformed)?)),
+            Tag::Fixed32 => Ok(Fixed32(bytes.try_into().map_err(|_| Error::Malformed)?)),
+
+            Tag::Ext => {
+                let (id, payload) = split_ext(bytes)?;
+                Ok(Ext(id, payload))
+            }
+
             Tag::String => {
-                let s = std::str::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)?;
+                let s = core::str::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)?;
                 Ok(ValueDecoder::Str(s))
             }
 
@@ -344,10 +457,10 @@ impl<'a> ValueDecoder<'a> {
                 let stride_bytes = inner.read_bytes(4)?;
                 let stride = u32::from_le_bytes([stride_bytes[0], stride_bytes[1], stride_bytes[2], stride_bytes[3]]) as usize;
 
-                let header_size = 5;
+                let header_size = spec::ARRAY_ITEM_HEADER;
                 let payload_len = bytes.len().saturating_sub(header_size);
 
-                if stride == 0 || payload_len % stride != 0 { return Err(Error::Malformed); }
+                if stride == 0 || !payload_len.is_multiple_of(stride) { return Err(Error::Malformed); }
                 let count = payload_len / stride;
 
                 Ok(Array(ArrayDecoder {
@@ -365,12 +478,15 @@ impl<'a> ValueDecoder<'a> {
 
         let len = match tag {
             Tag::Bool | Tag::U8 | Tag::S8 => 1,
-            Tag::U16 | Tag::S16 => 2,
+            Tag::U16 | Tag::S16 | Tag::F16 => 2,
             Tag::U32 | Tag::S32 | Tag::F32 => 4,
-            Tag::U64 | Tag::S64 | Tag::F64 => 8,
+            Tag::U64 | Tag::S64 | Tag::F64 |
+            Tag::Timestamp | Tag::Duration => 8,
+            Tag::U128 | Tag::S128 | Tag::Fixed16 => 16,
+            Tag::Fixed32 => 32,
 
             Tag::String | Tag::Bytes | Tag::Struct |
-            Tag::List | Tag::Map | Tag::Array => {
+            Tag::List | Tag::Map | Tag::Array | Tag::Ext => {
                 decoder.read_primitive::<u32>()? as usize
             }
         };
@@ -388,11 +504,53 @@ impl<'a> ValueDecoder<'a> {
     pub fn as_bytes(&self) -> Result<&'a [u8]> {
         match self { ValueDecoder::Bytes(v) => Ok(*v), _ => Err(Error::TypeMismatch) }
     }
+
+    pub fn as_fixed16(&self) -> Result<&'a [u8; 16]> {
+        match self { ValueDecoder::Fixed16(v) => Ok(*v), _ => Err(Error::TypeMismatch) }
+    }
+
+    pub fn as_fixed32(&self) -> Result<&'a [u8; 32]> {
+        match self { ValueDecoder::Fixed32(v) => Ok(*v), _ => Err(Error::TypeMismatch) }
+    }
+
+    pub fn as_ext(&self) -> Result<(u16, &'a [u8])> {
+        match self { ValueDecoder::Ext(id, v) => Ok((*id, *v)), _ => Err(Error::TypeMismatch) }
+    }
+}
+
+/// Splits an extension body into its id and payload.
+fn split_ext(bytes: &[u8]) -> Result<(u16, &[u8])> {
+    if bytes.len() < spec::EXT_ID_LEN { return Err(Error::Malformed); }
+    let (id, payload) = bytes.split_at(spec::EXT_ID_LEN);
+    Ok((u16::from_le_bytes([id[0], id[1]]), payload))
+}
+
+/// Formats the value as a tree, as `dump` does. Offsets are from the start
+/// of the container's body; only what the decoder hasn't read yet is shown.
+impl core::fmt::Display for ValueDecoder<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ValueDecoder::List(list) => {
+                writeln!(f, "List")?;
+                dump::write_values(f, list.cursor.as_slice(), list.cursor.pos(), 1)
+            }
+            ValueDecoder::Map(map) => {
+                writeln!(f, "Map")?;
+                dump::write_entries(f, map.cursor.as_slice(), map.cursor.pos(), 1)
+            }
+            ValueDecoder::Array(array) => {
+                writeln!(f, "Array item={:?} stride={} count={}", array.item_tag, array.stride, array.remaining)?;
+                dump::write_items(f, &mut array.clone(), array.cursor.pos(), 1)
+            }
+            scalar => dump::write_scalar(f, scalar),
+        }
+    }
 }
 
 pub struct RecordDecoder<'a> {
     cursor: Cursor<'a>,
     end: usize,
+    layout: Option<LayoutCheck<'a>>,
 }
 
 impl<'a> RecordDecoder<'a> {
@@ -400,6 +558,7 @@ impl<'a> RecordDecoder<'a> {
         Self {
             end: data.len(),
             cursor: Cursor::new(data),
+            layout: None,
         }
     }
 
@@ -407,9 +566,21 @@ impl<'a> RecordDecoder<'a> {
         Self {
             end: data.len(),
             cursor: Cursor::new(data),
+            layout: None,
         }
     }
 
+    /// Reads `data` as a record of `layout`; each read is checked against
+    /// the next field. Fails if `data` isn't one stride long.
+    pub fn with_layout(data: &'a [u8], layout: &RecordLayout<'a>) -> Result<Self> {
+        layout.check_size(data.len())?;
+        Ok(Self {
+            end: data.len(),
+            cursor: Cursor::new(data),
+            layout: Some(LayoutCheck::new(layout)),
+        })
+    }
+
     pub fn remaining(&self) -> usize {
         self.cursor.remaining()
     }
@@ -423,9 +594,19 @@ impl<'a> RecordDecoder<'a> {
         Ok(T::read_from(bytes))
     }
 
+    fn field(&mut self, tag: Tag) -> Result<()> {
+        match &mut self.layout {
+            Some(layout) => layout.field(tag),
+            None => Ok(()),
+        }
+    }
+
     for_each_scalar!(decode_record_prim, ());
 
+    /// Reads raw bytes. Fails on a record with a layout, since raw bytes
+    /// can't be checked against it.
     pub fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        self.field(Tag::Bytes)?;
         self.cursor.read_bytes(len)
     }
 }
@@ -0,0 +1,211 @@
+//! Structural diffs between neopack documents
+//!
+//! `diff` walks two documents side by side and reports each path where
+//! they differ. Map entries are matched by key and list items by index;
+//! an entry is reported where a value was added, removed, or changed, and
+//! the walk doesn't descend below it. Values that differ in kind, and
+//! arrays that differ in item tag or stride, are reported as changed
+//! whole.
+//!
+//! A document may hold several top-level values, so every path starts
+//! with the index of the top-level value it is in.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::fmt;
+use crate::neopack::decoder::Decoder;
+use crate::neopack::decoder::ValueDecoder;
+use crate::neopack::dump;
+use crate::neopack::spec::ARRAY_HEADER;
+use crate::neopack::spec::BLOB_HEADER;
+use crate::neopack::types::Result;
+use crate::neopack::types::Tag;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSegment<'a> {
+    /// A list or array item, or a top-level value.
+    Index(usize),
+    /// A map entry.
+    Key(&'a str),
+}
+
+/// A path where two documents differ, with the value on each side.
+#[derive(Debug)]
+pub struct DiffEntry<'a> {
+    pub path: Vec<PathSegment<'a>>,
+    /// The value in the old document; `None` if it was added.
+    pub old: Option<ValueDecoder<'a>>,
+    /// The value in the new document; `None` if it was removed.
+    pub new: Option<ValueDecoder<'a>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl DiffEntry<'_> {
+    pub fn kind(&self) -> DiffKind {
+        match (&self.old, &self.new) {
+            (None, _) => DiffKind::Added,
+            (_, None) => DiffKind::Removed,
+            _ => DiffKind::Changed,
+        }
+    }
+}
+
+/// One line: `+`, `-`, or `~`, the path, then the values.
+impl fmt::Display for DiffEntry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = match self.kind() {
+            DiffKind::Added => '+',
+            DiffKind::Removed => '-',
+            DiffKind::Changed => '~',
+        };
+        write!(f, "{} ", sign)?;
+        for segment in &self.path {
+            match segment {
+                PathSegment::Index(i) => write!(f, "/{}", i)?,
+                PathSegment::Key(key) => write!(f, "/{}", key)?,
+            }
+        }
+        f.write_str(": ")?;
+        if let Some(old) = &self.old {
+            dump::write_scalar(f, old)?;
+        }
+        if self.old.is_some() && self.new.is_some() {
+            f.write_str(" -> ")?;
+        }
+        if let Some(new) = &self.new {
+            dump::write_scalar(f, new)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every path where `new` differs from `old`, in document order with
+/// additions last at each level. Fails if either document is malformed.
+pub fn diff<'a>(old: &'a [u8], new: &'a [u8]) -> Result<Vec<DiffEntry<'a>>> {
+    let mut out = Vec::new();
+    diff_sequences(&mut Vec::new(), values(old)?, values(new)?, &mut out)?;
+    Ok(out)
+}
+
+/// Raw bytes of each value in `bytes`.
+fn values(bytes: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut dec = Decoder::new(bytes);
+    let mut values = Vec::new();
+    while dec.remaining() > 0 {
+        values.push(dec.raw_value()?);
+    }
+    Ok(values)
+}
+
+fn diff_sequences<'a>(
+    path: &mut Vec<PathSegment<'a>>,
+    old: Vec<&'a [u8]>,
+    new: Vec<&'a [u8]>,
+    out: &mut Vec<DiffEntry<'a>>,
+) -> Result<()> {
+    for i in 0..old.len().max(new.len()) {
+        path.push(PathSegment::Index(i));
+        diff_value(path, old.get(i).copied(), new.get(i).copied(), out)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+fn diff_value<'a>(
+    path: &mut Vec<PathSegment<'a>>,
+    old: Option<&'a [u8]>,
+    new: Option<&'a [u8]>,
+    out: &mut Vec<DiffEntry<'a>>,
+) -> Result<()> {
+    let (old, new) = match (old, new) {
+        (Some(old), Some(new)) => (old, new),
+        (old, new) => return report(path, old, new, out),
+    };
+    if old == new {
+        return Ok(());
+    }
+
+    match (Tag::from_u8(old[0]), Tag::from_u8(new[0])) {
+        (Some(Tag::List), Some(Tag::List)) => {
+            diff_sequences(path, values(&old[BLOB_HEADER..])?, values(&new[BLOB_HEADER..])?, out)
+        }
+        (Some(Tag::Map), Some(Tag::Map)) => {
+            let old_entries = entries(&old[BLOB_HEADER..])?;
+            let new_entries = entries(&new[BLOB_HEADER..])?;
+            let new_by_key: BTreeMap<_, _> = new_entries.iter().rev().copied().collect();
+            let old_by_key: BTreeMap<_, _> = old_entries.iter().rev().copied().collect();
+
+            for (key, old) in old_entries {
+                path.push(PathSegment::Key(key));
+                diff_value(path, Some(old), new_by_key.get(key).copied(), out)?;
+                path.pop();
+            }
+            for (key, new) in new_entries {
+                if !old_by_key.contains_key(key) {
+                    path.push(PathSegment::Key(key));
+                    report(path, None, Some(new), out)?;
+                    path.pop();
+                }
+            }
+            Ok(())
+        }
+        (Some(Tag::Array), Some(Tag::Array)) => {
+            let old_array = Decoder::new(old).array()?;
+            let new_array = Decoder::new(new).array()?;
+            let tag = old_array.item_tag();
+            let stride = old_array.stride();
+            if tag != new_array.item_tag() || stride != new_array.stride() {
+                return report(path, Some(old), Some(new), out);
+            }
+
+            let old_items: Vec<_> = old[ARRAY_HEADER..].chunks(stride).collect();
+            let new_items: Vec<_> = new[ARRAY_HEADER..].chunks(stride).collect();
+            for i in 0..old_items.len().max(new_items.len()) {
+                let (old, new) = (old_items.get(i), new_items.get(i));
+                if old == new {
+                    continue;
+                }
+                path.push(PathSegment::Index(i));
+                out.push(DiffEntry {
+                    path: path.clone(),
+                    old: old.map(|item| ValueDecoder::from_untagged_bytes(tag, item)).transpose()?,
+                    new: new.map(|item| ValueDecoder::from_untagged_bytes(tag, item)).transpose()?,
+                });
+                path.pop();
+            }
+            Ok(())
+        }
+        _ => report(path, Some(old), Some(new), out),
+    }
+}
+
+/// Each key and raw value of a map body.
+fn entries(bytes: &[u8]) -> Result<Vec<(&str, &[u8])>> {
+    let mut dec = Decoder::new(bytes);
+    let mut entries = Vec::new();
+    while dec.remaining() > 0 {
+        let key = dec.str()?;
+        entries.push((key, dec.raw_value()?));
+    }
+    Ok(entries)
+}
+
+fn report<'a>(
+    path: &[PathSegment<'a>],
+    old: Option<&'a [u8]>,
+    new: Option<&'a [u8]>,
+    out: &mut Vec<DiffEntry<'a>>,
+) -> Result<()> {
+    out.push(DiffEntry {
+        path: path.to_vec(),
+        old: old.map(|raw| Decoder::new(raw).value()).transpose()?,
+        new: new.map(|raw| Decoder::new(raw).value()).transpose()?,
+    });
+    Ok(())
+}
@@ -0,0 +1,306 @@
+//! Owned neopack documents
+//!
+//! `Doc` holds a decoded value and everything under it, so it can outlive
+//! the buffer it came from, be compared, or be built up and encoded in
+//! one go. Map entries keep their order and any repeated keys, and arrays
+//! keep their item tag and stride, so decoding and re-encoding a document
+//! the encoder wrote gives back the same bytes.
+//!
+//! With the `arbitrary` feature, `Doc` implements `arbitrary::Arbitrary`
+//! with nesting and sizes bounded by the constants below. The property
+//! tests and the `neopack_roundtrip` fuzz target both generate documents
+//! through it.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::neopack::decoder::Decoder;
+use crate::neopack::decoder::ValueDecoder;
+use crate::neopack::encoder::Encoder;
+use crate::neopack::spec;
+use crate::neopack::types::Duration;
+use crate::neopack::types::Error;
+use crate::neopack::types::F16;
+use crate::neopack::types::Result;
+use crate::neopack::types::Tag;
+use crate::neopack::types::Timestamp;
+use crate::neopack::writer::ValueWriter;
+
+/// Deepest nesting of containers a generated document has.
+pub const MAX_DEPTH: usize = 4;
+/// Most items in a generated list, map, or array.
+pub const MAX_ITEMS: usize = 8;
+/// Longest generated string or blob, in characters or bytes.
+pub const MAX_BLOB: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Doc {
+    Bool(bool),
+    U8(u8),
+    S8(i8),
+    U16(u16),
+    S16(i16),
+    U32(u32),
+    S32(i32),
+    U64(u64),
+    S64(i64),
+    F32(f32),
+    F64(f64),
+    F16(F16),
+    U128(u128),
+    S128(i128),
+    Timestamp(Timestamp),
+    Duration(Duration),
+    Str(String),
+    Bytes(Vec<u8>),
+    Struct(Vec<u8>),
+    Fixed16([u8; 16]),
+    Fixed32([u8; 32]),
+    Ext(u16, Vec<u8>),
+    List(Vec<Doc>),
+    Map(Vec<(String, Doc)>),
+    /// Items are written untagged, each exactly `stride` bytes.
+    Array { item_tag: Tag, stride: usize, items: Vec<Doc> },
+}
+
+impl Doc {
+    pub fn tag(&self) -> Tag {
+        match self {
+            Doc::Bool(_) => Tag::Bool,
+            Doc::U8(_) => Tag::U8,
+            Doc::S8(_) => Tag::S8,
+            Doc::U16(_) => Tag::U16,
+            Doc::S16(_) => Tag::S16,
+            Doc::U32(_) => Tag::U32,
+            Doc::S32(_) => Tag::S32,
+            Doc::U64(_) => Tag::U64,
+            Doc::S64(_) => Tag::S64,
+            Doc::F32(_) => Tag::F32,
+            Doc::F64(_) => Tag::F64,
+            Doc::F16(_) => Tag::F16,
+            Doc::U128(_) => Tag::U128,
+            Doc::S128(_) => Tag::S128,
+            Doc::Timestamp(_) => Tag::Timestamp,
+            Doc::Duration(_) => Tag::Duration,
+            Doc::Str(_) => Tag::String,
+            Doc::Bytes(_) => Tag::Bytes,
+            Doc::Struct(_) => Tag::Struct,
+            Doc::Fixed16(_) => Tag::Fixed16,
+            Doc::Fixed32(_) => Tag::Fixed32,
+            Doc::Ext(..) => Tag::Ext,
+            Doc::List(_) => Tag::List,
+            Doc::Map(_) => Tag::Map,
+            Doc::Array { .. } => Tag::Array,
+        }
+    }
+
+    /// Writes the document as one value.
+    pub fn write<W: ValueWriter>(&self, w: &mut W) -> Result<()> {
+        match self {
+            Doc::Bool(v) => { w.bool(*v)?; }
+            Doc::U8(v) => { w.u8(*v)?; }
+            Doc::S8(v) => { w.i8(*v)?; }
+            Doc::U16(v) => { w.u16(*v)?; }
+            Doc::S16(v) => { w.i16(*v)?; }
+            Doc::U32(v) => { w.u32(*v)?; }
+            Doc::S32(v) => { w.i32(*v)?; }
+            Doc::U64(v) => { w.u64(*v)?; }
+            Doc::S64(v) => { w.i64(*v)?; }
+            Doc::F32(v) => { w.f32(*v)?; }
+            Doc::F64(v) => { w.f64(*v)?; }
+            Doc::F16(v) => { w.f16(*v)?; }
+            Doc::U128(v) => { w.u128(*v)?; }
+            Doc::S128(v) => { w.i128(*v)?; }
+            Doc::Timestamp(v) => { w.timestamp(*v)?; }
+            Doc::Duration(v) => { w.duration(*v)?; }
+            Doc::Str(v) => { w.str(v)?; }
+            Doc::Bytes(v) => { w.bytes(v)?; }
+            Doc::Struct(v) => { w.record_raw(v)?; }
+            Doc::Fixed16(v) => { w.fixed16(v)?; }
+            Doc::Fixed32(v) => { w.fixed32(v)?; }
+            Doc::Ext(id, payload) => { w.ext(*id, payload)?; }
+            Doc::List(items) => {
+                let mut list = w.list()?;
+                for item in items {
+                    item.write(&mut list)?;
+                }
+                list.finish()?;
+            }
+            Doc::Map(entries) => {
+                let mut map = w.map()?;
+                for (key, value) in entries {
+                    value.write(&mut map.key(key)?)?;
+                }
+                map.finish()?;
+            }
+            Doc::Array { item_tag, stride, items } => {
+                if *stride == 0 {
+                    return Err(Error::Malformed);
+                }
+                let mut array = w.array(*item_tag, *stride)?;
+                for item in items {
+                    if item.tag() != *item_tag {
+                        return Err(Error::TypeMismatch);
+                    }
+                    array.push(&item.body()?)?;
+                }
+                array.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut enc = Encoder::new();
+        self.write(&mut enc)?;
+        Ok(enc.into_bytes())
+    }
+
+    /// The encoding without its tag and length: what an array item holds.
+    pub fn body(&self) -> Result<Vec<u8>> {
+        let mut bytes = self.encode()?;
+        let tag = self.tag();
+        let header = match tag {
+            Tag::Fixed16 | Tag::Fixed32 => 1,
+            _ if tag.scalar_size().is_some() => 1,
+            _ => spec::BLOB_HEADER,
+        };
+        bytes.drain(..header);
+        Ok(bytes)
+    }
+
+    /// Reads a value and everything under it.
+    pub fn read(value: ValueDecoder<'_>) -> Result<Doc> {
+        Ok(match value {
+            ValueDecoder::Bool(v) => Doc::Bool(v),
+            ValueDecoder::U8(v) => Doc::U8(v),
+            ValueDecoder::S8(v) => Doc::S8(v),
+            ValueDecoder::U16(v) => Doc::U16(v),
+            ValueDecoder::S16(v) => Doc::S16(v),
+            ValueDecoder::U32(v) => Doc::U32(v),
+            ValueDecoder::S32(v) => Doc::S32(v),
+            ValueDecoder::U64(v) => Doc::U64(v),
+            ValueDecoder::S64(v) => Doc::S64(v),
+            ValueDecoder::F32(v) => Doc::F32(v),
+            ValueDecoder::F64(v) => Doc::F64(v),
+            ValueDecoder::F16(v) => Doc::F16(v),
+            ValueDecoder::U128(v) => Doc::U128(v),
+            ValueDecoder::S128(v) => Doc::S128(v),
+            ValueDecoder::Timestamp(v) => Doc::Timestamp(v),
+            ValueDecoder::Duration(v) => Doc::Duration(v),
+            ValueDecoder::Str(v) => Doc::Str(v.into()),
+            ValueDecoder::Bytes(v) => Doc::Bytes(v.to_vec()),
+            ValueDecoder::Struct(v) => Doc::Struct(v.to_vec()),
+            ValueDecoder::Fixed16(v) => Doc::Fixed16(*v),
+            ValueDecoder::Fixed32(v) => Doc::Fixed32(*v),
+            ValueDecoder::Ext(id, payload) => Doc::Ext(id, payload.to_vec()),
+            ValueDecoder::List(list) => {
+                Doc::List(list.map(|item| Doc::read(item?)).collect::<Result<_>>()?)
+            }
+            ValueDecoder::Map(map) => {
+                Doc::Map(map.map(|entry| {
+                    let (key, value) = entry?;
+                    Ok((key.into(), Doc::read(value)?))
+                }).collect::<Result<_>>()?)
+            }
+            ValueDecoder::Array(array) => {
+                let item_tag = array.item_tag();
+                let stride = array.stride();
+                let items = array.map(|item| Doc::read(item?)).collect::<Result<_>>()?;
+                Doc::Array { item_tag, stride, items }
+            }
+        })
+    }
+
+    /// Decodes `bytes`, which must hold exactly one value.
+    pub fn decode(bytes: &[u8]) -> Result<Doc> {
+        let mut decoder = Decoder::new(bytes);
+        let doc = Doc::read(decoder.value()?)?;
+        if decoder.remaining() > 0 {
+            return Err(Error::Malformed);
+        }
+        Ok(doc)
+    }
+}
+
+/// Scalar tags, in the order the generator numbers them.
+#[cfg(any(test, feature = "arbitrary"))]
+const SCALAR_TAGS: [Tag; 16] = [
+    Tag::Bool, Tag::U8, Tag::S8, Tag::U16, Tag::S16, Tag::U32, Tag::S32, Tag::U64,
+    Tag::S64, Tag::F32, Tag::F64, Tag::F16, Tag::U128, Tag::S128, Tag::Timestamp, Tag::Duration,
+];
+
+#[cfg(any(test, feature = "arbitrary"))]
+impl Doc {
+    /// Generates a document nested at most `depth` containers deep.
+    pub fn arbitrary_bounded(u: &mut arbitrary::Unstructured<'_>, depth: usize) -> arbitrary::Result<Doc> {
+        let kinds = if depth == 0 { SCALAR_TAGS.len() + 6 } else { SCALAR_TAGS.len() + 9 };
+        let kind = u.choose_index(kinds)?;
+        if let Some(&tag) = SCALAR_TAGS.get(kind) {
+            return Doc::arbitrary_scalar(u, tag);
+        }
+
+        let count = u.int_in_range(0..=MAX_ITEMS)?;
+        Ok(match kind - SCALAR_TAGS.len() {
+            0 => Doc::Str(arbitrary_str(u)?),
+            1 => Doc::Bytes(arbitrary_blob(u)?),
+            2 => Doc::Struct(arbitrary_blob(u)?),
+            3 => Doc::Fixed16(u.arbitrary()?),
+            4 => Doc::Fixed32(u.arbitrary()?),
+            5 => Doc::Ext(u.arbitrary()?, arbitrary_blob(u)?),
+            6 => Doc::List((0..count)
+                .map(|_| Doc::arbitrary_bounded(u, depth - 1))
+                .collect::<arbitrary::Result<_>>()?),
+            7 => Doc::Map((0..count)
+                .map(|_| Ok((arbitrary_str(u)?, Doc::arbitrary_bounded(u, depth - 1)?)))
+                .collect::<arbitrary::Result<_>>()?),
+            _ => {
+                let item_tag = *u.choose(&SCALAR_TAGS)?;
+                let stride = item_tag.scalar_size().expect("scalar tags have a size");
+                let items = (0..count)
+                    .map(|_| Doc::arbitrary_scalar(u, item_tag))
+                    .collect::<arbitrary::Result<_>>()?;
+                Doc::Array { item_tag, stride, items }
+            }
+        })
+    }
+
+    fn arbitrary_scalar(u: &mut arbitrary::Unstructured<'_>, tag: Tag) -> arbitrary::Result<Doc> {
+        Ok(match tag {
+            Tag::Bool => Doc::Bool(u.arbitrary()?),
+            Tag::U8 => Doc::U8(u.arbitrary()?),
+            Tag::S8 => Doc::S8(u.arbitrary()?),
+            Tag::U16 => Doc::U16(u.arbitrary()?),
+            Tag::S16 => Doc::S16(u.arbitrary()?),
+            Tag::U32 => Doc::U32(u.arbitrary()?),
+            Tag::S32 => Doc::S32(u.arbitrary()?),
+            Tag::U64 => Doc::U64(u.arbitrary()?),
+            Tag::S64 => Doc::S64(u.arbitrary()?),
+            Tag::F32 => Doc::F32(u.arbitrary()?),
+            Tag::F64 => Doc::F64(u.arbitrary()?),
+            Tag::F16 => Doc::F16(F16(u.arbitrary()?)),
+            Tag::U128 => Doc::U128(u.arbitrary()?),
+            Tag::S128 => Doc::S128(u.arbitrary()?),
+            Tag::Timestamp => Doc::Timestamp(Timestamp(u.arbitrary()?)),
+            _ => Doc::Duration(Duration(u.arbitrary()?)),
+        })
+    }
+}
+
+#[cfg(any(test, feature = "arbitrary"))]
+fn arbitrary_blob(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<Vec<u8>> {
+    let len = u.int_in_range(0..=MAX_BLOB)?;
+    Ok(u.bytes(len.min(u.len()))?.to_vec())
+}
+
+#[cfg(any(test, feature = "arbitrary"))]
+fn arbitrary_str(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<String> {
+    let len = u.int_in_range(0..=MAX_BLOB)?;
+    (0..len).map(|_| u.arbitrary::<char>()).collect()
+}
+
+#[cfg(any(test, feature = "arbitrary"))]
+impl<'a> arbitrary::Arbitrary<'a> for Doc {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Doc::arbitrary_bounded(u, MAX_DEPTH)
+    }
+}
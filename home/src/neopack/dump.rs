@@ -0,0 +1,194 @@
+//! Tree dumps of neopack data for debugging
+//!
+//! Each line is one value: its byte offset in hex, then its tag and its
+//! length or contents. Container children are indented beneath it. Long
+//! strings, blobs, and arrays are cut short. Malformed data ends the dump
+//! with an error line rather than failing it, so the readable prefix of a
+//! damaged message is still shown.
+
+use alloc::string::String;
+use core::fmt;
+use core::fmt::Write;
+use crate::neopack::decoder::ArrayDecoder;
+use crate::neopack::decoder::Decoder;
+use crate::neopack::decoder::ValueDecoder;
+use crate::neopack::spec::ARRAY_HEADER;
+use crate::neopack::spec::BLOB_HEADER;
+use crate::neopack::types::Error;
+use crate::neopack::types::Tag;
+
+/// Bytes of a blob shown before it is cut short.
+const PREVIEW_BYTES: usize = 16;
+/// Characters of a string shown before it is cut short.
+const PREVIEW_CHARS: usize = 48;
+/// Array items shown before the rest are counted instead.
+const PREVIEW_ITEMS: usize = 16;
+
+/// Dumps every top-level value in `bytes` as an indented tree.
+pub fn dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    write_values(&mut out, bytes, 0, 0).expect("writing to a String");
+    out
+}
+
+/// Writes each value in `bytes`, the first at offset `base`.
+pub(crate) fn write_values<W: Write>(w: &mut W, bytes: &[u8], base: usize, depth: usize) -> fmt::Result {
+    let mut dec = Decoder::new(bytes);
+    while dec.remaining() > 0 {
+        let start = dec.pos();
+        match dec.raw_value() {
+            Ok(raw) => write_value(w, raw, base + start, depth, None)?,
+            Err(e) => return write_error(w, base + start, depth, e),
+        }
+    }
+    Ok(())
+}
+
+/// Writes each key and value of a map body.
+pub(crate) fn write_entries<W: Write>(w: &mut W, bytes: &[u8], base: usize, depth: usize) -> fmt::Result {
+    let mut dec = Decoder::new(bytes);
+    while dec.remaining() > 0 {
+        // Lines show where the value starts, after its key
+        let entry = dec.str().and_then(|key| Ok((key, dec.pos(), dec.raw_value()?)));
+        match entry {
+            Ok((key, start, raw)) => write_value(w, raw, base + start, depth, Some(key))?,
+            Err(e) => return write_error(w, base + dec.pos(), depth, e),
+        }
+    }
+    Ok(())
+}
+
+/// Writes the remaining items of an array, the next at offset `base`.
+pub(crate) fn write_items<W: Write>(w: &mut W, array: &mut ArrayDecoder<'_>, base: usize, depth: usize) -> fmt::Result {
+    let stride = array.stride();
+    for i in 0.. {
+        if i == PREVIEW_ITEMS && array.remaining() > 0 {
+            line_start(w, base + i * stride, depth, None)?;
+            return writeln!(w, "… {} more", array.remaining());
+        }
+        match array.next() {
+            Ok(Some(item)) => {
+                line_start(w, base + i * stride, depth, None)?;
+                write_scalar(w, &item)?;
+                writeln!(w)?;
+            }
+            Ok(None) => break,
+            Err(e) => return write_error(w, base + i * stride, depth, e),
+        }
+    }
+    Ok(())
+}
+
+/// Writes one whole value, as returned by `raw_value`, and its children.
+fn write_value<W: Write>(w: &mut W, raw: &[u8], offset: usize, depth: usize, key: Option<&str>) -> fmt::Result {
+    line_start(w, offset, depth, key)?;
+    let body = raw.get(BLOB_HEADER..).unwrap_or_default();
+    match Tag::from_u8(raw[0]) {
+        Some(Tag::List) => {
+            writeln!(w, "List len={}", body.len())?;
+            write_values(w, body, offset + BLOB_HEADER, depth + 1)
+        }
+        Some(Tag::Map) => {
+            writeln!(w, "Map len={}", body.len())?;
+            write_entries(w, body, offset + BLOB_HEADER, depth + 1)
+        }
+        Some(Tag::Array) => match Decoder::new(raw).array() {
+            Ok(mut array) => {
+                writeln!(
+                    w, "Array len={} item={:?} stride={} count={}",
+                    body.len(), array.item_tag(), array.stride(), array.remaining(),
+                )?;
+                write_items(w, &mut array, offset + ARRAY_HEADER, depth + 1)
+            }
+            Err(e) => writeln!(w, "<error: {:?}>", e),
+        },
+        _ => match Decoder::new(raw).value() {
+            Ok(value) => {
+                write_scalar(w, &value)?;
+                writeln!(w)
+            }
+            Err(e) => writeln!(w, "<error: {:?}>", e),
+        },
+    }
+}
+
+/// Writes a value on one line; containers by name only.
+pub(crate) fn write_scalar<W: Write>(w: &mut W, value: &ValueDecoder<'_>) -> fmt::Result {
+    use ValueDecoder::*;
+    match value {
+        Bool(v) => write!(w, "Bool {}", v),
+        U8(v) => write!(w, "U8 {}", v),
+        S8(v) => write!(w, "S8 {}", v),
+        U16(v) => write!(w, "U16 {}", v),
+        S16(v) => write!(w, "S16 {}", v),
+        U32(v) => write!(w, "U32 {}", v),
+        S32(v) => write!(w, "S32 {}", v),
+        U64(v) => write!(w, "U64 {}", v),
+        S64(v) => write!(w, "S64 {}", v),
+        U128(v) => write!(w, "U128 {}", v),
+        S128(v) => write!(w, "S128 {}", v),
+        F16(v) => write!(w, "F16 {}", v.to_f32()),
+        F32(v) => write!(w, "F32 {}", v),
+        F64(v) => write!(w, "F64 {}", v),
+        Timestamp(v) => write!(w, "Timestamp {}ns", v.0),
+        Duration(v) => write!(w, "Duration {}ns", v.0),
+        Str(v) => {
+            write!(w, "String len={} ", v.len())?;
+            write_str_preview(w, v)
+        }
+        Bytes(v) => {
+            write!(w, "Bytes len={} ", v.len())?;
+            write_hex(w, v, PREVIEW_BYTES)
+        }
+        Struct(v) => {
+            write!(w, "Struct len={} ", v.len())?;
+            write_hex(w, v, PREVIEW_BYTES)
+        }
+        Fixed16(v) => {
+            w.write_str("Fixed16 ")?;
+            write_hex(w, *v, 16)
+        }
+        Fixed32(v) => {
+            w.write_str("Fixed32 ")?;
+            write_hex(w, *v, 32)
+        }
+        Ext(id, v) => {
+            write!(w, "Ext id={} len={} ", id, v.len())?;
+            write_hex(w, v, PREVIEW_BYTES)
+        }
+        List(_) => w.write_str("List"),
+        Map(_) => w.write_str("Map"),
+        Array(_) => w.write_str("Array"),
+    }
+}
+
+fn line_start<W: Write>(w: &mut W, offset: usize, depth: usize, key: Option<&str>) -> fmt::Result {
+    write!(w, "{:06x} {:indent$}", offset, "", indent = depth * 2)?;
+    if let Some(key) = key {
+        write_str_preview(w, key)?;
+        w.write_str(": ")?;
+    }
+    Ok(())
+}
+
+fn write_error<W: Write>(w: &mut W, offset: usize, depth: usize, e: Error) -> fmt::Result {
+    line_start(w, offset, depth, None)?;
+    writeln!(w, "<error: {:?}>", e)
+}
+
+fn write_str_preview<W: Write>(w: &mut W, s: &str) -> fmt::Result {
+    match s.char_indices().nth(PREVIEW_CHARS) {
+        Some((end, _)) => write!(w, "{:?}…", &s[..end]),
+        None => write!(w, "{:?}", s),
+    }
+}
+
+fn write_hex<W: Write>(w: &mut W, bytes: &[u8], limit: usize) -> fmt::Result {
+    for b in bytes.iter().take(limit) {
+        write!(w, "{:02x}", b)?;
+    }
+    if bytes.len() > limit {
+        w.write_str("…")?;
+    }
+    Ok(())
+}
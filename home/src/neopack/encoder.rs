@@ -1,20 +1,33 @@
-use std::mem;
+
+use alloc::vec::Vec;
 
 use super::types::Result;
 use super::types::Error;
 use super::types::Tag;
+use super::spec;
+use super::layout::LayoutCheck;
+use super::layout::RecordLayout;
 use super::macros::encode_wrapper_method;
 use super::macros::for_each_multibyte_scalar;
 use super::macros::encode_wrapper_api;
 use super::macros::encode_record_multibyte;
 use super::macros::encode_array_multibyte;
+use super::macros::encode_array_extend;
 use super::macros::encode_root_multibyte;
 
+/// The largest body a length prefix can describe.
+const MAX_CONTAINER: usize = u32::MAX as usize;
+
 /// A growable buffer that encodes data into the NeoPack format.
 pub struct Encoder {
-    pub buf: Vec<u8>,
+    pub(crate) buf: Vec<u8>,
     last_flush: usize,
     open_scopes: usize,
+    /// Largest container body allowed, in bytes.
+    max_container: usize,
+    /// Body length of a container that was dropped over the limit, and
+    /// removed. Reported by the next enclosing `finish` or `flush`.
+    overflow: Option<usize>,
 }
 
 impl Encoder {
@@ -23,6 +36,8 @@ impl Encoder {
             buf: Vec::new(),
             last_flush: 0,
             open_scopes: 0,
+            max_container: MAX_CONTAINER,
+            overflow: None,
         }
     }
 
@@ -31,6 +46,8 @@ impl Encoder {
             buf: Vec::with_capacity(cap),
             last_flush: 0,
             open_scopes: 0,
+            max_container: MAX_CONTAINER,
+            overflow: None,
         }
     }
 
@@ -42,6 +59,42 @@ impl Encoder {
         self.buf
     }
 
+    /// Bytes written so far, including any open containers.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Limits container bodies to `max` bytes, at most the format's
+    /// limit of `u32::MAX`. A container over it fails to finish, and is
+    /// removed along with its tag, leaving the encoder as it was before
+    /// the container started.
+    pub fn set_max_container(&mut self, max: usize) {
+        self.max_container = max.min(MAX_CONTAINER);
+    }
+
+    pub fn max_container(&self) -> usize {
+        self.max_container
+    }
+
+    /// The underlying buffer.
+    ///
+    /// # Safety
+    ///
+    /// Open containers patch their length at fixed offsets when they
+    /// finish, so bytes before the newest open container must not be
+    /// removed or moved. Anything written must be well-formed neopack.
+    pub unsafe fn buf_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+
     /// Flush all bytes written since the last flush
     /// Returns a slice of the newly flushed bytes
     /// Can only flush when all containers are closed
@@ -49,6 +102,9 @@ impl Encoder {
         if self.open_scopes > 0 {
             return Err(Error::ScopeOpen);
         }
+        if let Some(len) = self.overflow.take() {
+            return Err(Error::ContainerTooLarge(len));
+        }
         let slice = &self.buf[self.last_flush..];
         self.last_flush = self.buf.len();
         Ok(slice)
@@ -78,6 +134,8 @@ impl Encoder {
             buf: bytes,
             last_flush: 0,
             open_scopes: 0,
+            max_container: MAX_CONTAINER,
+            overflow: None,
         })
     }
 
@@ -151,6 +209,18 @@ impl Encoder {
         Ok(ListEncoder::new(self))
     }
 
+    /// Writes a list with one item per element of `items`, each written
+    /// by `write`.
+    pub fn list_from_iter<I, F>(&mut self, items: I, write: F) -> Result<&mut Self>
+    where
+        I: IntoIterator,
+        F: FnMut(&mut ListEncoder<'_>, I::Item) -> Result<()>,
+    {
+        let mut list = self.list()?;
+        list.extend_with(items, write)?;
+        list.finish()
+    }
+
     pub fn map(&mut self) -> Result<MapEncoder<'_>> {
         if self.buf.len() >= u32::MAX as usize {
             return Err(Error::ContainerFull);
@@ -186,6 +256,41 @@ impl Encoder {
         Ok(self)
     }
 
+    /// Writes 16 bytes without a length prefix, saving the 4 bytes
+    /// `bytes` would spend on one.
+    pub fn fixed16(&mut self, v: &[u8; 16]) -> Result<&mut Self> {
+        if self.buf.len() >= u32::MAX as usize {
+            return Err(Error::ContainerFull);
+        }
+        self.write_tag(Tag::Fixed16);
+        self.buf.extend_from_slice(v);
+        Ok(self)
+    }
+
+    /// Writes 32 bytes without a length prefix.
+    pub fn fixed32(&mut self, v: &[u8; 32]) -> Result<&mut Self> {
+        if self.buf.len() >= u32::MAX as usize {
+            return Err(Error::ContainerFull);
+        }
+        self.write_tag(Tag::Fixed32);
+        self.buf.extend_from_slice(v);
+        Ok(self)
+    }
+
+    /// Writes a value of extension type `id`. Readers that don't know the
+    /// extension can still skip it.
+    pub fn ext(&mut self, id: u16, payload: &[u8]) -> Result<&mut Self> {
+        let len = payload.len() + spec::EXT_ID_LEN;
+        if len > u32::MAX as usize {
+            return Err(Error::BlobTooLarge(len));
+        }
+        self.write_tag(Tag::Ext);
+        self.write_u32_raw(len as u32);
+        self.buf.extend_from_slice(&id.to_le_bytes());
+        self.buf.extend_from_slice(payload);
+        Ok(self)
+    }
+
     /// Starts a standard Record (opaque struct with a Tag and Length header).
     pub fn record(&mut self) -> Result<RecordEncoder<'_>> {
         if self.buf.len() >= u32::MAX as usize {
@@ -193,63 +298,98 @@ impl Encoder {
         }
         self.write_tag(Tag::Struct);
         Ok(RecordEncoder {
-            scope: PatchScope::new(self)
+            scope: PatchScope::new(self),
+            layout: None,
         })
     }
+
+    /// Starts a Record whose fields are checked against `layout`.
+    pub fn record_with<'s>(&'s mut self, layout: &RecordLayout<'s>) -> Result<RecordEncoder<'s>> {
+        let mut record = self.record()?;
+        record.layout = Some(LayoutCheck::new(layout));
+        Ok(record)
+    }
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
+/// Patches a container's length when it ends. The parent is held in an
+/// `Option` so `finish` can hand it back without running `drop`.
 struct PatchScope<'a> {
-    parent: &'a mut Encoder,
+    parent: Option<&'a mut Encoder>,
     len_offset: usize,
     body_start_offset: usize,
 }
 
 impl<'a> PatchScope<'a> {
+    /// Starts a container whose tag was just written.
     fn new(parent: &'a mut Encoder) -> Self {
         let len_offset = parent.buf.len();
         parent.buf.extend_from_slice(&[0; 4]);
         let body_start_offset = parent.buf.len();
         parent.open_scopes += 1;
-        Self { parent, len_offset, body_start_offset }
+        Self { parent: Some(parent), len_offset, body_start_offset }
     }
 
+    /// Like `new`, for a container that wrote its own length placeholder
+    /// right after its tag.
     fn manual(parent: &'a mut Encoder, len_offset: usize, body_start_offset: usize) -> Self {
         parent.open_scopes += 1;
-        Self { parent, len_offset, body_start_offset }
+        Self { parent: Some(parent), len_offset, body_start_offset }
     }
 
-    fn check_size(&self) -> Result<()> {
-        let current_len = self.parent.buf.len();
-        let body_len = current_len.saturating_sub(self.body_start_offset);
-        if body_len > u32::MAX as usize {
-            return Err(Error::ContainerFull);
-        }
-        Ok(())
+    /// The parent is only taken by `finish`, which consumes the scope.
+    fn parent(&mut self) -> &mut Encoder {
+        self.parent.as_deref_mut().expect("scope already finished")
     }
 
+    /// Patches the length prefix. A body over the limit, or holding a
+    /// container that was dropped over it, is cut off along with the tag
+    /// before the prefix, and fails.
     fn flush(&mut self) -> Result<()> {
-        self.check_size()?;
-        let current_len = self.parent.buf.len();
-        let body_len = current_len.saturating_sub(self.body_start_offset);
-        let len_bytes = (body_len as u32).to_le_bytes();
-        let dest = &mut self.parent.buf[self.len_offset..self.len_offset + 4];
-        dest.copy_from_slice(&len_bytes);
+        let body_start_offset = self.body_start_offset;
+        let len_offset = self.len_offset;
+        let parent = self.parent();
+        let body_len = parent.buf.len().saturating_sub(body_start_offset);
+        let overflow = match parent.overflow.take() {
+            Some(len) => Some(len),
+            None if body_len > parent.max_container => Some(body_len),
+            None => None,
+        };
+        if let Some(len) = overflow {
+            parent.buf.truncate(len_offset - 1);
+            return Err(Error::ContainerTooLarge(len));
+        }
+        parent.buf[len_offset..len_offset + 4].copy_from_slice(&(body_len as u32).to_le_bytes());
         Ok(())
     }
 
     fn finish(mut self) -> Result<&'a mut Encoder> {
-        self.flush()?;
-        self.parent.open_scopes -= 1;
-        let parent_ptr = self.parent as *mut Encoder;
-        mem::forget(self);
-        Ok(unsafe { &mut *parent_ptr })
+        let result = self.flush();
+        let parent = self.parent.take().expect("scope already finished");
+        parent.open_scopes -= 1;
+        result.map(|()| parent)
     }
 }
 
 impl<'a> Drop for PatchScope<'a> {
+    /// A container dropped without `finish` has no way to fail, so one
+    /// over the limit is recorded on the encoder for the enclosing
+    /// `finish`, or `Encoder::flush`, to report.
     fn drop(&mut self) {
-        let _ = self.flush();
-        self.parent.open_scopes -= 1;
+        if self.parent.is_none() {
+            return;
+        }
+        let result = self.flush();
+        let parent = self.parent();
+        parent.open_scopes -= 1;
+        if let Err(Error::ContainerTooLarge(len)) = result {
+            parent.overflow = Some(len);
+        }
     }
 }
 
@@ -263,11 +403,23 @@ impl<'a> ListEncoder<'a> {
     }
 
     encode_wrapper_api!([&mut self], &mut Self, '_;
-        parent: self.scope.parent;
+        parent: self.scope.parent();
         pre: {};
         post: self
     );
 
+    /// Appends one item per element of `items`, each written by `write`.
+    pub fn extend_with<I, F>(&mut self, items: I, mut write: F) -> Result<&mut Self>
+    where
+        I: IntoIterator,
+        F: FnMut(&mut Self, I::Item) -> Result<()>,
+    {
+        for item in items {
+            write(self, item)?;
+        }
+        Ok(self)
+    }
+
     pub fn finish(self) -> Result<&'a mut Encoder> {
         self.scope.finish()
     }
@@ -282,11 +434,11 @@ impl<'a> MapEncoder<'a> {
         Self { scope: PatchScope::new(parent) }
     }
 
-    #[must_use]
+    #[must_use = "the key's value is written through the returned encoder"]
     pub fn key(&mut self, k: &str) -> Result<MapValueEncoder<'_>> {
-        self.scope.parent.str(k)?;
+        self.scope.parent().str(k)?;
         Ok(MapValueEncoder {
-            parent: self.scope.parent,
+            parent: self.scope.parent(),
         })
     }
 
@@ -297,7 +449,7 @@ impl<'a> MapEncoder<'a> {
 
 #[must_use]
 pub struct MapValueEncoder<'a> {
-    parent: &'a mut Encoder,
+    pub(super) parent: &'a mut Encoder,
 }
 
 impl<'a> MapValueEncoder<'a> {
@@ -310,38 +462,53 @@ impl<'a> MapValueEncoder<'a> {
 
 pub struct RecordEncoder<'a> {
     scope: PatchScope<'a>,
+    layout: Option<LayoutCheck<'a>>,
 }
 
 impl<'a> RecordEncoder<'a> {
+    /// Appends raw bytes. Fails on a record with a layout, since raw
+    /// bytes can't be checked against it.
     pub fn bytes(&mut self, data: &[u8]) -> Result<&mut Self> {
-        self.scope.parent.buf.extend_from_slice(data);
+        if let Some(layout) = &mut self.layout {
+            layout.field(Tag::Bytes)?;
+        }
+        self.scope.parent().buf.extend_from_slice(data);
         Ok(self)
     }
 
+    fn field(&mut self, tag: Tag, data: &[u8]) -> Result<()> {
+        if let Some(layout) = &mut self.layout {
+            layout.field(tag)?;
+        }
+        self.scope.parent().buf.extend_from_slice(data);
+        Ok(())
+    }
+
     #[inline]
     pub fn bool(&mut self, v: bool) -> Result<&mut Self> {
-        self.scope.parent.write_tag(Tag::Bool);
-        self.scope.parent.buf.push(v as u8);
+        self.field(Tag::Bool, &[v as u8])?;
         Ok(self)
     }
 
     #[inline]
     pub fn u8(&mut self, v: u8) -> Result<&mut Self> {
-        self.scope.parent.write_tag(Tag::U8);
-        self.scope.parent.buf.push(v);
+        self.field(Tag::U8, &[v])?;
         Ok(self)
     }
 
     #[inline]
     pub fn i8(&mut self, v: i8) -> Result<&mut Self> {
-        self.scope.parent.write_tag(Tag::S8);
-        self.scope.parent.buf.push(v as u8);
+        self.field(Tag::S8, &[v as u8])?;
         Ok(self)
     }
 
     for_each_multibyte_scalar!(encode_record_multibyte, ());
 
+    /// Fails if the record has a layout and some fields weren't written.
     pub fn finish(self) -> Result<&'a mut Encoder> {
+        if let Some(layout) = &self.layout {
+            layout.finish()?;
+        }
         self.scope.finish()
     }
 }
@@ -352,50 +519,112 @@ pub struct ArrayEncoder<'a> {
 }
 
 impl<'a> ArrayEncoder<'a> {
+    /// Appends raw bytes without checking them against the stride.
+    ///
+    /// # Safety
+    ///
+    /// `data` must be exactly one stride long (or, inside a record body,
+    /// sum to one stride before `finish`), otherwise the array is malformed.
     pub unsafe fn push_unchecked(&mut self, data: &[u8]) -> Result<()> {
-        self.scope.parent.buf.extend_from_slice(data);
+        self.append(data);
         Ok(())
     }
 
+    fn append(&mut self, data: &[u8]) {
+        self.scope.parent().buf.extend_from_slice(data);
+    }
+
     pub fn push(&mut self, data: &[u8]) -> Result<()> {
         if data.len() != self.stride {
             return Err(Error::Malformed);
         }
-        unsafe { self.push_unchecked(data) }
+        self.append(data);
+        Ok(())
     }
 
     #[inline]
     pub fn bool(&mut self, v: bool) -> Result<()> {
-        self.scope.parent.write_tag(Tag::Bool);
-        self.scope.parent.buf.push(v as u8);
+        self.scope.parent().write_tag(Tag::Bool);
+        self.scope.parent().buf.push(v as u8);
         Ok(())
     }
 
     #[inline]
     pub fn u8(&mut self, v: u8) -> Result<()> {
-        self.scope.parent.write_tag(Tag::U8);
-        self.scope.parent.buf.push(v);
+        self.scope.parent().write_tag(Tag::U8);
+        self.scope.parent().buf.push(v);
         Ok(())
     }
 
     #[inline]
     pub fn i8(&mut self, v: i8) -> Result<()> {
-        self.scope.parent.write_tag(Tag::S8);
-        self.scope.parent.buf.push(v as u8);
+        self.scope.parent().write_tag(Tag::S8);
+        self.scope.parent().buf.push(v as u8);
         Ok(())
     }
 
     for_each_multibyte_scalar!(encode_array_multibyte, ());
 
+    /// Appends every byte in `vs`; the stride must be 1.
+    pub fn extend_from_slice_u8(&mut self, vs: &[u8]) -> Result<()> {
+        if self.stride != 1 {
+            return Err(Error::Malformed);
+        }
+        self.scope.parent().buf.extend_from_slice(vs);
+        Ok(())
+    }
+
+    /// Appends every value in `vs`; the stride must be 1.
+    pub fn extend_from_slice_i8(&mut self, vs: &[i8]) -> Result<()> {
+        if self.stride != 1 {
+            return Err(Error::Malformed);
+        }
+        self.scope.parent().buf.extend(vs.iter().map(|v| *v as u8));
+        Ok(())
+    }
+
+    /// Appends every value in `vs`; the stride must be 1.
+    pub fn extend_from_slice_bool(&mut self, vs: &[bool]) -> Result<()> {
+        if self.stride != 1 {
+            return Err(Error::Malformed);
+        }
+        self.scope.parent().buf.extend(vs.iter().map(|v| *v as u8));
+        Ok(())
+    }
+
+    encode_array_extend!(extend_from_slice_u16, u16);
+    encode_array_extend!(extend_from_slice_i16, i16);
+    encode_array_extend!(extend_from_slice_u32, u32);
+    encode_array_extend!(extend_from_slice_i32, i32);
+    encode_array_extend!(extend_from_slice_u64, u64);
+    encode_array_extend!(extend_from_slice_i64, i64);
+    encode_array_extend!(extend_from_slice_u128, u128);
+    encode_array_extend!(extend_from_slice_i128, i128);
+    encode_array_extend!(extend_from_slice_f16, crate::neopack::types::F16);
+    encode_array_extend!(extend_from_slice_f32, f32);
+    encode_array_extend!(extend_from_slice_f64, f64);
+    encode_array_extend!(extend_from_slice_timestamp, crate::neopack::types::Timestamp);
+    encode_array_extend!(extend_from_slice_duration, crate::neopack::types::Duration);
+
     /// Starts writing a fixed-size record into the array.
     pub fn record(&mut self) -> RecordBodyEncoder<'_, 'a> {
-        let start = self.scope.parent.buf.len();
+        let start = self.scope.parent().buf.len();
         RecordBodyEncoder {
             parent: self,
             start,
+            layout: None,
         }
     }
 
+    /// Starts a record whose fields are checked against `layout`. Fails if
+    /// the layout's stride isn't the array's.
+    pub fn record_with<'p>(&'p mut self, layout: &RecordLayout<'p>) -> Result<RecordBodyEncoder<'p, 'a>> {
+        layout.check_size(self.stride)?;
+        let mut record = self.record();
+        record.layout = Some(LayoutCheck::new(layout));
+        Ok(record)
+    }
+
     pub fn finish(self) -> Result<&'a mut Encoder> {
         self.scope.finish()
     }
@@ -404,33 +633,44 @@ impl<'a> ArrayEncoder<'a> {
 pub struct RecordBodyEncoder<'p, 'a> {
     parent: &'p mut ArrayEncoder<'a>,
     start: usize,
+    layout: Option<LayoutCheck<'p>>,
 }
 
 impl<'p, 'a> RecordBodyEncoder<'p, 'a> {
+    /// Appends raw bytes. Fails on a record with a layout, since raw
+    /// bytes can't be checked against it.
     pub fn bytes(&mut self, data: &[u8]) -> Result<&mut Self> {
+        if let Some(layout) = &mut self.layout {
+            layout.field(Tag::Bytes)?;
+        }
         // We bypass stride checks until finish
-        unsafe { self.parent.push_unchecked(data)?; }
+        self.parent.append(data);
         Ok(self)
     }
 
+    fn field(&mut self, tag: Tag, data: &[u8]) -> Result<()> {
+        if let Some(layout) = &mut self.layout {
+            layout.field(tag)?;
+        }
+        self.parent.append(data);
+        Ok(())
+    }
+
     #[inline]
     pub fn bool(&mut self, v: bool) -> Result<&mut Self> {
-        self.parent.scope.parent.write_tag(Tag::Bool);
-        self.parent.scope.parent.buf.push(v as u8);
+        self.field(Tag::Bool, &[v as u8])?;
         Ok(self)
     }
 
     #[inline]
     pub fn u8(&mut self, v: u8) -> Result<&mut Self> {
-        self.parent.scope.parent.write_tag(Tag::U8);
-        self.parent.scope.parent.buf.push(v);
+        self.field(Tag::U8, &[v])?;
         Ok(self)
     }
 
     #[inline]
     pub fn i8(&mut self, v: i8) -> Result<&mut Self> {
-        self.parent.scope.parent.write_tag(Tag::S8);
-        self.parent.scope.parent.buf.push(v as u8);
+        self.field(Tag::S8, &[v as u8])?;
         Ok(self)
     }
 
@@ -440,7 +680,10 @@ impl<'p, 'a> RecordBodyEncoder<'p, 'a> {
     where
         'a: 'p,
     {
-        let end = self.parent.scope.parent.buf.len();
+        if let Some(layout) = &self.layout {
+            layout.finish()?;
+        }
+        let end = self.parent.scope.parent().buf.len();
         let written = end - self.start;
         if written != self.parent.stride {
             return Err(Error::Malformed);
@@ -0,0 +1,82 @@
+//! Record layouts: the field types of a fixed-size record
+//!
+//! Records are untagged, so a writer and reader that disagree about the
+//! fields silently misread each other. A `RecordLayout` names the fields
+//! once, and record encoders and `RecordDecoder`s built with it check each
+//! field as it is written or read, failing with `Error::LayoutMismatch` at
+//! the first field that differs.
+
+use super::types::Error;
+use super::types::Result;
+use super::types::Tag;
+
+/// The scalar type of each field of a record, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordLayout<'l> {
+    fields: &'l [Tag],
+    stride: usize,
+}
+
+impl<'l> RecordLayout<'l> {
+    /// Panics if a field isn't a fixed-size scalar.
+    pub const fn new(fields: &'l [Tag]) -> Self {
+        let mut stride = 0;
+        let mut i = 0;
+        while i < fields.len() {
+            match fields[i].scalar_size() {
+                Some(size) => stride += size,
+                None => panic!("record fields must be fixed-size scalars"),
+            }
+            i += 1;
+        }
+        Self { fields, stride }
+    }
+
+    pub fn fields(&self) -> &'l [Tag] {
+        self.fields
+    }
+
+    /// Bytes in one record.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Fails unless a record of `len` bytes fits the layout.
+    pub(crate) fn check_size(&self, len: usize) -> Result<()> {
+        if len != self.stride {
+            return Err(Error::LayoutSize { expected: self.stride, found: len });
+        }
+        Ok(())
+    }
+}
+
+/// Tracks progress through a layout while a record is written or read.
+#[derive(Debug, Clone)]
+pub(crate) struct LayoutCheck<'l> {
+    fields: &'l [Tag],
+    next: usize,
+}
+
+impl<'l> LayoutCheck<'l> {
+    pub(crate) fn new(layout: &RecordLayout<'l>) -> Self {
+        Self { fields: layout.fields, next: 0 }
+    }
+
+    /// Checks that the next field is a `found`.
+    pub(crate) fn field(&mut self, found: Tag) -> Result<()> {
+        let expected = self.fields.get(self.next).copied();
+        if expected != Some(found) {
+            return Err(Error::LayoutMismatch { field: self.next, expected, found: Some(found) });
+        }
+        self.next += 1;
+        Ok(())
+    }
+
+    /// Checks that every field has been seen.
+    pub(crate) fn finish(&self) -> Result<()> {
+        if let Some(expected) = self.fields.get(self.next) {
+            return Err(Error::LayoutMismatch { field: self.next, expected: Some(*expected), found: None });
+        }
+        Ok(())
+    }
+}
@@ -22,10 +22,15 @@ macro_rules! for_each_scalar {
         $m!(i64,  as_i64,  i64,  crate::neopack::types::Tag::S64,  S64,  $ctx);
         $m!(f32,  as_f32,  f32,  crate::neopack::types::Tag::F32,  F32,  $ctx);
         $m!(f64,  as_f64,  f64,  crate::neopack::types::Tag::F64,  F64,  $ctx);
+        $m!(f16,  as_f16,  crate::neopack::types::F16, crate::neopack::types::Tag::F16, F16, $ctx);
+        $m!(u128, as_u128, u128, crate::neopack::types::Tag::U128, U128, $ctx);
+        $m!(i128, as_i128, i128, crate::neopack::types::Tag::S128, S128, $ctx);
+        $m!(timestamp, as_timestamp, crate::neopack::types::Timestamp, crate::neopack::types::Tag::Timestamp, Timestamp, $ctx);
+        $m!(duration,  as_duration,  crate::neopack::types::Duration,  crate::neopack::types::Tag::Duration,  Duration,  $ctx);
     };
 }
 
-/// Only multibyte scalars (u16..f64) that have to_le_bytes()
+/// Only multibyte scalars (u16..i128) that have to_le_bytes()
 macro_rules! for_each_multibyte_scalar {
     ($m:ident, $ctx:tt) => {
         $m!(u16,  as_u16,  u16,  crate::neopack::types::Tag::U16,  U16,  $ctx);
@@ -36,11 +41,16 @@ macro_rules! for_each_multibyte_scalar {
         $m!(i64,  as_i64,  i64,  crate::neopack::types::Tag::S64,  S64,  $ctx);
         $m!(f32,  as_f32,  f32,  crate::neopack::types::Tag::F32,  F32,  $ctx);
         $m!(f64,  as_f64,  f64,  crate::neopack::types::Tag::F64,  F64,  $ctx);
+        $m!(f16,  as_f16,  crate::neopack::types::F16, crate::neopack::types::Tag::F16, F16, $ctx);
+        $m!(u128, as_u128, u128, crate::neopack::types::Tag::U128, U128, $ctx);
+        $m!(i128, as_i128, i128, crate::neopack::types::Tag::S128, S128, $ctx);
+        $m!(timestamp, as_timestamp, crate::neopack::types::Timestamp, crate::neopack::types::Tag::Timestamp, Timestamp, $ctx);
+        $m!(duration,  as_duration,  crate::neopack::types::Duration,  crate::neopack::types::Tag::Duration,  Duration,  $ctx);
     };
 }
 
 /// Generates optimized multi-byte writes for the base Encoder.
-/// Only for types with to_le_bytes() (u16, i16, u32, i32, u64, i64, f32, f64)
+/// Only for types with to_le_bytes() (u16 through i128, floats, times)
 macro_rules! encode_root_multibyte {
     ($name:ident, $as_name:ident, $ty:ty, $tag:expr, $var:ident, $ctx:tt) => {
         #[inline]
@@ -67,13 +77,43 @@ macro_rules! encode_array_multibyte {
     };
 }
 
+/// Generates bulk slice writes for ArrayEncoder (e.g. arr.extend_from_slice_u32(&vals)).
+/// Only for types with to_le_bytes()
+macro_rules! encode_array_extend {
+    ($name:ident, $ty:ty) => {
+        /// Appends every value in `vs`; the stride must be the type's size.
+        pub fn $name(&mut self, vs: &[$ty]) -> crate::neopack::types::Result<()> {
+            if self.stride != core::mem::size_of::<$ty>() {
+                return Err(crate::neopack::types::Error::Malformed);
+            }
+            #[cfg(target_endian = "little")]
+            {
+                // SAFETY: the type is plain old data, so every byte of the
+                // slice is initialised, and its memory order is little-endian.
+                let bytes = unsafe {
+                    core::slice::from_raw_parts(vs.as_ptr() as *const u8, core::mem::size_of_val(vs))
+                };
+                self.scope.parent().buf.extend_from_slice(bytes);
+            }
+            #[cfg(not(target_endian = "little"))]
+            {
+                self.scope.parent().buf.reserve(core::mem::size_of_val(vs));
+                for v in vs {
+                    self.scope.parent().buf.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+            Ok(())
+        }
+    };
+}
+
 /// Generates raw write methods for FixedRecordEncoder.
 /// Only for types with to_le_bytes()
 macro_rules! encode_record_multibyte {
     ($name:ident, $as_name:ident, $ty:ty, $tag:expr, $var:ident, $ctx:tt) => {
         #[inline]
         pub fn $name(&mut self, v: $ty) -> crate::neopack::types::Result<&mut Self> {
-            self.bytes(&v.to_le_bytes())?;
+            self.field($tag, &v.to_le_bytes())?;
             Ok(self)
         }
     };
@@ -114,6 +154,24 @@ macro_rules! encode_wrapper_api {
             Ok($post)
         }
 
+        pub fn fixed16($($recv)+, v: &[u8; 16]) -> crate::neopack::types::Result<$ret_ty> {
+            $pre
+            $parent.fixed16(v)?;
+            Ok($post)
+        }
+
+        pub fn fixed32($($recv)+, v: &[u8; 32]) -> crate::neopack::types::Result<$ret_ty> {
+            $pre
+            $parent.fixed32(v)?;
+            Ok($post)
+        }
+
+        pub fn ext($($recv)+, id: u16, payload: &[u8]) -> crate::neopack::types::Result<$ret_ty> {
+            $pre
+            $parent.ext(id, payload)?;
+            Ok($post)
+        }
+
         pub fn list($($recv)+) -> crate::neopack::types::Result<ListEncoder<$lt>> {
             $pre
             $parent.list()
@@ -143,7 +201,7 @@ macro_rules! impl_from_bytes {
             const SIZE: usize = $size;
             #[inline(always)]
             fn read_from(src: &[u8]) -> Self {
-                let (bytes, _) = src.split_at(std::mem::size_of::<Self>());
+                let (bytes, _) = src.split_at(core::mem::size_of::<Self>());
                 Self::from_le_bytes(bytes.try_into().unwrap())
             }
         }
@@ -165,8 +223,9 @@ macro_rules! decode_expect_tag {
 
 /// Generates a public read method that reads directly (no tag check).
 macro_rules! decode_record_prim {
-    ($name:ident, $as_name:ident, $ty:ty, $_tag:expr, $var:ident, $_ctx:tt) => {
+    ($name:ident, $as_name:ident, $ty:ty, $tag:expr, $var:ident, $_ctx:tt) => {
         pub fn $name(&mut self) -> crate::neopack::types::Result<$ty> {
+            self.field($tag)?;
             self.read_primitive::<$ty>()
         }
     };
@@ -209,6 +268,7 @@ pub(crate) use for_each_scalar;
 pub(crate) use for_each_multibyte_scalar;
 pub(crate) use encode_root_multibyte;
 pub(crate) use encode_array_multibyte;
+pub(crate) use encode_array_extend;
 pub(crate) use encode_record_multibyte;
 pub(crate) use encode_wrapper_method;
 pub(crate) use encode_wrapper_api;
@@ -1,13 +1,26 @@
 mod macros;
 
+pub mod spec;
 pub mod types;
 pub mod encoder;
 pub mod decoder;
 pub mod cursor;
+pub mod dump;
+pub mod diff;
+pub mod size;
+pub mod layout;
+pub mod doc;
+pub mod pack;
+pub mod writer;
+#[cfg(feature = "std")]
+pub mod stream;
 
 pub use types::Result;
 pub use types::Error;
 pub use types::Tag;
+pub use types::F16;
+pub use types::Timestamp;
+pub use types::Duration;
 
 pub use encoder::Encoder;
 pub use encoder::ListEncoder;
@@ -15,6 +28,9 @@ pub use encoder::MapEncoder;
 pub use encoder::ArrayEncoder;
 pub use encoder::RecordEncoder;
 pub use encoder::RecordBodyEncoder;
+pub use encoder::MapValueEncoder;
+pub use writer::ScalarWriter;
+pub use writer::ValueWriter;
 
 pub use decoder::Decoder;
 pub use decoder::ListDecoder;
@@ -23,9 +39,30 @@ pub use decoder::ArrayDecoder;
 pub use decoder::RecordDecoder;
 pub use decoder::ValueDecoder;
 
+pub use dump::dump;
+pub use diff::diff;
+pub use diff::DiffEntry;
+pub use diff::DiffKind;
+pub use diff::PathSegment;
+
+pub use size::SizeCounter;
+pub use size::ArrayCounter;
+
+pub use layout::RecordLayout;
+
+pub use doc::Doc;
+pub use pack::Pack;
+
+#[cfg(feature = "std")]
+pub use stream::StreamEncoder;
+#[cfg(feature = "std")]
+pub use stream::StreamError;
+
 pub use cursor::Cursor;
 pub use cursor::Location;
 pub use cursor::StreamBuffer;
 
 #[cfg(test)]
 mod tests;
+#[cfg(test)]
+mod proptests;
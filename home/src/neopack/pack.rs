@@ -0,0 +1,37 @@
+//! Types that write and read themselves as one neopack value
+//!
+//! `Pack` pairs a `write` through any `ValueWriter` with a `read` from the
+//! decoded value, so a type can be a whole message, a list item, or a map
+//! value, and every call site encodes it the same way. `encode` and
+//! `decode` wrap the two for a value on its own.
+
+use alloc::vec::Vec;
+use crate::neopack::decoder::Decoder;
+use crate::neopack::decoder::ValueDecoder;
+use crate::neopack::encoder::Encoder;
+use crate::neopack::types::Error;
+use crate::neopack::types::Result;
+use crate::neopack::writer::ValueWriter;
+
+pub trait Pack: Sized {
+    /// Writes `self` as exactly one value.
+    fn write<W: ValueWriter>(&self, w: &mut W) -> Result<()>;
+
+    fn read(value: ValueDecoder<'_>) -> Result<Self>;
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut enc = Encoder::new();
+        self.write(&mut enc)?;
+        Ok(enc.into_bytes())
+    }
+
+    /// Reads a buffer holding exactly one value.
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::new(bytes);
+        let value = Self::read(decoder.value()?)?;
+        if decoder.remaining() > 0 {
+            return Err(Error::Malformed);
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,137 @@
+//! Property tests over generated documents
+//!
+//! Documents come from `Doc`'s `Arbitrary` impl fed with random bytes, the
+//! same generator the fuzz target uses, so a failure here can be replayed
+//! there and the other way round.
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use arbitrary::Arbitrary;
+use arbitrary::Unstructured;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::strategy::ValueTree;
+use proptest::test_runner::TestRunner;
+use super::*;
+
+fn doc() -> impl Strategy<Value = Doc> {
+    vec(any::<u8>(), 0..2048).prop_filter_map("generator ran out of input", |bytes| {
+        Doc::arbitrary(&mut Unstructured::new(&bytes)).ok()
+    })
+}
+
+/// A document's encoding with one byte changed and the rest cut off at
+/// some point. Half the time the byte changed is a tag, changed to
+/// another valid tag.
+fn corrupted() -> impl Strategy<Value = Vec<u8>> {
+    (doc(), any::<bool>(), any::<usize>(), any::<u8>(), any::<usize>()).prop_map(|(doc, on_tag, at, byte, cut)| {
+        let mut bytes = doc.encode().unwrap();
+        let len = bytes.len();
+        if on_tag {
+            let mut offsets = Vec::new();
+            tag_offsets(&bytes, 0, &mut offsets);
+            let tags: Vec<u8> = (0..=u8::MAX).filter(|&b| Tag::from_u8(b).is_some()).collect();
+            bytes[offsets[at % offsets.len()]] = tags[byte as usize % tags.len()];
+        } else {
+            bytes[at % len] = byte;
+        }
+        bytes.truncate(len - cut % len.min(8));
+        bytes
+    })
+}
+
+/// Collects the offset of every tag in `bytes`, map keys and values in
+/// nested lists and maps included.
+fn tag_offsets(bytes: &[u8], base: usize, offsets: &mut Vec<usize>) {
+    let mut decoder = Decoder::new(bytes);
+    while decoder.remaining() > 0 {
+        let start = decoder.pos();
+        offsets.push(base + start);
+        let tag = decoder.peek_tag().unwrap();
+        let raw = decoder.raw_value().unwrap();
+        if matches!(tag, Tag::List | Tag::Map) {
+            let body = spec::BLOB_HEADER;
+            tag_offsets(&raw[body..], base + start + body, offsets);
+        }
+    }
+}
+
+/// Decodes every value in `bytes`, checking that skipping each one moves
+/// as far as decoding it.
+fn decode_checked(bytes: &[u8]) -> Result<Vec<Doc>> {
+    let mut decoder = Decoder::new(bytes);
+    let mut docs = Vec::new();
+    while decoder.remaining() > 0 {
+        let mut skipper = decoder.clone();
+        let skipped = skipper.skip_value();
+        let doc = decoder.value().and_then(Doc::read);
+        if doc.is_ok() {
+            assert!(skipped.is_ok());
+            assert_eq!(skipper.pos(), decoder.pos());
+        }
+        docs.push(doc?);
+    }
+    Ok(docs)
+}
+
+proptest! {
+    #[test]
+    fn encode_decode_encode_is_identical(doc in doc()) {
+        let bytes = doc.encode().unwrap();
+        let decoded = Doc::decode(&bytes).unwrap();
+        prop_assert_eq!(decoded.encode().unwrap(), bytes);
+    }
+
+    #[test]
+    fn skip_value_consumes_what_decoding_does(docs in vec(doc(), 0..8)) {
+        let mut enc = Encoder::new();
+        let mut ends = Vec::new();
+        for doc in &docs {
+            doc.write(&mut enc).unwrap();
+            ends.push(enc.len());
+        }
+        let bytes = enc.into_bytes();
+
+        let mut decoder = Decoder::new(&bytes);
+        for &end in &ends {
+            decoder.skip_value().unwrap();
+            prop_assert_eq!(decoder.pos(), end);
+        }
+        prop_assert_eq!(decode_checked(&bytes).unwrap().len(), docs.len());
+    }
+
+    #[test]
+    fn corrupt_input_never_panics(bytes in corrupted()) {
+        let _ = decode_checked(&bytes);
+        let _ = Doc::decode(&bytes);
+    }
+
+    #[test]
+    fn random_input_never_panics(bytes in vec(any::<u8>(), 0..256)) {
+        let _ = decode_checked(&bytes);
+    }
+}
+
+/// Each error the decoder can give for bad input turns up when decoding
+/// corrupted documents.
+#[test]
+fn corruption_reaches_every_decoder_error() {
+    let mut runner = TestRunner::deterministic();
+    let strategy = corrupted();
+    let mut seen = BTreeSet::new();
+    for _ in 0..5_000 {
+        let bytes = strategy.new_tree(&mut runner).unwrap().current();
+        if let Err(err) = decode_checked(&bytes) {
+            seen.insert(match err {
+                Error::Pending(_) => "Pending",
+                Error::InvalidTag(_) => "InvalidTag",
+                Error::InvalidUtf8 => "InvalidUtf8",
+                Error::TypeMismatch => "TypeMismatch",
+                Error::Malformed => "Malformed",
+                other => panic!("unexpected decoder error {other:?}"),
+            });
+        }
+    }
+    assert_eq!(seen.into_iter().collect::<Vec<_>>(), ["InvalidTag", "InvalidUtf8", "Malformed", "Pending", "TypeMismatch"]);
+}
+
@@ -0,0 +1,147 @@
+//! Dry runs that measure an encoding without writing it
+//!
+//! `SizeCounter` has the same writing methods as `Encoder` but only adds
+//! up how many bytes they would produce. Running the same writes against
+//! a counter first gives the exact size, so the real encoder can be made
+//! with `Encoder::with_capacity` and never grows.
+//!
+//! A container's size doesn't depend on anything written after it, so
+//! there is nothing to patch: `list` and `map` count their header and
+//! return the counter itself, and `finish` does nothing.
+
+use core::mem::size_of;
+use super::macros::for_each_multibyte_scalar;
+use super::macros::for_each_scalar;
+use super::spec;
+use super::types::Error;
+use super::types::Result;
+use super::types::Tag;
+
+/// Generates tagged scalar counts for SizeCounter.
+macro_rules! count_scalar {
+    ($name:ident, $as_name:ident, $ty:ty, $tag:expr, $var:ident, $ctx:tt) => {
+        #[inline]
+        pub fn $name(&mut self, _v: $ty) -> Result<&mut Self> {
+            self.add(1 + size_of::<$ty>())
+        }
+    };
+}
+
+/// Generates untagged item counts for ArrayCounter.
+macro_rules! count_array_item {
+    ($name:ident, $as_name:ident, $ty:ty, $tag:expr, $var:ident, $ctx:tt) => {
+        #[inline]
+        pub fn $name(&mut self, _v: $ty) -> Result<()> {
+            self.parent.add(self.stride)?;
+            Ok(())
+        }
+    };
+}
+
+/// Counts the bytes an `Encoder` would write.
+#[derive(Debug, Clone, Default)]
+pub struct SizeCounter {
+    len: usize,
+}
+
+impl SizeCounter {
+    pub fn new() -> Self {
+        Self { len: 0 }
+    }
+
+    /// Bytes counted so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn add(&mut self, n: usize) -> Result<&mut Self> {
+        self.len = self.len.checked_add(n).ok_or(Error::ContainerFull)?;
+        Ok(self)
+    }
+
+    fn add_blob(&mut self, len: usize) -> Result<&mut Self> {
+        if len > u32::MAX as usize {
+            return Err(Error::BlobTooLarge(len));
+        }
+        self.add(spec::BLOB_HEADER + len)
+    }
+
+    for_each_scalar!(count_scalar, ());
+
+    pub fn str(&mut self, v: &str) -> Result<&mut Self> {
+        self.add_blob(v.len())
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) -> Result<&mut Self> {
+        self.add_blob(v.len())
+    }
+
+    pub fn record_raw(&mut self, v: &[u8]) -> Result<&mut Self> {
+        self.add_blob(v.len())
+    }
+
+    pub fn fixed16(&mut self, _v: &[u8; 16]) -> Result<&mut Self> {
+        self.add(1 + 16)
+    }
+
+    pub fn fixed32(&mut self, _v: &[u8; 32]) -> Result<&mut Self> {
+        self.add(1 + 32)
+    }
+
+    pub fn ext(&mut self, _id: u16, payload: &[u8]) -> Result<&mut Self> {
+        self.add_blob(spec::EXT_ID_LEN + payload.len())
+    }
+
+    pub fn list(&mut self) -> Result<&mut Self> {
+        self.add(spec::BLOB_HEADER)
+    }
+
+    pub fn map(&mut self) -> Result<&mut Self> {
+        self.add(spec::BLOB_HEADER)
+    }
+
+    /// Counts a map key; the value is counted by the next write.
+    pub fn key(&mut self, k: &str) -> Result<&mut Self> {
+        self.str(k)
+    }
+
+    pub fn array(&mut self, _item_tag: Tag, stride: usize) -> Result<ArrayCounter<'_>> {
+        assert!(stride > 0 && stride <= u32::MAX as usize, "invalid stride: {}", stride);
+        self.add(spec::ARRAY_HEADER)?;
+        Ok(ArrayCounter { parent: self, stride })
+    }
+
+    /// Ends a list or map.
+    pub fn finish(&mut self) -> Result<&mut Self> {
+        Ok(self)
+    }
+}
+
+/// Counts the items of an array; each is one stride.
+pub struct ArrayCounter<'a> {
+    parent: &'a mut SizeCounter,
+    stride: usize,
+}
+
+impl<'a> ArrayCounter<'a> {
+    pub fn push(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() != self.stride {
+            return Err(Error::Malformed);
+        }
+        self.parent.add(self.stride)?;
+        Ok(())
+    }
+
+    count_array_item!(bool, as_bool, bool, Tag::Bool, Bool, ());
+    count_array_item!(u8, as_u8, u8, Tag::U8, U8, ());
+    count_array_item!(i8, as_i8, i8, Tag::S8, S8, ());
+    for_each_multibyte_scalar!(count_array_item, ());
+
+    pub fn finish(self) -> Result<&'a mut SizeCounter> {
+        Ok(self.parent)
+    }
+}
@@ -0,0 +1,55 @@
+//! The neopack wire format, as constants
+//!
+//! Every value starts with a one-byte tag. Scalars follow it with their
+//! little-endian bytes, and fixed values with their raw bytes. Blobs and
+//! containers follow it with a little-endian u32 byte length and then
+//! that many bytes. An array's body starts with its item tag and u32
+//! stride, then holds untagged items of exactly that stride. An
+//! extension's body starts with its u16 extension id.
+//!
+//! These values are the format: changing one breaks every reader of data
+//! already written. The golden vectors in the tests pin them down.
+
+/// Names this encoding, for files and protocols that record one.
+pub const FORMAT: &str = "neopack";
+/// Bumped only for changes old readers can't read.
+pub const FORMAT_VERSION: u16 = 1;
+
+pub const TAG_BOOL: u8 = 0x01;
+pub const TAG_S8: u8 = 0x02;
+pub const TAG_U8: u8 = 0x03;
+pub const TAG_S16: u8 = 0x04;
+pub const TAG_U16: u8 = 0x05;
+pub const TAG_S32: u8 = 0x06;
+pub const TAG_U32: u8 = 0x07;
+pub const TAG_S64: u8 = 0x08;
+pub const TAG_U64: u8 = 0x09;
+pub const TAG_F32: u8 = 0x0A;
+pub const TAG_F64: u8 = 0x0B;
+pub const TAG_F16: u8 = 0x0C;
+pub const TAG_U128: u8 = 0x0D;
+pub const TAG_S128: u8 = 0x0E;
+
+pub const TAG_STRING: u8 = 0x10;
+pub const TAG_BYTES: u8 = 0x11;
+pub const TAG_STRUCT: u8 = 0x12;
+pub const TAG_FIXED16: u8 = 0x13;
+pub const TAG_FIXED32: u8 = 0x14;
+pub const TAG_TIMESTAMP: u8 = 0x18;
+pub const TAG_DURATION: u8 = 0x19;
+
+pub const TAG_LIST: u8 = 0x20;
+pub const TAG_MAP: u8 = 0x21;
+pub const TAG_ARRAY: u8 = 0x23;
+pub const TAG_EXT: u8 = 0x30;
+
+/// Tag and u32 byte length before a blob or container body.
+pub const BLOB_HEADER: usize = 5;
+/// Item tag and u32 stride at the start of an array body.
+pub const ARRAY_ITEM_HEADER: usize = 5;
+/// Everything before an array's first item.
+pub const ARRAY_HEADER: usize = BLOB_HEADER + ARRAY_ITEM_HEADER;
+/// Extension id at the start of an extension body.
+pub const EXT_ID_LEN: usize = 2;
+/// Largest blob or container body.
+pub const MAX_BODY: usize = u32::MAX as usize;
@@ -0,0 +1,148 @@
+//! Encoding straight into an `io::Write`
+//!
+//! `StreamEncoder` writes each top-level value to its writer as soon as it
+//! is encoded, rather than collecting the whole document in a `Vec`.
+//! Scalars go through a small scratch buffer, and strings and blobs are
+//! written from the caller's slice after their header, so large payloads
+//! are never copied. A container's length comes before its body, so
+//! containers are built in the scratch buffer by `value` and written once
+//! they are finished; the buffer is reused from one value to the next.
+
+use std::io;
+use std::io::Write;
+use super::encoder::Encoder;
+use super::macros::for_each_scalar;
+use super::spec;
+use super::types::Error;
+use super::types::Tag;
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    Neopack(Error),
+}
+
+impl From<io::Error> for StreamError {
+    fn from(err: io::Error) -> Self {
+        StreamError::Io(err)
+    }
+}
+
+impl From<Error> for StreamError {
+    fn from(err: Error) -> Self {
+        StreamError::Neopack(err)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, StreamError>;
+
+/// Generates scalar writes that go through the scratch buffer.
+macro_rules! encode_stream_scalar {
+    ($name:ident, $as_name:ident, $ty:ty, $tag:expr, $var:ident, $ctx:tt) => {
+        pub fn $name(&mut self, v: $ty) -> Result<&mut Self> {
+            self.scratch.$name(v)?;
+            self.write_scratch()
+        }
+    };
+}
+
+/// Encodes neopack values directly into a writer.
+pub struct StreamEncoder<W: Write> {
+    out: W,
+    scratch: Encoder,
+    written: u64,
+}
+
+impl<W: Write> StreamEncoder<W> {
+    pub fn new(out: W) -> Self {
+        Self { out, scratch: Encoder::new(), written: 0 }
+    }
+
+    /// Bytes written to the writer so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+
+    /// Flushes the writer and returns it.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    for_each_scalar!(encode_stream_scalar, ());
+
+    pub fn str(&mut self, v: &str) -> Result<&mut Self> {
+        self.write_blob(Tag::String, &[], v.as_bytes())
+    }
+
+    pub fn bytes(&mut self, v: &[u8]) -> Result<&mut Self> {
+        self.write_blob(Tag::Bytes, &[], v)
+    }
+
+    pub fn record_raw(&mut self, v: &[u8]) -> Result<&mut Self> {
+        self.write_blob(Tag::Struct, &[], v)
+    }
+
+    pub fn ext(&mut self, id: u16, payload: &[u8]) -> Result<&mut Self> {
+        self.write_blob(Tag::Ext, &id.to_le_bytes(), payload)
+    }
+
+    pub fn fixed16(&mut self, v: &[u8; 16]) -> Result<&mut Self> {
+        self.scratch.fixed16(v)?;
+        self.write_scratch()
+    }
+
+    pub fn fixed32(&mut self, v: &[u8; 32]) -> Result<&mut Self> {
+        self.scratch.fixed32(v)?;
+        self.write_scratch()
+    }
+
+    /// Writes the values encoded by `f`, typically one container. They
+    /// are buffered until `f` returns so container lengths can be patched.
+    pub fn value<F>(&mut self, f: F) -> Result<&mut Self>
+    where
+        F: FnOnce(&mut Encoder) -> super::types::Result<()>,
+    {
+        let result = f(&mut self.scratch);
+        if let Err(e) = result {
+            // Start afresh, dropping any partial value and recorded overflow
+            self.scratch = Encoder::new();
+            return Err(e.into());
+        }
+        self.write_scratch()
+    }
+
+    fn write_scratch(&mut self) -> Result<&mut Self> {
+        let result = self.out.write_all(&self.scratch.buf);
+        let len = self.scratch.buf.len() as u64;
+        self.scratch.buf.clear();
+        result?;
+        self.written += len;
+        Ok(self)
+    }
+
+    /// Writes a blob header, then `prefix` and `data` as its body.
+    fn write_blob(&mut self, tag: Tag, prefix: &[u8], data: &[u8]) -> Result<&mut Self> {
+        let len = prefix.len() + data.len();
+        if len > u32::MAX as usize {
+            return Err(Error::BlobTooLarge(len).into());
+        }
+        let mut header = [0; spec::BLOB_HEADER];
+        header[0] = tag as u8;
+        header[1..].copy_from_slice(&(len as u32).to_le_bytes());
+        self.out.write_all(&header)?;
+        self.out.write_all(prefix)?;
+        self.out.write_all(data)?;
+        self.written += (spec::BLOB_HEADER + len) as u64;
+        Ok(self)
+    }
+}
@@ -1,5 +1,8 @@
+#![allow(clippy::approx_constant, clippy::bool_assert_comparison, clippy::needless_borrow)]
+
 use super::*;
 use crate::neopack::types::{Tag, Error};
+use crate::neopack::spec;
 use crate::neopack::{ValueDecoder, RecordDecoder};
 
 type R<T> = Result<T>;
@@ -504,6 +507,146 @@ fn test_f64_roundtrip() -> R<()> {
     Ok(())
 }
 
+#[test]
+fn test_time_roundtrip() -> R<()> {
+    let mut enc = Encoder::new();
+    enc.timestamp(Timestamp(1_700_000_000_123_456_789))?;
+    enc.duration(Duration(-1_500))?;
+    let mut map = enc.map()?;
+    map.key("at")?.timestamp(Timestamp::UNIX_EPOCH)?;
+    map.finish()?;
+    let bytes = enc.as_bytes();
+    assert_eq!(bytes[0], Tag::Timestamp as u8);
+
+    let mut r = Decoder::new(bytes);
+    assert_eq!(r.timestamp()?, Timestamp(1_700_000_000_123_456_789));
+    assert_eq!(r.duration()?, Duration(-1_500));
+    let mut map = r.map()?;
+    let (_, at) = map.next()?.unwrap();
+    assert_eq!(at.as_timestamp()?, Timestamp(0));
+
+    // A timestamp is not a duration, or a bare integer
+    let mut r = Decoder::new(bytes);
+    assert!(matches!(r.i64(), Err(Error::TypeMismatch)));
+    Ok(())
+}
+
+#[test]
+fn test_time_conversions() -> R<()> {
+    let d = core::time::Duration::from_millis(1500);
+    assert_eq!(Duration::try_from(d)?, Duration(1_500_000_000));
+    assert_eq!(core::time::Duration::try_from(Duration(1_500_000_000))?, d);
+    assert!(matches!(core::time::Duration::try_from(Duration(-1)), Err(Error::OutOfRange)));
+    assert!(matches!(Duration::try_from(core::time::Duration::MAX), Err(Error::OutOfRange)));
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_system_time_conversions() -> R<()> {
+    use std::time::SystemTime;
+    use std::time::UNIX_EPOCH;
+
+    let before = UNIX_EPOCH - core::time::Duration::from_secs(10);
+    assert_eq!(Timestamp::try_from(before)?, Timestamp(-10_000_000_000));
+    assert_eq!(SystemTime::from(Timestamp(-10_000_000_000)), before);
+    let now = Timestamp::now()?;
+    assert_eq!(Timestamp::try_from(SystemTime::from(now))?, now);
+
+    #[cfg(feature = "chrono")]
+    {
+        let t = chrono::DateTime::<chrono::Utc>::from(now);
+        assert_eq!(Timestamp::try_from(t)?, now);
+        assert_eq!(Duration::try_from(chrono::TimeDelta::from(Duration(-5)))?, Duration(-5));
+    }
+    #[cfg(feature = "time")]
+    {
+        let t = time::OffsetDateTime::from(now);
+        assert_eq!(Timestamp::try_from(t)?, now);
+        assert_eq!(Duration::try_from(time::Duration::from(Duration(-5)))?, Duration(-5));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_f16_conversion() {
+    assert_eq!(F16::from_f32(1.0), F16(0x3c00));
+    assert_eq!(F16::from_f32(-2.0), F16(0xc000));
+    assert_eq!(F16::from_f32(65504.0), F16(0x7bff));
+    assert_eq!(F16::from_f32(65520.0), F16(0x7c00)); // rounds up to infinity
+    assert_eq!(F16::from_f32(f32::NEG_INFINITY), F16(0xfc00));
+    assert!(F16::from_f32(f32::NAN).to_f32().is_nan());
+    assert_eq!(F16::from_f32(1.0 / (1 << 24) as f32), F16(0x0001)); // smallest subnormal
+    assert_eq!(F16::from_f32(2.9e-8), F16(0x0000));
+    assert_eq!(F16::from_f32(1.0 + 1.0 / 2048.0), F16(0x3c00)); // tie to even
+    assert_eq!(F16::from_f32(1.0 + 3.0 / 2048.0), F16(0x3c02));
+
+    for bits in 0..=u16::MAX {
+        let h = F16(bits);
+        if !h.to_f32().is_nan() {
+            assert_eq!(F16::from_f32(h.to_f32()), h);
+        }
+    }
+}
+
+#[test]
+fn test_wide_scalar_roundtrip() -> R<()> {
+    let mut enc = Encoder::new();
+    enc.f16(F16::from_f32(0.5))?;
+    enc.u128(u128::MAX)?;
+    enc.i128(i128::MIN)?;
+    let mut list = enc.list()?;
+    list.u128(1 << 100)?;
+    list.f16(F16::from_f32(-1.5))?;
+    list.finish()?;
+    let bytes = enc.as_bytes();
+
+    let mut r = Decoder::new(bytes);
+    assert_eq!(r.f16()?.to_f32(), 0.5);
+    assert_eq!(r.u128()?, u128::MAX);
+    assert_eq!(r.i128()?, i128::MIN);
+    let mut list = r.list()?;
+    assert_eq!(list.next()?.unwrap().as_u128()?, 1 << 100);
+    assert_eq!(list.next()?.unwrap().as_f16()?.to_f32(), -1.5);
+    assert!(list.next()?.is_none());
+
+    let mut r = Decoder::new(bytes);
+    r.skip_value()?;
+    r.skip_value()?;
+    assert_eq!(r.value()?.as_i128()?, i128::MIN);
+    Ok(())
+}
+
+#[test]
+fn test_wide_scalar_arrays() -> R<()> {
+    let mut enc = Encoder::new();
+    let mut arr = enc.array(Tag::F16, 2)?;
+    arr.f16(F16::from_f32(0.25))?;
+    arr.f16(F16::from_f32(4.0))?;
+    arr.finish()?;
+    let mut arr = enc.array(Tag::S128, 16)?;
+    arr.i128(-7)?;
+    arr.finish()?;
+    let mut arr = enc.array(Tag::S128, 8)?;
+    arr.i64(-7)?;
+    arr.finish()?;
+    let bytes = enc.as_bytes();
+
+    let mut r = Decoder::new(bytes);
+    let mut arr = r.array()?;
+    assert_eq!(arr.remaining(), 2);
+    assert_eq!(arr.f16()?.unwrap().to_f32(), 0.25);
+    assert_eq!(arr.next()?.unwrap().as_f16()?.to_f32(), 4.0);
+    let mut arr = r.array()?;
+    assert_eq!(arr.i128()?, Some(-7));
+    assert_eq!(arr.i128()?, None);
+
+    // Stride must match the item width
+    let mut arr = r.array()?;
+    assert!(matches!(arr.i128(), Err(Error::Malformed)));
+    Ok(())
+}
+
 #[test]
 fn test_string_roundtrip() -> R<()> {
     let mut enc = Encoder::new();
@@ -541,6 +684,78 @@ fn test_struct_blob_roundtrip() -> R<()> {
     Ok(())
 }
 
+#[test]
+fn test_fixed_roundtrip() -> R<()> {
+    let uuid = [0xab; 16];
+    let hash = [7; 32];
+    let mut enc = Encoder::new();
+    enc.fixed16(&uuid)?;
+    let mut map = enc.map()?;
+    map.key("hash")?.fixed32(&hash)?;
+    map.finish()?;
+    enc.fixed32(&hash)?;
+    let bytes = enc.as_bytes();
+    // Just the tag before the 16 bytes
+    assert_eq!(bytes[17], Tag::Map as u8);
+
+    let mut r = Decoder::new(bytes);
+    assert_eq!(r.fixed16()?, &uuid);
+    let mut map = r.map()?;
+    assert_eq!(map.next()?.unwrap().1.as_fixed32()?, &hash);
+    r.skip_value()?;
+    assert_eq!(r.remaining(), 0);
+
+    // Arrays of fixed items need no per-item header at all
+    let mut enc = Encoder::new();
+    let mut arr = enc.array(Tag::Fixed32, 32)?;
+    arr.push(&hash)?;
+    arr.push(&[8; 32])?;
+    arr.finish()?;
+    let mut r = Decoder::new(enc.as_bytes());
+    let mut arr = r.array()?;
+    assert_eq!(arr.next()?.unwrap().as_fixed32()?, &hash);
+    assert_eq!(arr.next()?.unwrap().as_fixed32()?, &[8; 32]);
+
+    let mut r = Decoder::new(&bytes[..10]);
+    assert!(matches!(r.fixed16(), Err(Error::Pending(_))));
+    let mut r = Decoder::new(bytes);
+    assert!(matches!(r.fixed32(), Err(Error::TypeMismatch)));
+    Ok(())
+}
+
+#[test]
+fn test_ext_roundtrip_and_skip() -> R<()> {
+    let mut enc = Encoder::new();
+    let mut list = enc.list()?;
+    list.ext(0x0101, &[1, 2, 3])?;
+    list.ext(7, &[])?;
+    list.u8(9)?;
+    list.finish()?;
+    let bytes = enc.as_bytes();
+
+    let mut r = Decoder::new(bytes);
+    let mut list = r.list()?;
+    assert_eq!(list.next()?.unwrap().as_ext()?, (0x0101, &[1u8, 2, 3][..]));
+    assert_eq!(list.next()?.unwrap().as_ext()?, (7, &[][..]));
+    assert_eq!(list.next()?.unwrap().as_u8()?, 9);
+
+    // A reader that doesn't know the extension skips over it
+    let mut enc = Encoder::new();
+    enc.ext(42, b"opaque")?;
+    enc.u8(1)?;
+    let mut r = Decoder::new(enc.as_bytes());
+    r.skip_value()?;
+    assert_eq!(r.u8()?, 1);
+
+    let mut r = Decoder::new(enc.as_bytes());
+    assert_eq!(r.ext()?, (42, &b"opaque"[..]));
+
+    // Too short to hold an id
+    let bytes = [Tag::Ext as u8, 1, 0, 0, 0, 0];
+    assert!(matches!(Decoder::new(&bytes).value(), Err(Error::Malformed)));
+    Ok(())
+}
+
 #[test]
 fn test_list_scalars() -> R<()> {
     let mut enc = Encoder::new();
@@ -1221,3 +1436,551 @@ fn test_array_of_structs_layout() -> R<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_iterator_pipelines() -> R<()> {
+    let mut enc = Encoder::new();
+    enc.list_from_iter(["a", "bb", "ccc"], |list, s| { list.str(s)?; Ok(()) })?;
+    let mut map = enc.map()?;
+    map.key("x")?.u32(1)?;
+    map.key("y")?.u32(2)?;
+    map.finish()?;
+    let mut arr = enc.array(Tag::U16, 2)?;
+    for v in [3, 4, 5] {
+        arr.u16(v)?;
+    }
+    arr.finish()?;
+    let mut list = enc.list()?;
+    list.u8(1)?.extend_with(2..4u8, |list, v| { list.u8(v)?; Ok(()) })?;
+    list.finish()?;
+    let bytes = enc.into_bytes();
+
+    let mut r = Decoder::new(&bytes);
+    let lens = r.list()?
+        .map(|item| Ok(item?.as_str()?.len()))
+        .collect::<R<Vec<_>>>()?;
+    assert_eq!(lens, vec![1, 2, 3]);
+
+    let entries = r.map()?
+        .map(|entry| { let (k, v) = entry?; Ok((k, v.as_u32()?)) })
+        .collect::<R<Vec<_>>>()?;
+    assert_eq!(entries, vec![("x", 1), ("y", 2)]);
+
+    let sum: u16 = r.array()?
+        .map(|item| item?.as_u16())
+        .sum::<R<u16>>()?;
+    assert_eq!(sum, 12);
+
+    let items = r.list()?
+        .map(|item| item?.as_u8())
+        .collect::<R<Vec<_>>>()?;
+    assert_eq!(items, vec![1, 2, 3]);
+
+    // A malformed item ends the iteration after one error
+    let bad = [spec::TAG_LIST, 3, 0, 0, 0, spec::TAG_U8, 7, 0xFF];
+    let results: Vec<_> = Decoder::new(&bad).list()?.collect();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].as_ref().unwrap().as_u8()?, 7);
+    assert!(results[1].is_err());
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_stream_encoder_matches_encoder() -> R<()> {
+    let big = vec![7u8; 4096];
+    let mut enc = Encoder::new();
+    enc.u32(1)?.str("hello")?.bytes(&big)?.ext(9, b"xy")?.fixed16(&[1; 16])?;
+    let mut list = enc.list()?;
+    list.bool(true)?.f16(F16::from_f32(1.5))?;
+    list.finish()?;
+    let expected = enc.into_bytes();
+
+    let mut stream = StreamEncoder::new(Vec::new());
+    stream.u32(1).unwrap().str("hello").unwrap().bytes(&big).unwrap();
+    stream.ext(9, b"xy").unwrap().fixed16(&[1; 16]).unwrap();
+    stream.value(|enc| {
+        let mut list = enc.list()?;
+        list.bool(true)?.f16(F16::from_f32(1.5))?;
+        list.finish()?;
+        Ok(())
+    }).unwrap();
+    assert_eq!(stream.bytes_written(), expected.len() as u64);
+    assert_eq!(stream.into_inner().unwrap(), expected);
+
+    // A failed value writes nothing
+    let mut stream = StreamEncoder::new(Vec::new());
+    let result = stream.value(|enc| {
+        enc.list()?.u8(1)?;
+        Err(Error::Malformed)
+    });
+    assert!(matches!(result, Err(StreamError::Neopack(Error::Malformed))));
+    stream.u8(2).unwrap();
+    assert_eq!(stream.into_inner().unwrap(), vec![spec::TAG_U8, 2]);
+    Ok(())
+}
+
+#[test]
+fn test_size_counter_is_exact() -> R<()> {
+    // The same writes, once against each
+    macro_rules! write_doc {
+        ($w:expr) => {{
+            let w = $w;
+            w.u32(1)?.str("hello")?.bytes(&[0; 300])?.f16(F16::from_f32(0.5))?;
+            w.timestamp(Timestamp(5))?.fixed32(&[2; 32])?.ext(3, b"abc")?.i128(-1)?;
+            #[allow(unused_mut)]
+            let mut list = w.list()?;
+            list.bool(true)?.u8(2)?.str("x")?;
+            #[allow(unused_mut)]
+            let mut map = list.map()?;
+            map.key("k")?.u64(7)?;
+            map.key("nested")?.list()?.finish()?;
+            map.finish()?;
+            list.finish()?;
+            let mut arr = w.array(Tag::U32, 4)?;
+            arr.u32(1)?;
+            arr.u32(2)?;
+            arr.push(&[0; 4])?;
+            arr.finish()?;
+        }};
+    }
+
+    let mut counter = SizeCounter::new();
+    write_doc!(&mut counter);
+
+    let mut enc = Encoder::with_capacity(counter.len());
+    let cap = enc.capacity();
+    write_doc!(&mut enc);
+    assert_eq!(enc.as_bytes().len(), counter.len());
+    assert_eq!(enc.capacity(), cap);
+    Ok(())
+}
+
+#[test]
+fn test_array_extend_from_slice() -> R<()> {
+    let values = [1u32, 0xDEADBEEF, 7, u32::MAX];
+    let mut one_by_one = Encoder::new();
+    let mut arr = one_by_one.array(Tag::U32, 4)?;
+    for v in values {
+        arr.u32(v)?;
+    }
+    arr.finish()?;
+
+    let mut bulk = Encoder::new();
+    let mut arr = bulk.array(Tag::U32, 4)?;
+    arr.extend_from_slice_u32(&values[..1])?;
+    arr.extend_from_slice_u32(&values[1..])?;
+    assert!(matches!(arr.extend_from_slice_u16(&[1]), Err(Error::Malformed)));
+    arr.finish()?;
+    assert_eq!(bulk.as_bytes(), one_by_one.as_bytes());
+
+    let mut enc = Encoder::new();
+    let mut arr = enc.array(Tag::F64, 8)?;
+    arr.extend_from_slice_f64(&[1.5, -2.25])?;
+    arr.finish()?;
+    let mut arr = enc.array(Tag::S8, 1)?;
+    arr.extend_from_slice_i8(&[-1, 2])?;
+    arr.finish()?;
+    let mut arr = enc.array(Tag::Timestamp, 8)?;
+    arr.extend_from_slice_timestamp(&[Timestamp(-5), Timestamp(9)])?;
+    arr.finish()?;
+    let bytes = enc.into_bytes();
+
+    let mut r = Decoder::new(&bytes);
+    let floats = r.array()?.map(|item| item?.as_f64()).collect::<R<Vec<_>>>()?;
+    assert_eq!(floats, vec![1.5, -2.25]);
+    let signed = r.array()?.map(|item| item?.as_i8()).collect::<R<Vec<_>>>()?;
+    assert_eq!(signed, vec![-1, 2]);
+    let times = r.array()?.map(|item| item?.as_timestamp()).collect::<R<Vec<_>>>()?;
+    assert_eq!(times, vec![Timestamp(-5), Timestamp(9)]);
+    Ok(())
+}
+
+#[test]
+fn test_record_layout_checks() -> R<()> {
+    const POINT: RecordLayout<'static> = RecordLayout::new(&[Tag::U32, Tag::F32, Tag::U8]);
+    assert_eq!(POINT.stride(), 9);
+
+    let mut enc = Encoder::new();
+    let mut arr = enc.array(Tag::Struct, POINT.stride())?;
+    for i in 0..2 {
+        let mut rec = arr.record_with(&POINT)?;
+        rec.u32(i)?.f32(0.5)?.u8(7)?;
+        rec.finish()?;
+    }
+    arr.finish()?;
+    let mut rec = enc.record_with(&POINT)?;
+    rec.u32(3)?.f32(2.5)?.u8(9)?;
+    rec.finish()?;
+    let bytes = enc.into_bytes();
+
+    let mut r = Decoder::new(&bytes);
+    let mut arr = r.array()?;
+    for _ in 0..2 {
+        let Some(ValueDecoder::Struct(item)) = arr.next()? else { panic!("expected a struct") };
+        let mut rec = RecordDecoder::with_layout(item, &POINT)?;
+        rec.u32()?;
+        rec.f32()?;
+        rec.u8()?;
+    }
+    let mut rec = r.record_with(&POINT)?;
+    assert_eq!((rec.u32()?, rec.f32()?, rec.u8()?), (3, 2.5, 9));
+
+    // Drift between writer and reader is caught at the first bad field
+    let mut enc = Encoder::new();
+    let mut rec = enc.record_with(&POINT)?;
+    rec.u32(1)?;
+    assert!(matches!(
+        rec.u64(2),
+        Err(Error::LayoutMismatch { field: 1, expected: Some(Tag::F32), found: Some(Tag::U64) })
+    ));
+    assert!(matches!(
+        rec.finish(),
+        Err(Error::LayoutMismatch { field: 1, expected: Some(Tag::F32), found: None })
+    ));
+
+    let mut enc = Encoder::new();
+    let mut arr = enc.array(Tag::Struct, 8)?;
+    assert!(matches!(arr.record_with(&POINT), Err(Error::LayoutSize { expected: 9, found: 8 })));
+    drop(arr);
+
+    let bytes = [1, 0, 0, 0, 0, 0, 0, 0x3F, 2];
+    let mut rec = RecordDecoder::with_layout(&bytes, &POINT)?;
+    rec.u32()?;
+    assert!(matches!(
+        rec.i32(),
+        Err(Error::LayoutMismatch { field: 1, expected: Some(Tag::F32), found: Some(Tag::S32) })
+    ));
+    rec.f32()?;
+    rec.u8()?;
+    assert!(matches!(RecordDecoder::with_layout(&bytes[..8], &POINT), Err(Error::LayoutSize { .. })));
+    Ok(())
+}
+
+#[test]
+fn test_generic_writers() -> R<()> {
+    fn fields<W: ScalarWriter>(w: &mut W) -> R<()> {
+        w.u32(7)?.bool(true)?.f64(0.5)?;
+        Ok(())
+    }
+    fn labelled<W: ValueWriter>(w: &mut W, label: &str) -> R<()> {
+        let mut list = w.list()?;
+        list.str(label)?;
+        fields(&mut list)?;
+        list.finish()?;
+        Ok(())
+    }
+
+    let mut enc = Encoder::new();
+    labelled(&mut enc, "root")?;
+    let mut map = enc.map()?;
+    labelled(&mut map.key("entry")?, "value")?;
+    map.finish()?;
+    let mut rec = enc.record()?;
+    fields(&mut rec)?;
+    rec.finish()?;
+    let bytes = enc.into_bytes();
+
+    let mut counter = SizeCounter::new();
+    fields(&mut counter)?;
+    assert_eq!(counter.len(), 5 + 2 + 9);
+
+    let mut r = Decoder::new(&bytes);
+    let mut list = r.list()?;
+    assert_eq!(list.next()?.unwrap().as_str()?, "root");
+    assert_eq!(list.next()?.unwrap().as_u32()?, 7);
+    let mut map = r.map()?;
+    let (key, ValueDecoder::List(mut list)) = map.next()?.unwrap() else { panic!("expected a list") };
+    assert_eq!((key, list.next()?.unwrap().as_str()?), ("entry", "value"));
+    let mut rec = r.record()?;
+    assert_eq!((rec.u32()?, rec.bool()?, rec.f64()?), (7, true, 0.5));
+    Ok(())
+}
+
+#[test]
+fn test_scopes_patch_on_finish_and_drop() -> R<()> {
+    let mut enc = Encoder::new();
+    let mut outer = enc.list()?;
+    {
+        let mut inner = outer.list()?;
+        inner.u8(1)?;
+        // Dropped without finish; the length is still patched
+    }
+    outer.u8(2)?;
+    let enc = outer.finish()?;
+    assert!(enc.flush().is_ok());
+    let bytes = enc.as_bytes().to_vec();
+
+    let mut r = Decoder::new(&bytes);
+    let mut outer = r.list()?;
+    let Some(ValueDecoder::List(mut inner)) = outer.next()? else { panic!("expected a list") };
+    assert_eq!(inner.next()?.unwrap().as_u8()?, 1);
+    assert_eq!(outer.next()?.unwrap().as_u8()?, 2);
+    assert!(outer.next()?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_container_size_limit() -> R<()> {
+    // A small limit stands in for the 4GB the length prefix allows
+    let mut enc = Encoder::new();
+    enc.set_max_container(16);
+    assert_eq!(enc.max_container(), 16);
+    let mut list = enc.list()?;
+    list.bytes(&[0; 11])?;
+    list.finish()?;
+
+    let before = enc.as_bytes().to_vec();
+
+    // A container over the limit is removed, and the encoder stays usable
+    let mut list = enc.list()?;
+    list.bytes(&[0; 12])?;
+    assert!(matches!(list.finish(), Err(Error::ContainerTooLarge(17))));
+    assert_eq!(enc.as_bytes(), before);
+    enc.u8(7)?;
+    assert_eq!(enc.flush()?.len(), before.len() + 2);
+
+    // An inner container dropped over the limit fails the outer finish
+    let mut enc = Encoder::new();
+    enc.set_max_container(16);
+    let mut outer = enc.list()?;
+    {
+        let mut inner = outer.list()?;
+        inner.bytes(&[0; 12])?;
+    }
+    assert!(matches!(outer.finish(), Err(Error::ContainerTooLarge(17))));
+    assert!(enc.is_empty());
+
+    // With nothing enclosing it, the next flush reports it, once
+    {
+        let mut list = enc.list()?;
+        list.bytes(&[0; 12])?;
+    }
+    assert!(matches!(enc.flush(), Err(Error::ContainerTooLarge(17))));
+    assert!(enc.flush()?.is_empty());
+
+    let mut enc = Encoder::new();
+    enc.set_max_container(usize::MAX);
+    assert_eq!(enc.max_container(), u32::MAX as usize);
+    Ok(())
+}
+
+#[test]
+#[ignore = "allocates over 4 GiB"]
+fn test_container_size_limit_at_u32_max() -> R<()> {
+    // The blob and its header come to one byte over what a length prefix
+    // can describe
+    let blob = vec![0u8; u32::MAX as usize - spec::BLOB_HEADER + 1];
+    let mut enc = Encoder::with_capacity(u32::MAX as usize + 16);
+    enc.u8(1)?;
+    let mut list = enc.list()?;
+    list.bytes(&blob)?;
+    assert!(matches!(list.finish(), Err(Error::ContainerTooLarge(len)) if len == u32::MAX as usize + 1));
+    assert_eq!(enc.len(), 2);
+
+    let mut list = enc.list()?;
+    list.bytes(&blob[1..])?;
+    list.finish()?;
+    assert_eq!(enc.len(), 2 + spec::BLOB_HEADER + u32::MAX as usize);
+    assert_eq!(enc.as_bytes()[3..7], u32::MAX.to_le_bytes());
+    Ok(())
+}
+
+#[test]
+fn test_dump_tree() -> R<()> {
+    let mut enc = Encoder::new();
+    let mut map = enc.map()?;
+    map.key("name")?.str("hello")?;
+    let mut list = map.key("items")?.list()?;
+    list.u8(1)?;
+    list.bytes(&[0xab; 20])?;
+    list.finish()?;
+    let mut arr = map.key("xs")?.array(Tag::U16, 2)?;
+    arr.u16(7)?;
+    arr.u16(8)?;
+    arr.finish()?;
+    map.finish()?;
+    enc.bool(true)?;
+    let bytes = enc.as_bytes();
+
+    let expected = "\
+000000 Map len=82
+00000e   \"name\": String len=5 \"hello\"
+000022   \"items\": List len=27
+000027     U8 1
+000029     Bytes len=20 abababababababababababababababab…
+000049   \"xs\": Array len=9 item=U16 stride=2 count=2
+000053     U16 7
+000055     U16 8
+000057 Bool true
+";
+    assert_eq!(dump(bytes), expected);
+
+    // A truncated message dumps up to the damage
+    let cut = dump(&bytes[..bytes.len() - 1]);
+    assert!(cut.ends_with("000057 <error: Pending(1)>\n"));
+
+    let mut r = Decoder::new(bytes);
+    let mut map = r.map()?;
+    map.next()?;
+    let (_, items) = map.next()?.unwrap();
+    assert_eq!(
+        items.to_string(),
+        "List\n000000   U8 1\n000002   Bytes len=20 abababababababababababababababab…\n",
+    );
+    Ok(())
+}
+
+#[test]
+fn test_diff_documents() -> R<()> {
+    let doc = |name: &str, tags: &[&str], counts: &[u32], extra: bool| -> R<Vec<u8>> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        map.key("name")?.str(name)?;
+        let mut list = map.key("tags")?.list()?;
+        for tag in tags {
+            list.str(tag)?;
+        }
+        list.finish()?;
+        let mut arr = map.key("counts")?.array(Tag::U32, 4)?;
+        for count in counts {
+            arr.u32(*count)?;
+        }
+        arr.finish()?;
+        if extra {
+            map.key("extra")?.bool(true)?;
+        }
+        map.finish()?;
+        Ok(enc.into_bytes())
+    };
+
+    let old = doc("a", &["x", "y"], &[1, 2], true)?;
+    assert!(diff(&old, &old)?.is_empty());
+
+    let new = doc("b", &["x"], &[1, 3, 4], false)?;
+    let entries = diff(&old, &new)?;
+    let lines: Vec<_> = entries.iter().map(|entry| entry.to_string()).collect();
+    assert_eq!(lines, vec![
+        "~ /0/name: String len=1 \"a\" -> String len=1 \"b\"",
+        "- /0/tags/1: String len=1 \"y\"",
+        "~ /0/counts/1: U32 2 -> U32 3",
+        "+ /0/counts/2: U32 4",
+        "- /0/extra: Bool true",
+    ]);
+    assert_eq!(entries[2].path, vec![PathSegment::Index(0), PathSegment::Key("counts"), PathSegment::Index(1)]);
+    assert_eq!(entries[2].new.as_ref().unwrap().as_u32()?, 3);
+    assert_eq!(entries[3].kind(), DiffKind::Added);
+
+    // A value that changes kind is replaced whole, and new top-level
+    // values are additions
+    let mut enc = Encoder::new();
+    enc.u8(1)?;
+    enc.u8(2)?;
+    let entries = diff(&old, enc.as_bytes())?;
+    assert_eq!(entries[0].to_string(), "~ /0: Map -> U8 1");
+    assert_eq!(entries[1].to_string(), "+ /1: U8 2");
+
+    assert!(diff(&old, &new[..new.len() - 1]).is_err());
+    Ok(())
+}
+
+// ==== GOLDEN VECTORS ====
+// Exact bytes for representative values. If one of these fails, the wire
+// format changed: data already written won't read back.
+
+type Vector = (&'static str, fn(&mut Encoder) -> R<()>, &'static str);
+
+const GOLDEN: &[Vector] = &[
+    ("bool", |e| { e.bool(true)?; e.bool(false)?; Ok(()) }, "01010100"),
+    ("u8 i8", |e| { e.u8(0x2a)?; e.i8(-1)?; Ok(()) }, "032a02ff"),
+    ("u16 i16", |e| { e.u16(0x1234)?; e.i16(-2)?; Ok(()) }, "05341204feff"),
+    ("u32 i32", |e| { e.u32(0xdeadbeef)?; e.i32(i32::MIN)?; Ok(()) }, "07efbeadde0600000080"),
+    ("u64 i64", |e| { e.u64(1)?; e.i64(-1)?; Ok(()) }, "09010000000000000008ffffffffffffffff"),
+    ("u128 i128", |e| { e.u128(1)?; e.i128(-1)?; Ok(()) }, "0d010000000000000000000000000000000effffffffffffffffffffffffffffffff"),
+    ("f16", |e| { e.f16(F16::from_f32(1.0))?; Ok(()) }, "0c003c"),
+    ("f32", |e| { e.f32(1.5)?; Ok(()) }, "0a0000c03f"),
+    ("f64", |e| { e.f64(-0.0)?; Ok(()) }, "0b0000000000000080"),
+    ("timestamp", |e| { e.timestamp(Timestamp(1_000_000_000))?; Ok(()) }, "1800ca9a3b00000000"),
+    ("duration", |e| { e.duration(Duration(-1))?; Ok(()) }, "19ffffffffffffffff"),
+    ("string", |e| { e.str("hi")?; e.str("")?; Ok(()) }, "100200000068691000000000"),
+    ("utf8 string", |e| { e.str("é")?; Ok(()) }, "1002000000c3a9"),
+    ("bytes", |e| { e.bytes(&[1, 2])?; Ok(()) }, "11020000000102"),
+    ("struct raw", |e| { e.record_raw(&[9])?; Ok(()) }, "120100000009"),
+    ("record", |e| {
+        let mut r = e.record()?;
+        r.u16(1)?;
+        r.u32(2)?;
+        r.finish()?;
+        Ok(())
+    }, "1206000000010002000000"),
+    ("fixed16", |e| { e.fixed16(&[0x11; 16])?; Ok(()) }, "1311111111111111111111111111111111"),
+    ("fixed32", |e| { e.fixed32(&[0x22; 32])?; Ok(()) }, "142222222222222222222222222222222222222222222222222222222222222222"),
+    ("ext", |e| { e.ext(7, &[0xff])?; Ok(()) }, "30030000000700ff"),
+    ("empty list", |e| { e.list()?.finish()?; Ok(()) }, "2000000000"),
+    ("list", |e| {
+        let mut l = e.list()?;
+        l.u8(1)?;
+        l.str("a")?;
+        l.finish()?;
+        Ok(())
+    }, "20080000000301100100000061"),
+    ("empty map", |e| { e.map()?.finish()?; Ok(()) }, "2100000000"),
+    ("map", |e| {
+        let mut m = e.map()?;
+        m.key("k")?.bool(true)?;
+        m.key("n")?.u32(5)?;
+        m.finish()?;
+        Ok(())
+    }, "211300000010010000006b010110010000006e0705000000"),
+    ("array", |e| {
+        let mut a = e.array(Tag::U16, 2)?;
+        a.u16(1)?;
+        a.u16(2)?;
+        a.finish()?;
+        Ok(())
+    }, "2309000000050200000001000200"),
+    ("array of records", |e| {
+        let mut a = e.array(Tag::Struct, 6)?;
+        let mut r = a.record();
+        r.u16(1)?.u32(2)?;
+        r.finish()?;
+        a.finish()?;
+        Ok(())
+    }, "230b0000001206000000010002000000"),
+    ("nested document", |e| {
+        let mut m = e.map()?;
+        m.key("name")?.str("doc")?;
+        let mut l = m.key("tags")?.list()?;
+        l.str("x")?;
+        let mut inner = l.map()?;
+        inner.key("id")?.fixed16(&[0; 16])?;
+        inner.finish()?;
+        l.finish()?;
+        m.key("at")?.timestamp(Timestamp(0))?;
+        m.finish()?;
+        Ok(())
+    }, concat!(
+        "2152000000",
+        "10040000006e616d65", "1003000000646f63",
+        "10040000007461677320230000001001000000782118000000",
+        "1002000000696413", "00000000000000000000000000000000",
+        "10020000006174", "180000000000000000",
+    )),
+];
+
+#[test]
+fn test_golden_vectors() -> R<()> {
+    for (name, build, expected) in GOLDEN {
+        let mut enc = Encoder::new();
+        build(&mut enc)?;
+        let bytes = enc.into_bytes();
+        assert_eq!(crate::hex::encode(&bytes), *expected, "golden vector {} changed", name);
+
+        // Every vector decodes back
+        let dumped = dump(&bytes);
+        assert!(!dumped.contains("<error"), "golden vector {} doesn't decode:\n{}", name, dumped);
+        Encoder::from_bytes(bytes)?;
+    }
+    assert_eq!(spec::FORMAT_VERSION, 1);
+    Ok(())
+}
@@ -1,52 +1,298 @@
 //! Core types for neopack binary format
 
+use crate::neopack::spec;
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Tag {
-    Bool = 0x01,
-    S8 = 0x02,
-    U8 = 0x03,
-    S16 = 0x04,
-    U16 = 0x05,
-    S32 = 0x06,
-    U32 = 0x07,
-    S64 = 0x08,
-    U64 = 0x09,
-    F32 = 0x0A,
-    F64 = 0x0B,
-    String = 0x10,
-    Bytes = 0x11,
-    Struct = 0x12,
-    List = 0x20,
-    Map = 0x21,
-    Array = 0x23,
+    Bool = spec::TAG_BOOL,
+    S8 = spec::TAG_S8,
+    U8 = spec::TAG_U8,
+    S16 = spec::TAG_S16,
+    U16 = spec::TAG_U16,
+    S32 = spec::TAG_S32,
+    U32 = spec::TAG_U32,
+    S64 = spec::TAG_S64,
+    U64 = spec::TAG_U64,
+    F32 = spec::TAG_F32,
+    F64 = spec::TAG_F64,
+    F16 = spec::TAG_F16,
+    U128 = spec::TAG_U128,
+    S128 = spec::TAG_S128,
+    String = spec::TAG_STRING,
+    Bytes = spec::TAG_BYTES,
+    Struct = spec::TAG_STRUCT,
+    /// 16 bytes with no length prefix, such as a UUID.
+    Fixed16 = spec::TAG_FIXED16,
+    /// 32 bytes with no length prefix, such as a hash or public key.
+    Fixed32 = spec::TAG_FIXED32,
+    /// Nanoseconds since the Unix epoch; see `Timestamp`.
+    Timestamp = spec::TAG_TIMESTAMP,
+    /// Signed nanoseconds; see `Duration`.
+    Duration = spec::TAG_DURATION,
+    List = spec::TAG_LIST,
+    Map = spec::TAG_MAP,
+    Array = spec::TAG_ARRAY,
+    /// Third-party type: a u16 extension id, then its payload.
+    Ext = spec::TAG_EXT,
 }
 
 impl Tag {
     pub const fn from_u8(b: u8) -> Option<Self> {
         match b {
-            0x01 => Some(Tag::Bool),
-            0x02 => Some(Tag::S8),
-            0x03 => Some(Tag::U8),
-            0x04 => Some(Tag::S16),
-            0x05 => Some(Tag::U16),
-            0x06 => Some(Tag::S32),
-            0x07 => Some(Tag::U32),
-            0x08 => Some(Tag::S64),
-            0x09 => Some(Tag::U64),
-            0x0A => Some(Tag::F32),
-            0x0B => Some(Tag::F64),
-            0x10 => Some(Tag::String),
-            0x11 => Some(Tag::Bytes),
-            0x12 => Some(Tag::Struct),
-            0x20 => Some(Tag::List),
-            0x21 => Some(Tag::Map),
-            0x23 => Some(Tag::Array),
+            spec::TAG_BOOL => Some(Tag::Bool),
+            spec::TAG_S8 => Some(Tag::S8),
+            spec::TAG_U8 => Some(Tag::U8),
+            spec::TAG_S16 => Some(Tag::S16),
+            spec::TAG_U16 => Some(Tag::U16),
+            spec::TAG_S32 => Some(Tag::S32),
+            spec::TAG_U32 => Some(Tag::U32),
+            spec::TAG_S64 => Some(Tag::S64),
+            spec::TAG_U64 => Some(Tag::U64),
+            spec::TAG_F32 => Some(Tag::F32),
+            spec::TAG_F64 => Some(Tag::F64),
+            spec::TAG_F16 => Some(Tag::F16),
+            spec::TAG_U128 => Some(Tag::U128),
+            spec::TAG_S128 => Some(Tag::S128),
+            spec::TAG_STRING => Some(Tag::String),
+            spec::TAG_BYTES => Some(Tag::Bytes),
+            spec::TAG_STRUCT => Some(Tag::Struct),
+            spec::TAG_FIXED16 => Some(Tag::Fixed16),
+            spec::TAG_FIXED32 => Some(Tag::Fixed32),
+            spec::TAG_TIMESTAMP => Some(Tag::Timestamp),
+            spec::TAG_DURATION => Some(Tag::Duration),
+            spec::TAG_LIST => Some(Tag::List),
+            spec::TAG_MAP => Some(Tag::Map),
+            spec::TAG_ARRAY => Some(Tag::Array),
+            spec::TAG_EXT => Some(Tag::Ext),
+            _ => None,
+        }
+    }
+
+    /// Bytes in an untagged value of this type, for the scalars that can
+    /// be array items or record fields.
+    pub const fn scalar_size(self) -> Option<usize> {
+        match self {
+            Tag::Bool | Tag::U8 | Tag::S8 => Some(1),
+            Tag::U16 | Tag::S16 | Tag::F16 => Some(2),
+            Tag::U32 | Tag::S32 | Tag::F32 => Some(4),
+            Tag::U64 | Tag::S64 | Tag::F64 | Tag::Timestamp | Tag::Duration => Some(8),
+            Tag::U128 | Tag::S128 => Some(16),
             _ => None,
         }
     }
 }
 
+/// An IEEE 754 half-precision float, kept as its raw bits.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct F16(pub u16);
+
+impl F16 {
+    /// Rounds to the nearest half, ties to even.
+    pub fn from_f32(v: f32) -> Self {
+        let x = v.to_bits();
+        let sign = ((x >> 16) & 0x8000) as u16;
+        let exp = ((x >> 23) & 0xff) as i32;
+        let man = x & 0x7f_ffff;
+
+        if exp == 0xff {
+            let nan = if man != 0 { 0x0200 } else { 0 };
+            return F16(sign | 0x7c00 | nan);
+        }
+        let e = exp - 127 + 15;
+        if e >= 0x1f {
+            return F16(sign | 0x7c00);
+        }
+        if e <= 0 {
+            // Subnormal, or too small for even that
+            if e < -10 { return F16(sign); }
+            let man = man | 0x80_0000;
+            let shift = (14 - e) as u32;
+            let half = man >> shift;
+            let rem = man & ((1 << shift) - 1);
+            let halfway = 1 << (shift - 1);
+            let round = rem > halfway || (rem == halfway && half & 1 == 1);
+            return F16(sign | (half + round as u32) as u16);
+        }
+
+        // A carry out of the mantissa bumps the exponent, up to infinity
+        let half = ((e as u32) << 10) | (man >> 13);
+        let rem = man & 0x1fff;
+        let round = rem > 0x1000 || (rem == 0x1000 && half & 1 == 1);
+        F16(sign | (half + round as u32) as u16)
+    }
+
+    /// Exact; every half is representable as an f32.
+    pub fn to_f32(self) -> f32 {
+        let h = self.0 as u32;
+        let sign = (h & 0x8000) << 16;
+        let exp = (h >> 10) & 0x1f;
+        let man = h & 0x3ff;
+        match exp {
+            0x1f => f32::from_bits(sign | 0x7f80_0000 | (man << 13)),
+            0 => {
+                let v = man as f32 / (1 << 24) as f32;
+                if sign != 0 { -v } else { v }
+            }
+            _ => f32::from_bits(sign | ((exp + 112) << 23) | (man << 13)),
+        }
+    }
+
+    pub const fn to_le_bytes(self) -> [u8; 2] {
+        self.0.to_le_bytes()
+    }
+
+    pub const fn from_le_bytes(bytes: [u8; 2]) -> Self {
+        F16(u16::from_le_bytes(bytes))
+    }
+}
+
+/// A point in time: signed nanoseconds since the Unix epoch, UTC.
+///
+/// This is the only encoding of a time, so equal instants always encode
+/// to equal bytes. It covers 1677 to 2262.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Timestamp(pub i64);
+
+/// A signed span of time in nanoseconds; about 292 years either way.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Duration(pub i64);
+
+impl Timestamp {
+    pub const UNIX_EPOCH: Timestamp = Timestamp(0);
+
+    /// The system clock's time. Fails with `OutOfRange` if the clock is
+    /// set outside the years a timestamp covers.
+    #[cfg(feature = "std")]
+    pub fn now() -> Result<Self> {
+        Self::try_from(std::time::SystemTime::now())
+    }
+
+    pub const fn to_le_bytes(self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+
+    pub const fn from_le_bytes(bytes: [u8; 8]) -> Self {
+        Timestamp(i64::from_le_bytes(bytes))
+    }
+}
+
+impl Duration {
+    pub const fn to_le_bytes(self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+
+    pub const fn from_le_bytes(bytes: [u8; 8]) -> Self {
+        Duration(i64::from_le_bytes(bytes))
+    }
+}
+
+impl TryFrom<core::time::Duration> for Duration {
+    type Error = Error;
+
+    fn try_from(d: core::time::Duration) -> Result<Self> {
+        i64::try_from(d.as_nanos()).map(Duration).map_err(|_| Error::OutOfRange)
+    }
+}
+
+impl TryFrom<Duration> for core::time::Duration {
+    type Error = Error;
+
+    /// Fails for negative durations.
+    fn try_from(d: Duration) -> Result<Self> {
+        u64::try_from(d.0).map(core::time::Duration::from_nanos).map_err(|_| Error::OutOfRange)
+    }
+}
+
+#[cfg(feature = "std")]
+impl TryFrom<std::time::SystemTime> for Timestamp {
+    type Error = Error;
+
+    fn try_from(t: std::time::SystemTime) -> Result<Self> {
+        let nanos = match t.duration_since(std::time::UNIX_EPOCH) {
+            Ok(after) => i64::try_from(after.as_nanos()).ok(),
+            Err(before) => i64::try_from(before.duration().as_nanos()).ok().map(|n| -n),
+        };
+        nanos.map(Timestamp).ok_or(Error::OutOfRange)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Timestamp> for std::time::SystemTime {
+    fn from(t: Timestamp) -> Self {
+        let offset = core::time::Duration::from_nanos(t.0.unsigned_abs());
+        if t.0 < 0 { std::time::UNIX_EPOCH - offset } else { std::time::UNIX_EPOCH + offset }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<chrono::DateTime<chrono::Utc>> for Timestamp {
+    type Error = Error;
+
+    fn try_from(t: chrono::DateTime<chrono::Utc>) -> Result<Self> {
+        t.timestamp_nanos_opt().map(Timestamp).ok_or(Error::OutOfRange)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<Timestamp> for chrono::DateTime<chrono::Utc> {
+    fn from(t: Timestamp) -> Self {
+        chrono::DateTime::from_timestamp_nanos(t.0)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<chrono::TimeDelta> for Duration {
+    type Error = Error;
+
+    fn try_from(d: chrono::TimeDelta) -> Result<Self> {
+        d.num_nanoseconds().map(Duration).ok_or(Error::OutOfRange)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<Duration> for chrono::TimeDelta {
+    fn from(d: Duration) -> Self {
+        chrono::TimeDelta::nanoseconds(d.0)
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<time::OffsetDateTime> for Timestamp {
+    type Error = Error;
+
+    fn try_from(t: time::OffsetDateTime) -> Result<Self> {
+        i64::try_from(t.unix_timestamp_nanos()).map(Timestamp).map_err(|_| Error::OutOfRange)
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<Timestamp> for time::OffsetDateTime {
+    fn from(t: Timestamp) -> Self {
+        time::OffsetDateTime::from_unix_timestamp_nanos(t.0 as i128)
+            .expect("i64 nanoseconds are within time's range")
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<time::Duration> for Duration {
+    type Error = Error;
+
+    fn try_from(d: time::Duration) -> Result<Self> {
+        i64::try_from(d.whole_nanoseconds()).map(Duration).map_err(|_| Error::OutOfRange)
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<Duration> for time::Duration {
+    fn from(d: Duration) -> Self {
+        time::Duration::nanoseconds(d.0)
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
     Pending(usize),
@@ -56,11 +302,21 @@ pub enum Error {
     Malformed,
     BlobTooLarge(usize),
     ContainerFull,
+    /// A container body of this many bytes is over the encoder's limit.
+    ContainerTooLarge(usize),
     SeekBeforeBuffer,
     SeekAfterBuffer,
     ScopeOpen,
     PositionFreed,
     OutOfBounds,
+    /// A time or duration doesn't fit the other representation.
+    OutOfRange,
+    /// A record field differs from its `RecordLayout`. `expected` is
+    /// `None` past the last field, and `found` is `None` if the record
+    /// ended before the field.
+    LayoutMismatch { field: usize, expected: Option<Tag>, found: Option<Tag> },
+    /// A record's length differs from its layout's stride.
+    LayoutSize { expected: usize, found: usize },
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
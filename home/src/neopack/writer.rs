@@ -0,0 +1,165 @@
+//! Traits over the encoders, for code generic in where it writes
+//!
+//! Every encoder has the same inherent write methods, but as inherent
+//! methods they can't be named by generic code. `ScalarWriter` covers the
+//! scalars, which records can hold too, and `ValueWriter` adds blobs and
+//! containers. A serializer written against them can target the root
+//! encoder, a list, a map value, or a record alike:
+//!
+//! ```
+//! use home::neopack::{Encoder, Result, ValueWriter};
+//!
+//! fn point<W: ValueWriter>(w: &mut W, x: i32, y: i32) -> Result<()> {
+//!     let mut list = w.list()?;
+//!     list.i32(x)?.i32(y)?;
+//!     list.finish()?;
+//!     Ok(())
+//! }
+//!
+//! let mut enc = Encoder::new();
+//! point(&mut enc, 1, 2)?;
+//! let mut map = enc.map()?;
+//! point(&mut map.key("origin")?, 0, 0)?;
+//! map.finish()?;
+//! # Ok::<(), home::neopack::Error>(())
+//! ```
+//!
+//! A `MapValueEncoder` holds the value of one entry, so exactly one value
+//! should be written through it.
+
+use super::encoder::ArrayEncoder;
+use super::encoder::Encoder;
+use super::encoder::ListEncoder;
+use super::encoder::MapEncoder;
+use super::encoder::MapValueEncoder;
+use super::encoder::RecordBodyEncoder;
+use super::encoder::RecordEncoder;
+use super::macros::for_each_scalar;
+use super::size::SizeCounter;
+use super::types::Result;
+use super::types::Tag;
+
+/// Declares a scalar write in ScalarWriter.
+macro_rules! scalar_writer_decl {
+    ($name:ident, $as_name:ident, $ty:ty, $tag:expr, $var:ident, $ctx:tt) => {
+        fn $name(&mut self, v: $ty) -> Result<&mut Self>;
+    };
+}
+
+/// Implements a scalar write by forwarding to the encoder `$par`. A
+/// `$par` of `self` calls the inherent method, which takes precedence.
+macro_rules! scalar_writer_method {
+    ($name:ident, $as_name:ident, $ty:ty, $tag:expr, $var:ident, ( ($($recv:tt)+), $par:expr, $post:expr )) => {
+        #[inline]
+        fn $name($($recv)+, v: $ty) -> Result<&mut Self> {
+            $par.$name(v)?;
+            Ok($post)
+        }
+    };
+}
+
+/// Writes scalars: the values that can be record fields.
+pub trait ScalarWriter {
+    for_each_scalar!(scalar_writer_decl, ());
+}
+
+/// Writes any value.
+pub trait ValueWriter: ScalarWriter {
+    fn str(&mut self, v: &str) -> Result<&mut Self>;
+    fn bytes(&mut self, v: &[u8]) -> Result<&mut Self>;
+    fn record_raw(&mut self, v: &[u8]) -> Result<&mut Self>;
+    fn fixed16(&mut self, v: &[u8; 16]) -> Result<&mut Self>;
+    fn fixed32(&mut self, v: &[u8; 32]) -> Result<&mut Self>;
+    fn ext(&mut self, id: u16, payload: &[u8]) -> Result<&mut Self>;
+    fn list(&mut self) -> Result<ListEncoder<'_>>;
+    fn map(&mut self) -> Result<MapEncoder<'_>>;
+    fn array(&mut self, item_tag: Tag, stride: usize) -> Result<ArrayEncoder<'_>>;
+    fn record(&mut self) -> Result<RecordEncoder<'_>>;
+}
+
+/// Implements ValueWriter by forwarding to the encoder `$par`.
+macro_rules! impl_value_writer {
+    ([$($recv:tt)+], $par:expr, $post:expr) => {
+        fn str($($recv)+, v: &str) -> Result<&mut Self> {
+            $par.str(v)?;
+            Ok($post)
+        }
+
+        fn bytes($($recv)+, v: &[u8]) -> Result<&mut Self> {
+            $par.bytes(v)?;
+            Ok($post)
+        }
+
+        fn record_raw($($recv)+, v: &[u8]) -> Result<&mut Self> {
+            $par.record_raw(v)?;
+            Ok($post)
+        }
+
+        fn fixed16($($recv)+, v: &[u8; 16]) -> Result<&mut Self> {
+            $par.fixed16(v)?;
+            Ok($post)
+        }
+
+        fn fixed32($($recv)+, v: &[u8; 32]) -> Result<&mut Self> {
+            $par.fixed32(v)?;
+            Ok($post)
+        }
+
+        fn ext($($recv)+, id: u16, payload: &[u8]) -> Result<&mut Self> {
+            $par.ext(id, payload)?;
+            Ok($post)
+        }
+
+        fn list($($recv)+) -> Result<ListEncoder<'_>> {
+            $par.list()
+        }
+
+        fn map($($recv)+) -> Result<MapEncoder<'_>> {
+            $par.map()
+        }
+
+        fn array($($recv)+, item_tag: Tag, stride: usize) -> Result<ArrayEncoder<'_>> {
+            $par.array(item_tag, stride)
+        }
+
+        fn record($($recv)+) -> Result<RecordEncoder<'_>> {
+            $par.record()
+        }
+    };
+}
+
+impl ScalarWriter for Encoder {
+    for_each_scalar!(scalar_writer_method, ((&mut self), self, self));
+}
+
+impl ValueWriter for Encoder {
+    impl_value_writer!([&mut self], self, self);
+}
+
+impl ScalarWriter for ListEncoder<'_> {
+    for_each_scalar!(scalar_writer_method, ((&mut self), self, self));
+}
+
+impl ValueWriter for ListEncoder<'_> {
+    impl_value_writer!([&mut self], self, self);
+}
+
+impl ScalarWriter for MapValueEncoder<'_> {
+    for_each_scalar!(scalar_writer_method, ((&mut self), self.parent, self));
+}
+
+impl ValueWriter for MapValueEncoder<'_> {
+    impl_value_writer!([&mut self], self.parent, self);
+}
+
+impl ScalarWriter for RecordEncoder<'_> {
+    for_each_scalar!(scalar_writer_method, ((&mut self), self, self));
+}
+
+impl ScalarWriter for RecordBodyEncoder<'_, '_> {
+    for_each_scalar!(scalar_writer_method, ((&mut self), self, self));
+}
+
+impl ScalarWriter for SizeCounter {
+    for_each_scalar!(scalar_writer_method, ((&mut self), self, self));
+}
@@ -0,0 +1,194 @@
+//! Filesystem operations whose behaviour differs between platforms
+//!
+//! Storage code goes through these instead of `std::fs` where Unix and
+//! Windows disagree:
+//!
+//! - Directories can only be synced on Unix. On Windows there is no way
+//!   to open one for syncing through std, and NTFS journals its own
+//!   metadata, so `sync_dir` does nothing there.
+//! - Windows refuses to rename over a file, or delete one, while another
+//!   handle has it open without delete sharing. Indexers and virus
+//!   scanners open new files briefly, so `rename` retries for a moment
+//!   before giving up.
+//! - Windows refuses to shrink a file that is memory mapped. Logs are
+//!   only shrunk by `Core::truncate`, which unmaps its reader first.
+//!
+//! Files are opened with `open_options`, which asks Windows for full
+//! read, write, and delete sharing so that a log being appended to can
+//! still be mapped by readers and renamed or removed by other handles.
+//!
+//! The sync and rename steps are behind `FileOps`, so tests can check the
+//! order they run in on any platform.
+
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The sync and rename steps of a durable write.
+pub trait FileOps {
+    fn sync_file(&self, file: &File) -> io::Result<()>;
+    fn sync_dir(&self, dir: &Path) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+}
+
+/// The operating system's filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsFileOps;
+
+impl FileOps for OsFileOps {
+    fn sync_file(&self, file: &File) -> io::Result<()> {
+        return file.sync_all();
+    }
+
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        return sync_dir(dir);
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        return rename(from, to);
+    }
+}
+
+/// `OpenOptions` with the share modes storage files need. On Windows a
+/// handle that leaves out a share flag blocks every other handle that
+/// wants it, so all three are always given.
+pub fn open_options() -> OpenOptions {
+    #[allow(unused_mut)]
+    let mut options = OpenOptions::new();
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        const FILE_SHARE_READ: u32 = 0x1;
+        const FILE_SHARE_WRITE: u32 = 0x2;
+        const FILE_SHARE_DELETE: u32 = 0x4;
+        options.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE);
+    }
+    return options;
+}
+
+/// Syncs a directory's entries, making files created, renamed, or removed
+/// in it durable. Does nothing outside Unix.
+#[cfg(unix)]
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    return File::open(dir)?.sync_all();
+}
+
+#[cfg(not(unix))]
+pub fn sync_dir(_dir: &Path) -> io::Result<()> {
+    return Ok(());
+}
+
+/// Renames `from` to `to`, replacing `to` if it exists.
+#[cfg(not(windows))]
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    return std::fs::rename(from, to);
+}
+
+/// Renames `from` to `to`, replacing `to` if it exists. Access errors,
+/// which on Windows usually mean another process has `to` open for a
+/// moment, are retried with a short backoff.
+#[cfg(windows)]
+pub fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    const ATTEMPTS: u64 = 5;
+    let mut attempt = 1;
+    loop {
+        match std::fs::rename(from, to) {
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied && attempt < ATTEMPTS => {
+                std::thread::sleep(std::time::Duration::from_millis(10 * attempt));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The directory holding `path`; `.` for a bare file name.
+pub fn parent_dir(path: &Path) -> &Path {
+    return match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+}
+
+/// Where a replacement for `path` is written before being renamed over
+/// it: the same directory, so the rename never crosses filesystems, and
+/// the full file name plus `.tmp`, so files differing only in extension
+/// don't share one.
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    return path.with_file_name(name);
+}
+
+/// Writes `bytes` to the temporary file for `path`, syncing it if `sync`
+/// is set, and renames it over `path`. The caller syncs the directory.
+pub fn replace_file(ops: &dyn FileOps, path: &Path, bytes: &[u8], sync: bool) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    let mut file = open_options().write(true).create(true).truncate(true).open(&tmp)?;
+    file.write_all(bytes)?;
+    if sync {
+        ops.sync_file(&file)?;
+    }
+    // Windows can't rename a file that is still open here
+    drop(file);
+    return ops.rename(&tmp, path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Records each step before running it on the real filesystem.
+    #[derive(Default)]
+    struct Recorder {
+        ops: RefCell<Vec<String>>,
+    }
+
+    impl FileOps for Recorder {
+        fn sync_file(&self, file: &File) -> io::Result<()> {
+            self.ops.borrow_mut().push("sync_file".into());
+            return OsFileOps.sync_file(file);
+        }
+
+        fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+            self.ops.borrow_mut().push(format!("sync_dir {}", dir.display()));
+            return OsFileOps.sync_dir(dir);
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            let name = |p: &Path| p.file_name().unwrap().to_string_lossy().into_owned();
+            self.ops.borrow_mut().push(format!("rename {} {}", name(from), name(to)));
+            return OsFileOps.rename(from, to);
+        }
+    }
+
+    #[test]
+    fn replace_file_syncs_before_renaming() {
+        let path = std::env::temp_dir().join("test_platform_replace.nd");
+        let recorder = Recorder::default();
+        replace_file(&recorder, &path, b"one", true).unwrap();
+        replace_file(&recorder, &path, b"two", false).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"two");
+        assert!(!tmp_path(&path).exists());
+        assert_eq!(*recorder.ops.borrow(), [
+            "sync_file",
+            "rename test_platform_replace.nd.tmp test_platform_replace.nd",
+            "rename test_platform_replace.nd.tmp test_platform_replace.nd",
+        ]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn paths() {
+        assert_eq!(parent_dir(Path::new("info.nd")), Path::new("."));
+        let dir = Path::new("cores").join("compact");
+        assert_eq!(parent_dir(&dir.join("info.nd")), dir);
+        assert_eq!(tmp_path(&dir.join("info.nd")), dir.join("info.nd.tmp"));
+    }
+}
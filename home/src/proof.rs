@@ -0,0 +1,929 @@
+//! Inclusion and consistency proofs over signed IsoCore roots
+//!
+//! An `InclusionProof` for item `i` under the root signed after `len`
+//! items carries the item's leaf hash, the child hashes of every node from
+//! the item's leaf node up to the peak that covers it, the hashes of all
+//! the peaks, and the signature over the bagged root. A verifier who knows
+//! only the signer's public key can rebuild the root from these and check
+//! the signature, without the rest of the core.
+//!
+//! The tree's shape follows from `len` and the item id alone, so the proof
+//! carries no positions: the verifier works out which child each hash must
+//! be and rejects proofs of the wrong shape. From format version 3 the
+//! signed root commits to `len` too, so a proof can't be relabelled with
+//! another length whose tree has the same shape.
+//!
+//! Encoded as a neopack Map: `version: U8`, `item: U64`, `len: U64`,
+//! `leaf: Bytes`, `path: List<Bytes>` (each entry the concatenated child
+//! hashes of one node, leaf side first), `peaks: List<Bytes>`, and
+//! `signature: Bytes`.
+//!
+//! A `RangeProof` covers the contiguous items `start..start + n` at once.
+//! Every node over the range is rebuilt from the hashes below it, so it
+//! carries the range's leaf hashes and, for each node above them, only
+//! the child hashes off the range, in the order a walk down from each
+//! peak meets them, left to right. Paths the items share are sent once,
+//! so a page of items costs little more than a proof of one. Encoded as a
+//! Map: `version: U8`, `start: U64`, `len: U64`, `leaves: List<Bytes>`,
+//! `siblings: List<Bytes>`, `peaks: List<Bytes>`, and `signature: Bytes`.
+//!
+//! A `ConsistencyProof` shows that the core signed at `old_len` items is a
+//! prefix of the core signed at `new_len`. Nodes never change once their
+//! items are all present, so every peak at `old_len` is still a node at
+//! `new_len`, under some new peak. The proof carries the old peaks, a path
+//! from each up to its new peak, the new peaks, and both signatures.
+//! Encoded as a Map: `version: U8`, `old_len: U64`, `new_len: U64`,
+//! `old_peaks: List<Bytes>`, `paths: List<List<Bytes>>`, `new_peaks:
+//! List<Bytes>`, `old_signature: Bytes`, and `new_signature: Bytes`.
+//!
+//! A `ForkProof` shows the signer equivocated: two `HeadProof`s, roots it
+//! signed at the same length, that differ. Before format version 3 the
+//! signature covers the root but not the length, and two lengths can have
+//! as many peaks, so a head proof pins its length by opening each peak down to its first leaf: the
+//! path's depth is the peak's height. A head proof is a Map of `version:
+//! U8`, `len: U64`, `peaks: List<Bytes>`, `leaves: List<Bytes>`, `paths:
+//! List<List<Bytes>>`, and `signature: Bytes`; a fork proof is a Map of
+//! `first: Bytes` and `second: Bytes`, each an encoded head proof.
+
+use std::ops::Range;
+
+use crate::covering::children_for_covering;
+use crate::covering::covering_range;
+use crate::covering::get_peaks;
+use crate::covering::CoveringId;
+use crate::covering::ItemId;
+use crate::format::FormatVersion;
+use crate::format::WIDTH;
+use crate::key::Hash;
+use crate::key::KeyPub;
+use crate::key::Signature;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+use crate::neopack::Pack;
+use crate::neopack::ValueDecoder;
+
+#[derive(Debug)]
+pub enum ProofError {
+    Neopack(neopack::Error),
+    UnsupportedVersion { found: u8, expected: u8 },
+    /// The proof's hashes don't fit the tree for its item and length.
+    Shape,
+    /// A node's child hashes don't include the hash computed below it.
+    HashMismatch,
+    /// The signature doesn't verify against the rebuilt root.
+    BadSignature,
+    /// The two heads of a fork proof have the same root.
+    NoFork,
+}
+
+impl From<neopack::Error> for ProofError {
+    fn from(err: neopack::Error) -> Self {
+        return ProofError::Neopack(err);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InclusionProof {
+    pub version: FormatVersion,
+    pub item_id: ItemId,
+    /// Number of items covered by the signed root.
+    pub len: u64,
+    /// Hash of the item's data.
+    pub leaf_hash: Hash,
+    /// Child hashes of each node from the item's leaf node's parent up to
+    /// its peak, leaf side first.
+    pub path: Vec<Vec<Hash>>,
+    /// Hashes of every peak, in order.
+    pub peaks: Vec<Hash>,
+    /// Signature over the bagged root.
+    pub signature: Signature,
+}
+
+impl InclusionProof {
+    /// Rebuilds the root from the leaf hash, path, and peaks, checking that
+    /// each hash sits where the tree puts it.
+    pub fn root(&self) -> Result<Hash, ProofError> {
+        let (peak_index, descent) = descent(self.item_id, self.len).ok_or(ProofError::Shape)?;
+        if self.path.len() != descent.len() || self.peaks.len() != get_peaks(self.len, WIDTH).len() {
+            return Err(ProofError::Shape);
+        }
+
+        // The leaf node has the data hash as its only child
+        let leaf_node = self.version.hash_node([&self.leaf_hash]);
+        let peak = climb(self.version, leaf_node, &self.path, &descent)?;
+        if self.peaks[peak_index] != peak {
+            return Err(ProofError::HashMismatch);
+        }
+        return Ok(self.version.hash_root(self.len, &self.peaks));
+    }
+
+    /// Rebuilds the root and checks `signer` signed it. Returns the root.
+    pub fn verify(&self, signer: &KeyPub) -> Result<Hash, ProofError> {
+        let root = self.root()?;
+        if !signer.verify(&root.0, &self.signature) {
+            return Err(ProofError::BadSignature);
+        }
+        return Ok(root);
+    }
+
+    /// Whether `data` is the item this proof is for.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        return self.version.hash_leaf(data) == self.leaf_hash;
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        map.key("version")?.u8(self.version as u8)?;
+        map.key("item")?.u64(self.item_id.0)?;
+        map.key("len")?.u64(self.len)?;
+        self.leaf_hash.write(&mut map.key("leaf")?)?;
+        let mut path = map.key("path")?.list()?;
+        for children in &self.path {
+            let bytes: Vec<u8> = children.iter().flat_map(|h| h.0).collect();
+            path.bytes(&bytes)?;
+        }
+        path.finish()?;
+        let mut peaks = map.key("peaks")?.list()?;
+        for peak in &self.peaks {
+            peak.write(&mut peaks)?;
+        }
+        peaks.finish()?;
+        self.signature.write(&mut map.key("signature")?)?;
+        map.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        let mut dec = Decoder::new(bytes);
+        let mut map = dec.map()?;
+        let mut field = |name: &str| match map.next()? {
+            Some((key, value)) if key == name => Ok(value),
+            _ => Err(ProofError::Shape),
+        };
+
+        let version = field("version")?.as_u8()?;
+        let version = FormatVersion::from_u8(version)
+            .map_err(|_| ProofError::UnsupportedVersion { found: version, expected: FormatVersion::CURRENT as u8 })?;
+        let item_id = ItemId(field("item")?.as_u64()?);
+        let len = field("len")?.as_u64()?;
+        let leaf_hash = Hash::read(field("leaf")?)?;
+
+        let ValueDecoder::List(mut list) = field("path")? else {
+            return Err(ProofError::Shape);
+        };
+        let mut path = Vec::new();
+        while let Some(children) = list.next()? {
+            path.push(read_children(children.as_bytes()?)?);
+        }
+
+        let peaks = read_hashes(field("peaks")?)?;
+        let signature = read_signature(field("signature")?)?;
+
+        return Ok(InclusionProof {
+            version,
+            item_id,
+            len,
+            leaf_hash,
+            path,
+            peaks,
+            signature,
+        });
+    }
+}
+
+/// Proof of every item in a contiguous range under the root signed after
+/// `len` items. See the module docs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeProof {
+    pub version: FormatVersion,
+    /// The first item in the range.
+    pub start: ItemId,
+    /// Number of items covered by the signed root.
+    pub len: u64,
+    /// Hash of each item's data, in order.
+    pub leaf_hashes: Vec<Hash>,
+    /// Child hashes off the range, in the order the walk down from each
+    /// peak over the range meets them.
+    pub siblings: Vec<Hash>,
+    /// Hashes of every peak, in order.
+    pub peaks: Vec<Hash>,
+    /// Signature over the bagged root.
+    pub signature: Signature,
+}
+
+impl RangeProof {
+    /// The items the proof covers, or `Shape` if they run past the last
+    /// possible item id.
+    pub fn items(&self) -> Result<Range<u64>, ProofError> {
+        let end = self.start.0.checked_add(self.leaf_hashes.len() as u64).ok_or(ProofError::Shape)?;
+        return Ok(self.start.0..end);
+    }
+
+    /// Rebuilds every node over the range, and the root, checking that
+    /// each hash sits where the tree puts it and every sibling is used.
+    pub fn root(&self) -> Result<Hash, ProofError> {
+        let items = self.items()?;
+        let peak_ids = get_peaks(self.len, WIDTH);
+        if items.is_empty() || items.end > self.len || self.peaks.len() != peak_ids.len() {
+            return Err(ProofError::Shape);
+        }
+
+        let mut leaves = self.leaf_hashes.iter();
+        let mut siblings = self.siblings.iter();
+        let mut walk = RangeWalk { version: self.version, items: &items, leaves: &mut leaves, siblings: &mut siblings };
+        for (peak_id, peak) in peak_ids.into_iter().zip(&self.peaks) {
+            if overlaps(peak_id, &items) && walk.hash(peak_id)? != *peak {
+                return Err(ProofError::HashMismatch);
+            }
+        }
+        if walk.siblings.next().is_some() {
+            return Err(ProofError::Shape);
+        }
+        return Ok(self.version.hash_root(self.len, &self.peaks));
+    }
+
+    /// Rebuilds the root and checks `signer` signed it. Returns the root.
+    pub fn verify(&self, signer: &KeyPub) -> Result<Hash, ProofError> {
+        let root = self.root()?;
+        if !signer.verify(&root.0, &self.signature) {
+            return Err(ProofError::BadSignature);
+        }
+        return Ok(root);
+    }
+
+    /// Whether `data` holds exactly the items this proof is for, in order.
+    pub fn matches<T: AsRef<[u8]>>(&self, data: &[T]) -> bool {
+        return data.len() == self.leaf_hashes.len()
+            && data.iter().zip(&self.leaf_hashes).all(|(data, hash)| self.version.hash_leaf(data.as_ref()) == *hash);
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        map.key("version")?.u8(self.version as u8)?;
+        map.key("start")?.u64(self.start.0)?;
+        map.key("len")?.u64(self.len)?;
+        for (key, hashes) in [("leaves", &self.leaf_hashes), ("siblings", &self.siblings), ("peaks", &self.peaks)] {
+            let mut list = map.key(key)?.list()?;
+            for hash in hashes {
+                hash.write(&mut list)?;
+            }
+            list.finish()?;
+        }
+        self.signature.write(&mut map.key("signature")?)?;
+        map.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        let mut dec = Decoder::new(bytes);
+        let mut map = dec.map()?;
+        let mut field = |name: &str| match map.next()? {
+            Some((key, value)) if key == name => Ok(value),
+            _ => Err(ProofError::Shape),
+        };
+
+        let version = field("version")?.as_u8()?;
+        let version = FormatVersion::from_u8(version)
+            .map_err(|_| ProofError::UnsupportedVersion { found: version, expected: FormatVersion::CURRENT as u8 })?;
+        let start = ItemId(field("start")?.as_u64()?);
+        let len = field("len")?.as_u64()?;
+        let leaf_hashes = read_hashes(field("leaves")?)?;
+        let siblings = read_hashes(field("siblings")?)?;
+        let peaks = read_hashes(field("peaks")?)?;
+        let signature = read_signature(field("signature")?)?;
+
+        return Ok(RangeProof {
+            version,
+            start,
+            len,
+            leaf_hashes,
+            siblings,
+            peaks,
+            signature,
+        });
+    }
+}
+
+/// Rebuilds nodes over `items` for `RangeProof::root`, taking leaf hashes
+/// and siblings as the walk meets them.
+struct RangeWalk<'a, L, S> {
+    version: FormatVersion,
+    items: &'a Range<u64>,
+    leaves: &'a mut L,
+    siblings: &'a mut S,
+}
+
+impl<'a, L, S> RangeWalk<'a, L, S>
+where
+    L: Iterator<Item = &'a Hash>,
+    S: Iterator<Item = &'a Hash>,
+{
+    fn hash(&mut self, node: CoveringId) -> Result<Hash, ProofError> {
+        let children = children_for_covering(node, WIDTH);
+        // A leaf node has the data hash as its only child
+        if children.is_empty() {
+            let leaf = self.leaves.next().ok_or(ProofError::Shape)?;
+            return Ok(self.version.hash_node([leaf]));
+        }
+        let mut hashes = Vec::with_capacity(children.len());
+        for child in children {
+            hashes.push(match overlaps(child, self.items) {
+                true => self.hash(child)?,
+                false => self.siblings.next().ok_or(ProofError::Shape)?.clone(),
+            });
+        }
+        return Ok(self.version.hash_node(&hashes));
+    }
+}
+
+/// Whether any of `items` is under `node`.
+pub(crate) fn overlaps(node: CoveringId, items: &Range<u64>) -> bool {
+    let range = covering_range(node, WIDTH);
+    return range.start.0 < items.end && items.start < range.end.0;
+}
+
+/// Proof that a core signed at `old_len` items is a prefix of the same core
+/// signed at `new_len`: nothing signed earlier was rewritten later.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsistencyProof {
+    pub version: FormatVersion,
+    pub old_len: u64,
+    pub new_len: u64,
+    /// Hashes of every peak at `old_len`, in order.
+    pub old_peaks: Vec<Hash>,
+    /// For each old peak, the child hashes of each node from the old peak's
+    /// parent up to the new peak over it, old peak side first. Empty when
+    /// the old peak is still a peak.
+    pub paths: Vec<Vec<Vec<Hash>>>,
+    /// Hashes of every peak at `new_len`, in order.
+    pub new_peaks: Vec<Hash>,
+    /// Signature over the root at `old_len`.
+    pub old_signature: Signature,
+    /// Signature over the root at `new_len`.
+    pub new_signature: Signature,
+}
+
+impl ConsistencyProof {
+    /// Rebuilds both roots, checking that every old peak sits unchanged
+    /// where the new tree puts it. Returns the old and new roots.
+    pub fn roots(&self) -> Result<(Hash, Hash), ProofError> {
+        if self.old_len == 0 || self.old_len > self.new_len {
+            return Err(ProofError::Shape);
+        }
+        let old_ids = get_peaks(self.old_len, WIDTH);
+        let new_ids = get_peaks(self.new_len, WIDTH);
+        if self.old_peaks.len() != old_ids.len()
+            || self.paths.len() != old_ids.len()
+            || self.new_peaks.len() != new_ids.len() {
+            return Err(ProofError::Shape);
+        }
+
+        for ((old_id, old_peak), path) in old_ids.iter().zip(&self.old_peaks).zip(&self.paths) {
+            let (peak_index, nodes) = ascent(*old_id, self.new_len).ok_or(ProofError::Shape)?;
+            let descent: Vec<usize> = nodes.into_iter().map(|(_, position)| position).collect();
+            if path.len() != descent.len() {
+                return Err(ProofError::Shape);
+            }
+            let peak = climb(self.version, old_peak.clone(), path, &descent)?;
+            if self.new_peaks[peak_index] != peak {
+                return Err(ProofError::HashMismatch);
+            }
+        }
+        return Ok((self.version.hash_root(self.old_len, &self.old_peaks), self.version.hash_root(self.new_len, &self.new_peaks)));
+    }
+
+    /// Rebuilds both roots and checks `signer` signed each of them.
+    pub fn verify(&self, signer: &KeyPub) -> Result<(Hash, Hash), ProofError> {
+        let (old_root, new_root) = self.roots()?;
+        if !signer.verify(&old_root.0, &self.old_signature) || !signer.verify(&new_root.0, &self.new_signature) {
+            return Err(ProofError::BadSignature);
+        }
+        return Ok((old_root, new_root));
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        map.key("version")?.u8(self.version as u8)?;
+        map.key("old_len")?.u64(self.old_len)?;
+        map.key("new_len")?.u64(self.new_len)?;
+        let mut peaks = map.key("old_peaks")?.list()?;
+        for peak in &self.old_peaks {
+            peak.write(&mut peaks)?;
+        }
+        peaks.finish()?;
+        let mut paths = map.key("paths")?.list()?;
+        for path in &self.paths {
+            let mut nodes = paths.list()?;
+            for children in path {
+                let bytes: Vec<u8> = children.iter().flat_map(|h| h.0).collect();
+                nodes.bytes(&bytes)?;
+            }
+            nodes.finish()?;
+        }
+        paths.finish()?;
+        let mut peaks = map.key("new_peaks")?.list()?;
+        for peak in &self.new_peaks {
+            peak.write(&mut peaks)?;
+        }
+        peaks.finish()?;
+        self.old_signature.write(&mut map.key("old_signature")?)?;
+        self.new_signature.write(&mut map.key("new_signature")?)?;
+        map.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        let mut dec = Decoder::new(bytes);
+        let mut map = dec.map()?;
+        let mut field = |name: &str| match map.next()? {
+            Some((key, value)) if key == name => Ok(value),
+            _ => Err(ProofError::Shape),
+        };
+
+        let version = field("version")?.as_u8()?;
+        let version = FormatVersion::from_u8(version)
+            .map_err(|_| ProofError::UnsupportedVersion { found: version, expected: FormatVersion::CURRENT as u8 })?;
+        let old_len = field("old_len")?.as_u64()?;
+        let new_len = field("new_len")?.as_u64()?;
+        let old_peaks = read_hashes(field("old_peaks")?)?;
+
+        let paths = read_paths(field("paths")?)?;
+        let new_peaks = read_hashes(field("new_peaks")?)?;
+        let old_signature = read_signature(field("old_signature")?)?;
+        let new_signature = read_signature(field("new_signature")?)?;
+
+        return Ok(ConsistencyProof {
+            version,
+            old_len,
+            new_len,
+            old_peaks,
+            paths,
+            new_peaks,
+            old_signature,
+            new_signature,
+        });
+    }
+}
+
+/// A root signed after `len` items, opened far enough to pin `len`. Before
+/// format version 3 the root leaves out `len`, and roots at different
+/// lengths can have as many peaks, so each peak comes with the path down
+/// to its first leaf node: that fixes its height, and the heights fix the
+/// length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadProof {
+    pub version: FormatVersion,
+    pub len: u64,
+    /// Hashes of every peak, in order.
+    pub peaks: Vec<Hash>,
+    /// For each peak, the leaf hash of the first item under it.
+    pub leaves: Vec<Hash>,
+    /// For each peak, the child hashes of each node from its first leaf
+    /// node's parent up to the peak, leaf side first.
+    pub paths: Vec<Vec<Vec<Hash>>>,
+    /// Signature over the bagged root.
+    pub signature: Signature,
+}
+
+impl HeadProof {
+    /// Climbs from each peak's first leaf to the peak, then bags the peaks.
+    pub fn root(&self) -> Result<Hash, ProofError> {
+        let ids = get_peaks(self.len, WIDTH);
+        if ids.is_empty()
+            || self.peaks.len() != ids.len()
+            || self.leaves.len() != ids.len()
+            || self.paths.len() != ids.len() {
+            return Err(ProofError::Shape);
+        }
+
+        for (((id, peak), leaf), path) in ids.iter().zip(&self.peaks).zip(&self.leaves).zip(&self.paths) {
+            let first = covering_range(*id, WIDTH).start;
+            let descent: Vec<usize> = nodes_below(*id, first).into_iter().map(|(_, position)| position).collect();
+            if path.len() != descent.len() {
+                return Err(ProofError::Shape);
+            }
+            let leaf_node = self.version.hash_node([leaf]);
+            if climb(self.version, leaf_node, path, &descent)? != *peak {
+                return Err(ProofError::HashMismatch);
+            }
+        }
+        return Ok(self.version.hash_root(self.len, &self.peaks));
+    }
+
+    /// Rebuilds the root and checks `signer` signed it. Returns the root.
+    pub fn verify(&self, signer: &KeyPub) -> Result<Hash, ProofError> {
+        let root = self.root()?;
+        if !signer.verify(&root.0, &self.signature) {
+            return Err(ProofError::BadSignature);
+        }
+        return Ok(root);
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        map.key("version")?.u8(self.version as u8)?;
+        map.key("len")?.u64(self.len)?;
+        let mut peaks = map.key("peaks")?.list()?;
+        for peak in &self.peaks {
+            peak.write(&mut peaks)?;
+        }
+        peaks.finish()?;
+        let mut leaves = map.key("leaves")?.list()?;
+        for leaf in &self.leaves {
+            leaf.write(&mut leaves)?;
+        }
+        leaves.finish()?;
+        let mut paths = map.key("paths")?.list()?;
+        for path in &self.paths {
+            let mut nodes = paths.list()?;
+            for children in path {
+                let bytes: Vec<u8> = children.iter().flat_map(|h| h.0).collect();
+                nodes.bytes(&bytes)?;
+            }
+            nodes.finish()?;
+        }
+        paths.finish()?;
+        self.signature.write(&mut map.key("signature")?)?;
+        map.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        let mut dec = Decoder::new(bytes);
+        let mut map = dec.map()?;
+        let mut field = |name: &str| match map.next()? {
+            Some((key, value)) if key == name => Ok(value),
+            _ => Err(ProofError::Shape),
+        };
+
+        let version = field("version")?.as_u8()?;
+        let version = FormatVersion::from_u8(version)
+            .map_err(|_| ProofError::UnsupportedVersion { found: version, expected: FormatVersion::CURRENT as u8 })?;
+        let len = field("len")?.as_u64()?;
+        let peaks = read_hashes(field("peaks")?)?;
+        let leaves = read_hashes(field("leaves")?)?;
+        let paths = read_paths(field("paths")?)?;
+        let signature = read_signature(field("signature")?)?;
+
+        return Ok(HeadProof {
+            version,
+            len,
+            peaks,
+            leaves,
+            paths,
+            signature,
+        });
+    }
+}
+
+/// Proof that a signer equivocated: two heads it signed at the same length
+/// with different roots. One history has one root at each length, so no
+/// honest signer can be shown to have made both.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForkProof {
+    pub first: HeadProof,
+    pub second: HeadProof,
+}
+
+impl ForkProof {
+    /// Pairs two heads into a fork proof, if they are at the same length
+    /// and have different roots. Doesn't check the signatures.
+    pub fn new(first: HeadProof, second: HeadProof) -> Option<Self> {
+        if first.len != second.len || first.root().ok()? == second.root().ok()? {
+            return None;
+        }
+        return Some(ForkProof { first, second });
+    }
+
+    /// Checks that `signer` signed both heads and that they differ.
+    /// Returns the length they fork at.
+    pub fn verify(&self, signer: &KeyPub) -> Result<u64, ProofError> {
+        if self.first.len != self.second.len {
+            return Err(ProofError::Shape);
+        }
+        if self.first.verify(signer)? == self.second.verify(signer)? {
+            return Err(ProofError::NoFork);
+        }
+        return Ok(self.first.len);
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        map.key("first")?.bytes(&self.first.to_bytes()?)?;
+        map.key("second")?.bytes(&self.second.to_bytes()?)?;
+        map.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProofError> {
+        let mut dec = Decoder::new(bytes);
+        let mut map = dec.map()?;
+        let mut field = |name: &str| match map.next()? {
+            Some((key, value)) if key == name => Ok(value),
+            _ => Err(ProofError::Shape),
+        };
+
+        let first = HeadProof::from_bytes(field("first")?.as_bytes()?)?;
+        let second = HeadProof::from_bytes(field("second")?.as_bytes()?)?;
+        return Ok(ForkProof { first, second });
+    }
+}
+
+/// Hashes up from `start` through `path`, the child hashes of each node
+/// above it, nearest first. `descent` gives, from the top down, where the
+/// node below sits among each node's children. Returns the top hash.
+fn climb(version: FormatVersion, start: Hash, path: &[Vec<Hash>], descent: &[usize]) -> Result<Hash, ProofError> {
+    let mut current = start;
+    for (children, &position) in path.iter().zip(descent.iter().rev()) {
+        if children.len() != WIDTH as usize {
+            return Err(ProofError::Shape);
+        }
+        if children[position] != current {
+            return Err(ProofError::HashMismatch);
+        }
+        current = version.hash_node(children);
+    }
+    return Ok(current);
+}
+
+fn read_hashes(value: ValueDecoder<'_>) -> Result<Vec<Hash>, ProofError> {
+    let ValueDecoder::List(mut list) = value else {
+        return Err(ProofError::Shape);
+    };
+    let mut hashes = Vec::new();
+    while let Some(hash) = list.next()? {
+        hashes.push(Hash::read(hash)?);
+    }
+    return Ok(hashes);
+}
+
+/// Reads a list of paths, each a list of concatenated child hashes.
+fn read_paths(value: ValueDecoder<'_>) -> Result<Vec<Vec<Vec<Hash>>>, ProofError> {
+    let ValueDecoder::List(mut list) = value else {
+        return Err(ProofError::Shape);
+    };
+    let mut paths = Vec::new();
+    while let Some(path) = list.next()? {
+        let ValueDecoder::List(mut nodes) = path else {
+            return Err(ProofError::Shape);
+        };
+        let mut path = Vec::new();
+        while let Some(children) = nodes.next()? {
+            path.push(read_children(children.as_bytes()?)?);
+        }
+        paths.push(path);
+    }
+    return Ok(paths);
+}
+
+fn read_children(bytes: &[u8]) -> Result<Vec<Hash>, ProofError> {
+    if !bytes.len().is_multiple_of(32) {
+        return Err(ProofError::Shape);
+    }
+    return bytes.chunks(32).map(hash_from).collect();
+}
+
+fn read_signature(value: ValueDecoder<'_>) -> Result<Signature, ProofError> {
+    // A wrong length is a malformed proof, not a neopack error
+    value.as_bytes()?;
+    return Signature::read(value).map_err(|_| ProofError::Shape);
+}
+
+fn hash_from(bytes: &[u8]) -> Result<Hash, ProofError> {
+    return Ok(Hash(bytes.try_into().map_err(|_| ProofError::Shape)?));
+}
+
+/// Where `item` sits in the tree over `len` items: the index of its peak,
+/// and from the peak down, which child to take at each node to reach the
+/// item's leaf node. `None` if `item` isn't below `len`.
+fn descent(item: ItemId, len: u64) -> Option<(usize, Vec<usize>)> {
+    let (peak_index, peak) = get_peaks(len, WIDTH)
+        .into_iter()
+        .enumerate()
+        .find(|(_, peak)| covering_range(*peak, WIDTH).contains(&item))?;
+    return Some((peak_index, nodes_below(peak, item).into_iter().map(|(_, p)| p).collect()));
+}
+
+/// Where covering node `node` sits in the tree over `len` items: the index
+/// of the peak over it, and each node from that peak down to `node`,
+/// exclusive, paired with the position of the next node among its
+/// children. `None` if `node` isn't complete at `len`.
+pub(crate) fn ascent(node: CoveringId, len: u64) -> Option<(usize, Vec<(CoveringId, usize)>)> {
+    let range = covering_range(node, WIDTH);
+    if range.end.0 > len {
+        return None;
+    }
+    let (peak_index, peak) = get_peaks(len, WIDTH)
+        .into_iter()
+        .enumerate()
+        .find(|(_, peak)| covering_range(*peak, WIDTH).contains(&range.start))?;
+    if peak == node {
+        return Some((peak_index, Vec::new()));
+    }
+    let mut nodes = Vec::new();
+    for (parent, position) in nodes_below(peak, range.start) {
+        nodes.push((parent, position));
+        if children_for_covering(parent, WIDTH)[position] == node {
+            return Some((peak_index, nodes));
+        }
+    }
+    return None;
+}
+
+/// Each node from `peak` down to `item`'s leaf node, exclusive, paired
+/// with the position of the next node among its children.
+pub(crate) fn nodes_below(peak: CoveringId, item: ItemId) -> Vec<(CoveringId, usize)> {
+    let mut nodes = Vec::new();
+    let mut node = peak;
+    loop {
+        let children = children_for_covering(node, WIDTH);
+        let Some(position) = children.iter()
+            .position(|child| covering_range(*child, WIDTH).contains(&item)) else {
+            return nodes;
+        };
+        nodes.push((node, position));
+        node = children[position];
+    }
+}
+
+#[cfg(all(test, feature = "disk", feature = "rng"))]
+mod tests {
+    use super::*;
+    use crate::isocore::IsoCore;
+    use crate::key::KeyPair;
+
+    #[test]
+    fn proofs_verify_for_every_item() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        for i in 0..75u32 {
+            isocore.add_message(&i.to_le_bytes(), &signer).unwrap();
+        }
+
+        for len in [1, 8, 9, 64, 75] {
+            for item in [0, len / 2, len - 1] {
+                let proof = isocore.prove(ItemId(item), len).unwrap();
+                let root = proof.verify(&signer.key_pub).unwrap();
+                assert_eq!(isocore.find_by_root(&root), Some(ItemId(len - 1)));
+                assert!(proof.matches(&(item as u32).to_le_bytes()));
+
+                let decoded = InclusionProof::from_bytes(&proof.to_bytes().unwrap()).unwrap();
+                assert_eq!(decoded, proof);
+            }
+        }
+    }
+
+    #[test]
+    fn range_proofs_share_nodes() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        for i in 0..75u32 {
+            isocore.add_message(&i.to_le_bytes(), &signer).unwrap();
+        }
+        let data = |items: Range<u64>| items.map(|i| (i as u32).to_le_bytes()).collect::<Vec<_>>();
+
+        for (items, len) in [(0..1, 1), (3..9, 9), (0..64, 64), (60..75, 75), (10..20, 75)] {
+            let proof = isocore.prove_range(items.clone(), len).unwrap();
+            let root = proof.verify(&signer.key_pub).unwrap();
+            assert_eq!(isocore.find_by_root(&root), Some(ItemId(len - 1)));
+            assert_eq!(proof.items().unwrap(), items);
+            assert!(proof.matches(&data(items.clone())));
+            assert!(!proof.matches(&data(items.start + 1..items.end + 1)));
+            assert_eq!(RangeProof::from_bytes(&proof.to_bytes().unwrap()).unwrap(), proof);
+        }
+
+        let range = isocore.prove_range(10..20, 75).unwrap().to_bytes().unwrap().len();
+        let separate: usize = (10..20).map(|i| isocore.prove(ItemId(i), 75).unwrap().to_bytes().unwrap().len()).sum();
+        assert!(range * 4 < separate);
+
+        let proof = isocore.prove_range(10..20, 75).unwrap();
+        let mut tampered = proof.clone();
+        tampered.leaf_hashes[3] = Hash([0; 32]);
+        assert!(matches!(tampered.root(), Err(ProofError::HashMismatch)));
+        let mut tampered = proof.clone();
+        tampered.siblings.pop();
+        assert!(matches!(tampered.root(), Err(ProofError::Shape)));
+        let mut tampered = proof.clone();
+        tampered.start = ItemId(11);
+        assert!(tampered.verify(&signer.key_pub).is_err());
+        assert!(isocore.prove_range(70..76, 75).is_err());
+
+        // A decoded range ending past the last item id is malformed, not
+        // an overflow
+        let mut overflowing = proof.clone();
+        overflowing.start = ItemId(u64::MAX);
+        let decoded = RangeProof::from_bytes(&overflowing.to_bytes().unwrap()).unwrap();
+        assert!(matches!(decoded.items(), Err(ProofError::Shape)));
+        assert!(matches!(decoded.verify(&signer.key_pub), Err(ProofError::Shape)));
+    }
+
+    #[test]
+    fn proofs_reject_tampering() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        for i in 0..20u32 {
+            isocore.add_message(&i.to_le_bytes(), &signer).unwrap();
+        }
+        let proof = isocore.prove(ItemId(3), 20).unwrap();
+
+        let mut wrong_leaf = proof.clone();
+        wrong_leaf.leaf_hash = Hash([1; 32]);
+        assert!(matches!(wrong_leaf.verify(&signer.key_pub), Err(ProofError::HashMismatch)));
+
+        let mut wrong_item = proof.clone();
+        wrong_item.item_id = ItemId(19);
+        assert!(wrong_item.verify(&signer.key_pub).is_err());
+        let mut wrong_item = proof.clone();
+        wrong_item.item_id = ItemId(4);
+        assert!(matches!(wrong_item.verify(&signer.key_pub), Err(ProofError::HashMismatch)));
+
+        // 20 and 27 items make trees of the same shape, but the signed
+        // root commits to the length
+        let mut wrong_len = proof.clone();
+        wrong_len.len = 27;
+        assert!(matches!(wrong_len.verify(&signer.key_pub), Err(ProofError::BadSignature)));
+
+        let other = KeyPair::ephemeral();
+        assert!(matches!(proof.verify(&other.key_pub), Err(ProofError::BadSignature)));
+        assert!(isocore.prove(ItemId(20), 20).is_err());
+    }
+
+    #[test]
+    fn consistency_proofs_link_signed_roots() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        let mut roots = Vec::new();
+        for i in 0..80u32 {
+            roots.push(isocore.add_message(&i.to_le_bytes(), &signer).unwrap());
+        }
+
+        for (old_len, new_len) in [(1, 1), (1, 80), (7, 9), (8, 9), (9, 64), (63, 65), (64, 80), (80, 80)] {
+            let proof = isocore.prove_consistency(old_len, new_len).unwrap();
+            let decoded = ConsistencyProof::from_bytes(&proof.to_bytes().unwrap()).unwrap();
+            assert_eq!(decoded, proof);
+            let (old_root, new_root) = proof.verify(&signer.key_pub).unwrap();
+            assert_eq!(old_root, roots[old_len as usize - 1]);
+            assert_eq!(new_root, roots[new_len as usize - 1]);
+        }
+
+        // A fork that rewrote item 3 can't be shown consistent with the original
+        let mut fork = IsoCore::create_mem(&signer);
+        for i in 0..80u32 {
+            let data = if i == 3 { b"fork".to_vec() } else { i.to_le_bytes().to_vec() };
+            fork.add_message(&data, &signer).unwrap();
+        }
+        let mut spliced = isocore.prove_consistency(9, 40).unwrap();
+        let forked = fork.prove_consistency(9, 40).unwrap();
+        spliced.new_peaks = forked.new_peaks;
+        spliced.new_signature = forked.new_signature;
+        assert!(matches!(spliced.verify(&signer.key_pub), Err(ProofError::HashMismatch)));
+
+        let mut short = isocore.prove_consistency(9, 40).unwrap();
+        short.paths.iter_mut().find(|path| !path.is_empty()).unwrap().pop();
+        assert!(matches!(short.verify(&signer.key_pub), Err(ProofError::Shape)));
+        assert!(isocore.prove_consistency(0, 5).is_err());
+        assert!(isocore.prove_consistency(6, 5).is_err());
+        assert!(isocore.prove_consistency(5, 81).is_err());
+    }
+
+    #[test]
+    fn fork_proofs_need_one_length() {
+        let signer = KeyPair::ephemeral();
+        let mut isocore = IsoCore::create_mem(&signer);
+        let mut fork = IsoCore::create_mem(&signer);
+        let mut roots = Vec::new();
+        for i in 0..70u32 {
+            roots.push(isocore.add_message(&i.to_le_bytes(), &signer).unwrap());
+            let data = if i == 3 { b"fork".to_vec() } else { i.to_le_bytes().to_vec() };
+            fork.add_message(&data, &signer).unwrap();
+        }
+
+        for len in [1, 8, 9, 64, 70] {
+            let head = isocore.prove_head(len).unwrap();
+            assert_eq!(HeadProof::from_bytes(&head.to_bytes().unwrap()).unwrap(), head);
+            assert_eq!(head.verify(&signer.key_pub).unwrap(), roots[len as usize - 1]);
+        }
+
+        let proof = ForkProof::new(isocore.prove_head(10).unwrap(), fork.prove_head(10).unwrap()).unwrap();
+        assert_eq!(ForkProof::from_bytes(&proof.to_bytes().unwrap()).unwrap(), proof);
+        assert_eq!(proof.verify(&signer.key_pub).unwrap(), 10);
+        assert!(matches!(proof.verify(&KeyPair::ephemeral().key_pub), Err(ProofError::BadSignature)));
+        assert!(ForkProof::new(isocore.prove_head(3).unwrap(), fork.prove_head(3).unwrap()).is_none());
+
+        // 9 and 65 items both make two peaks, but the openings tell them apart
+        let mut relabelled = isocore.prove_head(65).unwrap();
+        relabelled.len = 9;
+        assert!(matches!(relabelled.verify(&signer.key_pub), Err(ProofError::Shape)));
+        let framed = ForkProof { first: isocore.prove_head(9).unwrap(), second: relabelled };
+        assert!(framed.verify(&signer.key_pub).is_err());
+
+        let same = ForkProof { first: isocore.prove_head(9).unwrap(), second: isocore.prove_head(9).unwrap() };
+        assert!(matches!(same.verify(&signer.key_pub), Err(ProofError::NoFork)));
+    }
+}
@@ -0,0 +1,271 @@
+//! Read-only IsoCores, for replicas that never author items
+//!
+//! Loading an IsoCore needs no key, but the loaded core still offers
+//! `add_message`, and appending with the wrong key only fails once it is
+//! called. A `ReadOnlyIsoCore` wraps a core and exposes only what a
+//! replica needs: reading, proving, and verifying. It has no append
+//! methods, so code holding one can't write to it by mistake.
+//!
+//! The one way in is replication. `replicate` appends items that come
+//! with the source's signature, each checked against the signer before it
+//! is stored, through a crate-private `add_signed`; the receiving side of
+//! replication takes a `ReadOnlyIsoCore` for that reason.
+
+use std::borrow::Cow;
+use std::path::Path;
+use std::path::PathBuf;
+use crate::covering::ItemId;
+use crate::core::MessageId;
+use crate::isocore::AuditEvent;
+use crate::isocore::AuditReport;
+use crate::isocore::Checkpoint;
+use crate::isocore::CoreStats;
+use crate::isocore::Cosignature;
+use crate::isocore::FormatVersion;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::isocore::PageDirection;
+use crate::isocore::PageEntry;
+use crate::isocore::SignatureBlock;
+use crate::isocore::VerifiedItems;
+use crate::key::Hash;
+use crate::key::KeyPub;
+use crate::key::Signature;
+use crate::neodisk::Durability;
+use crate::proof::ConsistencyProof;
+use crate::proof::ForkProof;
+use crate::proof::HeadProof;
+use crate::proof::InclusionProof;
+
+#[derive(Debug)]
+pub struct ReadOnlyIsoCore {
+    core: IsoCore,
+}
+
+impl From<IsoCore> for ReadOnlyIsoCore {
+    fn from(core: IsoCore) -> Self {
+        return ReadOnlyIsoCore { core };
+    }
+}
+
+impl ReadOnlyIsoCore {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, IsoCoreError> {
+        return Ok(IsoCore::load(path)?.into());
+    }
+
+    /// An empty replica of the core signed by `signer`, stored at `path`
+    /// with `info`, the source's signed info.nd; see
+    /// `IsoCore::create_replica`.
+    pub fn create(path: PathBuf, signer: &KeyPub, info: &[u8]) -> Result<Self, IsoCoreError> {
+        return Ok(IsoCore::create_replica(path, signer, info)?.into());
+    }
+
+    pub fn replica_mem(signer: &KeyPub, version: FormatVersion) -> Self {
+        return IsoCore::replica_mem(signer, version).into();
+    }
+
+    /// Appends an item replicated from the source, checked against its
+    /// signature.
+    pub(crate) fn add_signed(&mut self, message: &[u8], signature: &Signature) -> Result<Hash, IsoCoreError> {
+        return self.core.add_signed(message, signature);
+    }
+
+    /// Writes replicated items to disk; see `IsoCore::flush`.
+    pub fn flush(&mut self) -> Result<(), IsoCoreError> {
+        return self.core.flush();
+    }
+
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.core.set_durability(durability);
+    }
+
+    pub fn len(&self) -> MessageId {
+        return self.core.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.core.len().0 == 0;
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        return self.core.path();
+    }
+
+    pub fn signer(&self) -> &KeyPub {
+        return self.core.signer();
+    }
+
+    pub fn version(&self) -> FormatVersion {
+        return self.core.version();
+    }
+
+    pub fn get_message(&mut self, item_id: ItemId) -> Result<Cow<'_, [u8]>, IsoCoreError> {
+        return self.core.get_message(item_id);
+    }
+
+    pub fn iter_verified(&mut self, from: ItemId) -> VerifiedItems<'_> {
+        return self.core.iter_verified(from);
+    }
+
+    pub fn inline_limit(&self) -> u64 {
+        return self.core.inline_limit();
+    }
+
+    pub fn is_light(&self) -> bool {
+        return self.core.is_light();
+    }
+
+    /// See `IsoCore::is_sealed`.
+    pub fn is_sealed(&self) -> bool {
+        return self.core.is_sealed();
+    }
+
+    /// Gives a light replica the payload of `item_id`; see
+    /// `IsoCore::hydrate`.
+    pub fn hydrate(&mut self, item_id: ItemId, bytes: &[u8]) -> Result<(), IsoCoreError> {
+        return self.core.hydrate(item_id, bytes);
+    }
+
+    pub fn page(&mut self, offset: u64, limit: u64, direction: PageDirection) -> Result<Vec<PageEntry>, IsoCoreError> {
+        return self.core.page(offset, limit, direction);
+    }
+
+    pub fn find_by_root(&self, root: &Hash) -> Option<ItemId> {
+        return self.core.find_by_root(root);
+    }
+
+    pub fn get_signature(&mut self, item_id: ItemId) -> Result<SignatureBlock, IsoCoreError> {
+        return self.core.get_signature(item_id);
+    }
+
+    pub fn get_root_hash(&mut self) -> Result<Hash, IsoCoreError> {
+        return self.core.get_root_hash();
+    }
+
+    pub fn prove(&mut self, item_id: ItemId, len: u64) -> Result<InclusionProof, IsoCoreError> {
+        return self.core.prove(item_id, len);
+    }
+
+    pub fn peaks(&mut self, len: u64) -> Result<Vec<Hash>, IsoCoreError> {
+        return self.core.peaks(len);
+    }
+
+    pub fn prove_consistency(&mut self, old_len: u64, new_len: u64) -> Result<ConsistencyProof, IsoCoreError> {
+        return self.core.prove_consistency(old_len, new_len);
+    }
+
+    pub fn prove_head(&mut self, len: u64) -> Result<HeadProof, IsoCoreError> {
+        return self.core.prove_head(len);
+    }
+
+    pub fn check_head(&mut self, head: &HeadProof) -> Result<Option<ForkProof>, IsoCoreError> {
+        return self.core.check_head(head);
+    }
+
+    pub fn verify_head(&mut self) -> Result<Hash, IsoCoreError> {
+        return self.core.verify_head();
+    }
+
+    pub fn verify_signature(&mut self, item_id: ItemId) -> Result<Hash, IsoCoreError> {
+        return self.core.verify_signature(item_id);
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        return self.core.checkpoints();
+    }
+
+    pub fn verify_from_checkpoint(&mut self, checkpoint: &Checkpoint) -> Result<Hash, IsoCoreError> {
+        return self.core.verify_from_checkpoint(checkpoint);
+    }
+
+    /// Keeps a witness's cosignature, as `IsoCore::add_cosignature` does.
+    /// It adds no items, so a replica may hold them too.
+    pub fn add_cosignature(&mut self, cosignature: Cosignature) -> Result<(), IsoCoreError> {
+        return self.core.add_cosignature(cosignature);
+    }
+
+    pub fn cosignatures(&self) -> &[Cosignature] {
+        return self.core.cosignatures();
+    }
+
+    pub fn verify_witnessed(&mut self, witnesses: &[KeyPub], needed: usize) -> Result<Hash, IsoCoreError> {
+        return self.core.verify_witnessed(witnesses, needed);
+    }
+
+    pub fn stats(&mut self) -> Result<CoreStats, IsoCoreError> {
+        return self.core.stats();
+    }
+
+    pub fn audit(&mut self, progress: impl FnMut(AuditEvent)) -> AuditReport {
+        return self.core.audit(progress);
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+    use crate::key::KeyPair;
+
+    #[test]
+    fn reads_and_verifies_a_loaded_core() {
+        let path = PathBuf::from("/tmp/test_readonly_isocore");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+
+        let mut core = IsoCore::create(path.clone(), &signer).unwrap();
+        let messages = ["message 0", "message 1", "message 2", "message 3"];
+        let root = core.add_messages(messages, &signer).unwrap().pop().unwrap();
+        core.flush().unwrap();
+        drop(core);
+
+        let mut replica = ReadOnlyIsoCore::load(&path).unwrap();
+        assert_eq!(replica.len().0, 4);
+        assert_eq!(replica.signer(), &signer.key_pub);
+        assert_eq!(&*replica.get_message(ItemId(2)).unwrap(), b"message 2");
+        assert_eq!(replica.verify_head().unwrap(), root);
+
+        let proof = replica.prove(ItemId(1), 4).unwrap();
+        assert!(proof.matches(b"message 1"));
+        assert_eq!(proof.verify(&signer.key_pub).unwrap(), root);
+        assert!(replica.audit(|_| {}).is_ok());
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn replica_on_disk_needs_only_the_public_key() {
+        let path = PathBuf::from("/tmp/test_readonly_replica_source");
+        let copy = PathBuf::from("/tmp/test_readonly_replica");
+        let _ = std::fs::remove_dir_all(&path);
+        let _ = std::fs::remove_dir_all(&copy);
+        let signer = KeyPair::ephemeral();
+        let mut source = IsoCore::create(path.clone(), &signer).unwrap();
+        let root = source.add_messages(["one", "two"], &signer).unwrap().pop().unwrap();
+        let info = source.info_bytes().unwrap().to_vec();
+
+        // The source's info.nd must name the signer and keep its seal
+        let stranger = KeyPair::ephemeral();
+        assert!(matches!(ReadOnlyIsoCore::create(copy.clone(), &stranger.key_pub, &info), Err(IsoCoreError::SignerMismatch)));
+        let mut forged = info.clone();
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+        assert!(matches!(ReadOnlyIsoCore::create(copy.clone(), &signer.key_pub, &forged), Err(IsoCoreError::BadInfo)));
+
+        let mut replica = ReadOnlyIsoCore::create(copy.clone(), &signer.key_pub, &info).unwrap();
+        for (i, message) in ["one", "two"].iter().enumerate() {
+            let block = source.get_signature(ItemId(i as u64)).unwrap();
+            replica.add_signed(message.as_bytes(), block.signature().unwrap()).unwrap();
+        }
+        replica.flush().unwrap();
+        drop(replica);
+
+        let mut replica = ReadOnlyIsoCore::load(&copy).unwrap();
+        assert!(replica.is_sealed());
+        assert_eq!(replica.signer(), &signer.key_pub);
+        assert_eq!(replica.verify_head().unwrap(), root);
+        assert_eq!(std::fs::read(copy.join(crate::isocore::INFO_ISOCORE)).unwrap(), info);
+
+        std::fs::remove_dir_all(&path).unwrap();
+        std::fs::remove_dir_all(&copy).unwrap();
+    }
+}
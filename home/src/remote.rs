@@ -0,0 +1,777 @@
+//! Remote cores: reading a core straight out of object storage
+//!
+//! A `RemoteCore` serves a core whose files sit behind a `RangeFetcher`,
+//! such as a static HTTP server or an S3 bucket, without copying them
+//! locally. Each log is read a frame at a time with range requests:
+//! opening it scans the frame headers, skipping their data, and a read
+//! fetches and decompresses only the frame it lands in. The last few
+//! frames of each log are kept, so neighbouring items, and the tree nodes
+//! above them, cost no further requests.
+//!
+//! Nothing fetched is trusted. Opening checks that info.nd names the
+//! signer the core was opened for and that the last signature block
+//! verifies. Every `get_message` then builds an `InclusionProof` from the
+//! fetched tree nodes and checks it, and the payload, against that head,
+//! just as a light client checks a proof from a peer. A store that serves
+//! altered or mismatched files gets `IntegrityError` rather than data.
+//!
+//! `HttpFetcher` speaks plain HTTP/1.1 over a `TcpStream`, enough for a
+//! core behind any file server that honours `Range`. Each request times
+//! out, and reads no more of the body than the range it asked for, so a
+//! slow or hostile server can't stall a reader or fill its memory. Frames
+//! are then decompressed no further than their headers say. HTTPS, and object
+//! stores that sign their requests, like S3 or GCS, are left to other
+//! `RangeFetcher`s.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::ops::Range;
+use std::time::Duration;
+use crate::covering::coverings_for_item;
+use crate::covering::covering_range;
+use crate::covering::get_peaks;
+use crate::covering::CoveringId;
+use crate::covering::ItemId;
+use crate::format::FormatError;
+use crate::format::FormatVersion;
+use crate::format::SignatureBlock;
+use crate::format::Signers;
+use crate::format::WIDTH;
+use crate::isocore::CoreInfo;
+use crate::isocore::IsoCoreError;
+use crate::isocore::NodeType;
+use crate::isocore::VerkleNode;
+use crate::isocore::FILE_DATA;
+use crate::isocore::FILE_SIG;
+use crate::isocore::FILE_VERKLE;
+use crate::isocore::INFO_ISOCORE;
+use crate::key::Hash;
+use crate::key::KeyPub;
+use crate::key::Signature;
+use crate::neodisk;
+use crate::neodisk::FrameInfo;
+use crate::neodisk::FOOTER_SIZE;
+use crate::neopack;
+use crate::proof::nodes_below;
+use crate::proof::InclusionProof;
+use crate::proof::ProofError;
+
+/// Bytes fetched at a time while scanning a log's frame headers.
+const SCAN_BLOCK: u64 = 64 * 1024;
+/// Decompressed frames kept per log.
+const CACHED_FRAMES: usize = 4;
+/// How long an `HttpFetcher` waits to connect, or for each read or write,
+/// unless set with `with_timeout`.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest HTTP response head read before giving up on the response.
+const MAX_HTTP_HEAD: usize = 16 * 1024;
+
+#[derive(Debug)]
+pub enum RemoteError {
+    Io(io::Error),
+    /// The server answered with something other than the bytes asked for.
+    Http(String),
+    NeoDisk(neodisk::Error),
+    Neopack(neopack::Error),
+    IsoCore(IsoCoreError),
+    Format(FormatError),
+    Proof(ProofError),
+    /// info.nd names other signers than the core was opened for.
+    WrongSigner,
+    /// Light cores keep their payloads outside their files, and threshold
+    /// cores sign without a single signature to prove against.
+    Unsupported(&'static str),
+    /// The item is past the signed head.
+    FutureItem(ItemId),
+    /// A fetched payload or tree node doesn't match the signed head.
+    IntegrityError,
+}
+
+impl From<io::Error> for RemoteError {
+    fn from(err: io::Error) -> Self {
+        return RemoteError::Io(err);
+    }
+}
+
+impl From<neodisk::Error> for RemoteError {
+    fn from(err: neodisk::Error) -> Self {
+        return RemoteError::NeoDisk(err);
+    }
+}
+
+impl From<neopack::Error> for RemoteError {
+    fn from(err: neopack::Error) -> Self {
+        return RemoteError::Neopack(err);
+    }
+}
+
+impl From<IsoCoreError> for RemoteError {
+    fn from(err: IsoCoreError) -> Self {
+        return RemoteError::IsoCore(err);
+    }
+}
+
+impl From<FormatError> for RemoteError {
+    fn from(err: FormatError) -> Self {
+        return RemoteError::Format(err);
+    }
+}
+
+impl From<ProofError> for RemoteError {
+    fn from(err: ProofError) -> Self {
+        return match err {
+            ProofError::BadSignature => RemoteError::IntegrityError,
+            err => RemoteError::Proof(err),
+        };
+    }
+}
+
+/// Byte ranges of the files of one core, by name: info.nd, data.nd, and
+/// so on.
+pub trait RangeFetcher {
+    /// Size in bytes of the file `name`.
+    fn size(&self, name: &str) -> Result<u64, RemoteError>;
+
+    /// Bytes `range` of the file `name`, all of them.
+    fn fetch(&self, name: &str, range: Range<u64>) -> Result<Vec<u8>, RemoteError>;
+}
+
+/// Fetches a core's files from under an `http://` URL, with `HEAD` for
+/// sizes and `Range` requests for bytes. One connection per request.
+#[derive(Debug, Clone)]
+pub struct HttpFetcher {
+    /// Host and port to connect to.
+    addr: String,
+    /// Host as the URL gave it, for the `Host` header.
+    host: String,
+    /// Path the files are under, without a trailing slash.
+    base: String,
+    timeout: Duration,
+}
+
+impl HttpFetcher {
+    /// A fetcher for the files under `url`, as in
+    /// `http://example.com:8080/cores/alice`.
+    pub fn new(url: &str) -> Result<Self, RemoteError> {
+        let rest = url.strip_prefix("http://")
+            .ok_or_else(|| RemoteError::Http(format!("not an http:// url: {url}")))?;
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if host.is_empty() {
+            return Err(RemoteError::Http(format!("no host in {url}")));
+        }
+        let addr = match host.contains(':') {
+            true => host.to_string(),
+            false => format!("{host}:80"),
+        };
+        let base = format!("/{}", path.trim_end_matches('/'));
+        let base = base.trim_end_matches('/').to_string();
+        return Ok(HttpFetcher { addr, host: host.to_string(), base, timeout: HTTP_TIMEOUT });
+    }
+
+    /// Sets how long to wait to connect, and for each read or write.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        return self;
+    }
+
+    fn connect(&self) -> Result<TcpStream, RemoteError> {
+        let mut last = None;
+        for addr in self.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok(stream);
+                }
+                Err(err) => last = Some(err),
+            }
+        }
+        return Err(last.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address")).into());
+    }
+
+    /// Sends one request for `name` and returns the status, the headers
+    /// with lowercased names, and the body. Only as much of the body is
+    /// read as `range` asks for, and none without one.
+    fn request(&self, method: &str, name: &str, range: Option<&Range<u64>>) -> Result<Response, RemoteError> {
+        let mut stream = self.connect()?;
+        let mut request = format!("{method} {}/{name} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n", self.base, self.host);
+        if let Some(range) = range {
+            request.push_str(&format!("Range: bytes={}-{}\r\n", range.start, range.end - 1));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        let mut raw = Vec::new();
+        let mut buf = [0u8; 1024];
+        let split = loop {
+            if let Some(split) = raw.windows(4).position(|w| w == b"\r\n\r\n") {
+                break split;
+            }
+            if raw.len() > MAX_HTTP_HEAD {
+                return Err(RemoteError::Http(format!("response head for {name} is too long")));
+            }
+            let n = stream.read(&mut buf)?;
+            if n == 0 {
+                return Err(RemoteError::Http(format!("truncated response for {name}")));
+            }
+            raw.extend_from_slice(&buf[..n]);
+        };
+        let head = std::str::from_utf8(&raw[..split])
+            .map_err(|_| RemoteError::Http(format!("bad response head for {name}")))?;
+        let mut lines = head.split("\r\n");
+        let status = lines.next()
+            .and_then(|line| line.split(' ').nth(1))
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| RemoteError::Http(format!("bad status line for {name}")))?;
+        let headers: Vec<(String, String)> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        let mut response = Response { status, headers, body: raw[split + 4..].to_vec() };
+        if response.header("transfer-encoding").is_some_and(|value| value.contains("chunked")) {
+            return Err(RemoteError::Http(format!("chunked response for {name}")));
+        }
+
+        // A server that ignored the range sends the whole file, of which
+        // only the part up to the range's end is needed
+        let limit = match (status, range) {
+            (206, Some(range)) => range.end - range.start,
+            (200, Some(range)) if method == "GET" => range.end,
+            _ => 0,
+        };
+        if (response.body.len() as u64) <= limit {
+            let wanted = limit + 1 - response.body.len() as u64;
+            stream.take(wanted).read_to_end(&mut response.body)?;
+        }
+        if response.body.len() as u64 > limit {
+            if status == 206 {
+                return Err(RemoteError::Http(format!("{name} sent more than the range asked for")));
+            }
+            response.body.truncate(limit as usize);
+        }
+        return Ok(response);
+    }
+}
+
+/// An HTTP response, with as much of its body as was asked for.
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        return self.headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        return self.header("content-length").and_then(|value| value.parse().ok());
+    }
+}
+
+impl RangeFetcher for HttpFetcher {
+    fn size(&self, name: &str) -> Result<u64, RemoteError> {
+        let response = self.request("HEAD", name, None)?;
+        if response.status != 200 {
+            return Err(RemoteError::Http(format!("{} for {name}", response.status)));
+        }
+        return response.content_length()
+            .ok_or_else(|| RemoteError::Http(format!("no length for {name}")));
+    }
+
+    fn fetch(&self, name: &str, range: Range<u64>) -> Result<Vec<u8>, RemoteError> {
+        if range.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut response = self.request("GET", name, Some(&range))?;
+        if let Some(len) = response.content_length() {
+            response.body.truncate(len as usize);
+        }
+        let body = match response.status {
+            206 => response.body,
+            // The server ignored the range and sent the whole file
+            200 => response.body.get(range.start as usize..range.end as usize)
+                .ok_or_else(|| RemoteError::Http(format!("{name} is shorter than {}", range.end)))?
+                .to_vec(),
+            status => return Err(RemoteError::Http(format!("{status} for {name}"))),
+        };
+        if body.len() as u64 != range.end - range.start {
+            return Err(RemoteError::Http(format!("short range for {name}")));
+        }
+        return Ok(body);
+    }
+}
+
+/// Reads one remote file a block at a time, so `neodisk::scan_headers`
+/// can walk it like a local one.
+struct BlockReader<'a, F: ?Sized> {
+    fetcher: &'a F,
+    name: &'a str,
+    size: u64,
+    pos: u64,
+    block_start: u64,
+    block: Vec<u8>,
+}
+
+impl<F: RangeFetcher + ?Sized> Read for BlockReader<'_, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.size {
+            return Ok(0);
+        }
+        let block_end = self.block_start + self.block.len() as u64;
+        if self.pos < self.block_start || self.pos >= block_end {
+            let end = (self.pos + SCAN_BLOCK).min(self.size);
+            self.block = self.fetcher.fetch(self.name, self.pos..end)
+                .map_err(|err| io::Error::other(format!("{err:?}")))?;
+            self.block_start = self.pos;
+        }
+        let offset = (self.pos - self.block_start) as usize;
+        let n = buf.len().min(self.block.len() - offset);
+        buf[..n].copy_from_slice(&self.block[offset..offset + n]);
+        self.pos += n as u64;
+        return Ok(n);
+    }
+}
+
+impl<F: RangeFetcher + ?Sized> Seek for BlockReader<'_, F> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.size.checked_add_signed(delta),
+        };
+        self.pos = pos.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before start"))?;
+        return Ok(self.pos);
+    }
+}
+
+/// One NeoDisk log of a remote core: its frame index, and the frames
+/// read most recently, newest last, as the payloads of their messages.
+#[derive(Debug)]
+struct RemoteLog {
+    name: &'static str,
+    frames: Vec<FrameInfo>,
+    /// Where the footer starts, which is where the last frame ends.
+    end: u64,
+    cache: VecDeque<(usize, Vec<Vec<u8>>)>,
+}
+
+impl RemoteLog {
+    fn open<F: RangeFetcher + ?Sized>(fetcher: &F, name: &'static str) -> Result<Self, RemoteError> {
+        let size = fetcher.size(name)?;
+        if size < FOOTER_SIZE as u64 {
+            return Err(RemoteError::NeoDisk(neodisk::Error::InvalidFormat));
+        }
+        let end = size - FOOTER_SIZE as u64;
+        neodisk::read_footer(&fetcher.fetch(name, end..size)?)?;
+        let mut reader = BlockReader { fetcher, name, size, pos: 0, block_start: 0, block: Vec::new() };
+        let frames = neodisk::scan_headers(&mut reader, end)?;
+        return Ok(RemoteLog { name, frames, end, cache: VecDeque::new() });
+    }
+
+    fn len(&self) -> u64 {
+        return self.frames.iter().map(|frame| frame.message_count).sum();
+    }
+
+    /// The payload of message `id`, fetching its frame unless it is cached.
+    fn read<F: RangeFetcher + ?Sized>(&mut self, fetcher: &F, id: u64) -> Result<Vec<u8>, RemoteError> {
+        let index = self.frames.partition_point(|frame| frame.first_message_id + frame.message_count <= id);
+        let frame = self.frames.get(index)
+            .filter(|frame| frame.first_message_id <= id)
+            .ok_or(RemoteError::NeoDisk(neodisk::Error::MessageNotFound(id)))?;
+        let offset = (id - frame.first_message_id) as usize;
+
+        if let Some(position) = self.cache.iter().position(|(cached, _)| *cached == index) {
+            let entry = self.cache.remove(position).unwrap();
+            let payload = entry.1[offset].clone();
+            self.cache.push_back(entry);
+            return Ok(payload);
+        }
+
+        let start = frame.header_offset;
+        let end = self.frames.get(index + 1).map_or(self.end, |next| next.header_offset);
+        let bytes = fetcher.fetch(self.name, start..end)?;
+        let (header, header_size) = neodisk::read_header_from(&mut io::Cursor::new(&bytes), end - start)?;
+        let compressed = bytes.get(header_size..header_size + header.compressed_size as usize)
+            .ok_or(RemoteError::NeoDisk(neodisk::Error::InvalidFormat))?;
+        let decompressed = neodisk::decompress(&header, compressed)?;
+
+        // Each Core message is framed as neopack Bytes
+        let messages = neodisk::frame_messages(&header, &decompressed, 0..frame.message_count as usize)?;
+        let mut payloads = Vec::with_capacity(messages.len());
+        for encoded in messages {
+            payloads.push(neopack::Decoder::new(&encoded).bytes()?.to_vec());
+        }
+        let payload = payloads.get(offset).cloned()
+            .ok_or(RemoteError::NeoDisk(neodisk::Error::InvalidFormat))?;
+        if self.cache.len() == CACHED_FRAMES {
+            self.cache.pop_front();
+        }
+        self.cache.push_back((index, payloads));
+        return Ok(payload);
+    }
+}
+
+/// A core read through a `RangeFetcher`, checked against its signed head.
+/// See the module docs.
+#[derive(Debug)]
+pub struct RemoteCore<F> {
+    fetcher: F,
+    signer: KeyPub,
+    version: FormatVersion,
+    data: RemoteLog,
+    verkle: RemoteLog,
+    /// Length, root, and signature of the head, the last signature block.
+    head: Option<(u64, Hash, Signature)>,
+    /// Whether info.nd is signed; see `IsoCore::is_sealed`.
+    sealed: bool,
+}
+
+impl<F: RangeFetcher> RemoteCore<F> {
+    /// Opens the core `fetcher` serves, which must be signed by `signer`.
+    pub fn open(fetcher: F, signer: &KeyPub) -> Result<Self, RemoteError> {
+        // Items are checked against `signer` either way, so a core from
+        // before seals were signed is read too
+        let (info, sealed) = CoreInfo::read(&fetcher.fetch(INFO_ISOCORE, 0..fetcher.size(INFO_ISOCORE)?)?)?;
+        let signers = Signers::single(signer.clone());
+        if info.signers != signers {
+            return Err(RemoteError::WrongSigner);
+        }
+        if info.light {
+            return Err(RemoteError::Unsupported("light cores keep their payloads elsewhere"));
+        }
+
+        let mut sig = RemoteLog::open(&fetcher, FILE_SIG)?;
+        let len = sig.len();
+        let head = match len {
+            0 => None,
+            len => {
+                let block = SignatureBlock::from_bytes(&sig.read(&fetcher, len - 1)?)?;
+                if !signers.verify(&block) {
+                    return Err(RemoteError::IntegrityError);
+                }
+                let signature = block.signature()
+                    .ok_or(RemoteError::Unsupported("threshold cores have no single signature"))?;
+                Some((len, block.global_root.clone(), signature.clone()))
+            }
+        };
+
+        let data = RemoteLog::open(&fetcher, FILE_DATA)?;
+        let verkle = RemoteLog::open(&fetcher, FILE_VERKLE)?;
+        return Ok(RemoteCore { fetcher, signer: signer.clone(), version: info.version, data, verkle, head, sealed });
+    }
+
+    /// Whether the core's info.nd is signed by its signer.
+    pub fn is_sealed(&self) -> bool {
+        return self.sealed;
+    }
+
+    /// Number of items under the signed head.
+    pub fn len(&self) -> u64 {
+        return self.head.as_ref().map_or(0, |(len, _, _)| *len);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /// The signed root of the head, or None for an empty core.
+    pub fn root(&self) -> Option<&Hash> {
+        return self.head.as_ref().map(|(_, root, _)| root);
+    }
+
+    pub fn version(&self) -> FormatVersion {
+        return self.version;
+    }
+
+    fn get_node(&mut self, covering_id: CoveringId) -> Result<VerkleNode, RemoteError> {
+        let bytes = self.verkle.read(&self.fetcher, covering_id.to_verkle_id().0 as u64)?;
+        return Ok(VerkleNode::from_bytes(&bytes)?);
+    }
+
+    /// Proves `item_id` is under the signed head, from the fetched tree,
+    /// and checks the proof before returning it.
+    pub fn prove(&mut self, item_id: ItemId) -> Result<InclusionProof, RemoteError> {
+        let Some((len, root, signature)) = self.head.clone() else {
+            return Err(RemoteError::FutureItem(item_id));
+        };
+        if item_id.0 >= len {
+            return Err(RemoteError::FutureItem(item_id));
+        }
+        let peak_ids = get_peaks(len, WIDTH);
+        let peak = *peak_ids.iter()
+            .find(|peak| covering_range(**peak, WIDTH).contains(&item_id))
+            .ok_or(RemoteError::IntegrityError)?;
+
+        let mut path = Vec::new();
+        for (node_id, _) in nodes_below(peak, item_id).into_iter().rev() {
+            let node = self.get_node(node_id)?;
+            path.push(node.children.into_iter().map(|child| child.hash).collect());
+        }
+        let leaf = self.get_node(coverings_for_item(item_id, WIDTH).leaf())?;
+        let leaf_hash = leaf.children.first().ok_or(RemoteError::IntegrityError)?.hash.clone();
+
+        let mut peaks = Vec::new();
+        for peak_id in peak_ids {
+            peaks.push(self.get_node(peak_id)?.compute_hash(self.version));
+        }
+
+        let proof = InclusionProof { version: self.version, item_id, len, leaf_hash, path, peaks, signature };
+        if proof.verify(&self.signer)? != root {
+            return Err(RemoteError::IntegrityError);
+        }
+        return Ok(proof);
+    }
+
+    /// The payload of `item_id`, once it and the tree above it check out
+    /// against the signed head.
+    pub fn get_message(&mut self, item_id: ItemId) -> Result<Vec<u8>, RemoteError> {
+        let proof = self.prove(item_id)?;
+        let leaf = self.get_node(coverings_for_item(item_id, WIDTH).leaf())?;
+        let child = match leaf.children.as_slice() {
+            [child] if child.node_type == NodeType::Leaf => child,
+            _ => return Err(RemoteError::IntegrityError),
+        };
+        let data = match &child.data {
+            Some(data) => data.clone(),
+            None => self.data.read(&self.fetcher, child.index.0 as u64)?,
+        };
+        if !proof.matches(&data) {
+            return Err(RemoteError::IntegrityError);
+        }
+        return Ok(data);
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use crate::isocore::IsoCore;
+    use crate::key::KeyPair;
+
+    /// Serves the files of a directory, counting requests.
+    struct DirFetcher {
+        dir: PathBuf,
+        fetches: Cell<usize>,
+        /// Files served from another directory instead.
+        swapped: Option<(&'static str, PathBuf)>,
+    }
+
+    impl DirFetcher {
+        fn new(dir: &Path) -> Self {
+            return DirFetcher { dir: dir.to_path_buf(), fetches: Cell::new(0), swapped: None };
+        }
+
+        fn path(&self, name: &str) -> PathBuf {
+            return match &self.swapped {
+                Some((swapped, dir)) if *swapped == name => dir.join(name),
+                _ => self.dir.join(name),
+            };
+        }
+    }
+
+    impl RangeFetcher for DirFetcher {
+        fn size(&self, name: &str) -> Result<u64, RemoteError> {
+            return Ok(std::fs::metadata(self.path(name))?.len());
+        }
+
+        fn fetch(&self, name: &str, range: Range<u64>) -> Result<Vec<u8>, RemoteError> {
+            self.fetches.set(self.fetches.get() + 1);
+            let bytes = std::fs::read(self.path(name))?;
+            return Ok(bytes[range.start as usize..range.end as usize].to_vec());
+        }
+    }
+
+    fn write_core(path: &Path, signer: &KeyPair, messages: &[String]) {
+        let _ = std::fs::remove_dir_all(path);
+        let mut core = IsoCore::create_inline(path.to_path_buf(), signer, 8).unwrap();
+        core.add_messages(messages, signer).unwrap();
+        core.flush().unwrap();
+    }
+
+    #[test]
+    fn remote_core_reads_and_checks_items() {
+        let path = PathBuf::from("/tmp/test_remote_core");
+        let other = PathBuf::from("/tmp/test_remote_core_other");
+        let signer = KeyPair::ephemeral();
+        let mut messages: Vec<String> = (0..40).map(|i| format!("message number {i}")).collect();
+        messages[7] = "tiny".to_string();
+        write_core(&path, &signer, &messages);
+
+        let mut remote = RemoteCore::open(DirFetcher::new(&path), &signer.key_pub).unwrap();
+        assert_eq!(remote.len(), 40);
+        assert_eq!(remote.root(), Some(&IsoCore::load(&path).unwrap().verify_head().unwrap()));
+        assert_eq!(remote.get_message(ItemId(0)).unwrap(), messages[0].as_bytes());
+
+        // Every frame is now cached
+        let fetches = remote.fetcher.fetches.get();
+        for (i, message) in messages.iter().enumerate() {
+            assert_eq!(remote.get_message(ItemId(i as u64)).unwrap(), message.as_bytes());
+        }
+        assert_eq!(remote.fetcher.fetches.get(), fetches);
+        assert!(matches!(remote.get_message(ItemId(40)), Err(RemoteError::FutureItem(ItemId(40)))));
+
+        // Payloads from another core with the same signer don't match
+        messages[3] = "a different message".to_string();
+        write_core(&other, &signer, &messages);
+        let mut fetcher = DirFetcher::new(&path);
+        fetcher.swapped = Some((FILE_DATA, other.clone()));
+        let mut remote = RemoteCore::open(fetcher, &signer.key_pub).unwrap();
+        assert!(remote.get_message(ItemId(2)).is_ok());
+        assert!(matches!(remote.get_message(ItemId(3)), Err(RemoteError::IntegrityError)));
+
+        // So do its tree nodes
+        let mut fetcher = DirFetcher::new(&path);
+        fetcher.swapped = Some((FILE_VERKLE, other.clone()));
+        let mut remote = RemoteCore::open(fetcher, &signer.key_pub).unwrap();
+        assert!(matches!(remote.get_message(ItemId(3)), Err(RemoteError::IntegrityError)));
+
+        let stranger = KeyPair::ephemeral();
+        assert!(matches!(RemoteCore::open(DirFetcher::new(&path), &stranger.key_pub), Err(RemoteError::WrongSigner)));
+        assert!(RemoteCore::open(DirFetcher::new(&path), &signer.key_pub).unwrap().is_sealed());
+
+        // A core from before info.nd was sealed is still read, and says so
+        let mut enc = neopack::Encoder::new();
+        let mut map = enc.map().unwrap();
+        map.key("version").unwrap().u8(FormatVersion::CURRENT as u8).unwrap();
+        map.key("signer").unwrap().bytes(&signer.key_pub.0).unwrap();
+        map.finish().unwrap();
+        std::fs::write(path.join(INFO_ISOCORE), enc.as_bytes()).unwrap();
+        let mut remote = RemoteCore::open(DirFetcher::new(&path), &signer.key_pub).unwrap();
+        assert!(!remote.is_sealed());
+        assert_eq!(remote.get_message(ItemId(5)).unwrap(), messages[5].as_bytes());
+
+        std::fs::remove_dir_all(&path).unwrap();
+        std::fs::remove_dir_all(&other).unwrap();
+    }
+
+    #[test]
+    fn remote_core_refuses_forged_counts() {
+        let path = PathBuf::from("/tmp/test_remote_core_counts");
+        let signer = KeyPair::ephemeral();
+        let messages: Vec<String> = (0..3).map(|i| format!("message {i}")).collect();
+        write_core(&path, &signer, &messages);
+
+        // One frame, whose header claims more messages than bytes
+        let log = std::fs::read(path.join(FILE_DATA)).unwrap();
+        let (mut header, header_size) = neodisk::read_header_from(&mut io::Cursor::new(&log), log.len() as u64).unwrap();
+        assert_eq!(header_size as u64 + header.compressed_size + 16, log.len() as u64);
+        header.message_count = Some(u64::MAX);
+        let mut forged = header.encode().unwrap();
+        forged.extend_from_slice(&log[header_size..]);
+        std::fs::write(path.join(FILE_DATA), &forged).unwrap();
+
+        let opened = RemoteCore::open(DirFetcher::new(&path), &signer.key_pub);
+        assert!(matches!(opened, Err(RemoteError::NeoDisk(neodisk::Error::InvalidFormat))));
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Answers `HEAD` and ranged `GET` requests for the files in `dir`.
+    fn serve(dir: PathBuf) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut raw = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !raw.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let n = stream.read(&mut buf).unwrap();
+                    raw.extend_from_slice(&buf[..n]);
+                }
+                let request = String::from_utf8(raw).unwrap();
+                let mut words = request.split_whitespace();
+                let (method, target) = (words.next().unwrap(), words.next().unwrap());
+                let name = target.rsplit('/').next().unwrap();
+                let Ok(bytes) = std::fs::read(dir.join(name)) else {
+                    stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").unwrap();
+                    continue;
+                };
+                let range = request.lines()
+                    .find_map(|line| line.strip_prefix("Range: bytes="))
+                    .and_then(|range| range.split_once('-'))
+                    .map(|(start, end)| start.parse::<usize>().unwrap()..end.parse::<usize>().unwrap() + 1);
+                let (status, body) = match range {
+                    Some(range) => ("206 Partial Content", &bytes[range]),
+                    None => ("200 OK", &bytes[..]),
+                };
+                let head = format!("HTTP/1.1 {status}\r\nContent-Length: {}\r\n\r\n", body.len());
+                stream.write_all(head.as_bytes()).unwrap();
+                if method == "GET" {
+                    stream.write_all(body).unwrap();
+                }
+            }
+        });
+        return format!("http://{addr}/cores/remote/");
+    }
+
+    #[test]
+    fn remote_core_over_http() {
+        let path = PathBuf::from("/tmp/test_remote_core_http");
+        let signer = KeyPair::ephemeral();
+        let messages: Vec<String> = (0..10).map(|i| format!("over the wire {i}")).collect();
+        write_core(&path, &signer, &messages);
+
+        let fetcher = HttpFetcher::new(&serve(path.clone())).unwrap();
+        assert_eq!(fetcher.base, "/cores/remote");
+        assert_eq!(fetcher.fetch(FILE_SIG, 0..0).unwrap(), b"");
+        assert!(matches!(fetcher.size("missing.nd"), Err(RemoteError::Http(_))));
+
+        let mut remote = RemoteCore::open(fetcher, &signer.key_pub).unwrap();
+        assert_eq!(remote.len(), 10);
+        assert_eq!(remote.get_message(ItemId(9)).unwrap(), messages[9].as_bytes());
+
+        assert!(matches!(HttpFetcher::new("https://example.com/core"), Err(RemoteError::Http(_))));
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    /// Answers one request with `response`, or with nothing if None.
+    fn answer_once(response: Option<Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf);
+            match response {
+                Some(response) => {
+                    let _ = stream.write_all(&response);
+                }
+                None => std::thread::sleep(Duration::from_secs(2)),
+            }
+        });
+        return format!("http://{addr}/core");
+    }
+
+    #[test]
+    fn http_fetcher_bounds_responses() {
+        // A body past the range asked for is refused, not read to the end
+        let mut response = b"HTTP/1.1 206 Partial Content\r\nContent-Length: 1048576\r\n\r\n".to_vec();
+        response.resize(response.len() + (1 << 20), b'x');
+        let fetcher = HttpFetcher::new(&answer_once(Some(response))).unwrap();
+        assert!(matches!(fetcher.fetch(FILE_SIG, 0..4), Err(RemoteError::Http(_))));
+
+        // As is a head that never ends
+        let response = vec![b'x'; 2 * MAX_HTTP_HEAD];
+        let fetcher = HttpFetcher::new(&answer_once(Some(response))).unwrap();
+        assert!(matches!(fetcher.fetch(FILE_SIG, 0..4), Err(RemoteError::Http(_))));
+
+        // A server that says nothing times out
+        let fetcher = HttpFetcher::new(&answer_once(None)).unwrap().with_timeout(Duration::from_millis(100));
+        let started = std::time::Instant::now();
+        assert!(matches!(fetcher.fetch(FILE_SIG, 0..4), Err(RemoteError::Io(_))));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
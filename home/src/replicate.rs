@@ -0,0 +1,922 @@
+//! Replication: bringing one replica of an IsoCore up to date from another
+//!
+//! Before transferring items, the replicas narrow down what is missing:
+//!
+//! 1. The receiver sends a `SyncHint`: for each range of its items, a bloom
+//!    filter of the leaf hashes it holds there.
+//! 2. The sender checks each of its own items against the hint, and replies
+//!    with an `Offer` of the item ranges the receiver lacks. Items past the
+//!    end of the receiver's hint are always offered.
+//!
+//! 3. The receiver fetches the items chunk by chunk with `Request`s, each
+//!    answered by `Items` carrying the source signature of every item. A
+//!    `ReplicationSession` tracks progress and can be saved and resumed.
+//!
+//! An offer of items the receiver already holds means the replicas
+//! disagree there. Before fetching, the receiver asks with `ProveHead` for
+//! the sender's signed head at the receiver's length, or the sender's own
+//! if shorter, and compares it with its own. If the signer signed both and
+//! they differ, the receiver stops with `ReplicateError::Fork`, carrying a
+//! `ForkProof` anyone holding the signer's key can check.
+//!
+//! `SyncReceiver` and `SyncSender` run the two sides as state machines that
+//! never touch I/O; `run_receiver` and `run_sender` drive them over any
+//! `Transport`.
+//! The receiving side works on a `ReadOnlyIsoCore`: a replica can take
+//! items from its source but never author its own.
+//!
+//! A filter can report an item as held when it isn't, so a hint narrows the
+//! transfer but doesn't prove the replicas agree. Received items are still
+//! checked against the sender's signed root.
+//!
+//! Messages are neopack Lists tagged with a string:
+//! `["hint", List<[start: u64, end: u64, hashes: u8, bits: Bytes]>]` or
+//! `["offer", List<[start: u64, end: u64]>]`, `["request", start: u64,
+//! end: u64]`, `["items", List<[item: u64, data: Bytes, signature:
+//! Bytes]>]`, `["prove_head", len: u64]`, or `["head", Bytes]` with an
+//! encoded `HeadProof`.
+
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+use crate::covering::ItemId;
+use crate::format::Artifact;
+use crate::format::FormatError;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::isocore::PageDirection;
+use crate::isocore::PageEntry;
+use crate::readonly::ReadOnlyIsoCore;
+use crate::key::Hash;
+use crate::key::KeyPub;
+use crate::key::Signature;
+use crate::neodisk::Durability;
+use crate::neodisk::write_atomic;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+use crate::neopack::Pack;
+use crate::neopack::ValueDecoder;
+use crate::proof::ForkProof;
+use crate::proof::HeadProof;
+use crate::proof::ProofError;
+use crate::transport::Transport;
+use crate::transport::TransportError;
+
+/// Items summarised by each filter in a hint.
+pub const DEFAULT_HINT_RANGE: u64 = 1024;
+/// Filter bits per item; with 7 hashes, about 1% false positives.
+const BITS_PER_ITEM: u64 = 10;
+const FILTER_HASHES: u8 = 7;
+
+#[derive(Debug)]
+pub enum ReplicateError {
+    IsoCore(IsoCoreError),
+    Neopack(neopack::Error),
+    Io(std::io::Error),
+    Transport(TransportError),
+    /// A hint range is empty, out of order, or its filter has no bits.
+    BadHint,
+    /// The replica is of a different core than the session.
+    WrongCore,
+    /// A received item doesn't follow on from the replica.
+    OutOfOrder { expected: ItemId, got: ItemId },
+    /// The peer sent a message the protocol doesn't allow at this point.
+    Protocol,
+    /// The peer holds a head signed by the core's signer that differs
+    /// from the replica's at the same length.
+    Fork(Box<ForkProof>),
+    Proof(ProofError),
+}
+
+impl From<IsoCoreError> for ReplicateError {
+    fn from(err: IsoCoreError) -> Self {
+        return ReplicateError::IsoCore(err);
+    }
+}
+
+impl From<FormatError> for ReplicateError {
+    fn from(err: FormatError) -> Self {
+        return ReplicateError::IsoCore(err.into());
+    }
+}
+
+impl From<ProofError> for ReplicateError {
+    fn from(err: ProofError) -> Self {
+        return ReplicateError::Proof(err);
+    }
+}
+
+impl From<std::io::Error> for ReplicateError {
+    fn from(err: std::io::Error) -> Self {
+        return ReplicateError::Io(err);
+    }
+}
+
+impl From<TransportError> for ReplicateError {
+    fn from(err: TransportError) -> Self {
+        return ReplicateError::Transport(err);
+    }
+}
+
+impl From<neopack::Error> for ReplicateError {
+    fn from(err: neopack::Error) -> Self {
+        return ReplicateError::Neopack(err);
+    }
+}
+
+/// A bloom filter over leaf hashes. Leaf hashes are already uniform, so bit
+/// positions come straight from their bytes by double hashing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashFilter {
+    hashes: u8,
+    bits: Vec<u8>,
+}
+
+impl HashFilter {
+    /// An empty filter sized for `items` hashes.
+    pub fn with_capacity(items: u64) -> Self {
+        let bytes = (items * BITS_PER_ITEM).div_ceil(8).max(1);
+        return HashFilter {
+            hashes: FILTER_HASHES,
+            bits: vec![0; bytes as usize],
+        };
+    }
+
+    fn positions(&self, hash: &Hash) -> impl Iterator<Item = usize> {
+        let len = self.bits.len() as u64 * 8;
+        let a = u64::from_le_bytes(hash.0[..8].try_into().unwrap());
+        let b = u64::from_le_bytes(hash.0[8..16].try_into().unwrap()) | 1;
+        return (0..self.hashes as u64).map(move |i| (a.wrapping_add(i.wrapping_mul(b)) % len) as usize);
+    }
+
+    pub fn insert(&mut self, hash: &Hash) {
+        for bit in self.positions(hash).collect::<Vec<_>>() {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// False if `hash` was never inserted; true if it probably was.
+    pub fn contains(&self, hash: &Hash) -> bool {
+        return self.positions(hash).all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0);
+    }
+}
+
+/// The hashes a replica holds in one range of items.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeHint {
+    pub items: Range<ItemId>,
+    pub filter: HashFilter,
+}
+
+/// What the receiver holds, sent to the sender before transfer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncHint {
+    /// Consecutive ranges from item 0, in order.
+    pub ranges: Vec<RangeHint>,
+}
+
+impl SyncHint {
+    /// Summarises every item in `core`, `range_size` items per filter.
+    pub fn build(core: &mut IsoCore, range_size: u64) -> Result<Self, ReplicateError> {
+        let len = core.len().0 as u64;
+        return Self::from_pages(len, range_size, |start, count| core.page(start, count, PageDirection::OldestFirst));
+    }
+
+    /// Like `build`, for a replica.
+    pub fn build_replica(core: &mut ReadOnlyIsoCore, range_size: u64) -> Result<Self, ReplicateError> {
+        let len = core.len().0 as u64;
+        return Self::from_pages(len, range_size, |start, count| core.page(start, count, PageDirection::OldestFirst));
+    }
+
+    /// Summarises `len` items read through `page`, oldest first.
+    fn from_pages(
+        len: u64,
+        range_size: u64,
+        mut page: impl FnMut(u64, u64) -> Result<Vec<PageEntry>, IsoCoreError>,
+    ) -> Result<Self, ReplicateError> {
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        while start < len {
+            let end = (start + range_size.max(1)).min(len);
+            let mut filter = HashFilter::with_capacity(end - start);
+            for entry in page(start, end - start)? {
+                filter.insert(&entry.hash);
+            }
+            ranges.push(RangeHint { items: ItemId(start)..ItemId(end), filter });
+            start = end;
+        }
+        return Ok(SyncHint { ranges });
+    }
+
+    /// Number of items the hint covers.
+    pub fn len(&self) -> u64 {
+        return self.ranges.last().map_or(0, |range| range.items.end.0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /// Ranges of `core`'s items the hint's sender probably lacks, merged
+    /// where they touch.
+    pub fn missing(&self, core: &mut IsoCore) -> Result<Vec<Range<ItemId>>, ReplicateError> {
+        let len = core.len().0 as u64;
+        let mut missing: Vec<Range<ItemId>> = Vec::new();
+        let mut push = |item: u64| match missing.last_mut() {
+            Some(last) if last.end.0 == item => last.end = ItemId(item + 1),
+            _ => missing.push(ItemId(item)..ItemId(item + 1)),
+        };
+
+        for range in &self.ranges {
+            let start = range.items.start.0;
+            let count = range.items.end.0.min(len).saturating_sub(start);
+            for entry in core.page(start, count, PageDirection::OldestFirst)? {
+                if !range.filter.contains(&entry.hash) {
+                    push(entry.item_id.0);
+                }
+            }
+        }
+        for item in self.len()..len {
+            push(item);
+        }
+        return Ok(missing);
+    }
+}
+
+/// An item as sent between replicas: its data and the signature its
+/// append produced in the source core.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedItem {
+    pub item_id: ItemId,
+    pub data: Vec<u8>,
+    pub signature: Signature,
+}
+
+/// One message of the replication protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncMessage {
+    Hint(SyncHint),
+    Offer(Vec<Range<ItemId>>),
+    /// The receiver asks for one chunk of items.
+    Request(Range<ItemId>),
+    /// The sender's reply to a `Request`, possibly cut short.
+    Items(Vec<SignedItem>),
+    /// The receiver asks for the sender's signed head at a length.
+    ProveHead(u64),
+    /// The sender's reply to a `ProveHead`.
+    Head(HeadProof),
+}
+
+impl SyncMessage {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        match self {
+            SyncMessage::Hint(hint) => {
+                list.str("hint")?;
+                let mut ranges = list.list()?;
+                for range in &hint.ranges {
+                    let mut entry = ranges.list()?;
+                    entry.u64(range.items.start.0)?;
+                    entry.u64(range.items.end.0)?;
+                    entry.u8(range.filter.hashes)?;
+                    entry.bytes(&range.filter.bits)?;
+                    entry.finish()?;
+                }
+                ranges.finish()?;
+            }
+            SyncMessage::Offer(offer) => {
+                list.str("offer")?;
+                let mut ranges = list.list()?;
+                for range in offer {
+                    let mut entry = ranges.list()?;
+                    entry.u64(range.start.0)?;
+                    entry.u64(range.end.0)?;
+                    entry.finish()?;
+                }
+                ranges.finish()?;
+            }
+            SyncMessage::Request(range) => {
+                list.str("request")?;
+                list.u64(range.start.0)?;
+                list.u64(range.end.0)?;
+            }
+            SyncMessage::Items(items) => {
+                list.str("items")?;
+                let mut entries = list.list()?;
+                for item in items {
+                    let mut entry = entries.list()?;
+                    entry.u64(item.item_id.0)?;
+                    entry.bytes(&item.data)?;
+                    item.signature.write(&mut entry)?;
+                    entry.finish()?;
+                }
+                entries.finish()?;
+            }
+            SyncMessage::ProveHead(len) => {
+                list.str("prove_head")?;
+                list.u64(*len)?;
+            }
+            SyncMessage::Head(head) => {
+                list.str("head")?;
+                list.bytes(&head.to_bytes()?)?;
+            }
+        }
+        list.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplicateError> {
+        let mut dec = Decoder::new(bytes);
+        let mut list = dec.list()?;
+        let tag = list.next()?.ok_or(neopack::Error::Malformed)?.as_str()?;
+
+        if tag == "request" {
+            let start = list.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+            let end = list.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+            return Ok(SyncMessage::Request(ItemId(start)..ItemId(end)));
+        }
+        if tag == "prove_head" {
+            let len = list.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+            return Ok(SyncMessage::ProveHead(len));
+        }
+        if tag == "head" {
+            let bytes = list.next()?.ok_or(neopack::Error::Malformed)?.as_bytes()?;
+            return Ok(SyncMessage::Head(HeadProof::from_bytes(bytes)?));
+        }
+
+        let Some(ValueDecoder::List(mut entries)) = list.next()? else {
+            return Err(neopack::Error::Malformed.into());
+        };
+
+        if tag == "items" {
+            let mut items = Vec::new();
+            while let Some(entry) = entries.next()? {
+                let ValueDecoder::List(mut entry) = entry else {
+                    return Err(neopack::Error::Malformed.into());
+                };
+                let mut next = || entry.next()?.ok_or(neopack::Error::Malformed);
+                let item_id = ItemId(next()?.as_u64()?);
+                let data = next()?.as_bytes()?.to_vec();
+                let signature = Signature::read(next()?)?;
+                items.push(SignedItem { item_id, data, signature });
+            }
+            return Ok(SyncMessage::Items(items));
+        }
+
+        let mut items = Vec::new();
+        let mut filters = Vec::new();
+        while let Some(entry) = entries.next()? {
+            let ValueDecoder::List(mut entry) = entry else {
+                return Err(neopack::Error::Malformed.into());
+            };
+            let mut next = || entry.next()?.ok_or(neopack::Error::Malformed);
+            let start = next()?.as_u64()?;
+            let end = next()?.as_u64()?;
+            if start >= end || items.last().is_some_and(|last: &Range<ItemId>| last.end.0 > start) {
+                return Err(ReplicateError::BadHint);
+            }
+            items.push(ItemId(start)..ItemId(end));
+            if tag == "hint" {
+                let hashes = next()?.as_u8()?;
+                let bits = next()?.as_bytes()?.to_vec();
+                if bits.is_empty() {
+                    return Err(ReplicateError::BadHint);
+                }
+                filters.push(HashFilter { hashes, bits });
+            }
+        }
+
+        return match tag {
+            "hint" => {
+                // Hint ranges must tile the items from 0
+                let mut end = 0;
+                for range in &items {
+                    if range.start.0 != end {
+                        return Err(ReplicateError::BadHint);
+                    }
+                    end = range.end.0;
+                }
+                let ranges = items.into_iter()
+                    .zip(filters)
+                    .map(|(items, filter)| RangeHint { items, filter })
+                    .collect();
+                Ok(SyncMessage::Hint(SyncHint { ranges }))
+            }
+            "offer" => Ok(SyncMessage::Offer(items)),
+            _ => Err(neopack::Error::Malformed.into()),
+        };
+    }
+}
+
+/// Limits a `ReplicationSession` works within.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionLimits {
+    /// Most items asked for in one `Request`.
+    pub chunk_items: u64,
+    /// Most data bytes `serve` puts in one reply. A reply always holds at
+    /// least one item, however large.
+    pub chunk_bytes: usize,
+    /// Average data bytes received per second, if limited.
+    pub bytes_per_second: Option<u64>,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        return SessionLimits {
+            chunk_items: 256,
+            chunk_bytes: 1 << 20,
+            bytes_per_second: None,
+        };
+    }
+}
+
+/// The receiving side of replication from one peer: how far the replica
+/// has been verified, and how far it has to go.
+///
+/// Every item is checked against its source signature as it is appended,
+/// so a resumed session starts from the replica's length and never checks
+/// an item twice. Saved as a neopack Map: `peer: Bytes`, `core: Bytes`,
+/// `verified: U64`, `target: U64`.
+#[derive(Debug)]
+pub struct ReplicationSession {
+    /// Who items are fetched from.
+    pub peer: KeyPub,
+    /// Signer of the core being replicated.
+    pub core: KeyPub,
+    /// Items verified and appended to the replica.
+    verified: u64,
+    /// Length of the source, as far as the peer has offered.
+    target: u64,
+    limits: SessionLimits,
+    /// Start of this run, for the bandwidth limit; not saved.
+    started: Instant,
+    received_bytes: u64,
+}
+
+impl ReplicationSession {
+    pub fn new(peer: KeyPub, core: KeyPub, limits: SessionLimits) -> Self {
+        return ReplicationSession {
+            peer,
+            core,
+            verified: 0,
+            target: 0,
+            limits,
+            started: Instant::now(),
+            received_bytes: 0,
+        };
+    }
+
+    /// Reads a session saved with `save`.
+    pub fn load<P: AsRef<Path>>(path: P, limits: SessionLimits) -> Result<Self, ReplicateError> {
+        let bytes = std::fs::read(path)?;
+        let (_, body) = Artifact::Session.read_header(&bytes)?;
+        let mut dec = Decoder::new(body);
+        let mut map = dec.map()?;
+        let mut field = |name: &str| match map.next()? {
+            Some((key, value)) if key == name => Ok(value),
+            _ => Err(ReplicateError::Neopack(neopack::Error::Malformed)),
+        };
+
+        let peer = KeyPub::read(field("peer")?)?;
+        let core = KeyPub::read(field("core")?)?;
+        let verified = field("verified")?.as_u64()?;
+        let target = field("target")?.as_u64()?;
+
+        let mut session = Self::new(peer, core, limits);
+        session.verified = verified;
+        session.target = target;
+        return Ok(session);
+    }
+
+    /// Writes the session via a temporary file, so a crash never leaves a
+    /// torn one.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ReplicateError> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        self.peer.write(&mut map.key("peer")?)?;
+        self.core.write(&mut map.key("core")?)?;
+        map.key("verified")?.u64(self.verified)?;
+        map.key("target")?.u64(self.target)?;
+        map.finish()?;
+
+        let path = path.as_ref();
+        write_atomic(path, &Artifact::Session.with_header(enc.as_bytes()), Durability::Full)?;
+        return Ok(());
+    }
+
+    /// The next item to fetch.
+    pub fn verified(&self) -> ItemId {
+        return ItemId(self.verified);
+    }
+
+    pub fn is_done(&self) -> bool {
+        return self.verified >= self.target;
+    }
+
+    /// Picks up from `replica`, which may have lost unflushed items since
+    /// the session was saved, or gained some from another peer.
+    pub fn resume(&mut self, replica: &ReadOnlyIsoCore) -> Result<(), ReplicateError> {
+        if replica.signer() != &self.core {
+            return Err(ReplicateError::WrongCore);
+        }
+        self.verified = replica.len().0 as u64;
+        return Ok(());
+    }
+
+    /// Extends the target to the end of the peer's offer.
+    pub fn offered(&mut self, offer: &[Range<ItemId>]) {
+        if let Some(end) = offer.iter().map(|range| range.end.0).max() {
+            self.target = self.target.max(end);
+        }
+    }
+
+    /// The next chunk to ask the peer for, if any remain.
+    pub fn next_request(&self) -> Option<Range<ItemId>> {
+        if self.is_done() {
+            return None;
+        }
+        let end = (self.verified + self.limits.chunk_items.max(1)).min(self.target);
+        return Some(ItemId(self.verified)..ItemId(end));
+    }
+
+    /// Verifies and appends `items` to `replica`. They must follow on from
+    /// what the replica holds. Returns how many were appended.
+    pub fn receive(&mut self, replica: &mut ReadOnlyIsoCore, items: &[SignedItem]) -> Result<u64, ReplicateError> {
+        if replica.signer() != &self.core {
+            return Err(ReplicateError::WrongCore);
+        }
+        for item in items {
+            let expected = ItemId(replica.len().0 as u64);
+            if item.item_id != expected {
+                return Err(ReplicateError::OutOfOrder { expected, got: item.item_id });
+            }
+            replica.add_signed(&item.data, &item.signature)?;
+            self.verified = item.item_id.0 + 1;
+            self.received_bytes += item.data.len() as u64;
+        }
+        return Ok(items.len() as u64);
+    }
+
+    /// How long to wait before the next request to stay within the
+    /// bandwidth limit.
+    pub fn delay(&self) -> Duration {
+        let Some(rate) = self.limits.bytes_per_second else {
+            return Duration::ZERO;
+        };
+        let allowed = Duration::from_secs_f64(self.received_bytes as f64 / rate.max(1) as f64);
+        return allowed.saturating_sub(self.started.elapsed());
+    }
+}
+
+/// The sender's reply to a request for `range`: the items it holds there,
+/// cut short at `limits.chunk_bytes`.
+pub fn serve(core: &mut IsoCore, range: Range<ItemId>, limits: &SessionLimits) -> Result<Vec<SignedItem>, ReplicateError> {
+    let start = range.start.0;
+    let count = range.end.0.saturating_sub(start).min(limits.chunk_items.max(1));
+    let mut items = Vec::new();
+    let mut bytes = 0;
+    for entry in core.page(start, count, PageDirection::OldestFirst)? {
+        bytes += entry.data.len();
+        if bytes > limits.chunk_bytes && !items.is_empty() {
+            break;
+        }
+        let block = core.get_signature(entry.item_id)?;
+        items.push(SignedItem {
+            item_id: entry.item_id,
+            data: entry.data,
+            signature: block.signature().ok_or(IsoCoreError::ThresholdCore)?.clone(),
+        });
+    }
+    return Ok(items);
+}
+
+/// What a state machine wants done next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Send `message` once `after` has passed, then hand the reply back.
+    Send { message: SyncMessage, after: Duration },
+    Done,
+}
+
+/// The receiving side of the protocol, without I/O: feed it the peer's
+/// messages and carry out the `Step`s it returns.
+#[derive(Debug)]
+pub struct SyncReceiver {
+    pub session: ReplicationSession,
+    /// Items summarised by each filter in the opening hint.
+    pub hint_range: u64,
+}
+
+impl SyncReceiver {
+    pub fn new(session: ReplicationSession) -> Self {
+        return SyncReceiver { session, hint_range: DEFAULT_HINT_RANGE };
+    }
+
+    /// Resumes the session from `replica` and opens with a hint.
+    pub fn start(&mut self, replica: &mut ReadOnlyIsoCore) -> Result<Step, ReplicateError> {
+        self.session.resume(replica)?;
+        let hint = SyncHint::build_replica(replica, self.hint_range)?;
+        return Ok(Step::Send { message: SyncMessage::Hint(hint), after: Duration::ZERO });
+    }
+
+    pub fn handle(&mut self, replica: &mut ReadOnlyIsoCore, message: SyncMessage) -> Result<Step, ReplicateError> {
+        match message {
+            SyncMessage::Offer(offer) => {
+                self.session.offered(&offer);
+                // Items offered below the replica's length differ from its own
+                let len = replica.len().0 as u64;
+                if offer.first().is_some_and(|range| range.start.0 < len) {
+                    let end = offer.iter().map(|range| range.end.0).max().unwrap_or(len);
+                    return Ok(Step::Send { message: SyncMessage::ProveHead(len.min(end)), after: Duration::ZERO });
+                }
+            }
+            SyncMessage::Head(head) => {
+                if let Some(fork) = replica.check_head(&head)? {
+                    return Err(ReplicateError::Fork(Box::new(fork)));
+                }
+            }
+            SyncMessage::Items(items) => {
+                // A peer that offered items must make progress on them
+                if items.is_empty() && !self.session.is_done() {
+                    return Err(ReplicateError::Protocol);
+                }
+                self.session.receive(replica, &items)?;
+            }
+            SyncMessage::Hint(_) | SyncMessage::Request(_) | SyncMessage::ProveHead(_) => {
+                return Err(ReplicateError::Protocol);
+            }
+        }
+        return Ok(match self.session.next_request() {
+            Some(range) => Step::Send {
+                message: SyncMessage::Request(range),
+                after: self.session.delay(),
+            },
+            None => Step::Done,
+        });
+    }
+}
+
+/// The sending side of the protocol, without I/O: every message it is
+/// given gets exactly one reply.
+#[derive(Debug, Clone, Default)]
+pub struct SyncSender {
+    pub limits: SessionLimits,
+}
+
+impl SyncSender {
+    pub fn handle(&self, core: &mut IsoCore, message: SyncMessage) -> Result<SyncMessage, ReplicateError> {
+        return match message {
+            SyncMessage::Hint(hint) => Ok(SyncMessage::Offer(hint.missing(core)?)),
+            SyncMessage::Request(range) => Ok(SyncMessage::Items(serve(core, range, &self.limits)?)),
+            SyncMessage::ProveHead(len) => Ok(SyncMessage::Head(core.prove_head(len)?)),
+            SyncMessage::Offer(_) | SyncMessage::Items(_) | SyncMessage::Head(_) => Err(ReplicateError::Protocol),
+        };
+    }
+}
+
+/// Runs `machine` over `transport` until the replica is up to date.
+pub fn run_receiver<T: Transport>(transport: &mut T, machine: &mut SyncReceiver, replica: &mut ReadOnlyIsoCore) -> Result<(), ReplicateError> {
+    let mut step = machine.start(replica)?;
+    while let Step::Send { message, after } = step {
+        std::thread::sleep(after);
+        transport.send(&message.to_bytes()?)?;
+        let reply = SyncMessage::from_bytes(&transport.recv()?)?;
+        step = machine.handle(replica, reply)?;
+    }
+    return Ok(());
+}
+
+/// Answers requests from `transport` until the other end closes it.
+pub fn run_sender<T: Transport>(transport: &mut T, machine: &SyncSender, core: &mut IsoCore) -> Result<(), ReplicateError> {
+    loop {
+        let message = match transport.recv() {
+            Err(TransportError::Closed) => return Ok(()),
+            message => message?,
+        };
+        let reply = machine.handle(core, SyncMessage::from_bytes(&message)?)?;
+        transport.send(&reply.to_bytes()?)?;
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+    use crate::key::KeyPair;
+
+    #[test]
+    fn hint_narrows_transfer() {
+        let signer = KeyPair::ephemeral();
+        let mut sender = IsoCore::create_mem(&signer);
+        let mut receiver = IsoCore::create_mem(&signer);
+        for i in 0..300u32 {
+            sender.add_message(&i.to_le_bytes(), &signer).unwrap();
+            if i < 250 {
+                receiver.add_message(&i.to_le_bytes(), &signer).unwrap();
+            }
+        }
+
+        let mut receiver = ReadOnlyIsoCore::from(receiver);
+        let hint = SyncHint::build_replica(&mut receiver, 64).unwrap();
+        assert_eq!(hint.ranges.len(), 4);
+        let bytes = SyncMessage::Hint(hint.clone()).to_bytes().unwrap();
+        let SyncMessage::Hint(received) = SyncMessage::from_bytes(&bytes).unwrap() else {
+            panic!("expected a hint");
+        };
+        assert_eq!(received, hint);
+
+        let offer = received.missing(&mut sender).unwrap();
+        assert_eq!(offer, vec![ItemId(250)..ItemId(300)]);
+        let bytes = SyncMessage::Offer(offer.clone()).to_bytes().unwrap();
+        assert_eq!(SyncMessage::from_bytes(&bytes).unwrap(), SyncMessage::Offer(offer));
+
+        // A replica that diverged mid-range is offered the differing items
+        let other = KeyPair::ephemeral();
+        let mut forked = IsoCore::create_mem(&other);
+        for i in 0..250u32 {
+            let message = if i == 100 { 9999 } else { i };
+            forked.add_message(&message.to_le_bytes(), &other).unwrap();
+        }
+        let hint = SyncHint::build(&mut forked, 64).unwrap();
+        let offer = hint.missing(&mut sender).unwrap();
+        assert_eq!(offer, vec![ItemId(100)..ItemId(101), ItemId(250)..ItemId(300)]);
+    }
+
+    #[test]
+    fn hint_rejects_gaps() {
+        let mut enc = Encoder::new();
+        let mut list = enc.list().unwrap();
+        list.str("hint").unwrap();
+        let mut ranges = list.list().unwrap();
+        let mut entry = ranges.list().unwrap();
+        entry.u64(5).unwrap();
+        entry.u64(10).unwrap();
+        entry.u8(7).unwrap();
+        entry.bytes(&[0; 8]).unwrap();
+        entry.finish().unwrap();
+        ranges.finish().unwrap();
+        list.finish().unwrap();
+        assert!(matches!(SyncMessage::from_bytes(enc.as_bytes()), Err(ReplicateError::BadHint)));
+    }
+
+    #[test]
+    fn session_resumes_after_interruption() {
+        let path = std::path::PathBuf::from("/tmp/test_replicate_session.nd");
+        let _ = std::fs::remove_file(&path);
+        let signer = KeyPair::ephemeral();
+        let peer = KeyPair::ephemeral();
+        let mut source = IsoCore::create_mem(&signer);
+        for i in 0..100u32 {
+            source.add_message(format!("item {}", i).as_bytes(), &signer).unwrap();
+        }
+
+        let limits = SessionLimits { chunk_items: 16, ..SessionLimits::default() };
+        let mut replica = ReadOnlyIsoCore::replica_mem(&signer.key_pub, source.version());
+        let mut session = ReplicationSession::new(peer.key_pub.clone(), signer.key_pub.clone(), limits);
+        let hint = SyncHint::build_replica(&mut replica, DEFAULT_HINT_RANGE).unwrap();
+        session.offered(&hint.missing(&mut source).unwrap());
+
+        // Three chunks, then the link drops
+        for _ in 0..3 {
+            let request = session.next_request().unwrap();
+            let reply = SyncMessage::Items(serve(&mut source, request, &limits).unwrap());
+            let SyncMessage::Items(items) = SyncMessage::from_bytes(&reply.to_bytes().unwrap()).unwrap() else {
+                panic!("expected items");
+            };
+            session.receive(&mut replica, &items).unwrap();
+        }
+        assert_eq!(session.verified(), ItemId(48));
+        session.save(&path).unwrap();
+
+        let mut session = ReplicationSession::load(&path, limits).unwrap();
+        session.resume(&replica).unwrap();
+        assert_eq!(session.next_request(), Some(ItemId(48)..ItemId(64)));
+        while let Some(request) = session.next_request() {
+            let items = serve(&mut source, request, &limits).unwrap();
+            session.receive(&mut replica, &items).unwrap();
+        }
+        assert!(session.is_done());
+        assert_eq!(replica.verify_head().unwrap(), source.verify_head().unwrap());
+
+        // Chunks are cut at the byte limit, but always make progress
+        let small = SessionLimits { chunk_bytes: 1, ..limits };
+        assert_eq!(serve(&mut source, ItemId(0)..ItemId(16), &small).unwrap().len(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn session_rejects_bad_items() {
+        let signer = KeyPair::ephemeral();
+        let mut source = IsoCore::create_mem(&signer);
+        source.add_messages(["a", "b", "c"], &signer).unwrap();
+        let limits = SessionLimits { bytes_per_second: Some(1), ..SessionLimits::default() };
+        let mut session = ReplicationSession::new(signer.key_pub.clone(), signer.key_pub.clone(), limits);
+        let mut replica = ReadOnlyIsoCore::replica_mem(&signer.key_pub, source.version());
+
+        let mut items = serve(&mut source, ItemId(0)..ItemId(3), &limits).unwrap();
+        items[1].data = b"forged".to_vec();
+        assert!(matches!(
+            session.receive(&mut replica, &items[1..]),
+            Err(ReplicateError::OutOfOrder { expected: ItemId(0), got: ItemId(1) })
+        ));
+        assert!(matches!(
+            session.receive(&mut replica, &items),
+            Err(ReplicateError::IsoCore(IsoCoreError::IntegrityError))
+        ));
+        assert_eq!(replica.len().0, 1);
+        assert!(session.delay() > Duration::ZERO);
+
+        let other = KeyPair::ephemeral();
+        assert!(matches!(
+            session.resume(&IsoCore::create_mem(&other).into()),
+            Err(ReplicateError::WrongCore)
+        ));
+    }
+
+    #[test]
+    fn state_machines_replicate_over_transports() {
+        let signer = KeyPair::ephemeral();
+        let mut source = IsoCore::create_mem(&signer);
+        for i in 0..40u32 {
+            source.add_message(&i.to_le_bytes(), &signer).unwrap();
+        }
+        let root = source.verify_head().unwrap();
+        let version = source.version();
+        let limits = SessionLimits { chunk_items: 8, ..SessionLimits::default() };
+
+        // Driven by hand, a receiver refuses messages out of turn
+        let session = ReplicationSession::new(signer.key_pub.clone(), signer.key_pub.clone(), limits);
+        let mut receiver = SyncReceiver::new(session);
+        let mut replica = ReadOnlyIsoCore::replica_mem(&signer.key_pub, version);
+        let Step::Send { message, .. } = receiver.start(&mut replica).unwrap() else {
+            panic!("expected a hint");
+        };
+        let sender = SyncSender { limits };
+        assert!(matches!(sender.handle(&mut source, message.clone()).unwrap(), SyncMessage::Offer(_)));
+        assert!(matches!(receiver.handle(&mut replica, message), Err(ReplicateError::Protocol)));
+
+        let (mut near, mut far) = crate::transport::MemTransport::pair();
+        let serving = std::thread::spawn(move || {
+            run_sender(&mut far, &sender, &mut source).unwrap();
+            return source;
+        });
+        let session = ReplicationSession::new(signer.key_pub.clone(), signer.key_pub.clone(), limits);
+        let mut receiver = SyncReceiver::new(session);
+        run_receiver(&mut near, &mut receiver, &mut replica).unwrap();
+        drop(near);
+        let mut source = serving.join().unwrap();
+        assert_eq!(replica.verify_head().unwrap(), root);
+
+        // Over TCP, a second replica catches up to new items
+        source.add_message(b"late", &signer).unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let serving = std::thread::spawn(move || {
+            let mut far = crate::transport::TcpTransport::new(listener.accept().unwrap().0);
+            run_sender(&mut far, &SyncSender { limits }, &mut source).unwrap();
+        });
+        let mut near = crate::transport::TcpTransport::connect(addr).unwrap();
+        run_receiver(&mut near, &mut receiver, &mut replica).unwrap();
+        drop(near);
+        serving.join().unwrap();
+        assert_eq!(replica.len().0, 41);
+        assert_eq!(&*replica.get_message(ItemId(40)).unwrap(), b"late");
+    }
+
+    #[test]
+    fn receiver_proves_a_fork() {
+        let signer = KeyPair::ephemeral();
+        let mut source = IsoCore::create_mem(&signer);
+        let mut forked = IsoCore::create_mem(&signer);
+        for i in 0..30u32 {
+            source.add_message(&i.to_le_bytes(), &signer).unwrap();
+            if i < 20 {
+                let message = if i == 5 { 9999 } else { i };
+                forked.add_message(&message.to_le_bytes(), &signer).unwrap();
+            }
+        }
+        let mut replica = ReadOnlyIsoCore::from(forked);
+        let sender = SyncSender::default();
+        let session = ReplicationSession::new(signer.key_pub.clone(), signer.key_pub.clone(), SessionLimits::default());
+        let mut receiver = SyncReceiver::new(session);
+
+        let Step::Send { message, .. } = receiver.start(&mut replica).unwrap() else {
+            panic!("expected a hint");
+        };
+        let offer = sender.handle(&mut source, message).unwrap();
+        let Step::Send { message, .. } = receiver.handle(&mut replica, offer).unwrap() else {
+            panic!("expected a head request");
+        };
+        assert_eq!(message, SyncMessage::ProveHead(20));
+        let head = sender.handle(&mut source, message).unwrap();
+        let head = SyncMessage::from_bytes(&head.to_bytes().unwrap()).unwrap();
+        let Err(ReplicateError::Fork(proof)) = receiver.handle(&mut replica, head) else {
+            panic!("expected a fork");
+        };
+        assert_eq!(proof.verify(&signer.key_pub).unwrap(), 20);
+        assert_eq!(replica.len().0, 20);
+    }
+}
@@ -0,0 +1,343 @@
+//! Schemas: typed, evolvable messages in a core
+//!
+//! A `SchemaRegistry` keeps schema definitions in a meta-core of their own,
+//! an IsoCore that holds nothing else. A schema's id is its item id there,
+//! so ids never change and old definitions stay readable. A schema evolves
+//! by registering a new definition under the same name; `latest` finds the
+//! newest.
+//!
+//! Data messages name the schema they were written with, and `decode`
+//! resolves it, checks the message against it, and returns a `Record`.
+//! Fields the schema doesn't know are kept rather than rejected, so a
+//! reader with an older schema can still read newer messages.
+//!
+//! A schema is a neopack Map: `name: String`, `fields: List<[name: String,
+//! tag: U8, required: Bool]>`, where `tag` is the field's neopack `Tag`.
+//! A data message is a neopack List: `[schema: U64, body: Map]`.
+
+use std::path::Path;
+use std::path::PathBuf;
+use crate::covering::ItemId;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::key::KeyPair;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+use crate::neopack::MapEncoder;
+use crate::neopack::Tag;
+use crate::neopack::ValueDecoder;
+
+#[derive(Debug)]
+pub enum SchemaError {
+    IsoCore(IsoCoreError),
+    Neopack(neopack::Error),
+    /// The meta-core item isn't a well-formed schema.
+    BadSchema(ItemId),
+    UnknownSchema(SchemaId),
+    MissingField { schema: SchemaId, field: String },
+    WrongType { schema: SchemaId, field: String, expected: Tag },
+}
+
+impl From<IsoCoreError> for SchemaError {
+    fn from(err: IsoCoreError) -> Self {
+        return SchemaError::IsoCore(err);
+    }
+}
+
+impl From<neopack::Error> for SchemaError {
+    fn from(err: neopack::Error) -> Self {
+        return SchemaError::Neopack(err);
+    }
+}
+
+/// A schema's item id in the meta-core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SchemaId(pub u64);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDef {
+    pub name: String,
+    pub tag: Tag,
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub name: String,
+    pub fields: Vec<FieldDef>,
+}
+
+impl Schema {
+    pub fn new(name: &str) -> Self {
+        return Schema { name: name.to_string(), fields: Vec::new() };
+    }
+
+    pub fn required(mut self, name: &str, tag: Tag) -> Self {
+        self.fields.push(FieldDef { name: name.to_string(), tag, required: true });
+        return self;
+    }
+
+    pub fn optional(mut self, name: &str, tag: Tag) -> Self {
+        self.fields.push(FieldDef { name: name.to_string(), tag, required: false });
+        return self;
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldDef> {
+        return self.fields.iter().find(|field| field.name == name);
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut map = enc.map()?;
+        map.key("name")?.str(&self.name)?;
+        let mut fields = map.key("fields")?.list()?;
+        for field in &self.fields {
+            let mut entry = fields.list()?;
+            entry.str(&field.name)?;
+            entry.u8(field.tag as u8)?;
+            entry.bool(field.required)?;
+            entry.finish()?;
+        }
+        fields.finish()?;
+        map.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, neopack::Error> {
+        let mut dec = Decoder::new(bytes);
+        let mut map = dec.map()?;
+        let Some(("name", name)) = map.next()? else {
+            return Err(neopack::Error::Malformed);
+        };
+        let Some(("fields", ValueDecoder::List(mut list))) = map.next()? else {
+            return Err(neopack::Error::Malformed);
+        };
+
+        let mut fields = Vec::new();
+        while let Some(entry) = list.next()? {
+            let ValueDecoder::List(mut entry) = entry else {
+                return Err(neopack::Error::Malformed);
+            };
+            let mut next = || entry.next()?.ok_or(neopack::Error::Malformed);
+            let name = next()?.as_str()?.to_string();
+            let tag = next()?.as_u8()?;
+            let tag = Tag::from_u8(tag).ok_or(neopack::Error::InvalidTag(tag))?;
+            let required = next()?.as_bool()?;
+            fields.push(FieldDef { name, tag, required });
+        }
+        return Ok(Schema { name: name.as_str()?.to_string(), fields });
+    }
+}
+
+/// A data message checked against its schema.
+#[derive(Debug)]
+pub struct Record<'a> {
+    pub schema_id: SchemaId,
+    pub schema: &'a Schema,
+    /// Every field in the message with its raw encoded value.
+    fields: Vec<(&'a str, &'a [u8])>,
+}
+
+impl<'a> Record<'a> {
+    /// The value of `name`, or `None` if the message doesn't have it.
+    pub fn get(&self, name: &str) -> Result<Option<ValueDecoder<'a>>, neopack::Error> {
+        let Some((_, raw)) = self.fields.iter().find(|(field, _)| *field == name) else {
+            return Ok(None);
+        };
+        return Ok(Some(Decoder::new(raw).value()?));
+    }
+
+    /// Fields the message has but its schema doesn't, such as those added
+    /// by a newer writer.
+    pub fn unknown(&self) -> impl Iterator<Item = &'a str> + '_ {
+        return self.fields.iter()
+            .map(|(name, _)| *name)
+            .filter(|name| self.schema.field(name).is_none());
+    }
+}
+
+#[derive(Debug)]
+pub struct SchemaRegistry {
+    /// The meta-core; holds only schemas.
+    core: IsoCore,
+    /// Every schema in the meta-core, by id.
+    schemas: Vec<Schema>,
+}
+
+impl SchemaRegistry {
+    /// Wraps a meta-core, reading every schema already in it.
+    pub fn new(core: IsoCore) -> Result<Self, SchemaError> {
+        let mut registry = SchemaRegistry { core, schemas: Vec::new() };
+        for item in 0..registry.core.len().0 as u64 {
+            let item_id = ItemId(item);
+            let schema = Schema::from_bytes(&registry.core.get_message(item_id)?)
+                .map_err(|_| SchemaError::BadSchema(item_id))?;
+            registry.schemas.push(schema);
+        }
+        return Ok(registry);
+    }
+
+    pub fn create_mem(signer: &KeyPair) -> Self {
+        return SchemaRegistry { core: IsoCore::create_mem(signer), schemas: Vec::new() };
+    }
+
+    pub fn create(path: PathBuf, signer: &KeyPair) -> Result<Self, SchemaError> {
+        return Ok(SchemaRegistry { core: IsoCore::create(path, signer)?, schemas: Vec::new() });
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SchemaError> {
+        return Self::new(IsoCore::load(path)?);
+    }
+
+    /// The meta-core, for reads such as its root or signer. Schemas are
+    /// added through `register`, which keeps the ids in step with it.
+    pub fn core(&self) -> &IsoCore {
+        return &self.core;
+    }
+
+    pub fn flush(&mut self) -> Result<(), SchemaError> {
+        self.core.flush()?;
+        return Ok(());
+    }
+
+    /// Appends `schema` to the meta-core, returning its id.
+    pub fn register(&mut self, schema: Schema, signer: &KeyPair) -> Result<SchemaId, SchemaError> {
+        let id = SchemaId(self.core.len().0 as u64);
+        self.core.add_message(&schema.to_bytes()?, signer)?;
+        self.schemas.push(schema);
+        return Ok(id);
+    }
+
+    pub fn get(&self, id: SchemaId) -> Option<&Schema> {
+        return self.schemas.get(id.0 as usize);
+    }
+
+    /// The newest schema registered under `name`.
+    pub fn latest(&self, name: &str) -> Option<(SchemaId, &Schema)> {
+        return self.schemas.iter()
+            .enumerate()
+            .rev()
+            .find(|(_, schema)| schema.name == name)
+            .map(|(id, schema)| (SchemaId(id as u64), schema));
+    }
+
+    /// Encodes a data message for schema `id`, with the body written by
+    /// `fields`, and checks it against the schema.
+    pub fn encode<F>(&self, id: SchemaId, fields: F) -> Result<Vec<u8>, SchemaError>
+    where
+        F: FnOnce(&mut MapEncoder) -> Result<(), neopack::Error>,
+    {
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        list.u64(id.0)?;
+        let mut body = list.map()?;
+        fields(&mut body)?;
+        body.finish()?;
+        list.finish()?;
+
+        let bytes = enc.into_bytes();
+        self.decode(&bytes)?;
+        return Ok(bytes);
+    }
+
+    /// Resolves a data message's schema and checks the message against it:
+    /// required fields must be present, and known fields must have the
+    /// schema's type.
+    pub fn decode<'a>(&'a self, message: &'a [u8]) -> Result<Record<'a>, SchemaError> {
+        let mut dec = Decoder::new(message);
+        if dec.read_tag()? != Tag::List {
+            return Err(neopack::Error::TypeMismatch.into());
+        }
+        dec.cursor_mut().skip(4)?;
+        let schema_id = SchemaId(dec.u64()?);
+        let schema = self.get(schema_id).ok_or(SchemaError::UnknownSchema(schema_id))?;
+
+        // Walk the body by hand to keep each value's raw bytes
+        let body = dec.raw_value()?;
+        let mut dec = Decoder::new(body);
+        if dec.read_tag()? != Tag::Map {
+            return Err(neopack::Error::TypeMismatch.into());
+        }
+        dec.cursor_mut().skip(4)?;
+        let mut fields = Vec::new();
+        while dec.remaining() > 0 {
+            let name = dec.str()?;
+            let raw = dec.raw_value()?;
+            if let Some(field) = schema.field(name)
+                && raw[0] != field.tag as u8 {
+                return Err(SchemaError::WrongType { schema: schema_id, field: name.to_string(), expected: field.tag });
+            }
+            fields.push((name, raw));
+        }
+
+        for field in schema.fields.iter().filter(|field| field.required) {
+            if !fields.iter().any(|(name, _)| *name == field.name) {
+                return Err(SchemaError::MissingField { schema: schema_id, field: field.name.clone() });
+            }
+        }
+        return Ok(Record { schema_id, schema, fields });
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schemas_evolve_and_validate() {
+        let path = PathBuf::from("/tmp/test_schema_registry");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+
+        let mut registry = SchemaRegistry::create(path.clone(), &signer).unwrap();
+        let v1 = registry.register(
+            Schema::new("post").required("title", Tag::String),
+            &signer,
+        ).unwrap();
+        let v2 = registry.register(
+            Schema::new("post").required("title", Tag::String).optional("likes", Tag::U64),
+            &signer,
+        ).unwrap();
+        assert_eq!(registry.latest("post").unwrap().0, v2);
+        assert_eq!(registry.core().len().0, 2);
+        registry.flush().unwrap();
+
+        let old = registry.encode(v1, |body| {
+            body.key("title")?.str("hello")?;
+            return Ok(());
+        }).unwrap();
+        let new = registry.encode(v2, |body| {
+            body.key("title")?.str("again")?;
+            body.key("likes")?.u64(3)?;
+            body.key("colour")?.str("blue")?;
+            return Ok(());
+        }).unwrap();
+
+        // Schemas are read back from the meta-core
+        let registry = SchemaRegistry::load(&path).unwrap();
+        let record = registry.decode(&old).unwrap();
+        assert_eq!(record.schema_id, v1);
+        assert_eq!(record.get("title").unwrap().unwrap().as_str().unwrap(), "hello");
+        assert!(record.get("likes").unwrap().is_none());
+
+        let record = registry.decode(&new).unwrap();
+        assert_eq!(record.get("likes").unwrap().unwrap().as_u64().unwrap(), 3);
+        assert_eq!(record.unknown().collect::<Vec<_>>(), vec!["colour"]);
+
+        assert!(matches!(
+            registry.encode(v2, |body| { body.key("likes")?.u64(1)?; return Ok(()); }),
+            Err(SchemaError::MissingField { .. })
+        ));
+        assert!(matches!(
+            registry.encode(v2, |body| { body.key("title")?.u64(1)?; return Ok(()); }),
+            Err(SchemaError::WrongType { expected: Tag::String, .. })
+        ));
+        assert!(matches!(
+            registry.encode(SchemaId(9), |_| Ok(())),
+            Err(SchemaError::UnknownSchema(SchemaId(9)))
+        ));
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
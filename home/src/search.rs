@@ -0,0 +1,370 @@
+//! Search: an inverted index over the text of a core's documents
+//!
+//! A `SearchIndex` maps each token to the items it appears in and how
+//! often. Like a `View`, it follows a source IsoCore: `update` indexes the
+//! items added since the last call and appends their postings to the
+//! index's own core as one page, so a reopened index replays its pages
+//! and carries on from where it stopped.
+//!
+//! Text comes from an `Extract` function, `document_text` for `site`
+//! documents by default; items it returns `None` for are skipped. Text is
+//! tokenized as markup, so styling and blob references don't get in the
+//! way, and tokens are lowercased runs of letters and digits.
+//!
+//! A query is words separated by whitespace, all of which must match. A
+//! word ending in `*` matches any token it's a prefix of. Results are
+//! scored by tf-idf: each matching token counts its occurrences in the
+//! item, weighted by how rare it is among indexed documents.
+//!
+//! A page is a neopack List: `[start: U64, end: U64, documents: U64,
+//! postings: Map]`, covering source items `start..end`, of which
+//! `documents` had text. `postings` maps each token to an Array of U64
+//! pairs, item id then count.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use crate::core::Core;
+use crate::core::CoreError;
+use crate::core::MessageId;
+use crate::covering::ItemId;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::markup;
+use crate::markup::Item;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+use crate::neopack::Tag;
+use crate::neopack::ValueDecoder;
+use crate::site::Document;
+
+#[derive(Debug)]
+pub enum SearchError {
+    Core(CoreError),
+    IsoCore(IsoCoreError),
+    Neopack(neopack::Error),
+    /// The index covers more items than the source holds, so it was built
+    /// over a different or since-truncated core.
+    AheadOfSource { applied: u64, source: u64 },
+    /// A page doesn't start where the one before it ended.
+    Gap { expected: u64, found: u64 },
+}
+
+impl From<CoreError> for SearchError {
+    fn from(err: CoreError) -> Self {
+        return SearchError::Core(err);
+    }
+}
+
+impl From<IsoCoreError> for SearchError {
+    fn from(err: IsoCoreError) -> Self {
+        return SearchError::IsoCore(err);
+    }
+}
+
+impl From<neopack::Error> for SearchError {
+    fn from(err: neopack::Error) -> Self {
+        return SearchError::Neopack(err);
+    }
+}
+
+/// Pulls the markup text out of an item, if it has any.
+pub type Extract = fn(&[u8]) -> Option<String>;
+
+/// The text of a `site::Document`.
+pub fn document_text(message: &[u8]) -> Option<String> {
+    return Document::from_bytes(message).ok().map(|document| document.text);
+}
+
+/// Lowercased runs of letters and digits in `text`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    return text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+}
+
+/// Tokens of the text of each markup item, styling left out.
+fn markup_tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for item in markup::parse_string(text.to_string()) {
+        let (Item::Heading(frag) | Item::Bullet(frag) | Item::Line(frag)) = item;
+        tokens.extend(tokenize(&frag.plain()));
+    }
+    return tokens;
+}
+
+#[derive(Debug)]
+pub struct SearchIndex {
+    /// Each token's items, with how often it appears in each.
+    postings: BTreeMap<String, BTreeMap<ItemId, u64>>,
+    /// Number of source items indexed.
+    applied: u64,
+    /// Number of indexed items that had text.
+    documents: u64,
+    extract: Extract,
+    pages: Core,
+}
+
+impl SearchIndex {
+    pub fn create_mem(extract: Extract) -> Self {
+        return Self::empty(extract, Core::create_mem());
+    }
+
+    /// Opens the page core at `path`, replaying its pages, or creates it.
+    pub fn open(path: PathBuf, extract: Extract) -> Result<Self, SearchError> {
+        if !path.exists() {
+            return Ok(Self::empty(extract, Core::create(path)?));
+        }
+
+        let mut index = Self::empty(extract, Core::load(&path)?);
+        for page in 0..index.pages.len().0 {
+            let bytes = index.pages.get_contents(MessageId(page))?.into_owned();
+            index.replay(&bytes)?;
+        }
+        return Ok(index);
+    }
+
+    fn empty(extract: Extract, pages: Core) -> Self {
+        return Self {
+            postings: BTreeMap::new(),
+            applied: 0,
+            documents: 0,
+            extract,
+            pages,
+        };
+    }
+
+    /// The next source item to be indexed.
+    pub fn applied(&self) -> ItemId {
+        return ItemId(self.applied);
+    }
+
+    /// Number of indexed items that had text.
+    pub fn documents(&self) -> u64 {
+        return self.documents;
+    }
+
+    /// Indexes every source item not yet indexed, then writes their
+    /// postings as a page if there were any. Returns how many items were
+    /// indexed.
+    pub fn update(&mut self, source: &mut IsoCore) -> Result<u64, SearchError> {
+        let len = source.len().0 as u64;
+        if self.applied > len {
+            return Err(SearchError::AheadOfSource { applied: self.applied, source: len });
+        }
+        if self.applied == len {
+            return Ok(0);
+        }
+
+        let start = self.applied;
+        let mut documents = 0;
+        let mut page: BTreeMap<String, BTreeMap<ItemId, u64>> = BTreeMap::new();
+        for item in start..len {
+            let item_id = ItemId(item);
+            let Some(text) = (self.extract)(&source.get_message(item_id)?) else {
+                continue;
+            };
+            documents += 1;
+            for token in markup_tokens(&text) {
+                *page.entry(token).or_default().entry(item_id).or_default() += 1;
+            }
+        }
+
+        self.pages.add_message(&encode_page(start, len, documents, &page)?)?;
+        self.pages.flush()?;
+        self.merge(len, documents, page);
+        return Ok(len - start);
+    }
+
+    fn replay(&mut self, bytes: &[u8]) -> Result<(), SearchError> {
+        let mut list = Decoder::new(bytes).list()?;
+        let start = list.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+        let end = list.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+        let documents = list.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+        if start != self.applied || end < start {
+            return Err(SearchError::Gap { expected: self.applied, found: start });
+        }
+
+        let Some(ValueDecoder::Map(mut map)) = list.next()? else {
+            return Err(neopack::Error::Malformed.into());
+        };
+        let mut page: BTreeMap<String, BTreeMap<ItemId, u64>> = BTreeMap::new();
+        while let Some((token, value)) = map.next()? {
+            let ValueDecoder::Array(mut array) = value else {
+                return Err(neopack::Error::TypeMismatch.into());
+            };
+            let items = page.entry(token.to_string()).or_default();
+            while let Some(item) = array.next()? {
+                let count = array.next()?.ok_or(neopack::Error::Malformed)?;
+                items.insert(ItemId(item.as_u64()?), count.as_u64()?);
+            }
+        }
+        self.merge(end, documents, page);
+        return Ok(());
+    }
+
+    fn merge(&mut self, end: u64, documents: u64, page: BTreeMap<String, BTreeMap<ItemId, u64>>) {
+        for (token, items) in page {
+            self.postings.entry(token).or_default().extend(items);
+        }
+        self.applied = end;
+        self.documents += documents;
+    }
+
+    /// Items matching every word of `query`, best first; ties go to the
+    /// older item. An empty query matches nothing.
+    pub fn search(&self, query: &str) -> Vec<(ItemId, f64)> {
+        let mut scores: Option<BTreeMap<ItemId, f64>> = None;
+        for word in query.split_whitespace() {
+            let (word, prefix) = match word.strip_suffix('*') {
+                Some(stem) => (stem, true),
+                None => (word, false),
+            };
+            let terms = tokenize(word);
+            for (i, term) in terms.iter().enumerate() {
+                // Only the last token of a word like `re-ind*` is a prefix
+                let matches = self.term_scores(term, prefix && i + 1 == terms.len());
+                scores = Some(match scores {
+                    None => matches,
+                    Some(scores) => scores
+                        .into_iter()
+                        .filter_map(|(item, score)| Some((item, score + matches.get(&item)?)))
+                        .collect(),
+                });
+            }
+        }
+
+        let mut results: Vec<(ItemId, f64)> = scores.unwrap_or_default().into_iter().collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        return results;
+    }
+
+    /// Each item containing `term`, or with `prefix` any token starting
+    /// with it, and its tf-idf score for the term.
+    fn term_scores(&self, term: &str, prefix: bool) -> BTreeMap<ItemId, f64> {
+        let mut scores = BTreeMap::new();
+        let tokens: Vec<&BTreeMap<ItemId, u64>> = match prefix {
+            true => self.postings
+                .range(term.to_string()..)
+                .take_while(|(token, _)| token.starts_with(term))
+                .map(|(_, items)| items)
+                .collect(),
+            false => self.postings.get(term).into_iter().collect(),
+        };
+        for items in tokens {
+            let idf = (1.0 + self.documents as f64 / items.len() as f64).ln();
+            for (&item, &count) in items {
+                *scores.entry(item).or_insert(0.0) += count as f64 * idf;
+            }
+        }
+        return scores;
+    }
+}
+
+fn encode_page(
+    start: u64,
+    end: u64,
+    documents: u64,
+    page: &BTreeMap<String, BTreeMap<ItemId, u64>>,
+) -> Result<Vec<u8>, neopack::Error> {
+    let mut enc = Encoder::new();
+    let mut list = enc.list()?;
+    list.u64(start)?;
+    list.u64(end)?;
+    list.u64(documents)?;
+    let mut map = list.map()?;
+    for (token, items) in page {
+        let mut array = map.key(token)?.array(Tag::U64, 8)?;
+        for (item, count) in items {
+            array.u64(item.0)?;
+            array.u64(*count)?;
+        }
+        array.finish()?;
+    }
+    map.finish()?;
+    list.finish()?;
+    return Ok(enc.into_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::KeyPair;
+    use crate::neopack::Timestamp;
+
+    fn items(results: &[(ItemId, f64)]) -> Vec<u64> {
+        return results.iter().map(|(item, _)| item.0).collect();
+    }
+
+    #[test]
+    fn tokenizes_markup_text() {
+        assert_eq!(tokenize("Héllo, *World* 42x"), ["héllo", "world", "42x"]);
+        let hash = "ab".repeat(32);
+        assert_eq!(markup_tokens(&format!("# A *bold* title\n- ![a cat](hash:{hash})")), ["a", "bold", "title", "a", "cat"]);
+    }
+
+    #[test]
+    fn search_and_queries_and_prefixes() {
+        let signer = KeyPair::ephemeral();
+        let mut source = IsoCore::create_mem(&signer);
+        let mut index = SearchIndex::create_mem(document_text);
+        let texts = [
+            "# Rust notes\nownership and *borrowing*",
+            "# Gardening\nrust on the roses, rust everywhere",
+            "# Cooking\nroast vegetables",
+        ];
+        for text in texts {
+            Document::new(Timestamp(0), text).append(&mut source, &signer).unwrap();
+        }
+        source.add_message(b"not a document", &signer).unwrap();
+        assert_eq!(index.update(&mut source).unwrap(), 4);
+        assert_eq!(index.documents(), 3);
+
+        // The gardening note says rust twice
+        assert_eq!(items(&index.search("rust")), [1, 0]);
+        assert_eq!(items(&index.search("RUST borrowing")), [0]);
+        assert_eq!(items(&index.search("rust roast")), Vec::<u64>::new());
+        assert_eq!(items(&index.search("ro*")), [1, 2]);
+        // Rarer tokens weigh more: roast is in one note, rust in two
+        assert_eq!(items(&index.search("r*")), [1, 2, 0]);
+        assert_eq!(items(&index.search("borrow")), Vec::<u64>::new());
+        assert_eq!(items(&index.search("")), Vec::<u64>::new());
+        let scores = index.search("rust");
+        assert!(scores[0].1 > scores[1].1);
+    }
+
+    #[test]
+    fn index_persists_in_pages() {
+        let path = PathBuf::from("/tmp/test_search_index.nd");
+        let _ = std::fs::remove_file(&path);
+        let signer = KeyPair::ephemeral();
+        let mut source = IsoCore::create_mem(&signer);
+
+        let mut index = SearchIndex::open(path.clone(), document_text).unwrap();
+        Document::new(Timestamp(0), "first apple").append(&mut source, &signer).unwrap();
+        Document::new(Timestamp(0), "second apple").append(&mut source, &signer).unwrap();
+        assert_eq!(index.update(&mut source).unwrap(), 2);
+        assert_eq!(index.update(&mut source).unwrap(), 0);
+        drop(index);
+
+        let mut index = SearchIndex::open(path.clone(), document_text).unwrap();
+        assert_eq!(index.applied(), ItemId(2));
+        Document::new(Timestamp(0), "third apricot").append(&mut source, &signer).unwrap();
+        assert_eq!(index.update(&mut source).unwrap(), 1);
+        drop(index);
+
+        let index = SearchIndex::open(path.clone(), document_text).unwrap();
+        assert_eq!((index.applied(), index.documents()), (ItemId(3), 3));
+        // apricot is rarer than apple, so the note with it ranks first
+        assert_eq!(items(&index.search("ap*")), [2, 0, 1]);
+        assert_eq!(items(&index.search("apple second")), [1]);
+
+        let mut other = IsoCore::create_mem(&signer);
+        let mut index = index;
+        assert!(matches!(index.update(&mut other), Err(SearchError::AheadOfSource { applied: 3, source: 0 })));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
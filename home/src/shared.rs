@@ -0,0 +1,281 @@
+//! Shared handles to an IsoCore, for reading from many threads
+//!
+//! `IsoCore::into_shared` moves a core behind a `SharedIsoCore`, a handle
+//! that is cheap to clone and can be sent to other threads. Every clone
+//! reads and writes the same core.
+//!
+//! Reads and writes go to two copies of the core, each behind its own
+//! mutex. Appends go to the core itself, and each appended item is queued
+//! with its signature for an in-memory replica that serves reads. A
+//! reader catches the replica up from the queue before it reads, so a
+//! slow reader holds up other readers but never an append, and an append
+//! never waits for a reader. The replica holds every item in memory, and
+//! a threshold core, whose items carry no single signature, can't be
+//! shared.
+//!
+//! The head, the length and global root, is published separately: writes
+//! through the handle update it once their append has succeeded and been
+//! queued, and `head` reads it without touching either copy, so it never
+//! waits on a read or write.
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+use crate::covering::ItemId;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::key::Hash;
+use crate::key::KeyPair;
+use crate::key::Signature;
+use crate::proof::InclusionProof;
+
+/// The latest state of a core.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Head {
+    pub len: u64,
+    /// The signed global root after the last item; `None` when empty.
+    pub root: Option<Hash>,
+}
+
+/// An appended item on its way to the replica.
+#[derive(Debug)]
+struct Queued {
+    /// The payload, or for a light core's item without one, its leaf hash.
+    body: Result<Vec<u8>, Hash>,
+    signature: Signature,
+}
+
+#[derive(Debug, Clone)]
+pub struct SharedIsoCore {
+    core: Arc<Mutex<IsoCore>>,
+    replica: Arc<Mutex<IsoCore>>,
+    queue: Arc<Mutex<Vec<Queued>>>,
+    head: Arc<RwLock<Head>>,
+}
+
+impl SharedIsoCore {
+    /// Copies every item of `core` into the replica reads are served
+    /// from. Fails with `ThresholdCore` for a threshold core.
+    pub fn new(mut core: IsoCore) -> Result<Self, IsoCoreError> {
+        let mut replica = match core.is_light() {
+            true => IsoCore::light_mem(core.signer(), core.version()),
+            false => IsoCore::replica_mem(core.signer(), core.version()),
+        };
+        for item in 0..core.len().0 as u64 {
+            apply(&mut replica, queued(&mut core, ItemId(item))?)?;
+        }
+        let head = read_head(&mut core)?;
+        return Ok(SharedIsoCore {
+            core: Arc::new(Mutex::new(core)),
+            replica: Arc::new(Mutex::new(replica)),
+            queue: Arc::new(Mutex::new(Vec::new())),
+            head: Arc::new(RwLock::new(head)),
+        });
+    }
+
+    /// The head as of the last completed write.
+    pub fn head(&self) -> Head {
+        return self.head.read().unwrap_or_else(PoisonError::into_inner).clone();
+    }
+
+    pub fn len(&self) -> u64 {
+        return self.head().len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    pub fn get_message(&self, item_id: ItemId) -> Result<Vec<u8>, IsoCoreError> {
+        return Ok(self.read()?.get_message(item_id)?.into_owned());
+    }
+
+    /// See `IsoCore::prove`.
+    pub fn prove(&self, item_id: ItemId, len: u64) -> Result<InclusionProof, IsoCoreError> {
+        return self.read()?.prove(item_id, len);
+    }
+
+    pub fn add_message(&self, message: &[u8], signer: &KeyPair) -> Result<Hash, IsoCoreError> {
+        let mut core = self.lock();
+        let root = core.add_message(message, signer)?;
+        let item_id = ItemId(core.len().0 as u64 - 1);
+        let block = core.get_signature(item_id)?;
+        let signature = block.signature().ok_or(IsoCoreError::ThresholdCore)?.clone();
+        self.queue(vec![Queued { body: Ok(message.to_vec()), signature }]);
+        self.publish(core.len().0 as u64, root.clone());
+        return Ok(root);
+    }
+
+    /// See `IsoCore::add_signed`.
+    pub fn add_signed(&self, message: &[u8], signature: &Signature) -> Result<Hash, IsoCoreError> {
+        let mut core = self.lock();
+        let root = core.add_signed(message, signature)?;
+        self.queue(vec![Queued { body: Ok(message.to_vec()), signature: signature.clone() }]);
+        self.publish(core.len().0 as u64, root.clone());
+        return Ok(root);
+    }
+
+    pub fn flush(&self) -> Result<(), IsoCoreError> {
+        return self.lock().flush();
+    }
+
+    /// Runs `f` with the core locked for writing, for anything the handle
+    /// doesn't cover. Items `f` appends are queued for readers, and the
+    /// head is read again afterwards.
+    pub fn with<R>(&self, f: impl FnOnce(&mut IsoCore) -> R) -> Result<R, IsoCoreError> {
+        let mut core = self.lock();
+        let before = core.len().0 as u64;
+        let result = f(&mut core);
+        let appended = (before..core.len().0 as u64)
+            .map(|item| queued(&mut core, ItemId(item)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.queue(appended);
+        let head = read_head(&mut core)?;
+        *self.head.write().unwrap_or_else(PoisonError::into_inner) = head;
+        return Ok(result);
+    }
+
+    /// Takes the core back if this is the last handle.
+    pub fn try_unwrap(self) -> Result<IsoCore, Self> {
+        let SharedIsoCore { core, replica, queue, head } = self;
+        return match Arc::try_unwrap(core) {
+            Ok(core) => Ok(core.into_inner().unwrap_or_else(PoisonError::into_inner)),
+            Err(core) => Err(SharedIsoCore { core, replica, queue, head }),
+        };
+    }
+
+    /// The core, for writing. Appends are atomic, so a core whose lock was
+    /// poisoned by a panic elsewhere is still consistent; the same goes
+    /// for the replica and the queue.
+    fn lock(&self) -> MutexGuard<'_, IsoCore> {
+        return self.core.lock().unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// The replica, caught up with every item queued so far.
+    fn read(&self) -> Result<MutexGuard<'_, IsoCore>, IsoCoreError> {
+        let mut replica = self.replica.lock().unwrap_or_else(PoisonError::into_inner);
+        let queued = std::mem::take(&mut *self.queue.lock().unwrap_or_else(PoisonError::into_inner));
+        for item in queued {
+            apply(&mut replica, item)?;
+        }
+        return Ok(replica);
+    }
+
+    fn queue(&self, items: Vec<Queued>) {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner).extend(items);
+    }
+
+    fn publish(&self, len: u64, root: Hash) {
+        *self.head.write().unwrap_or_else(PoisonError::into_inner) = Head { len, root: Some(root) };
+    }
+}
+
+/// Item `item_id` of `core` as it would be queued for the replica.
+fn queued(core: &mut IsoCore, item_id: ItemId) -> Result<Queued, IsoCoreError> {
+    let block = core.get_signature(item_id)?;
+    let signature = block.signature().ok_or(IsoCoreError::ThresholdCore)?.clone();
+    let body = match core.get_message(item_id) {
+        Ok(data) => Ok(data.into_owned()),
+        Err(IsoCoreError::NeedsData { hash, .. }) => Err(hash),
+        Err(e) => return Err(e),
+    };
+    return Ok(Queued { body, signature });
+}
+
+fn apply(replica: &mut IsoCore, item: Queued) -> Result<(), IsoCoreError> {
+    match item.body {
+        Ok(data) => replica.add_signed(&data, &item.signature)?,
+        Err(hash) => replica.add_signed_hash(&hash, &item.signature)?,
+    };
+    return Ok(());
+}
+
+fn read_head(core: &mut IsoCore) -> Result<Head, IsoCoreError> {
+    let len = core.len().0 as u64;
+    if len == 0 {
+        return Ok(Head { len, root: None });
+    }
+    let block = core.get_signature(ItemId(len - 1))?;
+    return Ok(Head { len, root: Some(block.global_root) });
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn readers_share_a_core_with_the_writer() {
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::create_mem(&signer);
+        core.add_message(b"first", &signer).unwrap();
+        let shared = core.into_shared().unwrap();
+        assert_eq!(shared.len(), 1);
+
+        let readers: Vec<_> = (0..4).map(|_| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    let head = shared.head();
+                    let last = ItemId(head.len - 1);
+                    assert!(!shared.get_message(last).unwrap().is_empty());
+                    let proof = shared.prove(ItemId(0), head.len).unwrap();
+                    assert_eq!(proof.len, head.len);
+                }
+            })
+        }).collect();
+        for i in 0..20 {
+            let root = shared.add_message(format!("item {}", i).as_bytes(), &signer).unwrap();
+            assert_eq!(shared.head().root, Some(root));
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        assert_eq!(shared.head().len, 21);
+        let appended = shared.with(|core| core.add_message(b"direct", &signer)).unwrap().unwrap();
+        assert_eq!(shared.head(), Head { len: 22, root: Some(appended) });
+
+        let clone = shared.clone();
+        let shared = shared.try_unwrap().unwrap_err();
+        drop(clone);
+        let mut core = shared.try_unwrap().unwrap();
+        assert_eq!(&*core.get_message(ItemId(21)).unwrap(), b"direct");
+    }
+
+    #[test]
+    fn a_slow_reader_doesnt_hold_up_appends() {
+        let path = std::path::PathBuf::from("/tmp/test_shared_slow_reader");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::create_inline(path.clone(), &signer, 16).unwrap();
+        core.add_message(b"inline", &signer).unwrap();
+        core.add_message(&[7; 100], &signer).unwrap();
+        let shared = core.into_shared().unwrap();
+
+        // Hold the replica, as a reader part way through a read would
+        let reading = shared.read().unwrap();
+        let (done, appended) = std::sync::mpsc::channel();
+        let writer = {
+            let shared = shared.clone();
+            let signer = signer.clone();
+            thread::spawn(move || {
+                shared.add_message(b"third", &signer).unwrap();
+                done.send(()).unwrap();
+            })
+        };
+        appended.recv_timeout(std::time::Duration::from_secs(10)).expect("the append waited for a reader");
+        assert_eq!(shared.len(), 3);
+        drop(reading);
+        writer.join().unwrap();
+
+        assert_eq!(shared.get_message(ItemId(0)).unwrap(), b"inline");
+        assert_eq!(shared.get_message(ItemId(2)).unwrap(), b"third");
+        let proof = shared.prove(ItemId(1), 3).unwrap();
+        let mut core = shared.try_unwrap().unwrap();
+        assert_eq!(proof, core.prove(ItemId(1), 3).unwrap());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
@@ -0,0 +1,462 @@
+//! Static sites from a core of markup documents
+//!
+//! `export` renders every document in a core to a directory of HTML: a
+//! page per document, named by item id, and an `index.html` listing them
+//! newest first. The core's signed head is embedded in every page, as
+//! `home:*` meta tags and a footer, and the signature block is written to
+//! `root.sig`, so anyone with the signer's key can check that a published
+//! site matches a replica of the core.
+//!
+//! `feed` renders the same documents as an Atom feed, which `export` also
+//! writes to `feed.xml`. Feed and entry ids are `urn:home:` URNs built
+//! from the signer's key and item ids, so they stay the same wherever the
+//! site is published.
+//!
+//! Documents refer to images and attachments by hash (see `markup`).
+//! `blobs` lists every hash a core's documents refer to, and `copy_blobs`
+//! copies them from a `BlobStore` into the site's `blobs/` directory,
+//! where rendered pages and the feed, through its `xml:base`, expect them.
+//!
+//! A document is an item that is a neopack List: `[time: Timestamp, text:
+//! String]`, where `text` is markup. Other items are skipped, so documents
+//! can share a core with anything else.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::Path;
+use crate::blob::BlobError;
+use crate::blob::BlobStore;
+use crate::covering::ItemId;
+use crate::format::SignatureBlock;
+use crate::hex;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::key::Hash;
+use crate::key::KeyPair;
+use crate::key::KeyPub;
+use crate::markup;
+use crate::markup::Item;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+use crate::neopack::Timestamp;
+
+#[derive(Debug)]
+pub enum SiteError {
+    IsoCore(IsoCoreError),
+    Neopack(neopack::Error),
+    Io(std::io::Error),
+    Blob(BlobError),
+}
+
+impl From<IsoCoreError> for SiteError {
+    fn from(err: IsoCoreError) -> Self {
+        return SiteError::IsoCore(err);
+    }
+}
+
+impl From<neopack::Error> for SiteError {
+    fn from(err: neopack::Error) -> Self {
+        return SiteError::Neopack(err);
+    }
+}
+
+impl From<std::io::Error> for SiteError {
+    fn from(err: std::io::Error) -> Self {
+        return SiteError::Io(err);
+    }
+}
+
+impl From<BlobError> for SiteError {
+    fn from(err: BlobError) -> Self {
+        return SiteError::Blob(err);
+    }
+}
+
+/// A markup document and when it was written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub time: Timestamp,
+    pub text: String,
+}
+
+impl Document {
+    pub fn new(time: Timestamp, text: impl Into<String>) -> Self {
+        return Document { time, text: text.into() };
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        list.timestamp(self.time)?;
+        list.str(&self.text)?;
+        list.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, neopack::Error> {
+        let mut list = Decoder::new(bytes).list()?;
+        let time = list.next()?.ok_or(neopack::Error::Malformed)?.as_timestamp()?;
+        let text = list.next()?.ok_or(neopack::Error::Malformed)?.as_str()?;
+        return Ok(Document::new(time, text));
+    }
+
+    pub fn items(&self) -> Vec<Item> {
+        return markup::parse_string(self.text.clone());
+    }
+
+    /// The blobs the document refers to.
+    pub fn blobs(&self) -> BTreeSet<Hash> {
+        return markup::blobs(&self.items());
+    }
+
+    /// Appends the document to `core`, returning the new root.
+    pub fn append(&self, core: &mut IsoCore, signer: &KeyPair) -> Result<Hash, SiteError> {
+        return Ok(core.add_message(&self.to_bytes()?, signer)?);
+    }
+}
+
+/// A document found in a core.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub item: ItemId,
+    pub document: Document,
+}
+
+impl Entry {
+    /// The document's first heading, or its item id if it has none.
+    pub fn title(&self) -> String {
+        return markup::title(&self.document.items())
+            .unwrap_or_else(|| format!("Item {}", self.item.0));
+    }
+
+    /// Path of the entry's page, relative to the site root.
+    pub fn page(&self) -> String {
+        return format!("{}.html", self.item.0);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SiteOptions {
+    /// Heading of the index and suffix of each page title.
+    pub title: String,
+    /// Where the site is published, such as `https://example.com/notes`.
+    /// Feed entries link to their pages under it; if empty they have no
+    /// links.
+    pub base_url: String,
+}
+
+impl Default for SiteOptions {
+    fn default() -> Self {
+        return SiteOptions { title: "Home".to_string(), base_url: String::new() };
+    }
+}
+
+/// Every document in `core`, newest first.
+pub fn documents(core: &mut IsoCore) -> Result<Vec<Entry>, SiteError> {
+    let mut entries = Vec::new();
+    for i in 0..core.len().0 as u64 {
+        let item = ItemId(i);
+        if let Ok(document) = Document::from_bytes(&core.get_message(item)?) {
+            entries.push(Entry { item, document });
+        }
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse((entry.document.time, entry.item)));
+    return Ok(entries);
+}
+
+/// The signed head a site was exported at.
+struct Head {
+    signer: KeyPub,
+    len: u64,
+    block: Option<SignatureBlock>,
+}
+
+impl Head {
+    fn read(core: &mut IsoCore) -> Result<Self, SiteError> {
+        let len = core.len().0 as u64;
+        let block = match len {
+            0 => None,
+            _ => Some(core.get_signature(ItemId(len - 1))?),
+        };
+        return Ok(Head { signer: core.signer().clone(), len, block });
+    }
+
+    fn meta(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "<meta name=\"home:signer\" content=\"{}\">", hex::encode(&self.signer.0));
+        let _ = writeln!(out, "<meta name=\"home:length\" content=\"{}\">", self.len);
+        if let Some(block) = &self.block {
+            let _ = writeln!(out, "<meta name=\"home:root\" content=\"{}\">", hex::encode(&block.global_root.0));
+            for (_, signature) in &block.signatures {
+                let _ = writeln!(out, "<meta name=\"home:signature\" content=\"{}\">", hex::encode(&signature.0));
+            }
+        }
+        return out;
+    }
+
+    fn footer(&self) -> String {
+        let mut out = String::from("<footer>\n");
+        let _ = writeln!(out, "<p>Signer <code>{}</code>, {} items</p>", hex::encode(&self.signer.0), self.len);
+        if let Some(block) = &self.block {
+            let _ = writeln!(out, "<p>Root <code>{}</code></p>", hex::encode(&block.global_root.0));
+            for (_, signature) in &block.signatures {
+                let _ = writeln!(out, "<p>Signature <code>{}</code></p>", hex::encode(&signature.0));
+            }
+        }
+        out.push_str("</footer>\n");
+        return out;
+    }
+}
+
+fn page(title: &str, head: &Head, body: &str) -> String {
+    return format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n{}</head>\n<body>\n{}{}</body>\n</html>\n",
+        markup::escape_html(title),
+        head.meta(),
+        body,
+        head.footer(),
+    );
+}
+
+/// Writes the site for `core` into `dir`, creating it if needed. Returns
+/// how many documents were exported.
+pub fn export(core: &mut IsoCore, dir: &Path, options: &SiteOptions) -> Result<usize, SiteError> {
+    let head = Head::read(core)?;
+    let entries = documents(core)?;
+    std::fs::create_dir_all(dir)?;
+
+    let mut index = format!("<h1>{}</h1>\n<p><a href=\"feed.xml\">Feed</a></p>\n<ul>\n", markup::escape_html(&options.title));
+    for entry in &entries {
+        let title = entry.title();
+        let time = format_time(entry.document.time);
+        let _ = writeln!(
+            index,
+            "<li><time datetime=\"{}\">{}</time> <a href=\"{}\">{}</a></li>",
+            time,
+            &time[..10],
+            entry.page(),
+            markup::escape_html(&title),
+        );
+
+        let body = format!(
+            "<p><a href=\"index.html\">{}</a></p>\n<article>\n<time datetime=\"{}\">{}</time>\n{}</article>\n",
+            markup::escape_html(&options.title),
+            time,
+            &time[..10],
+            markup::to_html(&entry.document.items()),
+        );
+        let title = format!("{} - {}", title, options.title);
+        std::fs::write(dir.join(entry.page()), page(&title, &head, &body))?;
+    }
+    index.push_str("</ul>\n");
+    std::fs::write(dir.join("index.html"), page(&options.title, &head, &index))?;
+
+    if let Some(block) = &head.block {
+        std::fs::write(dir.join("root.sig"), block.to_bytes())?;
+    }
+    std::fs::write(dir.join("feed.xml"), render_feed(&head, &entries, options))?;
+    return Ok(entries.len());
+}
+
+/// Every blob the documents in `core` refer to.
+pub fn blobs(core: &mut IsoCore) -> Result<BTreeSet<Hash>, SiteError> {
+    let mut hashes = BTreeSet::new();
+    for entry in documents(core)? {
+        hashes.extend(entry.document.blobs());
+    }
+    return Ok(hashes);
+}
+
+/// Copies every blob the documents in `core` refer to from `store` into
+/// `dir/blobs`, next to an export. Returns how many were copied; fails
+/// with `BlobError::Missing` if the store lacks one.
+pub fn copy_blobs(core: &mut IsoCore, store: &BlobStore, dir: &Path) -> Result<usize, SiteError> {
+    let hashes = blobs(core)?;
+    let blob_dir = dir.join("blobs");
+    std::fs::create_dir_all(&blob_dir)?;
+    for hash in &hashes {
+        std::fs::write(blob_dir.join(hex::encode(&hash.0)), store.get(hash)?)?;
+    }
+    return Ok(hashes.len());
+}
+
+/// An Atom feed of the documents in `core`, newest first. Each entry's
+/// title is the document's first heading and its content the rendered
+/// HTML.
+pub fn feed(core: &mut IsoCore, options: &SiteOptions) -> Result<String, SiteError> {
+    let head = Head::read(core)?;
+    let entries = documents(core)?;
+    return Ok(render_feed(&head, &entries, options));
+}
+
+fn render_feed(head: &Head, entries: &[Entry], options: &SiteOptions) -> String {
+    let signer = hex::encode(&head.signer.0);
+    let base = options.base_url.trim_end_matches('/');
+    let updated = entries.iter().map(|entry| entry.document.time).max().unwrap_or_default();
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\"");
+    if !base.is_empty() {
+        // Resolves the relative blob links in rendered content
+        let _ = write!(out, " xml:base=\"{}/\"", markup::escape_html(base));
+    }
+    out.push_str(">\n");
+    let _ = writeln!(out, "<id>urn:home:{}</id>", signer);
+    let _ = writeln!(out, "<title>{}</title>", markup::escape_html(&options.title));
+    let _ = writeln!(out, "<updated>{}</updated>", format_time(updated));
+    if !base.is_empty() {
+        let _ = writeln!(out, "<link href=\"{}/\"/>", markup::escape_html(base));
+        let _ = writeln!(out, "<link rel=\"self\" href=\"{}/feed.xml\"/>", markup::escape_html(base));
+    }
+    for entry in entries {
+        out.push_str("<entry>\n");
+        let _ = writeln!(out, "<id>urn:home:{}:{}</id>", signer, entry.item.0);
+        let _ = writeln!(out, "<title>{}</title>", markup::escape_html(&entry.title()));
+        let _ = writeln!(out, "<updated>{}</updated>", format_time(entry.document.time));
+        if !base.is_empty() {
+            let _ = writeln!(out, "<link href=\"{}/{}\"/>", markup::escape_html(base), entry.page());
+        }
+        let html = markup::to_html(&entry.document.items());
+        let _ = writeln!(out, "<content type=\"html\">{}</content>", markup::escape_html(&html));
+        out.push_str("</entry>\n");
+    }
+    out.push_str("</feed>\n");
+    return out;
+}
+
+/// Formats a timestamp as RFC 3339 in UTC, to the second.
+pub fn format_time(time: Timestamp) -> String {
+    let secs = time.0.div_euclid(1_000_000_000);
+    let days = secs.div_euclid(86_400);
+    let day_secs = secs.rem_euclid(86_400);
+
+    // Days since 1970-01-01 to a civil date, after Howard Hinnant
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    return format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, day_secs / 3_600, day_secs / 60 % 60, day_secs % 60,
+    );
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> Timestamp {
+        return Timestamp(secs * 1_000_000_000);
+    }
+
+    #[test]
+    fn formats_times() {
+        assert_eq!(format_time(at(0)), "1970-01-01T00:00:00Z");
+        assert_eq!(format_time(at(1_700_000_000)), "2023-11-14T22:13:20Z");
+        assert_eq!(format_time(at(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(format_time(at(-1)), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn exports_documents_newest_first() {
+        let dir = std::path::PathBuf::from("/tmp/test_site_export");
+        let _ = std::fs::remove_dir_all(&dir);
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::create_mem(&signer);
+
+        Document::new(at(1_700_000_000), "# Second\nbody *two*").append(&mut core, &signer).unwrap();
+        core.add_message(b"not a document", &signer).unwrap();
+        Document::new(at(1_600_000_000), "# First <post>\n- a\n- b").append(&mut core, &signer).unwrap();
+        Document::new(at(1_800_000_000), "no heading").append(&mut core, &signer).unwrap();
+
+        let options = SiteOptions { title: "Notes".to_string(), ..SiteOptions::default() };
+        assert_eq!(export(&mut core, &dir, &options).unwrap(), 3);
+
+        let index = std::fs::read_to_string(dir.join("index.html")).unwrap();
+        let third = index.find("<a href=\"3.html\">Item 3</a>").unwrap();
+        let second = index.find("<a href=\"0.html\">Second</a>").unwrap();
+        let first = index.find("<a href=\"2.html\">First &lt;post&gt;</a>").unwrap();
+        assert!(third < second && second < first);
+        assert!(index.contains("<time datetime=\"2023-11-14T22:13:20Z\">2023-11-14</time>"));
+        assert!(!dir.join("1.html").exists());
+
+        let block = core.get_signature(ItemId(3)).unwrap();
+        assert_eq!(block.global_root, core.verify_head().unwrap());
+        for page in ["index.html", "0.html", "2.html"] {
+            let html = std::fs::read_to_string(dir.join(page)).unwrap();
+            assert!(html.contains(&format!("<meta name=\"home:root\" content=\"{}\">", hex::encode(&block.global_root.0))));
+            assert!(html.contains(&format!("<meta name=\"home:signature\" content=\"{}\">", hex::encode(&block.signature().unwrap().0))));
+            assert!(html.contains("<meta name=\"home:length\" content=\"4\">"));
+        }
+        assert_eq!(std::fs::read(dir.join("root.sig")).unwrap(), block.to_bytes());
+
+        let page = std::fs::read_to_string(dir.join("2.html")).unwrap();
+        assert!(page.contains("<title>First &lt;post&gt; - Notes</title>"));
+        assert!(page.contains("<h1>First &lt;post&gt;</h1>\n<ul>\n<li>a</li>\n<li>b</li>\n</ul>\n"));
+
+        assert!(dir.join("feed.xml").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn feed_has_an_entry_per_document() {
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::create_mem(&signer);
+        let options = SiteOptions { title: "Notes & more".to_string(), base_url: "https://example.com/notes/".to_string() };
+        let empty = feed(&mut core, &options).unwrap();
+        assert!(empty.contains("<updated>1970-01-01T00:00:00Z</updated>"));
+        assert!(!empty.contains("<entry>"));
+
+        Document::new(at(1_600_000_000), "# Old").append(&mut core, &signer).unwrap();
+        Document::new(at(1_700_000_000), "# New *bold*").append(&mut core, &signer).unwrap();
+        let xml = feed(&mut core, &options).unwrap();
+
+        let signer = hex::encode(&signer.key_pub.0);
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\" xml:base=\"https://example.com/notes/\">\n"));
+        assert!(xml.contains(&format!("<id>urn:home:{}</id>\n<title>Notes &amp; more</title>\n<updated>2023-11-14T22:13:20Z</updated>\n", signer)));
+        assert!(xml.contains("<link rel=\"self\" href=\"https://example.com/notes/feed.xml\"/>"));
+        assert!(xml.contains(&format!(
+            "<entry>\n<id>urn:home:{}:1</id>\n<title>New bold</title>\n<updated>2023-11-14T22:13:20Z</updated>\n\
+             <link href=\"https://example.com/notes/1.html\"/>\n\
+             <content type=\"html\">&lt;h1&gt;New &lt;strong&gt;bold&lt;/strong&gt;&lt;/h1&gt;\n</content>\n</entry>\n",
+            signer,
+        )));
+        assert!(xml.find(":1</id>").unwrap() < xml.find(":0</id>").unwrap());
+        assert!(xml.ends_with("</feed>\n"));
+    }
+
+    #[test]
+    fn copies_referenced_blobs() {
+        let dir = std::path::PathBuf::from("/tmp/test_site_blobs");
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = BlobStore::open(dir.join("store")).unwrap();
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::create_mem(&signer);
+
+        let cat = store.put(b"cat.png").unwrap();
+        let dog = store.put(b"dog.png").unwrap();
+        store.put(b"unreferenced").unwrap();
+        let text = format!("# Pets\n![cat](hash:{})\n- ![dog](hash:{})", hex::encode(&cat.0), hex::encode(&dog.0));
+        let document = Document::new(at(1), text);
+        assert_eq!(document.blobs(), BTreeSet::from([cat.clone(), dog.clone()]));
+        document.append(&mut core, &signer).unwrap();
+        Document::new(at(2), format!("again ![cat](hash:{})", hex::encode(&cat.0))).append(&mut core, &signer).unwrap();
+
+        let site = dir.join("site");
+        assert_eq!(copy_blobs(&mut core, &store, &site).unwrap(), 2);
+        assert_eq!(std::fs::read(site.join("blobs").join(hex::encode(&dog.0))).unwrap(), b"dog.png");
+        assert_eq!(std::fs::read_dir(site.join("blobs")).unwrap().count(), 2);
+
+        let lost = crate::key::hash(b"lost");
+        Document::new(at(3), format!("![lost](hash:{})", hex::encode(&lost.0))).append(&mut core, &signer).unwrap();
+        assert!(matches!(copy_blobs(&mut core, &store, &site), Err(SiteError::Blob(BlobError::Missing(h))) if h == lost));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
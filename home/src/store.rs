@@ -4,3 +4,212 @@
 //! Each verified core has a public key.
 //! When a message is added to the verified core, it is signed.
 //! This is used to construct a merkle tree.
+//!
+//! # Frontiers
+//!
+//! When each device appends to its own core, what a replica has seen is
+//! the number of items it holds of each: a `Frontier`, a vector clock
+//! keyed by signer. Cores only grow, so one frontier covers another when
+//! it holds at least as many items of every core, and what it lacks is,
+//! core by core, the range between the two lengths. A frontier is only
+//! lengths: two replicas at the same frontier may still hold forks of a
+//! core, which its signed roots tell apart.
+//!
+//! Frontiers are neopack Lists of `[key: Fixed32, len: u64]` pairs in key
+//! order, with no zero lengths, so equal frontiers encode the same.
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+#[cfg(feature = "disk")]
+use crate::isocore::IsoCore;
+use crate::key::KeyPub;
+use crate::neopack;
+use crate::neopack::Pack;
+use crate::neopack::ValueDecoder;
+use crate::neopack::ValueWriter;
+
+/// Number of items held of each core, by signer. Cores it doesn't list
+/// are at length 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frontier {
+    lens: BTreeMap<[u8; 32], u64>,
+}
+
+impl Frontier {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// The local frontier: how far each of `cores` has got.
+    #[cfg(feature = "disk")]
+    pub fn of<'a>(cores: impl IntoIterator<Item = &'a IsoCore>) -> Self {
+        let mut frontier = Self::new();
+        for core in cores {
+            frontier.advance(core.signer(), core.len().0 as u64);
+        }
+        return frontier;
+    }
+
+    pub fn get(&self, signer: &KeyPub) -> u64 {
+        return self.lens.get(&signer.0).copied().unwrap_or(0);
+    }
+
+    /// Records that `len` items of `signer`'s core have been seen. A
+    /// shorter length than the one held changes nothing.
+    pub fn advance(&mut self, signer: &KeyPub, len: u64) {
+        if len > self.get(signer) {
+            self.lens.insert(signer.0, len);
+        }
+    }
+
+    /// Every core with at least one item, with its length, in key order.
+    pub fn iter(&self) -> impl Iterator<Item = (KeyPub, u64)> + '_ {
+        return self.lens.iter().map(|(key, len)| (KeyPub(*key), *len));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.lens.is_empty();
+    }
+
+    /// Takes the longer length of each core, as after a sync both ways.
+    pub fn merge(&mut self, other: &Frontier) {
+        for (key, len) in other.iter() {
+            self.advance(&key, len);
+        }
+    }
+
+    /// Whether this frontier holds everything `other` does.
+    pub fn covers(&self, other: &Frontier) -> bool {
+        return other.iter().all(|(key, len)| self.get(&key) >= len);
+    }
+
+    /// The items `other` holds and this frontier doesn't, by core, as
+    /// item index ranges in key order. What a sync from `other` fetches.
+    pub fn missing(&self, other: &Frontier) -> Vec<(KeyPub, Range<u64>)> {
+        return other.iter()
+            .filter_map(|(key, len)| {
+                let have = self.get(&key);
+                return (have < len).then_some((key, have..len));
+            })
+            .collect();
+    }
+}
+
+/// Frontiers are partly ordered: neither covers the other when each has
+/// seen items the other hasn't, and they compare as `None`.
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return match (self.covers(other), other.covers(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (false, false) => None,
+        };
+    }
+}
+
+impl Pack for Frontier {
+    fn write<W: ValueWriter>(&self, w: &mut W) -> neopack::Result<()> {
+        let mut list = w.list()?;
+        for (key, len) in self.iter() {
+            let mut entry = list.list()?;
+            key.write(&mut entry)?;
+            entry.u64(len)?;
+            entry.finish()?;
+        }
+        list.finish()?;
+        return Ok(());
+    }
+
+    /// Refuses entries out of key order, repeated, or at length 0, which
+    /// `write` never produces.
+    fn read(value: ValueDecoder<'_>) -> neopack::Result<Self> {
+        let ValueDecoder::List(mut list) = value else {
+            return Err(neopack::Error::TypeMismatch);
+        };
+        let mut lens = BTreeMap::new();
+        while let Some(entry) = list.next()? {
+            let ValueDecoder::List(mut entry) = entry else {
+                return Err(neopack::Error::TypeMismatch);
+            };
+            let key = KeyPub::read(entry.next()?.ok_or(neopack::Error::Malformed)?)?;
+            let len = entry.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+            if entry.next()?.is_some() || len == 0 || lens.last_key_value().is_some_and(|(last, _)| *last >= key.0) {
+                return Err(neopack::Error::Malformed);
+            }
+            lens.insert(key.0, len);
+        }
+        return Ok(Frontier { lens });
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frontiers_compare_and_plan_syncs() {
+        let [a, b, c] = [1u8, 2, 3].map(|n| KeyPub([n; 32]));
+        let mut ours = Frontier::new();
+        ours.advance(&a, 5);
+        ours.advance(&b, 2);
+        ours.advance(&b, 1);
+        let mut theirs = Frontier::new();
+        theirs.advance(&a, 3);
+        theirs.advance(&c, 4);
+
+        assert_eq!(ours.get(&b), 2);
+        assert_eq!(ours.partial_cmp(&theirs), None);
+        assert_eq!(ours.missing(&theirs), vec![(c.clone(), 0..4)]);
+        assert_eq!(theirs.missing(&ours), vec![(a.clone(), 3..5), (b.clone(), 0..2)]);
+
+        let mut both = ours.clone();
+        both.merge(&theirs);
+        assert!(both > ours && both > theirs);
+        assert!(both.missing(&ours).is_empty());
+        assert_eq!(both.partial_cmp(&both.clone()), Some(Ordering::Equal));
+
+        let bytes = both.encode().unwrap();
+        assert_eq!(Frontier::decode(&bytes).unwrap(), both);
+        assert!(Frontier::decode(&Frontier::new().encode().unwrap()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn frontier_rejects_unordered_entries() {
+        let write = |entries: &[(u8, u64)]| {
+            let mut enc = neopack::Encoder::new();
+            let mut list = enc.list().unwrap();
+            for (key, len) in entries {
+                let mut entry = list.list().unwrap();
+                entry.fixed32(&[*key; 32]).unwrap();
+                entry.u64(*len).unwrap();
+                entry.finish().unwrap();
+            }
+            list.finish().unwrap();
+            return enc.into_bytes();
+        };
+        assert!(Frontier::decode(&write(&[(1, 1), (2, 1)])).is_ok());
+        assert!(Frontier::decode(&write(&[(2, 1), (1, 1)])).is_err());
+        assert!(Frontier::decode(&write(&[(1, 1), (1, 2)])).is_err());
+        assert!(Frontier::decode(&write(&[(1, 0)])).is_err());
+    }
+
+    #[cfg(feature = "disk")]
+    #[test]
+    fn frontier_of_local_cores() {
+        use crate::key::KeyPair;
+
+        let signers = [KeyPair::ephemeral(), KeyPair::ephemeral()];
+        let mut cores: Vec<IsoCore> = signers.iter().map(IsoCore::create_mem).collect();
+        for (n, core) in cores.iter_mut().enumerate() {
+            for i in 0..=n {
+                core.add_message(&[i as u8], &signers[n]).unwrap();
+            }
+        }
+        let frontier = Frontier::of(&cores);
+        assert_eq!(frontier.get(&signers[0].key_pub), 1);
+        assert_eq!(frontier.get(&signers[1].key_pub), 2);
+    }
+}
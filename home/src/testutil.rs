@@ -0,0 +1,359 @@
+//! Fault injection for crash-consistency tests
+//!
+//! `FaultyFile` is an in-memory `LogFile` that crashes on a chosen
+//! operation. Every write, `set_len`, and sync counts as one operation;
+//! when the chosen one comes, it fails or is torn part way, and so does
+//! everything after it, as if the process had died there.
+//!
+//! A crash leaves two images worth recovering from. `contents` keeps
+//! every write that happened, as when only the process dies. `synced`
+//! keeps only what the last sync saw, as when the machine loses power
+//! and the OS never wrote back the rest. Disks that reorder writes
+//! within an unsynced span aren't modelled.
+//!
+//! Built for tests, and for embedders' tests with the `testutil`
+//! feature.
+
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::neodisk::LogFile;
+
+/// What happens at the operation a `FaultyFile` crashes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The operation doesn't happen.
+    Fail,
+    /// Only the first this many bytes of a write reach the file. Other
+    /// operations don't happen.
+    Tear(usize),
+}
+
+#[derive(Debug, Default)]
+struct State {
+    data: Vec<u8>,
+    synced: Vec<u8>,
+    pos: u64,
+    ops: usize,
+    fault: Option<(usize, Fault)>,
+    crashed: bool,
+}
+
+impl State {
+    /// Counts an operation, returning the fault if it's the one to crash on.
+    fn op(&mut self) -> io::Result<Option<Fault>> {
+        if self.crashed {
+            return Err(crashed());
+        }
+        let op = self.ops;
+        self.ops += 1;
+        return match self.fault {
+            Some((at, fault)) if at == op => {
+                self.crashed = true;
+                Ok(Some(fault))
+            }
+            _ => Ok(None),
+        };
+    }
+
+    fn write_at_pos(&mut self, buf: &[u8]) {
+        let start = self.pos as usize;
+        let end = start + buf.len();
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+        self.data[start..end].copy_from_slice(buf);
+        self.pos = end as u64;
+    }
+}
+
+fn crashed() -> io::Error {
+    return io::Error::other("injected crash");
+}
+
+/// An in-memory file that crashes on demand. Clones share the same file,
+/// so a test can keep one to inspect after handing another to a writer.
+#[derive(Debug, Clone, Default)]
+pub struct FaultyFile {
+    state: Arc<Mutex<State>>,
+}
+
+impl FaultyFile {
+    pub fn new() -> Self {
+        return FaultyFile::default();
+    }
+
+    /// A file holding `data`, all of it synced.
+    pub fn with_contents(data: Vec<u8>) -> Self {
+        let file = FaultyFile::new();
+        {
+            let mut state = file.state.lock().unwrap();
+            state.synced = data.clone();
+            state.data = data;
+        }
+        return file;
+    }
+
+    /// Crashes with `fault` on operation `op`, counting from 0.
+    pub fn inject(&self, op: usize, fault: Fault) {
+        self.state.lock().unwrap().fault = Some((op, fault));
+    }
+
+    /// Operations attempted so far.
+    pub fn ops(&self) -> usize {
+        return self.state.lock().unwrap().ops;
+    }
+
+    pub fn crashed(&self) -> bool {
+        return self.state.lock().unwrap().crashed;
+    }
+
+    /// Everything written, synced or not.
+    pub fn contents(&self) -> Vec<u8> {
+        return self.state.lock().unwrap().data.clone();
+    }
+
+    /// The file as of the last sync.
+    pub fn synced(&self) -> Vec<u8> {
+        return self.state.lock().unwrap().synced.clone();
+    }
+}
+
+impl Read for FaultyFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.crashed {
+            return Err(crashed());
+        }
+        let start = (state.pos as usize).min(state.data.len());
+        let n = buf.len().min(state.data.len() - start);
+        buf[..n].copy_from_slice(&state.data[start..start + n]);
+        state.pos += n as u64;
+        return Ok(n);
+    }
+}
+
+impl Write for FaultyFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        return match state.op()? {
+            None => {
+                state.write_at_pos(buf);
+                Ok(buf.len())
+            }
+            Some(Fault::Fail) => Err(crashed()),
+            Some(Fault::Tear(keep)) => {
+                state.write_at_pos(&buf[..keep.min(buf.len())]);
+                Err(crashed())
+            }
+        };
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+impl Seek for FaultyFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        if state.crashed {
+            return Err(crashed());
+        }
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => (state.data.len() as u64).checked_add_signed(delta),
+            SeekFrom::Current(delta) => state.pos.checked_add_signed(delta),
+        };
+        state.pos = target.ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+        return Ok(state.pos);
+    }
+}
+
+impl LogFile for FaultyFile {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.op()?.is_some() {
+            return Err(crashed());
+        }
+        state.data.resize(len as usize, 0);
+        return Ok(());
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.op()?.is_some() {
+            return Err(crashed());
+        }
+        state.synced = state.data.clone();
+        return Ok(());
+    }
+
+    fn file_len(&self) -> io::Result<u64> {
+        let state = self.state.lock().unwrap();
+        if state.crashed {
+            return Err(crashed());
+        }
+        return Ok(state.data.len() as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Core;
+    use crate::core::CoreError;
+    use crate::core::MessageId;
+    use crate::neodisk::Error;
+    use crate::neodisk::FlushPolicy;
+    use crate::neodisk::NeoDiskReader;
+    use crate::neodisk::NeoDiskWriter;
+    use crate::neopack::Encoder;
+
+    const MESSAGES: u64 = 20;
+
+    fn message(i: u64) -> Vec<u8> {
+        let mut enc = Encoder::new();
+        enc.u64(i).unwrap();
+        return enc.as_bytes().to_vec();
+    }
+
+    /// Appends `MESSAGES` messages in frames of three, flushing after
+    /// every fourth, until the file crashes. Returns how many messages
+    /// were flushed before it did.
+    fn append_until_crash(file: FaultyFile) -> u64 {
+        let Ok(writer) = NeoDiskWriter::create_in(file, 64) else {
+            return 0;
+        };
+        let mut writer = writer.with_flush_policy(FlushPolicy::Messages(3));
+        let mut flushed = 0;
+        for i in 0..MESSAGES {
+            if writer.append(&message(i)).is_err() {
+                return flushed;
+            }
+            if i % 4 == 3 {
+                if writer.flush().is_err() {
+                    return flushed;
+                }
+                flushed = i + 1;
+            }
+        }
+        return flushed;
+    }
+
+    /// Recovers a log from `image` and checks it holds a prefix of the
+    /// appended messages that can be appended to. Returns its length. An
+    /// image with neither a footer nor a whole frame is refused, and left
+    /// as it was; it counts as empty.
+    fn recover_prefix(image: Vec<u8>) -> u64 {
+        let file = FaultyFile::with_contents(image.clone());
+        let mut writer = match NeoDiskWriter::recover_in(file.clone()) {
+            Ok((writer, _)) => writer,
+            Err(Error::InvalidFormat) => {
+                assert_eq!(file.contents(), image);
+                return 0;
+            }
+            Err(e) => panic!("{e:?}"),
+        };
+        let len = writer.len();
+        assert!(len <= MESSAGES);
+
+        let reader = NeoDiskReader::from_bytes(file.contents()).unwrap();
+        assert_eq!(reader.len().unwrap(), len);
+        assert_eq!(reader.check().unwrap(), []);
+        for (i, msg) in reader.read_range(0..len).unwrap().into_iter().enumerate() {
+            assert_eq!(msg, message(i as u64));
+        }
+
+        writer.append(&message(len)).unwrap();
+        writer.flush().unwrap();
+        let reader = NeoDiskReader::from_bytes(file.contents()).unwrap();
+        assert_eq!(reader.read(crate::neodisk::MessageId(len)).unwrap(), message(len));
+        return len;
+    }
+
+    #[test]
+    fn neodisk_recovers_a_prefix_after_any_crash() {
+        let clean = FaultyFile::new();
+        assert_eq!(append_until_crash(clean.clone()), MESSAGES);
+        assert_eq!(recover_prefix(clean.contents()), MESSAGES);
+
+        let faults = [Fault::Fail, Fault::Tear(1), Fault::Tear(9), Fault::Tear(usize::MAX)];
+        for op in 0..clean.ops() {
+            for fault in faults {
+                let file = FaultyFile::new();
+                file.inject(op, fault);
+                let flushed = append_until_crash(file.clone());
+                assert!(file.crashed());
+
+                // Nothing flushed is lost, and losing what wasn't synced
+                // leaves exactly what was
+                assert!(recover_prefix(file.contents()) >= flushed, "op {op} {fault:?}");
+                assert_eq!(recover_prefix(file.synced()), flushed, "op {op} {fault:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn recovering_an_intact_log_changes_nothing() {
+        let file = FaultyFile::new();
+        append_until_crash(file.clone());
+        let before = file.contents();
+
+        let (writer, cut) = NeoDiskWriter::recover_in(FaultyFile::with_contents(before.clone())).unwrap();
+        assert_eq!((writer.len(), cut), (MESSAGES, 0));
+        let (writer, cut) = NeoDiskWriter::recover_in(FaultyFile::with_contents(before[..before.len() - 3].to_vec())).unwrap();
+        assert_eq!((writer.len(), cut), (MESSAGES, 13));
+    }
+
+    #[test]
+    fn core_recovers_a_torn_log_only_when_asked() {
+        let path = std::env::temp_dir().join("test_testutil_torn_core.nd");
+        let mut core = Core::create(path.clone()).unwrap();
+        for i in 0..10u64 {
+            core.add_message(&message(i)).unwrap();
+        }
+        core.flush().unwrap();
+        drop(core);
+
+        // A crash while the next frame was being written over the footer
+        let mut torn = std::fs::read(&path).unwrap();
+        let len = torn.len() - 16;
+        torn.truncate(len);
+        torn.extend_from_slice(&[0x42; 7]);
+        std::fs::write(&path, &torn).unwrap();
+
+        // Only loaded once asked to recover it
+        assert!(matches!(Core::load(&path), Err(CoreError::NeoDisk(Error::InvalidFormat))));
+        let (mut core, cut) = Core::recover(&path).unwrap();
+        assert_eq!(cut, 7);
+        assert_eq!(core.len(), MessageId(10));
+        assert_eq!(core.get_contents(MessageId(9)).unwrap(), message(9));
+        core.add_message(&message(10)).unwrap();
+        core.flush().unwrap();
+        drop(core);
+        assert_eq!(Core::load(&path).unwrap().len(), MessageId(11));
+
+        // A log from a newer version isn't cut, nor is a file that isn't a
+        // log
+        let mut newer = std::fs::read(&path).unwrap();
+        *newer.last_mut().unwrap() = 9;
+        std::fs::write(&path, &newer).unwrap();
+        let unsupported = |result| matches!(result, Err(CoreError::NeoDisk(Error::UnsupportedVersion { found: 9, .. })));
+        assert!(unsupported(Core::load(&path).map(|_| ())));
+        assert!(unsupported(Core::recover(&path).map(|_| ())));
+        assert_eq!(std::fs::read(&path).unwrap(), newer);
+
+        let text = b"some notes that were never a log, at all".to_vec();
+        std::fs::write(&path, &text).unwrap();
+        assert!(matches!(Core::recover(&path), Err(CoreError::NeoDisk(Error::InvalidFormat))));
+        assert_eq!(std::fs::read(&path).unwrap(), text);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
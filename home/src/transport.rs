@@ -0,0 +1,149 @@
+//! Transports: carrying framed messages between peers
+//!
+//! A `Transport` sends and receives whole messages, each already encoded
+//! with neopack; how they are framed on the wire is up to the transport.
+//! Protocols such as replication are written as state machines that only
+//! produce and consume messages, so they run over any transport, or none
+//! in tests. Transports here are blocking; an async one can drive the same
+//! state machines from its own loop.
+//!
+//! `TcpTransport` frames each message with a little-endian u32 length.
+
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::channel;
+
+/// Largest message a `TcpTransport` will send or accept.
+pub const MAX_FRAME: usize = 16 << 20;
+
+#[derive(Debug)]
+pub enum TransportError {
+    Io(std::io::Error),
+    /// The other end has gone away.
+    Closed,
+    /// A message is larger than `MAX_FRAME`.
+    FrameTooLarge(usize),
+    /// A message failed authentication on a secure channel.
+    Authentication,
+}
+
+impl From<std::io::Error> for TransportError {
+    fn from(err: std::io::Error) -> Self {
+        return TransportError::Io(err);
+    }
+}
+
+pub trait Transport {
+    fn send(&mut self, message: &[u8]) -> Result<(), TransportError>;
+    /// Blocks until a whole message arrives.
+    fn recv(&mut self) -> Result<Vec<u8>, TransportError>;
+}
+
+/// One end of an in-process connection, for tests and for peers that
+/// share a process.
+#[derive(Debug)]
+pub struct MemTransport {
+    outgoing: Sender<Vec<u8>>,
+    incoming: Receiver<Vec<u8>>,
+}
+
+impl MemTransport {
+    /// Both ends of a new connection.
+    pub fn pair() -> (Self, Self) {
+        let (a_out, b_in) = channel();
+        let (b_out, a_in) = channel();
+        let a = MemTransport { outgoing: a_out, incoming: a_in };
+        let b = MemTransport { outgoing: b_out, incoming: b_in };
+        return (a, b);
+    }
+}
+
+impl Transport for MemTransport {
+    fn send(&mut self, message: &[u8]) -> Result<(), TransportError> {
+        return self.outgoing.send(message.to_vec()).map_err(|_| TransportError::Closed);
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, TransportError> {
+        return self.incoming.recv().map_err(|_| TransportError::Closed);
+    }
+}
+
+#[derive(Debug)]
+pub struct TcpTransport {
+    stream: TcpStream,
+}
+
+impl TcpTransport {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self, TransportError> {
+        return Ok(Self::new(TcpStream::connect(addr)?));
+    }
+
+    /// Wraps an accepted or already connected stream.
+    pub fn new(stream: TcpStream) -> Self {
+        return TcpTransport { stream };
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, message: &[u8]) -> Result<(), TransportError> {
+        if message.len() > MAX_FRAME {
+            return Err(TransportError::FrameTooLarge(message.len()));
+        }
+        self.stream.write_all(&(message.len() as u32).to_le_bytes())?;
+        self.stream.write_all(message)?;
+        return Ok(());
+    }
+
+    fn recv(&mut self) -> Result<Vec<u8>, TransportError> {
+        let mut len = [0u8; 4];
+        match self.stream.read_exact(&mut len) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Err(TransportError::Closed),
+            result => result?,
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_FRAME {
+            return Err(TransportError::FrameTooLarge(len));
+        }
+        let mut message = vec![0; len];
+        self.stream.read_exact(&mut message)?;
+        return Ok(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn transports_carry_messages() {
+        let (mut a, mut b) = MemTransport::pair();
+        a.send(b"ping").unwrap();
+        assert_eq!(b.recv().unwrap(), b"ping");
+        drop(a);
+        assert!(matches!(b.recv(), Err(TransportError::Closed)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut server = TcpTransport::new(listener.accept().unwrap().0);
+            let message = server.recv().unwrap();
+            server.send(&message).unwrap();
+        });
+
+        let mut client = TcpTransport::connect(addr).unwrap();
+        client.send(b"").unwrap();
+        assert_eq!(client.recv().unwrap(), b"");
+        server.join().unwrap();
+        assert!(matches!(client.recv(), Err(TransportError::Closed)));
+        assert!(matches!(
+            client.send(&vec![0; MAX_FRAME + 1]),
+            Err(TransportError::FrameTooLarge(_))
+        ));
+    }
+}
@@ -0,0 +1,348 @@
+//! Transactions: several items of an IsoCore that stand or fall together
+//!
+//! One logical operation is sometimes several messages, such as a
+//! document's metadata and its chunks. `TxnLog::begin_txn` returns a
+//! `Txn`, whose appends are each tagged with the transaction's id, and
+//! `commit` appends a record closing it. Readers surface a transaction's
+//! entries only once they reach its commit, so a crash or an `abort`
+//! part way through leaves entries that are never seen.
+//!
+//! A transaction's id is the item id of its first record, so ids never
+//! need allocating and can't collide. A `Txn` borrows its log, so one
+//! writer has at most one transaction open; the interleavings a reader
+//! accepts are wider than that, to read logs a crash left a transaction
+//! open in.
+//!
+//! `TxnReader` follows a log as it grows, for views that fold committed
+//! transactions in as they arrive, holding the entries of open ones until
+//! they close.
+//!
+//! Records are neopack Lists: `["entry", txn: u64, payload: Bytes]`,
+//! `["commit", txn: u64, count: u64]` or `["abort", txn: u64]`, where
+//! `count` is the number of entries the commit closes.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use crate::covering::ItemId;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::key::KeyPair;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+
+#[derive(Debug)]
+pub enum TxnError {
+    IsoCore(IsoCoreError),
+    Neopack(neopack::Error),
+    /// The item isn't a well-formed record, or names a transaction that
+    /// isn't open, or a commit doesn't match its transaction's entries.
+    BadRecord(ItemId),
+}
+
+impl From<IsoCoreError> for TxnError {
+    fn from(err: IsoCoreError) -> Self {
+        return TxnError::IsoCore(err);
+    }
+}
+
+impl From<neopack::Error> for TxnError {
+    fn from(err: neopack::Error) -> Self {
+        return TxnError::Neopack(err);
+    }
+}
+
+/// One record in the log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxnRecord {
+    Entry { txn: ItemId, payload: Vec<u8> },
+    Commit { txn: ItemId, count: u64 },
+    Abort { txn: ItemId },
+}
+
+impl TxnRecord {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, neopack::Error> {
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        match self {
+            TxnRecord::Entry { txn, payload } => {
+                list.str("entry")?;
+                list.u64(txn.0)?;
+                list.bytes(payload)?;
+            }
+            TxnRecord::Commit { txn, count } => {
+                list.str("commit")?;
+                list.u64(txn.0)?;
+                list.u64(*count)?;
+            }
+            TxnRecord::Abort { txn } => {
+                list.str("abort")?;
+                list.u64(txn.0)?;
+            }
+        }
+        list.finish()?;
+        return Ok(enc.into_bytes());
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, neopack::Error> {
+        let mut dec = Decoder::new(bytes);
+        let mut list = dec.list()?;
+        let mut next = || list.next()?.ok_or(neopack::Error::Malformed);
+        let kind = next()?.as_str()?;
+        let txn = ItemId(next()?.as_u64()?);
+        return match kind {
+            "entry" => Ok(TxnRecord::Entry { txn, payload: next()?.as_bytes()?.to_vec() }),
+            "commit" => Ok(TxnRecord::Commit { txn, count: next()?.as_u64()? }),
+            "abort" => Ok(TxnRecord::Abort { txn }),
+            _ => Err(neopack::Error::Malformed),
+        };
+    }
+}
+
+/// A committed transaction, from `TxnReader` or `TxnLog::committed`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Committed {
+    pub txn: ItemId,
+    /// Item holding the commit record.
+    pub commit: ItemId,
+    /// Each entry's item id and payload, in the order they were appended.
+    pub entries: Vec<(ItemId, Vec<u8>)>,
+}
+
+/// Reads a log's records in order, yielding each transaction as it
+/// commits. See the module docs.
+#[derive(Debug, Default)]
+pub struct TxnReader {
+    next: u64,
+    /// Entries of the transactions begun and not yet closed.
+    open: BTreeMap<ItemId, Vec<(ItemId, Vec<u8>)>>,
+}
+
+impl TxnReader {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// The next item to be read.
+    pub fn position(&self) -> ItemId {
+        return ItemId(self.next);
+    }
+
+    /// Transactions begun and not yet committed or aborted.
+    pub fn open_txns(&self) -> impl Iterator<Item = ItemId> + '_ {
+        return self.open.keys().copied();
+    }
+
+    /// Reads every item of `core` not yet read, returning the
+    /// transactions committed among them in commit order. A bad record
+    /// stops the read before it, so the reader can't skip past it.
+    pub fn update(&mut self, core: &mut IsoCore) -> Result<Vec<Committed>, TxnError> {
+        let len = core.len().0 as u64;
+        let mut committed = Vec::new();
+        while self.next < len {
+            let item_id = ItemId(self.next);
+            let record = TxnRecord::from_bytes(&core.get_message(item_id)?)
+                .map_err(|_| TxnError::BadRecord(item_id))?;
+            if let Some(txn) = self.apply(item_id, record)? {
+                committed.push(txn);
+            }
+            self.next += 1;
+        }
+        return Ok(committed);
+    }
+
+    fn apply(&mut self, item_id: ItemId, record: TxnRecord) -> Result<Option<Committed>, TxnError> {
+        match record {
+            TxnRecord::Entry { txn, payload } => {
+                // A transaction begins with its first entry, whose item
+                // gives it its id
+                if txn == item_id {
+                    self.open.insert(txn, Vec::new());
+                }
+                let entries = self.open.get_mut(&txn).ok_or(TxnError::BadRecord(item_id))?;
+                entries.push((item_id, payload));
+            }
+            TxnRecord::Commit { txn, count } => {
+                let have = match txn == item_id {
+                    true => 0,
+                    false => self.open.get(&txn).ok_or(TxnError::BadRecord(item_id))?.len() as u64,
+                };
+                if have != count {
+                    return Err(TxnError::BadRecord(item_id));
+                }
+                let entries = self.open.remove(&txn).unwrap_or_default();
+                return Ok(Some(Committed { txn, commit: item_id, entries }));
+            }
+            TxnRecord::Abort { txn } => {
+                if txn != item_id && self.open.remove(&txn).is_none() {
+                    return Err(TxnError::BadRecord(item_id));
+                }
+            }
+        }
+        return Ok(None);
+    }
+}
+
+/// An open transaction. Dropping it without `commit` or `abort` leaves it
+/// open, as a crash would: its entries are never surfaced.
+#[derive(Debug)]
+pub struct Txn<'a> {
+    log: &'a mut TxnLog,
+    signer: &'a KeyPair,
+    /// Item of the first entry, once there is one.
+    id: Option<ItemId>,
+    count: u64,
+}
+
+impl Txn<'_> {
+    /// The transaction's id, once it has an entry.
+    pub fn id(&self) -> Option<ItemId> {
+        return self.id;
+    }
+
+    pub fn append(&mut self, payload: &[u8]) -> Result<ItemId, TxnError> {
+        let item_id = ItemId(self.log.len());
+        let txn = *self.id.get_or_insert(item_id);
+        self.log.write(&TxnRecord::Entry { txn, payload: payload.to_vec() }, self.signer)?;
+        self.count += 1;
+        return Ok(item_id);
+    }
+
+    /// Closes the transaction, surfacing its entries to readers. Returns
+    /// the item holding the commit record.
+    pub fn commit(self) -> Result<ItemId, TxnError> {
+        let item_id = ItemId(self.log.len());
+        let txn = self.id.unwrap_or(item_id);
+        self.log.write(&TxnRecord::Commit { txn, count: self.count }, self.signer)?;
+        return Ok(item_id);
+    }
+
+    /// Closes the transaction without surfacing its entries, which stay in
+    /// the log. Writes nothing if there were none.
+    pub fn abort(self) -> Result<(), TxnError> {
+        if let Some(txn) = self.id {
+            self.log.write(&TxnRecord::Abort { txn }, self.signer)?;
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Debug)]
+pub struct TxnLog {
+    pub core: IsoCore,
+}
+
+impl TxnLog {
+    pub fn new(core: IsoCore) -> Self {
+        return Self { core };
+    }
+
+    pub fn create_mem(signer: &KeyPair) -> Self {
+        return Self::new(IsoCore::create_mem(signer));
+    }
+
+    pub fn create(path: PathBuf, signer: &KeyPair) -> Result<Self, TxnError> {
+        return Ok(Self::new(IsoCore::create(path, signer)?));
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TxnError> {
+        return Ok(Self::new(IsoCore::load(path)?));
+    }
+
+    pub fn flush(&mut self) -> Result<(), TxnError> {
+        self.core.flush()?;
+        return Ok(());
+    }
+
+    pub fn len(&self) -> u64 {
+        return self.core.len().0 as u64;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /// Starts a transaction whose records `signer` signs.
+    pub fn begin_txn<'a>(&'a mut self, signer: &'a KeyPair) -> Txn<'a> {
+        return Txn { log: self, signer, id: None, count: 0 };
+    }
+
+    /// Every committed transaction in the log, in commit order.
+    pub fn committed(&mut self) -> Result<Vec<Committed>, TxnError> {
+        return TxnReader::new().update(&mut self.core);
+    }
+
+    fn write(&mut self, record: &TxnRecord, signer: &KeyPair) -> Result<(), TxnError> {
+        self.core.add_message(&record.to_bytes()?, signer)?;
+        return Ok(());
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn txn_entries_surface_on_commit() {
+        let path = PathBuf::from("/tmp/test_txn_log");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+        let mut log = TxnLog::create(path.clone(), &signer).unwrap();
+        let mut reader = TxnReader::new();
+
+        let mut txn = log.begin_txn(&signer);
+        assert_eq!(txn.id(), None);
+        txn.append(b"meta").unwrap();
+        txn.append(b"chunk 0").unwrap();
+        assert_eq!(txn.id(), Some(ItemId(0)));
+
+        // Nothing surfaces until the commit
+        assert!(reader.update(&mut log.core).unwrap().is_empty());
+        assert_eq!(reader.open_txns().collect::<Vec<_>>(), vec![ItemId(0)]);
+
+        let mut txn = log.begin_txn(&signer);
+        txn.append(b"chunk 1").unwrap();
+        txn.commit().unwrap();
+        let mut aborted = log.begin_txn(&signer);
+        aborted.append(b"dropped").unwrap();
+        aborted.abort().unwrap();
+        log.begin_txn(&signer).append(b"torn").unwrap();
+        log.begin_txn(&signer).commit().unwrap();
+
+        let committed = reader.update(&mut log.core).unwrap();
+        assert_eq!(committed, vec![
+            Committed { txn: ItemId(2), commit: ItemId(3), entries: vec![(ItemId(2), b"chunk 1".to_vec())] },
+            Committed { txn: ItemId(7), commit: ItemId(7), entries: vec![] },
+        ]);
+        assert_eq!(reader.position(), ItemId(8));
+        assert_eq!(reader.open_txns().collect::<Vec<_>>(), vec![ItemId(0), ItemId(6)]);
+        log.flush().unwrap();
+        drop(log);
+
+        let mut log = TxnLog::load(&path).unwrap();
+        assert_eq!(log.committed().unwrap(), committed);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn txn_rejects_records_for_closed_txns() {
+        let signer = KeyPair::ephemeral();
+        let mut log = TxnLog::create_mem(&signer);
+        let mut txn = log.begin_txn(&signer);
+        txn.append(b"a").unwrap();
+        txn.commit().unwrap();
+        log.write(&TxnRecord::Entry { txn: ItemId(0), payload: b"late".to_vec() }, &signer).unwrap();
+        assert!(matches!(log.committed(), Err(TxnError::BadRecord(ItemId(2)))));
+
+        // A commit has to close as many entries as the transaction holds
+        let mut log = TxnLog::create_mem(&signer);
+        log.begin_txn(&signer).append(b"a").unwrap();
+        log.write(&TxnRecord::Commit { txn: ItemId(0), count: 2 }, &signer).unwrap();
+        assert!(matches!(log.committed(), Err(TxnError::BadRecord(ItemId(1)))));
+
+        let mut core = IsoCore::create_mem(&signer);
+        core.add_message(b"not a record", &signer).unwrap();
+        assert!(matches!(TxnLog::new(core).committed(), Err(TxnError::BadRecord(ItemId(0)))));
+    }
+}
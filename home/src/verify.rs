@@ -0,0 +1,341 @@
+//! Verification for light clients
+//!
+//! These are the entry points for checking data from a replica you don't
+//! trust, given only the core's public key. Each takes plain byte slices,
+//! exactly as they arrive over the wire, and either returns what was
+//! proven or says why it wasn't. Nothing here reads files or needs OS
+//! randomness, so it builds with just the `std` feature, including for
+//! wasm.
+//!
+//! - `verify_signature_block`: a signature block is signed by the core.
+//! - `verify_head`: the peaks a replica reports for a length bag to the
+//!   root its signature block signs.
+//! - `verify_inclusion`: an item's data is in a signed root, by an
+//!   `InclusionProof`.
+//! - `verify_consistency`: a later signed root extends an earlier one, by
+//!   a `ConsistencyProof`.
+//! - `verify_head_proof`: a length and the root signed at it, by a
+//!   `HeadProof`.
+//! - `verify_inclusion_at` and `verify_consistency_at`: as above, against
+//!   heads the caller already trusts.
+//!
+//! A successful check only says the signer vouched for what was proven;
+//! callers still compare the returned item, lengths, and roots against
+//! what they asked for.
+//!
+//! Only roots in format version 3 and later commit to the core's length.
+//! Before that, different lengths can give trees of the same shape, so a
+//! replica could claim any of them; `verify_head`, `verify_inclusion`, and
+//! `verify_consistency` refuse those versions with `LengthNotSigned`
+//! rather than return a length nothing vouched for. To check a version 1
+//! or 2 core, pin its length first: `verify_head_proof` checks a
+//! `HeadProof` from `IsoCore::prove_head`, whose openings fix the length
+//! in any version, and returns a `Head`. Proofs are then checked with
+//! `verify_inclusion_at` or `verify_consistency_at`, which only accept
+//! proofs for that head's length and root. A `Head` the caller knows by
+//! other means, such as a checkpoint it kept, works the same way.
+//!
+//! ```
+//! use home::isocore::IsoCore;
+//! use home::key::KeyPair;
+//! use home::covering::ItemId;
+//! use home::verify;
+//!
+//! let signer = KeyPair::from_seed([7; 32]);
+//! let mut core = IsoCore::create_mem(&signer);
+//! for i in 0..10u8 {
+//!     core.add_message(&[i], &signer).unwrap();
+//! }
+//! let proof = core.prove(ItemId(4), 10).unwrap().to_bytes().unwrap();
+//!
+//! // On the client, with only the key and the bytes received
+//! let inclusion = verify::verify_inclusion(&signer.key_pub.0, &proof, &[4]).unwrap();
+//! assert_eq!((inclusion.item, inclusion.len), (ItemId(4), 10));
+//! ```
+
+use crate::covering::get_peaks;
+use crate::covering::ItemId;
+use crate::format::FormatError;
+use crate::format::FormatVersion;
+use crate::format::SignatureBlock;
+use crate::format::Signers;
+use crate::format::WIDTH;
+use crate::key::Hash;
+use crate::key::KeyPub;
+use crate::proof::ConsistencyProof;
+use crate::proof::HeadProof;
+use crate::proof::InclusionProof;
+use crate::proof::ProofError;
+
+#[derive(Debug)]
+pub enum VerifyError {
+    /// The public key isn't 32 bytes.
+    Key,
+    Format(FormatError),
+    Proof(ProofError),
+    /// The peaks aren't whole hashes, or not as many as the length has.
+    Peaks,
+    /// The data doesn't hash to the proven leaf.
+    DataMismatch,
+    /// The peaks don't bag to the signed root.
+    RootMismatch,
+    /// The signature doesn't verify against the key.
+    BadSignature,
+    /// The format version's roots don't commit to the length.
+    LengthNotSigned,
+    /// The proof is for another length or root than the trusted head.
+    HeadMismatch,
+}
+
+impl From<FormatError> for VerifyError {
+    fn from(err: FormatError) -> Self {
+        return VerifyError::Format(err);
+    }
+}
+
+impl From<ProofError> for VerifyError {
+    fn from(err: ProofError) -> Self {
+        return match err {
+            ProofError::BadSignature => VerifyError::BadSignature,
+            err => VerifyError::Proof(err),
+        };
+    }
+}
+
+/// What `verify_inclusion` proved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inclusion {
+    pub item: ItemId,
+    /// Length of the core when `root` was signed.
+    pub len: u64,
+    pub root: Hash,
+}
+
+/// What `verify_consistency` proved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Consistency {
+    pub old_len: u64,
+    pub new_len: u64,
+    pub old_root: Hash,
+    pub new_root: Hash,
+}
+
+/// A length and the root signed at it, as proven by `verify_head_proof`
+/// or known to the caller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Head {
+    pub len: u64,
+    pub root: Hash,
+}
+
+/// Checks that `block` is a signature block signed by `signer`. Returns
+/// the signed root.
+pub fn verify_signature_block(signer: &[u8], block: &[u8]) -> Result<Hash, VerifyError> {
+    let signer = key(signer)?;
+    let block = SignatureBlock::from_bytes(block)?;
+    if !Signers::single(signer).verify(&block) {
+        return Err(VerifyError::BadSignature);
+    }
+    return Ok(block.global_root);
+}
+
+/// Checks that `peaks`, the concatenated peak hashes of a core with `len`
+/// items in format `version`, bag to the root `block` signs. Returns the
+/// root.
+pub fn verify_head(signer: &[u8], version: u8, len: u64, peaks: &[u8], block: &[u8]) -> Result<Hash, VerifyError> {
+    let version = signed_length(FormatVersion::from_u8(version)?)?;
+    let root = verify_signature_block(signer, block)?;
+    if len == 0 || !peaks.len().is_multiple_of(32) || peaks.len() / 32 != get_peaks(len, WIDTH).len() {
+        return Err(VerifyError::Peaks);
+    }
+    let peaks: Vec<Hash> = peaks.chunks(32).map(|peak| Hash(peak.try_into().unwrap())).collect();
+    if version.hash_root(len, &peaks) != root {
+        return Err(VerifyError::RootMismatch);
+    }
+    return Ok(root);
+}
+
+/// Checks that `proof`, an encoded `HeadProof`, opens a root signed by
+/// `signer` far enough to pin its length. Works for every format version.
+pub fn verify_head_proof(signer: &[u8], proof: &[u8]) -> Result<Head, VerifyError> {
+    let signer = key(signer)?;
+    let proof = HeadProof::from_bytes(proof)?;
+    let root = proof.verify(&signer)?;
+    return Ok(Head { len: proof.len, root });
+}
+
+/// Checks that `proof`, an encoded `InclusionProof`, shows `data` is in a
+/// root signed by `signer`.
+pub fn verify_inclusion(signer: &[u8], proof: &[u8], data: &[u8]) -> Result<Inclusion, VerifyError> {
+    let signer = key(signer)?;
+    let proof = InclusionProof::from_bytes(proof)?;
+    signed_length(proof.version)?;
+    return inclusion(&signer, proof, data);
+}
+
+/// Checks that `proof`, an encoded `InclusionProof`, shows `data` is in
+/// `head`, a root signed by `signer` that the caller trusts. Works for
+/// every format version.
+pub fn verify_inclusion_at(signer: &[u8], proof: &[u8], data: &[u8], head: &Head) -> Result<Inclusion, VerifyError> {
+    let signer = key(signer)?;
+    let inclusion = inclusion(&signer, InclusionProof::from_bytes(proof)?, data)?;
+    if inclusion.len != head.len || inclusion.root != head.root {
+        return Err(VerifyError::HeadMismatch);
+    }
+    return Ok(inclusion);
+}
+
+/// Checks that `proof`, an encoded `ConsistencyProof`, shows two roots
+/// signed by `signer` are of the same core, one extending the other.
+pub fn verify_consistency(signer: &[u8], proof: &[u8]) -> Result<Consistency, VerifyError> {
+    let signer = key(signer)?;
+    let proof = ConsistencyProof::from_bytes(proof)?;
+    signed_length(proof.version)?;
+    return consistency(&signer, proof);
+}
+
+/// Checks that `proof`, an encoded `ConsistencyProof`, shows `new`
+/// extends `old`, both roots signed by `signer` that the caller trusts.
+/// Works for every format version.
+pub fn verify_consistency_at(signer: &[u8], proof: &[u8], old: &Head, new: &Head) -> Result<Consistency, VerifyError> {
+    let signer = key(signer)?;
+    let consistency = consistency(&signer, ConsistencyProof::from_bytes(proof)?)?;
+    if consistency.old_len != old.len || consistency.old_root != old.root
+        || consistency.new_len != new.len || consistency.new_root != new.root {
+        return Err(VerifyError::HeadMismatch);
+    }
+    return Ok(consistency);
+}
+
+fn inclusion(signer: &KeyPub, proof: InclusionProof, data: &[u8]) -> Result<Inclusion, VerifyError> {
+    if !proof.matches(data) {
+        return Err(VerifyError::DataMismatch);
+    }
+    let root = proof.verify(signer)?;
+    return Ok(Inclusion { item: proof.item_id, len: proof.len, root });
+}
+
+fn consistency(signer: &KeyPub, proof: ConsistencyProof) -> Result<Consistency, VerifyError> {
+    let (old_root, new_root) = proof.verify(signer)?;
+    return Ok(Consistency { old_len: proof.old_len, new_len: proof.new_len, old_root, new_root });
+}
+
+fn key(bytes: &[u8]) -> Result<KeyPub, VerifyError> {
+    return Ok(KeyPub(bytes.try_into().map_err(|_| VerifyError::Key)?));
+}
+
+/// `version`, if its roots commit to the length. See the module docs.
+fn signed_length(version: FormatVersion) -> Result<FormatVersion, VerifyError> {
+    return match version {
+        FormatVersion::V1 | FormatVersion::V2 => Err(VerifyError::LengthNotSigned),
+        version => Ok(version),
+    };
+}
+
+#[cfg(all(test, feature = "disk", feature = "rng"))]
+mod tests {
+    use super::*;
+    use crate::isocore::IsoCore;
+    use crate::key::KeyPair;
+
+    #[test]
+    fn verifies_replica_data_from_bytes() {
+        let signer = KeyPair::ephemeral();
+        let key = &signer.key_pub.0;
+        let mut core = IsoCore::create_mem(&signer);
+        for i in 0..30u32 {
+            core.add_message(&i.to_le_bytes(), &signer).unwrap();
+        }
+        let head = core.verify_head().unwrap();
+
+        let block = core.get_signature(ItemId(29)).unwrap().to_bytes();
+        assert_eq!(verify_signature_block(key, &block).unwrap(), head);
+        let peaks: Vec<u8> = core.peaks(30).unwrap().iter().flat_map(|peak| peak.0).collect();
+        assert_eq!(verify_head(key, 3, 30, &peaks, &block).unwrap(), head);
+        assert!(matches!(verify_head(key, 3, 29, &peaks, &block), Err(VerifyError::Peaks)));
+        assert!(matches!(verify_head(key, 3, 30, &vec![0; peaks.len()], &block), Err(VerifyError::RootMismatch)));
+        // One peak over 8 items, as over 1 or 64, but the root says 8
+        let block = core.get_signature(ItemId(7)).unwrap().to_bytes();
+        let peaks: Vec<u8> = core.peaks(8).unwrap().iter().flat_map(|peak| peak.0).collect();
+        verify_head(key, 3, 8, &peaks, &block).unwrap();
+        for len in [1, 64] {
+            assert!(matches!(verify_head(key, 3, len, &peaks, &block), Err(VerifyError::RootMismatch)));
+        }
+        let block = core.get_signature(ItemId(29)).unwrap().to_bytes();
+        let peaks: Vec<u8> = core.peaks(30).unwrap().iter().flat_map(|peak| peak.0).collect();
+        assert!(matches!(verify_head(key, 9, 30, &peaks, &block), Err(VerifyError::Format(FormatError::UnsupportedVersion { found: 9, expected: 3 }))));
+
+        let proof = core.prove(ItemId(12), 30).unwrap().to_bytes().unwrap();
+        let inclusion = verify_inclusion(key, &proof, &12u32.to_le_bytes()).unwrap();
+        assert_eq!(inclusion, Inclusion { item: ItemId(12), len: 30, root: head.clone() });
+        assert!(matches!(verify_inclusion(key, &proof, b"other"), Err(VerifyError::DataMismatch)));
+        assert!(matches!(verify_head(key, 2, 30, &peaks, &block), Err(VerifyError::LengthNotSigned)));
+
+        // A proof moved to another length or position doesn't verify, even
+        // where the tree has the same shape
+        let honest = core.prove(ItemId(12), 30).unwrap();
+        for (item, len) in [(12, 23), (12, 38), (13, 30), (20, 30)] {
+            let mut tampered = honest.clone();
+            tampered.item_id = ItemId(item);
+            tampered.len = len;
+            assert!(verify_inclusion(key, &tampered.to_bytes().unwrap(), &12u32.to_le_bytes()).is_err());
+        }
+        let mut v2 = honest.clone();
+        v2.version = FormatVersion::V2;
+        assert!(matches!(verify_inclusion(key, &v2.to_bytes().unwrap(), &12u32.to_le_bytes()), Err(VerifyError::LengthNotSigned)));
+
+        let proof = core.prove_consistency(10, 30).unwrap().to_bytes().unwrap();
+        let consistency = verify_consistency(key, &proof).unwrap();
+        assert_eq!(consistency.old_root, core.get_signature(ItemId(9)).unwrap().global_root);
+        assert_eq!(consistency.new_root, head);
+
+        let other = KeyPair::ephemeral();
+        assert!(matches!(verify_signature_block(&other.key_pub.0, &block), Err(VerifyError::BadSignature)));
+        assert!(matches!(verify_consistency(&other.key_pub.0, &proof), Err(VerifyError::BadSignature)));
+        assert!(matches!(verify_inclusion(&[0; 31], &proof, b""), Err(VerifyError::Key)));
+        assert!(matches!(verify_signature_block(key, &[b'z'; 129]), Err(VerifyError::Format(FormatError::SignatureBlock))));
+    }
+
+    #[test]
+    fn verifies_older_cores_against_a_pinned_head() {
+        let signer = KeyPair::ephemeral();
+        let key = &signer.key_pub.0;
+        let mut core = IsoCore::replica_mem(&signer.key_pub, FormatVersion::V2);
+        for i in 0..30u32 {
+            core.add_message(&i.to_le_bytes(), &signer).unwrap();
+        }
+        let data = 12u32.to_le_bytes();
+        let proof = core.prove(ItemId(12), 20).unwrap().to_bytes().unwrap();
+        assert!(matches!(verify_inclusion(key, &proof, &data), Err(VerifyError::LengthNotSigned)));
+
+        // The head proof fixes the length, and proofs are held to it
+        let head = verify_head_proof(key, &core.prove_head(20).unwrap().to_bytes().unwrap()).unwrap();
+        assert_eq!(head, Head { len: 20, root: core.get_signature(ItemId(19)).unwrap().global_root });
+        let inclusion = verify_inclusion_at(key, &proof, &data, &head).unwrap();
+        assert_eq!(inclusion, Inclusion { item: ItemId(12), len: 20, root: head.root.clone() });
+        assert!(matches!(verify_inclusion_at(key, &proof, b"other", &head), Err(VerifyError::DataMismatch)));
+
+        // 20 and 27 items give trees of the same shape, and a version 2
+        // root doesn't say which; the pinned head does
+        let mut relabelled = core.prove(ItemId(12), 27).unwrap();
+        relabelled.len = 20;
+        let relabelled = relabelled.to_bytes().unwrap();
+        assert!(matches!(verify_inclusion_at(key, &relabelled, &data, &head), Err(VerifyError::HeadMismatch)));
+        let other = Head { len: 27, root: head.root.clone() };
+        assert!(matches!(verify_inclusion_at(key, &proof, &data, &other), Err(VerifyError::HeadMismatch)));
+
+        let old = verify_head_proof(key, &core.prove_head(9).unwrap().to_bytes().unwrap()).unwrap();
+        let proof = core.prove_consistency(9, 20).unwrap().to_bytes().unwrap();
+        assert!(matches!(verify_consistency(key, &proof), Err(VerifyError::LengthNotSigned)));
+        let consistency = verify_consistency_at(key, &proof, &old, &head).unwrap();
+        assert_eq!((consistency.old_root, consistency.new_root), (old.root.clone(), head.root.clone()));
+        assert!(matches!(verify_consistency_at(key, &proof, &head, &old), Err(VerifyError::HeadMismatch)));
+
+        // A current core's proofs check against its heads too
+        let mut current = IsoCore::create_mem(&signer);
+        current.add_message(&data, &signer).unwrap();
+        let head = verify_head_proof(key, &current.prove_head(1).unwrap().to_bytes().unwrap()).unwrap();
+        let proof = current.prove(ItemId(0), 1).unwrap().to_bytes().unwrap();
+        assert_eq!(verify_inclusion_at(key, &proof, &data, &head).unwrap(), verify_inclusion(key, &proof, &data).unwrap());
+    }
+}
@@ -0,0 +1,284 @@
+//! Views: state folded from an IsoCore's messages
+//!
+//! A `View` applies a reducer to each item of a source core in order, and
+//! keeps the result. Snapshots of the state, tagged with how many items it
+//! covers, are appended to the view's own core, so a reopened view picks up
+//! from its last snapshot and only applies the items added since.
+//!
+//! Snapshots are kept, not replaced, so they double as checkpoints for
+//! looking back: `state_at` rebuilds the state as of an earlier length of
+//! the source from the latest snapshot at or before it.
+//!
+//! Each snapshot is a neopack List: `[applied: u64, state: Bytes]`.
+
+use std::path::PathBuf;
+use crate::core::Core;
+use crate::core::CoreError;
+use crate::core::MessageId;
+use crate::covering::ItemId;
+use crate::isocore::HistoricalView;
+use crate::isocore::IsoCore;
+use crate::isocore::IsoCoreError;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::Encoder;
+
+#[derive(Debug)]
+pub enum ViewError {
+    Core(CoreError),
+    IsoCore(IsoCoreError),
+    Neopack(neopack::Error),
+    /// The latest snapshot covers more items than the source holds, so it
+    /// was taken over a different or since-truncated core.
+    AheadOfSource { applied: u64, source: u64 },
+}
+
+impl From<CoreError> for ViewError {
+    fn from(err: CoreError) -> Self {
+        return ViewError::Core(err);
+    }
+}
+
+impl From<IsoCoreError> for ViewError {
+    fn from(err: IsoCoreError) -> Self {
+        return ViewError::IsoCore(err);
+    }
+}
+
+impl From<neopack::Error> for ViewError {
+    fn from(err: neopack::Error) -> Self {
+        return ViewError::Neopack(err);
+    }
+}
+
+/// View state that can be written to and read back from a snapshot.
+pub trait ViewState: Sized {
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(bytes: &[u8]) -> Result<Self, neopack::Error>;
+}
+
+/// Folds a state over one item's message.
+pub type Reducer<S> = fn(S, ItemId, &[u8]) -> S;
+
+#[derive(Debug)]
+pub struct View<S> {
+    /// Always `Some` outside of `update`; the reducer takes it by value.
+    state: Option<S>,
+    /// Number of source items folded into `state`.
+    applied: u64,
+    reducer: Reducer<S>,
+    snapshots: Core,
+}
+
+impl<S: ViewState> View<S> {
+    pub fn create_mem(initial: S, reducer: Reducer<S>) -> Self {
+        return Self {
+            state: Some(initial),
+            applied: 0,
+            reducer,
+            snapshots: Core::create_mem(),
+        };
+    }
+
+    /// Opens the snapshot core at `path`, resuming from its latest
+    /// snapshot, or creates it starting from `initial`.
+    pub fn open(path: PathBuf, initial: S, reducer: Reducer<S>) -> Result<Self, ViewError> {
+        if !path.exists() {
+            return Ok(Self {
+                state: Some(initial),
+                applied: 0,
+                reducer,
+                snapshots: Core::create(path)?,
+            });
+        }
+
+        let mut snapshots = Core::load(&path)?;
+        let Some(last) = snapshots.len().0.checked_sub(1) else {
+            return Ok(Self {
+                state: Some(initial),
+                applied: 0,
+                reducer,
+                snapshots,
+            });
+        };
+
+        let (applied, state) = read_snapshot(&mut snapshots, MessageId(last))?;
+        return Ok(Self {
+            state: Some(state),
+            applied,
+            reducer,
+            snapshots,
+        });
+    }
+
+    pub fn state(&self) -> &S {
+        return self.state.as_ref().expect("state is only taken inside update");
+    }
+
+    /// The next source item to be applied.
+    pub fn applied(&self) -> ItemId {
+        return ItemId(self.applied);
+    }
+
+    /// Applies every source item not yet in the state, then snapshots if
+    /// anything changed. Returns how many items were applied.
+    pub fn update(&mut self, source: &mut IsoCore) -> Result<u64, ViewError> {
+        let len = source.len().0 as u64;
+        if self.applied > len {
+            return Err(ViewError::AheadOfSource { applied: self.applied, source: len });
+        }
+
+        let start = self.applied;
+        while self.applied < len {
+            let item_id = ItemId(self.applied);
+            let message = source.get_message(item_id)?;
+            let state = self.state.take().expect("state is only taken inside update");
+            self.state = Some((self.reducer)(state, item_id, &message));
+            self.applied += 1;
+        }
+
+        if self.applied > start {
+            self.snapshot()?;
+        }
+        return Ok(self.applied - start);
+    }
+
+    /// The state as of the first `source.len()` items, folded from the
+    /// latest snapshot at or before that length, or from `initial` if
+    /// there is none. The view itself is left as it was.
+    pub fn state_at(&mut self, source: &mut HistoricalView<'_>, initial: S) -> Result<S, ViewError> {
+        let len = source.len();
+        let mut start = (0, initial);
+        // Snapshots are taken in order, so the first one back that fits
+        // is the latest
+        for id in (0..self.snapshots.len().0).rev() {
+            let (applied, state) = read_snapshot(&mut self.snapshots, MessageId(id))?;
+            if applied <= len {
+                start = (applied, state);
+                break;
+            }
+        }
+
+        let (mut applied, mut state) = start;
+        while applied < len {
+            let item_id = ItemId(applied);
+            state = (self.reducer)(state, item_id, &source.get_message(item_id)?);
+            applied += 1;
+        }
+        return Ok(state);
+    }
+
+    /// Appends the current state to the snapshot core and flushes it.
+    pub fn snapshot(&mut self) -> Result<(), ViewError> {
+        let mut enc = Encoder::new();
+        let mut list = enc.list()?;
+        list.u64(self.applied)?;
+        list.bytes(&self.state().to_bytes())?;
+        list.finish()?;
+
+        self.snapshots.add_message(enc.as_bytes())?;
+        self.snapshots.flush()?;
+        return Ok(());
+    }
+}
+
+/// Reads the snapshot at `id`: how many items it covers, and the state.
+fn read_snapshot<S: ViewState>(snapshots: &mut Core, id: MessageId) -> Result<(u64, S), ViewError> {
+    let bytes = snapshots.get_contents(id)?;
+    let mut dec = Decoder::new(&bytes);
+    let mut list = dec.list()?;
+    let applied = list.next()?.ok_or(neopack::Error::Malformed)?.as_u64()?;
+    let state = list.next()?.ok_or(neopack::Error::Malformed)?.as_bytes()?;
+    return Ok((applied, S::from_bytes(state)?));
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use super::*;
+    use crate::key::KeyPair;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Totals {
+        count: u64,
+        bytes: u64,
+    }
+
+    impl ViewState for Totals {
+        fn to_bytes(&self) -> Vec<u8> {
+            let mut enc = Encoder::new();
+            enc.u64(self.count).unwrap();
+            enc.u64(self.bytes).unwrap();
+            return enc.into_bytes();
+        }
+
+        fn from_bytes(bytes: &[u8]) -> Result<Self, neopack::Error> {
+            let mut dec = Decoder::new(bytes);
+            return Ok(Totals { count: dec.u64()?, bytes: dec.u64()? });
+        }
+    }
+
+    fn tally(state: Totals, _: ItemId, message: &[u8]) -> Totals {
+        return Totals {
+            count: state.count + 1,
+            bytes: state.bytes + message.len() as u64,
+        };
+    }
+
+    #[test]
+    fn view_resumes_from_snapshot() {
+        let path = PathBuf::from("/tmp/test_view_resumes.nd");
+        let _ = std::fs::remove_file(&path);
+        let signer = KeyPair::ephemeral();
+        let mut source = IsoCore::create_mem(&signer);
+        source.add_messages(["a", "bb", "ccc"], &signer).unwrap();
+
+        let mut view = View::open(path.clone(), Totals::default(), tally).unwrap();
+        assert_eq!(view.update(&mut source).unwrap(), 3);
+        assert_eq!(view.update(&mut source).unwrap(), 0);
+        drop(view);
+
+        source.add_message(b"dddd", &signer).unwrap();
+        let mut view = View::open(path.clone(), Totals::default(), tally).unwrap();
+        assert_eq!(view.applied(), ItemId(3));
+        assert_eq!(view.state(), &Totals { count: 3, bytes: 6 });
+        assert_eq!(view.update(&mut source).unwrap(), 1);
+        assert_eq!(view.state(), &Totals { count: 4, bytes: 10 });
+
+        // A shorter source can't be the one the snapshot was taken over
+        let mut other = IsoCore::create_mem(&signer);
+        assert!(matches!(
+            view.update(&mut other),
+            Err(ViewError::AheadOfSource { applied: 4, source: 0 })
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn view_rewinds_to_earlier_lengths() {
+        let signer = KeyPair::ephemeral();
+        let mut source = IsoCore::create_mem(&signer);
+        let mut view = View::create_mem(Totals::default(), tally);
+        source.add_messages(["a", "bb"], &signer).unwrap();
+        view.update(&mut source).unwrap();
+        source.add_messages(["ccc", "dddd"], &signer).unwrap();
+        view.update(&mut source).unwrap();
+
+        // From the initial state, from the first snapshot, and from one
+        // exactly at the length asked for
+        let at = |view: &mut View<Totals>, source: &mut IsoCore, len| {
+            return view.state_at(&mut source.at(len).unwrap(), Totals::default()).unwrap();
+        };
+        assert_eq!(at(&mut view, &mut source, 1), Totals { count: 1, bytes: 1 });
+        assert_eq!(at(&mut view, &mut source, 3), Totals { count: 3, bytes: 6 });
+        assert_eq!(at(&mut view, &mut source, 4), Totals { count: 4, bytes: 10 });
+        assert_eq!(at(&mut view, &mut source, 0), Totals::default());
+        assert_eq!(view.state(), &Totals { count: 4, bytes: 10 });
+
+        let mut history = source.at(2).unwrap();
+        assert!(history.get_message(ItemId(2)).is_err());
+        assert_eq!(Some(history.verify_head().unwrap()), history.root().unwrap());
+        assert!(history.prove(ItemId(1)).unwrap().verify(&signer.key_pub).is_ok());
+        assert!(source.at(5).is_err());
+    }
+}
@@ -0,0 +1,338 @@
+//! wasm-bindgen wrappers for decoding and verifying in the browser
+//!
+//! Only the pure pieces of the crate are exposed here: neopack decoding,
+//! blake3 hashing, covering tree math, ed25519 verification, and the proof
+//! checks of the `verify` module. Nothing
+//! touches the filesystem or needs OS randomness, so this module builds for
+//! wasm32-unknown-unknown. To produce a module for wasm-bindgen:
+//!
+//! ```text
+//! cargo rustc --lib --crate-type cdylib --target wasm32-unknown-unknown \
+//!     --no-default-features --features wasm
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::covering;
+use crate::covering::CoveringId;
+use crate::format::FormatVersion;
+use crate::hex;
+use crate::key;
+use crate::key::Hash;
+use crate::key::KeyPub;
+use crate::key::Signature;
+use crate::neopack;
+use crate::neopack::Decoder;
+use crate::neopack::ValueDecoder;
+use crate::verify;
+
+/// Decodes every top-level neopack value in `bytes` into a JSON array.
+/// Byte strings and raw structs are rendered as lowercase hex strings.
+#[wasm_bindgen]
+pub fn decode_json(bytes: &[u8]) -> Result<String, JsError> {
+    return to_json(bytes).map_err(|e| JsError::new(&format!("{:?}", e)));
+}
+
+/// Returns the blake3 hash of `bytes`.
+#[wasm_bindgen]
+pub fn hash(bytes: &[u8]) -> Vec<u8> {
+    return key::hash(bytes).0.to_vec();
+}
+
+/// Checks that `data` hashes to `expected`, as stored in a leaf node of a
+/// core with the given format `version`. Unknown versions never verify.
+#[wasm_bindgen]
+pub fn verify_leaf(data: &[u8], expected: &[u8], version: u8) -> bool {
+    return FormatVersion::from_u8(version).is_ok_and(|v| v.hash_leaf(data).0[..] == *expected);
+}
+
+/// Verifies an ed25519 signature. Malformed keys or signatures are rejected.
+#[wasm_bindgen]
+pub fn verify_signature(key_pub: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    let (Ok(key_pub), Ok(signature)) = (key_pub.try_into(), signature.try_into()) else {
+        return false;
+    };
+    return KeyPub(key_pub).verify(message, &Signature(signature));
+}
+
+/// Checks an encoded inclusion proof for `data` against `key_pub`, as
+/// `verify::verify_inclusion` does. Returns the signed root.
+#[wasm_bindgen]
+pub fn verify_inclusion(key_pub: &[u8], proof: &[u8], data: &[u8]) -> Result<Vec<u8>, JsError> {
+    let inclusion = verify::verify_inclusion(key_pub, proof, data).map_err(verify_error)?;
+    return Ok(inclusion.root.0.to_vec());
+}
+
+/// Checks an encoded consistency proof against `key_pub`, as
+/// `verify::verify_consistency` does. Returns the old root followed by
+/// the new one.
+#[wasm_bindgen]
+pub fn verify_consistency(key_pub: &[u8], proof: &[u8]) -> Result<Vec<u8>, JsError> {
+    let consistency = verify::verify_consistency(key_pub, proof).map_err(verify_error)?;
+    return Ok([consistency.old_root.0, consistency.new_root.0].concat());
+}
+
+/// Checks an encoded head proof against `key_pub`, as
+/// `verify::verify_head_proof` does. Returns the signed root followed by
+/// the length as 8 little-endian bytes.
+#[wasm_bindgen]
+pub fn verify_head_proof(key_pub: &[u8], proof: &[u8]) -> Result<Vec<u8>, JsError> {
+    let head = verify::verify_head_proof(key_pub, proof).map_err(verify_error)?;
+    return Ok([&head.root.0[..], &head.len.to_le_bytes()].concat());
+}
+
+/// Checks an encoded inclusion proof for `data` against the head of `len`
+/// items signed with `root`, as `verify::verify_inclusion_at` does. For
+/// cores of any format version, once the head is pinned.
+#[wasm_bindgen]
+pub fn verify_inclusion_at(key_pub: &[u8], proof: &[u8], data: &[u8], len: u64, root: &[u8]) -> Result<Vec<u8>, JsError> {
+    let head = head(len, root)?;
+    let inclusion = verify::verify_inclusion_at(key_pub, proof, data, &head).map_err(verify_error)?;
+    return Ok(inclusion.root.0.to_vec());
+}
+
+/// Checks an encoded consistency proof against two pinned heads, as
+/// `verify::verify_consistency_at` does.
+#[wasm_bindgen]
+pub fn verify_consistency_at(
+    key_pub: &[u8],
+    proof: &[u8],
+    old_len: u64,
+    old_root: &[u8],
+    new_len: u64,
+    new_root: &[u8],
+) -> Result<(), JsError> {
+    let (old, new) = (head(old_len, old_root)?, head(new_len, new_root)?);
+    verify::verify_consistency_at(key_pub, proof, &old, &new).map_err(verify_error)?;
+    return Ok(());
+}
+
+/// Returns `[start, end)` of the items covered by covering node `y`.
+#[wasm_bindgen]
+pub fn covering_range(y: u64, width: u64) -> Result<Vec<u64>, JsError> {
+    check_width(width)?;
+    let range = covering::covering_range(CoveringId(y), width);
+    return Ok(vec![range.start.0, range.end.0]);
+}
+
+/// Returns the covering ids of the children of covering node `y`.
+#[wasm_bindgen]
+pub fn covering_children(y: u64, width: u64) -> Result<Vec<u64>, JsError> {
+    check_width(width)?;
+    let children = covering::children_for_covering(CoveringId(y), width);
+    return Ok(children.into_iter().map(|c| c.0).collect());
+}
+
+/// Returns the peak covering ids of a forest of `n` items.
+#[wasm_bindgen]
+pub fn covering_peaks(n: u64, width: u64) -> Result<Vec<u64>, JsError> {
+    check_width(width)?;
+    let peaks = covering::get_peaks(n, width);
+    return Ok(peaks.into_iter().map(|c| c.0).collect());
+}
+
+fn verify_error(err: verify::VerifyError) -> JsError {
+    return JsError::new(&format!("{:?}", err));
+}
+
+fn head(len: u64, root: &[u8]) -> Result<verify::Head, JsError> {
+    let root = root.try_into().map_err(|_| JsError::new("root must be 32 bytes"))?;
+    return Ok(verify::Head { len, root: Hash(root) });
+}
+
+// The covering functions assert on width; a panic in wasm is an abort.
+fn check_width(width: u64) -> Result<(), JsError> {
+    if width > 1 && width.is_power_of_two() {
+        return Ok(());
+    }
+    return Err(JsError::new("width must be a power of two greater than one"));
+}
+
+fn to_json(bytes: &[u8]) -> neopack::Result<String> {
+    let mut out = String::from("[");
+    let mut dec = Decoder::new(bytes);
+    let mut first = true;
+    while dec.remaining() > 0 {
+        if !first { out.push(','); }
+        first = false;
+        let value = dec.value()?;
+        write_value(&mut out, value)?;
+    }
+    out.push(']');
+    return Ok(out);
+}
+
+fn write_value(out: &mut String, value: ValueDecoder<'_>) -> neopack::Result<()> {
+    use ValueDecoder::*;
+    match value {
+        Bool(v) => out.push_str(if v { "true" } else { "false" }),
+        U8(v) => out.push_str(&v.to_string()),
+        S8(v) => out.push_str(&v.to_string()),
+        U16(v) => out.push_str(&v.to_string()),
+        S16(v) => out.push_str(&v.to_string()),
+        U32(v) => out.push_str(&v.to_string()),
+        S32(v) => out.push_str(&v.to_string()),
+        U64(v) => out.push_str(&v.to_string()),
+        S64(v) => out.push_str(&v.to_string()),
+        F32(v) => write_float(out, v as f64),
+        F64(v) => write_float(out, v),
+        F16(v) => write_float(out, v.to_f32() as f64),
+        U128(v) => out.push_str(&v.to_string()),
+        S128(v) => out.push_str(&v.to_string()),
+        Timestamp(v) => out.push_str(&v.0.to_string()),
+        Duration(v) => out.push_str(&v.0.to_string()),
+        Bytes(v) | Struct(v) => write_hex(out, v),
+        Fixed16(v) => write_hex(out, v),
+        Fixed32(v) => write_hex(out, v),
+        Ext(id, v) => {
+            out.push_str(&format!("{{\"ext\":{},\"data\":", id));
+            write_hex(out, v);
+            out.push('}');
+        }
+        Str(v) => write_str(out, v),
+        List(mut list) => {
+            out.push('[');
+            let mut first = true;
+            while let Some(item) = list.next()? {
+                if !first { out.push(','); }
+                first = false;
+                write_value(out, item)?;
+            }
+            out.push(']');
+        }
+        Map(mut map) => {
+            out.push('{');
+            let mut first = true;
+            while let Some((key, item)) = map.next()? {
+                if !first { out.push(','); }
+                first = false;
+                write_str(out, key);
+                out.push(':');
+                write_value(out, item)?;
+            }
+            out.push('}');
+        }
+        Array(mut array) => {
+            out.push('[');
+            let mut first = true;
+            while let Some(item) = array.next()? {
+                if !first { out.push(','); }
+                first = false;
+                write_value(out, item)?;
+            }
+            out.push(']');
+        }
+    }
+    return Ok(());
+}
+
+fn write_float(out: &mut String, v: f64) {
+    if v.is_finite() {
+        out.push_str(&v.to_string());
+    } else {
+        out.push_str("null");
+    }
+}
+
+fn write_hex(out: &mut String, bytes: &[u8]) {
+    out.push('"');
+    hex::push(out, bytes);
+    out.push('"');
+}
+
+fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::neopack::Encoder;
+
+    #[test]
+    fn json_nested_document() {
+        let mut enc = Encoder::new();
+        let mut map = enc.map().unwrap();
+        map.key("name").unwrap().str("a \"quoted\"\nname").unwrap();
+        map.key("raw").unwrap().bytes(&[0xde, 0xad]).unwrap();
+        let mut list = map.key("items").unwrap().list().unwrap();
+        list.u32(1).unwrap();
+        list.bool(false).unwrap();
+        list.f64(f64::NAN).unwrap();
+        list.finish().unwrap();
+        map.finish().unwrap();
+        enc.i8(-3).unwrap();
+
+        let json = to_json(enc.as_bytes()).unwrap();
+        assert_eq!(json, r#"[{"name":"a \"quoted\"\nname","raw":"dead","items":[1,false,null]},-3]"#);
+    }
+
+    #[test]
+    fn json_rejects_truncated_input() {
+        let mut enc = Encoder::new();
+        enc.u64(7).unwrap();
+        let bytes = enc.as_bytes();
+        assert!(to_json(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "disk", feature = "rng"))]
+    fn leaves_verify_for_every_version() {
+        use crate::covering::ItemId;
+        use crate::isocore::IsoCore;
+        use crate::key::KeyPair;
+
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::create_mem(&signer);
+        core.add_message(b"leaf", &signer).unwrap();
+        let leaf = core.prove(ItemId(0), 1).unwrap().leaf_hash;
+        assert!(verify_leaf(b"leaf", &leaf.0, FormatVersion::V3 as u8));
+        // Version 3 only changed how roots are bagged
+        assert!(verify_leaf(b"leaf", &leaf.0, FormatVersion::V2 as u8));
+        assert!(!verify_leaf(b"leaf", &leaf.0, FormatVersion::V1 as u8));
+        assert!(!verify_leaf(b"other", &leaf.0, FormatVersion::V3 as u8));
+
+        assert!(verify_leaf(b"leaf", &key::hash(b"leaf").0, 1));
+        assert!(!verify_leaf(b"leaf", &leaf.0, 4));
+    }
+
+    #[test]
+    #[cfg(all(feature = "disk", feature = "rng"))]
+    fn older_cores_verify_against_a_pinned_head() {
+        use crate::covering::ItemId;
+        use crate::isocore::IsoCore;
+        use crate::key::KeyPair;
+
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::replica_mem(&signer.key_pub, FormatVersion::V2);
+        for i in 0..10u8 {
+            core.add_message(&[i], &signer).unwrap();
+        }
+        let head = verify_head_proof(&signer.key_pub.0, &core.prove_head(9).unwrap().to_bytes().unwrap()).unwrap();
+        let (root, len) = head.split_at(32);
+        assert_eq!(len, 9u64.to_le_bytes());
+        let proof = core.prove(ItemId(4), 9).unwrap().to_bytes().unwrap();
+        assert_eq!(verify_inclusion_at(&signer.key_pub.0, &proof, &[4], 9, root).unwrap(), root);
+        let proof = core.prove_consistency(3, 9).unwrap().to_bytes().unwrap();
+        let old = core.get_signature(ItemId(2)).unwrap().global_root;
+        verify_consistency_at(&signer.key_pub.0, &proof, 3, &old.0, 9, root).unwrap();
+    }
+
+    #[test]
+    fn signature_wrapper_rejects_bad_lengths() {
+        assert!(!verify_signature(&[0; 31], b"msg", &[0; 64]));
+        assert!(!verify_signature(&[0; 32], b"msg", &[0; 63]));
+    }
+}
@@ -0,0 +1,158 @@
+//! Witnesses: third parties that cosign a core's heads
+//!
+//! A signer could show one reader a core and another reader a different
+//! one, both signed at the same length. A witness makes that visible: it
+//! fetches a core's head, checks it, and cosigns the length and root with
+//! its own key. It only ever cosigns one history per core. A longer head
+//! must come with a `ConsistencyProof` from the last head it cosigned, and
+//! a different root at the same length is refused as a fork.
+//!
+//! The owner keeps the cosignatures next to its signature blocks with
+//! `IsoCore::add_cosignature`, and a reader can ask `verify_witnessed` for
+//! `k` of the witnesses it trusts before trusting a head.
+//!
+//! A `Witness` remembers heads in memory only. One that restarts without
+//! `remember`ing what it cosigned could be led to cosign a fork.
+
+use std::collections::HashMap;
+use crate::format::Checkpoint;
+use crate::format::Cosignature;
+use crate::key::KeyPair;
+use crate::key::KeyPub;
+use crate::proof::ConsistencyProof;
+use crate::proof::ProofError;
+
+#[derive(Debug)]
+pub enum WitnessError {
+    /// The head isn't signed by the core's signer.
+    BadSignature,
+    /// The head is shorter than the last one cosigned for the core.
+    Stale,
+    /// The head has another root at the length of one already cosigned.
+    Fork,
+    /// A longer head came without a proof from the last one cosigned, or
+    /// with one for other lengths or roots.
+    Inconsistent,
+    Proof(ProofError),
+}
+
+impl From<ProofError> for WitnessError {
+    fn from(err: ProofError) -> Self {
+        return WitnessError::Proof(err);
+    }
+}
+
+pub struct Witness {
+    key: KeyPair,
+    /// The last head cosigned for each core, by signer.
+    heads: HashMap<[u8; 32], Checkpoint>,
+}
+
+impl Witness {
+    pub fn new(key: KeyPair) -> Self {
+        return Witness { key, heads: HashMap::new() };
+    }
+
+    pub fn key_pub(&self) -> &KeyPub {
+        return &self.key.key_pub;
+    }
+
+    /// Cosigns `head` of the core signed by `signer`. The first head seen
+    /// for a core is taken on its signature; after that, `proof` must show
+    /// the new head extends the last one cosigned.
+    pub fn cosign(
+        &mut self,
+        signer: &KeyPub,
+        head: &Checkpoint,
+        proof: Option<&ConsistencyProof>,
+    ) -> Result<Cosignature, WitnessError> {
+        if !head.verify(signer) {
+            return Err(WitnessError::BadSignature);
+        }
+        if let Some(last) = self.heads.get(&signer.0) {
+            if head.len < last.len {
+                return Err(WitnessError::Stale);
+            }
+            if head.len == last.len && head.root != last.root {
+                return Err(WitnessError::Fork);
+            }
+            if head.len > last.len {
+                let proof = proof.ok_or(WitnessError::Inconsistent)?;
+                if proof.old_len != last.len || proof.new_len != head.len {
+                    return Err(WitnessError::Inconsistent);
+                }
+                let (old_root, new_root) = proof.verify(signer)?;
+                if old_root != last.root || new_root != head.root {
+                    return Err(WitnessError::Inconsistent);
+                }
+            }
+        }
+
+        self.heads.insert(signer.0, head.clone());
+        return Ok(Cosignature::sign(&self.key, head.len, head.root.clone()));
+    }
+
+    /// The last head cosigned for the core signed by `signer`.
+    pub fn head(&self, signer: &KeyPub) -> Option<&Checkpoint> {
+        return self.heads.get(&signer.0);
+    }
+
+    /// Restores a head cosigned before, say after a restart.
+    pub fn remember(&mut self, signer: KeyPub, head: Checkpoint) {
+        self.heads.insert(signer.0, head);
+    }
+}
+
+#[cfg(all(test, feature = "rng"))]
+mod tests {
+    use std::path::PathBuf;
+    use super::*;
+    use crate::isocore::IsoCore;
+    use crate::isocore::IsoCoreError;
+
+    #[test]
+    fn cosigns_one_history() {
+        let path = PathBuf::from("/tmp/test_witness_cosigns");
+        let _ = std::fs::remove_dir_all(&path);
+        let signer = KeyPair::ephemeral();
+        let mut core = IsoCore::create(path.clone(), &signer).unwrap();
+        let mut witnesses: Vec<Witness> = (0..3).map(|_| Witness::new(KeyPair::ephemeral())).collect();
+        let trusted: Vec<KeyPub> = witnesses.iter().map(|w| w.key_pub().clone()).collect();
+
+        core.add_messages([b"one", b"two"], &signer).unwrap();
+        let first = core.checkpoint().unwrap();
+        for witness in &mut witnesses[..2] {
+            core.add_cosignature(witness.cosign(&signer.key_pub, &first, None).unwrap()).unwrap();
+        }
+        assert_eq!(core.verify_witnessed(&trusted, 2).unwrap(), first.root);
+        assert!(matches!(core.verify_witnessed(&trusted, 3), Err(IsoCoreError::Unwitnessed { needed: 3, found: 2 })));
+
+        // A longer head needs a proof it extends the last one
+        core.add_message(b"three", &signer).unwrap();
+        let second = core.checkpoint().unwrap();
+        assert!(matches!(core.verify_witnessed(&trusted, 1), Err(IsoCoreError::Unwitnessed { needed: 1, found: 0 })));
+        assert!(matches!(witnesses[0].cosign(&signer.key_pub, &second, None), Err(WitnessError::Inconsistent)));
+        let proof = core.prove_consistency(2, 3).unwrap();
+        let cosignature = witnesses[0].cosign(&signer.key_pub, &second, Some(&proof)).unwrap();
+        core.add_cosignature(cosignature).unwrap();
+        core.flush().unwrap();
+        drop(core);
+
+        let mut core = IsoCore::load(&path).unwrap();
+        assert_eq!(core.cosignatures().len(), 3);
+        assert_eq!(core.verify_witnessed(&trusted, 1).unwrap(), second.root);
+
+        // A fork at a cosigned length is refused, as is going back
+        let mut fork = IsoCore::create_mem(&signer);
+        fork.add_messages(["one", "two", "other"], &signer).unwrap();
+        let forked = fork.checkpoint().unwrap();
+        assert!(matches!(witnesses[0].cosign(&signer.key_pub, &forked, None), Err(WitnessError::Fork)));
+        assert!(matches!(witnesses[0].cosign(&signer.key_pub, &first, None), Err(WitnessError::Stale)));
+
+        // The owner won't keep a cosignature of a head it never signed
+        let stranger = Witness::new(KeyPair::ephemeral()).cosign(&signer.key_pub, &forked, None).unwrap();
+        assert!(matches!(core.add_cosignature(stranger), Err(IsoCoreError::IntegrityError)));
+
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}